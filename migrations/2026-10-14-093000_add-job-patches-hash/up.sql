@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+ALTER TABLE
+    jobs
+ADD COLUMN
+    patches_hash VARCHAR(64);
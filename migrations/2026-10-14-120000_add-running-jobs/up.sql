@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+CREATE TABLE running_jobs (
+    id SERIAL PRIMARY KEY NOT NULL,
+    uuid UUID NOT NULL UNIQUE,
+    submit_id INTEGER REFERENCES submits(id) NOT NULL,
+    fingerprint VARCHAR(64) NOT NULL,
+    started_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX running_jobs_fingerprint ON running_jobs (fingerprint);
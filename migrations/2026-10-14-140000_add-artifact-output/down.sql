@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE
+    artifacts
+DROP COLUMN
+    output;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+ALTER TABLE
+    jobs
+ADD COLUMN
+    options VARCHAR;
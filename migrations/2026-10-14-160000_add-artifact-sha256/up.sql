@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+ALTER TABLE
+    artifacts
+ADD COLUMN
+    sha256 VARCHAR;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE job_phases (
+    id SERIAL PRIMARY KEY NOT NULL,
+    job_id INTEGER REFERENCES jobs(id) NOT NULL,
+    position INTEGER NOT NULL,
+    name VARCHAR NOT NULL,
+    started TIMESTAMP WITH TIME ZONE NOT NULL,
+    duration_secs DOUBLE PRECISION NOT NULL
+);
+
+CREATE INDEX job_phases_job_id ON job_phases (job_id);
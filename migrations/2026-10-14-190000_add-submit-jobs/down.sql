@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE job_state_transitions;
+DROP TABLE submit_jobs;
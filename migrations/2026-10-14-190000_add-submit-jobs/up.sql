@@ -0,0 +1,21 @@
+-- Your SQL goes here
+
+CREATE TABLE submit_jobs (
+    id SERIAL PRIMARY KEY NOT NULL,
+    submit_id INTEGER REFERENCES submits(id) NOT NULL,
+    uuid UUID NOT NULL UNIQUE,
+    package_id INTEGER REFERENCES packages(id) NOT NULL,
+    dependencies UUID[] NOT NULL
+);
+
+CREATE INDEX submit_jobs_submit_id ON submit_jobs (submit_id);
+
+CREATE TABLE job_state_transitions (
+    id SERIAL PRIMARY KEY NOT NULL,
+    submit_job_id INTEGER REFERENCES submit_jobs(id) NOT NULL,
+    state VARCHAR NOT NULL,
+    time TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    artifacts VARCHAR[] NOT NULL
+);
+
+CREATE INDEX job_state_transitions_submit_job_id ON job_state_transitions (submit_job_id);
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+CREATE TABLE submit_cancellations (
+    id SERIAL PRIMARY KEY NOT NULL,
+    submit_id INTEGER REFERENCES submits(id) NOT NULL,
+    canceled_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX submit_cancellations_submit_id ON submit_cancellations (submit_id);
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX submits_requested_image_id;
+DROP INDEX artifacts_job_id;
+DROP INDEX jobs_submit_id;
+DROP INDEX jobs_package_id;
+DROP INDEX jobs_script_text;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+-- The scripts are too long for a B-tree index, but artifacts are only searched for jobs with
+-- exactly the same script. The name and version of the packages are already indexed by their
+-- unique constraint, as well as the job_id of job_envs.
+CREATE INDEX jobs_script_text ON jobs USING hash (script_text);
+CREATE INDEX jobs_package_id ON jobs (package_id);
+CREATE INDEX jobs_submit_id ON jobs (submit_id);
+CREATE INDEX artifacts_job_id ON artifacts (job_id);
+CREATE INDEX submits_requested_image_id ON submits (requested_image_id);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+CREATE TABLE release_promotions (
+    id SERIAL PRIMARY KEY NOT NULL,
+    release_id INTEGER REFERENCES releases(id) ON DELETE CASCADE NOT NULL,
+    from_release_store_id INTEGER REFERENCES release_stores(id) NOT NULL,
+    promoted_by VARCHAR NOT NULL,
+    promoted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX release_promotions_release_id ON release_promotions (release_id);
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE audit (
+    id SERIAL PRIMARY KEY NOT NULL,
+    recorded_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    username VARCHAR NOT NULL,
+    operation VARCHAR NOT NULL,
+    parameters JSONB NOT NULL
+);
+
+CREATE INDEX audit_recorded_at ON audit (recorded_at);
+CREATE INDEX audit_operation ON audit (operation);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE submits ADD COLUMN submitted_by VARCHAR;
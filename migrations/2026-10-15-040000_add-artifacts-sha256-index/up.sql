@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+CREATE INDEX artifacts_sha256_idx ON artifacts (sha256);
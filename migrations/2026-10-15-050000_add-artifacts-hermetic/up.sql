@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE artifacts ADD COLUMN hermetic BOOLEAN NOT NULL DEFAULT false;
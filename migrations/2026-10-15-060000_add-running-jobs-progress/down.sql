@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE running_jobs
+    DROP COLUMN endpoint_name,
+    DROP COLUMN scheduled_at,
+    DROP COLUMN phase,
+    DROP COLUMN progress;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+ALTER TABLE running_jobs
+    ADD COLUMN endpoint_name VARCHAR NULL,
+    ADD COLUMN scheduled_at TIMESTAMPTZ NULL,
+    ADD COLUMN phase VARCHAR NULL,
+    ADD COLUMN progress INTEGER NULL;
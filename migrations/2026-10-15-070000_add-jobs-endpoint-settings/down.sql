@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE jobs DROP COLUMN endpoint_mounts;
+ALTER TABLE jobs DROP COLUMN endpoint_env;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE jobs ADD COLUMN endpoint_env VARCHAR[] NOT NULL DEFAULT '{}';
+ALTER TABLE jobs ADD COLUMN endpoint_mounts VARCHAR[] NOT NULL DEFAULT '{}';
@@ -14,8 +14,10 @@ use std::str::FromStr;
 use clap::crate_authors;
 use clap::crate_version;
 use clap::App;
+use clap::AppSettings;
 use clap::Arg;
 use clap::ArgGroup;
+use clap::ArgSettings;
 
 // Helper types to ship around stringly typed clap API.
 pub const IDENT_DEPENDENCY_TYPE_BUILD: &str = "build";
@@ -40,6 +42,56 @@ pub fn cli<'a>() -> App<'a> {
             .about("Hide all progress bars")
         )
 
+        .arg(Arg::new("progress")
+            .required(false)
+            .multiple(false)
+            .long("progress")
+            .takes_value(true)
+            .value_name("MODE")
+            .possible_values(&["bars", "plain", "json"])
+            .about("How the progress is shown")
+            .long_about(indoc::indoc!(r#"
+                How the progress is shown:
+
+                    bars  - progress bars (the default if stdout is a terminal)
+                    plain - timestamped lines on stderr when jobs start, change their phase and
+                            finish, for CI logs (the default if stdout is not a terminal)
+                    json  - the same events as JSON objects, one per line
+
+                In the plain and json modes, no progress bars are shown.
+                "--hide-bars" hides the progress bars without printing the events instead.
+            "#))
+        )
+
+        .arg(Arg::new("no_repo_cache")
+            .required(false)
+            .multiple(false)
+            .long("no-repo-cache")
+            .about("Do not use the on-disk cache of the parsed repository")
+            .long_about(indoc::indoc!(r#"
+                Do not use the on-disk cache of the parsed repository, but load all pkg.toml files
+                from the filesystem.
+                The cache is stored in the XDG cache directory and automatically invalidated if the
+                HEAD commit or any pkg.toml file changes.
+            "#))
+        )
+
+        .arg(Arg::new("profile")
+            .required(false)
+            .multiple(false)
+            .long("profile")
+            .takes_value(true)
+            .value_name("PROFILE")
+            .env("BUTIDO_PROFILE")
+            .about("Use the settings of the configuration profile PROFILE")
+            .long_about(indoc::indoc!(r#"
+                Use the settings of the configuration profile PROFILE, from the table [profile.PROFILE] in the
+                configuration. The settings of the profile override the settings from the configuration files,
+                but are overridden by environment variables and the commandline.
+                Can also be set via environment 'BUTIDO_PROFILE'.
+            "#))
+        )
+
         .arg(Arg::new("database_host")
             .required(false)
             .multiple(false)
@@ -120,6 +172,68 @@ pub fn cli<'a>() -> App<'a> {
             )
         )
 
+        .subcommand(App::new("completions")
+            .version(crate_version!())
+            .about("Print a completion script that also completes packages, versions, endpoints and images")
+            .long_about(indoc::indoc!(r#"
+                Print a completion script for SHELL.
+
+                In contrast to the scripts of "generate-completions", which only know the commandline interface,
+                these scripts call back into butido to complete the names and versions of the packages in the
+                repository and the names of the endpoints and images in the configuration.
+
+                Example:
+
+                    butido completions bash > ~/.local/share/bash-completion/completions/butido
+            "#))
+            .arg(Arg::new("shell")
+                .possible_values(&["bash", "fish", "zsh"])
+                .required(true)
+                .multiple(false)
+                .value_name("SHELL")
+                .about("Shell to print the completion script for")
+            )
+        )
+
+        .subcommand(App::new("__complete")
+            .setting(AppSettings::Hidden)
+            .setting(AppSettings::TrailingVarArg)
+            .about("Complete the last of WORDS, used by the completion scripts")
+            .arg(Arg::new("words")
+                .required(false)
+                .multiple(true)
+                .allow_hyphen_values(true)
+                .setting(ArgSettings::AllowEmptyValues)
+                .value_name("WORDS")
+            )
+        )
+
+        .subcommand(App::new("config")
+            .version(crate_version!())
+            .about("Configuration related commands")
+            .subcommand(App::new("check")
+                .version(crate_version!())
+                .about("Check the configuration and everything it refers to")
+                .long_about(indoc::indoc!(r#"
+                    Load and validate the configuration, then check that:
+
+                        - the database can be connected to
+                        - every endpoint answers a ping
+                        - the configured images are available on every docker endpoint
+                        - the staging, source cache, log and release store directories are writable
+
+                    Prints a report with the result of each check and fails if any check failed.
+                "#))
+                .arg(Arg::new("csv")
+                    .required(false)
+                    .multiple(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .about("Format output as CSV")
+                )
+            )
+        )
+
         .subcommand(App::new("db")
             .version(crate_version!())
             .about("Database CLI interface")
@@ -150,6 +264,18 @@ pub fn cli<'a>() -> App<'a> {
                 .about("Run the database setup")
                 .long_about(indoc::indoc!(r#"
                     Run the database setup migrations
+
+                    Creates the database schema from the migrations that are shipped with the butido
+                    binary, so no external tool is required for setting up the database.
+                "#))
+            )
+
+            .subcommand(App::new("migrate")
+                .version(crate_version!())
+                .about("Apply pending database migrations")
+                .long_about(indoc::indoc!(r#"
+                    Apply all migrations that are shipped with the butido binary, but not yet applied
+                    to the database, and print a summary of what was done.
                 "#))
             )
 
@@ -201,6 +327,12 @@ pub fn cli<'a>() -> App<'a> {
             .subcommand(App::new("submit")
                 .version(crate_version!())
                 .about("Show details about one specific submit")
+                .long_about(indoc::indoc!(r#"
+                    Show everything that is known about one submit: the requested package and
+                    image, the commit of the repository, the environment variables of the jobs,
+                    the status, duration and endpoint of every job and the artifacts the jobs
+                    produced, with the release stores they were released to.
+                "#))
                 .arg(Arg::new("submit")
                     .required(true)
                     .multiple(false)
@@ -209,6 +341,187 @@ pub fn cli<'a>() -> App<'a> {
                     .value_name("SUBMIT")
                     .about("The Submit to show details about")
                 )
+                .arg(Arg::new("json")
+                    .required(false)
+                    .multiple(false)
+                    .long("json")
+                    .takes_value(false)
+                    .about("Print the details as JSON")
+                )
+            )
+
+            .subcommand(App::new("coverage")
+                .version(crate_version!())
+                .about("Report packages that were never built, never released or not built for a long time")
+                .long_about(indoc::indoc!(r#"
+                    Compares the packages of the repository with the jobs and releases in the
+                    database and reports:
+
+                    * the package versions without a successful job,
+                    * the packages whose latest version was built, but never released,
+                    * with --not-built-since, the packages that were not built successfully since
+                      DATE.
+                "#))
+                .arg(Arg::new("not_built_since")
+                    .required(false)
+                    .multiple(false)
+                    .long("not-built-since")
+                    .takes_value(true)
+                    .value_name("DATE")
+                    .about("Report the packages that were not built since DATE, e.g. '90days' or '2022-01-01'")
+                )
+                .arg(Arg::new("csv")
+                    .required(false)
+                    .multiple(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .about("Format output as CSV")
+                )
+                .arg(Arg::new("json")
+                    .required(false)
+                    .multiple(false)
+                    .long("json")
+                    .takes_value(false)
+                    .conflicts_with("csv")
+                    .about("Format output as JSON")
+                )
+            )
+
+            .subcommand(App::new("tree-of-submit")
+                .version(crate_version!())
+                .about("Show the job tree of a submit as recorded in the database")
+                .long_about(indoc::indoc!(r#"
+                    Shows the tree of the jobs of a submit, with the state of each job, how long it
+                    ran and the paths of its artifacts, from the database alone. This works long
+                    after the staging directory of the submit was removed.
+
+                    If the submit was resumed, the tree of each run is shown.
+                "#))
+                .arg(Arg::new("submit")
+                    .required(true)
+                    .multiple(false)
+                    .index(1)
+                    .takes_value(true)
+                    .value_name("SUBMIT")
+                    .validator(uuid_validator)
+                    .about("The submit to show the tree of")
+                )
+                .arg(Arg::new("no_artifacts")
+                    .required(false)
+                    .multiple(false)
+                    .long("no-artifacts")
+                    .about("Do not show the artifacts of the jobs")
+                )
+            )
+
+            .subcommand(App::new("rerun-job")
+                .version(crate_version!())
+                .about("Run a job from the database again, e.g. to debug a flaky build")
+                .long_about(indoc::indoc!(r#"
+                    Runs a job again, as the only job of a new submit. The job runs with the script,
+                    the environment and the image it was recorded with in the database, the package
+                    is taken from the repository and has to have the same patches, options and
+                    sources as when the job ran.
+
+                    The artifacts of the dependencies of the job are taken from the staging
+                    directory of its submit or from the release stores, nothing else is built.
+                "#))
+                .arg(Arg::new("job_uuid")
+                    .required(true)
+                    .multiple(false)
+                    .index(1)
+                    .takes_value(true)
+                    .value_name("JOB")
+                    .validator(uuid_validator)
+                    .about("The job to run again")
+                )
+                .arg(Arg::new("endpoint")
+                    .required(false)
+                    .multiple(true)
+                    .long("endpoint")
+                    .short('e')
+                    .takes_value(true)
+                    .value_name("ENDPOINT")
+                    .about("Only run the job on ENDPOINT (default: any configured endpoint)")
+                )
+                .arg(Arg::new("write-log-file")
+                    .required(false)
+                    .multiple(false)
+                    .long("write-log")
+                    .short('L')
+                    .about("Write the log to the configured log directory as well")
+                )
+            )
+
+            .subcommand(App::new("download-artifact")
+                .version(crate_version!())
+                .about("Copy an artifact out of the stores, verified against its recorded hash")
+                .long_about(indoc::indoc!(r#"
+                    Copies the artifact with the id ARTIFACT, or all artifacts of the job with the
+                    UUID ARTIFACT, to the directory DIR.
+
+                    The artifact is taken from the release stores it was released to (latest
+                    release first), from the staging directory of its submit or from the additional
+                    staging directories. Copies that do not match the hash the artifact was
+                    recorded with are removed and the next store is tried.
+                "#))
+                .arg(Arg::new("artifact")
+                    .required(true)
+                    .multiple(false)
+                    .index(1)
+                    .takes_value(true)
+                    .value_name("ARTIFACT")
+                    .about("The id of the artifact or the UUID of the job that built it")
+                )
+                .arg(Arg::new("to")
+                    .required(true)
+                    .multiple(false)
+                    .long("to")
+                    .takes_value(true)
+                    .value_name("DIR")
+                    .about("The directory to copy the artifact to, created if it does not exist")
+                )
+                .arg(Arg::new("force")
+                    .required(false)
+                    .multiple(false)
+                    .long("force")
+                    .about("Overwrite files that exist in DIR already")
+                )
+            )
+
+            .subcommand(App::new("diff-submits")
+                .version(crate_version!())
+                .about("Compare two submits")
+                .long_about(indoc::indoc!(r#"
+                    Compare two submits with each other.
+
+                    Prints the packages that were added or removed, version changes, changes in the job
+                    status, differences in the environment and differences in the packaging scripts of
+                    the jobs of both submits.
+                "#))
+                .arg(Arg::new("submit_a")
+                    .required(true)
+                    .multiple(false)
+                    .index(1)
+                    .takes_value(true)
+                    .value_name("SUBMIT A")
+                    .about("The submit to compare from")
+                )
+                .arg(Arg::new("submit_b")
+                    .required(true)
+                    .multiple(false)
+                    .index(2)
+                    .takes_value(true)
+                    .value_name("SUBMIT B")
+                    .about("The submit to compare to")
+                )
+                .arg(Arg::new("no_script_diff")
+                    .required(false)
+                    .multiple(false)
+                    .long("no-script-diff")
+                    .takes_value(false)
+                    .about("Do not print the differences of the packaging scripts")
+                )
             )
 
             .subcommand(App::new("submits")
@@ -221,6 +534,13 @@ pub fn cli<'a>() -> App<'a> {
                     .takes_value(false)
                     .about("Format output as CSV")
                 )
+                .arg(arg_format(indoc::indoc!(r#"
+                    Print each row with the handlebars template TEMPLATE instead of a table. This
+                    overrides the `submit_list_format` setting of the configuration.
+
+                    Available fields: {{time}}, {{uuid}}, {{package_name}}, {{package_version}}
+                    and {{submitted_by}}.
+                "#)))
                 .arg(Arg::new("with_pkg")
                     .required(false)
                     .multiple(false)
@@ -275,6 +595,13 @@ pub fn cli<'a>() -> App<'a> {
                     .takes_value(false)
                     .about("Format output as CSV")
                 )
+                .arg(arg_format(indoc::indoc!(r#"
+                    Print each row with the handlebars template TEMPLATE instead of a table. This
+                    overrides the `job_list_format` setting of the configuration.
+
+                    Available fields: {{submit_uuid}}, {{job_uuid}}, {{time}}, {{endpoint}},
+                    {{success}}, {{package_name}} and {{package_version}}.
+                "#)))
 
                 .arg(Arg::new("submit_uuid")
                     .required(false)
@@ -391,6 +718,37 @@ pub fn cli<'a>() -> App<'a> {
                     .value_name("UUID")
                     .about("The id of the Job")
                 )
+                .arg(Arg::new("stderr_only")
+                    .required(false)
+                    .multiple(false)
+                    .long("stderr-only")
+                    .takes_value(false)
+                    .about("Print only the lines the script wrote to stderr")
+                    .long_about(indoc::indoc!(r#"
+                        Print only the lines the script wrote to stderr, e.g. to find the errors of
+                        a build between the output of the compiler on stdout.
+
+                        Jobs that ran before stdout and stderr were recorded separately, and jobs
+                        on sandbox endpoints, have all their output on stdout.
+                    "#))
+                )
+            )
+            .subcommand(App::new("provenance-of")
+                .version(crate_version!())
+                .about("Print the provenance attestations of the artifacts of a job")
+                .long_about(indoc::indoc!(r#"
+                    Print the in-toto/SLSA provenance attestations that were written next to the
+                    artifacts of a job (<artifact>.intoto.json), and the path of their signature,
+                    if they were signed.
+                "#))
+                .arg(Arg::new("job_uuid")
+                    .required(true)
+                    .multiple(false)
+                    .index(1)
+                    .takes_value(true)
+                    .value_name("UUID")
+                    .about("The id of the Job")
+                )
             )
             .subcommand(App::new("releases")
                 .version(crate_version!())
@@ -402,6 +760,21 @@ pub fn cli<'a>() -> App<'a> {
                     .takes_value(false)
                     .about("Format output as CSV")
                 )
+                .arg(arg_format(indoc::indoc!(r#"
+                    Print each row with the handlebars template TEMPLATE instead of a table. This
+                    overrides the `release_list_format` setting of the configuration.
+
+                    Available fields: {{package_name}}, {{package_version}}, {{date}}, {{store}} and
+                    {{path}}.
+                "#)))
+                .arg(Arg::new("json")
+                    .required(false)
+                    .multiple(false)
+                    .long("json")
+                    .takes_value(false)
+                    .conflicts_with_all(&["csv", "format"])
+                    .about("Format output as JSON, with the same fields as the --format template")
+                )
 
                 .arg(arg_older_than_date("List only releases older than DATE"))
                 .arg(arg_newer_than_date("List only releases newer than DATE"))
@@ -424,49 +797,263 @@ pub fn cli<'a>() -> App<'a> {
                     .value_name("PKG")
                     .about("Only list releases for package PKG")
                 )
+                .arg(Arg::new("package_version_constraint")
+                    .required(false)
+                    .multiple(false)
+                    .long("package-version")
+                    .takes_value(true)
+                    .value_name("VERSION_CONSTRAINT")
+                    .about("Only list releases of versions matching VERSION_CONSTRAINT, e.g. '>=1.0, <2'")
+                )
             )
-        )
 
-        .subcommand(App::new("build")
-            .version(crate_version!())
-            .about("Build packages in containers")
+            .subcommand(App::new("export")
+                .version(crate_version!())
+                .about("Export the database to a JSON file")
+                .long_about(indoc::indoc!(r#"
+                    Export the submits, jobs, packages, artifacts and releases, with everything they
+                    refer to, to a JSON file. The rows of every table are ordered by their id, so
+                    exporting the same data always results in the same file.
 
-            .arg(Arg::new("package_name")
-                .required(true)
-                .multiple(false)
-                .index(1)
-                .value_name("NAME")
-            )
-            .arg(Arg::new("package_version")
-                .required(false)
-                .multiple(false)
-                .index(2)
-                .value_name("VERSION")
-                .about("Exact package version to build (string match)")
+                    The file can be imported into another instance with "butido db import".
+                "#))
+                .arg(Arg::new("output")
+                    .required(true)
+                    .multiple(false)
+                    .long("output")
+                    .short('o')
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .about("Write the dump to FILE")
+                )
             )
 
-            .arg(Arg::new("no_verification")
-                .required(false)
-                .multiple(false)
-                .takes_value(false)
-                .long("no-verify")
-                .about("Skip hashsum check")
+            .subcommand(App::new("import")
+                .version(crate_version!())
+                .about("Import a JSON file written by 'db export'")
                 .long_about(indoc::indoc!(r#"
-                    Do not perform a hash sum check on all packages in the dependency tree before starting the build.
+                    Import a dump written by "butido db export" into the database. The database has
+                    to be set up with "butido db setup" and must not contain any data yet. All rows
+                    keep their ids, and the import is done in a single transaction, so nothing is
+                    imported if it fails.
                 "#))
+                .arg(Arg::new("input")
+                    .required(true)
+                    .multiple(false)
+                    .long("input")
+                    .short('i')
+                    .takes_value(true)
+                    .value_name("FILE")
+                    .about("Read the dump from FILE")
+                )
             )
-            .arg(Arg::new("no_lint")
-                .required(false)
-                .multiple(false)
-                .takes_value(false)
-                .long("no-lint")
-                .about("Skip linting")
+
+            .subcommand(App::new("analyze")
+                .version(crate_version!())
+                .about("Report statistics of the tables and the slowest statements from Postgres")
                 .long_about(indoc::indoc!(r#"
-                    Do not perform script linting before starting the build.
+                    Report how often the tables were read sequentially instead of with an index, and
+                    the statements with the longest mean execution time. The statistics of the
+                    statements require the "pg_stat_statements" extension of Postgres (see
+                    "shared_preload_libraries" and "CREATE EXTENSION pg_stat_statements").
                 "#))
+                .arg(Arg::new("csv")
+                    .required(false)
+                    .multiple(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .about("Format output as CSV")
+                )
+                .arg(Arg::new("limit")
+                    .required(false)
+                    .multiple(false)
+                    .long("limit")
+                    .takes_value(true)
+                    .value_name("LIMIT")
+                    .validator(parse_u64)
+                    .about("Report the LIMIT slowest statements (default: 10)")
+                )
             )
 
-            .arg(Arg::new("staging_dir")
+            .subcommand(App::new("audit")
+                .version(crate_version!())
+                .about("List the operations that changed something, with who ran them")
+                .long_about(indoc::indoc!(r#"
+                    List the operations from the audit log, newest first.
+
+                    Starting and canceling submits, releasing, promoting and removing releases,
+                    pruning containers and cache volumes and the garbage collection of the source
+                    cache are recorded with the time, the operator (see `operator` in the
+                    configuration) and their parameters.
+                "#))
+                .arg(Arg::new("csv")
+                    .required(false)
+                    .multiple(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .about("Format output as CSV")
+                )
+                .arg(Arg::new("json")
+                    .required(false)
+                    .multiple(false)
+                    .long("json")
+                    .takes_value(false)
+                    .conflicts_with("csv")
+                    .about("Format output as JSON")
+                )
+                .arg(arg_older_than_date("List only operations older than DATE"))
+                .arg(arg_newer_than_date("List only operations newer than DATE"))
+                .arg(Arg::new("operation")
+                    .required(false)
+                    .multiple(false)
+                    .long("operation")
+                    .takes_value(true)
+                    .value_name("OPERATION")
+                    .about("List only operations OPERATION, e.g. 'release' or 'submit-start'")
+                )
+                .arg(Arg::new("user")
+                    .required(false)
+                    .multiple(false)
+                    .long("user")
+                    .takes_value(true)
+                    .value_name("USER")
+                    .about("List only operations of USER")
+                )
+                .arg(Arg::new("limit")
+                    .required(false)
+                    .multiple(false)
+                    .long("limit")
+                    .takes_value(true)
+                    .value_name("LIMIT")
+                    .validator(parse_u64)
+                    .about("Only list the LIMIT newest operations")
+                )
+            )
+
+            .subcommand(App::new("running")
+                .version(crate_version!())
+                .about("List the jobs that are running or queued right now")
+                .long_about(indoc::indoc!(r#"
+                    List the jobs of all active submits that are running or wait for an endpoint ("queued"),
+                    with their endpoint, their phase, how long they have been running (or waiting) and their progress.
+
+                    The progress is the one reported by the script of the job, or estimated from the durations of earlier
+                    jobs of the package (marked with "~").
+                    Jobs that wait for their dependencies are not listed.
+                "#))
+                .arg(Arg::new("csv")
+                    .required(false)
+                    .multiple(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .about("Format output as CSV")
+                )
+                .arg(Arg::new("submit")
+                    .required(false)
+                    .multiple(false)
+                    .long("submit")
+                    .takes_value(true)
+                    .value_name("SUBMIT")
+                    .validator(uuid_validator)
+                    .about("List only the jobs of the submit SUBMIT")
+                )
+            )
+        )
+
+        .subcommand(App::new("build")
+            .version(crate_version!())
+            .about("Build packages in containers")
+
+            .arg(Arg::new("package_name")
+                .required_unless_present("since")
+                .multiple(false)
+                .index(1)
+                .value_name("NAME")
+            )
+            .arg(Arg::new("package_version")
+                .required(false)
+                .multiple(false)
+                .index(2)
+                .value_name("VERSION")
+                .about("Exact package version to build (string match)")
+            )
+            .arg(Arg::new("since")
+                .required(false)
+                .multiple(false)
+                .long("since")
+                .takes_value(true)
+                .value_name("REV")
+                .conflicts_with_all(&["package_name", "package_version", "also", "staging_dir", "resume", "only_subtree", "result_file", "status_file"])
+                .about("Build the packages affected by the changes since the git revision REV")
+                .long_about(indoc::indoc!(r#"
+                    Build the packages that are affected by the changes between the git revision REV
+                    (a commit, branch or tag) and HEAD of the repository.
+
+                    A package is changed if one of the package definition files it is merged from
+                    or one of its patches changed. All packages that depend on a changed package are
+                    affected as well, transitively. Every affected package that is not in the tree
+                    of another affected package is built in its own submit, like with --also.
+                "#))
+            )
+            .arg(Arg::new("also")
+                .required(false)
+                .multiple(true)
+                .long("also")
+                .takes_value(true)
+                .value_name("NAME[=VERSION]")
+                .conflicts_with_all(&["staging_dir", "resume", "only_subtree", "result_file", "status_file"])
+                .about("Build another package as a separate submit at the same time")
+                .long_about(indoc::indoc!(r#"
+                    Build another package as a separate submit at the same time, e.g.
+                    `butido build a --also b --also c=1.0`.
+
+                    Every package gets its own submit, with its own staging directory, but the
+                    submits run concurrently and share the endpoints: a free endpoint is given to
+                    the submits in turn, so that one large tree does not hold back the others.
+                    The progress bars are grouped by submit. If a submit fails, the others are
+                    still finished.
+                "#))
+            )
+
+            .arg(Arg::new("no_verification")
+                .required(false)
+                .multiple(false)
+                .takes_value(false)
+                .long("no-verify")
+                .about("Skip hashsum check")
+                .long_about(indoc::indoc!(r#"
+                    Do not perform a hash sum check on all packages in the dependency tree before starting the build.
+                "#))
+            )
+            .arg(Arg::new("hermetic")
+                .required(false)
+                .multiple(false)
+                .takes_value(false)
+                .long("hermetic")
+                .conflicts_with("no_verification")
+                .about("Build only with the declared inputs of the packages")
+                .long_about(indoc::indoc!(r#"
+                    Build only with the declared inputs of the packages, and mark the artifacts as hermetic.
+
+                    The jobs run without network access and without cache volumes.
+                    No environment variables are passed from the host environment ("pass_env"), all variables have to be passed with --env or --env-file and have to be allowed in the configuration, even if "check_env_names" is off.
+                    The build does not start if a source does not match its hash or if a patch is not committed to the repository.
+                    Only artifacts of earlier hermetic builds are reused, and only if their files still have the hash that was recorded when they were built.
+                "#))
+            )
+            .arg(Arg::new("no_lint")
+                .required(false)
+                .multiple(false)
+                .takes_value(false)
+                .long("no-lint")
+                .about("Skip linting")
+                .long_about(indoc::indoc!(r#"
+                    Do not perform script linting before starting the build.
+                "#))
+            )
+            .arg(deny_lint_warnings_arg().conflicts_with("no_lint"))
+
+            .arg(Arg::new("staging_dir")
                 .required(false)
                 .multiple(false)
                 .long("staging-dir")
@@ -476,13 +1063,52 @@ pub fn cli<'a>() -> App<'a> {
                 .about("Do not throw dice on staging directory name, but hardcode for this run.")
             )
 
+            .arg(Arg::new("resume")
+                .required(false)
+                .multiple(false)
+                .long("resume")
+                .takes_value(true)
+                .value_name("SUBMIT")
+                .conflicts_with("staging_dir")
+                .validator(uuid_validator)
+                .about("Resume the submit SUBMIT, which did not finish")
+                .long_about(indoc::indoc!(r#"
+                    Resume the submit SUBMIT, e.g. after butido crashed or the host rebooted.
+
+                    The states of the jobs of every submit are recorded in the database while
+                    they run. When resuming, the jobs that finished in an earlier run of the
+                    submit are not run again, if their artifacts are still in the staging
+                    directory of the submit or in a release store. All other jobs run as usual.
+
+                    The package, version and image have to be the same as in the earlier run.
+                "#))
+            )
+
+            .arg(Arg::new("additional_staging_dir")
+                .required(false)
+                .multiple(true)
+                .long("additional-staging-dir")
+                .takes_value(true)
+                .value_name("PATH")
+                .validator(dir_exists_validator)
+                .about("Also use artifacts from this staging dir when resolving dependencies (can be passed multiple times)")
+                .long_about(indoc::indoc!(r#"
+                    Also use artifacts from this staging directory when resolving dependencies.
+
+                    Can be passed multiple times. The directories are searched in the order they are
+                    passed, after the staging directory of this submit and before the
+                    `additional_staging_directories` from the configuration.
+                    Artifacts are never written to these directories.
+                "#))
+            )
+
             .arg(Arg::new("shebang")
                 .required(false)
                 .multiple(false)
                 .long("shebang")
                 .takes_value(true)
                 .value_name("BANG")
-                .about("Overwrite the configured shebang line")
+                .about("Overwrite the configured shebang line (not the shebang of packages that set their own)")
             )
 
             .arg(Arg::new("env")
@@ -498,6 +1124,204 @@ pub fn cli<'a>() -> App<'a> {
                 "#))
             )
 
+            .arg(arg_env_file())
+
+            .arg(arg_option("Switch a build option of the packages on or off"))
+
+            .arg(Arg::new("progress_tree")
+                .required(false)
+                .multiple(false)
+                .long("progress-tree")
+                .takes_value(false)
+                .about("Show the progress of the jobs as a tree, mirroring the dependencies")
+            )
+
+            .arg(Arg::new("pin")
+                .required(false)
+                .multiple(true)
+                .long("pin")
+                .takes_value(true)
+                .value_name("NAME=VERSION")
+                .validator(pin_validator)
+                .about("Force the version of a dependency anywhere in the tree")
+                .long_about(indoc::indoc!(r#"
+                    Force the version VERSION of the package NAME for all dependencies on it in
+                    the tree, regardless of their version constraints, e.g. to test the tree
+                    against an upcoming version of a library: `butido build a --pin openssl=3.0.0`.
+
+                    The version has to exist in the repository. The pins are recorded with the
+                    submit and shown in the plan of --dry-run.
+                "#))
+            )
+
+            .arg(Arg::new("only_subtree")
+                .required(false)
+                .multiple(false)
+                .long("only-subtree")
+                .alias("until")
+                .takes_value(true)
+                .value_name("PKG")
+                .about("Only build the dependency PKG of the package, with its dependencies")
+                .long_about(indoc::indoc!(r#"
+                    Only build the dependency PKG of the package, with its dependencies, e.g. to
+                    iterate on a library in the middle of the tree.
+
+                    The whole tree of the package is still resolved and checked for consistency,
+                    but only the packages of the subtree are verified, linted and built.
+                "#))
+            )
+            .arg(Arg::new("only_subtree_version")
+                .required(false)
+                .multiple(false)
+                .long("only-subtree-version")
+                .takes_value(true)
+                .value_name("VERSION")
+                .requires("only_subtree")
+                .about("Exact version of the dependency given with --only-subtree, if it is in the tree in several versions")
+            )
+            .arg(Arg::new("no_deps")
+                .required(false)
+                .multiple(false)
+                .long("no-deps")
+                .takes_value(false)
+                .about("Do not build the dependencies, use their artifacts from the stores")
+                .long_about(indoc::indoc!(r#"
+                    Do not build the dependencies of the package, but use their artifacts from the
+                    release and staging stores, as if they were reused.
+
+                    Fails before any job runs and lists the dependencies that have no artifacts in
+                    the stores, if there are any.
+                "#))
+            )
+
+            .arg(Arg::new("exclude")
+                .required(false)
+                .multiple(true)
+                .long("exclude")
+                .takes_value(true)
+                .value_name("NAME")
+                .about("Do not build the package NAME, fail if the tree requires it")
+                .long_about(indoc::indoc!(r#"
+                    Do not build the package NAME, e.g. because it is known to be broken.
+
+                    Fails before any job runs if the tree requires the package, and shows the
+                    dependencies that lead to it. With --substitute-released, the released
+                    artifacts of the package are used instead.
+                "#))
+            )
+            .arg(Arg::new("substitute_released")
+                .required(false)
+                .multiple(false)
+                .long("substitute-released")
+                .takes_value(false)
+                .requires("exclude")
+                .about("Use the released artifacts of the packages given with --exclude")
+                .long_about(indoc::indoc!(r#"
+                    Use the released artifacts of the packages given with --exclude instead of
+                    building them, like --no-deps does for all dependencies.
+
+                    The artifacts have to be built in the same image and with the same
+                    environment, but not necessarily with the same script. Fails before any job
+                    runs if there are no released artifacts for an excluded package. The excluded
+                    and substituted packages are recorded in the audit log.
+                "#))
+            )
+
+            .arg(Arg::new("dry_run")
+                .required(false)
+                .multiple(false)
+                .long("dry-run")
+                .takes_value(false)
+                .about("Only print the jobs that would be built and the critical path, do not build")
+                .long_about(indoc::indoc!(r#"
+                    Only print the jobs that would be built and the critical path, do not build.
+
+                    The critical path is the longest chain of dependencies. Jobs on it are
+                    scheduled first if there are more jobs ready than endpoints can run, because
+                    it determines how long the whole submit takes.
+                "#))
+            )
+
+            .arg(Arg::new("result_file")
+                .required(false)
+                .multiple(false)
+                .long("result-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .about("Write a JSON summary of the result of the submit to FILE")
+                .long_about(indoc::indoc!(r#"
+                    Write a JSON summary of the result of the submit to FILE, so that CI pipelines
+                    do not have to parse the output of butido.
+
+                    The summary contains the submit UUID, whether it succeeded and why it ended,
+                    its duration, and for each job its state ("done", "failed", "reused", or
+                    "waiting"/"running" if the submit stopped before the job finished), its
+                    duration, its error and its artifacts with their sha256 hashes and whether
+                    they were built or reused. Failed jobs have the last `build_error_lines` lines
+                    of their log and the phase they errored in as well.
+                "#))
+            )
+            .arg(Arg::new("status_file")
+                .required(false)
+                .multiple(false)
+                .long("status-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .about("Write the progress of the submit to FILE while it runs")
+                .long_about(indoc::indoc!(r#"
+                    Write the progress of the submit to FILE as JSON while it runs, every 10
+                    seconds and once more when the jobs finished, so that long submits can be
+                    watched without access to the terminal.
+
+                    The file contains the submit UUID, the number of jobs and how many of them
+                    are waiting, running, done, reused or failed, the running jobs and the
+                    estimated number of seconds until the submit finished ("eta_secs"). The
+                    estimate is based on the durations of the last successful jobs of the
+                    packages in the database, it is null if none are known.
+                "#))
+            )
+
+            .arg(Arg::new("log_socket")
+                .required(false)
+                .multiple(false)
+                .long("log-socket")
+                .takes_value(true)
+                .value_name("ADDR")
+                .validator(socket_addr_validator)
+                .about("Stream the logs of the running jobs on ADDR, as server-sent events")
+                .long_about(indoc::indoc!(r#"
+                    Stream the logs of the running jobs on ADDR (e.g. 127.0.0.1:8099), as
+                    server-sent events, so dashboards can show the output of the builds live.
+
+                    `GET /` streams the logs of all jobs, `GET /jobs/<UUID>` the log of one job.
+                    The event name is the kind of the log item ("line", "progress", "phase" or
+                    "state"), the data is a JSON object with the job, the package name and
+                    version and the log item.
+
+                    The logs are streamed without authentication, so bind to a local address or
+                    use a proxy if the logs contain anything confidential.
+                "#))
+            )
+
+            .arg(Arg::new("secret")
+                .required(false)
+                .multiple(true)
+                .long("secret")
+                .takes_value(true)
+                .value_name("NAME=@FILE|NAME=!COMMAND")
+                .validator(secret_validator)
+                .about("Pass a secret to all build jobs")
+                .long_about(indoc::indoc!(r#"
+                    Pass a secret as environment variable to each build job.
+
+                    With NAME=@FILE the value is read from FILE, with NAME=!COMMAND the value is the
+                    output of COMMAND, which is run with `sh -c`.
+
+                    Other than variables passed with --env, secrets are not recorded in the database
+                    or in the metadata files of the artifacts and they are replaced in the job logs.
+                "#))
+            )
+
             .arg(Arg::new("image")
                 .required(true)
                 .multiple(false)
@@ -508,6 +1332,26 @@ pub fn cli<'a>() -> App<'a> {
                 .about("Name of the docker image to use")
             )
 
+            .arg(Arg::new("constraint")
+                .required(false)
+                .multiple(true)
+                .long("constraint")
+                .takes_value(true)
+                .value_name("KEY=VALUE")
+                .validator(constraint_validator)
+                .about("Only run the jobs on endpoints with the label KEY=VALUE (or KEY)")
+                .long_about(indoc::indoc!(r#"
+                    Only run the jobs of the build on endpoints that satisfy all constraints.
+
+                    A constraint KEY=VALUE is satisfied by an endpoint with the label KEY=VALUE in
+                    the configuration, e.g. `--constraint arch=x86_64`. A constraint KEY without a
+                    value is satisfied by an endpoint with the label KEY or with any label
+                    KEY=..., e.g. `--constraint fast-io`.
+
+                    The constraints of the packages in their [endpoints] table apply as well.
+                "#))
+            )
+
             .arg(Arg::new("write-log-file")
                 .required(false)
                 .multiple(false)
@@ -549,6 +1393,7 @@ pub fn cli<'a>() -> App<'a> {
                 ])
                 .about("Specify which dependency types are to be checked. By default, all are checked")
             )
+            .arg(arg_maintainer())
         )
         .subcommand(App::new("dependencies-of")
             .version(crate_version!())
@@ -589,7 +1434,7 @@ pub fn cli<'a>() -> App<'a> {
         .subcommand(App::new("versions-of")
             .version(crate_version!())
             .alias("versions")
-            .about("List the versions of a package")
+            .about("List the versions of a package with their sources and whether they were released")
             .arg(Arg::new("package_name")
                 .required(true)
                 .multiple(false)
@@ -597,6 +1442,122 @@ pub fn cli<'a>() -> App<'a> {
                 .value_name("PACKAGE_NAME")
                 .about("The name of the package")
             )
+            .arg(Arg::new("csv")
+                .required(false)
+                .multiple(false)
+                .long("csv")
+                .takes_value(false)
+                .about("Format output as CSV")
+            )
+        )
+        .subcommand(App::new("new-version")
+            .version(crate_version!())
+            .about("Create the definition of a new version of a package from its latest version")
+            .long_about(indoc::indoc!(r#"
+                Copies the pkg.toml of the latest version of the package to a directory for the new
+                version next to it, with the version and the URLs of the sources updated. URLs that
+                contain the old version are changed to the new version, URLs that are templates
+                are kept.
+
+                The hashes of the sources are only updated with --download, otherwise they have to
+                be updated by hand. The new files are not committed, so they can be reviewed.
+            "#))
+            .arg(Arg::new("package_name")
+                .required(true)
+                .multiple(false)
+                .index(1)
+                .value_name("PACKAGE_NAME")
+                .about("The name of the package")
+            )
+            .arg(Arg::new("package_version")
+                .required(true)
+                .multiple(false)
+                .index(2)
+                .value_name("VERSION")
+                .about("The new version")
+            )
+            .arg(Arg::new("download")
+                .required(false)
+                .multiple(false)
+                .long("download")
+                .short('d')
+                .about("Download the sources of the new version and fill in their hashes")
+            )
+        )
+        .subcommand(App::new("repo")
+            .version(crate_version!())
+            .about("Commands for the whole repository")
+            .subcommand(App::new("graph")
+                .version(crate_version!())
+                .about("Print the dependency graph of all packages")
+                .long_about(indoc::indoc!(r#"
+                    Prints the graph of all packages of the repository and their build, runtime
+                    and external dependencies. A dependency has an edge to every package version
+                    that satisfies it, conditions of dependencies are not evaluated.
+
+                    The DOT output can be rendered with graphviz, e.g.:
+
+                        butido repo graph | dot -Tsvg > graph.svg
+                "#))
+                .arg(Arg::new("format")
+                    .required(false)
+                    .multiple(false)
+                    .long("format")
+                    .short('f')
+                    .takes_value(true)
+                    .possible_values(&["dot", "json"])
+                    .default_value("dot")
+                    .value_name("FORMAT")
+                    .about("The output format")
+                )
+            )
+            .subcommand(App::new("check")
+                .version(crate_version!())
+                .about("Check that all dependencies in the repository resolve")
+                .long_about(indoc::indoc!(r#"
+                    Checks that every build and runtime dependency of every package is satisfied by
+                    a package of the repository (and that the configured version resolution selects
+                    one of its versions) or by an external dependency that is declared in the
+                    repository. Conditions of dependencies are not evaluated.
+
+                    Dependencies that are provided by several packages are reported as warnings,
+                    they only resolve in a build that contains exactly one of the providers.
+
+                    Exits with an error if a dependency does not resolve, e.g. for CI.
+                "#))
+                .arg(Arg::new("strict")
+                    .required(false)
+                    .multiple(false)
+                    .long("strict")
+                    .about("Fail on warnings as well")
+                )
+                .arg(Arg::new("csv")
+                    .required(false)
+                    .multiple(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .about("Format output as CSV")
+                )
+            )
+            .subcommand(App::new("migrate")
+                .version(crate_version!())
+                .about("Rewrite the package definition files to the current schema version")
+                .long_about(indoc::indoc!(r#"
+                    Rewrites all package definition files of the repository that declare an older
+                    schema version (with the "schema_version" key, files without it are of version
+                    1) to the current version. Only what the format change requires is rewritten,
+                    the rest of the files is kept as it is.
+
+                    Files with unknown keys are not migrated, as the current version does not allow
+                    them. The migrated files are left uncommitted for review.
+                "#))
+                .arg(Arg::new("dry_run")
+                    .required(false)
+                    .multiple(false)
+                    .long("dry-run")
+                    .about("Only print which files would be migrated")
+                )
+            )
         )
         .subcommand(App::new("env-of")
             .version(crate_version!())
@@ -673,6 +1634,75 @@ pub fn cli<'a>() -> App<'a> {
             )
         )
 
+        .subcommand(App::new("explain-rebuild")
+            .version(crate_version!())
+            .about("Explain why a package is built instead of reusing artifacts of earlier builds")
+            .long_about(indoc::indoc!(r#"
+                Compare a package and its dependencies with the earlier jobs of them, like a build
+                does when it looks for artifacts to reuse, and report why no earlier job matches:
+                the script (with a diff), the image, the environment, the options, the patches or
+                the dependencies differ, or the artifacts are not in any store anymore.
+
+                Pass the same image, environment and options as for the build.
+            "#))
+            .arg(Arg::new("package_name")
+                .required(true)
+                .multiple(false)
+                .index(1)
+                .value_name("NAME")
+                .about("The name of the package")
+            )
+            .arg(Arg::new("package_version")
+                .required(true)
+                .multiple(false)
+                .index(2)
+                .value_name("VERSION")
+                .about("The exact version of the package")
+            )
+            .arg(Arg::new("image")
+                .required(true)
+                .multiple(false)
+                .takes_value(true)
+                .value_name("IMAGE NAME")
+                .short('I')
+                .long("image")
+                .about("Name of the docker image the package would be built in")
+            )
+            .arg(Arg::new("env")
+                .required(false)
+                .multiple(true)
+                .short('E')
+                .long("env")
+                .validator(env_pass_validator)
+                .about("Environment variable that would be passed to the build jobs")
+            )
+            .arg(arg_env_file())
+            .arg(arg_option("Build option that would be used for the build"))
+            .arg(Arg::new("staging_dir")
+                .required(false)
+                .multiple(false)
+                .long("staging-dir")
+                .takes_value(true)
+                .value_name("PATH")
+                .validator(dir_exists_validator)
+                .about("Also consider the artifacts in this staging dir")
+            )
+            .arg(Arg::new("all")
+                .required(false)
+                .multiple(false)
+                .long("all")
+                .short('a')
+                .about("Explain all earlier jobs of a package, not only the latest ones")
+            )
+            .arg(Arg::new("no_color")
+                .required(false)
+                .multiple(false)
+                .long("no-color")
+                .takes_value(false)
+                .about("Do not colorize the script diffs")
+            )
+        )
+
         .subcommand(App::new("find-pkg")
             .version(crate_version!())
             .about("Find a package by regex")
@@ -704,8 +1734,9 @@ pub fn cli<'a>() -> App<'a> {
                 .multiple(false)
                 .long("all")
                 .short('A')
-                .about("Same as: -SDpEFPs --denied-images --allowed-images (all flags enabled)")
+                .about("Same as: -SDpEFPs --maintainers --denied-images --allowed-images (all flags enabled)")
             )
+            .arg(arg_maintainer())
 
             .arg(Arg::new("show_sources")
                 .required(false)
@@ -749,6 +1780,13 @@ pub fn cli<'a>() -> App<'a> {
                 .about("Show the patches of the package")
             )
 
+            .arg(Arg::new("show_maintainers")
+                .required(false)
+                .multiple(false)
+                .long("maintainers")
+                .about("Show the maintainers of the package")
+            )
+
             .arg(Arg::new("show_env")
                 .required(false)
                 .multiple(false)
@@ -805,40 +1843,107 @@ pub fn cli<'a>() -> App<'a> {
             .about("Handle package sources")
             .subcommand(App::new("verify")
                 .version(crate_version!())
-                .about("Hash-check all source files")
+                .about("Hash-check all source files")
+                .arg(Arg::new("package_name")
+                    .required(false)
+                    .multiple(false)
+                    .index(1)
+                    .value_name("PKG")
+                    .about("Verify the sources of this package (optional, if left out, all packages are checked)")
+                )
+                .arg(Arg::new("package_version")
+                    .required(false)
+                    .multiple(false)
+                    .index(2)
+                    .value_name("VERSION")
+                    .about("Verify the sources of this package version (optional, if left out, all packages are checked)")
+                )
+
+                .arg(Arg::new("matching")
+                    .required(false)
+                    .multiple(false)
+                    .long("matching")
+                    .takes_value(true)
+                    .value_name("REGEX")
+                    .about("Verify all packages where the package name matches REGEX")
+                )
+                .arg(Arg::new("max_parallel")
+                    .required(false)
+                    .multiple(false)
+                    .long("max-parallel")
+                    .takes_value(true)
+                    .value_name("N")
+                    .about("Maximum number of sources that are verified in parallel (default: 16)")
+                )
+
+                .group(ArgGroup::new("verify-one-or-many")
+                    .args(&["package_name", "matching"])
+                    .required(true)
+                )
+            )
+            .subcommand(App::new("list-missing")
+                .version(crate_version!())
+                .about("List packages where the source is missing")
+            )
+            .subcommand(App::new("gc")
+                .version(crate_version!())
+                .about("Remove sources from the cache that are not used by any package anymore")
+                .long_about(indoc::indoc!(r#"
+                    Remove all files from the source cache that are not referenced by any package
+                    in the repository.
+
+                    Sources are stored by their hash and linked to the package they belong to, so
+                    identical sources used by multiple packages are only stored once. This removes
+                    the links of packages that do not exist anymore and all stored sources that are
+                    not linked by any package.
+                "#))
+                .arg(Arg::new("dry_run")
+                    .required(false)
+                    .multiple(false)
+                    .long("dry-run")
+                    .about("Only print what would be removed")
+                )
+            )
+            .subcommand(App::new("update-hash")
+                .version(crate_version!())
+                .about("Download sources and write their hashes to the package definitions")
+                .long_about(indoc::indoc!(r#"
+                    Download the sources of a package (or of the selected versions of it) and
+                    replace the hashes in the pkg.toml of the package with the hashes of the
+                    downloads, of the hash type the package declares.
+
+                    Only the hash in the table of the source is changed, the rest of the file is
+                    kept as it is. With --missing, only sources with an empty hash (hash = "") are
+                    updated, of all packages if no package is given.
+                    Sources that are downloaded manually are hashed as they are in the cache.
+                "#))
                 .arg(Arg::new("package_name")
                     .required(false)
                     .multiple(false)
                     .index(1)
                     .value_name("PKG")
-                    .about("Verify the sources of this package (optional, if left out, all packages are checked)")
+                    .about("Update the hashes of the sources of this package")
                 )
                 .arg(Arg::new("package_version")
                     .required(false)
                     .multiple(false)
                     .index(2)
-                    .value_name("VERSION")
-                    .about("Verify the sources of this package version (optional, if left out, all packages are checked)")
+                    .value_name("VERSION_CONSTRAINT")
+                    .about("Update the hashes of the sources of this package version (optional, if left out, all versions are updated)")
                 )
-
-                .arg(Arg::new("matching")
+                .arg(Arg::new("missing")
                     .required(false)
                     .multiple(false)
-                    .long("matching")
-                    .takes_value(true)
-                    .value_name("REGEX")
-                    .about("Verify all packages where the package name matches REGEX")
+                    .long("missing")
+                    .takes_value(false)
+                    .about("Only update the sources without a hash")
                 )
-
-                .group(ArgGroup::new("verify-one-or-many")
-                    .args(&["package_name", "matching"])
+                .group(ArgGroup::new("update-one-or-missing")
+                    .args(&["package_name", "missing"])
+                    .multiple(true)
                     .required(true)
                 )
             )
-            .subcommand(App::new("list-missing")
-                .version(crate_version!())
-                .about("List packages where the source is missing")
-            )
             .subcommand(App::new("url")
                 .version(crate_version!())
                 .about("Show the URL of the source of a package")
@@ -857,6 +1962,81 @@ pub fn cli<'a>() -> App<'a> {
                     .about("Verify the sources of this package version (optional, if left out, all packages are checked)")
                 )
             )
+            .subcommand(App::new("url-check")
+                .version(crate_version!())
+                .about("Check whether the upstream URLs of the sources still work")
+                .long_about(indoc::indoc!(r#"
+                    Check the upstream URLs of the sources of all packages (or the selected ones)
+                    with HEAD requests, falling back to GET requests for the first byte if the
+                    server does not answer HEAD requests.
+
+                    Reports URLs that do not work ("dead"), that redirect to another URL and where
+                    the size reported by the server differs from the source in the cache
+                    ("size-mismatch"). Fails if any URL is dead or has a size mismatch, redirects
+                    are only reported. Sources that are downloaded manually are not checked.
+                "#))
+                .arg(Arg::new("package_name")
+                    .required(false)
+                    .multiple(false)
+                    .index(1)
+                    .value_name("PKG")
+                    .about("Check the sources of this package (optional, if left out, all packages are checked)")
+                )
+                .arg(Arg::new("package_version")
+                    .required(false)
+                    .multiple(false)
+                    .index(2)
+                    .value_name("VERSION_CONSTRAINT")
+                    .about("Check the sources of this package version (optional, if left out, all versions are checked)")
+                )
+                .arg(Arg::new("matching")
+                    .required(false)
+                    .multiple(false)
+                    .long("matching")
+                    .takes_value(true)
+                    .value_name("REGEX")
+                    .conflicts_with("package_name")
+                    .about("Check all packages where the package name matches REGEX")
+                )
+                .arg(Arg::new("timeout")
+                    .required(false)
+                    .multiple(false)
+                    .long("timeout")
+                    .takes_value(true)
+                    .value_name("TIMEOUT")
+                    .about("Set timeout for each request in seconds")
+                )
+                .arg(Arg::new("max_parallel")
+                    .required(false)
+                    .multiple(false)
+                    .long("max-parallel")
+                    .takes_value(true)
+                    .value_name("N")
+                    .about("Maximum number of URLs that are checked in parallel (default: 16)")
+                )
+                .arg(Arg::new("all")
+                    .required(false)
+                    .multiple(false)
+                    .long("all")
+                    .takes_value(false)
+                    .about("Also list the URLs that work")
+                )
+                .arg(Arg::new("csv")
+                    .required(false)
+                    .multiple(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .about("Format output as CSV")
+                )
+                .arg(Arg::new("json")
+                    .required(false)
+                    .multiple(false)
+                    .long("json")
+                    .takes_value(false)
+                    .conflicts_with("csv")
+                    .about("Format output as JSON")
+                )
+            )
             .subcommand(App::new("download")
                 .version(crate_version!())
                 .about("Download the source for one or multiple packages")
@@ -903,6 +2083,33 @@ pub fn cli<'a>() -> App<'a> {
                     .value_name("TIMEOUT")
                     .about("Set timeout for download in seconds")
                 )
+
+                .arg(Arg::new("rate_limit")
+                    .required(false)
+                    .multiple(false)
+                    .long("rate-limit")
+                    .takes_value(true)
+                    .value_name("RATE")
+                    .about("Limit the bandwidth of all downloads together (bytes per second, K/M/G suffixes are supported)")
+                    .long_about(indoc::indoc!(r#"
+                        Limit the bandwidth of all downloads together, in bytes per second.
+                        The suffixes "K", "M" and "G" are supported, e.g. "500K".
+                        Overrides the 'source_download_rate_limit' setting from the configuration.
+                    "#))
+                )
+
+                .arg(Arg::new("max_parallel")
+                    .required(false)
+                    .multiple(false)
+                    .long("max-parallel")
+                    .takes_value(true)
+                    .value_name("N")
+                    .about("Maximum number of parallel downloads")
+                    .long_about(indoc::indoc!(r#"
+                        Maximum number of sources that are downloaded in parallel.
+                        Overrides the 'source_download_max_parallel' setting from the configuration.
+                    "#))
+                )
             )
             .subcommand(App::new("of")
                 .version(crate_version!())
@@ -963,15 +2170,146 @@ pub fn cli<'a>() -> App<'a> {
                 )
             )
 
+            .subcommand(App::new("dedup")
+                .version(crate_version!())
+                .about("Deduplicate the artifacts in the staging and release stores")
+                .long_about(indoc::indoc!(r#"
+                    Artifacts are stored by their content hash and linked into the staging and
+                    release stores, so identical artifacts are only stored once.
+
+                    This links the artifacts of stores that were created before this was the case
+                    to the object store and removes stored objects that are not linked by any
+                    artifact anymore, e.g. after a staging directory was removed.
+                "#))
+            )
+
+            .subcommand(App::new("stats")
+                .version(crate_version!())
+                .about("Show statistics about the release stores")
+                .long_about(indoc::indoc!(r#"
+                    Shows the number of artifacts and their total size for each release store, the
+                    oldest and the newest release and the number of released versions per package.
+
+                    The artifacts are counted on the filesystem, the releases are taken from the
+                    database. The size is the size of all artifacts, even if identical artifacts
+                    are only stored once.
+                "#))
+                .arg(Arg::new("csv")
+                    .required(false)
+                    .multiple(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .about("Format output as CSV")
+                )
+                .arg(Arg::new("release_store_name")
+                    .required(false)
+                    .multiple(false)
+                    .long("to")
+                    .takes_value(true)
+                    .value_name("RELEASE_STORE_NAME")
+                    .about("Show statistics for this release store only")
+                )
+            )
+
+            .subcommand(App::new("push")
+                .version(crate_version!())
+                .about("Push the artifacts of a release store to a remote")
+                .long_about(indoc::indoc!(r#"
+                    Mirrors all artifacts of a release store, with their metadata files, to a
+                    remote from the 'release_remotes' of the configuration, e.g. a deployment
+                    server. rsync skips the files that are on the remote already, sftp and HTTP
+                    remotes get all files again.
+
+                    Afterwards, the files on the remote are verified. rsync remotes compare the
+                    checksums of the files, sftp and HTTP remotes only their sizes.
+                "#))
+                .arg(Arg::new("release_store_name")
+                    .required(true)
+                    .multiple(false)
+                    .index(1)
+                    .value_name("RELEASE_STORE_NAME")
+                    .about("The release store to push")
+                )
+                .arg(Arg::new("remote_name")
+                    .required(true)
+                    .multiple(false)
+                    .index(2)
+                    .value_name("REMOTE")
+                    .about("The remote to push to")
+                )
+            )
+
+            .subcommand(App::new("promote")
+                .version(crate_version!())
+                .about("Promote released artifacts from one release store to another")
+                .long_about(indoc::indoc!(r#"
+                    The release stores can be used as channels, e.g. "testing" and "stable":
+                    artifacts are released to the first one and promoted to the next one after
+                    they were tested.
+
+                    This releases artifacts that are released in the --from store to the --to
+                    store as well and records who promoted them when in the database. Either a
+                    single artifact is promoted, by its path in the release store, or all
+                    artifacts of a submit that are released in the --from store.
+                "#))
+                .arg(Arg::new("artifact_or_submit")
+                    .required(true)
+                    .multiple(false)
+                    .index(1)
+                    .value_name("ARTIFACT|SUBMIT")
+                    .about("The path of the artifact in the release store, or the UUID of a submit")
+                )
+                .arg(Arg::new("from_store")
+                    .required(true)
+                    .multiple(false)
+                    .long("from")
+                    .value_name("RELEASE_STORE_NAME")
+                    .about("The release store to promote from")
+                )
+                .arg(Arg::new("to_store")
+                    .required(true)
+                    .multiple(false)
+                    .long("to")
+                    .value_name("RELEASE_STORE_NAME")
+                    .about("The release store to promote to")
+                )
+                .arg(Arg::new("package_do_update")
+                    .required(false)
+                    .multiple(false)
+                    .long("update")
+                    .about("Replace artifacts that exist in the release store to promote to already")
+                )
+                .arg(Arg::new("quiet")
+                    .required(false)
+                    .multiple(false)
+                    .long("quiet")
+                    .short('q')
+                    .about("Don't print the paths of the promoted files")
+                )
+            )
+
             .subcommand(App::new("new")
                 .version(crate_version!())
                 .about("Release artifacts")
+                .long_about(indoc::indoc!(r#"
+                    Releases the artifacts of a submit that are not released yet to a release
+                    store.
+
+                    Only a subset of the artifacts can be released, e.g. without debug or test
+                    artifacts, by selecting them with --include and --exclude patterns or
+                    interactively with --select.
+
+                    Instead of the submit UUID, the manifest that the build wrote into the staging
+                    directory of the submit (butido-manifest.json) can be passed. The artifacts are
+                    then taken from the manifest and from the directory of the manifest, and they
+                    must match the hashes in the manifest.
+                "#))
                 .arg(Arg::new("submit_uuid")
                     .required(true)
                     .multiple(false)
                     .index(1)
-                    .value_name("SUBMIT")
-                    .about("The submit uuid from which to release a package")
+                    .value_name("SUBMIT|MANIFEST")
+                    .about("The submit uuid from which to release a package, or the path of the manifest of the submit")
                 )
                 .arg(Arg::new("release_store_name")
                     .required(true)
@@ -1022,6 +2360,40 @@ pub fn cli<'a>() -> App<'a> {
                     .long("non-interactive")
                     .about("Dont be interactive (only with --update at the moment)")
                     .requires("package_do_update")
+                    .conflicts_with("select")
+                )
+                .arg(Arg::new("include")
+                    .required(false)
+                    .multiple(true)
+                    .long("include")
+                    .takes_value(true)
+                    .value_name("PATTERN")
+                    .about("Only release the artifacts whose path or file name matches one of these glob patterns")
+                    .long_about(indoc::indoc!(r#"
+                        Only release the artifacts whose path or file name matches one of these
+                        glob patterns. '*' matches any number of characters, '?' matches one
+                        character, e.g. '*.rpm'.
+                    "#))
+                )
+                .arg(Arg::new("exclude")
+                    .required(false)
+                    .multiple(true)
+                    .long("exclude")
+                    .takes_value(true)
+                    .value_name("PATTERN")
+                    .about("Do not release the artifacts whose path or file name matches one of these glob patterns, e.g. '*-debuginfo-*'")
+                )
+                .arg(Arg::new("select")
+                    .required(false)
+                    .multiple(false)
+                    .long("select")
+                    .short('s')
+                    .about("Select the artifacts to release interactively")
+                    .long_about(indoc::indoc!(r#"
+                        Lists the artifacts to release (after applying --include and --exclude)
+                        with checkboxes, all checked, so that the ones not to release can be
+                        unchecked. Space toggles an artifact, enter releases the checked ones.
+                    "#))
                 )
                 .arg(Arg::new("quiet")
                     .required(false)
@@ -1034,6 +2406,131 @@ pub fn cli<'a>() -> App<'a> {
 
         )
 
+        .subcommand(App::new("export")
+            .version(crate_version!())
+            .about("Export released artifacts to other formats")
+            .subcommand(App::new("oci")
+                .version(crate_version!())
+                .about("Export the released artifacts of a package as OCI image")
+                .long_about(indoc::indoc!(r#"
+                    Writes an OCI image layout with the released artifacts of a package (and
+                    their metadata files) in /packages, in the same directories as in the release
+                    store. Containers can use the artifacts from there, e.g. with
+                    "COPY --from=IMAGE /packages /packages".
+
+                    The image has a single, uncompressed layer. The same artifacts always result
+                    in the same image.
+                "#))
+                .arg(Arg::new("package_name")
+                    .required(true)
+                    .multiple(false)
+                    .index(1)
+                    .value_name("PKG")
+                    .about("The name of the package")
+                )
+                .arg(Arg::new("package_version")
+                    .required(true)
+                    .multiple(false)
+                    .index(2)
+                    .value_name("VERSION")
+                    .about("The exact version of the package")
+                )
+                .arg(Arg::new("output")
+                    .required(true)
+                    .multiple(false)
+                    .long("output")
+                    .short('o')
+                    .takes_value(true)
+                    .value_name("DIR")
+                    .about("The directory to write the image layout to, must not exist or be empty")
+                )
+                .arg(Arg::new("with_runtime_dependencies")
+                    .required(false)
+                    .multiple(false)
+                    .long("with-runtime-dependencies")
+                    .about("Include the released artifacts of the runtime dependencies, transitively")
+                    .long_about(indoc::indoc!(r#"
+                        Include the released artifacts of the runtime dependencies of the package,
+                        transitively.
+
+                        The conditions of the dependencies are checked without an image and with
+                        the default values of the build options.
+                    "#))
+                )
+                .arg(Arg::new("release_store_name")
+                    .required(false)
+                    .multiple(false)
+                    .long("from")
+                    .takes_value(true)
+                    .value_name("RELEASE_STORE_NAME")
+                    .about("Only use artifacts released to this release store")
+                )
+                .arg(Arg::new("tag")
+                    .required(false)
+                    .multiple(false)
+                    .long("tag")
+                    .takes_value(true)
+                    .value_name("TAG")
+                    .about("The name of the image in the layout, defaults to the version of the package")
+                )
+                .arg(Arg::new("architecture")
+                    .required(false)
+                    .multiple(false)
+                    .long("arch")
+                    .takes_value(true)
+                    .value_name("ARCH")
+                    .default_value("amd64")
+                    .about("The architecture of the image, e.g. amd64 or arm64")
+                )
+                .arg(Arg::new("push")
+                    .required(false)
+                    .multiple(false)
+                    .long("push")
+                    .takes_value(true)
+                    .value_name("REFERENCE")
+                    .about("Push the image to a registry afterwards, e.g. registry.example.com/packages/foo:1.0 (requires skopeo)")
+                )
+            )
+        )
+
+        .subcommand(App::new("license-report")
+            .version(crate_version!())
+            .about("List the licenses of a package and its runtime dependencies and check them against the license policy")
+            .long_about(indoc::indoc!(r#"
+                Lists the licenses ("license" in pkg.toml) of a package and of all packages it
+                depends on at runtime, transitively, and checks them against the
+                "license_policy" in the configuration.
+
+                Fails if a package has no license (unless "allow_missing" is set), a license that
+                is not accepted or a license that is not a valid license expression, so it can be
+                used as a compliance check in CI.
+
+                The conditions of the dependencies are checked without an image and with the
+                default values of the build options.
+            "#))
+            .arg(Arg::new("target")
+                .required(true)
+                .multiple(false)
+                .index(1)
+                .value_name("PKG|SUBMIT")
+                .about("The name of the package or the UUID of a submit, whose requested package is checked")
+            )
+            .arg(Arg::new("package_version")
+                .required(false)
+                .multiple(false)
+                .index(2)
+                .value_name("VERSION")
+                .about("The exact version of the package, all versions if not given")
+            )
+            .arg(Arg::new("csv")
+                .required(false)
+                .multiple(false)
+                .long("csv")
+                .takes_value(false)
+                .about("Format output as CSV")
+            )
+        )
+
         .subcommand(App::new("lint")
             .version(crate_version!())
             .about("Lint the package script of one or multiple packages")
@@ -1051,6 +2548,129 @@ pub fn cli<'a>() -> App<'a> {
                 .value_name("VERSION_CONSTRAINT")
                 .about("A version constraint to search for (optional), E.G. '=1.0.0'")
             )
+            .arg(deny_lint_warnings_arg())
+        )
+
+        .subcommand(App::new("verify-reproducibility")
+            .version(crate_version!())
+            .about("Rebuild a job with identical inputs and compare the artifacts")
+            .long_about(indoc::indoc!(r#"
+                Rebuild a previously built job of a package with the same script, image and
+                environment as recorded in the database and compare the hashes of the rebuilt
+                artifacts to the recorded ones.
+
+                The image digest is taken from the metadata files of the recorded artifacts. The
+                dependencies are taken from the stores, like during a build.
+                Exits with an error if any artifact differs.
+            "#))
+            .arg(Arg::new("package_name")
+                .required(true)
+                .multiple(false)
+                .index(1)
+                .value_name("NAME")
+                .about("The name of the package")
+            )
+            .arg(Arg::new("package_version")
+                .required(true)
+                .multiple(false)
+                .index(2)
+                .value_name("VERSION")
+                .about("The exact version of the package")
+            )
+            .arg(Arg::new("job_uuid")
+                .required(false)
+                .multiple(false)
+                .long("job")
+                .takes_value(true)
+                .value_name("UUID")
+                .about("Reproduce this job (default: the latest job of the package that produced artifacts)")
+            )
+            .arg(Arg::new("endpoint")
+                .required(false)
+                .multiple(false)
+                .long("endpoint")
+                .takes_value(true)
+                .value_name("ENDPOINT")
+                .about("Rebuild on this endpoint (default: the first configured endpoint)")
+            )
+            .arg(Arg::new("keep")
+                .required(false)
+                .multiple(false)
+                .long("keep")
+                .about("Keep the rebuilt artifacts instead of removing them after the comparison")
+            )
+        )
+
+        .subcommand(App::new("verify-artifacts")
+            .version(crate_version!())
+            .about("Check that the artifacts of a submit or release store did not change after they were built")
+            .long_about(indoc::indoc!(r#"
+                Hashes the artifacts of a submit in its staging directory, or the artifacts
+                released to a release store, again and reports the ones that are missing, were
+                truncated or were modified since they were built, compared to the checksums and
+                sizes recorded in the database.
+
+                Artifacts that were built before checksums were recorded are not verified. Fails
+                if any artifact changed.
+            "#))
+            .arg(Arg::new("target")
+                .required(true)
+                .multiple(false)
+                .index(1)
+                .value_name("SUBMIT|STORE")
+                .about("The UUID of a submit or the name of a release store")
+            )
+            .arg(Arg::new("csv")
+                .required(false)
+                .multiple(false)
+                .long("csv")
+                .takes_value(false)
+                .about("Format output as CSV")
+            )
+            .arg(Arg::new("json")
+                .required(false)
+                .multiple(false)
+                .long("json")
+                .takes_value(false)
+                .conflicts_with("csv")
+                .about("Format output as JSON")
+            )
+        )
+
+        .subcommand(App::new("verify-stores")
+            .version(crate_version!())
+            .about("Check the staging and release stores against the database")
+            .long_about(indoc::indoc!(r#"
+                Reports artifacts the database has that are missing in the stores, files in the
+                stores the database has no artifact for and artifacts whose hash changed since they
+                were built (for artifacts whose hash was recorded), with a suggestion how to repair
+                each of them.
+
+                Staging directories that were removed completely are not reported. Fails if any
+                inconsistency was found.
+            "#))
+            .arg(Arg::new("csv")
+                .required(false)
+                .multiple(false)
+                .long("csv")
+                .takes_value(false)
+                .about("Format output as CSV")
+            )
+            .arg(Arg::new("json")
+                .required(false)
+                .multiple(false)
+                .long("json")
+                .takes_value(false)
+                .conflicts_with("csv")
+                .about("Format output as JSON")
+            )
+            .arg(Arg::new("no_hash")
+                .required(false)
+                .multiple(false)
+                .long("no-hash")
+                .takes_value(false)
+                .about("Do not verify the hashes of the artifacts, which requires reading all of them")
+            )
         )
 
         .subcommand(App::new("tree-of")
@@ -1099,6 +2719,47 @@ pub fn cli<'a>() -> App<'a> {
                     conditions on dependencies.
                 "#))
             )
+            .arg(arg_option("Build option to use for the conditions of dependencies"))
+        )
+
+        .subcommand(App::new("script-diff")
+            .version(crate_version!())
+            .about("Show the differences between the scripts of two versions of a package")
+            .long_about(indoc::indoc!(r#"
+                Render the scripts of two versions of a package, as they would be run in a build,
+                and print a unified diff of them.
+
+                This shows what actually changes in the build between two versions, including the
+                changes that come from the package definitions up the tree.
+            "#))
+            .arg(Arg::new("package_name")
+                .required(true)
+                .multiple(false)
+                .index(1)
+                .value_name("PACKAGE_NAME")
+                .about("The name of the package")
+            )
+            .arg(Arg::new("version_a")
+                .required(true)
+                .multiple(false)
+                .index(2)
+                .value_name("VERSION_A")
+                .about("The old version")
+            )
+            .arg(Arg::new("version_b")
+                .required(true)
+                .multiple(false)
+                .index(3)
+                .value_name("VERSION_B")
+                .about("The new version")
+            )
+            .arg(Arg::new("no_color")
+                .required(false)
+                .multiple(false)
+                .long("no-color")
+                .takes_value(false)
+                .about("Do not colorize the diff")
+            )
         )
 
         .subcommand(App::new("metrics")
@@ -1106,6 +2767,107 @@ pub fn cli<'a>() -> App<'a> {
             .about("Print metrics about butido")
         )
 
+        .subcommand(App::new("cancel")
+            .version(crate_version!())
+            .about("Cancel a running submit")
+            .long_about(indoc::indoc!(r#"
+                Cancel a running submit.
+
+                The cancellation is recorded in the database. The butido process that runs the
+                submit notices it within a few seconds, stops scheduling jobs and kills the
+                containers of the running jobs. The submit can be continued with
+                'build --resume SUBMIT' later.
+            "#))
+            .arg(Arg::new("submit")
+                .required(true)
+                .multiple(false)
+                .index(1)
+                .value_name("SUBMIT")
+                .validator(uuid_validator)
+                .about("The UUID of the submit to cancel")
+            )
+        )
+
+        .subcommand(App::new("tui")
+            .version(crate_version!())
+            .about("Show a dashboard of the running submits on the terminal")
+            .long_about(indoc::indoc!(r#"
+                Show a dashboard of the running submits on the terminal.
+
+                The dashboard shows the submits with running jobs and their jobs from the
+                database, and how many containers run on each endpoint. With --log-socket, it
+                also shows the phase, state and live log of the jobs of the build that streams
+                its logs on that address.
+
+                Keys: "j"/"k" select a job or scroll its log, "tab", "enter" and "esc" move the
+                focus between the jobs and the log, "G" follows the log, "q" quits.
+            "#))
+            .arg(Arg::new("log_socket")
+                .required(false)
+                .multiple(false)
+                .long("log-socket")
+                .takes_value(true)
+                .value_name("ADDR")
+                .validator(socket_addr_validator)
+                .about("Show the live logs streamed by 'build --log-socket ADDR'")
+            )
+            .arg(Arg::new("refresh")
+                .required(false)
+                .multiple(false)
+                .long("refresh")
+                .takes_value(true)
+                .value_name("SECS")
+                .default_value("2")
+                .validator(parse_u64)
+                .about("Query the database and endpoints every SECS seconds")
+            )
+        )
+
+        .subcommand(App::new("schedule")
+            .version(crate_version!())
+            .about("Start builds periodically, e.g. nightly rebuilds")
+            .long_about(indoc::indoc!(r#"
+                Start builds periodically, e.g. nightly rebuilds of the toolchain.
+
+                The builds are configured in the 'schedules' table of the configuration. Every
+                build is started as 'butido build' with the arguments that were passed before
+                'schedule', so it is recorded as a normal submit.
+            "#))
+            .subcommand(App::new("list")
+                .version(crate_version!())
+                .about("List the configured schedules and when they start their next build")
+            )
+            .subcommand(App::new("run")
+                .version(crate_version!())
+                .about("Start the scheduled builds when they are due, until butido is stopped")
+                .long_about(indoc::indoc!(r#"
+                    Start the scheduled builds when they are due, until butido is stopped.
+
+                    The builds that are due at the same time run one after another. Builds that
+                    are due while scheduled builds are running are skipped.
+                "#))
+                .arg(Arg::new("only")
+                    .required(false)
+                    .multiple(true)
+                    .long("only")
+                    .takes_value(true)
+                    .value_name("NAME")
+                    .about("Only start the builds of the schedule NAME")
+                )
+            )
+            .subcommand(App::new("trigger")
+                .version(crate_version!())
+                .about("Start the build of a schedule now")
+                .arg(Arg::new("name")
+                    .required(true)
+                    .multiple(false)
+                    .index(1)
+                    .value_name("NAME")
+                    .about("The name of the schedule")
+                )
+            )
+        )
+
         .subcommand(App::new("endpoint")
             .version(crate_version!())
             .about("Endpoint maintentance commands")
@@ -1149,14 +2911,40 @@ pub fn cli<'a>() -> App<'a> {
                     .about("Format output as CSV")
                 )
             )
+            .subcommand(App::new("health")
+                .version(crate_version!())
+                .about("Show whether the endpoint(s) are healthy")
+                .long_about(indoc::indoc!(r#"
+                    Show whether the endpoint(s) are healthy, i.e. answer a ping within their timeout.
+
+                    During a build, the endpoints are checked in the interval configured with
+                    `docker.health_check_interval` and unhealthy endpoints get no new jobs until they
+                    are healthy again.
+                "#))
+                .arg(Arg::new("csv")
+                    .required(false)
+                    .multiple(false)
+                    .long("csv")
+                    .takes_value(false)
+                    .about("Format output as CSV")
+                )
+            )
             .subcommand(App::new("containers")
                 .version(crate_version!())
                 .about("Work with the containers of the endpoint(s)")
                 .subcommand(App::new("prune")
                     .version(crate_version!())
                     .about("Remove exited containers")
+                    .long_about(indoc::indoc!(r#"
+                        Remove exited containers
+
+                        With `docker.failed_container_retention_hours`, the containers of failed jobs
+                        are kept for debugging and removed by builds after they expired. This removes
+                        them earlier, e.g. with `--older-than 24h`.
+                    "#))
                     .arg(arg_older_than_date("Prune only containers older than DATE"))
                     .arg(arg_newer_than_date("Prune only containers newer than DATE"))
+                    .args(args_container_labels())
                 )
                 .subcommand(App::new("stop")
                     .version(crate_version!())
@@ -1204,6 +2992,7 @@ pub fn cli<'a>() -> App<'a> {
 
                     .arg(arg_older_than_date("List only containers older than DATE"))
                     .arg(arg_newer_than_date("List only containers newer than DATE"))
+                    .args(args_container_labels())
                 )
                 .subcommand(App::new("top")
                     .version(crate_version!())
@@ -1331,6 +3120,91 @@ pub fn cli<'a>() -> App<'a> {
                     )
                 )
             )
+            .subcommand(App::new("sync-image")
+                .version(crate_version!())
+                .about("Copy an image from one endpoint to the other endpoint(s), pulling it only once")
+                .long_about(indoc::indoc!(r#"
+                    Exports the image from an endpoint that has it (or from the endpoint of --from)
+                    and loads it on the other endpoints (or on the endpoint given before
+                    "sync-image"), like "docker save" and "docker load", so the image is only
+                    pulled from the registry once. If no endpoint has the image, it is pulled on
+                    one of them first.
+
+                    Endpoints that have the image already are skipped, unless --force is given.
+                "#))
+                .arg(Arg::new("image")
+                    .required(true)
+                    .multiple(false)
+                    .index(1)
+                    .value_name("IMAGE")
+                    .about("The image to copy, e.g. debian:bullseye")
+                )
+                .arg(Arg::new("from")
+                    .required(false)
+                    .multiple(false)
+                    .long("from")
+                    .takes_value(true)
+                    .value_name("ENDPOINT_NAME")
+                    .about("The endpoint to copy the image from")
+                )
+                .arg(Arg::new("force")
+                    .required(false)
+                    .multiple(false)
+                    .long("force")
+                    .about("Load the image on endpoints that have it already as well")
+                )
+            )
+            .subcommand(App::new("volumes")
+                .version(crate_version!())
+                .about("Manage the cache volumes on endpoint(s)")
+                .subcommand(App::new("list")
+                    .version(crate_version!())
+                    .about("List cache volumes on endpoint(s)")
+                    .arg(Arg::new("csv")
+                        .required(false)
+                        .multiple(false)
+                        .long("csv")
+                        .takes_value(false)
+                        .about("List top output as CSV")
+                    )
+                )
+                .subcommand(App::new("create")
+                    .version(crate_version!())
+                    .about("Create the configured cache volumes on endpoint(s)")
+                    .long_about(indoc::indoc!(r#"
+                        Create the configured cache volumes on endpoint(s).
+
+                        Volumes of caches with scope "package" are not created, because they are
+                        created when a package is built.
+                    "#))
+                )
+                .subcommand(App::new("prune")
+                    .version(crate_version!())
+                    .about("Remove cache volumes from endpoint(s)")
+                    .long_about(indoc::indoc!(r#"
+                        Remove cache volumes from endpoint(s).
+
+                        By default, only volumes of caches that are not in the configuration anymore
+                        are removed.
+                    "#))
+                    .arg(Arg::new("all")
+                        .required(false)
+                        .multiple(false)
+                        .long("all")
+                        .takes_value(false)
+                        .conflicts_with("cache")
+                        .about("Remove all cache volumes")
+                    )
+                    .arg(Arg::new("cache")
+                        .required(false)
+                        .multiple(true)
+                        .long("cache")
+                        .takes_value(true)
+                        .value_name("NAME")
+                        .about("Remove the volumes of these caches")
+                    )
+                )
+            )
         )
 }
 
@@ -1372,6 +3246,82 @@ fn script_arg_no_highlight<'a>() -> clap::Arg<'a> {
         .conflicts_with("script_highlight")
 }
 
+fn arg_maintainer<'a>() -> clap::Arg<'a> {
+    Arg::new("maintainer")
+        .required(false)
+        .multiple(false)
+        .long("maintainer")
+        .takes_value(true)
+        .value_name("PATTERN")
+        .about("Only list packages with a maintainer that contains PATTERN (ignoring case), e.g. a name or mail address")
+}
+
+fn deny_lint_warnings_arg<'a>() -> clap::Arg<'a> {
+    Arg::new("deny_lint_warnings")
+        .required(false)
+        .multiple(false)
+        .takes_value(false)
+        .long("deny-lint-warnings")
+        .about("Fail if the linter reports warnings, not only if it fails")
+        .long_about(indoc::indoc!(r#"
+            Fail the linting if the linter reports a warning or an error, even if it exits
+            successfully, e.g. in CI. Only diagnostics in the format of gcc are recognized
+            ("-:LINE:COLUMN: warning: MESSAGE", like `shellcheck -f gcc -` prints them).
+        "#))
+}
+
+fn arg_option(about: &str) -> Arg<'_> {
+    Arg::new("option")
+        .required(false)
+        .multiple(true)
+        .takes_value(true)
+        .long("opt")
+        .value_name("NAME=on|off")
+        .validator(option_validator)
+        .about(about)
+        .long_about(indoc::indoc!(r#"
+            Switch a build option of the packages on or off, e.g. 'ssl=on'.
+
+            Options are declared with their default values in the 'options' table of the
+            packages and are used in the conditions of dependencies and in the scripts.
+            Can be passed multiple times.
+        "#))
+}
+
+fn option_validator(s: &str) -> Result<(), String> {
+    crate::package::parse_option_setting(s).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn constraint_validator(s: &str) -> Result<(), String> {
+    s.parse::<crate::package::EndpointConstraint>().map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn arg_env_file<'a>() -> clap::Arg<'a> {
+    Arg::new("env_file")
+        .required(false)
+        .multiple(true)
+        .long("env-file")
+        .takes_value(true)
+        .value_name("PATH")
+        .about("Pass the environment variables in this dotenv file to all build jobs")
+        .long_about(indoc::indoc!(r#"
+            Pass the environment variables in this file to all build jobs, like with --env.
+
+            The file has one KEY=VALUE assignment per line (optionally prefixed with "export"),
+            values can be quoted with single quotes (taken literally) or double quotes (with
+            escapes like \n), lines starting with # are comments.
+            Variables passed with --env override the ones in the files, later files override
+            earlier ones.
+        "#))
+}
+
+fn pin_validator(s: &str) -> Result<(), String> {
+    match s.split_once('=') {
+        Some((name, version)) if !name.is_empty() && !version.is_empty() => Ok(()),
+        _ => Err(format!("'{}' is not a pin, expected NAME=VERSION", s)),
+    }
+}
+
 /// Naive check whether 's' is a 'key=value' pair or an existing environment variable
 ///
 /// TODO: Clean up this spaghetti code
@@ -1401,6 +3351,14 @@ fn env_pass_validator(s: &str) -> Result<(), String> {
     }
 }
 
+/// Check whether 's' is a secret reference, 'NAME=@FILE' or 'NAME=!COMMAND'
+fn secret_validator(s: &str) -> Result<(), String> {
+    match s.split_once('=') {
+        Some((name, source)) if !name.is_empty() && (source.starts_with('@') || source.starts_with('!')) => Ok(()),
+        _ => Err(String::from("Expected NAME=@FILE or NAME=!COMMAND")),
+    }
+}
+
 fn dir_exists_validator(s: &str) -> Result<(), String> {
     if PathBuf::from(&s).is_dir() {
         Ok(())
@@ -1409,6 +3367,53 @@ fn dir_exists_validator(s: &str) -> Result<(), String> {
     }
 }
 
+/// The --format argument of a listing
+fn arg_format(long_about: &str) -> Arg<'_> {
+    Arg::new("format")
+        .required(false)
+        .multiple(false)
+        .long("format")
+        .takes_value(true)
+        .value_name("TEMPLATE")
+        .conflicts_with("csv")
+        .about("Print each row with the handlebars template TEMPLATE")
+        .long_about(long_about)
+}
+
+/// The filters on the labels of the containers of jobs
+fn args_container_labels<'a>() -> [Arg<'a>; 4] {
+    [
+        Arg::new("submit")
+            .required(false)
+            .multiple(false)
+            .long("submit")
+            .takes_value(true)
+            .value_name("SUBMIT")
+            .about("Only the containers of the jobs of SUBMIT")
+            .validator(uuid_validator),
+        Arg::new("package")
+            .required(false)
+            .multiple(false)
+            .long("package")
+            .takes_value(true)
+            .value_name("NAME")
+            .about("Only the containers of the jobs of the package NAME"),
+        Arg::new("label_endpoint")
+            .required(false)
+            .multiple(false)
+            .long("job-endpoint")
+            .takes_value(true)
+            .value_name("ENDPOINT")
+            .about("Only the containers of the jobs that were scheduled on ENDPOINT"),
+        Arg::new("only_jobs")
+            .required(false)
+            .multiple(false)
+            .long("only-jobs")
+            .takes_value(false)
+            .about("Only the containers of the jobs of butido, not other containers on the endpoints"),
+    ]
+}
+
 fn arg_older_than_date(about: &str) -> Arg<'_> {
     Arg::new("older_than")
         .required(false)
@@ -1486,6 +3491,14 @@ fn parse_date_from_string(s: &str) -> std::result::Result<(), String> {
         })
 }
 
+fn socket_addr_validator(s: &str) -> std::result::Result<(), String> {
+    std::net::SocketAddr::from_str(s).map_err(|e| e.to_string()).map(|_| ())
+}
+
+fn uuid_validator(s: &str) -> std::result::Result<(), String> {
+    uuid::Uuid::parse_str(s).map_err(|e| e.to_string()).map(|_| ())
+}
+
 fn parse_usize(s: &str) -> std::result::Result<(), String> {
     usize::from_str(s) .map_err(|e| e.to_string()).map(|_| ())
 }
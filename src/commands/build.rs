@@ -10,6 +10,7 @@
 
 //! Implementation of the 'build' subcommand
 
+use std::collections::HashMap;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
@@ -23,7 +24,6 @@ use anyhow::Result;
 use clap::ArgMatches;
 use colored::Colorize;
 use diesel::ExpressionMethods;
-use diesel::PgConnection;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 use itertools::Itertools;
@@ -33,15 +33,19 @@ use tokio_stream::StreamExt;
 use uuid::Uuid;
 
 use crate::config::*;
+use crate::db::DbPool;
+use crate::filestore::ExternalArtifactCache;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
 use crate::filestore::path::StoreRoot;
 use crate::job::JobResource;
-use crate::log::LogItem;
 use crate::orchestrator::OrchestratorSetup;
 use crate::package::Dag;
+use crate::package::EndpointConstraint;
 use crate::package::PackageName;
+use crate::package::PackageOptions;
 use crate::package::PackageVersion;
+use crate::package::PhaseName;
 use crate::package::Shebang;
 use crate::package::condition::ConditionData;
 use crate::repository::Repository;
@@ -57,12 +61,12 @@ pub async fn build(
     repo_root: &Path,
     matches: &ArgMatches,
     progressbars: ProgressBars,
-    database_connection: PgConnection,
+    database_pool: DbPool,
     config: &Configuration,
     repo: Repository,
     repo_path: &Path,
 ) -> Result<()> {
-    use crate::db::models::{EnvVar, GitHash, Image, Job, Package, Submit};
+    use crate::db::models::{EnvVar, GitHash, Image, Package, Submit};
 
     let git_repo = git2::Repository::open(repo_path)
         .with_context(|| anyhow!("Opening repository at {}", repo_path.display()))?;
@@ -82,30 +86,50 @@ pub async fn build(
         .map(String::from)
         .map(ImageName::from)
         .unwrap(); // safe by clap
-    if config.docker().verify_images_present()
-        && !config
-            .docker()
-            .images()
-            .iter()
-            .any(|img| image_name == *img)
-    {
-        return Err(anyhow!(
-            "Requested build image {} is not in the configured images", image_name
-        ))
-        .with_context(|| anyhow!("Available images: {}", config.docker().images().iter().join(", ")))
-        .with_context(|| anyhow!("Image present verification failed"))
-        .map_err(Error::from);
-    }
+
+    // If the configured image is pinned to a digest, the build uses the pinned image, even if it
+    // was requested by its name only
+    let image_name = match config.docker().images().iter().find(|img| image_name.matches(img)) {
+        Some(configured) => configured.clone(),
+        None if config.docker().verify_images_present() => {
+            return Err(anyhow!(
+                "Requested build image {} is not in the configured images", image_name
+            ))
+            .with_context(|| anyhow!("Available images: {}", config.docker().images().iter().join(", ")))
+            .with_context(|| anyhow!("Image present verification failed"))
+            .map_err(Error::from);
+        },
+        None => image_name,
+    };
+    image_name.check_digest()?;
 
     debug!("Getting repository HEAD");
     let hash_str = crate::util::git::get_repo_head_commit_hash(&git_repo)?;
     trace!("Repository HEAD = {}", hash_str);
-    let phases = config.available_phases();
+    let branch = crate::util::git::get_repo_head_branch(&git_repo)?;
+    let submitted_by = crate::util::submitter(config, Some(&git_repo));
+    debug!("Submitting as {}", submitted_by);
+    let phases = config.phases_for_image(Some(&image_name));
+    if phases != config.available_phases() {
+        info!("Building with the phases configured for {}: {}", image_name, phases.iter().map(PhaseName::as_str).join(", "));
+    }
 
+    let constraints = matches.values_of("constraint")
+        .into_iter()
+        .flatten()
+        .map(|c| c.parse::<EndpointConstraint>())
+        .collect::<Result<Vec<_>>>()?;
     let mut endpoint_configurations = config
         .docker()
         .endpoints()
         .iter()
+        .filter(|(ep_name, ep_cfg)| {
+            let satisfied = constraints.iter().all(|c| c.is_satisfied_by(ep_cfg.labels()));
+            if !satisfied {
+                debug!("Endpoint {} does not satisfy the constraints, not using it", ep_name);
+            }
+            satisfied
+        })
         .map(|(ep_name, ep_cfg)| {
             crate::endpoint::EndpointConfiguration::builder()
                 .endpoint_name(ep_name.clone())
@@ -113,9 +137,17 @@ pub async fn build(
                 .required_images(config.docker().images().clone())
                 .required_docker_versions(config.docker().docker_versions().clone())
                 .required_docker_api_versions(config.docker().docker_api_versions().clone())
+                .negotiate_docker_api_version(config.docker().negotiate_docker_api_version())
+                .min_docker_api_version(config.docker().min_docker_api_version().clone())
+                .pull_missing_images(config.docker().pull_missing_images())
+                .failed_container_retention_hours(config.docker().failed_container_retention_hours())
+                .container_labels(config.docker().container_labels().clone())
                 .build()
         })
         .collect::<Vec<_>>();
+    if endpoint_configurations.is_empty() {
+        return Err(anyhow!("No endpoint satisfies the constraints {}", constraints.iter().join(", ")))
+    }
     {
         // Because we're loading always sequencially, to have a bit more spread over the endpoints,
         // shuffle the endpoints here. Not a perfect solution, but a working one.
@@ -125,66 +157,103 @@ pub async fn build(
     }
     info!("Endpoint config build");
 
-    let pname = matches
-        .value_of("package_name")
-        .map(String::from)
-        .map(PackageName::from)
-        .unwrap(); // safe by clap
-
-    let pvers = matches
-        .value_of("package_version")
-        .map(String::from)
-        .map(PackageVersion::from);
-    info!("We want {} ({:?})", pname, pvers);
+    // Every requested package is built in its own submit
+    let mut requested = if let Some(since) = matches.value_of("since") {
+        let affected = affected_packages_since(&git_repo, &repo, repo_path, since)?;
+        if affected.is_empty() {
+            info!("No package is affected by the changes since {}", since);
+            writeln!(std::io::stdout(), "No package is affected by the changes since {}", since)?;
+            return Ok(())
+        }
+        affected
+    } else {
+        let pname = matches
+            .value_of("package_name")
+            .map(String::from)
+            .map(PackageName::from)
+            .unwrap(); // safe by clap
+        let pname = repo.resolve_name(&pname)?;
 
-    let additional_env = matches
-        .values_of("env")
-        .unwrap_or_default()
-        .map(crate::util::env::parse_to_env)
-        .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?;
+        let pvers = matches
+            .value_of("package_version")
+            .map(String::from)
+            .map(PackageVersion::from);
+        info!("We want {} ({:?})", pname, pvers);
+        vec![(pname, pvers)]
+    };
 
-    let packages = if let Some(pvers) = pvers {
-        debug!("Searching for package with version: '{}' '{}'", pname, pvers);
-        repo.find(&pname, &pvers)
+    // Hermetic builds only get the environment variables that are passed explicitly, none from
+    // the host environment
+    let hermetic = matches.is_present("hermetic");
+    let additional_env = if hermetic {
+        crate::commands::util::cli_env(matches)?
     } else {
-        debug!("Searching for package by name: '{}'", pname);
-        repo.find_by_name(&pname)
+        crate::commands::util::additional_env(matches, config)?
     };
-    debug!("Found {} relevant packages", packages.len());
 
-    // We only support building one package per call.
-    // Everything else is invalid
-    if packages.len() > 1 {
-        return Err(anyhow!(
-            "Found multiple packages ({}). Cannot decide which one to build",
-            packages.len()
-        ));
+    let options = matches
+        .values_of("option")
+        .unwrap_or_default()
+        .map(crate::package::parse_option_setting)
+        .collect::<Result<PackageOptions>>()?;
+
+    for also in matches.values_of("also").into_iter().flatten() {
+        let (name, version) = match also.split_once('=') {
+            Some((name, version)) => (name, Some(PackageVersion::from(version.to_string()))),
+            None => (also, None),
+        };
+        let name = repo.resolve_name(&PackageName::from(name.to_string()))?;
+        info!("We want {} ({:?}) as well", name, version);
+        requested.push((name, version));
     }
-    let package = *packages
-        .get(0)
-        .ok_or_else(|| anyhow!("Found no package."))?;
+    let packages = requested.iter()
+        .map(|(pname, pvers)| find_package(&repo, pname, pvers.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(package) = packages.iter().duplicates_by(|p| (p.name(), p.version())).next() {
+        return Err(anyhow!("Package {} {} is requested more than once", package.name(), package.version()))
+    }
+    let concurrent = packages.len() > 1;
+
+    let pins = matches.values_of("pin")
+        .into_iter()
+        .flatten()
+        .map(|pin| {
+            let (name, version) = pin.split_once('=').unwrap(); // safe by clap validator
+            let name = repo.resolve_name(&PackageName::from(name.to_string()))?;
+            let version = PackageVersion::from(version.to_string());
+            if repo.find(&name, &version).is_empty() {
+                return Err(anyhow!("Pinned package {} {} not found in the repository", name, version))
+            }
+            Ok((name, version))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    let submit_pins = pins.iter()
+        .map(|(name, version)| format!("{}={}", name, version))
+        .sorted()
+        .collect::<Vec<_>>();
+    let resolver = config.resolver().clone().with_overrides(pins);
+
+    let excluded = matches.values_of("exclude")
+        .into_iter()
+        .flatten()
+        .map(|name| repo.resolve_name(&PackageName::from(name.to_string())))
+        .collect::<Result<Vec<_>>>()?;
+    let substitute_released = matches.is_present("substitute_released");
 
     let release_stores = config
         .release_stores()
         .iter()
         .map(|storename| {
-            let bar_release_loading = progressbars.bar()?;
-
             let p = config.releases_directory().join(storename);
-            debug!("Loading release directory: {}", p.display());
-            let r = ReleaseStore::load(StoreRoot::new(p)?, &bar_release_loading);
-            if r.is_ok() {
-                bar_release_loading.finish_with_message("Loaded releases successfully");
-            } else {
-                bar_release_loading.finish_with_message("Failed to load releases");
-            }
-            r.map(Arc::new)
+            debug!("Opening release directory: {}", p.display());
+            StoreRoot::new(p).map(ReleaseStore::open).map(Arc::new)
         })
-        .collect::<Result<Vec<_>>>()?;
-
-    let (staging_store, staging_dir, submit_id) = {
-        let bar_staging_loading = progressbars.bar()?;
+        .collect::<Result<Vec<_>>>()
+        .map_err(crate::error::Error::Store)?;
 
+    // The --staging-dir and --resume arguments conflict with building several packages
+    let mut staging = vec![];
+    for _ in packages.iter() {
         let (submit_id, p) = if let Some(staging_dir) = matches.value_of("staging_dir").map(PathBuf::from) {
             info!(
                 "Setting staging dir to {} for this run",
@@ -202,6 +271,15 @@ pub async fn build(
                 .with_context(|| anyhow!("Seems not to be a submit UUID: {}", uuid))?;
 
             (uuid, staging_dir)
+        } else if let Some(submit_id) = matches.value_of("resume") {
+            let submit_id = Uuid::parse_str(submit_id)
+                .with_context(|| anyhow!("Seems not to be a submit UUID: {}", submit_id))?;
+            let staging_dir = config
+                .staging_directory()
+                .join(submit_id.hyphenated().to_string());
+            info!("Resuming submit {}, with staging dir {}", submit_id, staging_dir.display());
+
+            (submit_id, staging_dir)
         } else {
             let submit_id = uuid::Uuid::new_v4();
             let staging_dir = config
@@ -215,87 +293,206 @@ pub async fn build(
             tokio::fs::create_dir_all(&p).await?;
         }
 
-        debug!("Loading staging directory: {}", p.display());
-        let r = StagingStore::load(StoreRoot::new(p.clone())?, &bar_staging_loading);
-        if r.is_ok() {
-            bar_staging_loading.finish_with_message("Loaded staging successfully");
-        } else {
-            bar_staging_loading.finish_with_message("Failed to load staging");
-        }
-        r.map(RwLock::new).map(Arc::new).map(|store| (store, p, submit_id))?
-    };
+        debug!("Opening staging directory: {}", p.display());
+        let r = StoreRoot::new(p.clone())
+            .map(StagingStore::open)
+            .map(|mut store| {
+                store.set_compression(config.artifact_compression().clone());
+                store.set_ownership(config.artifact_ownership().clone());
+                store
+            });
+        staging.push(r.map(RwLock::new).map(Arc::new).map(|store| (store, p, submit_id)).map_err(crate::error::Error::Store)?);
+    }
 
-    let dag = {
-        let bar_tree_building = progressbars.bar()?;
-        let condition_data = ConditionData {
-            image_name: Some(&image_name),
-            env: &additional_env,
-        };
+    let mut additional_staging_stores = matches
+        .values_of("additional_staging_dir")
+        .into_iter()
+        .flatten()
+        .map(PathBuf::from)
+        .chain(config.additional_staging_directories().iter().cloned())
+        .map(|p| {
+            debug!("Opening additional staging directory: {}", p.display());
+            StoreRoot::new(p).map(StagingStore::open).map(Arc::new)
+        })
+        .collect::<Result<Vec<_>>>()
+        .map_err(crate::error::Error::Store)?;
 
-        let dag = Dag::for_root_package(package.clone(), &repo, Some(&bar_tree_building), &condition_data)?;
-        bar_tree_building.finish_with_message("Finished loading Dag");
-        dag
+    let condition_data = ConditionData {
+        image_name: Some(&image_name),
+        env: &additional_env,
+        options: &options,
     };
+    let dags = packages.iter()
+        .map(|package| {
+            let bar_tree_building = progressbars.bar()?;
+            let dag = Dag::for_root_package((*package).clone(), &repo, Some(&bar_tree_building), &condition_data, &resolver)?;
+            bar_tree_building.finish_with_message("Finished loading Dag");
+
+            // --only-subtree conflicts with building several packages
+            match matches.value_of("only_subtree").map(|name| PackageName::from(String::from(name))) {
+                Some(name) => {
+                    let name = repo.resolve_name(&name)?;
+                    let version = matches.value_of("only_subtree_version").map(String::from).map(PackageVersion::from);
+                    let subtree = dag.subtree(&name, version.as_ref())?;
+                    info!("Only building the subtree of {} with {} of {} packages", name, subtree.all_packages().len(), dag.all_packages().len());
+                    Ok((dag.all_packages().into_iter().cloned().collect::<Vec<_>>(), subtree))
+                },
+                None => Ok((vec![], dag)),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Excluded packages must not be required by the trees, unless their released artifacts are
+    // used instead
+    for (package, (_, dag)) in packages.iter().zip(dags.iter()) {
+        for name in excluded.iter() {
+            if dag.dag()[*dag.root_idx()].name() == name {
+                return Err(anyhow!("The package {} is built and cannot be excluded", name))
+            }
+            if substitute_released {
+                continue
+            }
+            if let Some(path) = dag.dependency_path(name) {
+                return Err(anyhow!("Excluded package {} is required by the tree of {} {}: {}",
+                    name, package.name(), package.version(),
+                    path.iter().map(|p| format!("{} {}", p.name(), p.version())).join(" -> ")))
+            }
+        }
+    }
+    let substituted = excluded.iter()
+        .filter(|name| substitute_released && dags.iter().any(|(_, dag)| dag.dependency_path(name).is_some()))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // The options have to be known to the whole trees, even if only a subtree is built
+    let all_packages = dags.iter()
+        .flat_map(|(full_tree, dag)| full_tree.iter().chain(dag.all_packages()))
+        .unique_by(|p| (p.name(), p.version()))
+        .collect::<Vec<_>>();
+    if let Some(name) = options.keys().find(|name| !all_packages.iter().any(|p| p.options().contains_key(*name))) {
+        let requested = packages.iter().map(|p| format!("{} {}", p.name(), p.version())).join(", ");
+        return Err(anyhow!("No package in the tree of {} has the option '{}'", requested, name))
+    }
+    let dags = dags.into_iter().map(|(_, dag)| dag).collect::<Vec<_>>();
+
+    for dag in dags.iter() {
+        check_image_lists(dag, &image_name, config.docker().images())?;
+    }
 
     let source_cache = SourceCache::new(config.source_cache_root().clone());
 
+    // The packages of all trees, each once
+    let all_packages = dags.iter()
+        .flat_map(Dag::all_packages)
+        .unique_by(|p| (p.name(), p.version()))
+        .collect::<Vec<_>>();
+
     if matches.is_present("no_verification") {
         warn!("No hash verification will be performed");
     } else {
         crate::commands::source::verify_impl(
-            dag.all_packages().into_iter(),
+            all_packages.iter().copied(),
             &source_cache,
             &progressbars,
+            None,
+            true,
         )
         .await?;
     }
 
+    if hermetic {
+        check_patches_committed(&git_repo, repo_path, &all_packages)?;
+    }
+
     // linting the package scripts
     if matches.is_present("no_lint") {
         warn!("No script linting will be performed!");
     } else if let Some(linter) = crate::ui::find_linter_command(repo_root, config)? {
-        let all_packages = dag.all_packages();
         let bar = progressbars.bar()?;
         bar.set_length(all_packages.len() as u64);
         bar.set_message("Linting package scripts...");
 
-        let iter = all_packages.into_iter();
-        crate::commands::util::lint_packages(iter, &linter, config, bar).await?;
+        let iter = all_packages.iter().copied();
+        let deny_warnings = matches.is_present("deny_lint_warnings");
+        crate::commands::util::lint_packages(iter, &linter, repo_root, config, deny_warnings, bar).await?;
     } else {
         warn!("No linter set in configuration, no script linting will be performed!");
     } // linting
 
-    dag.all_packages()
-        .into_iter()
-        .map(|pkg| {
-            if let Some(allowlist) = pkg.allowed_images() {
-                if !allowlist.contains(&image_name) {
-                    return Err(anyhow!(
-                        "Package {} {} is only allowed on: {}",
-                        pkg.name(),
-                        pkg.version(),
-                        allowlist.iter().join(", ")
-                    ));
-                }
+    trace!("Setting up job sets");
+    // Secrets are only passed to the jobs, never recorded in the database
+    let secrets = matches
+        .values_of("secret")
+        .unwrap_or_default()
+        .map(crate::util::secret::parse_secret)
+        .collect::<Result<Vec<_>>>()?;
+
+    let resources: Vec<JobResource> = additional_env.iter()
+        .cloned()
+        .map(JobResource::from)
+        .chain(secrets.into_iter().map(JobResource::from))
+        .collect();
+    let external_dependencies = all_packages
+        .iter()
+        .flat_map(|p| p.dependencies().external().iter())
+        .unique_by(|d| (d.name().clone(), d.version().clone()))
+        .cloned()
+        .collect::<Vec<_>>();
+    let jobdags = dags.into_iter()
+        .map(|dag| crate::job::Dag::from_package_dag(dag, shebang.clone(), image_name.clone(), phases.clone(), resources.clone(), config.build().env()))
+        .collect::<Vec<_>>();
+    trace!("Setting up job sets finished successfully");
+
+    if matches.is_present("dry_run") {
+        // The staging directories were only created for these submits, nothing will be staged
+        if matches.value_of("staging_dir").is_none() {
+            for (_, staging_dir, _) in staging.iter() {
+                tokio::fs::remove_dir(&staging_dir).await?;
             }
+        }
 
-            if let Some(deniedlist) = pkg.denied_images() {
-                if deniedlist.iter().any(|denied| image_name == *denied) {
-                    return Err(anyhow!(
-                        "Package {} {} is not allowed to be built on {}",
-                        pkg.name(),
-                        pkg.version(),
-                        image_name
-                    ));
+        for (i, (package, jobdag)) in packages.iter().zip(jobdags.iter()).enumerate() {
+            if concurrent {
+                if i > 0 {
+                    println!();
                 }
+                println!("Plan for {} {}:", package.name(), package.version());
             }
+            print_plan(jobdag, &submit_pins, &substituted)?;
+        }
+        return Ok(())
+    }
 
-            Ok(())
-        })
-        .collect::<Result<Vec<()>>>()?;
+    if !external_dependencies.is_empty() {
+        let cache_root = config.external_artifact_cache()
+            .as_ref()
+            .ok_or_else(|| anyhow!("Packages have external dependencies, but no external_artifact_cache is configured"))?;
+        let cache = ExternalArtifactCache::new(cache_root.clone());
+        tokio::fs::create_dir_all(cache.root())
+            .await
+            .with_context(|| anyhow!("Creating directory: {}", cache.root().display()))?;
+
+        let transfers = progressbars.transfers()?;
+        transfers.total().set_message("Fetching external dependencies...");
+        for dependency in external_dependencies.iter() {
+            cache.fetch(dependency, config.external_artifact_sources(), &transfers).await?;
+        }
+        transfers.total().finish_with_message("Fetched external dependencies");
+
+        // The cache is searched like the additional staging directories
+        let bar_cache_loading = progressbars.bar()?;
+        let store = StagingStore::load(StoreRoot::new(cache.root().to_path_buf())?, &bar_cache_loading)?;
+        bar_cache_loading.finish_with_message("Loaded external artifact cache successfully");
+        additional_staging_stores.push(Arc::new(store));
+    }
 
     trace!("Setting up database jobs for Package, GitHash, Image");
-    let db_package = async { Package::create_or_fetch(&database_connection, package) };
+    let database_connection = database_pool.get()?;
+    let db_packages = async {
+        packages.iter()
+            .map(|package| Package::create_or_fetch(&database_connection, package))
+            .collect::<Result<Vec<_>>>()
+    };
     let db_githash = async { GitHash::create_or_fetch(&database_connection, &hash_str) };
     let db_image = async { Image::create_or_fetch(&database_connection, &image_name) };
     let db_envs = async {
@@ -313,25 +510,56 @@ pub async fn build(
     };
 
     trace!("Running database jobs for Package, GitHash, Image");
-    let (db_package, db_githash, db_image, db_envs) =
-        tokio::join!(db_package, db_githash, db_image, db_envs);
+    let (db_packages, db_githash, db_image, db_envs) =
+        tokio::join!(db_packages, db_githash, db_image, db_envs);
 
-    let (db_package, db_githash, db_image, _) = (db_package?, db_githash?, db_image?, db_envs?);
+    let (db_packages, db_githash, db_image, _) = (db_packages?, db_githash?, db_image?, db_envs?);
 
     trace!("Database jobs for Package, GitHash, Image finished successfully");
-    trace!("Creating Submit in database");
-    let submit = Submit::create(
-        &database_connection,
-        &now,
-        &submit_id,
-        &db_image,
-        &db_package,
-        &db_githash,
-    )?;
-    trace!(
-        "Creating Submit in database finished successfully: {:?}",
-        submit
-    );
+
+    // Resuming the submit for another package would mix up two submits
+    if matches.is_present("resume") {
+        let (db_package, (_, _, submit_id)) = (&db_packages[0], &staging[0]); // --resume conflicts with several packages
+        let resumed = Submit::with_id(&database_connection, submit_id)
+            .with_context(|| anyhow!("Loading submit {} to resume it", submit_id))?;
+        if resumed.requested_package_id != db_package.id || resumed.requested_image_id != db_image.id {
+            return Err(anyhow!("Submit {} was not for {} {} on {}, it cannot be resumed with them",
+                submit_id, db_package.name, db_package.version, db_image.name))
+        }
+    }
+
+    let submit_phases = phases.iter().map(|phase| phase.as_str().to_string()).collect::<Vec<_>>();
+    let mut submits = vec![];
+    for (db_package, (_, _, submit_id)) in db_packages.iter().zip(staging.iter()) {
+        trace!("Creating Submit in database");
+        let submit = Submit::create(
+            &database_connection,
+            &now,
+            submit_id,
+            &db_image,
+            db_package,
+            &db_githash,
+            &submitted_by,
+            Some(&submit_phases),
+            Some(&submit_pins),
+        )?;
+        trace!(
+            "Creating Submit in database finished successfully: {:?}",
+            submit
+        );
+        crate::db::models::AuditEntry::create(&database_connection, config, "submit-start", serde_json::json!({
+            "submit": submit.uuid,
+            "package": db_package.name,
+            "version": db_package.version,
+            "image": db_image.name,
+            "repo_hash": db_githash.hash,
+            "resume": matches.is_present("resume"),
+            "hermetic": hermetic,
+            "excluded": excluded,
+            "substituted": substituted,
+        }))?;
+        submits.push(submit);
+    }
 
     {
         let out = std::io::stdout();
@@ -342,45 +570,326 @@ pub async fn build(
             t.to_string().green()
         }
 
-        writeln!(outlock, "Starting submit: {}", mkgreen(&submit_id))?;
-        writeln!(outlock, "Started at:      {}", mkgreen(&now))?;
-        writeln!(outlock, "On Image:        {}", mkgreen(&db_image.name))?;
-        writeln!(outlock, "For Package:     {p} {v}",
-            p = mkgreen(&db_package.name),
-            v = mkgreen(&db_package.version))?;
-        writeln!(outlock, "On repo hash:    {}", mkgreen(&db_githash.hash))?;
+        for (i, (db_package, (_, _, submit_id))) in db_packages.iter().zip(staging.iter()).enumerate() {
+            if i > 0 {
+                writeln!(outlock)?;
+            }
+            writeln!(outlock, "Starting submit: {}", mkgreen(submit_id))?;
+            writeln!(outlock, "Started at:      {}", mkgreen(&now))?;
+            writeln!(outlock, "On Image:        {}", mkgreen(&db_image.name))?;
+            writeln!(outlock, "For Package:     {p} {v}",
+                p = mkgreen(&db_package.name),
+                v = mkgreen(&db_package.version))?;
+            writeln!(outlock, "On repo hash:    {}", mkgreen(&db_githash.hash))?;
+        }
+    }
+
+    let mut prepared = vec![];
+    for (db_package, (_, staging_dir, submit_id)) in db_packages.iter().zip(staging.iter()) {
+        let submit_description = crate::util::hooks::SubmitDescription {
+            submit: *submit_id,
+            started_at: now,
+            image: &db_image.name,
+            package_name: &db_package.name,
+            package_version: &db_package.version,
+            repo_hash: &db_githash.hash,
+            staging_dir,
+        };
+        let result_file = ResultFile {
+            path: matches.value_of("result_file").map(PathBuf::from), // conflicts with several packages
+            submit: *submit_id,
+            started_at: now,
+            started: std::time::Instant::now(),
+            job_reports: crate::orchestrator::JobReports::default(),
+            artifact_dirs: std::iter::once(staging_dir.clone())
+                .chain(config.release_stores().iter().map(|name| config.releases_directory().join(name)))
+                .collect(),
+            failure_tails: HashMap::new(),
+        };
+
+        if let Err(e) = crate::util::hooks::run_hooks("pre-submit", config.pre_submit_hooks(), &submit_description).await {
+            let e = e.context("Running pre-submit hooks");
+            result_file.write_or_warn("pre-submit-hook-failed", Some(&e), &HashMap::new()).await;
+            return Err(e)
+        }
+        prepared.push((submit_description, result_file));
     }
 
-    trace!("Setting up job sets");
-    let resources: Vec<JobResource> = additional_env.into_iter().map(JobResource::from).collect();
-    let jobdag = crate::job::Dag::from_package_dag(dag, shebang, image_name, phases.clone(), resources);
-    trace!("Setting up job sets finished successfully");
+    let live_log = matches.value_of("log_socket")
+        .map(|addr| -> Result<_> {
+            let addr = addr.parse::<std::net::SocketAddr>()
+                .with_context(|| anyhow!("Parsing log socket address: {}", addr))?;
+            let live_log = crate::log::LiveLog::new();
+            let server = live_log.serve(addr)?;
+            Ok((live_log, server))
+        })
+        .transpose()?;
 
-    trace!("Setting up Orchestrator");
-    let database_connection = Arc::new(database_connection);
-    let orch = OrchestratorSetup::builder()
-        .progress_generator(progressbars)
-        .endpoint_config(endpoint_configurations)
-        .staging_store(staging_store)
-        .release_stores(release_stores)
-        .database(database_connection.clone())
-        .source_cache(source_cache)
-        .submit(submit)
-        .log_dir(if matches.is_present("write-log-file") {
-            Some(config.log_dir().clone())
+    // The submits share the endpoints and the progress bars
+    let multibar = progressbars.multi();
+    let mut endpoint_pool = None;
+    let mut orchestrators = vec![];
+    for (((submit, jobdag), (staging_store, _, _)), (submit_description, result_file)) in submits.into_iter()
+        .zip(jobdags)
+        .zip(staging.iter())
+        .zip(prepared.iter())
+    {
+        let progress_group = if concurrent {
+            let header = format!("Submit {} for {} {}", submit.uuid, submit_description.package_name, submit_description.package_version);
+            Some(progressbars.group(&multibar, header)?)
         } else {
             None
-        })
-        .jobdag(jobdag)
-        .config(config)
-        .repository(git_repo)
-        .build()
-        .setup()
-        .await?;
+        };
+
+        trace!("Setting up Orchestrator");
+        let orch = OrchestratorSetup::builder()
+            .progress_generator(progressbars.clone())
+            .endpoint_config(std::mem::take(&mut endpoint_configurations)) // only used by the first, the others get its pool
+            .staging_store(staging_store.clone())
+            .additional_staging_stores(additional_staging_stores.clone())
+            .release_stores(release_stores.clone())
+            .database(database_pool.clone())
+            .source_cache(source_cache.clone())
+            .submit(submit)
+            .log_dir(if matches.is_present("write-log-file") {
+                Some(config.log_dir().clone())
+            } else {
+                None
+            })
+            .jobdag(jobdag)
+            .config(config)
+            .repository(git2::Repository::open(repo_path)
+                .with_context(|| anyhow!("Opening repository at {}", repo_path.display()))?)
+            .progress_tree(matches.is_present("progress_tree"))
+            .live_log(live_log.as_ref().map(|(live_log, _)| live_log.clone()))
+            .job_reports(result_file.job_reports.clone())
+            .resume(matches.is_present("resume"))
+            .no_deps(matches.is_present("no_deps"))
+            .substituted(substituted.iter().cloned().collect())
+            .endpoint_pool(endpoint_pool.clone())
+            .progress_group(progress_group.clone())
+            .concurrent(concurrent)
+            .status_file(matches.value_of("status_file").map(PathBuf::from))
+            .hermetic(hermetic)
+            .build()
+            .setup()
+            .await?;
+        endpoint_pool = Some(orch.endpoint_pool());
+        orchestrators.push((orch, progress_group));
+    }
+    drop(endpoint_pool);
 
     info!("Running orchestrator...");
-    let mut artifacts = vec![];
-    let errors = orch.run(&mut artifacts).await?;
+    let results = futures::future::join_all({
+        orchestrators.into_iter().map(|(orch, progress_group)| async move {
+            let mut artifacts = vec![];
+            let errors = orch.run(&mut artifacts).await;
+            if let Some(group) = progress_group {
+                let header = group.header();
+                match errors.as_ref() {
+                    Ok(errors) if errors.is_empty() => header.finish_with_message(format!("{} (finished)", header.message())),
+                    _ => header.finish_with_message(format!("{} (failed)", header.message())),
+                }
+            }
+            (artifacts, errors)
+        })
+    })
+    .await;
+    if concurrent {
+        // Signals are not handled by stopping the jobs anymore, so they have to exit again
+        crate::util::signal::exit_on_termination();
+    }
+    if let Some((_, server)) = live_log {
+        server.abort();
+    }
+
+    let mut failed = vec![];
+    for ((submit_description, result_file), (artifacts, errors)) in prepared.into_iter().zip(results) {
+        let submit_id = submit_description.submit;
+        let result = finish_submit(config, branch.as_deref(), &database_connection, submit_description, result_file, artifacts, errors).await;
+        match result {
+            Ok(()) => {},
+            Err(e) if !concurrent => return Err(e),
+            Err(e) => {
+                warn!("Submit {} failed: {:#}", submit_id, e);
+                failed.push(submit_id);
+            },
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("{} of {} submits failed: {}", failed.len(), packages.len(), failed.iter().join(", ")))
+    }
+}
+
+/// The packages to build for the changes of the repository since the revision `since`
+///
+/// These are the packages that are affected by the changes (see `Repository::affected_by()`),
+/// except for the packages that are built in the tree of another affected package anyways.
+fn affected_packages_since(
+    git_repo: &git2::Repository,
+    repo: &Repository,
+    repo_path: &Path,
+    since: &str,
+) -> Result<Vec<(PackageName, Option<PackageVersion>)>> {
+    let changed = crate::util::git::changed_files_since(git_repo, since)?;
+    debug!("Files changed since {}: {:?}", since, changed);
+    let affected = repo.affected_by(repo_path, &changed)?;
+
+    let mut roots = vec![];
+    for package in affected.iter() {
+        let mut in_other_tree = false;
+        for other in affected.iter().filter(|other| *other != package) {
+            if other.depends_on(package)? {
+                in_other_tree = true;
+                break
+            }
+        }
+
+        if in_other_tree {
+            debug!("{} {} is affected by the changes since {}, built in the tree of another package", package.name(), package.version(), since);
+        } else {
+            info!("{} {} is affected by the changes since {}", package.name(), package.version(), since);
+            roots.push((package.qualified_name(), Some(package.version().clone())));
+        }
+    }
+    roots.sort();
+    Ok(roots)
+}
+
+/// Fail if a patch of one of the `packages` is not committed to the repository, because a
+/// hermetic build must not depend on files that are not recorded in the repository
+fn check_patches_committed(git_repo: &git2::Repository, repo_path: &Path, packages: &[&crate::package::Package]) -> Result<()> {
+    for package in packages.iter() {
+        for patch in package.patches().iter() {
+            let relative = patch.strip_prefix(repo_path).unwrap_or(patch);
+            if !crate::util::git::is_committed(git_repo, relative)? {
+                return Err(anyhow!("Patch {} of package {} {} is not committed to the repository",
+                    patch.display(), package.name(), package.version()))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The package `pname` (in version `pvers`) of `repo`, which has to be unambiguous
+fn find_package<'a>(repo: &'a Repository, pname: &PackageName, pvers: Option<&PackageVersion>) -> Result<&'a crate::package::Package> {
+    let packages = if let Some(pvers) = pvers {
+        debug!("Searching for package with version: '{}' '{}'", pname, pvers);
+        repo.find(pname, pvers)
+    } else {
+        debug!("Searching for package by name: '{}'", pname);
+        repo.find_by_name(pname)
+    };
+    debug!("Found {} relevant packages", packages.len());
+
+    // We only support building one package per submit.
+    // Everything else is invalid
+    if packages.len() > 1 {
+        return Err(anyhow!(
+            "Found multiple packages ({}). Cannot decide which one to build",
+            packages.len()
+        ));
+    }
+    packages
+        .get(0)
+        .copied()
+        .ok_or_else(|| anyhow!("Found no package."))
+}
+
+/// Everything that happens after the jobs of a submit ran: signing the provenance, running the
+/// post-submit hooks, writing the result file, the notifications and printing the summary
+async fn finish_submit(
+    config: &Configuration,
+    branch: Option<&str>,
+    database_connection: &diesel::PgConnection,
+    submit_description: crate::util::hooks::SubmitDescription<'_>,
+    mut result_file: ResultFile,
+    artifacts: Vec<crate::filestore::ArtifactPath>,
+    errors: Result<HashMap<Uuid, Error>>,
+) -> Result<()> {
+    use crate::db::models::{Job, Package};
+
+    let staging_dir = submit_description.staging_dir;
+    let errors = match errors {
+        Ok(errors) => errors,
+        Err(e) => {
+            let result = crate::util::hooks::SubmitResult {
+                submit: &submit_description,
+                success: false,
+                artifacts: vec![],
+                failed_jobs: vec![],
+            };
+            if let Err(hook_error) = crate::util::hooks::run_hooks("post-submit", config.post_submit_hooks(), &result).await {
+                warn!("Running post-submit hooks failed: {:?}", hook_error);
+            }
+            let event = crate::util::notifications::NotificationEvent::SubmitFinished(&result);
+            crate::util::notifications::notify(config.notification_commands(), &event).await;
+            let reason = if crate::util::signal::terminated_by(&e).is_some() { "terminated" } else { "error" };
+            result_file.write_or_warn(reason, Some(&e), &HashMap::new()).await;
+            if let Some(email) = config.email_notifications().as_ref() {
+                let failure = crate::util::mail::SubmitFailure {
+                    submit: &submit_description,
+                    branch,
+                    error: Some(format!("{:#}", e)),
+                    failed_jobs: vec![],
+                };
+                if let Err(mail_error) = crate::util::mail::notify_submit_failure(email, &failure).await {
+                    warn!("Sending notification mail failed: {:?}", mail_error);
+                }
+            }
+            return Err(e)
+        },
+    };
+
+    if let Some(command) = config.provenance_signing_command().as_ref() {
+        for artifact_path in artifacts.iter() {
+            let artifact = staging_dir.join(artifact_path);
+            // Reused artifacts might have been signed already
+            if crate::filestore::provenance_path_for(&artifact).is_file()
+                && !crate::filestore::signature_path_for(&artifact).is_file()
+            {
+                debug!("Signing provenance of {}", artifact.display());
+                crate::filestore::sign_provenance(&artifact, command).await?;
+            }
+        }
+    }
+    let manifest = write_manifest(staging_dir, &result_file, &artifacts).await?;
+    debug!("Wrote manifest {}", manifest.display());
+
+    let submit_result = crate::util::hooks::SubmitResult {
+        submit: &submit_description,
+        success: errors.is_empty(),
+        artifacts: artifacts.iter().map(|artifact_path| staging_dir.join(artifact_path)).collect(),
+        failed_jobs: errors.keys().copied().collect(),
+    };
+    let post_submit_hooks = crate::util::hooks::run_hooks("post-submit", config.post_submit_hooks(), &submit_result)
+        .await
+        .context("Running post-submit hooks");
+    let event = crate::util::notifications::NotificationEvent::SubmitFinished(&submit_result);
+    crate::util::notifications::notify(config.notification_commands(), &event).await;
+
+    // The failed jobs and their logs, the ends of the logs are part of the summary
+    let mut failed_job_data = HashMap::new();
+    for job_uuid in errors.keys() {
+        let data = schema::jobs::table
+            .filter(schema::jobs::dsl::uuid.eq(job_uuid))
+            .inner_join(schema::packages::table)
+            .first::<(Job, Package)>(database_connection)?;
+        let log_text = crate::log::resolve_log(&data.0.log_text)?.into_owned();
+        let tail = crate::log::ParsedLog::from_str(&log_text)?.failure_tail(*config.build_error_lines());
+        result_file.failure_tails.insert(*job_uuid, tail);
+        failed_job_data.insert(*job_uuid, (data, log_text));
+    }
+
+    match (errors.is_empty(), post_submit_hooks.as_ref()) {
+        (false, _) => result_file.write("jobs-failed", None, &errors).await?,
+        (true, Err(e)) => result_file.write("post-submit-hook-failed", Some(e), &errors).await?,
+        (true, Ok(())) => result_file.write("success", None, &errors).await?,
+    }
+
     let out = std::io::stdout();
     let mut outlock = out.lock();
 
@@ -392,72 +901,58 @@ pub async fn build(
     })?;
 
     let mut had_error = false;
+    let mut failed_jobs = vec![];
     for (job_uuid, error) in errors {
         had_error = true;
         for cause in error.chain() {
             writeln!(outlock, "{}: {}", "[ERROR]".red(), cause)?;
         }
 
-        let data = schema::jobs::table
-            .filter(schema::jobs::dsl::uuid.eq(job_uuid))
-            .inner_join(schema::packages::table)
-            .first::<(Job, Package)>(database_connection.as_ref())?;
+        let (data, log_text) = failed_job_data.remove(&job_uuid).unwrap(); // inserted for all failed jobs above
 
-        let number_log_lines = *config.build_error_lines();
+        if let Some(email) = config.email_notifications().as_ref() {
+            let log_lines = email.log_lines().unwrap_or(*config.build_error_lines());
+            let log = log_text.lines().collect::<Vec<_>>();
+            failed_jobs.push(crate::util::mail::FailedJob {
+                uuid: job_uuid,
+                package_name: data.1.name.clone(),
+                package_version: data.1.version.clone(),
+                error: format!("{:#}", error),
+                log_tail: log[log.len().saturating_sub(log_lines)..].iter().map(|l| l.to_string()).collect(),
+            });
+        }
+
+        let tail = &result_file.failure_tails[&job_uuid];
         writeln!(
             outlock,
             "Last {} lines of Job {}",
-            number_log_lines, job_uuid.to_string().red()
-        )?;
-        writeln!(
-            outlock,
-            "for package {} {}\n\n",
-            data.1.name.to_string().red(),
-            data.1.version.to_string().red()
+            tail.items.len(), job_uuid.to_string().red()
         )?;
+        match tail.failed_phase.as_ref() {
+            Some(phase) => writeln!(
+                outlock,
+                "for package {} {}, which errored in phase '{}'\n\n",
+                data.1.name.to_string().red(),
+                data.1.version.to_string().red(),
+                phase.red()
+            )?,
+            None => writeln!(
+                outlock,
+                "for package {} {}\n\n",
+                data.1.name.to_string().red(),
+                data.1.version.to_string().red()
+            )?,
+        }
 
-        let mut last_phase = None;
-        let mut error_catched = false;
-        let lines = crate::log::ParsedLog::from_str(&data.0.log_text)?
-            .into_iter()
-            .map(|line_item| {
-                if let LogItem::CurrentPhase(ref p) = line_item {
-                    if !error_catched {
-                        last_phase = Some(p.clone());
-                    }
-                }
-
-                if let LogItem::State(_) = line_item {
-                    error_catched = true;
-                }
-
-
-                line_item.display().map(|d| d.to_string())
-            })
-            .collect::<Result<Vec<_>>>()?;
-
-        lines
+        tail.items
             .iter()
-            .enumerate()
-            .skip({
-                if lines.len() > number_log_lines {
-                    lines.len() - number_log_lines
-                } else {
-                    lines.len()
-                }
-            })
-            .try_for_each(|(i, line)| {
+            .try_for_each(|(i, item)| {
                 let lineno = format!("{:>4} | ", i).bright_black();
-                writeln!(outlock, "{}{}", lineno, line).map_err(Error::from)
+                writeln!(outlock, "{}{}", lineno, item.display()?).map_err(Error::from)
             })?;
 
         writeln!(outlock, "\n\n")?;
-        if error_catched {
-            if let Some(last_phase) = last_phase {
-                writeln!(outlock, "\tJob errored in Phase '{}'", last_phase)?;
-            }
-            writeln!(outlock, "\n\n")?;
-        } else {
+        if !tail.script_failed {
             writeln!(
                 outlock,
                 "{}",
@@ -466,9 +961,285 @@ pub async fn build(
         }
     }
 
+    if let (true, Some(email)) = (had_error, config.email_notifications().as_ref()) {
+        let failure = crate::util::mail::SubmitFailure {
+            submit: &submit_description,
+            branch,
+            error: None,
+            failed_jobs,
+        };
+        if let Err(mail_error) = crate::util::mail::notify_submit_failure(email, &failure).await {
+            warn!("Sending notification mail failed: {:?}", mail_error);
+        }
+    }
+
     if had_error {
-        Err(anyhow!("One or multiple errors during build"))
+        Err(crate::error::Error::Container(anyhow!("One or multiple errors during build")).into())
     } else {
-        Ok(())
+        post_submit_hooks
+    }
+}
+
+/// Check that all packages of `dag` may be built on `image_name`
+///
+/// All packages that may not are reported at once, with the images of `configured_images` they
+/// could be built on, so that the submit fails before any job runs.
+fn check_image_lists(dag: &Dag, image_name: &ImageName, configured_images: &[ImageName]) -> Result<()> {
+    let all_packages = dag.all_packages();
+    let offending = all_packages
+        .iter()
+        .copied()
+        .filter(|pkg| !pkg.allows_image(image_name))
+        .collect::<Vec<_>>();
+    if offending.is_empty() {
+        return Ok(())
+    }
+
+    let images_for = |pkgs: &[&crate::package::Package]| -> String {
+        let images = configured_images
+            .iter()
+            .filter(|image| pkgs.iter().all(|pkg| pkg.allows_image(image)))
+            .join(", ");
+        if images.is_empty() { String::from("none of the configured images") } else { images }
+    };
+
+    let report = offending
+        .iter()
+        .map(|pkg| {
+            let reason = match pkg.allowed_images() {
+                Some(allowed) if !allowed.iter().any(|i| i.matches(image_name)) => {
+                    format!("only allowed on {}", allowed.iter().join(", "))
+                },
+                _ => format!("denies {}", image_name),
+            };
+            format!("  {} {}: {}, can be built on {}", pkg.name(), pkg.version(), reason, images_for(&[pkg]))
+        })
+        .join("\n");
+
+    Err(anyhow!("{} of {} packages cannot be built on {}:\n{}\nAll packages can be built on {}",
+        offending.len(),
+        all_packages.len(),
+        image_name,
+        report,
+        images_for(&all_packages)))
+}
+
+/// Print the jobs of a submit, ordered by their scheduling priority, and the critical path
+fn print_plan(jobdag: &crate::job::Dag, pins: &[String], substituted: &[PackageName]) -> Result<()> {
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+
+    if !pins.is_empty() {
+        writeln!(outlock, "Pinned: {}", pins.join(", "))?;
+    }
+    if !substituted.is_empty() {
+        writeln!(outlock, "Excluded, using released artifacts: {}", substituted.iter().join(", "))?;
+    }
+    if !pins.is_empty() || !substituted.is_empty() {
+        writeln!(outlock)?;
+    }
+
+    let lengths = jobdag.critical_path_lengths();
+    let jobs = jobdag
+        .iter()
+        .map(|jobdef| (lengths.get(jobdef.job.uuid()).copied().unwrap_or(0), jobdef.job))
+        .sorted_by(|(a_len, a), (b_len, b)| {
+            b_len.cmp(a_len)
+                .then_with(|| a.package().name().cmp(b.package().name()))
+                .then_with(|| a.package().version().cmp(b.package().version()))
+        })
+        .collect::<Vec<_>>();
+
+    writeln!(outlock, "Jobs ({}), by scheduling priority:", jobs.len())?;
+    for (len, job) in jobs.iter() {
+        writeln!(outlock, "  {:>3}  {} {}", len, job.package().name(), job.package().version())?;
+    }
+
+    let critical_path = jobdag.critical_path();
+    writeln!(outlock)?;
+    writeln!(outlock, "Critical path ({} jobs): {}",
+        critical_path.len(),
+        critical_path
+            .iter()
+            .map(|job| format!("{} {}", job.package().name(), job.package().version()))
+            .join(" -> "))?;
+
+    Ok(())
+}
+
+/// The summary of a submit that is written to the `--result-file`
+#[derive(serde::Serialize)]
+struct BuildResult {
+    submit: Uuid,
+    success: bool,
+
+    /// Why the submit ended: "success", "jobs-failed", "pre-submit-hook-failed",
+    /// "post-submit-hook-failed", "terminated" (by SIGINT or SIGTERM) or "error"
+    exit_reason: &'static str,
+    error: Option<String>,
+
+    /// The category of `error`, e.g. "config", "resolution" or "database", see `ErrorKind`
+    error_code: Option<String>,
+    started_at: chrono::NaiveDateTime,
+    duration_secs: f64,
+    jobs: Vec<JobSummary>,
+}
+
+#[derive(serde::Serialize)]
+struct JobSummary {
+    job: Uuid,
+    package_name: String,
+    package_version: String,
+    state: crate::orchestrator::JobState,
+    duration_secs: Option<f64>,
+    error: Option<String>,
+
+    /// The category of `error`, "container" if the job failed in its container
+    error_code: Option<String>,
+
+    /// The phase a failed job errored in, if its script reported the error
+    failed_phase: Option<String>,
+
+    /// The last `build_error_lines` lines of the log of a failed job
+    log_tail: Vec<String>,
+    artifacts: Vec<ArtifactSummary>,
+}
+
+#[derive(serde::Serialize)]
+struct ArtifactSummary {
+    path: PathBuf,
+
+    /// `None` if the artifact could not be found in the staging or release stores
+    sha256: Option<String>,
+    reused: bool,
+}
+
+/// Write the manifest of the `artifacts` of the submit into its staging directory
+///
+/// The producing job of an artifact is taken from its metadata file, because reused artifacts
+/// were built by jobs of earlier submits.
+async fn write_manifest(staging_dir: &Path, result_file: &ResultFile, artifacts: &[crate::filestore::ArtifactPath]) -> Result<PathBuf> {
+    let reports = result_file.job_reports.reports();
+    let mut manifest = crate::filestore::SubmitManifest {
+        submit: result_file.submit,
+        created: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        artifacts: vec![],
+    };
+
+    for artifact_path in artifacts {
+        let file = staging_dir.join(artifact_path);
+        let (job, report) = match reports.iter().find(|(_, report)| report.artifacts.iter().any(|(a, _)| a == artifact_path)) {
+            Some(found) => found,
+            None => {
+                warn!("Artifact {} belongs to no job of the submit, not adding it to the manifest", artifact_path.display());
+                continue
+            },
+        };
+        let meta = tokio::fs::read_to_string(crate::filestore::meta_path_for(&file))
+            .await
+            .ok()
+            .and_then(|content| serde_json::from_str::<crate::filestore::ArtifactMetadata>(&content).ok());
+        let size = tokio::fs::metadata(&file)
+            .await
+            .with_context(|| anyhow!("Getting the size of {}", file.display()))?
+            .len();
+
+        manifest.artifacts.push(crate::filestore::ManifestArtifact {
+            path: artifact_path.as_ref().to_path_buf(),
+            package_name: report.package_name.clone(),
+            package_version: report.package_version.clone(),
+            sha256: crate::filestore::ObjectStore::hash_file(&file).await?,
+            size,
+            job: meta.map(|meta| meta.job_uuid).unwrap_or(*job),
+        });
+    }
+
+    manifest.artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+    manifest.write_to(staging_dir).await
+}
+
+/// Where and about what the summary of the submit is written, if at all
+struct ResultFile {
+    path: Option<PathBuf>,
+    submit: Uuid,
+    started_at: chrono::NaiveDateTime,
+    started: std::time::Instant,
+    job_reports: crate::orchestrator::JobReports,
+
+    /// The directories the artifacts are searched in for hashing them, in order
+    artifact_dirs: Vec<PathBuf>,
+
+    /// The ends of the logs of the failed jobs
+    failure_tails: HashMap<Uuid, crate::log::FailureTail>,
+}
+
+impl ResultFile {
+    async fn write(&self, exit_reason: &'static str, error: Option<&Error>, job_errors: &HashMap<Uuid, Error>) -> Result<()> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let mut jobs = vec![];
+        for (uuid, report) in self.job_reports.reports().into_iter().sorted_by_key(|(uuid, _)| *uuid) {
+            let mut artifacts = vec![];
+            for (artifact, built) in report.artifacts.iter() {
+                let file = self.artifact_dirs
+                    .iter()
+                    .map(|dir| dir.join(artifact))
+                    .find(|file| file.is_file());
+                let sha256 = match file.as_ref() {
+                    Some(file) => Some(crate::filestore::ObjectStore::hash_file(file).await?),
+                    None => None,
+                };
+
+                artifacts.push(ArtifactSummary {
+                    path: file.unwrap_or_else(|| artifact.as_ref().to_path_buf()),
+                    sha256,
+                    reused: !built,
+                });
+            }
+
+            let tail = self.failure_tails.get(&uuid);
+            jobs.push(JobSummary {
+                job: uuid,
+                package_name: report.package_name,
+                package_version: report.package_version,
+                state: report.state,
+                duration_secs: report.duration_secs,
+                error: job_errors.get(&uuid).map(|e| format!("{:#}", e)),
+                error_code: job_errors.get(&uuid).and_then(crate::error::ErrorKind::of).map(crate::error::ErrorKind::code),
+                failed_phase: tail.and_then(|tail| tail.failed_phase.clone()),
+                log_tail: tail
+                    .map(|tail| tail.items.iter().map(|(_, item)| item.raw()).collect::<Result<Vec<_>>>())
+                    .transpose()?
+                    .unwrap_or_default(),
+                artifacts,
+            });
+        }
+
+        let result = BuildResult {
+            submit: self.submit,
+            success: exit_reason == "success",
+            exit_reason,
+            error: error.map(|e| format!("{:#}", e)),
+            error_code: error.and_then(crate::error::ErrorKind::of).map(crate::error::ErrorKind::code),
+            started_at: self.started_at,
+            duration_secs: self.started.elapsed().as_secs_f64(),
+            jobs,
+        };
+
+        let content = serde_json::to_string_pretty(&result)?;
+        tokio::fs::write(path, content)
+            .await
+            .with_context(|| anyhow!("Writing result file {}", path.display()))
+    }
+
+    /// Write the result file when the submit fails anyways, so that failing to write it does not
+    /// hide the original error
+    async fn write_or_warn(&self, exit_reason: &'static str, error: Option<&Error>, job_errors: &HashMap<Uuid, Error>) {
+        if let Err(e) = self.write(exit_reason, error, job_errors).await {
+            warn!("{:?}", e);
+        }
     }
 }
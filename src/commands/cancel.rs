@@ -0,0 +1,51 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'cancel' subcommand
+
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use diesel::Connection;
+use diesel::PgConnection;
+
+use crate::config::Configuration;
+use crate::db::models as dbmodels;
+
+/// Implementation of the "cancel" subcommand
+pub async fn cancel(matches: &ArgMatches, config: &Configuration, conn: PgConnection) -> Result<()> {
+    let submit_uuid = matches
+        .value_of("submit")
+        .map(uuid::Uuid::parse_str)
+        .transpose()
+        .context("Parsing submit UUID")?
+        .unwrap(); // safe by clap
+
+    let submit = dbmodels::Submit::with_id(&conn, &submit_uuid)?;
+    let not_before = (chrono::Utc::now() - chrono::Duration::hours(crate::orchestrator::RUNNING_JOB_STALE_AFTER_HOURS)).naive_utc();
+    let running_jobs = dbmodels::RunningJob::count_of_submit(&conn, &submit, not_before)?;
+    if running_jobs == 0 {
+        return Err(anyhow!("Submit {} has no running jobs", submit_uuid))
+    }
+
+    conn.transaction::<_, anyhow::Error, _>(|| {
+        dbmodels::SubmitCancellation::create(&conn, &submit)?;
+        dbmodels::AuditEntry::create(&conn, config, "submit-cancel", serde_json::json!({
+            "submit": submit_uuid,
+            "running_jobs": running_jobs,
+        }))?;
+        Ok(())
+    })?;
+    writeln!(std::io::stdout(), "Canceled submit {} with {} running jobs", submit_uuid, running_jobs)
+        .map_err(anyhow::Error::from)
+}
@@ -0,0 +1,126 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'completions' subcommand and of the hidden '__complete' subcommand the
+//! completion scripts call
+
+use std::io::Write;
+
+use anyhow::Result;
+use clap::ArgMatches;
+use log::trace;
+
+use crate::config::Configuration;
+use crate::package::PackageName;
+use crate::repository::Repository;
+use crate::util::completion::DynamicValues;
+
+const BASH_SCRIPT: &str = r#"_butido() {
+    local IFS=$'\n'
+    COMPREPLY=($(butido __complete -- "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null))
+}
+
+complete -o bashdefault -o default -F _butido butido
+"#;
+
+const ZSH_SCRIPT: &str = r#"#compdef butido
+
+_butido() {
+    local -a candidates
+    candidates=("${(@f)$(butido __complete -- "${(@)words[2,$CURRENT]}" 2>/dev/null)}")
+    if [[ -n "${candidates[1]}" ]]; then
+        compadd -a candidates
+    else
+        _files
+    fi
+}
+
+if [ "$funcstack[1]" = "_butido" ]; then
+    _butido "$@"
+else
+    compdef _butido butido
+fi
+"#;
+
+const FISH_SCRIPT: &str = r#"function __butido_complete
+    set -l words (commandline -opc)
+    set -e words[1]
+    set -l current (commandline -ct)
+    set -l candidates (butido __complete -- $words "$current" 2>/dev/null)
+    if test (count $candidates) -gt 0
+        printf '%s\n' $candidates
+    else
+        __fish_complete_path "$current"
+    end
+end
+
+complete -c butido -f -a '(__butido_complete)'
+"#;
+
+/// Implementation of the "completions" subcommand
+pub fn completions(matches: &ArgMatches) -> Result<()> {
+    let script = match matches.value_of("shell").unwrap() { // unwrap safe by clap
+        "bash" => BASH_SCRIPT,
+        "fish" => FISH_SCRIPT,
+        "zsh" => ZSH_SCRIPT,
+        _ => unreachable!(),
+    };
+
+    std::io::stdout().write_all(script.as_bytes()).map_err(anyhow::Error::from)
+}
+
+/// Implementation of the "__complete" subcommand
+///
+/// Prints the candidates for the last of the passed words, one per line. The repository is only
+/// loaded if package names or versions are completed.
+pub fn complete<L>(matches: &ArgMatches, config: &Configuration, load_repo: L) -> Result<()>
+    where L: FnOnce() -> Result<Repository>
+{
+    let words = matches
+        .values_of("words")
+        .map(|words| words.map(String::from).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let completion = crate::util::completion::complete(&crate::cli::cli(), &words);
+    trace!("Completion for {:?}: {:?}", words, completion);
+
+    let prefix = completion.prefix;
+    let mut candidates = completion.candidates;
+    match completion.dynamic {
+        None => {},
+        Some(DynamicValues::Packages) => {
+            candidates.extend(load_repo()?.packages().map(|p| p.name().to_string()));
+        },
+        Some(DynamicValues::Versions(Some(package))) => {
+            let name = PackageName::from(package);
+            candidates.extend(load_repo()?.find_by_name(&name).into_iter().map(|p| p.version().to_string()));
+        },
+        Some(DynamicValues::Versions(None)) => {},
+        Some(DynamicValues::Endpoints) => {
+            candidates.extend(config.docker().endpoints().keys().map(|name| name.to_string()));
+        },
+        Some(DynamicValues::Images) => {
+            candidates.extend(config.docker().images().iter().map(|image| image.as_ref().to_string()));
+        },
+    }
+
+    let mut candidates = candidates
+        .into_iter()
+        .filter(|c| c.starts_with(&prefix))
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates.dedup();
+
+    let mut stdout = std::io::stdout();
+    candidates
+        .iter()
+        .try_for_each(|c| writeln!(stdout, "{}", c))
+        .map_err(anyhow::Error::from)
+}
@@ -0,0 +1,181 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'config' subcommand
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use futures::StreamExt;
+use log::debug;
+
+use crate::config::Configuration;
+use crate::db::DbConnectionConfig;
+use crate::endpoint::Endpoint;
+
+/// Implementation of the "config" subcommand
+pub async fn config(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    match matches.subcommand() {
+        Some(("check", matches)) => check(db_connection_config, config, matches).await,
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("No subcommand")),
+    }
+}
+
+/// The result of a single check
+struct CheckResult {
+    check: &'static str,
+    item: String,
+    result: Result<()>,
+
+    /// Whether the check was not done, because it does not apply to the item
+    skipped: bool,
+}
+
+impl CheckResult {
+    fn new(check: &'static str, item: impl Into<String>, result: Result<()>) -> Self {
+        CheckResult { check, item: item.into(), result, skipped: false }
+    }
+
+    fn skipped(check: &'static str, item: impl Into<String>) -> Self {
+        CheckResult { check, item: item.into(), result: Ok(()), skipped: true }
+    }
+
+    fn into_row(self) -> Vec<String> {
+        let (result, details) = match self.result {
+            Ok(()) if self.skipped => ("skipped", String::new()),
+            Ok(()) => ("ok", String::new()),
+            Err(e) => ("failed", format!("{:#}", e)),
+        };
+
+        vec![self.check.to_string(), self.item, result.to_string(), details]
+    }
+}
+
+async fn check(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let csv = matches.is_present("csv");
+
+    // Loading and validating the configuration already succeeded, otherwise we would not be here
+    let mut results = vec![CheckResult::new("configuration", "validation", Ok(()))];
+
+    results.push({
+        let item = format!("{}@{}:{}",
+            db_connection_config.database_name(),
+            db_connection_config.database_host(),
+            db_connection_config.database_port());
+        let result = tokio::task::block_in_place(|| db_connection_config.establish_connection().map(|_| ()));
+        CheckResult::new("database", item, result)
+    });
+
+    results.extend(check_endpoints(config).await?);
+
+    let store_dirs = {
+        let mut dirs = vec![
+            ("staging", config.staging_directory().clone()),
+            ("source cache", config.source_cache_root().clone()),
+            ("log dir", config.log_dir().clone()),
+            ("releases", config.releases_directory().clone()),
+        ];
+        dirs.extend({
+            config.release_stores()
+                .iter()
+                .map(|store| ("release store", config.releases_directory().join(store)))
+        });
+        dirs
+    };
+    results.extend({
+        store_dirs
+            .into_iter()
+            .map(|(what, dir)| CheckResult::new("directory", format!("{} ({})", what, dir.display()), check_dir_writable(&dir)))
+    });
+
+    let failed = results.iter().filter(|r| r.result.is_err()).count();
+    let total = results.len();
+
+    let hdr = crate::commands::util::mk_header(["Check", "Item", "Result", "Details"].to_vec());
+    let data = results.into_iter().map(CheckResult::into_row).collect::<Vec<_>>();
+    crate::commands::util::display_data(hdr, data, csv)?;
+
+    if failed > 0 {
+        Err(anyhow!("{} of {} checks failed", failed, total))
+    } else {
+        Ok(())
+    }
+}
+
+/// Ping all endpoints and check that the configured images are available on them
+async fn check_endpoints(config: &Configuration) -> Result<Vec<CheckResult>> {
+    let endpoint_names = config.docker().endpoints().keys().cloned().collect::<Vec<_>>();
+    let endpoint_configurations = crate::commands::endpoint::endpoint_configurations(config, &endpoint_names);
+
+    let endpoints = crate::endpoint::util::connect_endpoints_unchecked(endpoint_configurations)
+        .await
+        .context("Connecting to endpoints")?;
+
+    let mut results = endpoints
+        .iter()
+        .map(|endpoint| check_endpoint(endpoint.clone(), config))
+        .collect::<futures::stream::FuturesUnordered<_>>()
+        .collect::<Vec<Vec<CheckResult>>>()
+        .await
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    results.sort_by(|a, b| a.check.cmp(b.check).then_with(|| a.item.cmp(&b.item)));
+    Ok(results)
+}
+
+async fn check_endpoint(endpoint: Arc<Endpoint>, config: &Configuration) -> Vec<CheckResult> {
+    let ping = endpoint.check_health().await;
+    debug!("Ping of {}: {:?}", endpoint.name(), ping);
+    let reachable = ping.is_ok();
+    let mut results = vec![CheckResult::new("endpoint", endpoint.name().to_string(), ping)];
+
+    for image in config.docker().images() {
+        let item = format!("{} on {}", image, endpoint.name());
+        if !reachable || !endpoint.is_docker() {
+            // Sandbox endpoints get their images from a directory on the host, not from docker
+            results.push(CheckResult::skipped("image", item));
+        } else {
+            let result = endpoint.image_id(image).await.map(|_| ());
+            results.push(CheckResult::new("image", item, result));
+        }
+    }
+
+    results
+}
+
+/// Check that `dir` is a directory a file can be created in
+fn check_dir_writable(dir: &Path) -> Result<()> {
+    if !dir.is_dir() {
+        return Err(anyhow!("Not a directory"))
+    }
+
+    let probe = dir.join(format!(".butido-config-check-{}", uuid::Uuid::new_v4()));
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .context("Not writable")?;
+    std::fs::remove_file(&probe).with_context(|| anyhow!("Removing {}", probe.display()))
+}
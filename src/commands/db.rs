@@ -10,6 +10,8 @@
 
 //! Implementation of the 'db' subcommand
 
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::Command;
@@ -22,10 +24,12 @@ use anyhow::anyhow;
 use clap::ArgMatches;
 use colored::Colorize;
 use diesel::BelongingToDsl;
+use diesel::BoolExpressionMethods;
 use diesel::ExpressionMethods;
 use diesel::JoinOnDsl;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
+use diesel::TextExpressionMethods;
 use itertools::Itertools;
 use log::debug;
 use log::info;
@@ -36,29 +40,53 @@ use crate::config::Configuration;
 use crate::db::models;
 use crate::db::DbConnectionConfig;
 use crate::log::JobResult;
+use crate::package::PackageVersion;
+use crate::package::PackageVersionConstraint;
 use crate::package::Script;
+use crate::repository::Repository;
 use crate::schema;
+use crate::util::progress::ProgressBars;
 
 diesel_migrations::embed_migrations!("migrations");
 
 /// Implementation of the "db" subcommand
-pub fn db(
+pub async fn db<L>(
     db_connection_config: DbConnectionConfig<'_>,
     config: &Configuration,
     matches: &ArgMatches,
-) -> Result<()> {
+    progressbars: ProgressBars,
+    load_repo: L,
+) -> Result<()>
+    where L: FnOnce() -> Result<Repository>
+{
     match matches.subcommand() {
         Some(("cli", matches)) => cli(db_connection_config, matches),
-        Some(("setup", _matches)) => setup(db_connection_config),
+        Some(("setup", _matches)) => migrate(db_connection_config),
+        Some(("migrate", _matches)) => migrate(db_connection_config),
         Some(("artifacts", matches)) => artifacts(db_connection_config, matches),
         Some(("envvars", matches)) => envvars(db_connection_config, matches),
         Some(("images", matches)) => images(db_connection_config, matches),
         Some(("submit", matches)) => submit(db_connection_config, matches),
-        Some(("submits", matches)) => submits(db_connection_config, matches),
-        Some(("jobs", matches)) => jobs(db_connection_config, matches),
+        Some(("tree-of-submit", matches)) => tree_of_submit(db_connection_config, matches),
+        Some(("coverage", matches)) => coverage(db_connection_config, matches, load_repo),
+        Some(("rerun-job", matches)) => {
+            super::rerun_job::rerun_job(db_connection_config, config, matches, progressbars, load_repo()?).await
+        },
+        Some(("download-artifact", matches)) => {
+            super::download_artifact::download_artifact(db_connection_config, config, matches).await
+        },
+        Some(("diff-submits", matches)) => diff_submits(db_connection_config, matches),
+        Some(("submits", matches)) => submits(db_connection_config, config, matches),
+        Some(("jobs", matches)) => jobs(db_connection_config, config, matches),
         Some(("job", matches)) => job(db_connection_config, config, matches),
         Some(("log-of", matches)) => log_of(db_connection_config, matches),
+        Some(("provenance-of", matches)) => provenance_of(db_connection_config, config, matches),
         Some(("releases", matches)) => releases(db_connection_config, config, matches),
+        Some(("export", matches)) => export(db_connection_config, matches),
+        Some(("import", matches)) => import(db_connection_config, matches),
+        Some(("analyze", matches)) => analyze(db_connection_config, matches),
+        Some(("audit", matches)) => audit(db_connection_config, matches),
+        Some(("running", matches)) => running(db_connection_config, matches),
         Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
         None => Err(anyhow!("No subcommand")),
     }
@@ -148,9 +176,36 @@ fn cli(db_connection_config: DbConnectionConfig<'_>, matches: &ArgMatches) -> Re
         .run_for_uri(db_connection_config)
 }
 
-fn setup(conn_cfg: DbConnectionConfig<'_>) -> Result<()> {
+/// Implementation of the "db setup" and "db migrate" subcommands
+///
+/// Creates the schema (if it does not exist yet) and runs all pending migrations that are embedded
+/// in the binary.
+fn migrate(conn_cfg: DbConnectionConfig<'_>) -> Result<()> {
+    use diesel_migrations::MigrationConnection;
+
     let conn = conn_cfg.establish_connection()?;
-    embedded_migrations::run_with_output(&conn, &mut std::io::stdout()).map_err(Error::from)
+    diesel_migrations::setup_database(&conn).context("Setting up migrations table")?;
+    let previously_run = conn.previously_run_migration_versions()?;
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    embedded_migrations::run_with_output(&conn, &mut outlock)
+        .context("Running migrations")?;
+
+    let applied = conn.previously_run_migration_versions()?
+        .difference(&previously_run)
+        .cloned()
+        .sorted()
+        .collect::<Vec<_>>();
+
+    if applied.is_empty() {
+        writeln!(outlock, "Database schema is up to date")?;
+    } else {
+        writeln!(outlock, "Applied {} migration(s), schema is now at version {}",
+            applied.len().to_string().green(),
+            applied.last().unwrap().green())?; // safe because of the check above
+    }
+    Ok(())
 }
 
 /// Implementation of the "db artifacts" subcommand
@@ -246,6 +301,211 @@ fn images(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
     Ok(())
 }
 
+/// Everything that is known about a submit, for "db submit"
+#[derive(serde::Serialize)]
+struct SubmitDetails {
+    uuid: uuid::Uuid,
+    submit_time: chrono::NaiveDateTime,
+    commit: String,
+    requested_package: String,
+    requested_version: String,
+    requested_image: String,
+    submitted_by: Option<String>,
+    phases: Option<Vec<String>>,
+    pins: Option<Vec<String>>,
+
+    /// The environment variables that were set for the jobs of the submit
+    env: Vec<SubmitEnvDetails>,
+    jobs: Vec<SubmitJobDetails>,
+    artifacts: Vec<SubmitArtifactDetails>,
+}
+
+#[derive(serde::Serialize, PartialEq, Eq, PartialOrd, Ord)]
+struct SubmitEnvDetails {
+    name: String,
+    value: String,
+}
+
+#[derive(serde::Serialize)]
+struct SubmitJobDetails {
+    uuid: uuid::Uuid,
+    package: String,
+    version: String,
+
+    /// "success", "error" or "unknown" for jobs that ran, otherwise the last state of the job in
+    /// the job tree, e.g. "reused" or "waiting"
+    status: String,
+    duration_secs: Option<f64>,
+    endpoint: Option<String>,
+    image: Option<String>,
+    image_digest: Option<String>,
+    container: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct SubmitArtifactDetails {
+    job: uuid::Uuid,
+    path: String,
+
+    /// The release stores the artifact was released to, with the date of the release
+    releases: Vec<SubmitReleaseDetails>,
+}
+
+#[derive(serde::Serialize)]
+struct SubmitReleaseDetails {
+    store: String,
+    date: chrono::NaiveDateTime,
+}
+
+impl SubmitDetails {
+    fn load(conn: &diesel::PgConnection, submit: &models::Submit) -> Result<Self> {
+        use crate::orchestrator::JobState;
+
+        let githash = models::GitHash::with_id(conn, submit.repo_hash_id)
+            .with_context(|| anyhow!("Loading GitHash '{}' from DB", submit.repo_hash_id))?;
+        let requested_package = models::Package::fetch_by_id(conn, submit.requested_package_id)?
+            .ok_or_else(|| anyhow!("Requested package of submit {} not found", submit.uuid))?;
+        let requested_image = models::Image::fetch_by_id(conn, submit.requested_image_id)?
+            .ok_or_else(|| anyhow!("Requested image of submit {} not found", submit.uuid))?;
+
+        let jobs = schema::jobs::table
+            .filter(schema::jobs::submit_id.eq(submit.id))
+            .order_by(schema::jobs::id.asc())
+            .load::<models::Job>(conn)
+            .with_context(|| anyhow!("Loading jobs for submit = {}", submit.uuid))?;
+
+        // The job tree is only recorded by newer versions of butido, it also knows the jobs that
+        // did not run
+        let transitions = models::SubmitJob::with_states(conn, submit)?
+            .into_iter()
+            .map(|(job, package, transitions)| (job.uuid, (package, transitions)))
+            .collect::<Vec<_>>();
+
+        let mut env = jobs.iter()
+            .map(|job| job.env(conn))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .map(|var| SubmitEnvDetails { name: var.name, value: var.value })
+            .collect::<Vec<_>>();
+        env.sort();
+        env.dedup();
+
+        let mut job_details = jobs.iter()
+            .map(|job| {
+                let package = models::Package::fetch_for_job(conn, job)?
+                    .ok_or_else(|| anyhow!("Package for job {} not found", job.uuid))?;
+                let endpoint = models::Endpoint::fetch_for_job(conn, job)?
+                    .ok_or_else(|| anyhow!("Endpoint for job {} not found", job.uuid))?;
+                let image = models::Image::fetch_for_job(conn, job)?
+                    .ok_or_else(|| anyhow!("Image for job {} not found", job.uuid))?;
+
+                // From the job starting to run until it finished, or the time of its phases for
+                // jobs without a recorded job tree
+                let duration_secs = transitions.iter()
+                    .find(|(id, _)| *id == job.uuid)
+                    .and_then(|(_, (_, ts))| {
+                        ts.iter()
+                            .find(|t| t.state == JobState::Running.to_string())
+                            .zip(ts.last().filter(|t| t.state == JobState::Done.to_string() || t.state == JobState::Failed.to_string()))
+                    })
+                    .map(|(started, finished)| (finished.time - started.time).num_milliseconds() as f64 / 1000.0);
+                let duration_secs = match duration_secs {
+                    Some(secs) => Some(secs),
+                    None => {
+                        let phases = models::JobPhase::belonging_to(job).load::<models::JobPhase>(conn)?;
+                        Some(phases.iter().map(|p| p.duration_secs).sum::<f64>()).filter(|_| !phases.is_empty())
+                    },
+                };
+
+                Ok(SubmitJobDetails {
+                    uuid: job.uuid,
+                    package: package.name,
+                    version: package.version,
+                    status: match is_job_successfull(job)? {
+                        Some(true) => String::from("success"),
+                        Some(false) => String::from("error"),
+                        None => String::from("unknown"),
+                    },
+                    duration_secs,
+                    endpoint: Some(endpoint.name),
+                    image: Some(image.name),
+                    image_digest: job.image_digest.clone(),
+                    container: Some(job.container_hash.clone()),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        job_details.extend({
+            transitions.into_iter()
+                .filter(|(id, _)| !jobs.iter().any(|job| job.uuid == *id))
+                .map(|(uuid, (package, transitions))| SubmitJobDetails {
+                    uuid,
+                    package: package.name,
+                    version: package.version,
+                    status: transitions.last()
+                        .map(|t| t.state.clone())
+                        .unwrap_or_else(|| JobState::Waiting.to_string()),
+                    duration_secs: None,
+                    endpoint: None,
+                    image: None,
+                    image_digest: None,
+                    container: None,
+                })
+        });
+
+        let artifacts = schema::artifacts::table
+            .left_join(schema::releases::table.inner_join(schema::release_stores::table))
+            .filter(schema::artifacts::job_id.eq_any(jobs.iter().map(|job| job.id)))
+            .order_by(schema::artifacts::id.asc())
+            .load::<(models::Artifact, Option<(models::Release, models::ReleaseStore)>)>(conn)
+            .with_context(|| anyhow!("Loading artifacts for submit = {}", submit.uuid))?
+            .into_iter()
+            .map(|(artifact, release)| {
+                let job = jobs.iter()
+                    .find(|job| job.id == artifact.job_id)
+                    .map(|job| job.uuid)
+                    .ok_or_else(|| anyhow!("Job of artifact {} not found", artifact.path))?;
+                Ok((artifact.id, SubmitArtifactDetails {
+                    job,
+                    path: artifact.path,
+                    releases: release
+                        .map(|(release, store)| SubmitReleaseDetails { store: store.store_name, date: release.release_date })
+                        .into_iter()
+                        .collect(),
+                }))
+            })
+            .collect::<Result<Vec<(i32, SubmitArtifactDetails)>>>()?
+            .into_iter()
+            .fold(Vec::<(i32, SubmitArtifactDetails)>::new(), |mut artifacts, (id, artifact)| {
+                // An artifact that was released to several stores is in several rows
+                match artifacts.last_mut() {
+                    Some((last_id, last)) if *last_id == id => last.releases.extend(artifact.releases),
+                    _ => artifacts.push((id, artifact)),
+                }
+                artifacts
+            })
+            .into_iter()
+            .map(|(_, artifact)| artifact)
+            .collect();
+
+        Ok(SubmitDetails {
+            uuid: submit.uuid,
+            submit_time: submit.submit_time,
+            commit: githash.hash,
+            requested_package: requested_package.name,
+            requested_version: requested_package.version,
+            requested_image: requested_image.name,
+            submitted_by: submit.submitted_by.clone(),
+            phases: submit.phases.clone(),
+            pins: submit.pins.clone(),
+            env,
+            jobs: job_details,
+            artifacts,
+        })
+    }
+}
+
 /// Implementation of the "db submit" subcommand
 fn submit(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     let conn = conn_cfg.establish_connection()?;
@@ -257,89 +517,480 @@ fn submit(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
 
     let submit = models::Submit::with_id(&conn, &submit_id)
         .with_context(|| anyhow!("Loading submit '{}' from DB", submit_id))?;
+    let details = SubmitDetails::load(&conn, &submit)?;
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    if matches.is_present("json") {
+        serde_json::to_writer_pretty(&mut outlock, &details)?;
+        return writeln!(outlock).map_err(Error::from)
+    }
+
+    let count = |status: &str| details.jobs.iter().filter(|job| job.status == status).count();
+    let n_released = details.artifacts.iter().filter(|a| !a.releases.is_empty()).count();
+    indoc::writedoc!(outlock, r#"
+            Submit    {submit_id}
+            Date:     {submit_dt}
+            Commit:   {submit_commit}
+            Package:  {package} {version}
+            Image:    {image}
+            By:       {submitted_by}
+            Phases:   {phases}
+            Pins:     {pins}
+            Jobs:     {n_jobs}
+            Success:  {n_jobs_success}
+            Unknown:  {n_jobs_unknown}
+            Errored:  {n_jobs_err}
+            Released: {n_released} of {n_artifacts} artifacts
+
+        "#,
+        submit_id = details.uuid.to_string().cyan(),
+        submit_dt = details.submit_time.to_string().cyan(),
+        submit_commit = details.commit.cyan(),
+        package = details.requested_package.cyan(),
+        version = details.requested_version.cyan(),
+        image = details.requested_image.cyan(),
+        submitted_by = details.submitted_by.as_deref().unwrap_or("unknown").cyan(),
+        phases = details.phases.as_ref().map(|phases| phases.join(", ")).unwrap_or_else(|| String::from("unknown")).cyan(),
+        pins = match details.pins.as_ref() {
+            Some(pins) if pins.is_empty() => String::from("none"),
+            Some(pins) => pins.join(", "),
+            None => String::from("unknown"),
+        }.cyan(),
+        n_jobs = details.jobs.len().to_string().cyan(),
+        n_jobs_success = count("success").to_string().green(),
+        n_jobs_unknown = count("unknown").to_string().red(),
+        n_jobs_err = count("error").to_string().red(),
+        n_released = n_released.to_string().cyan(),
+        n_artifacts = details.artifacts.len().to_string().cyan(),
+    )?;
+
+    if !details.env.is_empty() {
+        writeln!(outlock, "Environment:")?;
+        for var in details.env.iter() {
+            writeln!(outlock, "\t{}={}", var.name, var.value)?;
+        }
+        writeln!(outlock)?;
+    }
+    drop(outlock);
+
+    let header = crate::commands::util::mk_header(["Job", "Status", "Package", "Version", "Duration", "Container", "Endpoint", "Image"].to_vec());
+    let data = details.jobs
+        .iter()
+        .map(|job| {
+            vec![
+                job.uuid.to_string().cyan(),
+                match job.status.as_ref() {
+                    "success" => "Success".green(),
+                    "error" => "Error".red(),
+                    "unknown" => "Unknown".yellow(),
+                    other => other.normal(),
+                },
+                job.package.cyan(),
+                job.version.cyan(),
+                job.duration_secs.map(crate::commands::util::format_duration_secs).unwrap_or_default().normal(),
+                job.container.as_deref().unwrap_or("").normal(),
+                job.endpoint.as_deref().unwrap_or("").normal(),
+                job.image.as_deref().unwrap_or("").normal(),
+            ]
+        })
+        .collect::<Vec<Vec<colored::ColoredString>>>();
+    crate::commands::util::display_data(header, data, false)?;
+
+    if !details.artifacts.is_empty() {
+        println!();
+        let header = crate::commands::util::mk_header(["Job", "Artifact", "Released"].to_vec());
+        let data = details.artifacts
+            .iter()
+            .map(|artifact| {
+                vec![
+                    artifact.job.to_string().cyan(),
+                    artifact.path.normal(),
+                    if artifact.releases.is_empty() {
+                        "no".yellow()
+                    } else {
+                        artifact.releases
+                            .iter()
+                            .map(|release| format!("{} ({})", release.store, release.date))
+                            .join(", ")
+                            .green()
+                    },
+                ]
+            })
+            .collect::<Vec<Vec<colored::ColoredString>>>();
+        crate::commands::util::display_data(header, data, false)?;
+    }
+    Ok(())
+}
+
+/// Implementation of the "db tree-of-submit" subcommand
+fn tree_of_submit(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    use crate::orchestrator::JobState;
+
+    let conn = conn_cfg.establish_connection()?;
+    let submit_id = matches.value_of("submit")
+        .map(uuid::Uuid::from_str)
+        .transpose()
+        .context("Parsing submit UUID")?
+        .unwrap(); // safe by clap
+    let show_artifacts = !matches.is_present("no_artifacts");
+
+    let submit = models::Submit::with_id(&conn, &submit_id)
+        .with_context(|| anyhow!("Loading submit '{}' from DB", submit_id))?;
+    let jobs = models::SubmitJob::with_states(&conn, &submit)?;
+    if jobs.is_empty() {
+        return Err(anyhow!("The job tree of submit {} was not recorded, it was built with an older version of butido", submit_id))
+    }
+
+    let dependencies = jobs.iter()
+        .map(|(job, _, _)| (job.uuid, job.dependencies.clone()))
+        .collect::<HashMap<_, _>>();
+    let jobs = jobs.iter()
+        .map(|(job, package, transitions)| (job.uuid, (package, transitions)))
+        .collect::<HashMap<_, _>>();
+
+    // Every run of the submit has its own root job, that no other job depends on
+    let roots = dependencies.keys()
+        .filter(|id| !dependencies.values().any(|deps| deps.contains(id)))
+        .sorted_by_key(|id| jobs.get(*id).and_then(|(_, ts)| ts.first()).map(|t| t.time))
+        .cloned();
 
-    let githash = models::GitHash::with_id(&conn, submit.repo_hash_id)
-        .with_context(|| anyhow!("Loading GitHash '{}' from DB", submit.repo_hash_id))?;
-
-    let jobs = schema::submits::table
-        .inner_join(schema::jobs::table)
-        .filter(schema::submits::uuid.eq(&submit_id))
-        .select(schema::jobs::all_columns)
-        .load::<models::Job>(&conn)
-        .with_context(|| anyhow!("Loading jobs for submit = {}", submit_id))?;
-
-    let n_jobs = jobs.len();
-    let (jobs_unknown, jobs_success, jobs_err) = {
-        let mut unkn = 0;
-        let mut succ = 0;
-        let mut err = 0;
-
-        for j in jobs.iter() {
-            match crate::log::ParsedLog::from_str(&j.log_text)?.is_successfull() {
-                JobResult::Unknown => unkn += 1,
-                JobResult::Success => succ += 1,
-                JobResult::Errored => err += 1,
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    writeln!(outlock, "Submit {} ({})", submit.uuid.to_string().cyan(), submit.submit_time)?;
+    for (run, root) in roots.enumerate() {
+        writeln!(outlock)?;
+        writeln!(outlock, "Run {}", run + 1)?;
+
+        for (id, prefix) in crate::orchestrator::tree_layout(root, &dependencies) {
+            let (package, transitions) = jobs.get(&id).ok_or_else(|| anyhow!("Job {} not found", id))?;
+            let last = transitions.last();
+            let state = last.and_then(|t| t.state.parse::<JobState>().ok());
+            let state_text = match state {
+                Some(JobState::Done) => "done".green(),
+                Some(JobState::Reused) => "reused".green(),
+                Some(JobState::Failed) => "failed".red(),
+                Some(JobState::Running) => "running (unfinished)".yellow(),
+                Some(JobState::Waiting) | None => "not started".yellow(),
+            };
+
+            // From the job starting to run until it finished
+            let duration = transitions.iter()
+                .find(|t| t.state == JobState::Running.to_string())
+                .zip(last.filter(|_| matches!(state, Some(JobState::Done) | Some(JobState::Failed))))
+                .map(|(started, finished)| (finished.time - started.time).num_milliseconds() as f64 / 1000.0)
+                .map(|secs| format!(" {}", crate::commands::util::format_duration_secs(secs)))
+                .unwrap_or_default();
+
+            writeln!(outlock, "{}{} {} [{}]{} {}", prefix, package.name.cyan(), package.version.cyan(), state_text, duration, id.to_string().bright_black())?;
+
+            if show_artifacts {
+                let indent = prefix.replace("├─ ", "│  ").replace("└─ ", "   ");
+                for artifact in last.iter().flat_map(|t| t.artifacts.iter()) {
+                    writeln!(outlock, "{}  {}", indent, artifact)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Implementation of the "db coverage" subcommand
+fn coverage<L>(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches, load_repo: L) -> Result<()>
+    where L: FnOnce() -> Result<Repository>
+{
+    use std::collections::HashSet;
+
+    let csv = matches.is_present("csv");
+    let not_built_since = get_date_filter("not_built_since", matches)?.map(|date| date.naive_utc());
+    let repo = load_repo()?;
+    let conn = conn_cfg.establish_connection()?;
+
+    // The time of the latest successful build of each package version and of each package
+    let mut last_built = HashMap::<(String, String), chrono::NaiveDateTime>::new();
+    let mut last_built_pkg = HashMap::<String, chrono::NaiveDateTime>::new();
+    schema::jobs::table
+        .inner_join(schema::packages::table)
+        .inner_join(schema::submits::table)
+        .filter(schema::jobs::log_text.like("%#BUTIDO:STATE:OK%"))
+        .select((schema::packages::name, schema::packages::version, schema::submits::submit_time))
+        .load::<(String, String, chrono::NaiveDateTime)>(&conn)
+        .context("Loading successful jobs")?
+        .into_iter()
+        .for_each(|(name, version, time)| {
+            let latest = last_built_pkg.entry(name.clone()).or_insert(time);
+            *latest = (*latest).max(time);
+            let latest = last_built.entry((name, version)).or_insert(time);
+            *latest = (*latest).max(time);
+        });
+
+    let released = schema::releases::table
+        .inner_join(schema::artifacts::table.inner_join(schema::jobs::table.inner_join(schema::packages::table)))
+        .select((schema::packages::name, schema::packages::version))
+        .distinct()
+        .load::<(String, String)>(&conn)
+        .context("Loading released packages")?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    let fmt_time = |time: Option<&chrono::NaiveDateTime>| time.map(|t| t.to_string()).unwrap_or_else(|| String::from("never"));
+    let mut data = vec![];
+    let packages = repo.packages()
+        .sorted_by(|a, b| a.name().cmp(b.name()).then_with(|| a.version().compare(b.version())))
+        .group_by(|p| p.name().clone());
+    for (name, versions) in packages.into_iter() {
+        let versions = versions.collect::<Vec<_>>();
+        for p in versions.iter() {
+            let key = (name.to_string(), p.version().to_string());
+            if !last_built.contains_key(&key) {
+                data.push(vec![key.0, key.1, String::from("never built"), fmt_time(None)]);
             }
         }
 
-        (unkn, succ, err)
+        if let Some(latest) = versions.last() {
+            let key = (name.to_string(), latest.version().to_string());
+            if last_built.contains_key(&key) && !released.contains(&key) {
+                let last = fmt_time(last_built.get(&key));
+                data.push(vec![key.0, key.1, String::from("latest version never released"), last]);
+            }
+        }
+
+        let last = last_built_pkg.get(name.as_ref());
+        if let (Some(since), Some(last)) = (not_built_since, last) {
+            if *last < since {
+                data.push(vec![name.to_string(), String::from("*"), String::from("not built since"), fmt_time(Some(last))]);
+            }
+        }
+    }
+
+    if matches.is_present("json") {
+        crate::commands::util::display_data_as_json(&["package_name", "package_version", "issue", "last_build"], data)
+    } else if data.is_empty() {
+        info!("No packages found");
+        Ok(())
+    } else {
+        let header = crate::commands::util::mk_header(["Package", "Version", "Issue", "Last build"].to_vec());
+        crate::commands::util::display_data(header, data, csv)
+    }
+}
+
+/// Implementation of the "db diff-submits" subcommand
+fn diff_submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    use std::collections::BTreeMap;
+    use std::collections::BTreeSet;
+
+    /// Helper type for all the information about a job we compare
+    struct JobInfo {
+        job: models::Job,
+        success: Option<bool>,
+        env: BTreeSet<(String, String)>,
+    }
+
+    let conn = conn_cfg.establish_connection()?;
+    let show_script_diff = !matches.is_present("no_script_diff");
+    let parse_uuid = |name: &str| {
+        matches.value_of(name)
+            .map(uuid::Uuid::from_str)
+            .transpose()
+            .context("Parsing submit UUID")
+            .map(|o| o.unwrap()) // safe by clap
     };
 
+    let load_submit = |submit_id: &uuid::Uuid| -> Result<_> {
+        let submit = models::Submit::with_id(&conn, submit_id)
+            .with_context(|| anyhow!("Loading submit '{}' from DB", submit_id))?;
+        let githash = models::GitHash::with_id(&conn, submit.repo_hash_id)
+            .with_context(|| anyhow!("Loading GitHash '{}' from DB", submit.repo_hash_id))?;
+        let image = models::Image::fetch_by_id(&conn, submit.requested_image_id)?
+            .ok_or_else(|| anyhow!("Image for submit {} not found", submit.uuid))?;
+        let package = models::Package::fetch_by_id(&conn, submit.requested_package_id)?
+            .ok_or_else(|| anyhow!("Package for submit {} not found", submit.uuid))?;
+
+        // package name -> package version -> job
+        let mut jobs: BTreeMap<String, BTreeMap<String, JobInfo>> = BTreeMap::new();
+        for (job, pkg) in schema::jobs::table
+            .inner_join(schema::packages::table)
+            .filter(schema::jobs::submit_id.eq(submit.id))
+            .load::<(models::Job, models::Package)>(&conn)
+            .with_context(|| anyhow!("Loading jobs for submit = {}", submit.uuid))?
+        {
+            let env = job.env(&conn)?
+                .into_iter()
+                .map(|e| (e.name, e.value))
+                .collect();
+            let success = is_job_successfull(&job)?;
+
+            jobs.entry(pkg.name)
+                .or_default()
+                .insert(pkg.version, JobInfo { job, success, env });
+        }
+
+        Ok((submit, githash, image, package, jobs))
+    };
+
+    let (submit_a, githash_a, image_a, package_a, jobs_a) = load_submit(&parse_uuid("submit_a")?)?;
+    let (submit_b, githash_b, image_b, package_b, jobs_b) = load_submit(&parse_uuid("submit_b")?)?;
+
     let out = std::io::stdout();
     let mut outlock = out.lock();
 
+    let changed = |a: &str, b: &str| if a == b { b.normal() } else { b.yellow() };
     indoc::writedoc!(outlock, r#"
-            Submit   {submit_id}
-            Date:    {submit_dt}
-            Commit:  {submit_commit}
-            Jobs:    {n_jobs}
-            Success: {n_jobs_success}
-            Unknown: {n_jobs_unknown}
-            Errored: {n_jobs_err}
+            Submit A: {a_uuid} from {a_time} on {a_hash}
+                      {a_pkg} {a_vers} on {a_image}
+            Submit B: {b_uuid} from {b_time} on {b_hash}
+                      {b_pkg} {b_vers} on {b_image}
 
         "#,
-        submit_id = submit.uuid.to_string().cyan(),
-        submit_dt = submit.submit_time.to_string().cyan(),
-        submit_commit = githash.hash.cyan(),
-        n_jobs = n_jobs.to_string().cyan(),
-        n_jobs_success = jobs_success.to_string().green(),
-        n_jobs_unknown = jobs_unknown.to_string().red(),
-        n_jobs_err = jobs_err.to_string().red(),
+        a_uuid = submit_a.uuid.to_string().cyan(),
+        a_time = submit_a.submit_time.to_string().cyan(),
+        a_hash = githash_a.hash.cyan(),
+        a_pkg = package_a.name.cyan(),
+        a_vers = package_a.version.cyan(),
+        a_image = image_a.name.cyan(),
+        b_uuid = submit_b.uuid.to_string().cyan(),
+        b_time = submit_b.submit_time.to_string().cyan(),
+        b_hash = changed(&githash_a.hash, &githash_b.hash),
+        b_pkg = changed(&package_a.name, &package_b.name),
+        b_vers = changed(&package_a.version, &package_b.version),
+        b_image = changed(&image_a.name, &image_b.name),
     )?;
 
-    let header = crate::commands::util::mk_header(["Job", "Success", "Package", "Version", "Container", "Endpoint", "Image"].to_vec());
-    let data = jobs.iter()
-        .map(|job| {
-            let image = models::Image::fetch_for_job(&conn, job)?
-                .ok_or_else(|| anyhow!("Image for job {} not found", job.uuid))?;
-            let package = models::Package::fetch_for_job(&conn, job)?
-                .ok_or_else(|| anyhow!("Package for job {} not found", job.uuid))?;
-            let endpoint = models::Endpoint::fetch_for_job(&conn, job)?
-                .ok_or_else(|| anyhow!("Endpoint for job {} not found", job.uuid))?;
+    let names = jobs_a.keys().chain(jobs_b.keys()).collect::<BTreeSet<_>>();
+    let empty = BTreeMap::new();
 
-            Ok(vec![
-                job.uuid.to_string().cyan(),
-                match is_job_successfull(job)? {
-                    Some(true) => "Success".green(),
-                    Some(false) => "Error".red(),
-                    None => "Unknown".yellow(),
-                },
-                package.name.cyan(),
-                package.version.cyan(),
-                job.container_hash.normal(),
-                endpoint.name.normal(),
-                image.name.normal(),
-            ])
-        })
-        .collect::<Result<Vec<Vec<colored::ColoredString>>>>()?;
-    crate::commands::util::display_data(header, data, false)
+    let mut added = vec![];
+    let mut removed = vec![];
+    let mut version_changes = vec![];
+
+    // pairs of jobs (package name, job in A, job in B) that describe the same package in both
+    // submits and can be compared with each other
+    let mut pairs = vec![];
+
+    for name in names {
+        let vers_a = jobs_a.get(name).unwrap_or(&empty);
+        let vers_b = jobs_b.get(name).unwrap_or(&empty);
+
+        if vers_a.is_empty() {
+            added.extend(vers_b.keys().map(|v| (name, v)));
+            continue;
+        }
+
+        if vers_b.is_empty() {
+            removed.extend(vers_a.keys().map(|v| (name, v)));
+            continue;
+        }
+
+        let only_a = vers_a.keys().filter(|v| !vers_b.contains_key(*v)).collect::<Vec<_>>();
+        let only_b = vers_b.keys().filter(|v| !vers_a.contains_key(*v)).collect::<Vec<_>>();
+
+        vers_a.iter()
+            .filter_map(|(v, ja)| vers_b.get(v).map(|jb| (name, ja, jb)))
+            .for_each(|pair| pairs.push(pair));
+
+        if !only_a.is_empty() || !only_b.is_empty() {
+            // If exactly one version was replaced with another, the jobs are still comparable
+            if let ([va], [vb]) = (&only_a[..], &only_b[..]) {
+                pairs.push((name, &vers_a[*va], &vers_b[*vb]));
+            }
+            version_changes.push((name, only_a, only_b));
+        }
+    }
+
+    if !added.is_empty() {
+        writeln!(outlock, "Packages added:")?;
+        for (name, version) in added {
+            writeln!(outlock, "{}", format!("  + {} {}", name, version).green())?;
+        }
+        writeln!(outlock)?;
+    }
+
+    if !removed.is_empty() {
+        writeln!(outlock, "Packages removed:")?;
+        for (name, version) in removed {
+            writeln!(outlock, "{}", format!("  - {} {}", name, version).red())?;
+        }
+        writeln!(outlock)?;
+    }
+
+    if !version_changes.is_empty() {
+        writeln!(outlock, "Version changes:")?;
+        for (name, from, to) in version_changes {
+            writeln!(outlock, "  ~ {}: {} -> {}", name, from.iter().join(", ").red(), to.iter().join(", ").green())?;
+        }
+        writeln!(outlock)?;
+    }
+
+    let status_str = |s: Option<bool>| match s {
+        Some(true) => "Success".green(),
+        Some(false) => "Error".red(),
+        None => "Unknown".yellow(),
+    };
+
+    let status_changes = pairs.iter()
+        .filter(|(_, ja, jb)| ja.success != jb.success)
+        .collect::<Vec<_>>();
+    if !status_changes.is_empty() {
+        writeln!(outlock, "Changed job status:")?;
+        for (name, ja, jb) in status_changes {
+            writeln!(outlock, "  {}: {} ({}) -> {} ({})",
+                name,
+                status_str(ja.success),
+                ja.job.uuid,
+                status_str(jb.success),
+                jb.job.uuid)?;
+        }
+        writeln!(outlock)?;
+    }
+
+    let env_changes = pairs.iter()
+        .filter(|(_, ja, jb)| ja.env != jb.env)
+        .collect::<Vec<_>>();
+    if !env_changes.is_empty() {
+        writeln!(outlock, "Environment differences:")?;
+        for (name, ja, jb) in env_changes {
+            writeln!(outlock, "  {} ({} -> {}):", name, ja.job.uuid, jb.job.uuid)?;
+            for (k, v) in ja.env.difference(&jb.env) {
+                writeln!(outlock, "{}", format!("    - {}={}", k, v).red())?;
+            }
+            for (k, v) in jb.env.difference(&ja.env) {
+                writeln!(outlock, "{}", format!("    + {}={}", k, v).green())?;
+            }
+        }
+        writeln!(outlock)?;
+    }
+
+    let script_changes = pairs.iter()
+        .filter(|(_, ja, jb)| ja.job.script_text != jb.job.script_text)
+        .collect::<Vec<_>>();
+    if !script_changes.is_empty() {
+        writeln!(outlock, "Script differences:")?;
+        for (name, ja, jb) in script_changes {
+            if show_script_diff {
+                writeln!(outlock, "  {}:", name)?;
+                let diff = crate::ui::diff_to_printable(
+                    &ja.job.script_text,
+                    &jb.job.script_text,
+                    &ja.job.uuid.to_string(),
+                    &jb.job.uuid.to_string(),
+                    atty::is(atty::Stream::Stdout),
+                );
+                writeln!(outlock, "{}", diff)?;
+            } else {
+                writeln!(outlock, "  {} ({} -> {})", name, ja.job.uuid, jb.job.uuid)?;
+            }
+        }
+    }
+
+    Ok(())
 }
 
 /// Implementation of the "db submits" subcommand
-fn submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+fn submits(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMatches) -> Result<()> {
     let csv = matches.is_present("csv");
+    let format = list_format(matches, config.submit_list_format());
     let limit = matches.value_of("limit").map(i64::from_str).transpose()?;
-    let hdrs = crate::commands::util::mk_header(vec!["Time", "UUID", "For Package", "For Package Version"]);
+    let hdrs = crate::commands::util::mk_header(vec!["Time", "UUID", "For Package", "For Package Version", "Submitted by"]);
     let conn = conn_cfg.establish_connection()?;
     let commit = matches.value_of("for-commit");
 
@@ -430,6 +1081,7 @@ fn submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
             submit.uuid.to_string(),
             package.name,
             package.version,
+            submit.submitted_by.unwrap_or_default(),
         ]
     };
 
@@ -438,15 +1090,17 @@ fn submits(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
     if data.is_empty() {
         info!("No submits in database");
     } else {
-        crate::commands::util::display_data(hdrs, data, csv)?;
+        let fields = ["time", "uuid", "package_name", "package_version", "submitted_by"];
+        crate::commands::util::display_data_with_format(hdrs, &fields, data, csv, format)?;
     }
 
     Ok(())
 }
 
 /// Implementation of the "db jobs" subcommand
-fn jobs(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+fn jobs(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMatches) -> Result<()> {
     let csv = matches.is_present("csv");
+    let format = list_format(matches, config.job_list_format());
     let hdrs = crate::commands::util::mk_header(vec![
         "Submit",
         "Job",
@@ -477,11 +1131,7 @@ fn jobs(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     if let Some((name, val)) = matches.value_of("env_filter").map(crate::util::env::parse_to_env).transpose()? {
         debug!("Filtering for ENV: {} = {}", name, val);
         let jids = schema::envvars::table
-            .filter({
-                use crate::diesel::BoolExpressionMethods;
-                schema::envvars::dsl::name.eq(name.as_ref())
-                    .and(schema::envvars::dsl::value.eq(val))
-            })
+            .filter(schema::envvars::dsl::name.eq(name.as_ref()).and(schema::envvars::dsl::value.eq(val)))
             .inner_join(schema::job_envs::table)
             .select(schema::job_envs::job_id)
             .load::<i32>(&conn)?;
@@ -536,7 +1186,8 @@ fn jobs(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
     if data.is_empty() {
         info!("No submits in database");
     } else {
-        crate::commands::util::display_data(hdrs, data, csv)?;
+        let fields = ["submit_uuid", "job_uuid", "time", "endpoint", "success", "package_name", "package_version"];
+        crate::commands::util::display_data_with_format(hdrs, &fields, data, csv, format)?;
     }
 
     Ok(())
@@ -572,7 +1223,8 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
         )>(&conn)?;
 
     trace!("Parsing log");
-    let parsed_log = crate::log::ParsedLog::from_str(&data.0.log_text)?;
+    let log_text = crate::log::resolve_log(&data.0.log_text)?;
+    let parsed_log = crate::log::ParsedLog::from_str(&log_text)?;
     trace!("Parsed log = {:?}", parsed_log);
     let success = parsed_log.is_successfull();
     trace!("log successfull = {:?}", success);
@@ -609,9 +1261,10 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
                     .inner_join(schema::envvars::table)
                     .load::<(models::JobEnv, models::EnvVar)>(&conn)?
                     .into_iter()
-                    .map(|tpl| tpl.1)
+                    .map(|tpl| format!("{}={}", tpl.1.name, tpl.1.value))
+                    .chain(data.0.endpoint_env.iter().map(|env| format!("{} (from endpoint)", env)))
                     .enumerate()
-                    .map(|(i, env)| format!("\t{:>3}. {}={}", i, env.name, env.value))
+                    .map(|(i, env)| format!("\t{:>3}. {}", i, env))
                     .join("\n")
             })
         } else {
@@ -628,7 +1281,9 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
 
                 Ran on:     {endpoint_name}
                 Image:      {image_name}
+                Image ID:   {image_digest}
                 Container:  {container_hash}
+                Mounts:     {mounts}
 
                 Script:     {script_len} lines
                 Log:        {log_len} lines
@@ -649,9 +1304,15 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
             package_version = data.3.version.cyan(),
             endpoint_name = data.2.name.cyan(),
             image_name = data.4.name.cyan(),
+            image_digest = data.0.image_digest.as_deref().unwrap_or("unknown").cyan(),
             container_hash = data.0.container_hash.cyan(),
+            mounts = if data.0.endpoint_mounts.is_empty() {
+                String::from("none").cyan()
+            } else {
+                data.0.endpoint_mounts.join(", ").cyan()
+            },
             script_len = format!("{:<4}", data.0.script_text.lines().count()).cyan(),
-            log_len = format!("{:<4}", data.0.log_text.lines().count()).cyan(),
+            log_len = format!("{:<4}", log_text.lines().count()).cyan(),
         );
         writeln!(out, "{}", s)?;
 
@@ -668,6 +1329,40 @@ fn job(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMa
             writeln!(out, "{}", s)?;
         }
 
+        let phases = models::JobPhase::belonging_to(&data.0)
+            .order_by(schema::job_phases::position.asc())
+            .load::<models::JobPhase>(&conn)?;
+        if !phases.is_empty() {
+            let total = phases.iter().map(|p| p.duration_secs).sum::<f64>();
+            let name_width = phases.iter().map(|p| p.name.len()).max().unwrap_or(0); // never empty
+            let phases = phases
+                .iter()
+                .map(|phase| {
+                    let share = if total > 0.0 { phase.duration_secs / total * 100.0 } else { 0.0 };
+                    format!("\t{:>3}. {:<width$}  {:>10}  {:>5.1}%",
+                        phase.position + 1,
+                        phase.name,
+                        crate::commands::util::format_duration_secs(phase.duration_secs),
+                        share,
+                        width = name_width)
+                })
+                .join("\n");
+
+            let s = indoc::formatdoc!(
+                r#"
+                ---
+
+                Phases:     {total}
+
+                {phases}
+
+            "#,
+                total = crate::commands::util::format_duration_secs(total).cyan(),
+                phases = phases
+            );
+            writeln!(out, "{}", s)?;
+        }
+
         if show_script {
             let theme = configured_theme.as_ref().ok_or_else(|| {
                 anyhow!("Highlighting for script enabled, but no theme configured")
@@ -724,6 +1419,7 @@ fn log_of(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
         .map(uuid::Uuid::parse_str)
         .transpose()?
         .unwrap();
+    let stderr_only = matches.is_present("stderr_only");
     let out = std::io::stdout();
     let mut lock = out.lock();
 
@@ -732,16 +1428,66 @@ fn log_of(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()>
         .select(schema::jobs::dsl::log_text)
         .first::<String>(&conn)
         .map_err(Error::from)
-        .and_then(|s| crate::log::ParsedLog::from_str(&s))?
+        .and_then(|s| crate::log::ParsedLog::from_str(&crate::log::resolve_log(&s)?))?
         .into_iter()
+        .filter(|item| !stderr_only || matches!(item, crate::log::LogItem::StderrLine(_)))
         .map(|line| line.display().and_then(|d| writeln!(lock, "{}", d).map_err(Error::from)))
         .collect::<Result<Vec<()>>>()
         .map(|_| ())
 }
 
+/// Implementation of the "db provenance-of" subcommand
+///
+/// Prints the provenance attestations that were written next to the artifacts of a job, searching
+/// the staging directory of the submit and the release stores.
+fn provenance_of(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMatches) -> Result<()> {
+    let conn = conn_cfg.establish_connection()?;
+    let job_uuid = matches
+        .value_of("job_uuid")
+        .map(uuid::Uuid::parse_str)
+        .transpose()?
+        .unwrap();
+
+    let (job, submit) = schema::jobs::table
+        .filter(schema::jobs::dsl::uuid.eq(job_uuid))
+        .inner_join(schema::submits::table)
+        .first::<(models::Job, models::Submit)>(&conn)?;
+    let artifacts = models::Artifact::belonging_to(&job).load::<models::Artifact>(&conn)?;
+
+    let roots = std::iter::once(config.staging_directory().join(submit.uuid.to_string()))
+        .chain(config.release_stores().iter().map(|name| config.releases_directory().join(name)))
+        .collect::<Vec<_>>();
+
+    let out = std::io::stdout();
+    let mut lock = out.lock();
+    for art in artifacts {
+        let artifact = roots.iter()
+            .map(|root| root.join(&art.path))
+            .find(|p| crate::filestore::provenance_path_for(p).is_file());
+
+        match artifact {
+            Some(artifact) => {
+                let (provenance, signature) = (
+                    crate::filestore::provenance_path_for(&artifact),
+                    crate::filestore::signature_path_for(&artifact),
+                );
+                writeln!(lock, "{}: {}", art.path, provenance.display())?;
+                if signature.is_file() {
+                    writeln!(lock, "Signature: {}", signature.display())?;
+                }
+                writeln!(lock, "{}", std::fs::read_to_string(&provenance)?)?;
+            },
+            None => log::warn!("No provenance found for artifact {}", art.path),
+        }
+    }
+
+    Ok(())
+}
+
 /// Implementation of the "db releases" subcommand
 fn releases(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &ArgMatches) -> Result<()> {
     let csv    = matches.is_present("csv");
+    let format = list_format(matches, config.release_list_format());
     let conn   = conn_cfg.establish_connection()?;
     let header = crate::commands::util::mk_header(["Package", "Version", "Date", "Path"].to_vec());
     let mut query = schema::jobs::table
@@ -772,6 +1518,11 @@ fn releases(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &
         query = query.filter(schema::packages::dsl::name.eq(pkg));
     }
 
+    let version_constraint = matches
+        .value_of("package_version_constraint")
+        .map(PackageVersionConstraint::try_from)
+        .transpose()?;
+
     let data = query
         .select({
             let art = schema::artifacts::all_columns;
@@ -782,8 +1533,14 @@ fn releases(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &
         })
         .load::<(models::Artifact, models::Package, models::Release, models::ReleaseStore)>(&conn)?
         .into_iter()
+        .filter(|(_, pack, _, _)| {
+            version_constraint
+                .as_ref()
+                .map(|c| c.matches(&PackageVersion::from(pack.version.clone())))
+                .unwrap_or(true)
+        })
         .filter_map(|(art, pack, rel, rstore)| {
-            let p = config.releases_directory().join(rstore.store_name).join(&art.path);
+            let p = config.releases_directory().join(&rstore.store_name).join(&art.path);
 
             if p.is_file() {
                 Some(vec![
@@ -791,6 +1548,7 @@ fn releases(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &
                     pack.version,
                     rel.release_date.to_string(),
                     p.display().to_string(),
+                    rstore.store_name,
                 ])
             } else {
                 log::warn!("Released file for {} {} not found: {}", pack.name, pack.version, p.display());
@@ -799,7 +1557,23 @@ fn releases(conn_cfg: DbConnectionConfig<'_>, config: &Configuration, matches: &
         })
         .collect::<Vec<Vec<_>>>();
 
-    crate::commands::util::display_data(header, data, csv)
+    let fields = ["package_name", "package_version", "date", "path", "store"];
+    if matches.is_present("json") {
+        crate::commands::util::display_data_as_json(&fields, data)
+    } else if format.is_some() {
+        crate::commands::util::display_data_with_format(header, &fields, data, csv, format)
+    } else {
+        // The store is only available in the format, the path contains it already
+        let data = data.into_iter().map(|mut row| { row.truncate(4); row }).collect();
+        crate::commands::util::display_data(header, data, csv)
+    }
+}
+
+/// The format for the rows of a listing: the --format argument, or the format from the
+/// configuration if the output is not CSV
+fn list_format<'a>(matches: &'a ArgMatches, configured: &'a Option<String>) -> Option<&'a str> {
+    matches.value_of("format")
+        .or_else(|| configured.as_deref().filter(|_| !matches.is_present("csv")))
 }
 
 /// Check if a job is successful
@@ -809,3 +1583,260 @@ fn is_job_successfull(job: &models::Job) -> Result<Option<bool>> {
     crate::log::ParsedLog::from_str(&job.log_text).map(|pl| pl.is_successfull().to_bool())
 }
 
+/// Implementation of the "db export" subcommand
+fn export(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let output = matches.value_of("output").map(PathBuf::from).unwrap(); // safe by clap
+    let conn = conn_cfg.establish_connection()?;
+    let dump = crate::db::Dump::load(&conn)?;
+
+    let file = std::fs::File::create(&output)
+        .with_context(|| anyhow!("Creating file: {}", output.display()))?;
+    let mut writer = std::io::BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, &dump)
+        .with_context(|| anyhow!("Writing dump to {}", output.display()))?;
+    writer.flush()?;
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    for (table, rows) in dump.row_counts() {
+        writeln!(outlock, "Exported {} rows from {}", rows.to_string().green(), table)?;
+    }
+    Ok(())
+}
+
+/// Implementation of the "db import" subcommand
+fn import(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let input = matches.value_of("input").map(PathBuf::from).unwrap(); // safe by clap
+    let file = std::fs::File::open(&input)
+        .with_context(|| anyhow!("Opening file: {}", input.display()))?;
+    let dump: crate::db::Dump = serde_json::from_reader(std::io::BufReader::new(file))
+        .with_context(|| anyhow!("Parsing dump from {}", input.display()))?;
+
+    let conn = conn_cfg.establish_connection()?;
+    dump.restore(&conn)?;
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    for (table, rows) in dump.row_counts() {
+        writeln!(outlock, "Imported {} rows into {}", rows.to_string().green(), table)?;
+    }
+    Ok(())
+}
+
+/// The access statistics of a table, from `pg_stat_user_tables`
+#[derive(QueryableByName)]
+struct TableStatistics {
+    #[sql_type = "diesel::sql_types::Text"]
+    table_name: String,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    seq_scan: i64,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    seq_tup_read: i64,
+    #[sql_type = "diesel::sql_types::Nullable<diesel::sql_types::BigInt>"]
+    idx_scan: Option<i64>,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    n_live_tup: i64,
+}
+
+/// The execution statistics of a statement, from `pg_stat_statements`
+#[derive(QueryableByName)]
+struct StatementStatistics {
+    #[sql_type = "diesel::sql_types::Text"]
+    query: String,
+    #[sql_type = "diesel::sql_types::BigInt"]
+    calls: i64,
+    #[sql_type = "diesel::sql_types::Double"]
+    mean_ms: f64,
+    #[sql_type = "diesel::sql_types::Double"]
+    total_ms: f64,
+}
+
+/// Implementation of the "db analyze" subcommand
+///
+/// Tables that are mostly read sequentially (instead of with an index) and the statements that
+/// take the longest point to missing indexes. The statistics of the statements are only available
+/// if the `pg_stat_statements` extension is installed.
+fn analyze(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let csv = matches.is_present("csv");
+    let limit = matches.value_of("limit").map(i64::from_str).transpose()?.unwrap_or(10);
+    let conn = conn_cfg.establish_connection()?;
+
+    let tables = diesel::sql_query(indoc::indoc!(r#"
+            SELECT relname::text AS table_name, seq_scan, seq_tup_read, idx_scan, n_live_tup
+            FROM pg_stat_user_tables
+            WHERE relname != '__diesel_schema_migrations'
+            ORDER BY seq_tup_read DESC
+        "#))
+        .load::<TableStatistics>(&conn)
+        .context("Loading table statistics")?
+        .into_iter()
+        .map(|t| {
+            vec![
+                t.table_name,
+                t.n_live_tup.to_string(),
+                t.seq_scan.to_string(),
+                t.seq_tup_read.to_string(),
+                t.idx_scan.map(|i| i.to_string()).unwrap_or_default(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let hdrs = crate::commands::util::mk_header(vec!["Table", "Rows", "Seq. scans", "Rows read by seq. scans", "Index scans"]);
+    crate::commands::util::display_data(hdrs, tables, csv)?;
+
+    let has_statement_statistics = diesel::select(diesel::dsl::sql::<diesel::sql_types::Bool>(
+            "EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_stat_statements')"
+        ))
+        .get_result::<bool>(&conn)?;
+    if !has_statement_statistics {
+        info!("The pg_stat_statements extension is not installed, no statistics of the statements are available");
+        return Ok(())
+    }
+
+    let statements = diesel::sql_query(indoc::indoc!(r#"
+            SELECT query, calls, mean_exec_time AS mean_ms, total_exec_time AS total_ms
+            FROM pg_stat_statements
+            WHERE dbid = (SELECT oid FROM pg_database WHERE datname = current_database())
+            ORDER BY mean_exec_time DESC
+            LIMIT $1
+        "#))
+        .bind::<diesel::sql_types::BigInt, _>(limit)
+        .load::<StatementStatistics>(&conn)
+        .context("Loading statement statistics from pg_stat_statements")?
+        .into_iter()
+        .map(|s| {
+            vec![
+                format!("{:.1}", s.mean_ms),
+                s.calls.to_string(),
+                format!("{:.1}", s.total_ms),
+                s.query.split_whitespace().join(" "),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let hdrs = crate::commands::util::mk_header(vec!["Mean (ms)", "Calls", "Total (ms)", "Statement"]);
+    writeln!(std::io::stdout())?;
+    crate::commands::util::display_data(hdrs, statements, csv)
+}
+
+/// Implementation of the "db audit" subcommand
+fn audit(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let csv = matches.is_present("csv");
+    let conn = conn_cfg.establish_connection()?;
+    let mut query = schema::audit::table
+        .order_by(schema::audit::id.desc())
+        .into_boxed();
+
+    if let Some(date) = get_date_filter("older_than", matches)? {
+        query = query.filter(schema::audit::recorded_at.lt(date));
+    }
+    if let Some(date) = get_date_filter("newer_than", matches)? {
+        query = query.filter(schema::audit::recorded_at.gt(date));
+    }
+    if let Some(operation) = matches.value_of("operation") {
+        query = query.filter(schema::audit::operation.eq(operation));
+    }
+    if let Some(user) = matches.value_of("user") {
+        query = query.filter(schema::audit::username.eq(user));
+    }
+    if let Some(limit) = matches.value_of("limit").map(i64::from_str).transpose()? {
+        query = query.limit(limit);
+    }
+
+    let entries = query.load::<models::AuditEntry>(&conn)?;
+    if matches.is_present("json") {
+        let entries = entries.into_iter()
+            .map(|entry| serde_json::json!({
+                "date": entry.recorded_at.to_string(),
+                "user": entry.username,
+                "operation": entry.operation,
+                "parameters": entry.parameters,
+            }))
+            .collect::<Vec<_>>();
+        let out = std::io::stdout();
+        let mut outlock = out.lock();
+        serde_json::to_writer_pretty(&mut outlock, &entries)?;
+        return writeln!(outlock).map_err(Error::from)
+    }
+
+    let data = entries.into_iter()
+        .map(|entry| vec![entry.recorded_at.to_string(), entry.username, entry.operation, entry.parameters.to_string()])
+        .collect::<Vec<_>>();
+    let hdrs = crate::commands::util::mk_header(vec!["Date", "User", "Operation", "Parameters"]);
+    crate::commands::util::display_data(hdrs, data, csv)
+}
+
+/// Implementation of the "db running" subcommand
+///
+/// Lists the jobs that the orchestrators of all active submits registered as running, which
+/// includes the jobs that wait for an endpoint ("queued").
+fn running(conn_cfg: DbConnectionConfig<'_>, matches: &ArgMatches) -> Result<()> {
+    let csv = matches.is_present("csv");
+    let conn = conn_cfg.establish_connection()?;
+    let now = chrono::Utc::now().naive_utc();
+    let not_before = now - chrono::Duration::hours(crate::orchestrator::RUNNING_JOB_STALE_AFTER_HOURS);
+
+    let mut query = schema::running_jobs::table
+        .inner_join(schema::submits::table.inner_join(schema::images::table))
+        .inner_join(schema::submit_jobs::table.on({
+            schema::submit_jobs::uuid.eq(schema::running_jobs::uuid)
+                .and(schema::submit_jobs::submit_id.eq(schema::running_jobs::submit_id))
+        }))
+        .inner_join(schema::packages::table.on(schema::submit_jobs::package_id.eq(schema::packages::id)))
+        .filter(schema::running_jobs::started_at.ge(not_before))
+        .order_by(schema::running_jobs::id.asc())
+        .into_boxed();
+    if let Some(submit) = matches.value_of("submit").map(uuid::Uuid::parse_str).transpose()? {
+        query = query.filter(schema::submits::uuid.eq(submit));
+    }
+
+    let jobs = query
+        .select((
+            schema::running_jobs::all_columns,
+            schema::submits::uuid,
+            schema::images::name,
+            schema::packages::name,
+            schema::packages::version,
+        ))
+        .load::<(models::RunningJob, uuid::Uuid, String, String, String)>(&conn)?;
+    if jobs.is_empty() {
+        info!("No running jobs");
+        return Ok(())
+    }
+
+    let data = jobs.into_iter()
+        .map(|(job, submit, image, package_name, package_version)| {
+            let elapsed_secs = (now - job.scheduled_at.unwrap_or(job.started_at)).num_seconds() as f64;
+
+            // Without progress reported by the script, the progress is estimated from the
+            // durations of earlier jobs of the package
+            let progress = match (job.progress, job.scheduled_at.is_some()) {
+                (Some(progress), _) => format!("{}%", progress),
+                (None, true) => models::JobPhase::average_job_duration_secs(
+                        &conn,
+                        &package_name,
+                        &package_version,
+                        &image,
+                        crate::orchestrator::EXPECTED_DURATION_JOBS,
+                    )?
+                    .filter(|expected_secs| *expected_secs > 0.0)
+                    .map(|expected_secs| format!("~{}%", ((elapsed_secs / expected_secs * 100.0) as u64).min(99)))
+                    .unwrap_or_default(),
+                (None, false) => String::new(),
+            };
+
+            Ok(vec![
+                submit.to_string(),
+                job.uuid.to_string(),
+                package_name,
+                package_version,
+                String::from(if job.scheduled_at.is_some() { "running" } else { "queued" }),
+                job.endpoint_name.unwrap_or_default(),
+                job.phase.unwrap_or_default(),
+                crate::commands::util::format_duration_secs(elapsed_secs),
+                progress,
+            ])
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let hdrs = crate::commands::util::mk_header(vec!["Submit", "Job", "Package", "Version", "State", "Endpoint", "Phase", "Elapsed", "Progress"]);
+    crate::commands::util::display_data(hdrs, data, csv)
+}
@@ -38,6 +38,7 @@ pub async fn dependencies_of(
             .map(String::from)
             .map(PackageName::from)
             .unwrap();
+        let name = repo.resolve_name(&name)?;
         trace!("Checking for package with name = {}", name);
 
         crate::util::filters::build_package_filter_by_name(name)
@@ -73,6 +74,7 @@ pub async fn dependencies_of(
         print_sources: false,
         print_dependencies: true,
         print_patches: false,
+        print_maintainers: false,
         print_env: false,
         print_flags: false,
         print_allowed_images: false,
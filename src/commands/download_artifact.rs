@@ -0,0 +1,157 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'db download-artifact' subcommand
+
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use log::debug;
+use log::trace;
+use log::warn;
+
+use crate::config::Configuration;
+use crate::db::models as dbmodels;
+use crate::db::DbConnectionConfig;
+use crate::filestore::ObjectStore;
+use crate::schema;
+
+/// Implementation of the "db download-artifact" subcommand
+///
+/// The artifact is searched in the release stores it was released to (latest release first), in
+/// the staging directory of its submit and in the additional staging directories. Candidates
+/// that do not match the recorded hash of the artifact are skipped.
+pub async fn download_artifact(
+    conn_cfg: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    let target = matches.value_of("artifact").unwrap(); // safe by clap
+    let to = matches.value_of("to").map(PathBuf::from).unwrap(); // safe by clap
+    let overwrite = matches.is_present("force");
+    let conn = conn_cfg.establish_connection()?;
+
+    let artifacts = match uuid::Uuid::parse_str(target) {
+        Ok(job_uuid) => {
+            let job = schema::jobs::table
+                .filter(schema::jobs::uuid.eq(job_uuid))
+                .first::<dbmodels::Job>(&conn)
+                .optional()?
+                .ok_or_else(|| anyhow!("Job {} not found", job_uuid))?;
+            let artifacts = schema::artifacts::table
+                .filter(schema::artifacts::job_id.eq(job.id))
+                .order(schema::artifacts::path.asc())
+                .load::<dbmodels::Artifact>(&conn)?;
+            if artifacts.is_empty() {
+                return Err(anyhow!("Job {} has no artifacts", job_uuid))
+            }
+            artifacts
+        },
+        Err(_) => {
+            let id = target.parse::<i32>()
+                .map_err(|_| anyhow!("'{}' is neither an artifact id nor a job UUID", target))?;
+            let artifact = schema::artifacts::table
+                .find(id)
+                .first::<dbmodels::Artifact>(&conn)
+                .optional()?
+                .ok_or_else(|| anyhow!("Artifact {} not found", id))?;
+            vec![artifact]
+        },
+    };
+
+    tokio::fs::create_dir_all(&to)
+        .await
+        .with_context(|| anyhow!("Creating directory {}", to.display()))?;
+
+    let mut out = std::io::stdout();
+    for artifact in artifacts {
+        let file_name = artifact.path_buf()
+            .file_name()
+            .map(PathBuf::from)
+            .ok_or_else(|| anyhow!("Artifact {} has no file name: {}", artifact.id, artifact.path))?;
+        let destination = to.join(file_name);
+        if destination.exists() && !overwrite {
+            return Err(anyhow!("{} exists already", destination.display()))
+        }
+
+        let mut copied = None;
+        for candidate in candidates(&conn, config, &artifact)?.into_iter().filter(|c| c.is_file()) {
+            match copy_verified(&candidate, &destination, &artifact).await {
+                Ok(()) => {
+                    copied = Some(candidate);
+                    break
+                },
+                Err(e) => warn!("Skipping {}: {:?}", candidate.display(), e),
+            }
+        }
+
+        let source = copied
+            .ok_or_else(|| anyhow!("No matching file of artifact {} ({}) found in the stores", artifact.id, artifact.path))?;
+        writeln!(out, "{} -> {}", source.display(), destination.display())?;
+    }
+    Ok(())
+}
+
+/// The files the artifact might be stored in, in the order they are tried
+fn candidates(conn: &PgConnection, config: &Configuration, artifact: &dbmodels::Artifact) -> Result<Vec<PathBuf>> {
+    let released_to = schema::releases::table
+        .inner_join(schema::release_stores::table)
+        .filter(schema::releases::artifact_id.eq(artifact.id))
+        .order(schema::releases::release_date.desc())
+        .select(schema::release_stores::store_name)
+        .load::<String>(conn)?;
+
+    let submit_uuid = schema::jobs::table
+        .inner_join(schema::submits::table)
+        .filter(schema::jobs::id.eq(artifact.job_id))
+        .select(schema::submits::uuid)
+        .first::<uuid::Uuid>(conn)?;
+
+    let candidates = released_to.into_iter()
+        .map(|store| config.releases_directory().join(store))
+        .chain(std::iter::once(config.staging_directory().join(submit_uuid.to_string())))
+        .chain(config.additional_staging_directories().iter().cloned())
+        .map(|root| root.join(artifact.path_buf()))
+        .collect::<Vec<_>>();
+    trace!("Candidates for artifact {}: {:?}", artifact.id, candidates);
+    Ok(candidates)
+}
+
+/// Copy `source` to `destination` and check the copy against the recorded hash of `artifact`
+///
+/// A copy that does not match is removed again.
+async fn copy_verified(source: &Path, destination: &Path, artifact: &dbmodels::Artifact) -> Result<()> {
+    debug!("Copying {} to {}", source.display(), destination.display());
+    tokio::fs::copy(source, destination)
+        .await
+        .with_context(|| anyhow!("Copying {} to {}", source.display(), destination.display()))?;
+
+    let expected = match artifact.sha256.as_ref() {
+        Some(sha256) => sha256,
+        None => {
+            warn!("Artifact {} was built before its checksum was recorded, {} is not verified", artifact.id, destination.display());
+            return Ok(())
+        },
+    };
+
+    let actual = ObjectStore::hash_file(destination).await?;
+    if actual != *expected {
+        let _ = tokio::fs::remove_file(destination).await;
+        return Err(anyhow!("{} does not match artifact {}: sha256 {} instead of {}", source.display(), artifact.id, actual, expected))
+    }
+    Ok(())
+}
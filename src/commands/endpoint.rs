@@ -27,10 +27,18 @@ use tokio_stream::StreamExt;
 
 use crate::config::Configuration;
 use crate::config::EndpointName;
+use crate::db::DbConnectionConfig;
+use crate::db::models as dbmodels;
 use crate::util::progress::ProgressBars;
+use crate::endpoint::ContainerStat;
 use crate::endpoint::Endpoint;
 
-pub async fn endpoint(matches: &ArgMatches, config: &Configuration, progress_generator: ProgressBars) -> Result<()> {
+pub async fn endpoint(
+    matches: &ArgMatches,
+    config: &Configuration,
+    db_connection_config: DbConnectionConfig<'_>,
+    progress_generator: ProgressBars,
+) -> Result<()> {
     let endpoint_names = matches
         .value_of("endpoint_name")
         .map(String::from)
@@ -48,9 +56,12 @@ pub async fn endpoint(matches: &ArgMatches, config: &Configuration, progress_gen
     match matches.subcommand() {
         Some(("ping", matches)) => ping(endpoint_names, matches, config, progress_generator).await,
         Some(("stats", matches)) => stats(endpoint_names, matches, config, progress_generator).await,
+        Some(("health", matches)) => health(endpoint_names, matches, config).await,
         Some(("container", matches)) => crate::commands::endpoint_container::container(endpoint_names, matches, config).await,
-        Some(("containers", matches)) => containers(endpoint_names, matches, config).await,
+        Some(("containers", matches)) => containers(endpoint_names, matches, config, db_connection_config).await,
         Some(("images", matches)) => images(endpoint_names, matches, config).await,
+        Some(("volumes", matches)) => volumes(endpoint_names, matches, config, db_connection_config).await,
+        Some(("sync-image", matches)) => sync_image(endpoint_names, matches, config, progress_generator).await,
         Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
         None => Err(anyhow!("No subcommand")),
     }
@@ -126,6 +137,7 @@ async fn stats(endpoint_names: Vec<EndpointName>,
         "Cores",
         "OS",
         "System Time",
+        "API version",
     ].to_vec());
 
     let data = endpoints
@@ -157,6 +169,7 @@ async fn stats(endpoint_names: Vec<EndpointName>,
                 stat.n_cpu.to_string(),
                 stat.operating_system.to_string(),
                 stat.system_time.unwrap_or_else(|| String::from("unknown")),
+                stat.api_version.unwrap_or_else(|| String::from("unknown")),
             ]
         })
         .collect();
@@ -165,14 +178,47 @@ async fn stats(endpoint_names: Vec<EndpointName>,
     crate::commands::util::display_data(hdr, data, csv)
 }
 
+async fn health(endpoint_names: Vec<EndpointName>,
+    matches: &ArgMatches,
+    config: &Configuration,
+) -> Result<()> {
+    let csv = matches.is_present("csv");
+    let endpoints = crate::endpoint::util::connect_endpoints_unchecked(endpoint_configurations(config, &endpoint_names)).await?;
+    let hdr = crate::commands::util::mk_header(["Name", "Healthy", "Response time", "Error"].to_vec());
+
+    let data = endpoints
+        .iter()
+        .map(|endpoint| async move {
+            let start = std::time::Instant::now();
+            let result = endpoint.check_health().await;
+            let elapsed = start.elapsed();
+            debug!("Health check of {}: {:?}", endpoint.name(), result);
+
+            vec![
+                endpoint.name().to_string(),
+                if endpoint.is_healthy() { "yes" } else { "no" }.to_string(),
+                result.as_ref().map(|_| format!("{}ms", elapsed.as_millis())).unwrap_or_default(),
+                result.err().map(|e| e.to_string()).unwrap_or_default(),
+            ]
+        })
+        .collect::<futures::stream::FuturesUnordered<_>>()
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .sorted()
+        .collect::<Vec<_>>();
+
+    crate::commands::util::display_data(hdr, data, csv)
+}
 
 async fn containers(endpoint_names: Vec<EndpointName>,
     matches: &ArgMatches,
     config: &Configuration,
+    db_connection_config: DbConnectionConfig<'_>,
 ) -> Result<()> {
     match matches.subcommand() {
         Some(("list", matches)) => containers_list(endpoint_names, matches, config).await,
-        Some(("prune", matches)) => containers_prune(endpoint_names, matches, config).await,
+        Some(("prune", matches)) => containers_prune(endpoint_names, matches, config, db_connection_config).await,
         Some(("top", matches)) => containers_top(endpoint_names, matches, config).await,
         Some(("stop", matches)) => containers_stop(endpoint_names, matches, config).await,
         Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
@@ -188,13 +234,18 @@ async fn containers_list(endpoint_names: Vec<EndpointName>,
     let filter_image = matches.value_of("filter_image");
     let older_than_filter = crate::commands::util::get_date_filter("older_than", matches)?;
     let newer_than_filter = crate::commands::util::get_date_filter("newer_than", matches)?;
+    let label_filter = ContainerLabelFilter::from_matches(matches);
     let csv = matches.is_present("csv");
     let hdr = crate::commands::util::mk_header([
         "Endpoint",
         "Container id",
+        "Name",
         "Image",
         "Created",
         "Status",
+        "Package",
+        "Submit",
+        "Job",
     ].to_vec());
 
     let data = connect_to_endpoints(config, &endpoint_names)
@@ -215,13 +266,18 @@ async fn containers_list(endpoint_names: Vec<EndpointName>,
                 .filter(|stat| filter_image.map(|fim| fim == stat.image).unwrap_or(true))
                 .filter(|stat| older_than_filter.as_ref().map(|time| time > &stat.created).unwrap_or(true))
                 .filter(|stat| newer_than_filter.as_ref().map(|time| time < &stat.created).unwrap_or(true))
+                .filter(|stat| label_filter.matches(stat))
                 .map(|stat| {
                     vec![
                         endpoint_name.as_ref().to_owned(),
                         stat.id,
+                        stat.name.unwrap_or_default(),
                         stat.image,
                         stat.created.to_string(),
                         stat.status,
+                        stat.package.map(|(name, version)| format!("{} {}", name, version)).unwrap_or_default(),
+                        stat.submit.unwrap_or_default(),
+                        stat.job.unwrap_or_default(),
                     ]
                 })
                 .collect::<Vec<Vec<String>>>()
@@ -231,12 +287,41 @@ async fn containers_list(endpoint_names: Vec<EndpointName>,
     crate::commands::util::display_data(hdr, data, csv)
 }
 
+/// Filters on the labels butido sets on the containers of jobs
+struct ContainerLabelFilter<'a> {
+    submit: Option<&'a str>,
+    package: Option<&'a str>,
+    endpoint: Option<&'a str>,
+    only_jobs: bool,
+}
+
+impl<'a> ContainerLabelFilter<'a> {
+    fn from_matches(matches: &'a ArgMatches) -> Self {
+        ContainerLabelFilter {
+            submit: matches.value_of("submit"),
+            package: matches.value_of("package"),
+            endpoint: matches.value_of("label_endpoint"),
+            only_jobs: matches.is_present("only_jobs"),
+        }
+    }
+
+    fn matches(&self, stat: &ContainerStat) -> bool {
+        (!self.only_jobs || stat.job.is_some())
+            && self.submit.map(|submit| stat.submit.as_deref() == Some(submit)).unwrap_or(true)
+            && self.package.map(|package| stat.package.as_ref().map(|(name, _)| name.as_str()) == Some(package)).unwrap_or(true)
+            && self.endpoint.map(|endpoint| stat.endpoint.as_deref() == Some(endpoint)).unwrap_or(true)
+    }
+}
+
 async fn containers_prune(endpoint_names: Vec<EndpointName>,
     matches: &ArgMatches,
     config: &Configuration,
+    db_connection_config: DbConnectionConfig<'_>,
 ) -> Result<()> {
     let older_than_filter = crate::commands::util::get_date_filter("older_than", matches)?;
     let newer_than_filter = crate::commands::util::get_date_filter("newer_than", matches)?;
+    let label_filter = ContainerLabelFilter::from_matches(matches);
+    let label_filter = &label_filter;
 
     let stats = connect_to_endpoints(config, &endpoint_names)
         .await?
@@ -248,6 +333,7 @@ async fn containers_prune(endpoint_names: Vec<EndpointName>,
                 .filter(|stat| stat.state == "exited")
                 .filter(|stat| older_than_filter.as_ref().map(|time| time > &stat.created).unwrap_or(true))
                 .filter(|stat| newer_than_filter.as_ref().map(|time| time < &stat.created).unwrap_or(true))
+                .filter(|stat| label_filter.matches(stat))
                 .map(|stat| (ep.clone(), stat))
                 .collect::<Vec<(_, _)>>();
             Ok(stats)
@@ -261,6 +347,14 @@ async fn containers_prune(endpoint_names: Vec<EndpointName>,
         return Ok(())
     }
 
+    // Recorded before, so that containers are only removed if the audit log has it
+    let containers = stats.iter()
+        .flatten()
+        .map(|(ep, stat)| serde_json::json!({ "endpoint": ep.name().as_ref(), "container": stat.id }))
+        .collect::<Vec<_>>();
+    let conn = db_connection_config.establish_connection()?;
+    dbmodels::AuditEntry::create(&conn, config, "containers-prune", serde_json::json!({ "containers": containers }))?;
+
     stats.into_iter()
         .flat_map(Vec::into_iter)
         .map(|(ep, stat)| async move {
@@ -505,10 +599,267 @@ async fn images_present(endpoint_names: Vec<EndpointName>,
         })
 }
 
+/// Implementation of the "endpoint sync-image" subcommand
+///
+/// The image is exported from one endpoint (pulled there first if no endpoint has it) and loaded
+/// on the other endpoints, one at a time, so the registry is only asked once.
+async fn sync_image(endpoint_names: Vec<EndpointName>,
+    matches: &ArgMatches,
+    config: &Configuration,
+    progress_generator: ProgressBars,
+) -> Result<()> {
+    use crate::util::docker::ImageName;
+
+    let image = matches.value_of("image").map(String::from).map(ImageName::from).unwrap(); // safe by clap
+    let force = matches.is_present("force");
+    let from = matches.value_of("from").map(String::from).map(EndpointName::from);
+    if let Some(from) = from.as_ref().filter(|from| !config.docker().endpoints().contains_key(*from)) {
+        return Err(anyhow!("Endpoint '{}' is not configured", from))
+    }
+
+    // The source can be any configured endpoint, not only one of the targets
+    let all_names = config.docker().endpoints().keys().cloned().collect::<Vec<_>>();
+    let endpoints = crate::endpoint::util::connect_endpoints_unchecked(endpoint_configurations(config, &all_names))
+        .await?
+        .into_iter()
+        .filter(|ep| ep.is_docker())
+        .collect::<Vec<_>>();
+
+    let mut has_image = HashMap::new();
+    for ep in endpoints.iter() {
+        has_image.insert(ep.name().clone(), ep.image_id(&image).await.is_ok());
+    }
+
+    let source = match from.as_ref() {
+        Some(from) => endpoints.iter().find(|ep| ep.name() == from),
+        None => endpoints.iter()
+            .find(|ep| has_image[ep.name()])
+            .or_else(|| endpoints.iter().find(|ep| endpoint_names.contains(ep.name()))),
+    }
+    .ok_or_else(|| anyhow!("No docker endpoint to get the image {} from", image))?;
+
+    if !has_image[source.name()] {
+        let bar = progress_generator.bar()?;
+        source.pull_image_if_missing(&image, &bar).await?;
+    }
+
+    let targets = endpoints.iter()
+        .filter(|ep| ep.name() != source.name() && endpoint_names.contains(ep.name()))
+        .filter(|ep| {
+            let skip = has_image[ep.name()] && !force;
+            if skip {
+                info!("{} is present on {} already", image, ep.name());
+            }
+            !skip
+        })
+        .collect::<Vec<_>>();
+
+    let out = std::io::stdout();
+    if targets.is_empty() {
+        writeln!(out.lock(), "{} is present on all endpoints", image)?;
+        return Ok(())
+    }
+
+    let tarball = std::env::temp_dir().join(format!("butido-image-{}.tar", uuid::Uuid::new_v4()));
+    let result = async {
+        let bar = progress_generator.bar()?;
+        bar.set_message(format!("Exporting {} from {}", image, source.name()));
+        let size = source.export_image(&image, &tarball).await?;
+        bar.finish_with_message(format!("Exported {} from {} ({})", image, source.name(), bytesize::ByteSize::b(size)));
+
+        let bar = progress_generator.bar()?;
+        bar.set_length(targets.len() as u64);
+        for target in targets.iter() {
+            bar.set_message(format!("Loading {} on {}", image, target.name()));
+            target.load_image(&image, &tarball).await?;
+            bar.inc(1);
+            writeln!(out.lock(), "Loaded {} on {}", image, target.name())?;
+        }
+        bar.finish_with_message(format!("Loaded {} on {} endpoints", image, targets.len()));
+        Ok(())
+    }.await;
+
+    if let Err(e) = tokio::fs::remove_file(&tarball).await {
+        debug!("Removing {}: {}", tarball.display(), e);
+    }
+    result
+}
+
+async fn volumes(endpoint_names: Vec<EndpointName>,
+    matches: &ArgMatches,
+    config: &Configuration,
+    db_connection_config: DbConnectionConfig<'_>,
+) -> Result<()> {
+    match matches.subcommand() {
+        Some(("list", matches)) => volumes_list(endpoint_names, matches, config).await,
+        Some(("create", matches)) => volumes_create(endpoint_names, matches, config).await,
+        Some(("prune", matches)) => volumes_prune(endpoint_names, matches, config, db_connection_config).await,
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("No subcommand")),
+    }
+}
+
+/// Get the cache volumes on the endpoints, with the name of the cache they belong to
+async fn cache_volumes(endpoints: &[Arc<Endpoint>]) -> Result<Vec<(Arc<Endpoint>, String, String)>> {
+    endpoints
+        .iter()
+        .filter(|ep| ep.is_docker())
+        .map(|ep| async move {
+            ep.docker()?
+                .volumes()
+                .list()
+                .await
+                .with_context(|| anyhow!("Listing volumes on {}", ep.name()))
+                .map(|volumes| {
+                    volumes.into_iter()
+                        .filter_map(|v| {
+                            let cache = v.labels.as_ref()?.get(crate::config::CACHE_VOLUME_LABEL)?.clone();
+                            Some((ep.clone(), v.name, cache))
+                        })
+                        .collect::<Vec<_>>()
+                })
+        })
+        .collect::<futures::stream::FuturesUnordered<_>>()
+        .collect::<Result<Vec<_>>>()
+        .await
+        .map(|v| v.into_iter().flatten().collect())
+}
+
+async fn volumes_list(endpoint_names: Vec<EndpointName>,
+    matches: &ArgMatches,
+    config: &Configuration,
+) -> Result<()> {
+    let csv = matches.is_present("csv");
+    let endpoints = connect_to_endpoints(config, &endpoint_names).await?;
+    let data = cache_volumes(&endpoints)
+        .await?
+        .into_iter()
+        .sorted_by(|a, b| (a.0.name(), &a.1).cmp(&(b.0.name(), &b.1)))
+        .map(|(ep, volume, cache)| vec![ep.name().to_string(), volume, cache])
+        .collect::<Vec<_>>();
+
+    if data.is_empty() {
+        info!("No cache volumes found");
+        return Ok(())
+    }
+
+    let hdrs = crate::commands::util::mk_header(vec!["Endpoint", "Volume", "Cache"]);
+    crate::commands::util::display_data(hdrs, data, csv)
+}
+
+async fn volumes_create(endpoint_names: Vec<EndpointName>,
+    _matches: &ArgMatches,
+    config: &Configuration,
+) -> Result<()> {
+    use crate::config::CacheVolumeScope;
+
+    let endpoints = connect_to_endpoints(config, &endpoint_names).await?;
+
+    // Volumes per package are created when a package is built the first time, because we do not
+    // want to load the repository here
+    let volumes = config.containers()
+        .cache_volumes()
+        .iter()
+        .flat_map(|cv| match cv.scope() {
+            CacheVolumeScope::Global => {
+                let no_package = crate::package::PackageName::from(String::new());
+                let no_image = crate::util::docker::ImageName::from(String::new());
+                vec![(cv.volume_name(&no_package, &no_image), cv.name().clone())]
+            },
+            CacheVolumeScope::Image => config.docker()
+                .images()
+                .iter()
+                .map(|image| (cv.volume_name(&crate::package::PackageName::from(String::new()), image), cv.name().clone()))
+                .collect(),
+            CacheVolumeScope::Package => {
+                info!("Volumes for cache '{}' are created per package when building", cv.name());
+                vec![]
+            },
+        })
+        .collect::<Vec<_>>();
+
+    let out = std::io::stdout();
+    let mut lock = out.lock();
+    // Endpoints without docker keep their caches in directories, which are created when building
+    for ep in endpoints.iter().filter(|ep| ep.is_docker()) {
+        for (volume, cache) in volumes.iter() {
+            ep.create_cache_volume(volume, cache).await?;
+            writeln!(lock, "Created {} on {}", volume, ep.name())?;
+        }
+    }
+    Ok(())
+}
+
+async fn volumes_prune(endpoint_names: Vec<EndpointName>,
+    matches: &ArgMatches,
+    config: &Configuration,
+    db_connection_config: DbConnectionConfig<'_>,
+) -> Result<()> {
+    let all = matches.is_present("all");
+    let caches = matches.values_of("cache").map(|v| v.collect::<Vec<_>>());
+    let endpoints = connect_to_endpoints(config, &endpoint_names).await?;
+
+    let to_remove = cache_volumes(&endpoints)
+        .await?
+        .into_iter()
+        .filter(|(_, _, cache)| {
+            if let Some(caches) = caches.as_ref() {
+                caches.contains(&cache.as_str())
+            } else {
+                // Without --all only volumes of caches that are not configured anymore are removed
+                all || !config.containers().cache_volumes().iter().any(|cv| cv.name() == cache)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if to_remove.is_empty() {
+        info!("No cache volumes to remove");
+        return Ok(())
+    }
+
+    let prompt = format!("Really remove {} cache volumes?", to_remove.len());
+    if !dialoguer::Confirm::new().with_prompt(prompt).interact()? {
+        return Ok(())
+    }
+
+    // Recorded before, so that volumes are only removed if the audit log has it
+    let volumes = to_remove.iter()
+        .map(|(ep, volume, _)| serde_json::json!({ "endpoint": ep.name().as_ref(), "volume": volume }))
+        .collect::<Vec<_>>();
+    let conn = db_connection_config.establish_connection()?;
+    dbmodels::AuditEntry::create(&conn, config, "volumes-prune", serde_json::json!({ "volumes": volumes }))?;
+
+    let out = std::io::stdout();
+    let mut lock = out.lock();
+    for (ep, volume, _) in to_remove {
+        ep.docker()?
+            .volumes()
+            .get(&volume)
+            .delete()
+            .await
+            .with_context(|| anyhow!("Removing volume {} on {}", volume, ep.name()))?;
+        writeln!(lock, "Removed {} on {}", volume, ep.name())?;
+    }
+    Ok(())
+}
+
 /// Helper function to connect to all endpoints from the configuration, that appear (by name) in
 /// the `endpoint_names` list
 pub(super) async fn connect_to_endpoints(config: &Configuration, endpoint_names: &[EndpointName]) -> Result<Vec<Arc<Endpoint>>> {
-    let endpoint_configurations = config
+    let endpoint_configurations = endpoint_configurations(config, endpoint_names);
+
+    info!("Endpoint config build");
+    info!("Connecting to {n} endpoints: {eps}",
+        n = endpoint_configurations.len(),
+        eps = endpoint_configurations.iter().map(|epc| epc.endpoint_name()).join(", "));
+
+    crate::endpoint::util::setup_endpoints(endpoint_configurations).await
+}
+
+/// Helper function to build the configurations of all endpoints from the configuration, that
+/// appear (by name) in the `endpoint_names` list
+pub(super) fn endpoint_configurations(config: &Configuration, endpoint_names: &[EndpointName]) -> Vec<crate::endpoint::EndpointConfiguration> {
+    config
         .docker()
         .endpoints()
         .iter()
@@ -520,14 +871,9 @@ pub(super) async fn connect_to_endpoints(config: &Configuration, endpoint_names:
                 .required_images(config.docker().images().clone())
                 .required_docker_versions(config.docker().docker_versions().clone())
                 .required_docker_api_versions(config.docker().docker_api_versions().clone())
+                .negotiate_docker_api_version(config.docker().negotiate_docker_api_version())
+                .min_docker_api_version(config.docker().min_docker_api_version().clone())
                 .build()
         })
-        .collect::<Vec<_>>();
-
-    info!("Endpoint config build");
-    info!("Connecting to {n} endpoints: {eps}",
-        n = endpoint_configurations.len(),
-        eps = endpoint_configurations.iter().map(|epc| epc.endpoint_name()).join(", "));
-
-    crate::endpoint::util::setup_endpoints(endpoint_configurations).await
+        .collect::<Vec<_>>()
 }
@@ -31,6 +31,7 @@ pub async fn env_of(matches: &ArgMatches, repo: Repository) -> Result<()> {
             .map(String::from)
             .map(PackageName::from)
             .unwrap();
+        let name = repo.resolve_name(&name)?;
         let constraint = matches
             .value_of("package_version_constraint")
             .map(PackageVersionConstraint::try_from)
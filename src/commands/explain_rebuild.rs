@@ -0,0 +1,453 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'explain-rebuild' subcommand
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use clap::ArgMatches;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use itertools::Itertools;
+use log::debug;
+use uuid::Uuid;
+
+use crate::config::Configuration;
+use crate::db::models as dbmodels;
+use crate::filestore::path::ArtifactPath;
+use crate::filestore::path::StoreRoot;
+use crate::filestore::ReleaseStore;
+use crate::filestore::StagingStore;
+use crate::job::Job;
+use crate::package::condition::ConditionData;
+use crate::package::Dag;
+use crate::package::PackageName;
+use crate::package::PackageOptions;
+use crate::package::PackageVersion;
+use crate::package::ParseDependency;
+use crate::package::ScriptBuilder;
+use crate::package::Shebang;
+use crate::repository::Repository;
+use crate::schema;
+use crate::util::docker::ImageName;
+use crate::util::progress::ProgressBars;
+use crate::util::EnvironmentVariableName;
+
+/// The number of earlier jobs of a package that are explained, unless all are requested
+const MAX_EXPLAINED_JOBS: usize = 3;
+
+/// The stores in which the artifacts of earlier jobs are searched
+struct Stores {
+    release_stores: Vec<Arc<ReleaseStore>>,
+    staging_stores: Vec<StagingStore>,
+}
+
+impl Stores {
+    fn contain(&self, path: &ArtifactPath) -> bool {
+        self.staging_stores.iter().any(|store| store.get(path).is_some())
+            || self.release_stores.iter().any(|store| store.get(path).is_some())
+    }
+}
+
+/// An earlier job of a package and the reasons why its artifacts cannot be reused
+struct Candidate {
+    job: dbmodels::Job,
+    submit_time: NaiveDateTime,
+    reasons: Vec<String>,
+    script_differs: bool,
+}
+
+/// Why the job of a package is built instead of reusing artifacts
+enum Explanation {
+    /// The artifacts of this earlier job are reused
+    Reused(Uuid),
+
+    /// These dependencies of the package are built, so the package is built as well
+    DependenciesBuilt(Vec<String>),
+
+    /// No earlier job of the package matches
+    NoMatch(Vec<Candidate>),
+}
+
+/// Implementation of the "explain-rebuild" subcommand
+pub async fn explain_rebuild(
+    repo_path: &Path,
+    matches: &ArgMatches,
+    progressbars: ProgressBars,
+    config: &Configuration,
+    repo: Repository,
+    conn: PgConnection,
+) -> Result<()> {
+    let name = matches
+        .value_of("package_name")
+        .map(String::from)
+        .map(PackageName::from)
+        .unwrap(); // safe by clap
+    let name = repo.resolve_name(&name)?;
+    let version = matches
+        .value_of("package_version")
+        .map(String::from)
+        .map(PackageVersion::from)
+        .unwrap(); // safe by clap
+    let package = match repo.find(&name, &version).as_slice() {
+        [package] => (*package).clone(),
+        [] => return Err(anyhow!("Package {} {} not found", name, version)),
+        _ => return Err(anyhow!("Package {} {} found multiple times", name, version)),
+    };
+
+    let image_name = matches
+        .value_of("image")
+        .map(String::from)
+        .map(ImageName::from)
+        .unwrap(); // safe by clap
+    let image_name = config.docker()
+        .images()
+        .iter()
+        .find(|img| image_name.matches(img))
+        .cloned()
+        .unwrap_or(image_name);
+
+    let additional_env = crate::commands::util::additional_env(matches, config)?;
+    let options = matches
+        .values_of("option")
+        .unwrap_or_default()
+        .map(crate::package::parse_option_setting)
+        .collect::<Result<PackageOptions>>()?;
+
+    let stores = load_stores(matches, config, &progressbars)?;
+
+    let dag = {
+        let condition_data = ConditionData {
+            image_name: Some(&image_name),
+            env: &additional_env,
+            options: &options,
+        };
+        Dag::for_root_package(package, &repo, None, &condition_data, config.resolver())?
+    };
+    let resources = additional_env.into_iter().map(crate::job::JobResource::from).collect();
+    let shebang = Shebang::from(config.shebang().clone());
+    let jobdag = crate::job::Dag::from_package_dag(dag, shebang.clone(), image_name.clone(), config.phases_for_image(Some(&image_name)).clone(), resources, config.build().env());
+
+    let git_env = git_env(repo_path, config)?;
+    let jobs = jobdag.iter().map(|jobdef| (*jobdef.job.uuid(), (jobdef.job, jobdef.dependencies))).collect::<HashMap<_, _>>();
+    let root = jobdag.iter()
+        .find(|jobdef| !jobs.values().any(|(_, dependencies)| dependencies.contains(jobdef.job.uuid())))
+        .map(|jobdef| *jobdef.job.uuid())
+        .ok_or_else(|| anyhow!("Dependency tree has no root"))?;
+
+    let mut explanations = HashMap::new();
+    explain_tree(&root, &jobs, &mut explanations, &mut |job| {
+        explain_job(&conn, config, &shebang, job, &git_env, &stores)
+    })?;
+
+    let all = matches.is_present("all");
+    let colored = !matches.is_present("no_color") && atty::is(atty::Stream::Stdout);
+    let mut stdout = std::io::stdout();
+
+    if config.docker().require_image_digest_match() {
+        writeln!(stdout, "Note: Artifacts are only reused if they were built from the same image ID, which is not checked here")?;
+    }
+
+    // Explain the dependencies first, so the explanation of the package is at the end
+    let ordered = order_dependencies_first(&root, &jobs);
+    for uuid in ordered.iter() {
+        let (job, _) = jobs.get(uuid).unwrap(); // the order only contains the jobs of the tree
+        let package = job.package();
+        match explanations.get(uuid).unwrap() { // every job of the tree was explained
+            Explanation::Reused(earlier) => if *uuid == root {
+                writeln!(stdout, "{} {} is not built, the artifacts of job {} are reused", package.name(), package.version(), earlier)?;
+            },
+
+            Explanation::DependenciesBuilt(dependencies) => {
+                writeln!(stdout, "{} {} is built because its dependencies are built: {}", package.name(), package.version(), dependencies.join(", "))?;
+            },
+
+            Explanation::NoMatch(candidates) if candidates.is_empty() => {
+                writeln!(stdout, "{} {} is built because it was never built before", package.name(), package.version())?;
+            },
+
+            Explanation::NoMatch(candidates) => {
+                writeln!(stdout, "{} {} is built because no earlier job matches:", package.name(), package.version())?;
+                let shown = if all { candidates.len() } else { MAX_EXPLAINED_JOBS };
+                for candidate in candidates.iter().take(shown) {
+                    writeln!(stdout, "  Job {} ({}):", candidate.job.uuid, candidate.submit_time)?;
+                    for reason in candidate.reasons.iter() {
+                        writeln!(stdout, "    - {}", reason)?;
+                    }
+                }
+                if candidates.len() > shown {
+                    writeln!(stdout, "  ... and {} older jobs (see --all)", candidates.len() - shown)?;
+                }
+
+                // Show what changed in the script since the latest job with a different script
+                if let Some(latest) = candidates.iter().find(|c| c.script_differs) {
+                    let script = render_script(config, &shebang, job)?;
+                    let diff = crate::ui::diff_to_printable(
+                        &latest.job.script_text,
+                        script.as_ref(),
+                        &format!("job {}", latest.job.uuid),
+                        &format!("{} {} now", package.name(), package.version()),
+                        colored,
+                    );
+                    write!(stdout, "{}", diff)?;
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Explain the job `uuid` and its dependencies, if they were not explained yet
+///
+/// A job is only reused if none of its dependencies is built, like in the orchestrator.
+/// Returns whether the job is built.
+fn explain_tree<F>(
+    uuid: &Uuid,
+    jobs: &HashMap<Uuid, (&Job, Vec<Uuid>)>,
+    explanations: &mut HashMap<Uuid, Explanation>,
+    explain_job: &mut F,
+) -> Result<bool>
+where
+    F: FnMut(&Job) -> Result<Explanation>,
+{
+    if let Some(explanation) = explanations.get(uuid) {
+        return Ok(!matches!(explanation, Explanation::Reused(_)))
+    }
+
+    let (job, dependencies) = jobs.get(uuid).ok_or_else(|| anyhow!("Job {} is not in the tree", uuid))?;
+    let mut built = vec![];
+    for dependency in dependencies.iter() {
+        if explain_tree(dependency, jobs, explanations, explain_job)? {
+            let (dependency, _) = jobs.get(dependency).unwrap(); // checked by the recursion
+            built.push(format!("{} {}", dependency.package().name(), dependency.package().version()));
+        }
+    }
+
+    let explanation = if built.is_empty() {
+        explain_job(job)?
+    } else {
+        built.sort();
+        Explanation::DependenciesBuilt(built)
+    };
+    let is_built = !matches!(explanation, Explanation::Reused(_));
+    explanations.insert(*uuid, explanation);
+    Ok(is_built)
+}
+
+/// The jobs of the tree with `root`, each after all of its dependencies
+fn order_dependencies_first(root: &Uuid, jobs: &HashMap<Uuid, (&Job, Vec<Uuid>)>) -> Vec<Uuid> {
+    fn visit(uuid: &Uuid, jobs: &HashMap<Uuid, (&Job, Vec<Uuid>)>, ordered: &mut Vec<Uuid>) {
+        if ordered.contains(uuid) {
+            return
+        }
+        if let Some((_, dependencies)) = jobs.get(uuid) {
+            for dependency in dependencies.iter().sorted() {
+                visit(dependency, jobs, ordered);
+            }
+        }
+        ordered.push(*uuid);
+    }
+
+    let mut ordered = vec![];
+    visit(root, jobs, &mut ordered);
+    ordered
+}
+
+fn render_script(config: &Configuration, shebang: &Shebang, job: &Job) -> Result<crate::package::Script> {
+    ScriptBuilder::new(shebang)
+        .build(job.package(), job.script_phases(), *config.strict_script_interpolation())
+        .with_context(|| anyhow!("Rendering script of {} {}", job.package().name(), job.package().version()))
+}
+
+/// Compare `job` with the earlier jobs of its package, like `FindArtifacts` does
+fn explain_job(
+    conn: &PgConnection,
+    config: &Configuration,
+    shebang: &Shebang,
+    job: &Job,
+    git_env: &[(EnvironmentVariableName, String)],
+    stores: &Stores,
+) -> Result<Explanation> {
+    let package = job.package();
+    let script = render_script(config, shebang, job)?;
+    let patches_hash = package.patches_hash()?;
+    let options = package.options_string();
+    let dependency_constraints = package
+        .dependencies()
+        .build()
+        .iter()
+        .map(ParseDependency::parse_as_name_and_version)
+        .chain(package.dependencies().runtime().iter().map(ParseDependency::parse_as_name_and_version))
+        .collect::<Result<Vec<_>>>()?;
+    let env = job.resources()
+        .iter()
+        .filter_map(crate::job::JobResource::env)
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .chain(git_env.iter().cloned())
+        .collect::<Vec<_>>();
+
+    let earlier_jobs = schema::packages::table
+        .filter(schema::packages::name.eq(package.name().as_ref() as &str))
+        .filter(schema::packages::version.eq(package.version().as_ref() as &str))
+        .inner_join(schema::jobs::table.inner_join(schema::submits::table))
+        .inner_join(schema::images::table.on(schema::submits::requested_image_id.eq(schema::images::id)))
+        .order_by(schema::submits::submit_time.desc())
+        .select((schema::jobs::all_columns, schema::submits::submit_time, schema::images::name))
+        .load::<(dbmodels::Job, NaiveDateTime, String)>(conn)?;
+    debug!("Found {} earlier jobs of {} {}", earlier_jobs.len(), package.name(), package.version());
+
+    let mut artifacts = schema::artifacts::table
+        .filter(schema::artifacts::job_id.eq_any(earlier_jobs.iter().map(|(job, _, _)| job.id).collect::<Vec<_>>()))
+        .load::<dbmodels::Artifact>(conn)?
+        .into_iter()
+        .into_group_map_by(|artifact| artifact.job_id);
+
+    let mut candidates = vec![];
+    for (earlier, submit_time, image) in earlier_jobs {
+        // Jobs without artifacts (e.g. failed ones) are never reused
+        let artifacts = match artifacts.remove(&earlier.id) {
+            Some(artifacts) => artifacts,
+            None => continue,
+        };
+
+        let mut reasons = vec![];
+        if let Some(allowed) = package.allowed_images() {
+            if !allowed.iter().any(|img| img.as_ref() == image) {
+                reasons.push(format!("It was built in image {}, which the package does not allow", image));
+            }
+        }
+        if let Some(denied) = package.denied_images() {
+            if denied.iter().any(|img| img.as_ref() == image) {
+                reasons.push(format!("It was built in image {}, which the package denies", image));
+            }
+        }
+        if image != job.image().as_ref() {
+            reasons.push(format!("It was built in image {} instead of {}", image, job.image()));
+        }
+
+        let script_differs = earlier.script_text != script.as_ref();
+        if script_differs {
+            reasons.push(String::from("The script differs"));
+        }
+
+        if earlier.patches_hash != patches_hash {
+            reasons.push(String::from("The patches differ"));
+        }
+        if earlier.options != options {
+            reasons.push(format!(
+                "The options were {} instead of {}",
+                earlier.options.as_deref().unwrap_or("the defaults"),
+                options.as_deref().unwrap_or("the defaults")
+            ));
+        }
+
+        if config.docker().require_image_digest_match() && earlier.image_digest.is_none() {
+            reasons.push(String::from("The ID of its image was not recorded"));
+        }
+
+        let earlier_env = earlier.env(conn)?
+            .into_iter()
+            .map(|var| (var.name, var.value))
+            .collect::<Vec<_>>();
+        reasons.extend({
+            crate::db::environment_mismatches(&earlier_env, package.environment().as_ref(), &env)
+                .into_iter()
+                .map(|mismatch| format!("The environment differs: {}", mismatch))
+        });
+
+        reasons.extend({
+            crate::db::dependency_mismatches(&crate::db::job_dependencies(conn, &earlier)?, &dependency_constraints)
+                .into_iter()
+                .map(|mismatch| format!("It was built with another version of a dependency: {}", mismatch))
+        });
+
+        let available = artifacts.iter()
+            .map(|artifact| ArtifactPath::new(artifact.path_buf()))
+            .collect::<Result<Vec<_>>>()?
+            .iter()
+            .any(|path| stores.contain(path));
+        if !available {
+            reasons.push(String::from("Its artifacts are not in any store anymore"));
+        }
+
+        if reasons.is_empty() {
+            return Ok(Explanation::Reused(earlier.uuid))
+        }
+        candidates.push(Candidate { job: earlier, submit_time, reasons, script_differs });
+    }
+
+    Ok(Explanation::NoMatch(candidates))
+}
+
+/// The variables with the git author and commit that the jobs get, see `git_author` and
+/// `git_commit_hash` in the configuration
+fn git_env(repo_path: &Path, config: &Configuration) -> Result<Vec<(EnvironmentVariableName, String)>> {
+    let git_repo = git2::Repository::open(repo_path)
+        .with_context(|| anyhow!("Opening repository at {}", repo_path.display()))?;
+
+    let author = config.containers()
+        .git_author()
+        .as_ref()
+        .map(|varname| -> Result<_> {
+            let username = git_repo.config()?.get_string("user.name")?;
+            Ok((varname.clone(), username))
+        })
+        .transpose()?;
+    let commit = config.containers()
+        .git_commit_hash()
+        .as_ref()
+        .map(|varname| -> Result<_> {
+            let hash = crate::util::git::get_repo_head_commit_hash(&git_repo)?;
+            Ok((varname.clone(), hash))
+        })
+        .transpose()?;
+
+    Ok(author.into_iter().chain(commit).collect())
+}
+
+fn load_stores(matches: &ArgMatches, config: &Configuration, progressbars: &ProgressBars) -> Result<Stores> {
+    let release_stores = config
+        .release_stores()
+        .iter()
+        .map(|storename| {
+            let bar = progressbars.bar()?;
+            let p = config.releases_directory().join(storename);
+            debug!("Loading release directory: {}", p.display());
+            let r = ReleaseStore::load(StoreRoot::new(p)?, &bar);
+            bar.finish_with_message("Loaded releases");
+            r.map(Arc::new)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let staging_stores = matches
+        .value_of("staging_dir")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(config.additional_staging_directories().iter().cloned())
+        .map(|p| {
+            let bar = progressbars.bar()?;
+            debug!("Loading staging directory: {}", p.display());
+            let r = StagingStore::load(StoreRoot::new(p)?, &bar);
+            bar.finish_with_message("Loaded staging");
+            r
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Stores { release_stores, staging_stores })
+}
@@ -0,0 +1,160 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'export' subcommand
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use colored::Colorize;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use itertools::Itertools;
+use log::trace;
+
+use crate::config::Configuration;
+use crate::db::models as dbmodels;
+use crate::filestore::sidecar_paths_for;
+use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::repository::Repository;
+use crate::schema;
+use crate::util::oci::OciImage;
+
+/// The directory in the image that contains the artifacts, with the same layout as the release
+/// store
+const OCI_PACKAGES_DIR: &str = "/packages";
+
+/// Implementation of the "export" subcommand
+pub async fn export(matches: &ArgMatches, config: &Configuration, repo: Repository, conn: PgConnection) -> Result<()> {
+    match matches.subcommand() {
+        Some(("oci", matches)) => oci(matches, config, &repo, &conn).await,
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("Missing subcommand")),
+    }
+}
+
+/// Implementation of the "export oci" subcommand
+async fn oci(matches: &ArgMatches, config: &Configuration, repo: &Repository, conn: &PgConnection) -> Result<()> {
+    let name = matches.value_of("package_name").map(String::from).map(PackageName::from).unwrap(); // safe by clap
+    let name = repo.resolve_name(&name)?;
+    let version = matches.value_of("package_version").map(String::from).map(PackageVersion::from).unwrap(); // safe by clap
+    let store = matches.value_of("release_store_name");
+    let output = matches.value_of("output").map(PathBuf::from).unwrap(); // safe by clap
+    let tag = matches.value_of("tag").map(String::from).unwrap_or_else(|| version.to_string());
+
+    let package = repo.find(&name, &version)
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Package {} {} not found in the repository", name, version))?;
+
+    let packages = if matches.is_present("with_runtime_dependencies") {
+        crate::commands::util::runtime_closure(package, repo, config)?
+    } else {
+        vec![package]
+    };
+
+    let mut files = vec![];
+    for package in packages.iter() {
+        let artifacts = released_artifacts(package, store, config, conn)?;
+        if artifacts.is_empty() {
+            return Err(anyhow!("No released artifacts found for {} {}", package.name(), package.version()))
+        }
+
+        for (store_name, artifact) in artifacts {
+            let store_root = config.releases_directory().join(store_name);
+            let in_image = PathBuf::from(OCI_PACKAGES_DIR).join(&artifact);
+
+            // The metadata files next to the artifacts are exported as well
+            files.extend({
+                std::iter::once((artifact.clone(), in_image.clone()))
+                    .chain(sidecar_paths_for(&artifact).into_iter().zip(sidecar_paths_for(&in_image)))
+                    .map(|(artifact, in_image)| (store_root.join(artifact), in_image))
+                    .filter(|(path, _)| path.is_file())
+            });
+        }
+    }
+    trace!("Files of the image: {:?}", files);
+
+    let mut labels = BTreeMap::new();
+    labels.insert(String::from("org.opencontainers.image.title"), package.name().to_string());
+    labels.insert(String::from("org.opencontainers.image.version"), package.version().to_string());
+    labels.insert(String::from("butido.packages"), packages.iter().map(|p| format!("{} {}", p.name(), p.version())).join(", "));
+
+    let image = OciImage {
+        files,
+        architecture: matches.value_of("architecture").map(String::from).unwrap(), // has a default
+        labels,
+        tag: tag.clone(),
+    };
+    let digest = tokio::task::block_in_place(|| image.write_layout(&output))
+        .with_context(|| anyhow!("Writing OCI image layout to {}", output.display()))?;
+
+    let mut out = std::io::stdout();
+    writeln!(out, "Exported {} files of {} packages to {}:{} ({})",
+        image.files.len(), packages.len(), output.display(), tag, digest.green())?;
+
+    if let Some(reference) = matches.value_of("push") {
+        push(&output, &tag, reference).await?;
+        writeln!(out, "Pushed to {}", reference.green())?;
+    }
+    Ok(())
+}
+
+/// The released artifacts of `package` as (release store, path in the store), only from `store`
+/// if given
+///
+/// If an artifact was released several times, the latest release is used.
+fn released_artifacts(package: &Package, store: Option<&str>, config: &Configuration, conn: &PgConnection) -> Result<Vec<(String, PathBuf)>> {
+    let released = schema::releases::table
+        .inner_join(schema::artifacts::table.inner_join(schema::jobs::table.inner_join(schema::packages::table)))
+        .inner_join(schema::release_stores::table)
+        .filter(schema::packages::name.eq(package.name().as_ref() as &str))
+        .filter(schema::packages::version.eq(package.version().as_ref() as &str))
+        .order(schema::releases::release_date.desc())
+        .select((schema::release_stores::store_name, schema::artifacts::all_columns))
+        .load::<(String, dbmodels::Artifact)>(conn)?;
+
+    Ok(released.into_iter()
+        .filter(|(store_name, _)| store.map(|s| s == store_name).unwrap_or(true))
+        .unique_by(|(_, artifact)| artifact.path.clone())
+        .map(|(store_name, artifact)| (store_name, artifact.path_buf()))
+        .filter(|(store_name, path)| {
+            let exists = config.releases_directory().join(store_name).join(path).is_file();
+            if !exists {
+                log::warn!("Released artifact is missing from release store {}: {}", store_name, path.display());
+            }
+            exists
+        })
+        .collect())
+}
+
+/// Push the image `tag` of the layout in `layout` to `reference` in a registry, with skopeo
+async fn push(layout: &std::path::Path, tag: &str, reference: &str) -> Result<()> {
+    let output = tokio::process::Command::new("skopeo")
+        .arg("copy")
+        .arg(format!("oci:{}:{}", layout.display(), tag))
+        .arg(format!("docker://{}", reference))
+        .output()
+        .await
+        .context("Starting skopeo")?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("Pushing to {} failed: {}", reference, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
@@ -95,7 +95,22 @@ pub async fn find_artifact(matches: &ArgMatches, config: &Configuration, progres
         None
     };
 
-    let database = Arc::new(database_connection);
+    let additional_staging_stores = config
+        .additional_staging_directories()
+        .iter()
+        .map(|p| {
+            let bar_staging_loading = progressbars.bar()?;
+            debug!("Loading additional staging directory: {}", p.display());
+            let r = StagingStore::load(StoreRoot::new(p.clone())?, &bar_staging_loading);
+            if r.is_ok() {
+                bar_staging_loading.finish_with_message("Loaded additional staging successfully");
+            } else {
+                bar_staging_loading.finish_with_message("Failed to load additional staging");
+            }
+            r.map(Arc::new)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
     repo.packages()
         .filter(|p| package_name_regex.captures(p.name()).is_some())
         .filter(|p| {
@@ -111,7 +126,8 @@ pub async fn find_artifact(matches: &ArgMatches, config: &Configuration, progres
                 .config(config)
                 .release_stores(&release_stores)
                 .staging_store(staging_store.as_ref())
-                .database_connection(database.clone())
+                .additional_staging_stores(&additional_staging_stores)
+                .database_connection(&database_connection)
                 .env_filter(&env_filter)
                 .script_filter(script_filter)
                 .image_name(image_name.as_ref())
@@ -52,6 +52,11 @@ pub async fn find_pkg(
                 .map(|v| v.matches(p.version()))
                 .unwrap_or(true)
         })
+        .filter(|p| {
+            matches.value_of("maintainer")
+                .map(|pattern| crate::commands::util::is_maintained_by(p, pattern))
+                .unwrap_or(true)
+        })
         .inspect(|pkg| trace!("Found package: {:?}", pkg));
 
     let out = std::io::stdout();
@@ -77,6 +82,7 @@ pub async fn find_pkg(
             print_sources: matches.is_present("show_sources"),
             print_dependencies: matches.is_present("show_dependencies"),
             print_patches: matches.is_present("show_patches"),
+            print_maintainers: matches.is_present("show_maintainers"),
             print_env: matches.is_present("show_env"),
             print_flags: matches.is_present("show_flags"),
             print_allowed_images: matches.is_present("show_allowed_images"),
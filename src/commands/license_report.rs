@@ -0,0 +1,158 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'license-report' subcommand
+
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use clap::ArgMatches;
+use colored::Colorize;
+use diesel::prelude::*;
+
+use crate::config::Configuration;
+use crate::config::LicensePolicy;
+use crate::db::DbConnectionConfig;
+use crate::package::LicenseExpression;
+use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::repository::Repository;
+use crate::schema;
+
+/// Implementation of the "license-report" subcommand
+///
+/// Lists the licenses of the package (or the package of the submit) and of all packages it
+/// depends on at runtime, and fails if a license is missing or not accepted by the
+/// `license_policy`.
+pub async fn license_report(
+    matches: &ArgMatches,
+    config: &Configuration,
+    repo: Repository,
+    conn_cfg: DbConnectionConfig<'_>,
+) -> Result<()> {
+    let target = matches.value_of("target").unwrap(); // safe by clap
+    let (name, version) = match uuid::Uuid::parse_str(target) {
+        Ok(submit_uuid) => {
+            let conn = conn_cfg.establish_connection()?;
+            let (name, version) = schema::submits::table
+                .inner_join(schema::packages::table)
+                .filter(schema::submits::uuid.eq(submit_uuid))
+                .select((schema::packages::name, schema::packages::version))
+                .first::<(String, String)>(&conn)
+                .optional()?
+                .ok_or_else(|| anyhow!("Submit {} not found", submit_uuid))?;
+            (PackageName::from(name), Some(PackageVersion::from(version)))
+        },
+        Err(_) => {
+            let name = repo.resolve_name(&PackageName::from(target.to_string()))?;
+            (name, matches.value_of("package_version").map(String::from).map(PackageVersion::from))
+        },
+    };
+
+    let packages = match version.as_ref() {
+        Some(version) => repo.find(&name, version),
+        None => repo.find_by_name(&name),
+    };
+    if packages.is_empty() {
+        return Err(anyhow!("Package {} {} not found in the repository", name, version.map(|v| v.to_string()).unwrap_or_default()))
+    }
+
+    let mut closure: Vec<&Package> = vec![];
+    for package in packages {
+        for dependency in crate::commands::util::runtime_closure(package, &repo, config)? {
+            if !closure.contains(&dependency) {
+                closure.push(dependency);
+            }
+        }
+    }
+    closure.sort_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+
+    let policy = config.license_policy();
+    let statuses = closure.iter().map(|p| LicenseStatus::of(p, policy)).collect::<Vec<_>>();
+    let data = closure.iter()
+        .zip(statuses.iter())
+        .map(|(package, status)| {
+            vec![
+                package.name().to_string(),
+                package.version().to_string(),
+                package.license().clone().unwrap_or_default(),
+                status.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let csv = matches.is_present("csv");
+    crate::commands::util::display_data(crate::commands::util::mk_header(vec!["Package", "Version", "License", "Status"]), data, csv)?;
+
+    let problems = statuses.iter().filter(|s| s.is_problem()).count();
+    if !csv {
+        let summary = format!("{} packages, {} with license problems", closure.len(), problems);
+        let mut out = std::io::stdout();
+        if problems == 0 {
+            writeln!(out, "{}", summary.green())?;
+        } else {
+            writeln!(out, "{}", summary.red())?;
+        }
+    }
+
+    if problems > 0 {
+        Err(anyhow!("License check failed"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether the license of a package is accepted by the policy
+#[derive(parse_display::Display, Clone, Debug, Eq, PartialEq)]
+enum LicenseStatus {
+    #[display("ok")]
+    Ok,
+
+    #[display("missing (allowed)")]
+    MissingAllowed,
+
+    #[display("missing")]
+    Missing,
+
+    /// The licenses of the expression that are not accepted
+    #[display("denied: {0}")]
+    Denied(String),
+
+    #[display("invalid: {0}")]
+    Invalid(String),
+}
+
+impl LicenseStatus {
+    fn of(package: &Package, policy: &LicensePolicy) -> Self {
+        let license = match package.license().as_ref() {
+            Some(license) => license,
+            None if policy.allow_missing() => return LicenseStatus::MissingAllowed,
+            None => return LicenseStatus::Missing,
+        };
+
+        match LicenseExpression::parse(license) {
+            Ok(expression) if expression.is_acceptable(&|l| policy.is_allowed(l)) => LicenseStatus::Ok,
+            Ok(expression) => {
+                let denied = expression.licenses()
+                    .into_iter()
+                    .filter(|l| !policy.is_allowed(l))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                LicenseStatus::Denied(denied)
+            },
+            Err(e) => LicenseStatus::Invalid(e.to_string()),
+        }
+    }
+
+    fn is_problem(&self) -> bool {
+        !matches!(self, LicenseStatus::Ok | LicenseStatus::MissingAllowed)
+    }
+}
@@ -36,7 +36,9 @@ pub async fn lint(
     let pname = matches
         .value_of("package_name")
         .map(String::from)
-        .map(PackageName::from);
+        .map(PackageName::from)
+        .map(|name| repo.resolve_name(&name))
+        .transpose()?;
     let pvers = matches
         .value_of("package_version")
         .map(PackageVersionConstraint::try_from)
@@ -47,7 +49,7 @@ pub async fn lint(
 
     let iter = repo
         .packages()
-        .filter(|p| pname.as_ref().map(|n| p.name() == n).unwrap_or(true))
+        .filter(|p| pname.as_ref().map(|n| p.has_name(n)).unwrap_or(true))
         .filter(|p| {
             pvers
                 .as_ref()
@@ -55,5 +57,6 @@ pub async fn lint(
                 .unwrap_or(true)
         });
 
-    crate::commands::util::lint_packages(iter, &linter, config, bar).await
+    let deny_warnings = matches.is_present("deny_lint_warnings");
+    crate::commands::util::lint_packages(iter, &linter, repo_path, config, deny_warnings, bar).await
 }
@@ -10,14 +10,15 @@
 
 //! Implementation of the 'metrics' subcommand
 
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::io::Write;
 
-use anyhow::Error;
 use anyhow::Result;
 use diesel::PgConnection;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
+use itertools::Itertools;
 use walkdir::WalkDir;
 
 use crate::config::Configuration;
@@ -107,6 +108,52 @@ pub async fn metrics(
         n_releasestores = n_releasestores,
         n_releases = n_releases,
         n_submits = n_submits,
-    )).map_err(Error::from)
+    ))?;
+
+    average_phase_durations(&conn)
+}
+
+/// Print the average duration of each phase per package, over all jobs recorded in the database
+fn average_phase_durations(conn: &PgConnection) -> Result<()> {
+    let phases = crate::schema::job_phases::table
+        .inner_join(crate::schema::jobs::table.inner_join(crate::schema::packages::table))
+        .select((
+            crate::schema::packages::name,
+            crate::schema::job_phases::name,
+            crate::schema::job_phases::position,
+            crate::schema::job_phases::duration_secs,
+        ))
+        .load::<(String, String, i32, f64)>(conn)?;
+
+    if phases.is_empty() {
+        return Ok(())
+    }
+
+    // Per package and phase: the sum of the durations, the number of jobs and the earliest
+    // position of the phase, so that the phases are listed in the order they usually run in
+    let mut averages = BTreeMap::<(String, String), (f64, usize, i32)>::new();
+    for (package, phase, position, duration) in phases {
+        let entry = averages.entry((package, phase)).or_insert((0.0, 0, position));
+        entry.0 += duration;
+        entry.1 += 1;
+        entry.2 = entry.2.min(position);
+    }
+
+    let data = averages
+        .into_iter()
+        .sorted_by(|((pkg_a, _), (_, _, pos_a)), ((pkg_b, _), (_, _, pos_b))| (pkg_a, pos_a).cmp(&(pkg_b, pos_b)))
+        .map(|((package, phase), (sum, count, _))| {
+            vec![
+                package,
+                phase,
+                count.to_string(),
+                crate::commands::util::format_duration_secs(sum / count as f64),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    writeln!(std::io::stdout(), "\nAverage phase durations:")?;
+    let header = crate::commands::util::mk_header(["Package", "Phase", "Jobs", "Average"].to_vec());
+    crate::commands::util::display_data(header, data, false)
 }
 
@@ -11,6 +11,16 @@
 mod build;
 pub use build::build;
 
+mod cancel;
+pub use cancel::cancel;
+
+mod completions;
+pub use completions::complete;
+pub use completions::completions;
+
+mod config;
+pub use self::config::config;
+
 mod db;
 pub use db::db;
 
@@ -21,6 +31,12 @@ pub(super) mod endpoint_container;
 mod env_of;
 pub use env_of::env_of;
 
+mod explain_rebuild;
+pub use explain_rebuild::explain_rebuild;
+
+mod export;
+pub use export::export;
+
 mod find_artifact;
 pub use find_artifact::find_artifact;
 
@@ -30,24 +46,56 @@ pub use find_pkg::find_pkg;
 mod dependencies_of;
 pub use dependencies_of::dependencies_of;
 
+mod license_report;
+pub use license_report::license_report;
+
 mod lint;
 pub use lint::lint;
 
+mod new_version;
+pub use new_version::new_version;
+
+mod repo;
+pub use repo::repo;
+pub use repo::repo_migrate;
+
 mod what_depends;
 pub use what_depends::what_depends;
 
+mod rerun_job;
+
+mod download_artifact;
+
 mod release;
 pub use release::release;
 
+mod schedule;
+pub use schedule::schedule;
+
+mod script_diff;
+pub use script_diff::script_diff;
+
 mod source;
 pub use source::source;
 
+mod verify_reproducibility;
+pub use verify_reproducibility::verify_reproducibility;
+
+mod verify_artifacts;
+pub use verify_artifacts::verify_artifacts;
+
+mod verify_stores;
+pub use verify_stores::verify_stores;
+
 mod versions_of;
 pub use versions_of::versions_of;
 
 mod tree_of;
 pub use tree_of::tree_of;
 
+mod tui;
+pub use tui::tui;
+
 mod metrics;
 pub use metrics::metrics;
 
@@ -0,0 +1,176 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'new-version' subcommand
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use log::debug;
+use log::info;
+use log::warn;
+
+use crate::config::Configuration;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::repository::Repository;
+use crate::source::SourceCache;
+use crate::util::progress::ProgressBars;
+
+/// Implementation of the "new-version" subcommand
+pub async fn new_version(
+    repo_path: &Path,
+    matches: &ArgMatches,
+    config: &Configuration,
+    progressbars: ProgressBars,
+    repo: Repository,
+) -> Result<()> {
+    let pname = matches.value_of("package_name").map(String::from).map(PackageName::from).unwrap(); // safe by clap
+    let new_version = matches.value_of("package_version").map(String::from).map(PackageVersion::from).unwrap(); // safe by clap
+    let pname = repo.resolve_name(&pname)?;
+
+    if repo.packages().any(|p| p.has_name(&pname) && *p.version() == new_version) {
+        return Err(anyhow!("Package {} {} exists already", pname, new_version))
+    }
+
+    let latest = repo.packages()
+        .filter(|p| p.has_name(&pname))
+        .max_by(|a, b| a.version().compare(b.version()))
+        .ok_or_else(|| anyhow!("Package {} not found", pname))?;
+    let definition_file = latest.definition_file()
+        .as_ref()
+        .ok_or_else(|| anyhow!("Definition file of {} {} is not known", pname, latest.version()))?;
+    info!("Creating {} {} from {}", pname, new_version, latest.display_with_location());
+
+    // The version of a package is usually set in a directory that is named after the version,
+    // next to the directories of the other versions
+    let old_dir = definition_file.parent().unwrap_or_else(|| Path::new(""));
+    if old_dir.file_name().and_then(|name| name.to_str()) != Some(latest.version().as_ref() as &str) {
+        return Err(anyhow!("The directory of {} is not named after the version {}, cannot derive the directory for {}",
+            definition_file.display(), latest.version(), new_version))
+    }
+    let new_dir = old_dir.with_file_name(new_version.as_ref() as &str);
+    let new_file = new_dir.join(definition_file.file_name().unwrap()); // file name checked when loading
+    if repo_path.join(&new_dir).exists() {
+        return Err(anyhow!("{} exists already", new_dir.display()))
+    }
+
+    let content = tokio::fs::read_to_string(repo_path.join(definition_file))
+        .await
+        .with_context(|| anyhow!("Reading {}", definition_file.display()))?;
+    let mut content = update_definition(&content, &pname, latest.version(), &new_version)
+        .with_context(|| anyhow!("Updating {}", definition_file.display()))?;
+
+    tokio::fs::create_dir_all(repo_path.join(&new_dir))
+        .await
+        .with_context(|| anyhow!("Creating directory {}", new_dir.display()))?;
+    tokio::fs::write(repo_path.join(&new_file), &content)
+        .await
+        .with_context(|| anyhow!("Writing {}", new_file.display()))?;
+
+    // The patches next to the old definition file are needed for the new one to load, they might
+    // not apply to the new version though
+    for patch in latest.patches().iter().filter(|patch| patch.parent() == Some(old_dir)) {
+        let target = new_dir.join(patch.file_name().unwrap()); // a file, has a name
+        debug!("Copying patch {} to {}", patch.display(), target.display());
+        tokio::fs::copy(repo_path.join(patch), repo_path.join(&target))
+            .await
+            .with_context(|| anyhow!("Copying patch {} to {}", patch.display(), target.display()))?;
+    }
+
+    let mut outdated_hashes = latest.sources().keys().cloned().collect::<Vec<_>>();
+    if matches.is_present("download") {
+        // Loaded without the cache, which does not know the new file
+        let reloaded = {
+            let bar = progressbars.bar()?;
+            let r = Repository::load(repo_path, &bar);
+            bar.finish_with_message("Repository loading finished");
+            r.with_context(|| anyhow!("Loading the repository with {}", new_file.display()))?
+        };
+        let package = reloaded.packages()
+            .find(|p| p.has_name(&pname) && *p.version() == new_version)
+            .ok_or_else(|| anyhow!("Package {} {} not found in {}", pname, new_version, new_file.display()))?;
+
+        outdated_hashes.clear();
+        let source_cache = SourceCache::new(config.source_cache_root().clone());
+        for source in source_cache.sources_for(package) {
+            super::source::download_source(&source, config, &progressbars)
+                .await
+                .with_context(|| anyhow!("Downloading source {} of {} {}", source.source_name(), pname, new_version))?;
+
+            let hash = super::source::hash_of(package, source.source_name(), &source.path()).await?;
+            let old_hash = latest.sources()
+                .get(source.source_name())
+                .map(|s| s.hash().value().to_string());
+            match old_hash {
+                Some(old_hash) if content.contains(&old_hash) => {
+                    content = content.replace(&old_hash, &hash);
+                },
+                _ => {
+                    warn!("The hash of source {} is not set in {}, it is {}", source.source_name(), new_file.display(), hash);
+                    outdated_hashes.push(source.source_name().to_string());
+                },
+            }
+        }
+
+        tokio::fs::write(repo_path.join(&new_file), &content)
+            .await
+            .with_context(|| anyhow!("Writing {}", new_file.display()))?;
+    }
+
+    let mut out = std::io::stdout();
+    writeln!(out, "Created {}", new_file.display())?;
+    for source in outdated_hashes {
+        writeln!(out, "The hash of source '{}' has to be updated", source)?;
+    }
+    writeln!(out, "Review the new version and commit it")?;
+    Ok(())
+}
+
+/// The contents of the definition file `content` of `old` for the version `new`
+///
+/// The version is replaced and the literal URLs of the sources are turned into templates with
+/// `url_template_for_version()` that are rendered for the new version. URLs that are templates
+/// already are kept, they are rendered when the package is loaded.
+fn update_definition(content: &str, name: &PackageName, old: &PackageVersion, new: &PackageVersion) -> Result<String> {
+    let version_re = regex::Regex::new(&format!(r#"(?m)^(\s*"?version"?\s*[=:]\s*["']?){}(["']?\s*,?\s*)$"#, regex::escape(old.as_ref())))?;
+    if !version_re.is_match(content) {
+        return Err(anyhow!("The version {} is not set in the file", old))
+    }
+    let content = version_re.replace(content, format!("${{1}}{}${{2}}", new.as_ref() as &str).as_str());
+
+    let hb = crate::package::source_url_registry();
+    let data = serde_json::json!({
+        "name": name.as_ref() as &str,
+        "version": new.as_ref() as &str,
+    });
+    let url_re = regex::Regex::new(r#"("?url"?\s*[=:]\s*)(["'])([^"']*)(["'])"#)?;
+
+    let mut updated = String::with_capacity(content.len());
+    let mut last = 0;
+    for captures in url_re.captures_iter(&content) {
+        let url = captures.get(3).unwrap(); // always matches
+        let template = crate::package::url_template_for_version(url.as_str(), old.as_ref());
+        if template.contains("{{") && !url.as_str().contains("{{") {
+            let rendered = hb.render_template(&template, &data)
+                .with_context(|| anyhow!("Rendering source URL template: {}", template))?;
+            debug!("Source URL {} is {} for {}", url.as_str(), rendered, new);
+            updated.push_str(&content[last..url.start()]);
+            updated.push_str(&rendered);
+            last = url.end();
+        }
+    }
+    updated.push_str(&content[last..]);
+    Ok(updated)
+}
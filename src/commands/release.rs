@@ -11,6 +11,7 @@
 //! Implementation of the 'release' subcommand
 
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
@@ -19,6 +20,7 @@ use anyhow::Error;
 use anyhow::Result;
 use clap::ArgMatches;
 use diesel::prelude::*;
+use diesel::PgConnection;
 use log::{debug, error, info, trace};
 use tokio_stream::StreamExt;
 use resiter::AndThen;
@@ -26,16 +28,27 @@ use resiter::AndThen;
 use crate::config::Configuration;
 use crate::db::models as dbmodels;
 use crate::db::DbConnectionConfig;
+use crate::filestore::ObjectStore;
+use crate::filestore::RemotePush;
+use crate::filestore::SubmitManifest;
+use crate::filestore::sidecar_paths_for;
+use crate::util::glob::PathSelection;
+use crate::util::progress::ProgressBars;
 
 /// Implementation of the "release" subcommand
 pub async fn release(
     db_connection_config: DbConnectionConfig<'_>,
     config: &Configuration,
+    progressbars: ProgressBars,
     matches: &ArgMatches,
 ) -> Result<()> {
     match matches.subcommand() {
-        Some(("new", matches))  => new_release(db_connection_config, config, matches).await,
+        Some(("new", matches))  => new_release(db_connection_config, config, progressbars, matches).await,
         Some(("rm", matches))   => rm_release(db_connection_config, config, matches).await,
+        Some(("promote", matches)) => promote(db_connection_config, config, progressbars, matches).await,
+        Some(("dedup", _))      => dedup(config).await,
+        Some(("stats", matches)) => stats(db_connection_config, config, matches).await,
+        Some(("push", matches)) => push(config, progressbars, matches).await,
         Some((other, _matches)) => Err(anyhow!("Unknown subcommand: {}", other)),
         None => Err(anyhow!("Missing subcommand")),
     }
@@ -45,6 +58,7 @@ pub async fn release(
 async fn new_release(
     db_connection_config: DbConnectionConfig<'_>,
     config: &Configuration,
+    progressbars: ProgressBars,
     matches: &ArgMatches,
 ) -> Result<()> {
     let print_released_file_pathes = !matches.is_present("quiet");
@@ -63,11 +77,17 @@ async fn new_release(
     debug!("Release called for: {:?} {:?}", pname, pvers);
 
     let conn = db_connection_config.establish_connection()?;
-    let submit_uuid = matches
-        .value_of("submit_uuid")
-        .map(uuid::Uuid::parse_str)
-        .transpose()?
-        .unwrap(); // safe by clap
+    let submit_or_manifest = matches.value_of("submit_uuid").unwrap(); // safe by clap
+    let (submit_uuid, manifest) = match uuid::Uuid::parse_str(submit_or_manifest) {
+        Ok(submit_uuid) => (submit_uuid, None),
+        Err(_) => {
+            let path = PathBuf::from(submit_or_manifest);
+            let manifest = SubmitManifest::load(&path)
+                .await
+                .with_context(|| anyhow!("'{}' is neither a submit UUID nor a readable manifest", submit_or_manifest))?;
+            (manifest.submit, Some((path, manifest)))
+        },
+    };
     debug!("Release called for submit: {:?}", submit_uuid);
 
     let submit = crate::schema::submits::dsl::submits
@@ -75,7 +95,9 @@ async fn new_release(
         .first::<dbmodels::Submit>(&conn)?;
     debug!("Found Submit: {:?}", submit_uuid);
 
-    let arts = {
+    let arts = if let Some((_, manifest)) = manifest.as_ref() {
+        manifest_artifacts(&conn, manifest, pname.as_deref(), pvers.as_deref())?
+    } else {
         let sel = crate::schema::artifacts::dsl::artifacts
             .inner_join(crate::schema::jobs::table.inner_join(crate::schema::packages::table))
             .filter(crate::schema::jobs::submit_id.eq(submit.id))
@@ -120,6 +142,21 @@ async fn new_release(
     };
     debug!("Artifacts = {:?}", arts);
 
+    let selection = PathSelection::new(
+        matches.values_of("include").into_iter().flatten(),
+        matches.values_of("exclude").into_iter().flatten(),
+    )?;
+    let arts = arts.into_iter()
+        .filter(|art| selection.selects(&art.path_buf()))
+        .collect::<Vec<_>>();
+    let arts = if matches.is_present("select") {
+        select_artifacts(arts)?
+    } else {
+        arts
+    };
+    debug!("Selected artifacts = {:?}", arts);
+    check_hermetic(config, release_store_name, &arts)?;
+
     arts.iter()
         .filter_map(|art| {
             art.path_buf()
@@ -134,13 +171,19 @@ async fn new_release(
         .collect::<Result<()>>()
         .await?;
 
-    let staging_base: &PathBuf = &config.staging_directory().join(submit.uuid.to_string());
+    // A manifest is in the staging directory, wherever that is
+    let staging_base: &PathBuf = &manifest.as_ref()
+        .and_then(|(path, _)| path.parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| config.staging_directory().join(submit.uuid.to_string()));
 
+    let objects = ObjectStore::in_directory(config.releases_directory());
     let release_store = crate::db::models::ReleaseStore::create(&conn, release_store_name)?;
     let do_update = matches.is_present("package_do_update");
     let interactive = !matches.is_present("noninteractive");
 
     let now = chrono::offset::Local::now().naive_local();
+    let mut released = vec![];
     let any_err = arts.into_iter()
         .map(|art| async {
             let art = art; // ensure it is moved
@@ -159,6 +202,13 @@ async fn new_release(
                 );
                 Err(anyhow!("Not a file: {}", art_path.display()))
             } else {
+                if let Some(listed) = manifest.as_ref().and_then(|(_, manifest)| manifest.artifact(&art.path_buf())) {
+                    let sha256 = ObjectStore::hash_file(&art_path).await?;
+                    if sha256 != listed.sha256 {
+                        return Err(anyhow!("{} does not match the manifest: sha256 {} instead of {}", art_path.display(), sha256, listed.sha256));
+                    }
+                }
+
                 if dest_path.exists() && !do_update {
                     return Err(anyhow!("Does already exist: {}", dest_path.display()));
                 } else if dest_path.exists() && do_update {
@@ -176,23 +226,41 @@ async fn new_release(
                 }
 
                 // else !dest_path.exists()
-                tokio::fs::copy(&art_path, &dest_path)
+                objects.link_from(&art_path, &dest_path)
                     .await
-                    .with_context(|| anyhow!("Copying {} to {}", art_path.display(), dest_path.display()))
-                    .map_err(Error::from)
-                    .and_then(|_| {
-                        debug!("Updating {:?} to set released = true", art);
-                        let rel = crate::db::models::Release::create(&conn, &art, &now, &release_store)?;
-                        debug!("Release object = {:?}", rel);
-                        Ok(dest_path)
-                    })
+                    .with_context(|| anyhow!("Releasing {} to {}", art_path.display(), dest_path.display()))?;
+
+                // The metadata and provenance files are released as well, so the released
+                // artifact stays self-describing
+                let sidecars = sidecar_paths_for(&art_path).into_iter().zip(sidecar_paths_for(&dest_path));
+                for (src, dest) in sidecars {
+                    if src.is_file() {
+                        tokio::fs::copy(&src, &dest)
+                            .await
+                            .with_context(|| anyhow!("Copying {} to {}", src.display(), dest.display()))?;
+                    }
+                }
+
+                debug!("Updating {:?} to set released = true", art);
+                let rel = conn.transaction::<_, Error, _>(|| {
+                    let rel = crate::db::models::Release::create(&conn, &art, &now, &release_store)?;
+                    crate::db::models::AuditEntry::create(&conn, config, "release", serde_json::json!({
+                        "submit": submit.uuid,
+                        "artifact": art.path,
+                        "store": release_store_name,
+                    }))?;
+                    Ok(rel)
+                })?;
+                debug!("Release object = {:?}", rel);
+                Ok((dest_path, art.path_buf()))
             }
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
         .collect::<Vec<Result<_>>>()
         .await
         .into_iter()
-        .and_then_ok(|dest_path| {
+        .and_then_ok(|(dest_path, artifact_path)| {
+            released.push(artifact_path);
             if print_released_file_pathes {
                 writeln!(std::io::stdout(), "{}", dest_path.display()).map_err(Error::from)
             } else {
@@ -205,9 +273,239 @@ async fn new_release(
         .is_some(); // consume iterator completely, if not empty, there was an error
 
     if any_err {
-        Err(anyhow!("Releasing one or more artifacts failed"))
+        return Err(anyhow!("Releasing one or more artifacts failed"))
+    }
+
+    auto_push(config, release_store_name, &released, &progressbars).await
+}
+
+/// The artifacts listed in `manifest`, only of the package `name` and version `version` if given
+///
+/// The artifacts are only looked up in the database to record their releases, which artifacts
+/// belong to the submit is taken from the manifest.
+fn manifest_artifacts(conn: &PgConnection, manifest: &SubmitManifest, name: Option<&str>, version: Option<&str>) -> Result<Vec<dbmodels::Artifact>> {
+    manifest.artifacts
+        .iter()
+        .filter(|artifact| name.map(|name| artifact.package_name == name).unwrap_or(true))
+        .filter(|artifact| version.map(|version| artifact.package_version == version).unwrap_or(true))
+        .map(|artifact| {
+            let path = artifact.path.display().to_string();
+            crate::schema::artifacts::table
+                .inner_join(crate::schema::jobs::table)
+                .filter(crate::schema::jobs::uuid.eq(artifact.job))
+                .filter(crate::schema::artifacts::path.eq(&path))
+                .select(crate::schema::artifacts::all_columns)
+                .first::<dbmodels::Artifact>(conn)
+                .optional()?
+                .ok_or_else(|| anyhow!("Artifact {} of job {} from the manifest not found in the database", path, artifact.job))
+        })
+        .collect()
+}
+
+/// Mirror the `released` artifacts of the release store to the remotes that push it automatically
+async fn auto_push(config: &Configuration, release_store_name: &str, released: &[PathBuf], progressbars: &ProgressBars) -> Result<()> {
+    let store_root = config.releases_directory().join(release_store_name);
+    for (remote_name, remote) in config.release_remotes().iter() {
+        if remote.auto_push().iter().any(|store| store == release_store_name) {
+            let push = RemotePush::new(remote_name, remote, &store_root);
+            push_and_verify(&push, push.files_with_sidecars(released.iter().cloned()), progressbars).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Implementation of the "release promote" subcommand
+async fn promote(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    progressbars: ProgressBars,
+    matches: &ArgMatches,
+) -> Result<()> {
+    use crate::schema::artifacts;
+    use crate::schema::jobs;
+    use crate::schema::release_stores;
+    use crate::schema::releases;
+
+    let from_store_name = matches.value_of("from_store").unwrap(); // safe by clap
+    let to_store_name = matches.value_of("to_store").unwrap(); // safe by clap
+    let artifact_or_submit = matches.value_of("artifact_or_submit").unwrap(); // safe by clap
+    let do_update = matches.is_present("package_do_update");
+    let print_promoted_file_pathes = !matches.is_present("quiet");
+
+    for name in [from_store_name, to_store_name] {
+        if !config.release_stores().iter().any(|s| s == name) {
+            return Err(anyhow!("Unknown release store name: {}", name))
+        }
+    }
+    if from_store_name == to_store_name {
+        return Err(anyhow!("Cannot promote from release store {} to itself", from_store_name))
+    }
+
+    let conn = db_connection_config.establish_connection()?;
+    let from_store = dbmodels::ReleaseStore::create(&conn, from_store_name)?;
+    let to_store = dbmodels::ReleaseStore::create(&conn, to_store_name)?;
+
+    let sel = artifacts::table
+        .inner_join(releases::table)
+        .inner_join(jobs::table)
+        .filter(releases::release_store_id.eq(from_store.id))
+        .select(artifacts::all_columns)
+        .distinct()
+        .into_boxed();
+    let arts = match uuid::Uuid::parse_str(artifact_or_submit) {
+        Ok(submit_uuid) => {
+            let submit = dbmodels::Submit::with_id(&conn, &submit_uuid)?;
+            sel.filter(jobs::submit_id.eq(submit.id)).load::<dbmodels::Artifact>(&conn)?
+        },
+        Err(_) => sel.filter(artifacts::path.eq(artifact_or_submit)).load::<dbmodels::Artifact>(&conn)?,
+    };
+    if arts.is_empty() {
+        return Err(anyhow!("Nothing to promote: {} is not released in {}", artifact_or_submit, from_store_name))
+    }
+    check_hermetic(config, to_store_name, &arts)?;
+
+    let promoted_by = crate::util::operator(config);
+    let objects = ObjectStore::in_directory(config.releases_directory());
+    let now = chrono::offset::Local::now().naive_local();
+    let mut promoted = vec![];
+    for art in arts {
+        let src_path = config.releases_directory().join(from_store_name).join(&art.path);
+        let dest_path = config.releases_directory().join(to_store_name).join(&art.path);
+        if !src_path.is_file() {
+            return Err(anyhow!("Not a file: {}", src_path.display()))
+        }
+
+        let already_released = releases::table
+            .inner_join(release_stores::table)
+            .filter(releases::artifact_id.eq(art.id))
+            .filter(release_stores::store_name.eq(to_store_name))
+            .select(releases::id)
+            .first::<i32>(&conn)
+            .optional()?
+            .is_some();
+        if already_released && !do_update {
+            info!("Already released in {}: {}", to_store_name, art.path);
+            continue
+        }
+
+        if dest_path.exists() {
+            if !do_update {
+                return Err(anyhow!("Does already exist: {}", dest_path.display()))
+            }
+            debug!("Removing {} before writing new file to this path", dest_path.display());
+            tokio::fs::remove_file(&dest_path)
+                .await
+                .with_context(|| anyhow!("Removing {} before writing new file to this path", dest_path.display()))?;
+        }
+        if let Some(parent) = dest_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        objects.link_from(&src_path, &dest_path)
+            .await
+            .with_context(|| anyhow!("Promoting {} to {}", src_path.display(), dest_path.display()))?;
+        let sidecars = sidecar_paths_for(&src_path).into_iter().zip(sidecar_paths_for(&dest_path));
+        for (src, dest) in sidecars {
+            if src.is_file() {
+                tokio::fs::copy(&src, &dest)
+                    .await
+                    .with_context(|| anyhow!("Copying {} to {}", src.display(), dest.display()))?;
+            }
+        }
+
+        conn.transaction::<_, Error, _>(|| {
+            let release = dbmodels::Release::create(&conn, &art, &now, &to_store)?;
+            dbmodels::ReleasePromotion::create(&conn, &release, &from_store, &promoted_by)?;
+            dbmodels::AuditEntry::create(&conn, config, "release-promote", serde_json::json!({
+                "artifact": art.path,
+                "from": from_store_name,
+                "to": to_store_name,
+            }))?;
+            Ok(())
+        })?;
+
+        if print_promoted_file_pathes {
+            writeln!(std::io::stdout(), "{}", dest_path.display())?;
+        }
+        promoted.push(art.path_buf());
+    }
+
+    auto_push(config, to_store_name, &promoted, &progressbars).await
+}
+
+/// Fail if `store_name` is one of the `hermetic_release_stores` and one of the `arts` was not
+/// built hermetically
+fn check_hermetic(config: &Configuration, store_name: &str, arts: &[dbmodels::Artifact]) -> Result<()> {
+    if !config.hermetic_release_stores().iter().any(|s| s == store_name) {
+        return Ok(())
+    }
+
+    let not_hermetic = arts.iter()
+        .filter(|art| !art.hermetic)
+        .map(|art| art.path.as_str())
+        .collect::<Vec<_>>();
+    if not_hermetic.is_empty() {
+        Ok(())
     } else {
+        Err(anyhow!("Release store {} only takes artifacts of hermetic builds, these were not built hermetically: {}",
+            store_name, not_hermetic.join(", ")))
+    }
+}
+
+/// Let the user uncheck the artifacts that should not be released
+fn select_artifacts(arts: Vec<dbmodels::Artifact>) -> Result<Vec<dbmodels::Artifact>> {
+    let items = arts.iter().map(|art| art.path.clone()).collect::<Vec<_>>();
+    let defaults = vec![true; items.len()];
+    let selected = dialoguer::MultiSelect::new()
+        .with_prompt("Artifacts to release")
+        .items(&items)
+        .defaults(&defaults)
+        .interact()?;
+
+    Ok(arts.into_iter()
+        .enumerate()
+        .filter(|(i, _)| selected.contains(i))
+        .map(|(_, art)| art)
+        .collect())
+}
+
+/// Implementation of the "release push" subcommand
+async fn push(config: &Configuration, progressbars: ProgressBars, matches: &ArgMatches) -> Result<()> {
+    let store_name = matches.value_of("release_store_name").unwrap(); // safe by clap
+    let remote_name = matches.value_of("remote_name").unwrap(); // safe by clap
+
+    if !config.release_stores().iter().any(|s| s == store_name) {
+        return Err(anyhow!("Unknown release store: {}", store_name))
+    }
+    let remote = config.release_remotes()
+        .get(remote_name)
+        .ok_or_else(|| anyhow!("Unknown release remote: {}", remote_name))?;
+
+    let store_root = config.releases_directory().join(store_name);
+    let bar = progressbars.bar()?;
+    bar.set_message(format!("Loading release store {}", store_name));
+    let store = crate::filestore::ReleaseStore::load(crate::filestore::path::StoreRoot::new(store_root.clone())?, &bar)?;
+    bar.finish_and_clear();
+
+    let push = RemotePush::new(remote_name, remote, &store_root);
+    let mut artifacts = store.artifacts()?.map(|a| a.as_ref().to_path_buf()).collect::<Vec<_>>();
+    artifacts.sort();
+    push_and_verify(&push, push.files_with_sidecars(artifacts), &progressbars).await
+}
+
+/// Push the `files` and verify that the remote has them afterwards
+async fn push_and_verify(push: &RemotePush<'_>, files: Vec<PathBuf>, progressbars: &ProgressBars) -> Result<()> {
+    push.push(&files, &progressbars.transfers()?).await?;
+
+    let differing = push.verify(&files, &progressbars.bar()?).await?;
+    for file in differing.iter() {
+        error!("Differs on the remote after pushing: {}", file.display());
+    }
+
+    if differing.is_empty() {
         Ok(())
+    } else {
+        Err(anyhow!("{} files differ on the remote after pushing", differing.len()))
     }
 }
 
@@ -247,7 +545,7 @@ pub async fn rm_release(
         .select((crate::schema::releases::all_columns, crate::schema::artifacts::all_columns))
         .first::<(crate::db::models::Release, crate::db::models::Artifact)>(&conn)?;
 
-    let artifact_path = config.releases_directory().join(release_store_name).join(&artifact.path);
+    let artifact_path = config.releases_directory().join(&release_store_name).join(&artifact.path);
     if !artifact_path.is_file() {
         return Err(anyhow!("Not a file: {}", artifact_path.display()))
     }
@@ -258,12 +556,157 @@ pub async fn rm_release(
         return Ok(())
     }
 
-    tokio::fs::remove_file(&artifact_path).await?;
+    ObjectStore::in_directory(config.releases_directory())
+        .remove(&artifact_path)
+        .await?;
+    for sidecar in sidecar_paths_for(&artifact_path) {
+        if sidecar.is_file() {
+            tokio::fs::remove_file(&sidecar).await?;
+        }
+    }
     info!("File removed");
 
-    diesel::delete(&release).execute(&conn)?;
+    conn.transaction::<_, Error, _>(|| {
+        diesel::delete(&release).execute(&conn)?;
+        crate::db::models::AuditEntry::create(&conn, config, "release-rm", serde_json::json!({
+            "artifact": artifact.path,
+            "store": release_store_name,
+        }))?;
+        Ok(())
+    })?;
     info!("Release deleted from database");
 
     Ok(())
 }
 
+
+/// Implementation of the "release dedup" subcommand
+///
+/// Stores that were created before artifacts were stored by their content hash contain plain
+/// files. This moves all artifacts of the staging stores and the release stores into the object
+/// stores and removes objects that are not linked by any artifact anymore (e.g. because a staging
+/// directory was removed).
+async fn dedup(config: &Configuration) -> Result<()> {
+    let staging_stores = std::fs::read_dir(config.staging_directory())?
+        .map(|entry| entry.map(|e| e.path()).map_err(Error::from))
+        .filter(|path| {
+            path.as_ref()
+                .map(|p| p.is_dir() && p.file_name() != Some(std::ffi::OsStr::new(crate::filestore::OBJECTS_DIR_NAME)))
+                .unwrap_or(true)
+        })
+        .collect::<Result<Vec<PathBuf>>>()?;
+    let release_stores = config.release_stores()
+        .iter()
+        .map(|name| config.releases_directory().join(name))
+        .filter(|p| p.is_dir())
+        .collect::<Vec<PathBuf>>();
+
+    let out = std::io::stdout();
+    for (objects, stores) in [
+        (ObjectStore::in_directory(config.staging_directory()), staging_stores),
+        (ObjectStore::in_directory(config.releases_directory()), release_stores),
+    ] {
+        let mut inserted = 0;
+        for store in stores {
+            for entry in walkdir::WalkDir::new(&store).follow_links(false) {
+                let entry = entry?;
+                if entry.file_type().is_file() && !crate::filestore::is_sidecar_path(entry.path()) {
+                    objects.insert(entry.path()).await?;
+                    inserted += 1;
+                }
+            }
+        }
+
+        let pruned = objects.prune().await?;
+        writeln!(out.lock(), "{}: {} artifacts stored, {} unreferenced objects removed", objects.root().display(), inserted, pruned)?;
+    }
+
+    Ok(())
+}
+
+/// Print the statistics of the release stores
+async fn stats(
+    db_connection_config: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+) -> Result<()> {
+    use std::collections::BTreeMap;
+    use std::collections::BTreeSet;
+    use crate::filestore::path::StoreRoot;
+    use crate::filestore::ReleaseStore;
+
+    let csv = matches.is_present("csv");
+    let stores = match matches.value_of("release_store_name") {
+        Some(name) if !config.release_stores().iter().any(|s| s == name) => {
+            return Err(anyhow!("Unknown release store name: {}", name))
+        },
+        Some(name) => vec![name.to_string()],
+        None => config.release_stores().clone(),
+    };
+
+    let conn = db_connection_config.establish_connection()?;
+    let mut store_rows = vec![];
+    let mut package_rows = vec![];
+    for store_name in stores {
+        let path = config.releases_directory().join(&store_name);
+        if !path.is_dir() {
+            debug!("Release store {} does not exist: {}", store_name, path.display());
+            continue
+        }
+
+        let store = ReleaseStore::load(StoreRoot::new(path)?, &indicatif::ProgressBar::hidden())?;
+        let (count, bytes) = store.artifacts()?
+            .map(|artifact| {
+                let path = store.root_path().join(artifact)?
+                    .ok_or_else(|| anyhow!("Artifact vanished: {}", artifact.display()))?
+                    .joined();
+                std::fs::metadata(&path)
+                    .with_context(|| anyhow!("Getting metadata of {}", path.display()))
+                    .map(|meta| meta.len())
+            })
+            .try_fold((0, 0), |(count, bytes), size| size.map(|size| (count + 1, bytes + size)))?;
+
+        let releases = crate::schema::jobs::table
+            .inner_join(crate::schema::packages::table)
+            .inner_join(crate::schema::artifacts::table)
+            .inner_join(crate::schema::releases::table
+                .on(crate::schema::releases::artifact_id.eq(crate::schema::artifacts::id)))
+            .inner_join(crate::schema::release_stores::table
+                .on(crate::schema::release_stores::id.eq(crate::schema::releases::release_store_id)))
+            .filter(crate::schema::release_stores::dsl::store_name.eq(&store_name))
+            .order(crate::schema::releases::dsl::release_date.asc())
+            .select((
+                crate::schema::packages::all_columns,
+                crate::schema::artifacts::all_columns,
+                crate::schema::releases::all_columns,
+            ))
+            .load::<(dbmodels::Package, dbmodels::Artifact, dbmodels::Release)>(&conn)?;
+
+        let describe = |(_, artifact, release): &(dbmodels::Package, dbmodels::Artifact, dbmodels::Release)| {
+            format!("{} ({})", artifact.path, release.release_date)
+        };
+        store_rows.push(vec![
+            store_name.clone(),
+            count.to_string(),
+            bytesize::ByteSize::b(bytes).to_string(),
+            releases.len().to_string(),
+            releases.first().map(describe).unwrap_or_default(),
+            releases.last().map(describe).unwrap_or_default(),
+        ]);
+
+        let mut versions: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for (package, _, _) in releases.iter() {
+            versions.entry(&package.name).or_default().insert(&package.version);
+        }
+        package_rows.extend(versions.into_iter().map(|(name, versions)| {
+            vec![store_name.clone(), name.to_string(), versions.len().to_string()]
+        }));
+    }
+
+    let header = crate::commands::util::mk_header(["Store", "Artifacts", "Size", "Releases", "Oldest release", "Newest release"].to_vec());
+    crate::commands::util::display_data(header, store_rows, csv)?;
+    writeln!(std::io::stdout())?;
+
+    let header = crate::commands::util::mk_header(["Store", "Package", "Released versions"].to_vec());
+    crate::commands::util::display_data(header, package_rows, csv)
+}
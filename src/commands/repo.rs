@@ -0,0 +1,367 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'repo' subcommand
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use itertools::Itertools;
+use log::error;
+use serde::Serialize;
+
+use crate::config::Configuration;
+use crate::package::BuildDependency;
+use crate::package::Dependency;
+use crate::package::Package;
+use crate::package::ParseDependency;
+use crate::repository::PackageFileFormat;
+use crate::repository::Repository;
+use crate::repository::schema::CURRENT_SCHEMA_VERSION;
+
+/// Implementation of the "repo" subcommand
+pub async fn repo(matches: &ArgMatches, config: &Configuration, repo: Repository) -> Result<()> {
+    match matches.subcommand() {
+        Some(("graph", matches)) => graph(matches, repo),
+        Some(("check", matches)) => check(matches, config, repo),
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("No subcommand")),
+    }
+}
+
+/// Implementation of the "repo migrate" subcommand
+///
+/// The package definition files are migrated without loading the repository, so that files the
+/// repository cannot be loaded with can be migrated as well.
+pub fn repo_migrate(repo_path: &Path, matches: &ArgMatches) -> Result<()> {
+    let dry_run = matches.is_present("dry_run");
+    let files = crate::repository::pkgtoml_files(repo_path)?
+        .into_iter()
+        .map(|entry| entry.into_path())
+        .sorted()
+        .collect::<Vec<_>>();
+
+    let mut out = std::io::stdout();
+    let mut migrated = 0;
+    let mut failed = 0;
+    for path in files.iter() {
+        let relative = path.strip_prefix(repo_path).unwrap_or(path);
+        let content = std::fs::read_to_string(path)
+            .with_context(|| anyhow!("Reading {}", relative.display()))?;
+
+        match crate::repository::schema::migrate(&content, PackageFileFormat::of(path)?) {
+            Ok(None) => {},
+            Ok(Some((from, content))) => {
+                if !dry_run {
+                    std::fs::write(path, content)
+                        .with_context(|| anyhow!("Writing {}", relative.display()))?;
+                }
+                writeln!(out, "{}: schema version {} -> {}", relative.display(), from, CURRENT_SCHEMA_VERSION)?;
+                migrated += 1;
+            },
+            Err(e) => {
+                error!("Cannot migrate {}: {:#}", relative.display(), e);
+                failed += 1;
+            },
+        }
+    }
+
+    if dry_run {
+        writeln!(out, "{} of {} package definition files would be migrated to schema version {}", migrated, files.len(), CURRENT_SCHEMA_VERSION)?;
+    } else {
+        writeln!(out, "Migrated {} of {} package definition files to schema version {}", migrated, files.len(), CURRENT_SCHEMA_VERSION)?;
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} package definition files could not be migrated", failed))
+    }
+    if migrated > 0 && !dry_run {
+        writeln!(out, "Review the migrated files and commit them")?;
+    }
+    Ok(())
+}
+
+/// The dependency graph of all packages of the repository
+#[derive(Serialize)]
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+#[derive(Serialize)]
+struct Node {
+    id: String,
+    name: String,
+    version: String,
+
+    /// Whether the node is an external dependency, i.e. not a package of the repository
+    external: bool,
+}
+
+#[derive(Serialize)]
+struct Edge {
+    from: String,
+
+    /// The id of the package that satisfies the dependency, `None` if no package does
+    to: Option<String>,
+
+    /// "build", "runtime" or "external"
+    kind: &'static str,
+
+    /// The dependency as written in the package, e.g. "openssl >=1.1"
+    dependency: String,
+
+    /// Whether the dependency only applies under a condition
+    conditional: bool,
+}
+
+fn node_id(name: &str, version: &str) -> String {
+    format!("{} {}", name, version)
+}
+
+impl Graph {
+    /// The graph of all packages of `repo`
+    ///
+    /// A dependency has an edge to every package that satisfies it, regardless of conditions and
+    /// of which version a build would select.
+    fn of_repository(repo: &Repository) -> Result<Self> {
+        let packages = repo.packages()
+            .sorted_by(|a, b| a.name().cmp(b.name()).then_with(|| a.version().compare(b.version())))
+            .collect::<Vec<_>>();
+
+        let mut nodes = packages.iter()
+            .map(|p| Node {
+                id: node_id(p.name(), p.version()),
+                name: p.name().to_string(),
+                version: p.version().to_string(),
+                external: false,
+            })
+            .collect::<Vec<_>>();
+        let mut edges = vec![];
+
+        for package in packages.iter() {
+            let from = node_id(package.name(), package.version());
+            let build = package.dependencies()
+                .build()
+                .iter()
+                .map(|d| (d as &dyn ParseDependency, "build", d.as_ref(), matches!(d, BuildDependency::Conditional { .. })));
+            let runtime = package.dependencies()
+                .runtime()
+                .iter()
+                .map(|d| (d as &dyn ParseDependency, "runtime", d.as_ref(), matches!(d, Dependency::Conditional { .. })));
+
+            for (dependency, kind, text, conditional) in build.chain(runtime) {
+                let (name, constraint) = dependency.parse_as_name_and_version()?;
+                let satisfying = repo.find_with_version(&name, &constraint);
+                if satisfying.is_empty() {
+                    edges.push(Edge { from: from.clone(), to: None, kind, dependency: text.to_string(), conditional });
+                }
+                edges.extend(satisfying.into_iter().map(|p: &Package| Edge {
+                    from: from.clone(),
+                    to: Some(node_id(p.name(), p.version())),
+                    kind,
+                    dependency: text.to_string(),
+                    conditional,
+                }));
+            }
+
+            for external in package.dependencies().external() {
+                let to = node_id(external.name(), external.version());
+                if !nodes.iter().any(|n| n.external && n.id == to) {
+                    nodes.push(Node {
+                        id: to.clone(),
+                        name: external.name().to_string(),
+                        version: external.version().to_string(),
+                        external: true,
+                    });
+                }
+                edges.push(Edge {
+                    from: from.clone(),
+                    to: Some(to.clone()),
+                    kind: "external",
+                    dependency: to,
+                    conditional: false,
+                });
+            }
+        }
+
+        Ok(Graph { nodes, edges })
+    }
+
+    /// Write the graph in the DOT language of graphviz
+    fn write_dot<W: Write>(&self, mut out: W) -> Result<()> {
+        fn escape(s: &str) -> String {
+            s.replace('\\', "\\\\").replace('"', "\\\"")
+        }
+        fn quote(s: &str) -> String {
+            format!("\"{}\"", escape(s))
+        }
+
+        writeln!(out, "digraph repository {{")?;
+        for node in self.nodes.iter() {
+            let shape = if node.external { ", shape=box, style=dashed" } else { "" };
+            writeln!(out, "  {} [label=\"{}\\n{}\", version={}{}];",
+                quote(&node.id),
+                escape(&node.name),
+                escape(&node.version),
+                quote(&node.version),
+                shape)?;
+        }
+
+        for edge in self.edges.iter() {
+            let style = match edge.kind {
+                "build" => "dashed",
+                _ => "solid",
+            };
+            let to = match edge.to.as_ref() {
+                Some(to) => quote(to),
+                None => {
+                    // A node for the dependency that is not satisfied by any package
+                    let missing = quote(&format!("missing: {}", edge.dependency));
+                    writeln!(out, "  {} [shape=box, color=red];", missing)?;
+                    missing
+                },
+            };
+            writeln!(out, "  {} -> {} [kind={}, dependency={}, conditional={}, style={}];",
+                quote(&edge.from),
+                to,
+                quote(edge.kind),
+                quote(&edge.dependency),
+                edge.conditional,
+                style)?;
+        }
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+}
+
+fn graph(matches: &ArgMatches, repo: Repository) -> Result<()> {
+    let graph = Graph::of_repository(&repo)?;
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+
+    match matches.value_of("format") {
+        Some("json") => {
+            serde_json::to_writer_pretty(&mut outlock, &graph)?;
+            writeln!(outlock)?;
+            Ok(())
+        },
+        _ => graph.write_dot(outlock),
+    }
+}
+
+/// A dependency that does not resolve to exactly one package
+struct Problem {
+    package: String,
+    version: String,
+    kind: &'static str,
+    dependency: String,
+
+    /// Problems that break builds are errors, the others are warnings
+    error: bool,
+    message: String,
+}
+
+/// Check that every dependency of every package resolves
+///
+/// A build or runtime dependency resolves if packages of the repository satisfy it and the
+/// configured version resolution selects one of them, or if a package of the repository declares
+/// an external dependency that satisfies it. Dependencies that several packages provide are
+/// ambiguous, they only resolve if the tree of a build contains exactly one of the providers.
+/// Conditions of dependencies are not evaluated.
+fn check(matches: &ArgMatches, config: &Configuration, repo: Repository) -> Result<()> {
+    let strict = matches.is_present("strict");
+    let externals = repo.packages()
+        .flat_map(|p| p.dependencies().external().iter())
+        .collect::<Vec<_>>();
+
+    let mut problems = vec![];
+    let mut number_of_dependencies = 0;
+    for package in repo.packages().sorted_by(|a, b| a.name().cmp(b.name()).then_with(|| a.version().compare(b.version()))) {
+        let build = package.dependencies()
+            .build()
+            .iter()
+            .map(|d| (d as &dyn ParseDependency, "build", d.as_ref()));
+        let runtime = package.dependencies()
+            .runtime()
+            .iter()
+            .map(|d| (d as &dyn ParseDependency, "runtime", d.as_ref()));
+
+        for (dependency, kind, text) in build.chain(runtime) {
+            number_of_dependencies += 1;
+            let problem = |error: bool, message: String| Problem {
+                package: package.name().to_string(),
+                version: package.version().to_string(),
+                kind,
+                dependency: text.to_string(),
+                error,
+                message,
+            };
+
+            let (name, constraint) = match dependency.parse_as_name_and_version() {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    problems.push(problem(true, format!("{:#}", e)));
+                    continue
+                },
+            };
+
+            // Packages with the name are preferred over packages that provide it, as in builds
+            let mut candidates = repo.find_with_version(&name, &constraint);
+            if candidates.iter().any(|p| p.is_named(&name)) {
+                candidates.retain(|p| p.is_named(&name));
+            }
+
+            if candidates.is_empty() {
+                let external = externals.iter().any(|e| *e.name() == name && constraint.matches(e.version()));
+                if !external {
+                    problems.push(problem(true, String::from("not satisfied by any package")));
+                }
+                continue
+            }
+
+            match config.resolver().select(&name, &constraint, candidates) {
+                Err(e) => problems.push(problem(true, format!("{:#}", e))),
+                Ok(selected) if selected.len() > 1 => {
+                    let providers = selected.iter()
+                        .map(|p| format!("{} {}", p.qualified_name(), p.version()))
+                        .join(", ");
+                    problems.push(problem(false, format!("ambiguous, provided by {}", providers)));
+                },
+                Ok(_) => {},
+            }
+        }
+    }
+
+    let errors = problems.iter().filter(|p| p.error).count();
+    let warnings = problems.len() - errors;
+    let data = problems.into_iter()
+        .map(|p| vec![
+            p.package,
+            p.version,
+            p.kind.to_string(),
+            p.dependency,
+            String::from(if p.error { "error" } else { "warning" }),
+            p.message,
+        ])
+        .collect::<Vec<_>>();
+    let header = crate::commands::util::mk_header(vec!["Package", "Version", "Kind", "Dependency", "Severity", "Problem"]);
+    crate::commands::util::display_data(header, data, matches.is_present("csv"))?;
+
+    writeln!(std::io::stdout(), "{} dependencies checked: {} errors, {} warnings", number_of_dependencies, errors, warnings)?;
+    if errors > 0 || (strict && warnings > 0) {
+        Err(anyhow!("Repository check failed"))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,327 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'db rerun-job' subcommand
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use colored::Colorize;
+use diesel::prelude::*;
+use log::info;
+use log::warn;
+use tokio::sync::RwLock;
+
+use crate::config::Configuration;
+use crate::config::EndpointName;
+use crate::db::models as dbmodels;
+use crate::db::DbConnectionConfig;
+use crate::filestore::path::StoreRoot;
+use crate::filestore::ArtifactMetadata;
+use crate::filestore::ArtifactPath;
+use crate::filestore::ReleaseStore;
+use crate::filestore::StagingStore;
+use crate::job::JobResource;
+use crate::job::RunnableJob;
+use crate::orchestrator::JobState;
+use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::Script;
+use crate::repository::Repository;
+use crate::source::SourceCache;
+use crate::util::docker::ImageName;
+use crate::util::progress::ProgressBars;
+
+use super::verify_reproducibility::find_dependency_artifacts;
+use super::verify_reproducibility::find_recorded_file;
+use super::verify_reproducibility::package_of_job;
+use super::verify_reproducibility::recorded_env;
+
+/// Implementation of the "db rerun-job" subcommand
+pub async fn rerun_job(
+    conn_cfg: DbConnectionConfig<'_>,
+    config: &Configuration,
+    matches: &ArgMatches,
+    progressbars: ProgressBars,
+    repo: Repository,
+) -> Result<()> {
+    let job_uuid = matches.value_of("job_uuid")
+        .map(uuid::Uuid::parse_str)
+        .transpose()?
+        .unwrap(); // safe by clap
+    let pool = conn_cfg.establish_pool()?;
+    let conn = pool.get()?;
+
+    let job = crate::schema::jobs::table
+        .filter(crate::schema::jobs::uuid.eq(job_uuid))
+        .first::<dbmodels::Job>(&conn)
+        .optional()?
+        .ok_or_else(|| anyhow!("Job {} not found", job_uuid))?;
+    let old_submit = crate::schema::submits::table
+        .find(job.submit_id)
+        .first::<dbmodels::Submit>(&conn)?;
+    let db_package = dbmodels::Package::fetch_for_job(&conn, &job)?
+        .ok_or_else(|| anyhow!("Package for job {} not found", job.uuid))?;
+    let db_image = dbmodels::Image::fetch_by_id(&conn, job.image_id)?
+        .ok_or_else(|| anyhow!("Image for job {} not found", job.uuid))?;
+    let image_name = ImageName::from(db_image.name.clone());
+
+    // Jobs are recorded with the name of the package, without its namespace
+    let pname = repo.resolve_name(&PackageName::from(db_package.name.clone()))?;
+    let pvers = PackageVersion::from(db_package.version.clone());
+    let (package, options) = package_of_job(&repo, &job, &pname, &pvers)?;
+    let recorded_env = recorded_env(&conn, &job)?;
+
+    let release_stores = config
+        .release_stores()
+        .iter()
+        .map(|name| config.releases_directory().join(name))
+        .filter(|p| p.is_dir())
+        .map(|p| StoreRoot::new(p).map(ReleaseStore::open).map(Arc::new))
+        .collect::<Result<Vec<_>>>()?;
+
+    // The artifacts the job got from its dependencies are in the staging store of its submit or
+    // were released since
+    let old_staging = {
+        let p = config.staging_directory().join(old_submit.uuid.to_string());
+        if !p.is_dir() {
+            warn!("Staging directory of submit {} does not exist anymore, the artifacts of the dependencies have to be released", old_submit.uuid);
+        }
+        let bar = progressbars.bar()?;
+        let r = if p.is_dir() {
+            StagingStore::load(StoreRoot::new(p)?, &bar).map(Some)
+        } else {
+            Ok(None)
+        };
+        bar.finish_with_message("Loaded staging");
+        r?.map(Arc::new)
+    };
+
+    check_sources(&conn, &job, &package, old_staging.as_deref(), &release_stores)?;
+
+    let dependencies = match recorded_dependency_artifacts(&conn, &job)? {
+        Some(artifacts) => artifacts
+            .into_iter()
+            .map(|artifact| {
+                match find_file(&artifact, old_staging.as_deref(), &release_stores)? {
+                    Some(_) => Ok(artifact),
+                    None => Err(anyhow!("Artifact {} of a dependency of job {} not found in any store", artifact.display(), job.uuid)),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?,
+        None => {
+            // The job graph of older submits was not recorded, the dependencies are searched like
+            // the build does
+            info!("Dependencies of job {} were not recorded, searching the stores for their artifacts", job.uuid);
+            let staging = match old_staging.as_ref() {
+                Some(staging) => staging.clone(),
+                None => return Err(anyhow!("Dependencies of job {} were not recorded and its staging directory does not exist anymore", job.uuid)),
+            };
+            find_dependency_artifacts(config, &conn, &repo, &package, &image_name, &recorded_env, &options, &staging, &release_stores)?
+        },
+    };
+
+    let resources = dependencies.into_iter()
+        .map(JobResource::from)
+        .chain({
+            // The package environment is added to the job environment anyways
+            recorded_env.into_iter()
+                .filter(|(k, v)| package.environment().as_ref().and_then(|env| env.get(k)) != Some(v))
+                .map(JobResource::from)
+        })
+        .collect();
+    let source_cache = SourceCache::new(config.source_cache_root().clone());
+    let runnable = RunnableJob::with_script(package, image_name, &source_cache, Script::from(job.script_text.clone()), resources, config);
+
+    // The job runs as the only job of a new submit, so that it does not mix with the old one
+    let submit_id = uuid::Uuid::new_v4();
+    let githash = dbmodels::GitHash::with_id(&conn, old_submit.repo_hash_id)?;
+    let now = chrono::offset::Local::now().naive_local();
+    let submitted_by = crate::util::submitter(config, git2::Repository::discover(".").ok().as_ref());
+    let submit = dbmodels::Submit::create(&conn, &now, &submit_id, &db_image, &db_package, &githash, &submitted_by, old_submit.phases.as_deref(), old_submit.pins.as_deref())?;
+    let submit_job = dbmodels::SubmitJob::create(&conn, &submit, runnable.uuid(), &db_package, vec![])?;
+    let new_job_uuid = *runnable.uuid();
+
+    let staging_dir = config.staging_directory().join(submit_id.to_string());
+    tokio::fs::create_dir_all(&staging_dir).await?;
+    let staging_store = {
+        let bar = progressbars.bar()?;
+        let r = StagingStore::load(StoreRoot::new(staging_dir.clone())?, &bar)
+            .map(|mut store| {
+                store.set_compression(config.artifact_compression().clone());
+                store.set_ownership(config.artifact_ownership().clone());
+                store
+            });
+        bar.finish_with_message("Loaded staging");
+        Arc::new(RwLock::new(r?))
+    };
+
+    {
+        let mut out = std::io::stdout();
+        writeln!(out, "Rerunning job:   {}", job.uuid.to_string().green())?;
+        writeln!(out, "Of submit:       {}", old_submit.uuid.to_string().green())?;
+        writeln!(out, "Starting submit: {}", submit_id.to_string().green())?;
+        writeln!(out, "On Image:        {}", db_image.name.green())?;
+        writeln!(out, "For Package:     {} {}", db_package.name.green(), db_package.version.green())?;
+    }
+
+    let endpoint_names = matches.values_of("endpoint")
+        .map(|names| names.map(String::from).map(EndpointName::from).collect::<Vec<_>>())
+        .unwrap_or_else(|| config.docker().endpoints().keys().cloned().collect());
+    let endpoint_configurations = config
+        .docker()
+        .endpoints()
+        .iter()
+        .filter(|(ep_name, _)| endpoint_names.contains(ep_name))
+        .map(|(ep_name, ep_cfg)| {
+            crate::endpoint::EndpointConfiguration::builder()
+                .endpoint_name(ep_name.clone())
+                .endpoint(ep_cfg.clone())
+                .required_images(config.docker().images().clone())
+                .required_docker_versions(config.docker().docker_versions().clone())
+                .required_docker_api_versions(config.docker().docker_api_versions().clone())
+                .negotiate_docker_api_version(config.docker().negotiate_docker_api_version())
+                .min_docker_api_version(config.docker().min_docker_api_version().clone())
+                .pull_missing_images(config.docker().pull_missing_images())
+                .failed_container_retention_hours(config.docker().failed_container_retention_hours())
+                .container_labels(config.docker().container_labels().clone())
+                .build()
+        })
+        .collect::<Vec<_>>();
+    if endpoint_configurations.is_empty() {
+        return Err(anyhow!("No endpoint to run the job on"))
+    }
+
+    let scheduler = crate::endpoint::EndpointScheduler::setup(
+        endpoint_configurations,
+        staging_store,
+        old_staging.into_iter().collect(),
+        release_stores,
+        pool.clone(),
+        submit,
+        if matches.is_present("write-log-file") { Some(config.log_dir().clone()) } else { None },
+        config.docker(),
+    )
+    .await?
+    .with_notification_commands(config.notification_commands().clone())
+    .with_max_log_size(config.max_log_size().map(|size| size.bytes()));
+
+    let record_state = |state: JobState, artifacts: &[ArtifactPath]| {
+        let artifacts = artifacts.iter().map(|a| a.display().to_string()).collect();
+        if let Err(e) = dbmodels::JobStateTransition::create(&conn, submit_job.id, &state.to_string(), artifacts) {
+            warn!("Recording state '{}' of job {} failed: {:#}", state, new_job_uuid, e);
+        }
+    };
+
+    record_state(JobState::Running, &[]);
+    let bar = progressbars.bar()?;
+    let result = scheduler.run_job(runnable, bar.clone(), 0).await;
+    match result {
+        Ok(Ok(artifacts)) => {
+            record_state(JobState::Done, &artifacts);
+            bar.finish_with_message(format!("Job {} finished successfully", new_job_uuid));
+
+            let mut out = std::io::stdout();
+            if !artifacts.is_empty() {
+                writeln!(out, "Packages created:")?;
+            }
+            artifacts.iter()
+                .try_for_each(|artifact| writeln!(out, "-> {}", staging_dir.join(artifact).display()))?;
+            Ok(())
+        },
+        Ok(Err(e)) | Err(e) => {
+            record_state(JobState::Failed, &[]);
+            bar.finish_with_message(format!("Job {} failed", new_job_uuid));
+            Err(e).with_context(|| anyhow!("Rerunning job {} as job {} of submit {}", job.uuid, new_job_uuid, submit_id))
+        },
+    }
+}
+
+/// The artifacts the dependencies of `job` finished with in its submit
+///
+/// `None` if the job graph of the submit was not recorded.
+fn recorded_dependency_artifacts(conn: &PgConnection, job: &dbmodels::Job) -> Result<Option<Vec<ArtifactPath>>> {
+    let dependencies = match dbmodels::SubmitJob::dependency_states(conn, job.submit_id, &job.uuid)? {
+        Some(dependencies) => dependencies,
+        None => return Ok(None),
+    };
+
+    dependencies.into_iter()
+        .map(|(package, transition)| {
+            let finished = transition
+                .filter(|t| matches!(JobState::from_str(&t.state), Ok(JobState::Done) | Ok(JobState::Reused)))
+                .ok_or_else(|| anyhow!("Dependency {} {} of job {} did not finish", package.name, package.version, job.uuid))?;
+            finished.artifacts
+                .iter()
+                .map(|path| ArtifactPath::new(PathBuf::from(path)))
+                .collect::<Result<Vec<_>>>()
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(|artifacts| Some(artifacts.into_iter().flatten().collect()))
+}
+
+/// Find the file of an artifact in the staging store of the old submit or the release stores
+fn find_file(artifact: &ArtifactPath, staging: Option<&StagingStore>, release_stores: &[Arc<ReleaseStore>]) -> Result<Option<PathBuf>> {
+    if let Some(staging) = staging {
+        return find_recorded_file(&artifact.display().to_string(), staging, release_stores)
+    }
+
+    for store in release_stores {
+        if let Some(full) = store.root_path().join(artifact)? {
+            return Ok(Some(full.joined()))
+        }
+    }
+    Ok(None)
+}
+
+/// Check that the sources of `package` are the ones `job` was built from
+///
+/// The sources are only recorded in the metadata files of the artifacts of the job, so they cannot
+/// be checked if none of them is found.
+fn check_sources(conn: &PgConnection, job: &dbmodels::Job, package: &Package, staging: Option<&StagingStore>, release_stores: &[Arc<ReleaseStore>]) -> Result<()> {
+    let recorded = dbmodels::Artifact::belonging_to(job)
+        .load::<dbmodels::Artifact>(conn)?
+        .into_iter()
+        .filter_map(|art| ArtifactPath::new(PathBuf::from(&art.path)).ok())
+        .filter_map(|path| find_file(&path, staging, release_stores).ok().flatten())
+        .map(|file| crate::filestore::meta_path_for(&file))
+        .filter_map(|meta| std::fs::read_to_string(meta).ok())
+        .find_map(|content| serde_json::from_str::<ArtifactMetadata>(&content).ok())
+        .map(|meta| {
+            meta.sources
+                .into_iter()
+                .map(|source| (source.url, source.hash))
+                .collect::<std::collections::BTreeSet<_>>()
+        });
+
+    let recorded = match recorded {
+        Some(recorded) => recorded,
+        None => {
+            warn!("No metadata of the artifacts of job {} found, cannot verify that the sources are unchanged", job.uuid);
+            return Ok(())
+        },
+    };
+
+    let current = package.sources()
+        .values()
+        .map(|source| (source.url().to_string(), source.hash().value().to_string()))
+        .collect::<std::collections::BTreeSet<_>>();
+    if recorded != current {
+        return Err(anyhow!("The sources of {} {} changed since job {}", package.name(), package.version(), job.uuid))
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'schedule' subcommand
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use clap::ArgMatches;
+use log::info;
+use log::warn;
+
+use crate::config::Configuration;
+use crate::config::Schedule;
+use crate::util::cron::CronSchedule;
+
+/// Implementation of the "schedule" subcommand
+pub async fn schedule(matches: &ArgMatches, config: &Configuration) -> Result<()> {
+    match matches.subcommand() {
+        Some(("list", _)) => list(config),
+        Some(("run", matches)) => run(matches, config).await,
+        Some(("trigger", matches)) => {
+            let name = matches.value_of("name").unwrap(); // safe by clap
+            let (name, schedule, _) = schedules(config, Some(vec![name]))?.remove(0);
+            let succeeded = start_build(config, name, schedule).await?;
+            if succeeded {
+                Ok(())
+            } else {
+                Err(anyhow!("Scheduled build '{}' failed", name))
+            }
+        },
+        Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
+        None => Err(anyhow!("Missing subcommand")),
+    }
+}
+
+/// The configured schedules with their parsed cron schedule, sorted by name
+///
+/// If `names` is passed, only these schedules are returned and all of them have to exist.
+fn schedules<'a>(config: &'a Configuration, names: Option<Vec<&str>>) -> Result<Vec<(&'a str, &'a Schedule, CronSchedule)>> {
+    if let Some(name) = names.iter().flatten().find(|name| !config.schedules().contains_key(**name)) {
+        return Err(anyhow!("Schedule '{}' is not configured", name))
+    }
+
+    let mut schedules = config.schedules()
+        .iter()
+        .filter(|(name, _)| names.as_ref().map(|names| names.contains(&name.as_str())).unwrap_or(true))
+        .map(|(name, schedule)| schedule.cron().map(|cron| (name.as_str(), schedule, cron)))
+        .collect::<Result<Vec<_>>>()?;
+    schedules.sort_by_key(|(name, _, _)| *name);
+    Ok(schedules)
+}
+
+fn list(config: &Configuration) -> Result<()> {
+    let now = chrono::Local::now().naive_local();
+    let data = schedules(config, None)?
+        .into_iter()
+        .map(|(name, schedule, cron)| {
+            vec![
+                name.to_string(),
+                schedule.at().clone(),
+                schedule.package().clone(),
+                schedule.version().clone().unwrap_or_default(),
+                schedule.image().clone(),
+                cron.next_after(&now).map(|t| t.to_string()).unwrap_or_else(|| String::from("never")),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    if data.is_empty() {
+        info!("No schedules configured");
+        return Ok(())
+    }
+    let header = crate::commands::util::mk_header(vec!["Name", "At", "Package", "Version", "Image", "Next run"]);
+    crate::commands::util::display_data(header, data, false)
+}
+
+/// Start the scheduled builds when they are due, until butido is stopped
+async fn run(matches: &ArgMatches, config: &Configuration) -> Result<()> {
+    let schedules = schedules(config, matches.values_of("only").map(|names| names.collect()))?;
+    if schedules.is_empty() {
+        return Err(anyhow!("No schedules configured"))
+    }
+
+    let termination = crate::util::signal::termination();
+    tokio::pin!(termination);
+
+    loop {
+        let now = chrono::Local::now().naive_local();
+        let next = schedules.iter()
+            .filter_map(|(_, _, cron)| cron.next_after(&now))
+            .min()
+            .ok_or_else(|| anyhow!("None of the schedules will ever start a build"))?;
+        info!("Next scheduled build at {}", next);
+
+        tokio::select! {
+            _ = tokio::time::sleep(duration_until(&next)) => {},
+            signal = &mut termination => return Err(Error::from(crate::util::signal::Terminated(signal?))),
+        }
+
+        for (name, schedule, _) in schedules.iter().filter(|(_, _, cron)| cron.matches(&next)) {
+            let build = start_build(config, name, schedule);
+            tokio::pin!(build);
+            let result = tokio::select! {
+                result = &mut build => result,
+                signal = &mut termination => {
+                    // The build got the signal as well and stops its jobs
+                    warn!("Waiting for the scheduled build '{}' to stop", name);
+                    let _ = build.await;
+                    return Err(Error::from(crate::util::signal::Terminated(signal?)))
+                },
+            };
+
+            match result {
+                Ok(true) => info!("Scheduled build '{}' succeeded", name),
+                Ok(false) => warn!("Scheduled build '{}' failed", name),
+                Err(e) => warn!("Scheduled build '{}' could not be started: {:?}", name, e),
+            }
+        }
+    }
+}
+
+/// How long to sleep until `time` (local time)
+fn duration_until(time: &NaiveDateTime) -> std::time::Duration {
+    (*time - chrono::Local::now().naive_local())
+        .to_std()
+        .unwrap_or_default()
+}
+
+/// Run "butido build" for the schedule `name` and return whether the build succeeded
+///
+/// The build runs in a separate process, so that the repository and the configuration are loaded
+/// anew for every build and it is recorded like any other submit. The identity of the submit
+/// names the schedule.
+async fn start_build(config: &Configuration, name: &str, schedule: &Schedule) -> Result<bool> {
+    // The arguments before the subcommand, like "--profile NAME"
+    let global_args = std::env::args()
+        .skip(1)
+        .take_while(|arg| arg != "schedule")
+        .collect::<Vec<_>>();
+    let identity = format!("{} (schedule {})", crate::util::submitter(config, None), name);
+
+    info!("Starting scheduled build '{}' of {} {}", name, schedule.package(), schedule.version().as_deref().unwrap_or(""));
+    let status = tokio::process::Command::new(std::env::current_exe().context("Finding the butido executable")?)
+        .args(global_args)
+        .args(schedule.build_command_args())
+        .env("BUTIDO_IDENTITY", identity)
+        .status()
+        .await
+        .with_context(|| anyhow!("Starting the build of schedule '{}'", name))?;
+    Ok(status.success())
+}
@@ -0,0 +1,71 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'script-diff' subcommand
+
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+
+use crate::config::Configuration;
+use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::ScriptBuilder;
+use crate::package::Shebang;
+use crate::repository::Repository;
+
+/// Implementation of the "script-diff" subcommand
+pub async fn script_diff(matches: &ArgMatches, config: &Configuration, repo: Repository) -> Result<()> {
+    let name = matches
+        .value_of("package_name")
+        .map(String::from)
+        .map(PackageName::from)
+        .unwrap(); // safe by clap
+    let name = repo.resolve_name(&name)?;
+    let version = |arg| {
+        let version = matches.value_of(arg).map(String::from).map(PackageVersion::from).unwrap(); // safe by clap
+        find_package(&repo, &name, &version)
+    };
+    let (old, new) = (version("version_a")?, version("version_b")?);
+
+    let render = |package: &Package| {
+        ScriptBuilder::new(&Shebang::from(config.shebang().clone()))
+            .build(package, config.available_phases(), *config.strict_script_interpolation())
+            .with_context(|| anyhow!("Rendering script of {} {}", package.name(), package.version()))
+    };
+    let (old_script, new_script) = (render(old)?, render(new)?);
+
+    let mut stdout = std::io::stdout();
+    if old_script.as_ref() == new_script.as_ref() {
+        writeln!(stdout, "The scripts of {name} {} and {name} {} are identical", old.version(), new.version(), name = name)?;
+        return Ok(())
+    }
+
+    let diff = crate::ui::diff_to_printable(
+        old_script.as_ref(),
+        new_script.as_ref(),
+        &format!("{} {}", name, old.version()),
+        &format!("{} {}", name, new.version()),
+        !matches.is_present("no_color") && atty::is(atty::Stream::Stdout),
+    );
+    write!(stdout, "{}", diff).map_err(anyhow::Error::from)
+}
+
+fn find_package<'a>(repo: &'a Repository, name: &PackageName, version: &PackageVersion) -> Result<&'a Package> {
+    match repo.find(name, version).as_slice() {
+        [package] => Ok(package),
+        [] => Err(anyhow!("Package {} {} not found", name, version)),
+        _ => Err(anyhow!("Package {} {} found multiple times", name, version)),
+    }
+}
@@ -8,7 +8,9 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::io::Write;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -29,95 +31,173 @@ use crate::package::PackageVersionConstraint;
 use crate::repository::Repository;
 use crate::source::*;
 use crate::util::progress::ProgressBars;
+use crate::util::progress::TransferBars;
 
 const NUMBER_OF_MAX_CONCURRENT_DOWNLOADS: usize = 100;
 
-/// A wrapper around the indicatif::ProgressBar
+/// A wrapper around the progress bars of the downloads
 ///
-/// A wrapper around the indicatif::ProgressBar that is used to synchronize status information from
-/// the individual download jobs to the progress bar that is used to display download progress to
-/// the user.
+/// The bars of the individual downloads count the bytes received, the bar for all downloads
+/// together shows the total throughput and, in its message, how many downloads finished.
 ///
-/// The problem this helper solves is that we only have one status bar for all downloads, and all
-/// download tasks must be able to increase the number of bytes received, for example, (that is
-/// displayed in the status message) but in a sync way.
+/// The counts are shared by all download tasks, so they are kept behind a mutex.
 #[derive(Clone)]
 struct ProgressWrapper {
     download_count: u64,
     finished_downloads: u64,
-    current_bytes: usize,
-    sum_bytes: u64,
-    bar: Arc<Mutex<indicatif::ProgressBar>>,
+    transfers: TransferBars,
+
+    /// Number of succeeded and failed downloads per mirror (template)
+    mirror_stats: BTreeMap<String, (u64, u64)>,
 }
 
 impl ProgressWrapper {
-    fn new(bar: indicatif::ProgressBar) -> Self {
+    fn new(transfers: TransferBars) -> Self {
         Self {
             download_count: 0,
             finished_downloads: 0,
-            current_bytes: 0,
-            sum_bytes: 0,
-            bar: Arc::new(Mutex::new(bar))
+            transfers,
+            mirror_stats: BTreeMap::new(),
         }
     }
 
-    async fn inc_download_count(&mut self) {
-        self.download_count += 1;
-        self.set_message().await;
-        let bar = self.bar.lock().await;
-        bar.inc_length(1);
+    fn mirror_download_finished(&mut self, mirror: &str, success: bool) {
+        let stats = self.mirror_stats.entry(mirror.to_string()).or_insert((0, 0));
+        if success {
+            stats.0 += 1;
+        } else {
+            stats.1 += 1;
+        }
     }
 
-    async fn inc_download_bytes(&mut self, bytes: u64) {
-        self.sum_bytes += bytes;
-        self.set_message().await;
+    fn inc_download_count(&mut self) {
+        self.download_count += 1;
+        self.set_message();
     }
 
-    async fn finish_one_download(&mut self) {
+    fn finish_one_download(&mut self) {
         self.finished_downloads += 1;
-        self.bar.lock().await.inc(1);
-        self.set_message().await;
-    }
-
-    async fn add_bytes(&mut self, len: usize) {
-        self.current_bytes += len;
-        self.set_message().await;
+        self.set_message();
     }
 
-    async fn set_message(&self) {
-        let bar = self.bar.lock().await;
-        bar.set_message(format!("Downloading ({current_bytes}/{sum_bytes} bytes, {dlfinished}/{dlsum} downloads finished)",
-                current_bytes = self.current_bytes,
-                sum_bytes = self.sum_bytes,
+    fn set_message(&self) {
+        self.transfers.total().set_message(format!("Downloading ({dlfinished}/{dlsum} downloads finished)",
                 dlfinished = self.finished_downloads,
                 dlsum = self.download_count));
     }
 
-    async fn success(&self) {
-        let bar = self.bar.lock().await;
-        bar.finish_with_message(format!("Succeeded {}/{} downloads", self.finished_downloads, self.download_count));
+    fn success(&self) {
+        self.transfers.total().finish_with_message(format!("Succeeded {}/{} downloads", self.finished_downloads, self.download_count));
+    }
+
+    fn error(&self) {
+        self.transfers.total().finish_with_message(format!("At least one download of {} failed", self.download_count));
+    }
+}
+
+/// A global limit for the bandwidth used by all downloads together
+///
+/// Every download reports the bytes it received and is delayed if all downloads together are
+/// faster than the limit, measured since the first download started.
+struct RateLimit {
+    bytes_per_second: u64,
+    state: Mutex<Option<(std::time::Instant, u64)>>,
+}
+
+impl RateLimit {
+    fn new(bytes_per_second: u64) -> Self {
+        RateLimit {
+            bytes_per_second,
+            state: Mutex::new(None),
+        }
+    }
+
+    async fn consume(&self, bytes: usize) {
+        let delay = {
+            let mut state = self.state.lock().await;
+            let (start, consumed) = state.get_or_insert_with(|| (std::time::Instant::now(), 0));
+            *consumed += bytes as u64;
+
+            let expected = std::time::Duration::from_secs_f64(*consumed as f64 / self.bytes_per_second as f64);
+            expected.checked_sub(start.elapsed())
+        };
+
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Parse a rate like "500K" or "2M" (bytes per second) into a number of bytes per second
+fn parse_rate(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let (number, factor) = match s.chars().last() {
+        Some('k') | Some('K') => (&s[..s.len() - 1], 1024),
+        Some('m') | Some('M') => (&s[..s.len() - 1], 1024 * 1024),
+        Some('g') | Some('G') => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s, 1),
+    };
+
+    let rate = u64::from_str(number)
+        .with_context(|| anyhow!("Parsing rate: '{}'", s))?
+        .checked_mul(factor)
+        .ok_or_else(|| anyhow!("Rate too big: '{}'", s))?;
+
+    if rate == 0 {
+        Err(anyhow!("Rate must not be zero"))
+    } else {
+        Ok(rate)
     }
+}
+
+/// Settings that apply to all downloads
+struct DownloadSettings<'a> {
+    mirrors: &'a [String],
+    timeout: Option<u64>,
+    rate_limit: Option<RateLimit>,
+}
 
-    async fn error(&self) {
-        let bar = self.bar.lock().await;
-        bar.finish_with_message(format!("At least one download of {} failed", self.download_count));
+/// Download `source` from the first of its download URLs that works
+///
+/// Mirrors are tried first, in order, the upstream URL is tried last.
+async fn download_from_any(source: &SourceEntry, settings: &DownloadSettings<'_>, progress: Arc<Mutex<ProgressWrapper>>) -> Result<()> {
+    for (mirror, url) in source.download_urls(settings.mirrors)? {
+        match mirror {
+            None => return perform_download(source, &url, settings, progress).await,
+            Some(mirror) => {
+                let r = perform_download(source, &url, settings, progress.clone()).await;
+                progress.lock().await.mirror_download_finished(&mirror, r.is_ok());
+                match r {
+                    Ok(()) => return Ok(()),
+
+                    // A partial download is only resumed from the same URL, so the next URL
+                    // starts over
+                    Err(e) => debug!("Downloading from mirror {} failed, trying next: {:?}", url, e),
+                }
+            },
+        }
     }
+
+    unreachable!("The upstream URL is always the last download URL")
 }
 
-async fn perform_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrapper>>, timeout: Option<u64>) -> Result<()> {
-    trace!("Creating: {:?}", source);
-    let file = source.create().await.with_context(|| {
+/// Download `source` from `url`
+///
+/// If there is a partial download of the source from `url`, the download is resumed with a HTTP
+/// range request. If the server does not support that, the download starts over.
+async fn perform_download(source: &SourceEntry, url: &url::Url, settings: &DownloadSettings<'_>, progress: Arc<Mutex<ProgressWrapper>>) -> Result<()> {
+    trace!("Opening: {:?}", source);
+    let (file, already_downloaded) = source.open_partial(url).await.with_context(|| {
         anyhow!(
             "Creating source file destination: {}",
-            source.path().display()
+            source.partial_path().display()
         )
     })?;
 
-    let mut file = tokio::io::BufWriter::new(file);
     let client_builder = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::limited(10));
 
-    let client_builder = if let Some(to) = timeout {
+    let client_builder = if let Some(to) = settings.timeout {
         client_builder.timeout(std::time::Duration::from_secs(to))
     } else {
         client_builder
@@ -125,43 +205,128 @@ async fn perform_download(source: &SourceEntry, progress: Arc<Mutex<ProgressWrap
 
     let client = client_builder.build().context("Building HTTP client failed")?;
 
-    let request = client.get(source.url().as_ref())
-        .build()
-        .with_context(|| anyhow!("Building request for {} failed", source.url().as_ref()))?;
+    let mut request = client.get(url.as_ref());
+    if already_downloaded > 0 {
+        debug!("Resuming download of {} at byte {}", url, already_downloaded);
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", already_downloaded));
+    }
+    let request = request.build()
+        .with_context(|| anyhow!("Building request for {} failed", url.as_ref()))?;
 
     let response = match client.execute(request).await {
         Ok(resp) => resp,
         Err(e) => {
-            return Err(e).with_context(|| anyhow!("Downloading '{}'", source.url()))
+            return Err(e).with_context(|| anyhow!("Downloading '{}'", url))
         }
     };
 
-    progress.lock()
-        .await
-        .inc_download_bytes(response.content_length().unwrap_or(0))
-        .await;
+    if already_downloaded > 0 && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial download is broken, so the next try starts from scratch
+        source.remove_partial().await?;
+        return Err(anyhow!("Cannot resume download of '{}', removed the partial download, please retry", url))
+    }
+
+    // A server that does not have the file must not result in a "successful" download of the
+    // error page
+    let response = response.error_for_status()
+        .with_context(|| anyhow!("Downloading '{}'", url))?;
+
+    if already_downloaded > 0 && response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        debug!("Server did not resume download of {}, starting over", url);
+        file.set_len(0).await?;
+    }
+    let mut file = tokio::io::BufWriter::new(file);
+
+    let bar = progress.lock().await.transfers.transfer(url.to_string())?;
+    bar.inc_length(response.content_length().unwrap_or(0));
 
     let mut stream = response.bytes_stream();
     while let Some(bytes) = stream.next().await {
-        let bytes = bytes?;
-        tokio::try_join!(
-            file.write_all(bytes.as_ref()),
-            async {
-                progress.lock()
-                    .await
-                    .add_bytes(bytes.len())
-                    .await;
-                Ok(())
-            }
-        )?;
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                // The next try (from the next URL) counts the bytes again
+                bar.restart();
+                bar.finish();
+                return Err(e.into())
+            },
+        };
+        file.write_all(bytes.as_ref()).await?;
+        bar.inc(bytes.len() as u64);
+
+        if let Some(limit) = settings.rate_limit.as_ref() {
+            limit.consume(bytes.len()).await;
+        }
     }
+    bar.finish();
+
+    file.flush().await?;
+    source.finish_partial().await
+}
+
+/// Download the upstream signature of `source`, if it has one
+///
+/// The signature is always downloaded from upstream, as it is what the mirrors are checked against.
+async fn download_signature(source: &SourceEntry, settings: &DownloadSettings<'_>) -> Result<()> {
+    let signature = match source.signature() {
+        Some(signature) => signature,
+        None => return Ok(()),
+    };
+
+    let client_builder = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::limited(10));
+    let client_builder = if let Some(to) = settings.timeout {
+        client_builder.timeout(std::time::Duration::from_secs(to))
+    } else {
+        client_builder
+    };
+    let client = client_builder.build().context("Building HTTP client failed")?;
+
+    trace!("Downloading signature {}", signature.url());
+    let bytes = client.get(signature.url().as_ref())
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .with_context(|| anyhow!("Downloading signature '{}'", signature.url()))?
+        .bytes()
+        .await
+        .with_context(|| anyhow!("Downloading signature '{}'", signature.url()))?;
 
-    file.flush()
+    let path = source.signature_path();
+    tokio::fs::write(&path, &bytes)
         .await
-        .map_err(Error::from)
-        .map(|_| ())
+        .with_context(|| anyhow!("Writing signature: {}", path.display()))
 }
 
+/// Download `source` with the mirrors and the rate limit from the configuration
+///
+/// A partial download of the source is discarded, as it might be of another version.
+pub async fn download_source(source: &SourceEntry, config: &Configuration, progressbars: &ProgressBars) -> Result<()> {
+    let settings = DownloadSettings {
+        mirrors: config.source_mirrors(),
+        timeout: None,
+        rate_limit: config.source_download_rate_limit()
+            .as_deref()
+            .map(parse_rate)
+            .transpose()?
+            .map(RateLimit::new),
+    };
+    let progressbar = Arc::new(Mutex::new(ProgressWrapper::new(progressbars.transfers()?)));
+
+    source.remove_partial().await?;
+    progressbar.lock().await.inc_download_count();
+    let r = async {
+        download_from_any(source, &settings, progressbar.clone()).await?;
+        download_signature(source, &settings).await
+    }.await;
+    progressbar.lock().await.finish_one_download();
+    if r.is_err() {
+        progressbar.lock().await.error();
+    } else {
+        progressbar.lock().await.success();
+    }
+    r
+}
 
 // Implementation of the 'source download' subcommand
 pub async fn download(
@@ -190,9 +355,29 @@ pub async fn download(
         .map(crate::commands::util::mk_package_name_regex)
         .transpose()?;
 
-    let progressbar = Arc::new(Mutex::new(ProgressWrapper::new(progressbars.bar()?)));
+    let progressbar = Arc::new(Mutex::new(ProgressWrapper::new(progressbars.transfers()?)));
 
-    let download_sema = Arc::new(tokio::sync::Semaphore::new(NUMBER_OF_MAX_CONCURRENT_DOWNLOADS));
+    let max_parallel = matches.value_of("max_parallel")
+        .map(usize::from_str)
+        .transpose()
+        .context("Parsing max-parallel argument to integer")?
+        .or(*config.source_download_max_parallel())
+        .unwrap_or(NUMBER_OF_MAX_CONCURRENT_DOWNLOADS);
+    if max_parallel == 0 {
+        return Err(anyhow!("Number of parallel downloads must not be zero"))
+    }
+    let download_sema = Arc::new(tokio::sync::Semaphore::new(max_parallel));
+
+    let settings = DownloadSettings {
+        mirrors: config.source_mirrors(),
+        timeout,
+        rate_limit: matches.value_of("rate_limit")
+            .or_else(|| config.source_download_rate_limit().as_deref())
+            .map(parse_rate)
+            .transpose()?
+            .map(RateLimit::new),
+    };
+    let settings = &settings;
 
     let r = repo.packages()
         .filter(|p| {
@@ -223,19 +408,32 @@ pub async fn download(
                     }
 
                     if source_path_exists && !force {
+                        if source.signature().is_some() && !source.signature_path().exists() {
+                            // The signature was added to the package after the source was downloaded
+                            return download_signature(&source, settings).await
+                        }
                         Err(anyhow!("Source exists: {}", source.path().display()))
                     } else {
                         if source_path_exists /* && force is implied by 'if' above*/ {
                             source.remove_file().await?;
                         }
+                        if force {
+                            source.remove_partial().await?;
+                        } else if source.link_object().await? {
+                            // Another package uses a source with the same content, which is
+                            // already in the cache
+                            trace!("Linked existing object for: {}", source.path().display());
+                            return download_signature(&source, settings).await
+                        }
 
-                        progressbar.lock().await.inc_download_count().await;
+                        progressbar.lock().await.inc_download_count();
                         {
                             let permit = download_sema.acquire_owned().await?;
-                            perform_download(&source, progressbar.clone(), timeout).await?;
+                            download_from_any(&source, settings, progressbar.clone()).await?;
+                            download_signature(&source, settings).await?;
                             drop(permit);
                         }
-                        progressbar.lock().await.finish_one_download().await;
+                        progressbar.lock().await.finish_one_download();
                         Ok(())
                     }
                 }
@@ -248,12 +446,40 @@ pub async fn download(
         .collect::<Result<()>>();
 
     if r.is_err() {
-        progressbar.lock().await.error().await;
+        progressbar.lock().await.error();
     } else {
-        progressbar.lock().await.success().await;
+        progressbar.lock().await.success();
+    }
+
+    {
+        let progressbar = progressbar.lock().await;
+        if !progressbar.mirror_stats.is_empty() {
+            let out = std::io::stdout();
+            let mut outlock = out.lock();
+            writeln!(outlock, "Mirror statistics:")?;
+            for (mirror, (succeeded, failed)) in progressbar.mirror_stats.iter() {
+                writeln!(outlock, "  {}: {} succeeded, {} failed", mirror, succeeded, failed)?;
+            }
+        }
     }
 
     debug!("r = {:?}", r);
     r
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("100").unwrap(), 100);
+        assert_eq!(parse_rate("3k").unwrap(), 3 * 1024);
+        assert_eq!(parse_rate("500K").unwrap(), 500 * 1024);
+        assert_eq!(parse_rate("2M").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_rate("1G").unwrap(), 1024 * 1024 * 1024);
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("M").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+}
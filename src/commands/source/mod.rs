@@ -12,7 +12,9 @@
 
 use std::convert::TryFrom;
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use anyhow::Context;
 use anyhow::Error;
@@ -20,10 +22,12 @@ use anyhow::Result;
 use anyhow::anyhow;
 use clap::ArgMatches;
 use colored::Colorize;
-use log::{info, trace};
-use tokio_stream::StreamExt;
+use itertools::Itertools;
+use log::{info, trace, warn};
+use futures::StreamExt;
 
 use crate::config::*;
+use crate::db::DbConnectionConfig;
 use crate::package::Package;
 use crate::package::PackageName;
 use crate::package::PackageVersionConstraint;
@@ -31,12 +35,20 @@ use crate::repository::Repository;
 use crate::source::*;
 use crate::util::progress::ProgressBars;
 
+/// How many sources are verified at the same time, to not run out of file handles
+const NUMBER_OF_MAX_CONCURRENT_VERIFICATIONS: usize = 16;
+
 mod download;
+pub(super) use download::download_source;
+mod update_hash;
+mod url_check;
 
 /// Implementation of the "source" subcommand
 pub async fn source(
+    repo_path: &Path,
     matches: &ArgMatches,
     config: &Configuration,
+    db_connection_config: DbConnectionConfig<'_>,
     repo: Repository,
     progressbars: ProgressBars,
 ) -> Result<()> {
@@ -44,13 +56,33 @@ pub async fn source(
         Some(("verify", matches)) => verify(matches, config, repo, progressbars).await,
         Some(("list-missing", matches)) => list_missing(matches, config, repo).await,
         Some(("url", matches)) => url(matches, repo).await,
+        Some(("url-check", matches)) => crate::commands::source::url_check::url_check(matches, config, repo, progressbars).await,
         Some(("download", matches)) => crate::commands::source::download::download(matches, config, repo, progressbars).await,
         Some(("of", matches)) => of(matches, config, repo).await,
+        Some(("gc", matches)) => gc(matches, config, db_connection_config, repo).await,
+        Some(("update-hash", matches)) => crate::commands::source::update_hash::update_hash(repo_path, matches, config, repo, progressbars).await,
         Some((other, _)) => Err(anyhow!("Unknown subcommand: {}", other)),
         None => Err(anyhow!("No subcommand")),
     }
 }
 
+/// The hash of the downloaded `file` of the source `source_name` of `package`, of the type of its
+/// hash in the package
+pub(super) async fn hash_of(package: &Package, source_name: &str, file: &Path) -> Result<String> {
+    let source = package.sources()
+        .get(source_name)
+        .ok_or_else(|| anyhow!("Source {} not found in {} {}", source_name, package.name(), package.version()))?;
+    let reader = tokio::fs::File::open(file)
+        .await
+        .map(tokio::io::BufReader::new)
+        .with_context(|| anyhow!("Opening {}", file.display()))?;
+    source.hash()
+        .hashtype()
+        .hash_from_reader(reader, &indicatif::ProgressBar::hidden())
+        .await
+        .map(|hash| hash.to_string())
+}
+
 pub async fn verify(
     matches: &ArgMatches,
     config: &Configuration,
@@ -87,76 +119,188 @@ pub async fn verify(
         })
         .inspect(|p| trace!("Found for verification: {} {}", p.name(), p.version()));
 
-    verify_impl(packages, &sc, &progressbars).await
+    let max_parallel = matches.value_of("max_parallel")
+        .map(usize::from_str)
+        .transpose()
+        .context("Parsing max-parallel argument to integer")?;
+
+    verify_impl(packages, &sc, &progressbars, max_parallel, false).await
+}
+
+/// The result of the verification of one source
+enum Verification {
+    Ok,
+    Missing,
+    HashMismatch(Error),
+    BadSignature(Error),
+}
+
+impl Verification {
+    fn status(&self) -> &'static str {
+        match self {
+            Verification::Ok => "ok",
+            Verification::Missing => "missing",
+            Verification::HashMismatch(_) => "mismatch",
+            Verification::BadSignature(_) => "bad signature",
+        }
+    }
 }
 
+/// Verify the sources of all `packages`, `max_parallel` (or a default number of) sources at a time
+///
+/// All sources are verified before the result is reported, with a table of the sources that
+/// are missing or do not match their hash or signature.
+///
+/// The sources that match their hash are recorded in the verification cache of `sc`. With
+/// `cached`, sources that did not change since they were recorded are not hashed again.
 pub(in crate::commands) async fn verify_impl<'a, I>(
     packages: I,
     sc: &SourceCache,
     progressbars: &ProgressBars,
+    max_parallel: Option<usize>,
+    cached: bool,
 ) -> Result<()>
 where
     I: Iterator<Item = &'a Package> + 'a,
 {
+    let max_parallel = max_parallel.unwrap_or(NUMBER_OF_MAX_CONCURRENT_VERIFICATIONS);
+    if max_parallel == 0 {
+        return Err(anyhow!("Number of parallel verifications must not be zero"))
+    }
+
     let sources = packages
         .flat_map(|p| sc.sources_for(p).into_iter())
         .collect::<Vec<_>>();
+    let cache = sc.verification_cache();
+
+    // The progress is measured in bytes hashed, because sources differ a lot in size. Every
+    // source that is being verified has its own bar, the total bar counts the finished ones.
+    let transfers = progressbars.transfers()?;
+    let total = transfers.total().clone();
+    total.set_length({
+        sources.iter()
+            .filter_map(|source| source.path().metadata().ok())
+            .map(|meta| meta.len())
+            .sum()
+    });
+    let number_of_sources = sources.len();
+    let finished = std::sync::atomic::AtomicUsize::new(0);
+    let set_message = |finished: usize| {
+        total.set_message(format!("Verifying sources ({}/{} finished)", finished, number_of_sources));
+    };
+    set_message(0);
+
+    let verifications = sources.into_iter()
+        .map(|source| {
+            let (transfers, total, finished, set_message, cache) = (&transfers, &total, &finished, &set_message, &cache);
+            async move {
+                trace!("Verifying: {}", source.path().display());
+                let verification = match source.path().metadata() {
+                    Err(_) => {
+                        trace!("Failed verifying: {}", source.path().display());
+                        Verification::Missing
+                    },
+                    Ok(meta) => {
+                        trace!("Exists: {}", source.path().display());
+                        let hash = if cached && cache.is_verified(&source, &meta) {
+                            trace!("Unchanged since the last verification: {}", source.path().display());
+                            total.inc(meta.len());
+                            Ok(())
+                        } else {
+                            let bar = transfers.transfer(source.path().display().to_string())?;
+                            bar.bar().set_length(meta.len());
+                            let hash = source.verify_hash(bar.bar()).await;
+                            bar.finish();
+                            total.inc(meta.len());
 
-    let bar = progressbars.bar()?;
-    bar.set_message("Verifying sources");
-    bar.set_length(sources.len() as u64);
-
-    let results = sources.into_iter()
-        .map(|src| (bar.clone(), src))
-        .map(|(bar, source)| async move {
-            trace!("Verifying: {}", source.path().display());
-            if source.path().exists() {
-                trace!("Exists: {}", source.path().display());
-                source.verify_hash().await.with_context(|| {
-                    anyhow!("Hash verification failed for: {}", source.path().display())
-                })?;
-
-                trace!("Success verifying: {}", source.path().display());
-                bar.inc(1);
-                Ok(())
-            } else {
-                trace!("Failed verifying: {}", source.path().display());
-                bar.inc(1);
-                Err(anyhow!("Source missing: {}", source.path().display()))
+                            match hash.as_ref() {
+                                Ok(()) => cache.insert_verified(&source, &meta),
+                                Err(_) => cache.remove(&source),
+                            }
+                            hash
+                        };
+
+                        match hash {
+                            Err(e) => Verification::HashMismatch(e),
+                            Ok(()) => match source.verify_signature().await {
+                                Err(e) => Verification::BadSignature(e),
+                                Ok(()) => {
+                                    trace!("Success verifying: {}", source.path().display());
+                                    Verification::Ok
+                                },
+                            },
+                        }
+                    },
+                };
+
+                set_message(finished.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1);
+                Ok((source, verification))
             }
-        })
-        .collect::<futures::stream::FuturesUnordered<_>>()
+        });
+
+    let results = futures::stream::iter(verifications)
+        .buffer_unordered(max_parallel)
         .collect::<Vec<Result<_>>>()
-        .await;
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
 
     info!("Verification processes finished");
+    if let Err(e) = cache.store().await {
+        warn!("Storing the verification cache failed: {:?}", e);
+    }
 
-    if results.iter().any(Result::is_err) {
-        bar.finish_with_message("Source verification failed");
+    let failed = results.iter()
+        .filter(|(_, verification)| !matches!(verification, Verification::Ok))
+        .sorted_by(|(a, _), (b, _)| {
+            (a.package_name(), a.package_version(), a.source_name())
+                .cmp(&(b.package_name(), b.package_version(), b.source_name()))
+        })
+        .collect::<Vec<_>>();
+    if failed.is_empty() {
+        total.finish_with_message("Source verification successfull");
     } else {
-        bar.finish_with_message("Source verification successfull");
+        total.finish_with_message("Source verification failed");
     }
 
-    let out = std::io::stdout();
-    let mut any_error = false;
-    for result in results {
-        if let Err(e) = result {
-            let mut outlock = out.lock();
-            any_error = true;
-            for cause in e.chain() {
-                let _ = writeln!(outlock, "Error: {}", cause.to_string().red());
-            }
-            let _ = writeln!(outlock);
-        }
-    }
+    let count = |status: &str| results.iter().filter(|(_, v)| v.status() == status).count();
+    let summary = format!("{} sources: {} ok, {} missing, {} mismatched, {} with bad signature",
+        results.len(),
+        count("ok"),
+        count("missing"),
+        count("mismatch"),
+        count("bad signature"));
 
-    if any_error {
-        Err(anyhow!(
-            "At least one package failed with source verification"
-        ))
-    } else {
-        Ok(())
+    if failed.is_empty() {
+        info!("{}", summary);
+        return Ok(())
     }
+
+    let data = failed.iter()
+        .map(|(source, verification)| {
+            let detail = match verification {
+                Verification::Ok => String::new(),
+                Verification::Missing => source.path().display().to_string(),
+                Verification::HashMismatch(e) | Verification::BadSignature(e) => format!("{:#}", e),
+            };
+            vec![
+                source.package_name().to_string(),
+                source.package_version().to_string(),
+                source.source_name().to_string(),
+                verification.status().to_string(),
+                detail,
+            ]
+        })
+        .collect::<Vec<_>>();
+    crate::commands::util::display_data(
+        crate::commands::util::mk_header(vec!["Package", "Version", "Source", "Status", "Detail"]),
+        data,
+        false,
+    )?;
+
+    let out = std::io::stdout();
+    let _ = writeln!(out.lock(), "{}", summary.red());
+    Err(anyhow!("Source verification failed for {} of {} sources", failed.len(), results.len()))
 }
 
 pub async fn list_missing(_: &ArgMatches, config: &Configuration, repo: Repository) -> Result<()> {
@@ -261,3 +405,126 @@ async fn of(
         })
         .map(|_| ())
 }
+
+/// Implementation of the "source gc" subcommand
+///
+/// Removes all files from the source cache that are not referenced by any package in the
+/// repository, i.e. sources of packages that were removed and objects no package links to anymore.
+async fn gc(
+    matches: &ArgMatches,
+    config: &Configuration,
+    db_connection_config: DbConnectionConfig<'_>,
+    repo: Repository,
+) -> Result<()> {
+    let dry_run = matches.is_present("dry_run");
+    let sc = SourceCache::new(config.source_cache_root().clone());
+
+    let out = std::io::stdout();
+    let mut outlock = out.lock();
+    let (removed, freed) = remove_unreferenced(&sc, &repo, dry_run, &mut outlock)?;
+
+    if dry_run {
+        writeln!(outlock, "Would remove {} files, freeing {} bytes", removed, freed)?;
+    } else {
+        writeln!(outlock, "Removed {} files, freed {} bytes", removed, freed)?;
+        let conn = db_connection_config.establish_connection()?;
+        crate::db::models::AuditEntry::create(&conn, config, "source-gc", serde_json::json!({
+            "removed_files": removed,
+            "freed_bytes": freed,
+        }))?;
+    }
+    Ok(())
+}
+
+/// Removes all files from the source cache `sc` that are not referenced by any package in `repo`
+///
+/// Returns the number of removed files and the number of bytes freed.
+fn remove_unreferenced(sc: &SourceCache, repo: &Repository, dry_run: bool, out: &mut impl Write) -> Result<(usize, u64)> {
+    let referenced = repo.packages()
+        .flat_map(|p| sc.sources_for(p).into_iter())
+        .flat_map(|source| vec![source.path(), source.partial_path(), source.partial_origin_path(), source.object_path(), source.signature_path()])
+        .chain(std::iter::once(sc.verification_cache_path()))
+        .collect::<std::collections::HashSet<PathBuf>>();
+
+    let (mut removed, mut freed) = (0usize, 0u64);
+
+    // Contents first, so directories that became empty can be removed as well
+    for entry in walkdir::WalkDir::new(sc.root()).min_depth(1).contents_first(true) {
+        let entry = entry?;
+        let path = entry.path();
+
+        if entry.file_type().is_dir() {
+            let is_empty = std::fs::read_dir(path)?.next().is_none();
+            if is_empty && !dry_run {
+                trace!("Removing empty directory: {}", path.display());
+                std::fs::remove_dir(path)
+                    .with_context(|| anyhow!("Removing directory: {}", path.display()))?;
+            }
+            continue
+        }
+
+        if referenced.contains(path) {
+            continue
+        }
+
+        // Links do not free any space, the objects they point to do
+        if entry.file_type().is_file() {
+            freed += entry.metadata()?.len();
+        }
+        removed += 1;
+
+        if dry_run {
+            writeln!(out, "Would remove: {}", path.display())?;
+        } else {
+            trace!("Removing: {}", path.display());
+            std::fs::remove_file(path)
+                .with_context(|| anyhow!("Removing file: {}", path.display()))?;
+        }
+    }
+
+    Ok((removed, freed))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::package::Dependencies;
+    use crate::package::Source;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+
+    #[test]
+    fn test_gc_keeps_signatures() {
+        let source: Source = toml::from_str(indoc::indoc!(r#"
+            url = "https://example.com/a-1.0.tar.gz"
+            hash = { type = "sha256", hash = "123" }
+            download_manually = false
+            signature = { url = "https://example.com/a-1.0.tar.gz.asc", keyring = "keys/a.gpg" }
+        "#)).unwrap();
+        let mut sources = HashMap::new();
+        sources.insert(String::from("src"), source);
+        let pkg = Package::new(pname("a"), pversion("1.0"), false, sources, Dependencies::empty());
+
+        let root = tempfile::tempdir().unwrap();
+        let sc = SourceCache::new(root.path().to_path_buf());
+        let entry = sc.sources_for(&pkg).pop().unwrap();
+        std::fs::create_dir_all(entry.path().parent().unwrap()).unwrap();
+        std::fs::write(entry.path(), b"source").unwrap();
+        std::fs::write(entry.signature_path(), b"signature").unwrap();
+        let unreferenced = entry.path().with_file_name("old.source.sig");
+        std::fs::write(&unreferenced, b"old signature").unwrap();
+
+        let mut repo = BTreeMap::new();
+        repo.insert((pname("a"), pversion("1.0")), pkg);
+        let repo = Repository::from(repo);
+
+        let mut out = vec![];
+        assert_eq!(remove_unreferenced(&sc, &repo, false, &mut out).unwrap(), (1, 13));
+        assert!(entry.path().is_file());
+        assert!(entry.signature_path().is_file());
+        assert!(!unreferenced.exists());
+    }
+}
@@ -0,0 +1,193 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'source update-hash' subcommand
+
+use std::convert::TryFrom;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use clap::ArgMatches;
+use log::{error, info};
+
+use crate::config::*;
+use crate::package::PackageName;
+use crate::package::PackageVersionConstraint;
+use crate::repository::Repository;
+use crate::source::*;
+use crate::util::progress::ProgressBars;
+
+/// Implementation of the "source update-hash" subcommand
+///
+/// The sources are downloaded again and the hashes in the definition files of the packages are
+/// replaced with the hashes of the downloads, of the hash type the package declares.
+pub async fn update_hash(
+    repo_path: &Path,
+    matches: &ArgMatches,
+    config: &Configuration,
+    repo: Repository,
+    progressbars: ProgressBars,
+) -> Result<()> {
+    let missing_only = matches.is_present("missing");
+    let pname = matches
+        .value_of("package_name")
+        .map(String::from)
+        .map(PackageName::from)
+        .map(|name| repo.resolve_name(&name))
+        .transpose()?;
+    let pvers = matches
+        .value_of("package_version")
+        .map(PackageVersionConstraint::try_from)
+        .transpose()?;
+
+    let sc = SourceCache::new(config.source_cache_root().clone());
+    let sources = repo.packages()
+        .filter(|p| pname.as_ref().map(|n| p.name() == n).unwrap_or(true))
+        .filter(|p| pvers.as_ref().map(|v| v.matches(p.version())).unwrap_or(true))
+        .flat_map(|p| sc.sources_for(p).into_iter().map(move |source| (p, source)))
+        .filter(|(p, source)| {
+            !missing_only || p.sources()
+                .get(source.source_name())
+                .map(|s| s.hash().value().to_string().is_empty())
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+
+    if sources.is_empty() {
+        if missing_only {
+            info!("No sources without a hash found");
+            return Ok(())
+        }
+        return Err(anyhow!("No package found"))
+    }
+
+    let mut out = std::io::stdout();
+    let mut failed = 0;
+    for (package, source) in sources {
+        let definition_file = package.definition_file()
+            .as_ref()
+            .ok_or_else(|| anyhow!("Definition file of {} {} is not known", package.name(), package.version()))?;
+
+        // Sources that are downloaded manually are hashed as they are in the cache
+        if source.download_manually() {
+            if !source.path().exists() {
+                error!("Source {} of {} {} has to be downloaded manually to {}",
+                    source.source_name(), package.name(), package.version(), source.path().display());
+                failed += 1;
+                continue
+            }
+        } else if let Err(e) = super::download_source(&source, config, &progressbars).await {
+            error!("Downloading source {} of {} {}: {:?}", source.source_name(), package.name(), package.version(), e);
+            failed += 1;
+            continue
+        }
+
+        let old_hash = package.sources()
+            .get(source.source_name())
+            .map(|s| s.hash().value().to_string())
+            .unwrap_or_default();
+        let hash = super::hash_of(package, source.source_name(), &source.path()).await?;
+        if hash == old_hash {
+            writeln!(out, "{} {}: hash of source '{}' is up to date", package.name(), package.version(), source.source_name())?;
+            continue
+        }
+
+        let path = repo_path.join(definition_file);
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| anyhow!("Reading {}", definition_file.display()))?;
+        let content = set_source_hash(&content, source.source_name(), &old_hash, &hash)
+            .with_context(|| anyhow!("Updating {}", definition_file.display()))?;
+        tokio::fs::write(&path, content)
+            .await
+            .with_context(|| anyhow!("Writing {}", definition_file.display()))?;
+        writeln!(out, "{} {}: updated hash of source '{}' in {}", package.name(), package.version(), source.source_name(), definition_file.display())?;
+    }
+
+    if failed > 0 {
+        return Err(anyhow!("The hash of {} sources could not be updated", failed))
+    }
+    Ok(())
+}
+
+/// The contents of the definition file `content` with the hash `old` of the source `source_name`
+/// replaced by `new`
+///
+/// The hash is only replaced in the table of the source, `[sources.NAME]` (with an inline table
+/// or a dotted key for the hash) or `[sources.NAME.hash]`. The rest of the file, like comments and
+/// formatting, is kept as it is.
+fn set_source_hash(content: &str, source_name: &str, old: &str, new: &str) -> Result<String> {
+    let header_re = regex::Regex::new(r#"(?m)^\s*\[\[?([^\[\]\n]*)\]\]?"#)?;
+    let hash_re = regex::Regex::new(&format!(r#"\bhash\s*=\s*(["']){}(["'])"#, regex::escape(old)))?;
+
+    let headers = header_re.captures_iter(content).collect::<Vec<_>>();
+    for (i, header) in headers.iter().enumerate() {
+        let key = header.get(1)
+            .unwrap() // always matches
+            .as_str()
+            .split('.')
+            .map(|part| part.trim().trim_matches(|c| c == '"' || c == '\''))
+            .collect::<Vec<_>>();
+        if key != ["sources", source_name] && key != ["sources", source_name, "hash"] {
+            continue
+        }
+
+        let start = header.get(0).unwrap().end(); // always matches
+        let end = headers.get(i + 1).map(|h| h.get(0).unwrap().start()).unwrap_or(content.len());
+        if let Some(value) = hash_re.captures(&content[start..end]) {
+            let value_start = start + value.get(1).unwrap().end(); // always matches
+            let value_end = start + value.get(2).unwrap().start(); // always matches
+            return Ok(format!("{}{}{}", &content[..value_start], new, &content[value_end..]))
+        }
+    }
+
+    Err(anyhow!("The hash '{}' of source '{}' is not set in the file", old, source_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_source_hash() {
+        let content = indoc::indoc!(r#"
+            version = "1.0"
+
+            [sources.src]
+            url = "https://example.com/src-1.0.tar.gz"
+            # the hash of the release tarball
+            hash.type = "sha256"
+            hash.hash = "0000"
+
+            [sources.patches]
+            url = "https://example.com/patches-1.0.tar.gz"
+            hash = { type = "sha256", hash = "0000" }
+
+            [sources.docs.hash]
+            type = "sha1"
+            hash = ''
+        "#);
+
+        let updated = set_source_hash(content, "patches", "0000", "abcd").unwrap();
+        assert_eq!(updated, content.replace(r#"hash = { type = "sha256", hash = "0000" }"#, r#"hash = { type = "sha256", hash = "abcd" }"#));
+
+        let updated = set_source_hash(content, "src", "0000", "abcd").unwrap();
+        assert_eq!(updated, content.replace(r#"hash.hash = "0000""#, r#"hash.hash = "abcd""#));
+
+        let updated = set_source_hash(content, "docs", "", "abcd").unwrap();
+        assert_eq!(updated, content.replace("hash = ''", "hash = 'abcd'"));
+
+        assert!(set_source_hash(content, "docs", "0000", "abcd").is_err());
+        assert!(set_source_hash(content, "other", "0000", "abcd").is_err());
+    }
+}
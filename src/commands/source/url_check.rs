@@ -0,0 +1,255 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'source url-check' subcommand
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use clap::ArgMatches;
+use futures::StreamExt;
+use log::{debug, trace};
+use url::Url;
+
+use crate::config::*;
+use crate::package::PackageName;
+use crate::package::PackageVersionConstraint;
+use crate::repository::Repository;
+use crate::source::*;
+use crate::util::progress::ProgressBars;
+
+/// How many URLs are checked at the same time, if not configured otherwise
+const NUMBER_OF_MAX_CONCURRENT_CHECKS: usize = 16;
+
+/// What checking the URL of a source found
+#[derive(Debug, Eq, PartialEq)]
+enum UrlState {
+    Ok,
+
+    /// The URL redirects to another URL, which works
+    Redirect(Url),
+
+    /// The size the server reports differs from the size of the source in the cache
+    SizeMismatch { remote: u64, local: u64 },
+
+    /// The URL does not work, with the reason
+    Dead(String),
+}
+
+impl UrlState {
+    fn name(&self) -> &'static str {
+        match self {
+            UrlState::Ok => "ok",
+            UrlState::Redirect(_) => "redirect",
+            UrlState::SizeMismatch { .. } => "size-mismatch",
+            UrlState::Dead(_) => "dead",
+        }
+    }
+
+    fn detail(&self) -> String {
+        match self {
+            UrlState::Ok => String::new(),
+            UrlState::Redirect(url) => format!("Redirects to {}", url),
+            UrlState::SizeMismatch { remote, local } => format!("Remote has {} bytes, cached source {} bytes", remote, local),
+            UrlState::Dead(reason) => reason.clone(),
+        }
+    }
+
+    /// Whether the source cannot be downloaded (as it is) from the URL
+    fn is_failure(&self) -> bool {
+        matches!(self, UrlState::SizeMismatch { .. } | UrlState::Dead(_))
+    }
+}
+
+/// Find out the state of `url` from what the server responded
+///
+/// A size mismatch is more important than a redirect, because it means the source changed.
+fn classify(url: &Url, final_url: &Url, remote_size: Option<u64>, local_size: Option<u64>) -> UrlState {
+    match (remote_size, local_size) {
+        (Some(remote), Some(local)) if remote != local => UrlState::SizeMismatch { remote, local },
+        _ if url != final_url => UrlState::Redirect(final_url.clone()),
+        _ => UrlState::Ok,
+    }
+}
+
+/// The complete size of the file from a "Content-Range" header like "bytes 0-0/1234"
+fn size_from_content_range(value: &str) -> Option<u64> {
+    value.rsplit('/').next().and_then(|size| u64::from_str(size.trim()).ok())
+}
+
+/// Check `url`, with a HEAD request or, if the server does not answer those successfully, with a
+/// GET request for the first byte
+async fn check_url(client: &reqwest::Client, url: &Url, local_size: Option<u64>) -> UrlState {
+    let head = match client.head(url.as_ref()).send().await {
+        Ok(response) => response,
+        Err(e) => return UrlState::Dead(e.to_string()),
+    };
+
+    let response = if head.status().is_success() {
+        head
+    } else {
+        debug!("HEAD {} returned {}, trying ranged GET", url, head.status());
+        let get = client.get(url.as_ref())
+            .header(reqwest::header::RANGE, "bytes=0-0")
+            .send()
+            .await;
+
+        match get {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => return UrlState::Dead(format!("HTTP {}", response.status())),
+            Err(e) => return UrlState::Dead(e.to_string()),
+        }
+    };
+
+    let remote_size = if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+        response.headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(size_from_content_range)
+    } else {
+        response.content_length()
+    };
+    trace!("{}: {} ({:?} bytes, final URL {})", url, response.status(), remote_size, response.url());
+
+    classify(url, response.url(), remote_size, local_size)
+}
+
+/// Implementation of the 'source url-check' subcommand
+pub async fn url_check(
+    matches: &ArgMatches,
+    config: &Configuration,
+    repo: Repository,
+    progressbars: ProgressBars,
+) -> Result<()> {
+    let sc = SourceCache::new(config.source_cache_root().clone());
+    let pname = matches
+        .value_of("package_name")
+        .map(String::from)
+        .map(PackageName::from);
+    let pvers = matches
+        .value_of("package_version")
+        .map(PackageVersionConstraint::try_from)
+        .transpose()?;
+    let matching_regexp = matches.value_of("matching")
+        .map(crate::commands::util::mk_package_name_regex)
+        .transpose()?;
+
+    let timeout = matches.value_of("timeout")
+        .map(u64::from_str)
+        .transpose()
+        .context("Parsing timeout argument to integer")?;
+    let max_parallel = matches.value_of("max_parallel")
+        .map(usize::from_str)
+        .transpose()
+        .context("Parsing max-parallel argument to integer")?
+        .unwrap_or(NUMBER_OF_MAX_CONCURRENT_CHECKS);
+    if max_parallel == 0 {
+        return Err(anyhow!("Number of parallel checks must not be zero"))
+    }
+
+    let client = {
+        let builder = reqwest::Client::builder().redirect(reqwest::redirect::Policy::limited(10));
+        let builder = match timeout {
+            Some(to) => builder.timeout(std::time::Duration::from_secs(to)),
+            None => builder,
+        };
+        builder.build().context("Building HTTP client failed")?
+    };
+
+    let sources = repo.packages()
+        .filter(|p| pname.as_ref().map(|n| p.name() == n).unwrap_or(true))
+        .filter(|p| pvers.as_ref().map(|v| v.matches(p.version())).unwrap_or(true))
+        .filter(|p| matching_regexp.as_ref().map(|r| r.is_match(p.name())).unwrap_or(true))
+        .flat_map(|p| sc.sources_for(p).into_iter().map(move |source| (p, source)))
+        .filter(|(_, source)| !source.download_manually())
+        .collect::<Vec<_>>();
+
+    let bar = progressbars.bar()?;
+    bar.set_message("Checking source URLs");
+    bar.set_length(sources.len() as u64);
+
+    let checks = sources.into_iter()
+        .map(|(package, source)| {
+            let (client, bar) = (&client, bar.clone());
+            async move {
+                let local_size = source.path().metadata().ok().map(|m| m.len());
+                let state = check_url(client, source.url(), local_size).await;
+                bar.inc(1);
+                (package, source, state)
+            }
+        });
+
+    let results = futures::stream::iter(checks)
+        .buffer_unordered(max_parallel)
+        .collect::<Vec<_>>()
+        .await;
+
+    let failures = results.iter().filter(|(_, _, state)| state.is_failure()).count();
+    if failures == 0 {
+        bar.finish_with_message(format!("Checked {} source URLs", results.len()));
+    } else {
+        bar.finish_with_message(format!("{} of {} source URLs failed", failures, results.len()));
+    }
+
+    let show_all = matches.is_present("all");
+    let mut rows = results.into_iter()
+        .filter(|(_, _, state)| show_all || *state != UrlState::Ok)
+        .map(|(package, source, state)| {
+            vec![
+                package.name().to_string(),
+                package.version().to_string(),
+                source.source_name().to_string(),
+                source.url().to_string(),
+                state.name().to_string(),
+                state.detail(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    rows.sort();
+
+    if matches.is_present("json") {
+        crate::commands::util::display_data_as_json(&["package", "version", "source", "url", "state", "detail"], rows)?;
+    } else {
+        let header = crate::commands::util::mk_header(["Package", "Version", "Source", "URL", "State", "Detail"].to_vec());
+        crate::commands::util::display_data(header, rows, matches.is_present("csv"))?;
+    }
+
+    if failures == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!("{} source URLs are dead or do not match the cached sources", failures))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let url = Url::parse("https://example.com/a-1.0.tar.gz").unwrap();
+        let moved = Url::parse("https://mirror.example.com/a-1.0.tar.gz").unwrap();
+
+        assert_eq!(classify(&url, &url, Some(10), Some(10)), UrlState::Ok);
+        assert_eq!(classify(&url, &url, None, Some(10)), UrlState::Ok);
+        assert_eq!(classify(&url, &moved, Some(10), None), UrlState::Redirect(moved.clone()));
+        assert_eq!(classify(&url, &moved, Some(11), Some(10)), UrlState::SizeMismatch { remote: 11, local: 10 });
+        assert!(!UrlState::Redirect(moved).is_failure());
+    }
+
+    #[test]
+    fn test_size_from_content_range() {
+        assert_eq!(size_from_content_range("bytes 0-0/1234"), Some(1234));
+        assert_eq!(size_from_content_range("bytes 0-0/*"), None);
+    }
+}
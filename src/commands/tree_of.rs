@@ -17,8 +17,10 @@ use anyhow::Result;
 use clap::ArgMatches;
 use resiter::AndThen;
 
+use crate::config::Configuration;
 use crate::package::Dag;
 use crate::package::PackageName;
+use crate::package::PackageOptions;
 use crate::package::PackageVersionConstraint;
 use crate::package::condition::ConditionData;
 use crate::repository::Repository;
@@ -28,12 +30,15 @@ use crate::util::docker::ImageName;
 /// Implementation of the "tree_of" subcommand
 pub async fn tree_of(
     matches: &ArgMatches,
+    config: &Configuration,
     repo: Repository,
 ) -> Result<()> {
     let pname = matches
         .value_of("package_name")
         .map(String::from)
-        .map(PackageName::from);
+        .map(PackageName::from)
+        .map(|name| repo.resolve_name(&name))
+        .transpose()?;
     let pvers = matches
         .value_of("package_version")
         .map(PackageVersionConstraint::try_from)
@@ -50,20 +55,27 @@ pub async fn tree_of(
         .map(crate::util::env::parse_to_env)
         .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?;
 
+    let options = matches
+        .values_of("option")
+        .unwrap_or_default()
+        .map(crate::package::parse_option_setting)
+        .collect::<Result<PackageOptions>>()?;
+
     let condition_data = ConditionData {
         image_name: image_name.as_ref(),
         env: &additional_env,
+        options: &options,
     };
 
     repo.packages()
-        .filter(|p| pname.as_ref().map(|n| p.name() == n).unwrap_or(true))
+        .filter(|p| pname.as_ref().map(|n| p.has_name(n)).unwrap_or(true))
         .filter(|p| {
             pvers
                 .as_ref()
                 .map(|v| v.matches(p.version()))
                 .unwrap_or(true)
         })
-        .map(|package| Dag::for_root_package(package.clone(), &repo, None, &condition_data))
+        .map(|package| Dag::for_root_package(package.clone(), &repo, None, &condition_data, config.resolver()))
         .and_then_ok(|tree| {
             let stdout = std::io::stdout();
             let mut outlock = stdout.lock();
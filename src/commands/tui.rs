@@ -0,0 +1,480 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'tui' subcommand
+//!
+//! The dashboard shows the running submits and their jobs from the database, the utilization of
+//! the endpoints and, if the log socket of a running build is given, the phases, states and logs
+//! of its jobs as they run.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use clap::ArgMatches;
+use colored::Colorize;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use futures::StreamExt;
+use itertools::Itertools;
+use log::debug;
+use uuid::Uuid;
+
+use crate::config::Configuration;
+use crate::endpoint::Endpoint;
+use crate::log::LiveLogEvent;
+use crate::log::LogItem;
+use crate::schema;
+use crate::util::terminal::fit;
+use crate::util::terminal::usage_bar;
+use crate::util::terminal::Key;
+use crate::util::terminal::RawTerminal;
+
+/// The number of log lines that are kept per job
+const LOG_LINES: usize = 5000;
+
+/// How long to wait before connecting to the log socket again
+const LOG_SOCKET_RETRY_SECS: u64 = 5;
+
+/// Implementation of the "tui" subcommand
+pub async fn tui(matches: &ArgMatches, config: &Configuration, conn: PgConnection) -> Result<()> {
+    let refresh = matches.value_of("refresh").map(str::parse::<u64>).unwrap()?; // has a default
+    let refresh = Duration::from_secs(std::cmp::max(refresh, 1));
+
+    let endpoints = connect(config).await;
+    let (live_sender, mut live) = tokio::sync::mpsc::unbounded_channel();
+    if let Some(addr) = matches.value_of("log_socket") {
+        tokio::spawn(receive_live_log(addr.to_string(), live_sender));
+    }
+
+    let terminal = RawTerminal::enter()?;
+    let mut keys = crate::util::terminal::keys()?;
+    let mut interval = tokio::time::interval(refresh);
+    let mut dashboard = Dashboard::default();
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => dashboard.refresh(&conn, &endpoints, refresh).await,
+            Some(message) = live.recv() => dashboard.receive(message),
+            key = keys.recv() => match key {
+                Some(Key::Char('q')) | Some(Key::Interrupt) | None => break,
+                Some(key) => dashboard.handle_key(key, &conn),
+            },
+        }
+
+        terminal.draw(&dashboard.render(terminal.size()))?;
+    }
+    Ok(())
+}
+
+/// Connect to all configured endpoints, each on its own so that an unreachable endpoint does
+/// not keep the dashboard from showing the others
+async fn connect(config: &Configuration) -> Vec<(String, Result<Arc<Endpoint>>)> {
+    let names = config.docker().endpoints().keys().cloned().sorted();
+    futures::future::join_all(names.map(|name| async move {
+        let endpoint = super::endpoint::connect_to_endpoints(config, std::slice::from_ref(&name))
+            .await
+            .and_then(|eps| eps.into_iter().next().ok_or_else(|| anyhow!("Endpoint not found: {}", name)));
+        (name.as_ref().to_string(), endpoint)
+    }))
+    .await
+}
+
+/// A message from the task that receives the live log
+enum LiveMessage {
+    Connected(String),
+    Event(Result<LiveLogEvent>),
+    Disconnected(Error),
+}
+
+/// Receive the live log from the log socket on `addr`, connecting again whenever the connection
+/// fails, until the dashboard is closed
+async fn receive_live_log(addr: String, sender: tokio::sync::mpsc::UnboundedSender<LiveMessage>) {
+    let url = format!("http://{}/", addr);
+    loop {
+        let result = async {
+            let response = reqwest::get(&url).await?.error_for_status()?;
+            if sender.send(LiveMessage::Connected(addr.clone())).is_err() {
+                return Ok(())
+            }
+
+            let mut stream = response.bytes_stream();
+            let mut buffer = vec![];
+            while let Some(chunk) = stream.next().await {
+                buffer.extend_from_slice(&chunk?);
+                while let Some(event) = LiveLogEvent::next_from_stream(&mut buffer) {
+                    if sender.send(LiveMessage::Event(event)).is_err() {
+                        return Ok(())
+                    }
+                }
+            }
+            Err(anyhow!("Log socket {} closed the connection", addr))
+        }
+        .await;
+
+        match result {
+            Ok(()) => return,
+            Err(e) => {
+                debug!("Receiving the live log failed: {:?}", e);
+                if sender.send(LiveMessage::Disconnected(e)).is_err() {
+                    return
+                }
+            },
+        }
+        tokio::time::sleep(Duration::from_secs(LOG_SOCKET_RETRY_SECS)).await;
+    }
+}
+
+struct SubmitRow {
+    uuid: Uuid,
+    submit_time: NaiveDateTime,
+    package: String,
+    running: usize,
+    finished: usize,
+}
+
+struct JobRow {
+    uuid: Uuid,
+    submit: Uuid,
+
+    /// Only known for finished jobs, running ones are only in the live log
+    package: Option<String>,
+    endpoint: Option<String>,
+    running: bool,
+}
+
+/// What is known about a job from the live log
+#[derive(Default)]
+struct LiveJob {
+    package: String,
+    phase: Option<String>,
+    state: Option<std::result::Result<(), String>>,
+    lines: VecDeque<String>,
+}
+
+impl LiveJob {
+    fn push_line(&mut self, line: String) {
+        if self.lines.len() == LOG_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+}
+
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+enum Focus {
+    #[default]
+    Jobs,
+    Log,
+}
+
+#[derive(Default)]
+struct Dashboard {
+    submits: Vec<SubmitRow>,
+    jobs: Vec<JobRow>,
+
+    /// The endpoints as (name, running containers and maximum number of jobs)
+    endpoints: Vec<(String, Result<(usize, usize)>)>,
+    live: HashMap<Uuid, LiveJob>,
+
+    selected: usize,
+    focus: Focus,
+
+    /// How many lines the log is scrolled up from its end
+    scroll: usize,
+    status: String,
+}
+
+impl Dashboard {
+    async fn refresh(&mut self, conn: &PgConnection, endpoints: &[(String, Result<Arc<Endpoint>>)], timeout: Duration) {
+        let selected = self.jobs.get(self.selected).map(|job| job.uuid);
+        match load_jobs(conn) {
+            Ok((submits, jobs)) => {
+                self.submits = submits;
+                self.jobs = jobs;
+            },
+            Err(e) => self.status = format!("Loading the running jobs failed: {:#}", e),
+        }
+
+        // The selection stays on the same job
+        self.selected = selected
+            .and_then(|uuid| self.jobs.iter().position(|job| job.uuid == uuid))
+            .unwrap_or_else(|| std::cmp::min(self.selected, self.jobs.len().saturating_sub(1)));
+
+        self.endpoints = futures::future::join_all(endpoints.iter().map(|(name, endpoint)| async move {
+            let usage = match endpoint {
+                Ok(endpoint) => tokio::time::timeout(timeout, endpoint.number_of_running_containers())
+                    .await
+                    .map_err(|_| anyhow!("Timeout"))
+                    .and_then(|r| r)
+                    .map(|running| (running, endpoint.num_max_jobs())),
+                Err(e) => Err(anyhow!("{:#}", e)),
+            };
+            (name.clone(), usage)
+        }))
+        .await;
+    }
+
+    fn receive(&mut self, message: LiveMessage) {
+        let event = match message {
+            LiveMessage::Connected(addr) => {
+                self.status = format!("Connected to log socket {}", addr);
+                return
+            },
+            LiveMessage::Disconnected(e) => {
+                self.status = format!("{:#}", e);
+                return
+            },
+            LiveMessage::Event(Err(e)) => {
+                self.status = format!("{:#}", e);
+                return
+            },
+            LiveMessage::Event(Ok(event)) => event,
+        };
+
+        let job = self.live.entry(event.job).or_default();
+        job.package = format!("{} {}", event.package_name, event.package_version);
+        match event.item() {
+            Ok(LogItem::Line(line)) | Ok(LogItem::StderrLine(line)) => job.push_line(String::from_utf8_lossy(&line).to_string()),
+            Ok(LogItem::Progress(_)) => {},
+            Ok(LogItem::CurrentPhase(phase)) => {
+                job.push_line(event.data);
+                job.phase = Some(phase);
+            },
+            Ok(LogItem::State(state)) => {
+                job.push_line(event.data);
+                job.state = Some(state);
+            },
+            Err(e) => self.status = format!("{:#}", e),
+        }
+    }
+
+    fn handle_key(&mut self, key: Key, conn: &PgConnection) {
+        match (key, self.focus) {
+            (Key::Tab, Focus::Jobs) | (Key::Enter, _) => {
+                self.focus = Focus::Log;
+                self.load_log(conn);
+            },
+            (Key::Tab, Focus::Log) | (Key::Esc, _) => self.focus = Focus::Jobs,
+
+            (Key::Char('j'), Focus::Jobs) | (Key::Down, Focus::Jobs) => {
+                self.selected = std::cmp::min(self.selected + 1, self.jobs.len().saturating_sub(1));
+                self.scroll = 0;
+            },
+            (Key::Char('k'), Focus::Jobs) | (Key::Up, Focus::Jobs) => {
+                self.selected = self.selected.saturating_sub(1);
+                self.scroll = 0;
+            },
+
+            (Key::Char('j'), Focus::Log) | (Key::Down, Focus::Log) => self.scroll = self.scroll.saturating_sub(1),
+            (Key::Char('k'), Focus::Log) | (Key::Up, Focus::Log) => self.scroll += 1,
+            (Key::PageDown, _) => self.scroll = self.scroll.saturating_sub(10),
+            (Key::PageUp, _) => self.scroll += 10,
+            (Key::Char('G'), _) => self.scroll = 0,
+            _ => {},
+        }
+    }
+
+    /// Load the log of the selected job from the database, if it finished and is not in the live
+    /// log
+    fn load_log(&mut self, conn: &PgConnection) {
+        let job = match self.jobs.get(self.selected) {
+            Some(job) if !job.running && !self.live.contains_key(&job.uuid) => job,
+            _ => return,
+        };
+
+        let log = schema::jobs::table
+            .filter(schema::jobs::uuid.eq(job.uuid))
+            .select(schema::jobs::log_text)
+            .first::<String>(conn)
+            .map_err(anyhow::Error::from)
+            .and_then(|log| crate::log::resolve_log(&log).map(|log| log.into_owned()));
+
+        match log {
+            Ok(log) => {
+                let mut live = LiveJob {
+                    package: job.package.clone().unwrap_or_default(),
+                    ..LiveJob::default()
+                };
+                log.lines().for_each(|line| live.push_line(line.to_string()));
+                self.live.insert(job.uuid, live);
+            },
+            Err(e) => self.status = format!("Loading the log of {} failed: {}", job.uuid, e),
+        }
+    }
+
+    fn render(&self, (rows, cols): (usize, usize)) -> Vec<String> {
+        let mut lines = vec![];
+        let title = |s: &str| fit(s, cols).bold().to_string();
+
+        let running = self.jobs.iter().filter(|job| job.running).count();
+        lines.push(title(&format!("butido: {} running submits, {} running jobs", self.submits.len(), running)));
+        lines.push(String::new());
+
+        lines.push(title("Submits"));
+        if self.submits.is_empty() {
+            lines.push(fit("  No running submits", cols));
+        }
+        for submit in self.submits.iter() {
+            lines.push(fit(&format!("  {}  {}  {:<30}  {} running, {} finished",
+                submit.uuid,
+                submit.submit_time.format("%Y-%m-%d %H:%M:%S"),
+                submit.package,
+                submit.running,
+                submit.finished), cols));
+        }
+        lines.push(String::new());
+
+        lines.push(title("Jobs"));
+        lines.push(fit(&format!("  {:<36}  {:<36}  {:<30}  {:<16}  {:<16}  State", "Job", "Submit", "Package", "Endpoint", "Phase"), cols));
+
+        // At most a third of the screen for the jobs, scrolled so that the selected one is visible
+        let job_rows = std::cmp::max(3, rows / 3);
+        let first = (self.selected + 1).saturating_sub(job_rows);
+        for (i, job) in self.jobs.iter().enumerate().skip(first).take(job_rows) {
+            let live = self.live.get(&job.uuid);
+            let state = match live.and_then(|l| l.state.as_ref()) {
+                Some(Ok(())) => String::from("success"),
+                Some(Err(e)) => format!("failed: {}", e),
+                None if job.running => String::from("running"),
+                None => String::from("finished"),
+            };
+
+            let line = format!("{} {:<36}  {:<36}  {:<30}  {:<16}  {:<16}  {}",
+                if i == self.selected { ">" } else { " " },
+                job.uuid,
+                job.submit,
+                job.package.as_deref().or_else(|| live.map(|l| l.package.as_str())).unwrap_or("-"),
+                job.endpoint.as_deref().unwrap_or("-"),
+                live.and_then(|l| l.phase.as_deref()).unwrap_or("-"),
+                state);
+
+            let line = fit(&line, cols);
+            if i == self.selected && self.focus == Focus::Jobs {
+                lines.push(line.reversed().to_string());
+            } else {
+                lines.push(line);
+            }
+        }
+        lines.push(String::new());
+
+        lines.push(title("Endpoints"));
+        for (name, usage) in self.endpoints.iter() {
+            let line = match usage {
+                Ok((running, max)) => format!("  {:<20}  {}  {}/{}", name, usage_bar(*running, *max, 20), running, max),
+                Err(e) => format!("  {:<20}  {}", name, e),
+            };
+            lines.push(fit(&line, cols));
+        }
+        lines.push(String::new());
+
+        // The log takes the rest of the screen, except for the status line
+        let selected = self.jobs.get(self.selected);
+        let log_title = match selected {
+            Some(job) => format!("Log of {}{}", job.uuid, if self.focus == Focus::Log { " (focused)" } else { "" }),
+            None => String::from("Log"),
+        };
+        lines.push(title(&log_title));
+
+        let log_rows = rows.saturating_sub(lines.len() + 1);
+        match selected.and_then(|job| self.live.get(&job.uuid)) {
+            Some(live) => {
+                let end = live.lines.len().saturating_sub(self.scroll);
+                let start = end.saturating_sub(log_rows);
+                live.lines.range(start..end).for_each(|line| lines.push(fit(line, cols)));
+            },
+            None if selected.is_some() => lines.push(fit("  No log received for this job yet", cols)),
+            None => {},
+        }
+
+        lines.truncate(rows.saturating_sub(1));
+        while lines.len() < rows.saturating_sub(1) {
+            lines.push(String::new());
+        }
+        let help = "q: quit  j/k: select or scroll  tab/enter/esc: focus  G: follow log";
+        lines.push(fit(&format!("{}  {}", help, self.status), cols).dimmed().to_string());
+        lines
+    }
+}
+
+/// Load the submits with running jobs, with their running and finished jobs
+fn load_jobs(conn: &PgConnection) -> Result<(Vec<SubmitRow>, Vec<JobRow>)> {
+    let not_before = (chrono::Utc::now() - chrono::Duration::hours(crate::orchestrator::RUNNING_JOB_STALE_AFTER_HOURS)).naive_utc();
+    let running = schema::running_jobs::table
+        .inner_join(schema::submits::table.inner_join(schema::packages::table))
+        .filter(schema::running_jobs::started_at.ge(not_before))
+        .order_by(schema::running_jobs::id.asc())
+        .select((
+            schema::running_jobs::uuid,
+            schema::submits::id,
+            schema::submits::uuid,
+            schema::submits::submit_time,
+            schema::packages::name,
+            schema::packages::version,
+        ))
+        .load::<(Uuid, i32, Uuid, NaiveDateTime, String, String)>(conn)?;
+
+    let submit_ids = running.iter().map(|r| r.1).unique().collect::<Vec<_>>();
+    let finished = schema::jobs::table
+        .inner_join(schema::packages::table)
+        .inner_join(schema::endpoints::table)
+        .filter(schema::jobs::submit_id.eq_any(submit_ids.clone()))
+        .order_by(schema::jobs::id.asc())
+        .select((
+            schema::jobs::uuid,
+            schema::jobs::submit_id,
+            schema::packages::name,
+            schema::packages::version,
+            schema::endpoints::name,
+        ))
+        .load::<(Uuid, i32, String, String, String)>(conn)?;
+
+    let mut submits = vec![];
+    let mut jobs = vec![];
+    for submit_id in submit_ids {
+        let (_, _, uuid, submit_time, name, version) = running.iter().find(|r| r.1 == submit_id).unwrap(); // from running
+        let running = running.iter()
+            .filter(|r| r.1 == submit_id)
+            .map(|r| JobRow {
+                uuid: r.0,
+                submit: *uuid,
+                package: None,
+                endpoint: None,
+                running: true,
+            })
+            .collect::<Vec<_>>();
+        let finished = finished.iter()
+            .filter(|f| f.1 == submit_id)
+            .map(|f| JobRow {
+                uuid: f.0,
+                submit: *uuid,
+                package: Some(format!("{} {}", f.2, f.3)),
+                endpoint: Some(f.4.clone()),
+                running: false,
+            })
+            .collect::<Vec<_>>();
+
+        submits.push(SubmitRow {
+            uuid: *uuid,
+            submit_time: *submit_time,
+            package: format!("{} {}", name, version),
+            running: running.len(),
+            finished: finished.len(),
+        });
+        jobs.extend(running);
+        jobs.extend(finished);
+    }
+    Ok((submits, jobs))
+}
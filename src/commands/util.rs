@@ -10,9 +10,11 @@
 
 //! Utility module for subcommand implementation helpers
 
+use std::collections::BTreeMap;
 use std::io::Write;
 use std::fmt::Display;
 use std::path::Path;
+use std::path::PathBuf;
 
 use anyhow::Context;
 use anyhow::Error;
@@ -20,15 +22,25 @@ use anyhow::Result;
 use anyhow::anyhow;
 use clap::ArgMatches;
 use itertools::Itertools;
-use log::{error, info, trace};
+use log::{debug, error, info, trace};
 use regex::Regex;
 use tokio_stream::StreamExt;
 
 use crate::config::*;
+use crate::package::condition::ConditionCheckable;
+use crate::package::condition::ConditionData;
+use crate::package::LintDiagnostic;
+use crate::package::LintSeverity;
 use crate::package::Package;
+use crate::package::PackageOptions;
+use crate::package::ParseDependency;
 use crate::package::PhaseName;
+use crate::package::Script;
 use crate::package::ScriptBuilder;
 use crate::package::Shebang;
+use crate::repository::PackageFileFormat;
+use crate::repository::Repository;
+use crate::util::EnvironmentVariableName;
 
 /// Helper for getting a boolean value by name form the argument object
 pub fn getbool(m: &ArgMatches, name: &str, cmp: &str) -> bool {
@@ -37,10 +49,16 @@ pub fn getbool(m: &ArgMatches, name: &str, cmp: &str) -> bool {
 }
 
 /// Helper function to lint all packages in an interator
+///
+/// The diagnostics the linter prints in the format of gcc are reported with the phase and the
+/// lines of the script they refer to. With `deny_warnings`, warnings fail the linting like a
+/// failing linter does.
 pub async fn lint_packages<'a, I>(
     iter: I,
     linter: &Path,
+    repo_root: &Path,
     config: &Configuration,
+    deny_warnings: bool,
     bar: indicatif::ProgressBar,
 ) -> Result<()>
 where
@@ -66,56 +84,153 @@ where
 
                 let (status, stdout, stderr) = script.lint(cmd).await?;
                 bar.inc(1);
-                Ok((pkg.name().clone(), pkg.version().clone(), status, stdout, stderr))
+                Ok((pkg, script, status, stdout, stderr))
             }
         })
         .collect::<futures::stream::FuturesUnordered<_>>()
         .collect::<Result<Vec<_>>>()
-        .await?
-        .into_iter()
-        .map(|tpl| {
-            let pkg_name = tpl.0;
-            let pkg_vers = tpl.1;
-            let status = tpl.2;
-            let stdout = tpl.3;
-            let stderr = tpl.4;
-
-            if status.success() {
+        .await?;
+
+    let mut reports = vec![];
+    let package_results = lint_results
+        .iter()
+        .map(|(pkg, script, status, stdout, stderr)| {
+            let diagnostics = LintDiagnostic::parse(stdout, script)
+                .into_iter()
+                .chain(LintDiagnostic::parse(stderr, script))
+                .collect::<Vec<_>>();
+            let denied = deny_warnings && diagnostics.iter().any(|d| d.severity() >= LintSeverity::Warning);
+
+            if status.success() && !denied {
                 info!("Linting {pkg_name} {pkg_vers} script ({status}):\nstdout:\n{stdout}\n\nstderr:\n\n{stderr}",
-                    pkg_name = pkg_name,
-                    pkg_vers = pkg_vers,
+                    pkg_name = pkg.name(),
+                    pkg_vers = pkg.version(),
                     status = status,
                     stdout = stdout,
                     stderr = stderr
                 );
-                true
+            } else if denied && status.success() {
+                error!("Linting {pkg_name} {pkg_vers} found warnings, which are denied", pkg_name = pkg.name(), pkg_vers = pkg.version());
             } else {
                 error!("Linting {pkg_name} {pkg_vers} errored ({status}):\n\nstdout:\n{stdout}\n\nstderr:\n{stderr}\n\n",
-                    pkg_name = pkg_name,
-                    pkg_vers = pkg_vers,
+                    pkg_name = pkg.name(),
+                    pkg_vers = pkg.version(),
                     status = status,
                     stdout = stdout,
                     stderr = stderr
                 );
-                false
             }
+
+            if !diagnostics.is_empty() {
+                reports.push((*pkg, script, diagnostics));
+            }
+            status.success() && !denied
         })
         .collect::<Vec<_>>();
-
-    let lint_ok = lint_results.iter().all(|b| *b);
+    let lint_ok = package_results.iter().all(|b| *b);
 
     if !lint_ok {
         bar.finish_with_message("Linting errored");
-        Err(anyhow!("Linting was not successful"))
     } else {
         bar.finish_with_message(format!(
             "Finished linting {} package scripts",
             lint_results.len()
         ));
+    }
+
+    if !reports.is_empty() {
+        let theme = config.script_highlight_theme()
+            .as_deref()
+            .filter(|_| atty::is(atty::Stream::Stdout));
+        let out = std::io::stdout();
+        let mut outlock = out.lock();
+        for (pkg, script, diagnostics) in reports {
+            print_lint_diagnostics(&mut outlock, repo_root, pkg, script, &diagnostics, theme)?;
+        }
+    }
+
+    if !lint_ok {
+        Err(anyhow!("Linting was not successful"))
+    } else {
         Ok(())
     }
 }
 
+/// The number of lines of the script that are printed before and after the line of a diagnostic
+const LINT_CONTEXT_LINES: usize = 2;
+
+/// Print the `diagnostics` of the linter for the `script` of `pkg`, with the lines of the script
+/// around them, highlighted with `theme` if it is set
+fn print_lint_diagnostics<W: Write>(
+    out: &mut W,
+    repo_root: &Path,
+    pkg: &Package,
+    script: &Script,
+    diagnostics: &[LintDiagnostic],
+    theme: Option<&str>,
+) -> Result<()> {
+    let plain_lines = script.as_ref().lines().collect::<Vec<_>>();
+    let lines = match theme {
+        Some(theme) => script.highlighted(theme)
+            .lines()?
+            .map(|line| format!("{}\x1b[0m", line.trim_end_matches('\n')))
+            .collect::<Vec<_>>(),
+        None => plain_lines.iter().map(|line| line.to_string()).collect(),
+    };
+
+    for diagnostic in diagnostics {
+        writeln!(out, "{} {}: {}: {}", pkg.name(), pkg.version(), diagnostic.severity(), diagnostic.message())?;
+
+        let script_line = match diagnostic.column() {
+            Some(column) => format!("script line {}, column {}", diagnostic.line(), column),
+            None => format!("script line {}", diagnostic.line()),
+        };
+        let location = match diagnostic.phase() {
+            Some((phase, line)) => format!("phase '{}', line {} ({})", phase, line, script_line),
+            None => script_line,
+        };
+        let definition = pkg.definition_file()
+            .as_ref()
+            .zip(plain_lines.get(diagnostic.line().wrapping_sub(1)))
+            .and_then(|(file, line)| find_in_definition(repo_root, file, line));
+        match definition {
+            Some((file, line)) => writeln!(out, "  in {}, {}:{}", location, file.display(), line)?,
+            None => writeln!(out, "  in {}", location)?,
+        }
+
+        let first = diagnostic.line().saturating_sub(LINT_CONTEXT_LINES).max(1);
+        let last = (diagnostic.line() + LINT_CONTEXT_LINES).min(lines.len());
+        for n in first..=last {
+            let marker = if n == diagnostic.line() { '>' } else { ' ' };
+            writeln!(out, "{} {:>4} | {}", marker, n, lines[n - 1])?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Find the `script_line` in the package definition file `definition_file` or in the package
+/// definition files of the directories above it, closest first
+///
+/// The paths are relative to `repo_root`. Returns the file and the line, starting at 1.
+fn find_in_definition(repo_root: &Path, definition_file: &Path, script_line: &str) -> Option<(PathBuf, usize)> {
+    let script_line = script_line.trim();
+    if script_line.is_empty() {
+        return None
+    }
+
+    let parent_files = definition_file.ancestors()
+        .skip(2)
+        .flat_map(|dir| PackageFileFormat::ALL.iter().map(move |format| dir.join(format.file_name())));
+    std::iter::once(definition_file.to_path_buf())
+        .chain(parent_files)
+        .find_map(|file| {
+            let content = std::fs::read_to_string(repo_root.join(&file)).ok()?;
+            let line = content.lines().position(|line| line.trim() == script_line)?;
+            Some((file, line + 1))
+        })
+}
+
 /// Check whether all phases are available in the package,
 /// generate a nice error message if one is not.
 fn all_phases_available(pkg: &Package, available_phases: &[PhaseName]) -> Result<()> {
@@ -172,10 +287,57 @@ pub fn mk_header(vec: Vec<&str>) -> Vec<ascii_table::Column> {
         .collect()
 }
 
+/// Print `data` as a JSON array, with one object per row
+///
+/// The values of a row are the members of the object, named by `fields`.
+pub fn display_data_as_json(fields: &[&str], data: Vec<Vec<String>>) -> Result<()> {
+    let rows = data
+        .into_iter()
+        .map(|row| fields.iter().zip(row).collect::<BTreeMap<_, _>>())
+        .collect::<Vec<_>>();
+
+    let out = std::io::stdout();
+    let mut lock = out.lock();
+    serde_json::to_writer_pretty(&mut lock, &rows)?;
+    writeln!(lock).map_err(Error::from)
+}
+
 /// Display the passed data as nice ascii table,
 /// or, if stdout is a pipe, print it nicely parseable
 ///
 /// If `csv` is `true`, convert the data to CSV and print that instead.
+/// Display `data` with the handlebars template `format`, one line per row, or with
+/// `display_data()` if there is no format
+///
+/// The values of a row are available in the template under the names in `fields`.
+pub fn display_data_with_format(
+    headers: Vec<ascii_table::Column>,
+    fields: &[&str],
+    data: Vec<Vec<String>>,
+    csv: bool,
+    format: Option<&str>,
+) -> Result<()> {
+    let format = match format {
+        Some(format) => format,
+        None => return display_data(headers, data, csv),
+    };
+
+    let mut hb = handlebars::Handlebars::new();
+    hb.register_escape_fn(handlebars::no_escape);
+    hb.set_strict_mode(true);
+    hb.register_template_string("row", format)
+        .with_context(|| anyhow!("Parsing format: {}", format))?;
+
+    let out = std::io::stdout();
+    let mut lock = out.lock();
+    for row in data {
+        let values = fields.iter().zip(row).collect::<BTreeMap<_, _>>();
+        let line = hb.render("row", &values).context("Rendering row with format")?;
+        writeln!(lock, "{}", line)?;
+    }
+    Ok(())
+}
+
 pub fn display_data<D: Display>(
     headers: Vec<ascii_table::Column>,
     data: Vec<Vec<D>>,
@@ -225,6 +387,100 @@ pub fn display_data<D: Display>(
     }
 }
 
+/// The environment variables passed to the jobs with `--env` and `--env-file` and the `pass_env`
+/// variables of the host environment, unless they are overridden on the commandline
+pub fn additional_env(matches: &ArgMatches, config: &Configuration) -> Result<Vec<(EnvironmentVariableName, String)>> {
+    let cli_env = cli_env(matches)?;
+    let host_env = config.containers()
+        .pass_env()
+        .iter()
+        .filter(|name| !cli_env.iter().any(|(k, _)| k == *name))
+        .filter_map(|name| {
+            let value = std::env::var(name.as_ref() as &str).ok()?;
+            trace!("Passing host environment variable {} to containers", name);
+            Some((name.clone(), value))
+        });
+
+    Ok(host_env.chain(cli_env.iter().cloned()).collect())
+}
+
+/// The environment variables passed to the jobs with `--env` and `--env-file`
+///
+/// Variables from `--env` override the ones from the files, later files override earlier ones.
+pub fn cli_env(matches: &ArgMatches) -> Result<Vec<(EnvironmentVariableName, String)>> {
+    let mut cli_env: Vec<(EnvironmentVariableName, String)> = vec![];
+    let file_env = matches
+        .values_of("env_file")
+        .unwrap_or_default()
+        .map(|path| {
+            let content = std::fs::read_to_string(path).with_context(|| anyhow!("Reading env file {}", path))?;
+            crate::util::env::parse_env_file(&content).with_context(|| anyhow!("Parsing env file {}", path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let flag_env = matches
+        .values_of("env")
+        .unwrap_or_default()
+        .map(crate::util::env::parse_to_env)
+        .collect::<Result<Vec<(EnvironmentVariableName, String)>>>()?;
+    for (name, value) in file_env.into_iter().flatten().chain(flag_env) {
+        cli_env.retain(|(k, _)| *k != name);
+        cli_env.push((name, value));
+    }
+    Ok(cli_env)
+}
+
+/// The package and all packages it depends on at runtime, transitively
+///
+/// Conditions of the dependencies are checked without an image and with the default options,
+/// because there is no build to take them from.
+pub fn runtime_closure<'a>(package: &'a Package, repo: &'a Repository, config: &Configuration) -> Result<Vec<&'a Package>> {
+    let mut closure = vec![package];
+    let mut next = 0;
+    while let Some(package) = closure.get(next).copied() {
+        next += 1;
+
+        let options = package.options_with(&PackageOptions::default());
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &options,
+        };
+
+        for dependency in package.dependencies().runtime() {
+            if !dependency.check_condition(&condition_data)? {
+                continue
+            }
+
+            let (name, constraint) = dependency.parse_as_name_and_version()?;
+            let candidates = repo.find_with_version(&name, &constraint);
+            if candidates.is_empty() {
+                return Err(anyhow!("Dependency of {} {} not found: {} {}", package.name(), package.version(), name, constraint))
+            }
+
+            for dependency in config.resolver().select(&name, &constraint, candidates)? {
+                if !closure.contains(&dependency) {
+                    debug!("{} {} depends on {} {} at runtime", package.name(), package.version(), dependency.name(), dependency.version());
+                    closure.push(dependency);
+                }
+            }
+        }
+    }
+    Ok(closure)
+}
+
+/// Whether a maintainer of `package` contains `pattern`, ignoring case
+pub fn is_maintained_by(package: &Package, pattern: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    package.maintainers()
+        .iter()
+        .any(|maintainer| maintainer.to_lowercase().contains(&pattern))
+}
+
+/// Format a duration in seconds for humans, rounded to whole seconds, e.g. "1m 12s"
+pub fn format_duration_secs(secs: f64) -> String {
+    humantime::format_duration(std::time::Duration::from_secs(secs.max(0.0).round() as u64)).to_string()
+}
+
 pub fn get_date_filter(name: &str, matches: &ArgMatches) -> Result<Option<chrono::DateTime::<chrono::Local>>> {
     matches.value_of(name)
         .map(|s| {
@@ -0,0 +1,150 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'verify-artifacts' subcommand
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use clap::ArgMatches;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use log::info;
+use log::trace;
+
+use crate::config::Configuration;
+use crate::db::models as dbmodels;
+use crate::filestore::ObjectStore;
+use crate::schema;
+
+/// How an artifact differs from what was recorded when it was built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Problem {
+    /// The file of the artifact does not exist
+    Missing,
+
+    /// The file is smaller than when it was built
+    Truncated,
+
+    /// The content of the file changed after it was built
+    Modified,
+}
+
+impl Problem {
+    fn name(self) -> &'static str {
+        match self {
+            Problem::Missing => "missing",
+            Problem::Truncated => "truncated",
+            Problem::Modified => "modified",
+        }
+    }
+}
+
+/// Implementation of the "verify-artifacts" subcommand
+pub async fn verify_artifacts(matches: &ArgMatches, config: &Configuration, conn: PgConnection) -> Result<()> {
+    let csv = matches.is_present("csv");
+    let json = matches.is_present("json");
+    let target = matches.value_of("target").unwrap(); // safe by clap
+
+    let (root, artifacts) = match uuid::Uuid::parse_str(target) {
+        Ok(submit_uuid) => {
+            let submit = dbmodels::Submit::with_id(&conn, &submit_uuid)?;
+            let artifacts = schema::artifacts::table
+                .inner_join(schema::jobs::table)
+                .filter(schema::jobs::submit_id.eq(submit.id))
+                .order(schema::artifacts::path.asc())
+                .select((schema::jobs::uuid, schema::artifacts::all_columns))
+                .load::<(uuid::Uuid, dbmodels::Artifact)>(&conn)?;
+            (config.staging_directory().join(submit_uuid.to_string()), artifacts)
+        },
+
+        Err(_) if config.release_stores().iter().any(|name| name == target) => {
+            // If an artifact was released to the same path several times, the latest release is
+            // expected in the store
+            let artifacts = schema::releases::table
+                .inner_join(schema::artifacts::table.inner_join(schema::jobs::table))
+                .inner_join(schema::release_stores::table)
+                .filter(schema::release_stores::store_name.eq(target))
+                .order(schema::releases::release_date.asc())
+                .select((schema::jobs::uuid, schema::artifacts::all_columns))
+                .load::<(uuid::Uuid, dbmodels::Artifact)>(&conn)?
+                .into_iter()
+                .map(|(job, artifact)| (artifact.path_buf(), (job, artifact)))
+                .collect::<HashMap<PathBuf, _>>();
+
+            let mut artifacts = artifacts.into_values().collect::<Vec<_>>();
+            artifacts.sort_by(|a, b| a.1.path.cmp(&b.1.path));
+            (config.releases_directory().join(target), artifacts)
+        },
+
+        Err(_) => return Err(anyhow!("'{}' is neither a submit UUID nor a configured release store", target)),
+    };
+
+    let mut unverified = 0;
+    let mut rows = vec![];
+    for (job, artifact) in artifacts {
+        let sha256 = match artifact.sha256.as_ref() {
+            Some(sha256) => sha256,
+            None => {
+                unverified += 1;
+                continue
+            },
+        };
+
+        let file = root.join(artifact.path_buf());
+        trace!("Verifying {}", file.display());
+        let problem = match tokio::fs::metadata(&file).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Some((Problem::Missing, String::new())),
+            Err(e) => return Err(anyhow!("Reading metadata of {}: {}", file.display(), e)),
+            Ok(metadata) => match artifact.size {
+                Some(size) if (metadata.len() as i64) < size => {
+                    Some((Problem::Truncated, format!("{} bytes instead of {}", metadata.len(), size)))
+                },
+                _ => {
+                    let actual = ObjectStore::hash_file(&file).await?;
+                    if actual != *sha256 {
+                        Some((Problem::Modified, format!("sha256 {} instead of {}", actual, sha256)))
+                    } else {
+                        None
+                    }
+                },
+            },
+        };
+
+        if let Some((problem, details)) = problem {
+            rows.push(vec![
+                problem.name().to_string(),
+                artifact.path.clone(),
+                job.to_string(),
+                details,
+            ]);
+        }
+    }
+
+    if unverified > 0 {
+        info!("{} artifacts were built before their checksums were recorded and were not verified", unverified);
+    }
+
+    let problems = rows.len();
+    if json {
+        crate::commands::util::display_data_as_json(&["problem", "path", "job", "details"], rows)?;
+    } else {
+        let header = crate::commands::util::mk_header(["Problem", "Path", "Job", "Details"].to_vec());
+        crate::commands::util::display_data(header, rows, csv)?;
+    }
+
+    if problems == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!("Found {} artifacts in {} that changed after they were built", problems, root.display()))
+    }
+}
@@ -0,0 +1,371 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'verify-reproducibility' subcommand
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use clap::ArgMatches;
+use colored::Colorize;
+use diesel::prelude::*;
+use log::debug;
+use log::info;
+use log::trace;
+use log::warn;
+use tokio::sync::RwLock;
+
+use crate::config::Configuration;
+use crate::config::EndpointName;
+use crate::db::models as dbmodels;
+use crate::filestore::path::StoreRoot;
+use crate::filestore::ArtifactMetadata;
+use crate::filestore::ArtifactPath;
+use crate::filestore::ObjectStore;
+use crate::filestore::ReleaseStore;
+use crate::filestore::StagingStore;
+use crate::job::JobResource;
+use crate::job::RunnableJob;
+use crate::package::condition::ConditionData;
+use crate::package::Dag;
+use crate::package::PackageName;
+use crate::package::PackageOptions;
+use crate::package::PackageVersion;
+use crate::package::Script;
+use crate::repository::Repository;
+use crate::source::SourceCache;
+use crate::util::EnvironmentVariableName;
+use crate::util::docker::ImageName;
+use crate::util::progress::ProgressBars;
+
+/// Implementation of the "verify-reproducibility" subcommand
+pub async fn verify_reproducibility(
+    matches: &ArgMatches,
+    config: &Configuration,
+    progressbars: ProgressBars,
+    repo: Repository,
+    conn: PgConnection,
+) -> Result<()> {
+    let pname = matches.value_of("package_name").map(String::from).map(PackageName::from).unwrap(); // safe by clap
+    let pvers = matches.value_of("package_version").map(String::from).map(PackageVersion::from).unwrap(); // safe by clap
+    let keep = matches.is_present("keep");
+    let pname = repo.resolve_name(&pname)?;
+
+    // Jobs are recorded with the name of the package, without its namespace
+    let db_name = PackageName::from(pname.split_namespace().1.to_string());
+    let job = find_job(&conn, &db_name, &pvers, matches.value_of("job_uuid"))?;
+    let submit = crate::schema::submits::table
+        .find(job.submit_id)
+        .first::<dbmodels::Submit>(&conn)?;
+    let image = dbmodels::Image::fetch_by_id(&conn, job.image_id)?
+        .ok_or_else(|| anyhow!("Image for job {} not found", job.uuid))?;
+    let image_name = ImageName::from(image.name.clone());
+    info!("Reproducing job {} of submit {}", job.uuid, submit.uuid);
+
+    let (package, options) = package_of_job(&repo, &job, &pname, &pvers)?;
+    let recorded_env = recorded_env(&conn, &job)?;
+
+    let recorded_artifacts = dbmodels::Artifact::belonging_to(&job)
+        .load::<dbmodels::Artifact>(&conn)?;
+
+    // Load the stores the recorded artifacts and the artifacts of the dependencies can be found in
+    let release_stores = config
+        .release_stores()
+        .iter()
+        .map(|name| config.releases_directory().join(name))
+        .filter(|p| p.is_dir())
+        .map(|p| {
+            let bar = progressbars.bar()?;
+            let r = ReleaseStore::load(StoreRoot::new(p)?, &bar);
+            bar.finish_with_message("Loaded releases");
+            r.map(Arc::new)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let submit_staging = {
+        let p = config.staging_directory().join(submit.uuid.to_string());
+        let bar = progressbars.bar()?;
+        let r = StagingStore::load(StoreRoot::new(p)?, &bar);
+        bar.finish_with_message("Loaded staging");
+        Arc::new(r?)
+    };
+
+    let dependencies = find_dependency_artifacts(config, &conn, &repo, &package, &image_name, &recorded_env, &options, &submit_staging, &release_stores)?;
+
+    // The staging store the rebuilt artifacts are written to
+    let rebuild_dir = config.staging_directory().join(uuid::Uuid::new_v4().to_string());
+    tokio::fs::create_dir_all(&rebuild_dir).await?;
+    let rebuild_store = {
+        let bar = progressbars.bar()?;
+        let mut r = StagingStore::load(StoreRoot::new(rebuild_dir.clone())?, &bar)?;
+        bar.finish_with_message("Loaded staging for rebuild");
+
+        // Compressed like the original artifacts, so that they can be compared
+        r.set_compression(config.artifact_compression().clone());
+        r.set_ownership(config.artifact_ownership().clone());
+        Arc::new(RwLock::new(r))
+    };
+
+    let endpoint = {
+        let name = matches.value_of("endpoint")
+            .map(String::from)
+            .map(EndpointName::from)
+            .or_else(|| config.docker().endpoints().keys().next().cloned())
+            .ok_or_else(|| anyhow!("No endpoint configured"))?;
+        crate::commands::endpoint::connect_to_endpoints(config, &[name.clone()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("Endpoint not found: {}", name))?
+    };
+
+    let recorded_digest = recorded_image_digest(&recorded_artifacts, &submit_staging, &release_stores);
+    match (recorded_digest, endpoint.image_id(&image_name).await) {
+        (Some(recorded), Ok(current)) if recorded != current => {
+            return Err(anyhow!("Image {} changed: recorded {}, on endpoint {}", image_name, recorded, current))
+        },
+        (None, _) => warn!("No image digest recorded for job {}, cannot verify that the image is unchanged", job.uuid),
+        (_, Err(e)) => return Err(e),
+        _ => {},
+    }
+
+    let source_cache = SourceCache::new(config.source_cache_root().clone());
+    let resources = dependencies.into_iter()
+        .map(JobResource::from)
+        .chain({
+            // The package environment is added to the job environment anyways
+            recorded_env.into_iter()
+                .filter(|(k, v)| package.environment().as_ref().and_then(|env| env.get(k)) != Some(v))
+                .map(JobResource::from)
+        })
+        .collect();
+    let runnable = RunnableJob::with_script(package, image_name, &source_cache, Script::from(job.script_text.clone()), resources, config);
+
+    info!("Rebuilding on endpoint {}", endpoint.name());
+    let (log_sender, mut log_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let log_drain = async move {
+        while let Some(item) = log_receiver.recv().await {
+            trace!("Log: {:?}", item);
+        }
+    };
+    let prepared = endpoint
+        .prepare_container(&submit.uuid, runnable, rebuild_store.clone(), vec![submit_staging.clone()], release_stores.clone(), &indicatif::ProgressBar::hidden())
+        .await?;
+    let (executed, _) = tokio::join!(prepared.start().await?.execute_script(log_sender), log_drain);
+    let (rebuilt, res) = executed?.finalize(rebuild_store.clone()).await?.unpack();
+    res.context("Rebuild failed")?;
+
+    let objects = ObjectStore::in_directory(config.staging_directory());
+    let any_divergence = {
+        let rebuild_read = rebuild_store.read().await;
+        let mut rebuilt_hashes = BTreeMap::new();
+        for art in rebuilt.iter() {
+            if let Some(full) = rebuild_read.root_path().join(art)? {
+                rebuilt_hashes.insert(PathBuf::from(art.as_ref()), ObjectStore::hash_file(&full.joined()).await?);
+            }
+        }
+
+        let mut out = std::io::stdout();
+        let mut any_divergence = false;
+        for art in recorded_artifacts.iter() {
+            let path = PathBuf::from(&art.path);
+            let recorded = match find_recorded_file(&art.path, &submit_staging, &release_stores)? {
+                Some(file) => ObjectStore::hash_file(&file).await?,
+                None => {
+                    writeln!(out, "{}: recorded artifact not found in any store", path.display().to_string().yellow())?;
+                    any_divergence = true;
+                    continue
+                },
+            };
+
+            match rebuilt_hashes.remove(&path) {
+                Some(rebuilt) if rebuilt == recorded => {
+                    writeln!(out, "{}: identical", path.display().to_string().green())?;
+                },
+                Some(rebuilt) => {
+                    writeln!(out, "{}: differs (recorded {}, rebuilt {})", path.display().to_string().red(), recorded, rebuilt)?;
+                    any_divergence = true;
+                },
+                None => {
+                    writeln!(out, "{}: not produced by rebuild", path.display().to_string().red())?;
+                    any_divergence = true;
+                },
+            }
+        }
+
+        for path in rebuilt_hashes.keys() {
+            writeln!(out, "{}: only produced by rebuild", path.display().to_string().red())?;
+            any_divergence = true;
+        }
+
+        any_divergence
+    };
+
+    if keep {
+        writeln!(std::io::stdout(), "Rebuilt artifacts kept in {}", rebuild_dir.display())?;
+    } else {
+        for entry in walkdir::WalkDir::new(&rebuild_dir).into_iter().filter_map(Result::ok) {
+            if entry.file_type().is_file() && !crate::filestore::is_sidecar_path(entry.path()) {
+                objects.remove(entry.path()).await?;
+            }
+        }
+        tokio::fs::remove_dir_all(&rebuild_dir).await?;
+    }
+
+    if any_divergence {
+        Err(anyhow!("Job {} is not reproducible", job.uuid))
+    } else {
+        Ok(())
+    }
+}
+
+/// The package `pname` `pvers` from the repository, with the options `job` was built with
+///
+/// Fails if the patches or the options of the package changed since the job ran.
+pub(super) fn package_of_job(
+    repo: &Repository,
+    job: &dbmodels::Job,
+    pname: &PackageName,
+    pvers: &PackageVersion,
+) -> Result<(crate::package::Package, PackageOptions)> {
+    let mut package = repo.packages()
+        .find(|p| p.has_name(pname) && p.version() == pvers)
+        .ok_or_else(|| anyhow!("Package {} {} not found in repository", pname, pvers))?
+        .clone();
+
+    if package.patches_hash()? != job.patches_hash {
+        return Err(anyhow!("The patches of {} {} changed since job {}", pname, pvers, job.uuid))
+    }
+
+    let options = job.options
+        .as_deref()
+        .map(crate::package::parse_options)
+        .transpose()?
+        .unwrap_or_default();
+    package.set_option_values(&options);
+    if package.options_string() != job.options {
+        return Err(anyhow!("The options of {} {} changed since job {}", pname, pvers, job.uuid))
+    }
+
+    Ok((package, options))
+}
+
+/// The environment `job` was run with, as recorded in the database
+pub(super) fn recorded_env(conn: &PgConnection, job: &dbmodels::Job) -> Result<Vec<(EnvironmentVariableName, String)>> {
+    dbmodels::JobEnv::belonging_to(job)
+        .inner_join(crate::schema::envvars::table)
+        .load::<(dbmodels::JobEnv, dbmodels::EnvVar)>(conn)
+        .map(|envs| {
+            envs.into_iter()
+                .map(|(_, env)| (EnvironmentVariableName::from(env.name.as_str()), env.value))
+                .collect()
+        })
+        .map_err(anyhow::Error::from)
+}
+
+/// Find the job to reproduce: The passed one or the latest job of the package that produced
+/// artifacts
+fn find_job(conn: &PgConnection, pname: &PackageName, pvers: &PackageVersion, job_uuid: Option<&str>) -> Result<dbmodels::Job> {
+    use crate::schema::{artifacts, jobs, packages};
+
+    let query = jobs::table
+        .inner_join(packages::table)
+        .filter(packages::name.eq(pname.as_ref() as &str))
+        .filter(packages::version.eq(pvers.as_ref() as &str))
+        .filter(diesel::dsl::exists(artifacts::table.filter(artifacts::job_id.eq(jobs::id))))
+        .select(jobs::all_columns)
+        .into_boxed();
+
+    let query = if let Some(uuid) = job_uuid {
+        let uuid = uuid::Uuid::parse_str(uuid).context("Parsing job UUID")?;
+        query.filter(jobs::uuid.eq(uuid))
+    } else {
+        query.order(jobs::id.desc())
+    };
+
+    debug!("Query: {:?}", diesel::debug_query::<diesel::pg::Pg, _>(&query));
+    query.first::<dbmodels::Job>(conn)
+        .optional()?
+        .ok_or_else(|| anyhow!("No job with artifacts found for {} {}", pname, pvers))
+}
+
+/// Find the artifacts of the dependencies of `package`, the same way the build does
+#[allow(clippy::too_many_arguments)]
+pub(super) fn find_dependency_artifacts(
+    config: &Configuration,
+    conn: &PgConnection,
+    repo: &Repository,
+    package: &crate::package::Package,
+    image_name: &ImageName,
+    env: &[(EnvironmentVariableName, String)],
+    options: &PackageOptions,
+    staging: &Arc<StagingStore>,
+    release_stores: &[Arc<ReleaseStore>],
+) -> Result<Vec<ArtifactPath>> {
+    let condition_data = ConditionData {
+        image_name: Some(image_name),
+        env,
+        options,
+    };
+    let dag = Dag::for_root_package(package.clone(), repo, None, &condition_data, config.resolver())?;
+
+    let mut found = HashSet::new();
+    for dependency in dag.all_packages().into_iter().filter(|p| *p != package) {
+        let artifacts = crate::db::FindArtifacts::builder()
+            .database_connection(conn)
+            .config(config)
+            .package(dependency)
+            .release_stores(release_stores)
+            .staging_store(Some(staging))
+            .image_name(Some(image_name))
+            .env_filter(env)
+            .script_filter(true)
+            .build()
+            .run()?;
+
+        if artifacts.is_empty() {
+            return Err(anyhow!("No artifacts found for dependency {} {}", dependency.name(), dependency.version()))
+        }
+
+        found.extend(artifacts.into_iter().map(|(path, _)| path.artifact_path().clone()));
+    }
+
+    Ok(found.into_iter().collect())
+}
+
+/// Find the file of a recorded artifact in the staging store of the submit or the release stores
+pub(super) fn find_recorded_file(path: &str, staging: &StagingStore, release_stores: &[Arc<ReleaseStore>]) -> Result<Option<PathBuf>> {
+    let path = ArtifactPath::new(PathBuf::from(path))?;
+    if let Some(full) = staging.root_path().join(&path)? {
+        return Ok(Some(full.joined()))
+    }
+
+    for store in release_stores {
+        if let Some(full) = store.root_path().join(&path)? {
+            return Ok(Some(full.joined()))
+        }
+    }
+
+    Ok(None)
+}
+
+/// Get the image digest from the metadata file of one of the recorded artifacts
+fn recorded_image_digest(artifacts: &[dbmodels::Artifact], staging: &StagingStore, release_stores: &[Arc<ReleaseStore>]) -> Option<String> {
+    artifacts.iter()
+        .filter_map(|art| find_recorded_file(&art.path, staging, release_stores).ok().flatten())
+        .map(|file| crate::filestore::meta_path_for(&file))
+        .filter_map(|meta| std::fs::read_to_string(meta).ok())
+        .filter_map(|content| serde_json::from_str::<ArtifactMetadata>(&content).ok())
+        .find_map(|meta| meta.image_digest)
+}
@@ -0,0 +1,231 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Implementation of the 'verify-stores' subcommand
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use anyhow::Result;
+use clap::ArgMatches;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use log::debug;
+use log::trace;
+
+use crate::config::Configuration;
+use crate::db::models as dbmodels;
+use crate::filestore::path::ArtifactPath;
+use crate::filestore::path::StoreRoot;
+use crate::filestore::ObjectStore;
+use crate::filestore::ReleaseStore;
+use crate::filestore::StagingStore;
+use crate::schema;
+
+/// An inconsistency between a store and the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Problem {
+    /// The database has an artifact the store does not contain
+    Missing,
+
+    /// The store contains a file the database has no artifact for
+    Untracked,
+
+    /// The file in the store does not have the hash that was recorded when it was built
+    HashMismatch,
+}
+
+impl Problem {
+    fn name(self) -> &'static str {
+        match self {
+            Problem::Missing => "missing",
+            Problem::Untracked => "untracked",
+            Problem::HashMismatch => "hash-mismatch",
+        }
+    }
+
+    fn suggestion(self, store: &StoreKind) -> &'static str {
+        match (self, store) {
+            (Problem::Missing, StoreKind::Staging(_)) => "Restore the file or rebuild the package",
+            (Problem::Missing, StoreKind::Release(_)) => "Restore the file from a backup or release the package again",
+            (Problem::Untracked, StoreKind::Staging(_)) => "Remove the file, it was not built by a job of this submit",
+            (Problem::Untracked, StoreKind::Release(_)) => "Remove the file, or release it with 'butido release new'",
+            (Problem::HashMismatch, _) => "The file changed after it was built, restore it or rebuild the package",
+        }
+    }
+}
+
+/// The store a problem was found in
+enum StoreKind {
+    /// A staging store, named after its submit
+    Staging(uuid::Uuid),
+
+    /// A release store, with its name
+    Release(String),
+}
+
+impl std::fmt::Display for StoreKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StoreKind::Staging(uuid) => write!(f, "staging/{}", uuid),
+            StoreKind::Release(name) => write!(f, "release/{}", name),
+        }
+    }
+}
+
+/// Implementation of the "verify-stores" subcommand
+pub async fn verify_stores(matches: &ArgMatches, config: &Configuration, conn: PgConnection) -> Result<()> {
+    let csv = matches.is_present("csv");
+    let json = matches.is_present("json");
+    let check_hashes = !matches.is_present("no_hash");
+
+    let mut rows = vec![];
+    for (kind, root, expected) in staging_stores(config, &conn)?.into_iter().chain(release_stores(config, &conn)?) {
+        let store = kind.to_string();
+        for (problem, path) in verify_store(&kind, &root, expected, check_hashes).await? {
+            debug!("{} {}: {}", store, problem.name(), path.display());
+            rows.push(vec![
+                problem.name().to_string(),
+                store.clone(),
+                path.display().to_string(),
+                problem.suggestion(&kind).to_string(),
+            ]);
+        }
+    }
+
+    let problems = rows.len();
+    if json {
+        crate::commands::util::display_data_as_json(&["problem", "store", "path", "suggestion"], rows)?;
+    } else {
+        let header = crate::commands::util::mk_header(["Problem", "Store", "Path", "Suggestion"].to_vec());
+        crate::commands::util::display_data(header, rows, csv)?;
+    }
+
+    if problems == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!("Found {} inconsistencies between the stores and the database", problems))
+    }
+}
+
+/// The artifacts the database has for a store, by their path in the store
+type ExpectedArtifacts = HashMap<PathBuf, dbmodels::Artifact>;
+
+/// Get the staging stores with the artifacts the database has for their submits
+///
+/// Staging directories that were removed are not reported, because removing a staging directory
+/// once its artifacts are released is expected.
+fn staging_stores(config: &Configuration, conn: &PgConnection) -> Result<Vec<(StoreKind, PathBuf, ExpectedArtifacts)>> {
+    let mut artifacts = schema::artifacts::table
+        .inner_join(schema::jobs::table.inner_join(schema::submits::table))
+        .select((schema::submits::uuid, schema::artifacts::all_columns))
+        .load::<(uuid::Uuid, dbmodels::Artifact)>(conn)?
+        .into_iter()
+        .fold(HashMap::<uuid::Uuid, ExpectedArtifacts>::new(), |mut hm, (submit, artifact)| {
+            hm.entry(submit).or_default().insert(artifact.path_buf(), artifact);
+            hm
+        });
+
+    std::fs::read_dir(config.staging_directory())?
+        .map(|entry| entry.map(|e| e.path()).map_err(Error::from))
+        .filter_map(|path| {
+            let path = match path {
+                Ok(path) => path,
+                Err(e) => return Some(Err(e)),
+            };
+
+            let submit = path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| uuid::Uuid::parse_str(name).ok());
+            match submit {
+                Some(submit) if path.is_dir() => {
+                    let expected = artifacts.remove(&submit).unwrap_or_default();
+                    Some(Ok((StoreKind::Staging(submit), path, expected)))
+                },
+                _ => {
+                    trace!("Not a staging directory: {}", path.display());
+                    None
+                },
+            }
+        })
+        .collect()
+}
+
+/// Get the release stores with the artifacts the database has released to them
+///
+/// If an artifact was released to the same path several times, the latest release is expected.
+fn release_stores(config: &Configuration, conn: &PgConnection) -> Result<Vec<(StoreKind, PathBuf, ExpectedArtifacts)>> {
+    let mut artifacts = schema::releases::table
+        .inner_join(schema::artifacts::table)
+        .inner_join(schema::release_stores::table)
+        .order(schema::releases::release_date.asc())
+        .select((schema::release_stores::store_name, schema::artifacts::all_columns))
+        .load::<(String, dbmodels::Artifact)>(conn)?
+        .into_iter()
+        .fold(HashMap::<String, ExpectedArtifacts>::new(), |mut hm, (store, artifact)| {
+            hm.entry(store).or_default().insert(artifact.path_buf(), artifact);
+            hm
+        });
+
+    Ok(config.release_stores()
+        .iter()
+        .map(|name| {
+            let expected = artifacts.remove(name).unwrap_or_default();
+            (StoreKind::Release(name.clone()), config.releases_directory().join(name), expected)
+        })
+        .collect())
+}
+
+/// Compare the files in the store at `root` with the `expected` artifacts from the database
+async fn verify_store(
+    kind: &StoreKind,
+    root: &Path,
+    expected: ExpectedArtifacts,
+    check_hashes: bool,
+) -> Result<Vec<(Problem, PathBuf)>> {
+    fn paths<'a>(artifacts: impl Iterator<Item = &'a ArtifactPath>) -> HashSet<PathBuf> {
+        artifacts.map(|artifact| artifact.as_ref().to_path_buf()).collect()
+    }
+
+    let progress = indicatif::ProgressBar::hidden();
+    let on_disk = match kind {
+        _ if !root.is_dir() => HashSet::new(),
+        StoreKind::Staging(_) => paths(StagingStore::load(StoreRoot::new(root.to_path_buf())?, &progress)?.artifacts()?),
+        StoreKind::Release(_) => paths(ReleaseStore::load(StoreRoot::new(root.to_path_buf())?, &progress)?.artifacts()?),
+    };
+
+    let mut problems = vec![];
+    for (path, artifact) in expected.iter() {
+        if !on_disk.contains(path) {
+            problems.push((Problem::Missing, path.clone()));
+            continue
+        }
+
+        if let (true, Some(sha256)) = (check_hashes, artifact.sha256.as_ref()) {
+            let file = root.join(path);
+            trace!("Verifying hash of {}", file.display());
+            if ObjectStore::hash_file(&file).await? != *sha256 {
+                problems.push((Problem::HashMismatch, path.clone()));
+            }
+        }
+    }
+
+    problems.extend({
+        on_disk.into_iter()
+            .filter(|path| !expected.contains_key(path))
+            .map(|path| (Problem::Untracked, path))
+    });
+    problems.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(problems)
+}
@@ -10,35 +10,86 @@
 
 //! Implementation of the 'versions_of' subcommand
 
-use anyhow::Error;
+use std::collections::HashSet;
+
 use anyhow::Result;
 use clap::ArgMatches;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use itertools::Itertools;
 use log::trace;
 
+use crate::package::Package;
 use crate::package::PackageName;
 use crate::repository::Repository;
+use crate::schema;
 
 /// Implementation of the "versions_of" subcommand
-pub async fn versions_of(matches: &ArgMatches, repo: Repository) -> Result<()> {
+///
+/// Lists the versions of the package with their sources and whether artifacts of them were
+/// released.
+pub async fn versions_of(matches: &ArgMatches, repo: Repository, conn: PgConnection) -> Result<()> {
     use filters::filter::Filter;
-    use std::io::Write;
 
+    let csv = matches.is_present("csv");
     let package_filter = {
         let name = matches
             .value_of("package_name")
             .map(String::from)
             .map(PackageName::from)
             .unwrap();
+        let name = repo.resolve_name(&name)?;
         trace!("Checking for package with name = {}", name);
 
         crate::util::filters::build_package_filter_by_name(name)
     };
 
-    let mut stdout = std::io::stdout();
-    repo.packages()
+    let packages = repo.packages()
         .filter(|package| package_filter.filter(package))
         .inspect(|pkg| trace!("Found package: {:?}", pkg))
-        .map(|pkg| writeln!(stdout, "{}", pkg.version()).map_err(Error::from))
-        .collect::<Result<Vec<_>>>()
-        .map(|_| ())
+        .sorted_by(|a, b| a.version().compare(b.version()))
+        .collect::<Vec<_>>();
+
+    let released = match packages.first() {
+        Some(package) => released_versions(&conn, package)?,
+        None => HashSet::new(),
+    };
+
+    let data = packages
+        .into_iter()
+        .map(|package| {
+            let sources = package.sources()
+                .iter()
+                .sorted_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, source)| source)
+                .collect::<Vec<_>>();
+
+            vec![
+                package.version().to_string(),
+                sources.iter().map(|source| source.url().to_string()).join(", "),
+                sources.iter()
+                    .map(|source| format!("{}:{}", source.hash().hashtype(), source.hash().value()))
+                    .join(", "),
+                if released.contains(package.version().as_str()) { "yes" } else { "no" }.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let header = crate::commands::util::mk_header(["Version", "Source", "Hash", "Released"].to_vec());
+    crate::commands::util::display_data(header, data, csv)
+}
+
+/// Get the versions of `package` that have released artifacts
+fn released_versions(conn: &PgConnection, package: &Package) -> Result<HashSet<String>> {
+    schema::jobs::table
+        .inner_join(schema::packages::table)
+        .inner_join(schema::artifacts::table)
+        .inner_join(schema::releases::table
+            .on(schema::releases::artifact_id.eq(schema::artifacts::id)))
+        .filter(schema::packages::name.eq(package.name().as_str()))
+        .select(schema::packages::version)
+        .distinct()
+        .load::<String>(conn)
+        .map(|versions| versions.into_iter().collect())
+        .map_err(anyhow::Error::from)
 }
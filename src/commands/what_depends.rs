@@ -70,6 +70,7 @@ pub async fn what_depends(
         print_sources: false,
         print_dependencies: true,
         print_patches: false,
+        print_maintainers: false,
         print_env: false,
         print_flags: false,
         print_allowed_images: false,
@@ -86,6 +87,11 @@ pub async fn what_depends(
         .map(|package| package_filter.filter(package).map(|b| (b, package)))
         .filter_ok(|(b, _)| *b)
         .map_ok(|tpl| tpl.1)
+        .filter_ok(|p| {
+            matches.value_of("maintainer")
+                .map(|pattern| crate::commands::util::is_maintained_by(p, pattern))
+                .unwrap_or(true)
+        })
         .inspect(|pkg| trace!("Found package: {:?}", pkg))
         .map_ok(|p| { // poor mans enumerate_ok()
             i += 1;
@@ -0,0 +1,80 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Result;
+use getset::CopyGetters;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// A compression format for tarballs, which is applied with the command of the same name
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionFormat {
+    Zstd,
+    Xz,
+    Gzip,
+}
+
+impl CompressionFormat {
+    /// The file name extension of tarballs in this format, including the ".tar"
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompressionFormat::Zstd => ".tar.zst",
+            CompressionFormat::Xz => ".tar.xz",
+            CompressionFormat::Gzip => ".tar.gz",
+        }
+    }
+
+    /// The command that (de)compresses this format
+    pub fn command(&self) -> &'static str {
+        match self {
+            CompressionFormat::Zstd => "zstd",
+            CompressionFormat::Xz => "xz",
+            CompressionFormat::Gzip => "gzip",
+        }
+    }
+
+    fn levels(&self) -> std::ops::RangeInclusive<u32> {
+        match self {
+            CompressionFormat::Zstd => 1..=19,
+            CompressionFormat::Xz => 0..=9,
+            CompressionFormat::Gzip => 1..=9,
+        }
+    }
+}
+
+/// How the tarballs that are collected from the jobs are compressed in the staging store
+#[derive(Clone, Debug, CopyGetters, Deserialize)]
+pub struct ArtifactCompression {
+    #[getset(get_copy = "pub")]
+    format: CompressionFormat,
+
+    /// The compression level, the default of the command if not set
+    #[getset(get_copy = "pub")]
+    level: Option<u32>,
+
+    /// Whether tarballs that are already compressed in another format are converted as well
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    recompress: bool,
+}
+
+impl ArtifactCompression {
+    pub fn check_level(&self) -> Result<()> {
+        match self.level {
+            Some(level) if !self.format.levels().contains(&level) => {
+                let levels = self.format.levels();
+                Err(anyhow!("Compression level {} out of range for {}: {} to {}", level, self.format.command(), levels.start(), levels.end()))
+            },
+            _ => Ok(()),
+        }
+    }
+}
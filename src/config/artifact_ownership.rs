@@ -0,0 +1,65 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Result;
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// The owner and permissions the artifacts collected from the jobs get in the staging store
+#[derive(Clone, Debug, CopyGetters, Getters, Deserialize)]
+pub struct ArtifactOwnership {
+    /// The user ID of the owner, the user butido runs as if not set
+    #[getset(get_copy = "pub")]
+    uid: Option<u32>,
+
+    /// The group ID of the group, the primary group of the user butido runs as if not set
+    #[getset(get_copy = "pub")]
+    gid: Option<u32>,
+
+    /// The permissions as octal number, e.g. "0644", the permissions from the container (plus
+    /// read and write for the owner) if not set
+    #[getset(get = "pub")]
+    file_mode: Option<String>,
+}
+
+impl ArtifactOwnership {
+    /// The permissions from `file_mode`
+    pub fn mode(&self) -> Result<Option<u32>> {
+        self.file_mode
+            .as_ref()
+            .map(|mode| {
+                u32::from_str_radix(mode, 8)
+                    .ok()
+                    .filter(|mode| *mode <= 0o7777)
+                    .ok_or_else(|| anyhow!("Invalid file mode '{}', expected an octal number like \"0644\"", mode))
+            })
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ownership(file_mode: &str) -> ArtifactOwnership {
+        ArtifactOwnership { uid: None, gid: None, file_mode: Some(file_mode.to_string()) }
+    }
+
+    #[test]
+    fn test_mode() {
+        assert_eq!(ownership("0644").mode().unwrap(), Some(0o644));
+        assert_eq!(ownership("640").mode().unwrap(), Some(0o640));
+        assert!(ownership("0999").mode().is_err());
+        assert!(ownership("17777").mode().is_err());
+        assert_eq!(ArtifactOwnership { uid: Some(1000), gid: None, file_mode: None }.mode().unwrap(), None);
+    }
+}
@@ -0,0 +1,47 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::collections::BTreeMap;
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+use crate::config::util::default_dependency_watchdog_timeout;
+use crate::util::EnvironmentVariableName;
+
+/// Configuration of the builds
+#[derive(Debug, CopyGetters, Getters, Deserialize)]
+pub struct BuildConfig {
+    /// Environment variables that are set in all jobs, unless the package or `--env` sets them
+    ///
+    /// These are implicitly allowed.
+    #[serde(default)]
+    #[getset(get = "pub")]
+    env: BTreeMap<EnvironmentVariableName, String>,
+
+    /// The time in seconds after which a job that waits for its dependencies checks whether the
+    /// tasks of the dependencies that did not report yet still run
+    ///
+    /// If one of them does not run anymore, the job fails instead of waiting forever. 0 disables
+    /// the check.
+    #[serde(default = "default_dependency_watchdog_timeout")]
+    #[getset(get_copy = "pub")]
+    dependency_watchdog_timeout: u64,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        BuildConfig {
+            env: BTreeMap::new(),
+            dependency_watchdog_timeout: default_dependency_watchdog_timeout(),
+        }
+    }
+}
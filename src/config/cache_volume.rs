@@ -0,0 +1,97 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+use crate::package::PackageName;
+use crate::util::docker::ImageName;
+
+/// The prefix of the names of the docker volumes of the caches
+pub const CACHE_VOLUME_PREFIX: &str = "butido-cache-";
+
+/// The docker label that holds the name of the cache a volume belongs to
+pub const CACHE_VOLUME_LABEL: &str = "butido.cache";
+
+/// Which builds share a cache volume
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheVolumeScope {
+    /// One volume for all builds
+    Global,
+
+    /// One volume per package name
+    Package,
+
+    /// One volume per image
+    Image,
+}
+
+impl Default for CacheVolumeScope {
+    fn default() -> Self {
+        CacheVolumeScope::Global
+    }
+}
+
+/// A persistent docker volume that is mounted into the build containers, e.g. for compiler caches
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct CacheVolume {
+    /// The name of the cache
+    #[getset(get = "pub")]
+    name: String,
+
+    /// Where the volume is mounted in the container
+    #[getset(get = "pub")]
+    path: String,
+
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    scope: CacheVolumeScope,
+}
+
+impl CacheVolume {
+    /// The name of the docker volume for a build of `package` in `image`
+    pub fn volume_name(&self, package: &PackageName, image: &ImageName) -> String {
+        let suffix = match self.scope {
+            CacheVolumeScope::Global => None,
+            CacheVolumeScope::Package => Some(package.as_ref()),
+            CacheVolumeScope::Image => Some(image.as_ref()),
+        };
+
+        let name = std::iter::once(self.name.as_str())
+            .chain(suffix)
+            .collect::<Vec<_>>()
+            .join("-");
+
+        // docker allows only [a-zA-Z0-9_.-] in volume names
+        let name = name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' { c } else { '_' })
+            .collect::<String>();
+
+        format!("{}{}", CACHE_VOLUME_PREFIX, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_name() {
+        let package = PackageName::from(String::from("foo"));
+        let image = ImageName::from(String::from("registry.local/debian:bullseye"));
+        let volume = |scope| CacheVolume { name: String::from("ccache"), path: String::from("/ccache"), scope };
+
+        assert_eq!(volume(CacheVolumeScope::Global).volume_name(&package, &image), "butido-cache-ccache");
+        assert_eq!(volume(CacheVolumeScope::Package).volume_name(&package, &image), "butido-cache-ccache-foo");
+        assert_eq!(volume(CacheVolumeScope::Image).volume_name(&package, &image), "butido-cache-ccache-registry.local_debian_bullseye");
+    }
+}
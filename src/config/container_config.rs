@@ -12,6 +12,9 @@ use getset::CopyGetters;
 use getset::Getters;
 use serde::Deserialize;
 
+use crate::config::CacheVolume;
+use crate::package::ContainerLimits;
+use crate::package::Network;
 use crate::util::EnvironmentVariableName;
 
 /// The configuration for the containers
@@ -25,6 +28,29 @@ pub struct ContainerConfig {
     #[getset(get = "pub")]
     allowed_env: Vec<EnvironmentVariableName>,
 
+    /// Environment variables (names) that are passed from the host environment to all containers,
+    /// if they are set
+    ///
+    /// These are implicitly allowed.
+    #[serde(default)]
+    #[getset(get = "pub")]
+    pass_env: Vec<EnvironmentVariableName>,
+
+    /// Default resource limits for all build containers, can be overridden per package
+    #[serde(default)]
+    #[getset(get = "pub")]
+    limits: ContainerLimits,
+
+    /// Default network access for all build containers, can be overridden per package
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    network: Network,
+
+    /// Persistent volumes that are mounted into all build containers
+    #[serde(default)]
+    #[getset(get = "pub")]
+    cache_volumes: Vec<CacheVolume>,
+
     /// Pass the current git author to the container
     /// This can be used to the the "packager" name in a package, for example
     #[getset(get = "pub")]
@@ -13,6 +13,8 @@ use std::collections::HashMap;
 use getset::{CopyGetters, Getters};
 use serde::Deserialize;
 
+use crate::config::util::default_health_check_interval;
+use crate::config::util::default_max_reschedules;
 use crate::config::Endpoint;
 use crate::config::EndpointName;
 use crate::util::docker::ImageName;
@@ -42,6 +44,19 @@ pub struct DockerConfig {
     #[getset(get = "pub")]
     docker_api_versions: Option<Vec<String>>,
 
+    /// Whether the docker API version is negotiated with the daemon when connecting, instead of
+    /// checking it against `docker_api_versions`
+    ///
+    /// The requests are then made with the API version of the daemon, which has to be at least
+    /// `min_docker_api_version`, if set.
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    negotiate_docker_api_version: bool,
+
+    /// The minimum docker API version, if the API version is negotiated
+    #[getset(get = "pub")]
+    min_docker_api_version: Option<String>,
+
     /// Whether the program should verify that the required images are present.
     /// You want this to be true normally.
     #[getset(get_copy = "pub")]
@@ -50,6 +65,44 @@ pub struct DockerConfig {
     #[getset(get = "pub")]
     images: Vec<ImageName>,
 
+    /// Whether images that are missing on an endpoint are pulled before the first job runs,
+    /// instead of failing when connecting to the endpoint
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    pull_missing_images: bool,
+
+    /// Whether artifacts are only reused if they were built in an image with the same ID as the
+    /// image of the job, not only with the same name
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    require_image_digest_match: bool,
+
+    /// The interval in seconds in which the endpoints are pinged during a build
+    ///
+    /// Endpoints that do not answer get no new jobs until they answer again.
+    #[serde(default = "default_health_check_interval")]
+    #[getset(get_copy = "pub")]
+    health_check_interval: u64,
+
+    /// How often a job is rescheduled onto another endpoint, because the endpoint it ran on
+    /// became unreachable while the job was running
+    #[serde(default = "default_max_reschedules")]
+    #[getset(get_copy = "pub")]
+    max_reschedules: usize,
+
+    /// How many hours the containers of failed jobs are kept on the endpoints for debugging
+    ///
+    /// If set, the containers of successful jobs are removed once their artifacts were copied,
+    /// and the containers of failed jobs are removed by the next build after they expired.
+    #[getset(get_copy = "pub")]
+    failed_container_retention_hours: Option<u64>,
+
+    /// Labels that the containers of jobs get, besides the labels butido sets (`butido.submit`,
+    /// `butido.job`, `butido.package`, `butido.version` and `butido.endpoint`)
+    #[serde(default)]
+    #[getset(get = "pub")]
+    container_labels: HashMap<String, String>,
+
     #[getset(get = "pub")]
     endpoints: HashMap<EndpointName, Endpoint>,
 }
@@ -0,0 +1,91 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// How the connection to the SMTP server is secured
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain connection, upgraded with STARTTLS
+    #[default]
+    StartTls,
+
+    /// TLS from the start ("SMTPS")
+    Tls,
+
+    /// No encryption at all, only for servers on the same host or a trusted network
+    None,
+}
+
+impl SmtpSecurity {
+    pub fn default_port(self) -> u16 {
+        match self {
+            SmtpSecurity::StartTls => 587,
+            SmtpSecurity::Tls => 465,
+            SmtpSecurity::None => 25,
+        }
+    }
+}
+
+/// The configuration of the notification mails about failed submits
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct EmailNotificationConfig {
+    /// The host name of the SMTP server
+    #[getset(get = "pub")]
+    smtp_host: String,
+
+    /// The port of the SMTP server, defaults to the usual port for `security`
+    smtp_port: Option<u16>,
+
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    security: SmtpSecurity,
+
+    /// The user to authenticate as, no authentication if not set
+    #[getset(get = "pub")]
+    username: Option<String>,
+
+    /// The name of the environment variable that holds the password of `username`
+    #[getset(get = "pub")]
+    password_env: Option<String>,
+
+    /// The address the mails are sent from
+    #[getset(get = "pub")]
+    from: String,
+
+    /// The addresses the mails are sent to
+    #[getset(get = "pub")]
+    to: Vec<String>,
+
+    /// Only send mails for submits from these branches of the repository, all branches if empty
+    #[serde(default)]
+    #[getset(get = "pub")]
+    only_branches: Vec<String>,
+
+    /// How many lines of the log of each failed job are included, defaults to
+    /// `build_error_lines`
+    #[getset(get_copy = "pub")]
+    log_lines: Option<usize>,
+}
+
+impl EmailNotificationConfig {
+    pub fn smtp_port(&self) -> u16 {
+        self.smtp_port.unwrap_or_else(|| self.security.default_port())
+    }
+
+    /// Whether mails are sent for submits from `branch`, which is `None` if HEAD is detached
+    pub fn notifies_for_branch(&self, branch: Option<&str>) -> bool {
+        self.only_branches.is_empty()
+            || branch.map(|b| self.only_branches.iter().any(|o| o == b)).unwrap_or(false)
+    }
+}
@@ -8,9 +8,14 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 use getset::{CopyGetters, Getters};
 use serde::Deserialize;
 
+use crate::util::EnvironmentVariableName;
+
 #[derive(Debug, Clone, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
 #[serde(transparent)]
 pub struct EndpointName(String);
@@ -41,6 +46,7 @@ pub struct Endpoint {
     uri: String,
 
     /// The type of the endpoint
+    #[serde(default)]
     #[getset(get = "pub")]
     endpoint_type: EndpointType,
 
@@ -51,17 +57,123 @@ pub struct Endpoint {
     #[getset(get = "pub")]
     network_mode: Option<String>,
 
+    /// Labels of the endpoint, which packages can require or prefer for their jobs, like the
+    /// name of the endpoint
+    #[serde(default)]
+    #[getset(get = "pub")]
+    labels: Vec<String>,
+
+    /// The number of CPUs of the endpoint that jobs can use, the number the docker daemon reports
+    /// if not set
+    #[getset(get_copy = "pub")]
+    cpus: Option<f64>,
+
+    /// The memory of the endpoint that jobs can use, the memory of the host if not set
+    #[getset(get_copy = "pub")]
+    memory: Option<crate::package::MemorySize>,
+
     /// Duration length of timeout for connecting endpoint
     #[getset(get = "pub")]
     timeout: Option<u64>,
+
+    /// Settings for endpoints of type "ssh" and "local"
+    #[getset(get = "pub")]
+    sandbox: Option<SandboxSettings>,
+
+    /// Environment variables that are set in all containers on this endpoint, unless the job
+    /// sets them, e.g. a proxy that is only reachable from the endpoint
+    #[serde(default)]
+    #[getset(get = "pub")]
+    env: BTreeMap<EnvironmentVariableName, String>,
+
+    /// Directories of the host that are bind-mounted into all containers on this endpoint, e.g.
+    /// a local mirror
+    #[serde(default)]
+    #[getset(get = "pub")]
+    mounts: Vec<EndpointMount>,
+}
+
+/// A directory of the host of an endpoint that is bind-mounted into the containers
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize, Eq, PartialEq)]
+pub struct EndpointMount {
+    /// The directory on the host
+    #[getset(get = "pub")]
+    source: PathBuf,
+
+    /// Where the directory is mounted in the container
+    #[getset(get = "pub")]
+    target: PathBuf,
+
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    read_only: bool,
+}
+
+impl EndpointMount {
+    /// The mount as docker bind ("SOURCE:TARGET[:ro]"), as it is recorded in the database as well
+    pub fn bind(&self) -> String {
+        let mode = if self.read_only { ":ro" } else { "" };
+        format!("{}:{}{}", self.source.display(), self.target.display(), mode)
+    }
 }
 
 /// The type of an endpoint
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
 pub enum EndpointType {
+    /// A docker daemon, reached via a `unix://`, `tcp://`, `http(s)://` or `ssh://` URI
+    #[default]
+    #[serde(rename = "docker")]
+    Docker,
+
+    /// A docker daemon, like "docker", kept for configurations that predate it
     #[serde(rename = "socket")]
     Socket,
+
+    /// A docker daemon, like "docker", kept for configurations that predate it
     #[serde(rename = "http")]
     Http,
+
+    /// A host without docker, where the builds are run in a sandbox over SSH
+    #[serde(rename = "ssh")]
+    Ssh,
+
+    /// The host butido runs on, where the builds are run in a sandbox without docker
+    #[serde(rename = "local")]
+    Local,
+}
+
+/// Settings of an endpoint of type "ssh" or "local"
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct SandboxSettings {
+    /// The directory on the host that contains one root filesystem per image, named like
+    /// the image, e.g. `<images_dir>/debian:bullseye`
+    #[getset(get = "pub")]
+    images_dir: PathBuf,
+
+    /// The directory on the host where the jobs are run and the caches are kept
+    #[getset(get = "pub")]
+    work_dir: PathBuf,
+
+    /// The sandbox the builds are run in
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    tool: SandboxTool,
+}
+
+/// The sandbox the builds on an endpoint without docker are run in
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SandboxTool {
+    /// bubblewrap, with a temporary overlay over the image root filesystem
+    Bwrap,
+
+    /// chroot into a copy of the image root filesystem, requires root on the host
+    Chroot,
+}
+
+impl Default for SandboxTool {
+    fn default() -> Self {
+        SandboxTool::Bwrap
+    }
 }
 
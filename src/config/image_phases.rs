@@ -0,0 +1,29 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::Getters;
+use serde::Deserialize;
+
+use crate::package::PhaseName;
+use crate::util::docker::ImageName;
+
+/// The phases of the builds in an image, instead of the `available_phases`
+///
+/// E.g. a bootstrap image that cannot run the test phases.
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct ImagePhases {
+    /// The image, images that are pinned to a digest match it regardless of their digest
+    #[getset(get = "pub")]
+    image: ImageName,
+
+    /// A subset of the `available_phases`, in the order they are executed
+    #[getset(get = "pub")]
+    phases: Vec<PhaseName>,
+}
@@ -0,0 +1,79 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Result;
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// Which licenses of packages are accepted by the license report
+#[derive(Clone, Debug, Default, CopyGetters, Getters, Deserialize)]
+pub struct LicensePolicy {
+    /// The licenses that are accepted, all licenses that are not denied if not set
+    #[getset(get = "pub")]
+    allowed: Option<Vec<String>>,
+
+    /// The licenses that are not accepted
+    #[serde(default)]
+    #[getset(get = "pub")]
+    denied: Vec<String>,
+
+    /// Whether packages without a license are accepted
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    allow_missing: bool,
+}
+
+impl LicensePolicy {
+    /// Whether the license `license` is accepted, the identifiers are compared ignoring case
+    pub fn is_allowed(&self, license: &str) -> bool {
+        let contains = |list: &[String]| list.iter().any(|l| l.eq_ignore_ascii_case(license));
+        !contains(&self.denied) && self.allowed.as_ref().map(|allowed| contains(allowed)).unwrap_or(true)
+    }
+
+    /// Error if a license is both allowed and denied
+    pub fn check(&self) -> Result<()> {
+        if let Some(license) = self.denied.iter().find(|l| self.allowed.as_ref().map(|a| a.contains(l)).unwrap_or(false)) {
+            return Err(anyhow!("License '{}' is both allowed and denied", license))
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_allowed() {
+        let policy = LicensePolicy {
+            allowed: None,
+            denied: vec![String::from("GPL-3.0-only")],
+            allow_missing: false,
+        };
+        assert!(policy.is_allowed("MIT"));
+        assert!(!policy.is_allowed("gpl-3.0-only"));
+
+        let policy = LicensePolicy {
+            allowed: Some(vec![String::from("MIT"), String::from("Apache-2.0")]),
+            ..policy
+        };
+        assert!(policy.is_allowed("apache-2.0"));
+        assert!(!policy.is_allowed("Zlib"));
+        assert!(policy.check().is_ok());
+
+        let policy = LicensePolicy {
+            allowed: Some(vec![String::from("GPL-3.0-only")]),
+            ..policy
+        };
+        assert!(policy.check().is_err());
+    }
+}
@@ -20,6 +20,18 @@
 //! that is not possible to do with TOML itself.
 //!
 
+mod artifact_compression;
+pub use artifact_compression::*;
+
+mod artifact_ownership;
+pub use artifact_ownership::*;
+
+mod build_config;
+pub use build_config::*;
+
+mod cache_volume;
+pub use cache_volume::*;
+
 mod configuration;
 pub use configuration::*;
 
@@ -29,10 +41,28 @@ pub use container_config::*;
 mod docker_config;
 pub use docker_config::*;
 
+mod email_config;
+pub use email_config::*;
+
 mod endpoint_config;
 pub use endpoint_config::*;
 
+mod image_phases;
+pub use image_phases::*;
+
+mod license_policy;
+pub use license_policy::*;
+
 mod not_validated;
 pub use not_validated::*;
 
+mod profile;
+pub use profile::*;
+
+mod release_remote;
+pub use release_remote::*;
+
+mod schedule;
+pub use schedule::*;
+
 mod util;
@@ -13,13 +13,25 @@ use anyhow::Context;
 use anyhow::Result;
 use getset::Getters;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 use crate::config::util::*;
+use crate::config::ArtifactCompression;
+use crate::config::ArtifactOwnership;
+use crate::config::BuildConfig;
 use crate::config::Configuration;
 use crate::config::ContainerConfig;
 use crate::config::DockerConfig;
+use crate::config::EmailNotificationConfig;
+use crate::config::ImagePhases;
+use crate::config::LicensePolicy;
+use crate::config::ReleaseRemote;
+use crate::config::RemoteKind;
+use crate::config::Schedule;
 use crate::package::PhaseName;
+use crate::package::VersionResolution;
+use crate::util::docker::ImageName;
 
 /// The configuration that is loaded from the filesystem
 #[derive(Debug, Getters, Deserialize)]
@@ -37,6 +49,22 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     log_dir: PathBuf,
 
+    /// Whether the complete logs of the jobs are written to `log_dir` instead of the database,
+    /// which only stores a reference to the log file and the last lines of the log
+    #[serde(default)]
+    #[getset(get = "pub")]
+    offload_logs: bool,
+
+    /// How many lines at the end of an offloaded log are also stored in the database
+    #[serde(default = "default_offloaded_log_lines")]
+    #[getset(get = "pub")]
+    offloaded_log_lines: usize,
+
+    /// The maximum size of the log of a job that is stored, the lines in the middle of bigger logs
+    /// are dropped
+    #[getset(get = "pub")]
+    max_log_size: Option<crate::package::MemorySize>,
+
     /// Whether the script interpolation feature should be struct, i.e. missing variables result in
     /// a failing interpolation. This should be `true` for most users.
     #[serde(default = "default_strict_script_interpolation")]
@@ -48,6 +76,11 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     progress_format: String,
 
+    /// The format of the progress bars of transfers, which count bytes
+    #[serde(default = "default_progress_bytes_format")]
+    #[getset(get = "pub")]
+    progress_bytes_format: String,
+
     /// The format of the spinners in the CLI
     #[serde(default = "default_spinner_format")]
     #[getset(get = "pub")]
@@ -61,6 +94,24 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     package_print_format: String,
 
+    /// The format used to print the rows of `db submits`, instead of a table
+    ///
+    /// This is handlebars syntax
+    #[getset(get = "pub")]
+    submit_list_format: Option<String>,
+
+    /// The format used to print the rows of `db jobs`, instead of a table
+    ///
+    /// This is handlebars syntax
+    #[getset(get = "pub")]
+    job_list_format: Option<String>,
+
+    /// The format used to print the rows of `db releases`, instead of a table
+    ///
+    /// This is handlebars syntax
+    #[getset(get = "pub")]
+    release_list_format: Option<String>,
+
     /// How many lines should be printed from the log if a build fails
     #[serde(default = "default_build_error_lines")]
     #[getset(get = "pub")]
@@ -89,6 +140,17 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     release_stores: Vec<String>,
 
+    /// The release stores that only artifacts of hermetic builds ("build --hermetic") can be
+    /// released or promoted to
+    #[serde(default)]
+    #[getset(get = "pub")]
+    hermetic_release_stores: Vec<String>,
+
+    /// The remotes the release stores are mirrored to with "release push", by their name
+    #[serde(default)]
+    #[getset(get = "pub")]
+    release_remotes: HashMap<String, ReleaseRemote>,
+
     /// The directory where intermediate ("staging") artifacts are stored.
     /// This is used as a root directory, a UUID-named directory will be added below this, using
     /// the UUID of the submit
@@ -96,11 +158,99 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     staging_directory: PathBuf,
 
+    /// Staging directories of other submits (e.g. a staging directory shared by a team) whose
+    /// artifacts are used when resolving dependencies, in the order they are searched
+    ///
+    /// These are only read from, never written to.
+    #[serde(default)]
+    #[getset(get = "pub")]
+    additional_staging_directories: Vec<PathBuf>,
+
+    /// How the tarballs collected from the jobs are compressed when they are written to the
+    /// staging store, they are stored as they are if not set
+    #[getset(get = "pub")]
+    artifact_compression: Option<ArtifactCompression>,
+
+    /// The owner and permissions of the artifacts collected from the jobs, the artifacts belong
+    /// to the user butido runs as if not set
+    #[getset(get = "pub")]
+    artifact_ownership: Option<ArtifactOwnership>,
+
+    /// Base URLs of remote release stores that the artifacts of external dependencies are
+    /// fetched from, in the order they are tried
+    #[serde(default)]
+    #[getset(get = "pub")]
+    external_artifact_sources: Vec<String>,
+
+    /// The directory the artifacts of external dependencies are cached in
+    #[getset(get = "pub")]
+    external_artifact_cache: Option<PathBuf>,
+
+    /// The command (program and arguments) to sign the provenance attestations of artifacts with
+    ///
+    /// The attestation is passed on stdin, the signature is expected on stdout.
+    #[getset(get = "pub")]
+    provenance_signing_command: Option<Vec<String>>,
+
+    /// The name of the operator that is recorded in the audit log and with promotions
+    ///
+    /// Defaults to the login name of the user.
+    #[getset(get = "pub")]
+    operator: Option<String>,
+
+    /// The identity that is recorded with every submit, like "Jane Doe <jane@example.com>"
+    ///
+    /// Defaults to the user.name and user.email of the git configuration of the repository.
+    #[getset(get = "pub")]
+    identity: Option<String>,
+
+    /// Commands (program and arguments) that run before a submit starts, with a JSON description
+    /// of the submit on stdin
+    ///
+    /// If one of them fails, the submit is not started.
+    #[serde(default)]
+    #[getset(get = "pub")]
+    pre_submit_hooks: Vec<Vec<String>>,
+
+    /// Commands (program and arguments) that run after a submit finished, with a JSON description
+    /// of the submit and its result on stdin
+    #[serde(default)]
+    #[getset(get = "pub")]
+    post_submit_hooks: Vec<Vec<String>>,
+
+    /// Commands (program and arguments) that are notified about the progress of a submit, with a
+    /// JSON description of the event on stdin
+    ///
+    /// See `crate::util::notifications`.
+    #[serde(default)]
+    #[getset(get = "pub")]
+    notification_commands: Vec<Vec<String>>,
+
+    /// Mail notifications about failed submits
+    #[getset(get = "pub")]
+    email_notifications: Option<EmailNotificationConfig>,
+
     /// Where the sources are cached
     #[serde(rename = "source_cache")]
     #[getset(get = "pub")]
     source_cache_root: PathBuf,
 
+    /// URL templates for mirrors that are tried (in order) before the upstream URL of a source
+    ///
+    /// This is handlebars syntax
+    #[serde(default)]
+    #[getset(get = "pub")]
+    source_mirrors: Vec<String>,
+
+    /// The bandwidth limit for all source downloads together, in bytes per second, with an
+    /// optional suffix ("K", "M", "G")
+    #[getset(get = "pub")]
+    source_download_rate_limit: Option<String>,
+
+    /// The maximum number of sources that are downloaded in parallel
+    #[getset(get = "pub")]
+    source_download_max_parallel: Option<usize>,
+
     /// The hostname used to connect to the database
     #[getset(get = "pub")]
     #[serde(rename = "database_host")]
@@ -134,6 +284,11 @@ pub struct NotValidatedConfiguration {
     #[getset(get = "pub")]
     docker: DockerConfig,
 
+    /// The configuration for the builds
+    #[serde(default)]
+    #[getset(get = "pub")]
+    build: BuildConfig,
+
     /// The configuration for the containers
     #[getset(get = "pub")]
     containers: ContainerConfig,
@@ -141,9 +296,36 @@ pub struct NotValidatedConfiguration {
     /// The names of the phases which should be compiled into the packaging script
     #[getset(get = "pub")]
     available_phases: Vec<PhaseName>,
+
+    /// The phases of the builds in specific images, instead of `available_phases`
+    #[serde(default)]
+    #[getset(get = "pub")]
+    image_phases: Vec<ImagePhases>,
+
+    /// How dependencies that match several versions of a package are resolved
+    #[serde(default)]
+    #[getset(get = "pub")]
+    resolver: VersionResolution,
+
+    /// Which licenses of packages are accepted by "butido license-report"
+    #[serde(default)]
+    #[getset(get = "pub")]
+    license_policy: LicensePolicy,
+
+    /// The builds that are started periodically by "butido schedule run", by their name
+    #[serde(default)]
+    #[getset(get = "pub")]
+    schedules: HashMap<String, Schedule>,
 }
 
 impl NotValidatedConfiguration {
+    /// The phases of builds in `image`, the `image_phases` of the image or the `available_phases`
+    pub fn phases_for_image(&self, image: Option<&ImageName>) -> &Vec<PhaseName> {
+        image.and_then(|image| self.image_phases.iter().find(|p| p.image().matches(image)))
+            .map(|p| p.phases())
+            .unwrap_or(&self.available_phases)
+    }
+
     /// Validate the NotValidatedConfiguration object and make it into a Configuration object, if
     /// validation succeeds
     ///
@@ -180,6 +362,30 @@ impl NotValidatedConfiguration {
             return Err(anyhow!("You need at least one release store in 'release_stores'"))
         }
 
+        if let Some(store) = self.hermetic_release_stores.iter().find(|s| !self.release_stores.contains(s)) {
+            return Err(anyhow!("Unknown release store '{}' in 'hermetic_release_stores'", store));
+        }
+
+        for (name, remote) in self.release_remotes.iter() {
+            if let Some(store) = remote.auto_push().iter().find(|s| !self.release_stores.contains(s)) {
+                return Err(anyhow!("Release remote '{}' pushes unknown release store '{}'", name, store));
+            }
+            if remote.authorization_env().is_some() && remote.kind() != RemoteKind::Http {
+                return Err(anyhow!("'authorization_env' of release remote '{}' is only used for HTTP remotes", name));
+            }
+        }
+
+        if let Some(compression) = self.artifact_compression.as_ref() {
+            compression.check_level().context("Checking 'artifact_compression'")?;
+        }
+        if let Some(ownership) = self.artifact_ownership.as_ref() {
+            ownership.mode().context("Checking 'artifact_ownership'")?;
+        }
+        self.license_policy.check().context("Checking 'license_policy'")?;
+        for (name, schedule) in self.schedules.iter() {
+            schedule.cron().with_context(|| anyhow!("Checking schedule '{}'", name))?;
+        }
+
         // Error if source_cache_root is not a directory
         if !self.source_cache_root.is_dir() {
             return Err(anyhow!(
@@ -188,13 +394,50 @@ impl NotValidatedConfiguration {
             ));
         }
 
+        if self.pre_submit_hooks.iter().chain(self.post_submit_hooks.iter()).any(Vec::is_empty) {
+            return Err(anyhow!("Empty command in 'pre_submit_hooks' or 'post_submit_hooks'"));
+        }
+
+        for image in self.docker.images() {
+            image.check_digest().context("Checking 'docker.images'")?;
+        }
+
+        if self.notification_commands.iter().any(Vec::is_empty) {
+            return Err(anyhow!("Empty command in 'notification_commands'"));
+        }
+
+        if let Some(email) = self.email_notifications.as_ref() {
+            if email.to().is_empty() {
+                return Err(anyhow!("No recipients configured in 'email_notifications.to'"));
+            }
+            if email.username().is_some() && email.password_env().is_none() {
+                return Err(anyhow!("'email_notifications.username' is set, but 'email_notifications.password_env' is not"));
+            }
+        }
+
         // Error if there are no phases configured
         if self.available_phases.is_empty() {
             return Err(anyhow!("No phases configured"));
         }
+        for image_phases in self.image_phases.iter() {
+            let image = image_phases.image();
+            if image_phases.phases().is_empty() {
+                return Err(anyhow!("No phases configured for image {} in 'image_phases'", image));
+            }
+            if let Some(phase) = image_phases.phases().iter().find(|phase| !self.available_phases.contains(phase)) {
+                return Err(anyhow!("Phase '{}' of image {} in 'image_phases' is not in 'available_phases'", phase.as_str(), image));
+            }
+            if self.image_phases.iter().filter(|other| other.image() == image).count() > 1 {
+                return Err(anyhow!("Image {} is configured more than once in 'image_phases'", image));
+            }
+        }
 
         // Error if script highlighting theme is not valid
-        if let Some(configured_theme) = self.script_highlight_theme.as_ref() {
+        if let Some(configured_theme) = self.script_highlight_theme.as_ref().filter(|t| crate::package::is_theme_file(t)) {
+            syntect::highlighting::ThemeSet::get_theme(configured_theme)
+                .map_err(|e| anyhow!("Failed to load theme file {}: {}", configured_theme, e))
+                .context("Checking 'script_highlight_theme'")?;
+        } else if let Some(configured_theme) = self.script_highlight_theme.as_ref() {
             let allowed_theme_present = [
                 // from syntect
                 "base16-ocean.dark",
@@ -209,7 +452,10 @@ impl NotValidatedConfiguration {
             .any(|allowed_theme| configured_theme == *allowed_theme);
 
             if !allowed_theme_present {
-                return Err(anyhow!("Theme not known: {}", configured_theme));
+                return Err(anyhow!(
+                    "Theme not known: {} (use one of the bundled themes or the path of a .tmTheme file)",
+                    configured_theme
+                ));
             }
         }
 
@@ -0,0 +1,100 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Named sets of settings in the `profile` table of the configuration, which override the other
+//! settings when the profile is selected
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+/// The key of the table that holds the profiles
+pub const PROFILES_KEY: &str = "profile";
+
+/// The settings of a selected profile, as a source for the configuration
+#[derive(Clone, Debug)]
+pub struct ProfileSource(HashMap<String, ::config::Value>);
+
+impl ProfileSource {
+    /// Get the settings of the profile `name` from the (not yet complete) configuration
+    pub fn load(config: &::config::Config, name: &str) -> Result<Self> {
+        let profiles = config
+            .get_table(PROFILES_KEY)
+            .unwrap_or_default();
+
+        profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| {
+                let mut available = profiles.keys().map(String::as_str).collect::<Vec<_>>();
+                available.sort_unstable();
+                anyhow!("Profile '{}' not found, available profiles: [{}]", name, available.join(", "))
+            })?
+            .into_table()
+            .map(ProfileSource)
+            .map_err(|e| anyhow!("Profile '{}' is not a table: {}", name, e))
+    }
+}
+
+impl ::config::Source for ProfileSource {
+    fn clone_into_box(&self) -> Box<dyn ::config::Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<HashMap<String, ::config::Value>, ::config::ConfigError> {
+        Ok(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ::config::Config {
+        let mut config = ::config::Config::default();
+        config
+            .merge(::config::File::from_str(
+                indoc::indoc!(r#"
+                    database_host = "db.example.com"
+                    database_port = 5432
+
+                    [docker.endpoints.prod]
+                    uri = "http://prod:2375"
+
+                    [profile.local]
+                    database_host = "localhost"
+
+                    [profile.local.docker.endpoints.prod]
+                    uri = "http://localhost:2375"
+                "#),
+                ::config::FileFormat::Toml,
+            ))
+            .unwrap();
+        config
+    }
+
+    #[test]
+    fn test_profile_overrides_settings() {
+        let mut config = config();
+        let profile = ProfileSource::load(&config, "local").unwrap();
+        config.merge(profile).unwrap();
+
+        assert_eq!(config.get_str("database_host").unwrap(), "localhost");
+        assert_eq!(config.get_int("database_port").unwrap(), 5432);
+        assert_eq!(config.get_str("docker.endpoints.prod.uri").unwrap(), "http://localhost:2375");
+    }
+
+    #[test]
+    fn test_unknown_profile() {
+        let err = ProfileSource::load(&config(), "prod").unwrap_err();
+        assert_eq!(err.to_string(), "Profile 'prod' not found, available profiles: [local]");
+    }
+}
@@ -0,0 +1,53 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use getset::CopyGetters;
+use getset::Getters;
+use serde::Deserialize;
+
+/// How the artifacts are transferred to a remote
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteKind {
+    /// With the `rsync` command, over SSH or to an rsync daemon
+    Rsync,
+
+    /// With the `sftp` command
+    Sftp,
+
+    /// With HTTP PUT requests
+    Http,
+}
+
+/// A remote that the artifacts of a release store are mirrored to, e.g. a deployment server
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+pub struct ReleaseRemote {
+    #[getset(get_copy = "pub")]
+    kind: RemoteKind,
+
+    /// Where the artifacts are pushed to
+    ///
+    /// For rsync a destination like "user@host:/srv/packages" or "rsync://host/module", for sftp
+    /// "user@host:/srv/packages" and for HTTP the base URL the paths of the artifacts are appended
+    /// to.
+    #[getset(get = "pub")]
+    target: String,
+
+    /// The name of the environment variable that holds the value of the "Authorization" header
+    /// of the HTTP requests
+    #[getset(get = "pub")]
+    authorization_env: Option<String>,
+
+    /// The release stores whose newly released artifacts are pushed to this remote by
+    /// "release new"
+    #[serde(default)]
+    #[getset(get = "pub")]
+    auto_push: Vec<String>,
+}
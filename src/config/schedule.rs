@@ -0,0 +1,56 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::Result;
+use getset::Getters;
+use serde::Deserialize;
+
+use crate::util::cron::CronSchedule;
+
+/// A build that is started periodically by "butido schedule run", e.g. a nightly rebuild
+#[derive(Clone, Debug, Getters, Deserialize)]
+pub struct Schedule {
+    /// When the build is started, as cron-like schedule in local time, e.g. "30 2 * * *"
+    #[getset(get = "pub")]
+    at: String,
+
+    /// The package that is built
+    #[getset(get = "pub")]
+    package: String,
+
+    /// The version of the package, the newest version if not set
+    #[getset(get = "pub")]
+    version: Option<String>,
+
+    /// The image the package is built in
+    #[getset(get = "pub")]
+    image: String,
+
+    /// Further arguments for "butido build", e.g. `["--env", "CFLAGS=-O3"]`
+    #[serde(default)]
+    #[getset(get = "pub")]
+    build_args: Vec<String>,
+}
+
+impl Schedule {
+    pub fn cron(&self) -> Result<CronSchedule> {
+        CronSchedule::parse(&self.at)
+    }
+
+    /// The arguments for "butido build" to start the build
+    pub fn build_command_args(&self) -> Vec<String> {
+        let mut args = vec![String::from("build"), self.package.clone()];
+        args.extend(self.version.iter().cloned());
+        args.push(String::from("--image"));
+        args.push(self.image.clone());
+        args.extend(self.build_args.iter().cloned());
+        args
+    }
+}
@@ -16,6 +16,11 @@ pub fn default_progress_format() -> String {
     String::from("[{elapsed_precise}] ({percent:>3}%): {bar:40.cyan/blue} | {msg}")
 }
 
+/// The default format of the progress bars that count bytes
+pub fn default_progress_bytes_format() -> String {
+    String::from("[{elapsed_precise}] {bar:30.cyan/blue} {bytes}/{total_bytes} ({binary_bytes_per_sec}, {eta}) | {msg}")
+}
+
 /// The default spinner format
 pub fn default_spinner_format() -> String {
     String::from("[{elapsed_precise}] {spinner} | {msg}")
@@ -30,6 +35,13 @@ pub fn default_package_print_format() -> String {
 
             ==================================
 
+            {{#if print_maintainers}}
+            Maintainers:
+            {{#each p.maintainers}}
+                {{this}}
+            {{/each}}
+            {{/if~}}
+
             {{#if print_sources}}
             Sources:
             {{#each p.sources}}
@@ -115,3 +127,23 @@ pub fn default_script_shebang() -> String {
 pub fn default_build_error_lines() -> usize {
     10
 }
+
+/// The default number of lines of an offloaded log that are stored in the database
+pub fn default_offloaded_log_lines() -> usize {
+    100
+}
+
+/// The default value for the interval in seconds in which the endpoints are checked during a build
+pub fn default_health_check_interval() -> u64 {
+    30
+}
+
+/// The default value for how often a job is rescheduled because its endpoint became unreachable
+pub fn default_max_reschedules() -> usize {
+    2
+}
+
+/// The default value for the time in seconds after which waiting jobs check their dependencies
+pub fn default_dependency_watchdog_timeout() -> u64 {
+    600
+}
@@ -9,6 +9,10 @@
 //
 
 
+/// The name of the optional configuration file in the package repository root, which is merged over
+/// the configuration of the user
+pub const REPO_CONFIG_OVERLAY_FILE: &str = ".butido.toml";
+
 /// The path to the directory inside the container where the inputs for the script run are copied
 /// to.
 pub const INPUTS_DIR_PATH: &str  = "/inputs";
@@ -20,6 +24,10 @@ pub const OUTPUTS_DIR_NAME: &str = "outputs";
 
 pub const PATCH_DIR_PATH: &str = "/patches";
 
+/// The name of the environment variable inside the container which holds the (space separated)
+/// paths of the patches of the package
+pub const PATCHES_ENV_VAR: &str = "BUTIDO_PATCHES";
+
 /// The path where the script that is executed inside the container is copied to.
 pub const SCRIPT_PATH: &str      = "/script";
 
@@ -15,11 +15,16 @@ use anyhow::Result;
 use clap::ArgMatches;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::Pool;
 use getset::Getters;
 use log::debug;
 
 use crate::config::Configuration;
 
+/// A pool of connections to the database
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+
 #[derive(Getters)]
 pub struct DbConnectionConfig<'a> {
     #[getset(get = "pub")]
@@ -78,9 +83,8 @@ impl<'a> DbConnectionConfig<'a> {
         })
     }
 
-    pub fn establish_connection(self) -> Result<PgConnection> {
-        debug!("Trying to connect to database: {:?}", self);
-        let database_uri: String = format!(
+    fn database_uri(&self) -> String {
+        format!(
             "postgres://{user}:{password}@{host}:{port}/{name}?connect_timeout={timeout}",
             host = self.database_host,
             port = self.database_port,
@@ -88,9 +92,43 @@ impl<'a> DbConnectionConfig<'a> {
             password = self.database_password,
             name = self.database_name,
             timeout = self.database_connection_timeout,
-        );
-        PgConnection::establish(&database_uri).map_err(Error::from)
+        )
+    }
+
+    pub fn establish_connection(self) -> Result<PgConnection> {
+        debug!("Trying to connect to database: {:?}", self);
+        PgConnection::establish(&self.database_uri())
+            .map_err(|e| crate::error::Error::Database(Error::from(e)).into())
+    }
+
+    /// Build a pool of database connections
+    ///
+    /// This is used where multiple jobs access the database concurrently, so they don't have to
+    /// share a single connection.
+    pub fn establish_pool(self) -> Result<DbPool> {
+        debug!("Trying to build connection pool for database: {:?}", self);
+        let manager = ConnectionManager::<PgConnection>::new(self.database_uri());
+        Pool::builder()
+            .connection_timeout(std::time::Duration::from_secs(self.database_connection_timeout as u64))
+            .build(manager)
+            .map_err(|e| crate::error::Error::Database(Error::from(e)).into())
     }
 
 }
 
+/// Run a blocking database operation on a connection from the pool
+///
+/// Diesel is synchronous, so the operation is executed via `tokio::task::spawn_blocking()` to not
+/// block the async runtime while many jobs write to the database concurrently.
+pub async fn with_connection<F, T>(pool: &DbPool, f: F) -> Result<T>
+where
+    F: FnOnce(&PgConnection) -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || {
+        let conn = pool.get()?;
+        f(&conn)
+    })
+    .await?
+}
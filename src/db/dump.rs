@@ -0,0 +1,407 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! A dump of the database, for moving the data of one butido instance to another
+//!
+//! The rows of the dump have their own types instead of reusing the models, so that the format
+//! of the dump does not change when the models change.
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::schema::*;
+
+/// The version of the dump format, increased whenever the format changes incompatibly
+pub const DUMP_FORMAT_VERSION: u32 = 1;
+
+/// Postgres limits the number of parameters of a statement, so rows are inserted in chunks
+const INSERT_CHUNK_SIZE: usize = 1000;
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "endpoints"]
+pub struct EndpointRow {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "envvars"]
+pub struct EnvVarRow {
+    pub id: i32,
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "githashes"]
+pub struct GitHashRow {
+    pub id: i32,
+    pub hash: String,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "images"]
+pub struct ImageRow {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "packages"]
+pub struct PackageRow {
+    pub id: i32,
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "release_stores"]
+pub struct ReleaseStoreRow {
+    pub id: i32,
+    pub store_name: String,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "submits"]
+pub struct SubmitRow {
+    pub id: i32,
+    pub uuid: ::uuid::Uuid,
+    pub submit_time: NaiveDateTime,
+    pub requested_image_id: i32,
+    pub requested_package_id: i32,
+    pub repo_hash_id: i32,
+
+    /// Not part of dumps of databases from before the submitter was recorded
+    #[serde(default)]
+    pub submitted_by: Option<String>,
+
+    /// Not part of dumps of databases from before the phases of submits were recorded
+    #[serde(default)]
+    pub phases: Option<Vec<String>>,
+
+    /// Not part of dumps of databases from before the pins of submits were recorded
+    #[serde(default)]
+    pub pins: Option<Vec<String>>,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "submit_envs"]
+pub struct SubmitEnvRow {
+    pub id: i32,
+    pub submit_id: i32,
+    pub env_id: i32,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "jobs"]
+pub struct JobRow {
+    pub id: i32,
+    pub submit_id: i32,
+    pub endpoint_id: i32,
+    pub package_id: i32,
+    pub image_id: i32,
+    pub container_hash: String,
+    pub script_text: String,
+    pub log_text: String,
+    pub uuid: ::uuid::Uuid,
+    pub patches_hash: Option<String>,
+    pub options: Option<String>,
+
+    /// Not part of dumps of databases from before image digests were recorded
+    #[serde(default)]
+    pub image_digest: Option<String>,
+
+    /// Not part of dumps of databases from before the settings of endpoints were recorded
+    #[serde(default)]
+    pub endpoint_env: Vec<String>,
+
+    #[serde(default)]
+    pub endpoint_mounts: Vec<String>,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "job_envs"]
+pub struct JobEnvRow {
+    pub id: i32,
+    pub job_id: i32,
+    pub env_id: i32,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "job_phases"]
+pub struct JobPhaseRow {
+    pub id: i32,
+    pub job_id: i32,
+    pub position: i32,
+    pub name: String,
+    pub started: NaiveDateTime,
+    pub duration_secs: f64,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "artifacts"]
+pub struct ArtifactRow {
+    pub id: i32,
+    pub path: String,
+    pub job_id: i32,
+    pub output: Option<String>,
+    pub sha256: Option<String>,
+
+    /// Not part of dumps of databases from before artifact sizes were recorded
+    #[serde(default)]
+    pub size: Option<i64>,
+
+    /// Not part of dumps of databases from before hermetic builds
+    #[serde(default)]
+    pub hermetic: bool,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "releases"]
+pub struct ReleaseRow {
+    pub id: i32,
+    pub artifact_id: i32,
+    pub release_date: NaiveDateTime,
+    pub release_store_id: i32,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "release_promotions"]
+pub struct ReleasePromotionRow {
+    pub id: i32,
+    pub release_id: i32,
+    pub from_release_store_id: i32,
+    pub promoted_by: String,
+    pub promoted_at: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "audit"]
+pub struct AuditRow {
+    pub id: i32,
+    pub recorded_at: NaiveDateTime,
+    pub username: String,
+    pub operation: String,
+    pub parameters: serde_json::Value,
+}
+
+/// All persistent data of a database
+///
+/// Running jobs, the recorded job states of submits (for resuming them) and the cancellations of
+/// submits are not part of the dump, they only matter to the instance that runs them.
+/// The tables are ordered so that every row only refers to rows of the tables before it, and the
+/// rows of each table are ordered by their id, so that dumping the same data always results in
+/// the same dump.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dump {
+    pub format_version: u32,
+    pub endpoints: Vec<EndpointRow>,
+    pub envvars: Vec<EnvVarRow>,
+    pub githashes: Vec<GitHashRow>,
+    pub images: Vec<ImageRow>,
+    pub packages: Vec<PackageRow>,
+    pub release_stores: Vec<ReleaseStoreRow>,
+    pub submits: Vec<SubmitRow>,
+    pub submit_envs: Vec<SubmitEnvRow>,
+    pub jobs: Vec<JobRow>,
+    pub job_envs: Vec<JobEnvRow>,
+
+    /// Not part of dumps of databases from before phase timings were recorded
+    #[serde(default)]
+    pub job_phases: Vec<JobPhaseRow>,
+    pub artifacts: Vec<ArtifactRow>,
+    pub releases: Vec<ReleaseRow>,
+
+    /// Not part of dumps of databases from before promotions were recorded
+    #[serde(default)]
+    pub release_promotions: Vec<ReleasePromotionRow>,
+
+    /// Not part of dumps of databases from before the audit log was recorded
+    #[serde(default)]
+    pub audit: Vec<AuditRow>,
+}
+
+macro_rules! load_table {
+    ($conn:expr, $table:ident) => {
+        $table::table
+            .order($table::id.asc())
+            .load($conn)
+            .with_context(|| anyhow!("Loading table {}", stringify!($table)))?
+    };
+}
+
+macro_rules! restore_table {
+    ($conn:expr, $table:ident, $rows:expr) => {
+        for chunk in $rows.chunks(INSERT_CHUNK_SIZE) {
+            diesel::insert_into($table::table)
+                .values(chunk)
+                .execute($conn)
+                .with_context(|| anyhow!("Inserting into table {}", stringify!($table)))?;
+        }
+
+        // The ids were inserted explicitly, so the sequence has to continue after them
+        diesel::sql_query(format!(
+            "SELECT setval(pg_get_serial_sequence('{table}', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM {table}",
+            table = stringify!($table)
+        ))
+        .execute($conn)
+        .with_context(|| anyhow!("Resetting id sequence of table {}", stringify!($table)))?;
+    };
+}
+
+macro_rules! ensure_empty {
+    ($conn:expr, $table:ident) => {
+        if diesel::select(diesel::dsl::exists($table::table.select($table::id))).get_result::<bool>($conn)? {
+            return Err(anyhow!("Table {} is not empty, a dump can only be imported into an empty database", stringify!($table)))
+        }
+    };
+}
+
+impl Dump {
+    /// Load all data from the database
+    pub fn load(conn: &PgConnection) -> Result<Dump> {
+        conn.transaction::<_, Error, _>(|| {
+            Ok(Dump {
+                format_version: DUMP_FORMAT_VERSION,
+                endpoints: load_table!(conn, endpoints),
+                envvars: load_table!(conn, envvars),
+                githashes: load_table!(conn, githashes),
+                images: load_table!(conn, images),
+                packages: load_table!(conn, packages),
+                release_stores: load_table!(conn, release_stores),
+                submits: load_table!(conn, submits),
+                submit_envs: load_table!(conn, submit_envs),
+                jobs: load_table!(conn, jobs),
+                job_envs: load_table!(conn, job_envs),
+                job_phases: load_table!(conn, job_phases),
+                artifacts: load_table!(conn, artifacts),
+                releases: load_table!(conn, releases),
+                release_promotions: load_table!(conn, release_promotions),
+                audit: load_table!(conn, audit),
+            })
+        })
+    }
+
+    /// Insert all data of the dump into the database, which has to be empty
+    ///
+    /// The rows keep their ids, so that references between them stay valid.
+    pub fn restore(&self, conn: &PgConnection) -> Result<()> {
+        if self.format_version != DUMP_FORMAT_VERSION {
+            return Err(anyhow!("Unsupported dump format version {}, expected version {}",
+                self.format_version, DUMP_FORMAT_VERSION))
+        }
+
+        conn.transaction::<_, Error, _>(|| {
+            ensure_empty!(conn, endpoints);
+            ensure_empty!(conn, envvars);
+            ensure_empty!(conn, githashes);
+            ensure_empty!(conn, images);
+            ensure_empty!(conn, packages);
+            ensure_empty!(conn, release_stores);
+            ensure_empty!(conn, submits);
+            ensure_empty!(conn, submit_envs);
+            ensure_empty!(conn, jobs);
+            ensure_empty!(conn, job_envs);
+            ensure_empty!(conn, job_phases);
+            ensure_empty!(conn, artifacts);
+            ensure_empty!(conn, releases);
+            ensure_empty!(conn, release_promotions);
+            ensure_empty!(conn, audit);
+
+            restore_table!(conn, endpoints, self.endpoints);
+            restore_table!(conn, envvars, self.envvars);
+            restore_table!(conn, githashes, self.githashes);
+            restore_table!(conn, images, self.images);
+            restore_table!(conn, packages, self.packages);
+            restore_table!(conn, release_stores, self.release_stores);
+            restore_table!(conn, submits, self.submits);
+            restore_table!(conn, submit_envs, self.submit_envs);
+            restore_table!(conn, jobs, self.jobs);
+            restore_table!(conn, job_envs, self.job_envs);
+            restore_table!(conn, job_phases, self.job_phases);
+            restore_table!(conn, artifacts, self.artifacts);
+            restore_table!(conn, releases, self.releases);
+            restore_table!(conn, release_promotions, self.release_promotions);
+            restore_table!(conn, audit, self.audit);
+            Ok(())
+        })
+    }
+
+    /// The number of rows per table, for reporting what was exported or imported
+    pub fn row_counts(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            ("endpoints", self.endpoints.len()),
+            ("envvars", self.envvars.len()),
+            ("githashes", self.githashes.len()),
+            ("images", self.images.len()),
+            ("packages", self.packages.len()),
+            ("release_stores", self.release_stores.len()),
+            ("submits", self.submits.len()),
+            ("submit_envs", self.submit_envs.len()),
+            ("jobs", self.jobs.len()),
+            ("job_envs", self.job_envs.len()),
+            ("job_phases", self.job_phases.len()),
+            ("artifacts", self.artifacts.len()),
+            ("releases", self.releases.len()),
+            ("release_promotions", self.release_promotions.len()),
+            ("audit", self.audit.len()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_roundtrip() {
+        let dump = Dump {
+            format_version: DUMP_FORMAT_VERSION,
+            endpoints: vec![EndpointRow { id: 1, name: String::from("local") }],
+            envvars: vec![],
+            githashes: vec![GitHashRow { id: 1, hash: String::from("abc") }],
+            images: vec![ImageRow { id: 1, name: String::from("debian:bullseye") }],
+            packages: vec![PackageRow { id: 1, name: String::from("a"), version: String::from("1.0") }],
+            release_stores: vec![],
+            submits: vec![SubmitRow {
+                id: 1,
+                uuid: ::uuid::Uuid::nil(),
+                submit_time: NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
+                requested_image_id: 1,
+                requested_package_id: 1,
+                repo_hash_id: 1,
+                submitted_by: Some(String::from("Jane Doe <jane@example.com>")),
+                phases: Some(vec![String::from("build")]),
+                pins: Some(vec![]),
+            }],
+            submit_envs: vec![],
+            jobs: vec![],
+            job_envs: vec![],
+            job_phases: vec![],
+            artifacts: vec![],
+            releases: vec![],
+            release_promotions: vec![],
+            audit: vec![],
+        };
+
+        let json = serde_json::to_string_pretty(&dump).unwrap();
+        let parsed: Dump = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string_pretty(&parsed).unwrap(), json);
+        assert_eq!(parsed.submits[0].submit_time, dump.submits[0].submit_time);
+        assert_eq!(parsed.row_counts().iter().map(|(_, n)| n).sum::<usize>(), 5);
+    }
+}
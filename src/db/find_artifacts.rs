@@ -8,6 +8,7 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -20,7 +21,12 @@ use diesel::JoinOnDsl;
 use diesel::PgConnection;
 use diesel::QueryDsl;
 use diesel::RunQueryDsl;
+use diesel::dsl::sql;
+use diesel::sql_types::Array;
+use diesel::sql_types::Bool;
+use diesel::sql_types::Text;
 use log::trace;
+use log::warn;
 use resiter::AndThen;
 use resiter::FilterMap;
 
@@ -28,9 +34,14 @@ use crate::config::Configuration;
 use crate::db::models as dbmodels;
 use crate::filestore::path::ArtifactPath;
 use crate::filestore::path::FullArtifactPath;
+use crate::filestore::MergedStores;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
 use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::PackageVersionConstraint;
+use crate::package::ParseDependency;
 use crate::package::ScriptBuilder;
 use crate::package::Shebang;
 use crate::schema;
@@ -52,7 +63,7 @@ use crate::util::docker::ImageName;
 #[derive(typed_builder::TypedBuilder)]
 pub struct FindArtifacts<'a> {
     config: &'a Configuration,
-    database_connection: Arc<PgConnection>,
+    database_connection: &'a PgConnection,
 
     /// The release stores to search in
     release_stores: &'a [Arc<ReleaseStore>],
@@ -61,6 +72,10 @@ pub struct FindArtifacts<'a> {
     #[builder(default)]
     staging_store: Option<&'a StagingStore>,
 
+    /// Further staging stores to search in, after the staging store
+    #[builder(default)]
+    additional_staging_stores: &'a [Arc<StagingStore>],
+
     /// Whether to apply a filter that matches for equal script
     ///
     /// If a job can be found, but the script is not equal to the script of the found, the job is
@@ -74,6 +89,16 @@ pub struct FindArtifacts<'a> {
     #[builder(default)]
     image_name: Option<&'a ImageName>,
 
+    /// Filter for the ID of the image the job ran in ("sha256:...")
+    ///
+    /// Jobs that were recorded before image IDs were recorded never match.
+    #[builder(default)]
+    image_digest: Option<&'a str>,
+
+    /// Only find artifacts of hermetic builds, whose files still have the recorded hash
+    #[builder(default)]
+    hermetic_only: bool,
+
     /// Search for this package
     package: &'a Package,
 }
@@ -85,7 +110,7 @@ impl<'a> FindArtifacts<'a> {
         let script = if self.script_filter {
             let script = ScriptBuilder::new(&shebang).build(
                 self.package,
-                self.config.available_phases(),
+                self.config.phases_for_image(self.image_name),
                 *self.config.strict_script_interpolation(),
             )?;
             Some(script)
@@ -94,6 +119,13 @@ impl<'a> FindArtifacts<'a> {
         };
 
         let package_environment = self.package.environment();
+        let dependency_constraints = self.package
+            .dependencies()
+            .build()
+            .iter()
+            .map(ParseDependency::parse_as_name_and_version)
+            .chain(self.package.dependencies().runtime().iter().map(ParseDependency::parse_as_name_and_version))
+            .collect::<Result<Vec<_>>>()?;
         let mut query = schema::packages::table
             .filter({
                 // The package with pkg.name() and pkg.version()
@@ -104,8 +136,7 @@ impl<'a> FindArtifacts<'a> {
                 package_name_filter.and(package_version_filter)
             })
 
-            // TODO: Only select from submits where the submit contained jobs that are in the
-            // dependencies of `pkg`.
+            // The versions of the dependencies the jobs were built with are checked below
             .inner_join(schema::jobs::table.inner_join(schema::submits::table))
             .inner_join(schema::artifacts::table.on(schema::jobs::id.eq(schema::artifacts::job_id)))
 
@@ -142,8 +173,63 @@ impl<'a> FindArtifacts<'a> {
             query = query.filter(schema::images::name.eq(image_name.as_ref()));
         }
 
+        if let Some(image_digest) = self.image_digest {
+            query = query.filter(schema::jobs::image_digest.eq(image_digest));
+        }
+
+        if self.hermetic_only {
+            query = query.filter(schema::artifacts::hermetic.eq(true));
+        }
+
+        // Artifacts are only equal if they were built with the same patches
+        if let Some(patches_hash) = self.package.patches_hash()? {
+            query = query.filter(schema::jobs::patches_hash.eq(patches_hash));
+        } else {
+            query = query.filter(schema::jobs::patches_hash.is_null());
+        }
+
+        // ... and with the same build options
+        if let Some(options) = self.package.options_string() {
+            query = query.filter(schema::jobs::options.eq(options));
+        } else {
+            query = query.filter(schema::jobs::options.is_null());
+        }
+
+        // ... and with the same environment: All environment variables of the package and the
+        // additional ones must have been set in the job, and no other variables.
+        let (env_names, env_values): (Vec<&str>, Vec<&str>) = package_environment
+            .iter()
+            .flat_map(|hm| hm.iter())
+            .chain(self.env_filter.iter().map(|(k, v)| (k, v)))
+            .map(|(k, v)| (k.as_ref(), v.as_str()))
+            .unzip();
+        trace!("Filtering with env = {:?}", env_names.iter().zip(env_values.iter()).collect::<Vec<_>>());
+
+        query = query.filter({
+            sql::<Bool>("NOT EXISTS (SELECT 1 FROM unnest(")
+                .bind::<Array<Text>, _>(env_names.clone())
+                .sql(", ")
+                .bind::<Array<Text>, _>(env_values.clone())
+                .sql(") AS expected(name, value) WHERE NOT EXISTS (\
+                    SELECT 1 FROM job_envs INNER JOIN envvars ON job_envs.env_id = envvars.id \
+                    WHERE job_envs.job_id = jobs.id AND envvars.name = expected.name AND envvars.value = expected.value\
+                ))")
+        });
+        query = query.filter({
+            sql::<Bool>("NOT EXISTS (\
+                    SELECT 1 FROM job_envs INNER JOIN envvars ON job_envs.env_id = envvars.id \
+                    WHERE job_envs.job_id = jobs.id AND NOT EXISTS (SELECT 1 FROM unnest(")
+                .bind::<Array<Text>, _>(env_names)
+                .sql(", ")
+                .bind::<Array<Text>, _>(env_values)
+                .sql(") AS expected(name, value) WHERE expected.name = envvars.name AND expected.value = envvars.value\
+                ))")
+        });
+
         trace!("Query = {}", diesel::debug_query(&query));
 
+        let stores = MergedStores::new(self.staging_store, self.additional_staging_stores, self.release_stores);
+
         query
             .select({
                 let arts = schema::artifacts::all_columns;
@@ -152,118 +238,215 @@ impl<'a> FindArtifacts<'a> {
 
                 (arts, jobs)
             })
-            .load::<(dbmodels::Artifact, dbmodels::Job)>(&*self.database_connection)?
+            .load::<(dbmodels::Artifact, dbmodels::Job)>(self.database_connection)?
             .into_iter()
             .inspect(|(art, job)| log::debug!("Filtering further: {:?}, job {:?}", art, job.id))
             //
-            // Filter by environment variables
-            // All environment variables of the package must be present in the loaded
-            // package, so that we can be sure that the loaded package was built with
-            // the same ENV.
+            // Filter by the versions of the dependencies the jobs were built with
             //
-            // TODO:
-            // Doing this in the database query would be way nicer, but I was not able
-            // to implement it.
-            //
-            .map(|tpl| -> Result<(_, _)> {
+            .map(|(art, job)| -> Result<(_, _)> {
                 // This is a Iterator::filter() but because our condition here might fail, we
                 // map() and do the actual filtering later.
-
-                let job = tpl.1;
-                let job_env: Vec<(String, String)> = job
-                    .env(&*self.database_connection)?
-                    .into_iter()
-                    .map(|var: dbmodels::EnvVar| (var.name, var.value))
-                    .collect();
-
-                trace!("The job we found had env: {:?}", job_env);
-                let envs_equal = environments_equal(&job_env, package_environment.as_ref(), self.env_filter);
-                trace!("environments where equal = {}", envs_equal);
-                Ok((tpl.0, envs_equal))
+                let dependencies_match = dependency_versions_match(
+                    &job_dependencies(self.database_connection, &job)?,
+                    &dependency_constraints,
+                );
+                trace!("dependency versions matched = {}", dependencies_match);
+                Ok((art, dependencies_match))
             })
             .filter(|r| match r { // the actual filtering from above
                 Err(_)         => true,
                 Ok((_, bl)) => *bl,
             })
             .and_then_ok(|(art, _)| {
-                if let Some(release) = art.get_release(&*self.database_connection)? {
+                if let Some(release) = art.get_release(self.database_connection)? {
                     Ok((art, Some(release.release_date)))
                 } else {
                     Ok((art, None))
                 }
             })
-            .and_then_ok(|(p, ndt)| ArtifactPath::new(PathBuf::from(p.path)).map(|a| (a, ndt)))
-            .and_then_ok(|(artpath, ndt)| {
-                if let Some(staging) = self.staging_store.as_ref() {
-                    trace!(
-                        "Searching in staging: {:?} for {:?}",
-                        staging.root_path(),
-                        artpath
-                    );
-                    if let Some(art) = staging.get(&artpath) {
-                        trace!("Found in staging: {:?}", art);
-                        return staging.root_path().join(art).map(|p| p.map(|p| (p, ndt)))
-                    }
+            .and_then_ok(|(art, ndt)| {
+                let artpath = ArtifactPath::new(PathBuf::from(&art.path))?;
+                trace!("Searching in stores for {:?}", artpath);
+                if let Some(full) = stores.get(&artpath)? {
+                    return Ok(self.verified(&art, full).map(|full| (full, ndt)))
                 }
 
-                // If we cannot find the artifact in the release store either, we return None.
-                // This is the case if there indeed was a release, but it was removed from the
-                // filesystem.
-                for release_store in self.release_stores {
-                    if let Some(art) = release_store.get(&artpath) {
-                        trace!("Found in release: {:?}", art);
-                        return release_store.root_path().join(art).map(|p| p.map(|p| (p, ndt)))
+                // The artifact could have been moved within the stores, so it is searched by its
+                // hash as well.
+                if let Some(hash) = art.sha256.as_ref() {
+                    if let Some(full) = stores.find_by_hash(self.database_connection, hash)? {
+                        trace!("Found {:?} by its hash at {:?}", artpath, full.artifact_path());
+                        return Ok(self.verified(&art, full).map(|full| (full, ndt)))
                     }
                 }
 
-                trace!("Found no release for artifact {:?} in any release store", artpath.display());
+                // If we cannot find the artifact in any store, we return None.
+                // This is the case if there indeed was a release, but it was removed from the
+                // filesystem.
+                trace!("Found no artifact {:?} in any store", artpath.display());
                 Ok(None)
             })
             .filter_map_ok(|opt| opt)
             .collect::<Result<Vec<(FullArtifactPath<'a>, Option<NaiveDateTime>)>>>()
     }
-}
 
+    /// The artifact `full` of `art`, unless only hermetic artifacts are searched and the file
+    /// in the store was changed since it was built
+    fn verified(&self, art: &dbmodels::Artifact, full: FullArtifactPath<'a>) -> Option<FullArtifactPath<'a>> {
+        if !self.hermetic_only {
+            return Some(full)
+        }
 
-fn environments_equal(job_env: &[(String, String)], pkg_env: Option<&HashMap<EnvironmentVariableName, String>>, add_env: &[(EnvironmentVariableName, String)]) -> bool {
-    use std::ops::Deref;
+        match (art.sha256.as_ref(), crate::filestore::ObjectStore::hash_file_blocking(&full.joined())) {
+            (Some(expected), Ok(actual)) if *expected == actual => Some(full),
+            (Some(_), Ok(_)) => {
+                warn!("Not using {}, its hash does not match the recorded one", full.display());
+                None
+            },
+            (None, _) => {
+                warn!("Not using {}, no hash was recorded for it", full.display());
+                None
+            },
+            (_, Err(e)) => {
+                warn!("Not using {}: {:?}", full.display(), e);
+                None
+            },
+        }
+    }
+}
 
-    let job_envs_all_found = || job_env.iter()
-        .map(|(key, value)| (EnvironmentVariableName::from(key.deref()), value))
-        .all(|(key, value)| {
 
-            // check whether pair is in pkg_env
-            let is_in_pkg_env = || pkg_env.as_ref()
-                .map(|hm| {
-                    if let Some(val) = hm.get(&key) {
-                        value == val
-                    } else {
-                        false
-                    }
-                })
-                .unwrap_or(false);
+/// The names and versions of the packages that `job` was built with
+///
+/// These are the dependencies of the job in the recorded job graph of its submit. If the graph
+/// was not recorded (for submits of older versions of butido), these are the packages of the
+/// other jobs of the submit.
+pub fn job_dependencies(database_connection: &PgConnection, job: &dbmodels::Job) -> Result<Vec<(String, String)>> {
+    let packages = match dbmodels::SubmitJob::dependency_packages(database_connection, job.submit_id, &job.uuid)? {
+        Some(packages) => packages,
+        None => schema::jobs::table
+            .inner_join(schema::packages::table)
+            .filter(schema::jobs::submit_id.eq(job.submit_id))
+            .filter(schema::jobs::id.ne(job.id))
+            .select(schema::packages::all_columns)
+            .load::<dbmodels::Package>(database_connection)?,
+    };
+
+    Ok(packages.into_iter().map(|p| (p.name, p.version)).collect())
+}
 
-            // check whether pair is in add_env
-            let is_in_add_env = || add_env.iter().any(|(k, v)| *k == key && v == value);
+fn dependency_versions_match(dependencies: &[(String, String)], constraints: &[(PackageName, PackageVersionConstraint)]) -> bool {
+    dependency_mismatches(dependencies, constraints).is_empty()
+}
 
-            let r = is_in_pkg_env() || is_in_add_env();
-            trace!("Job Env ({}, {}) found: {}", key, value, r);
-            r
-        });
+/// The dependencies whose versions that a job was built with (`dependencies`) do not satisfy the
+/// `constraints` of the dependencies of its package, one message per dependency
+///
+/// A dependency only fails to match if no package with its name, but packages with its name in
+/// other versions were built with the job. The name of a dependency may be qualified with a
+/// namespace, the names of the packages in the database are not.
+pub fn dependency_mismatches(dependencies: &[(String, String)], constraints: &[(PackageName, PackageVersionConstraint)]) -> Vec<String> {
+    constraints.iter()
+        .filter_map(|(name, constraint)| {
+            let name = name.split_namespace().1;
+            let versions = dependencies.iter()
+                .filter(|(dependency, _)| dependency == name)
+                .map(|(_, version)| version.as_str())
+                .collect::<Vec<_>>();
 
-    let pkg_envs_all_found = || pkg_env.map(|hm| {
-        hm.iter()
-            .all(|(k, v)| {
-            job_env.contains(&(k.as_ref().to_string(), v.to_string())) // TODO: do not allocate
-            })
-    })
-    .unwrap_or(true);
+            if versions.is_empty() || versions.iter().any(|version| constraint.matches(&PackageVersion::from(version.to_string()))) {
+                None
+            } else {
+                trace!("No dependency {} matching '{}' was built with the job", name, constraint);
+                Some(format!("{} {} does not match '{}'", name, versions.join(", "), constraint))
+            }
+        })
+        .collect()
+}
 
-    let add_envs_all_found = || add_env.iter()
-        .all(|(k, v)| {
-            job_env.contains(&(k.as_ref().to_string(), v.to_string())) // TODO: do not allocate
-        });
+/// The differences between the environment a job was built with (`job_env`) and the environment
+/// of the package (`pkg_env`) together with the additional environment (`add_env`), one message
+/// per differing variable
+///
+/// This is the comparison that `FindArtifacts` does in its database query, for explaining why
+/// the artifacts of a job are not reused.
+pub fn environment_mismatches(job_env: &[(String, String)], pkg_env: Option<&HashMap<EnvironmentVariableName, String>>, add_env: &[(EnvironmentVariableName, String)]) -> Vec<String> {
+    let expected = pkg_env
+        .into_iter()
+        .flat_map(|hm| hm.iter())
+        .chain(add_env.iter().map(|(k, v)| (k, v)))
+        .map(|(k, v)| (k.as_ref(), v.as_str()))
+        .collect::<Vec<(&str, &str)>>();
+    let job_env = job_env.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect::<Vec<_>>();
+
+    let mut mismatches = BTreeSet::new();
+    for (name, value) in job_env.iter() {
+        if !expected.contains(&(name, value)) {
+            let message = match expected.iter().find(|(k, _)| k == name) {
+                Some((_, now)) => format!("{} was '{}' instead of '{}'", name, value, now),
+                None => format!("{} was '{}', but is not set now", name, value),
+            };
+            trace!("Job Env ({}, {}) not found", name, value);
+            mismatches.insert(message);
+        }
+    }
 
-    job_envs_all_found() && pkg_envs_all_found() && add_envs_all_found()
+    for (name, value) in expected.iter() {
+        if !job_env.contains(&(name, value)) {
+            let message = match job_env.iter().find(|(k, _)| k == name) {
+                Some((_, was)) => format!("{} was '{}' instead of '{}'", name, was, value),
+                None => format!("{} was not set, but is '{}' now", name, value),
+            };
+            mismatches.insert(message);
+        }
+    }
+
+    mismatches.into_iter().collect()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_environment_mismatches() {
+        let job_env = vec![
+            (String::from("CFLAGS"), String::from("-O2")),
+            (String::from("JOBS"), String::from("4")),
+            (String::from("OLD"), String::from("1")),
+        ];
+        let mut pkg_env = HashMap::new();
+        pkg_env.insert(EnvironmentVariableName::from("CFLAGS"), String::from("-O2"));
+        let add_env = vec![
+            (EnvironmentVariableName::from("JOBS"), String::from("8")),
+            (EnvironmentVariableName::from("NEW"), String::from("x")),
+        ];
+
+        assert_eq!(environment_mismatches(&job_env, Some(&pkg_env), &add_env), vec![
+            String::from("JOBS was '4' instead of '8'"),
+            String::from("NEW was not set, but is 'x' now"),
+            String::from("OLD was '1', but is not set now"),
+        ]);
+        assert!(environment_mismatches(&job_env[..1], Some(&pkg_env), &[]).is_empty());
+    }
+
+    #[test]
+    fn test_dependency_versions_match() {
+        use std::convert::TryFrom;
+
+        let constraints = vec![
+            (PackageName::from(String::from("core/openssl")), PackageVersionConstraint::try_from("=3.0").unwrap()),
+            (PackageName::from(String::from("zlib")), PackageVersionConstraint::try_from("=1.2").unwrap()),
+        ];
+        let built_with = |deps: &[(&str, &str)]| deps.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect::<Vec<_>>();
+
+        assert!(dependency_versions_match(&built_with(&[("openssl", "3.0"), ("zlib", "1.2")]), &constraints));
+        assert!(dependency_versions_match(&built_with(&[("openssl", "1.1"), ("openssl", "3.0")]), &constraints));
+        assert!(dependency_versions_match(&built_with(&[]), &constraints));
+        assert_eq!(
+            dependency_mismatches(&built_with(&[("openssl", "1.1"), ("zlib", "1.2")]), &constraints),
+            vec![String::from("openssl 1.1 does not match '=3.0'")]
+        );
+    }
+}
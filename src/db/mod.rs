@@ -11,7 +11,13 @@
 mod connection;
 pub use connection::*;
 
+mod dump;
+pub use dump::Dump;
+
 mod find_artifacts;
+pub use find_artifacts::dependency_mismatches;
+pub use find_artifacts::environment_mismatches;
+pub use find_artifacts::job_dependencies;
 pub use find_artifacts::FindArtifacts;
 
 pub mod models;
@@ -30,6 +30,18 @@ pub struct Artifact {
     pub id: i32,
     pub path: String,
     pub job_id: i32,
+
+    /// The output of the package the artifact belongs to
+    pub output: Option<String>,
+
+    /// The sha256 hash of the artifact when it was built
+    pub sha256: Option<String>,
+
+    /// The size of the artifact in bytes when it was built
+    pub size: Option<i64>,
+
+    /// Whether the artifact was built with "build --hermetic"
+    pub hermetic: bool,
 }
 
 #[derive(Insertable)]
@@ -37,6 +49,10 @@ pub struct Artifact {
 struct NewArtifact<'a> {
     pub path: &'a str,
     pub job_id: i32,
+    pub output: Option<&'a str>,
+    pub sha256: Option<&'a str>,
+    pub size: Option<i64>,
+    pub hermetic: bool,
 }
 
 impl Artifact {
@@ -66,10 +82,25 @@ impl Artifact {
             .map_err(Error::from)
     }
 
+    /// The distinct paths of all artifacts that had the sha256 hash `hash` when they were built
+    pub fn paths_with_sha256(database_connection: &PgConnection, hash: &str) -> Result<Vec<String>> {
+        dsl::artifacts
+            .filter(sha256.eq(hash))
+            .select(path)
+            .distinct()
+            .order_by(path)
+            .load::<String>(database_connection)
+            .map_err(Error::from)
+    }
+
     pub fn create(
         database_connection: &PgConnection,
         art_path: &ArtifactPath,
         job: &Job,
+        art_output: Option<&str>,
+        art_sha256: Option<&str>,
+        art_size: Option<i64>,
+        art_hermetic: bool,
     ) -> Result<Artifact> {
         let path_str = art_path
             .to_str()
@@ -78,6 +109,10 @@ impl Artifact {
         let new_art = NewArtifact {
             path: path_str,
             job_id: job.id,
+            output: art_output,
+            sha256: art_sha256,
+            size: art_size,
+            hermetic: art_hermetic,
         };
 
         database_connection.transaction::<_, Error, _>(|| {
@@ -0,0 +1,62 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::Context;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::config::Configuration;
+use crate::schema::audit;
+
+/// An operation that changed the state of butido, like starting a submit or releasing artifacts
+///
+/// The `parameters` are a JSON object with the arguments of the operation.
+#[derive(Clone, Debug, Identifiable, Queryable)]
+#[table_name = "audit"]
+pub struct AuditEntry {
+    pub id: i32,
+    pub recorded_at: NaiveDateTime,
+
+    /// The user who ran the operation
+    pub username: String,
+    pub operation: String,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Insertable)]
+#[table_name = "audit"]
+struct NewAuditEntry<'a> {
+    pub username: &'a str,
+    pub operation: &'a str,
+    pub parameters: serde_json::Value,
+}
+
+impl AuditEntry {
+    /// Record the `operation` with its `parameters`, by the operator of `config`
+    pub fn create(
+        database_connection: &PgConnection,
+        config: &Configuration,
+        operation: &str,
+        parameters: serde_json::Value,
+    ) -> Result<AuditEntry> {
+        let new_entry = NewAuditEntry {
+            username: &crate::util::operator(config),
+            operation,
+            parameters,
+        };
+
+        diesel::insert_into(audit::table)
+            .values(&new_entry)
+            .get_result::<AuditEntry>(database_connection)
+            .with_context(|| anyhow::anyhow!("Recording operation {} in the audit log", operation))
+    }
+}
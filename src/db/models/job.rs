@@ -37,6 +37,19 @@ pub struct Job {
     pub script_text: String,
     pub log_text: String,
     pub uuid: ::uuid::Uuid,
+    pub patches_hash: Option<String>,
+
+    /// The build options of the package, formatted as "name=on,other=off"
+    pub options: Option<String>,
+
+    /// The ID of the image the job ran in, "sha256:..."
+    pub image_digest: Option<String>,
+
+    /// The environment variables the endpoint set in the container, as "NAME=VALUE"
+    pub endpoint_env: Vec<String>,
+
+    /// The directories of the endpoint that were mounted into the container, as docker binds
+    pub endpoint_mounts: Vec<String>,
 }
 
 #[derive(Debug, Insertable)]
@@ -50,6 +63,11 @@ struct NewJob<'a> {
     pub script_text: String,
     pub log_text: String,
     pub uuid: &'a ::uuid::Uuid,
+    pub patches_hash: Option<&'a str>,
+    pub options: Option<&'a str>,
+    pub image_digest: Option<&'a str>,
+    pub endpoint_env: Vec<String>,
+    pub endpoint_mounts: Vec<String>,
 }
 
 impl Job {
@@ -64,6 +82,11 @@ impl Job {
         container: &ContainerHash,
         script: &Script,
         log: &str,
+        job_patches_hash: Option<&str>,
+        job_options: Option<&str>,
+        job_image_digest: Option<&str>,
+        job_endpoint_env: Vec<String>,
+        job_endpoint_mounts: Vec<String>,
     ) -> Result<Job> {
         let new_job = NewJob {
             uuid: job_uuid,
@@ -74,6 +97,11 @@ impl Job {
             container_hash: container.as_ref(),
             script_text: script.as_ref().replace('\0', ""),
             log_text: log.replace('\0', ""),
+            patches_hash: job_patches_hash,
+            options: job_options,
+            image_digest: job_image_digest,
+            endpoint_env: job_endpoint_env,
+            endpoint_mounts: job_endpoint_mounts,
         };
 
         trace!("Creating Job in database: {:?}", new_job);
@@ -0,0 +1,110 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Job;
+use crate::filestore::PhaseTiming;
+use crate::schema::job_phases;
+
+/// How long a phase of a job took
+#[derive(Debug, Identifiable, Queryable, Associations)]
+#[belongs_to(Job)]
+#[table_name = "job_phases"]
+pub struct JobPhase {
+    pub id: i32,
+    pub job_id: i32,
+
+    /// The index of the phase in the order the phases ran in
+    pub position: i32,
+    pub name: String,
+    pub started: NaiveDateTime,
+    pub duration_secs: f64,
+}
+
+#[derive(Insertable)]
+#[table_name = "job_phases"]
+struct NewJobPhase<'a> {
+    pub job_id: i32,
+    pub position: i32,
+    pub name: &'a str,
+    pub started: NaiveDateTime,
+    pub duration_secs: f64,
+}
+
+impl JobPhase {
+    pub fn create(database_connection: &PgConnection, job: &Job, position: i32, timing: &PhaseTiming) -> Result<()> {
+        let started = chrono::DateTime::parse_from_rfc3339(&timing.started)
+            .with_context(|| anyhow!("Parsing start time of phase {}: {}", timing.name, timing.started))?
+            .naive_utc();
+
+        let new_jobphase = NewJobPhase {
+            job_id: job.id,
+            position,
+            name: &timing.name,
+            started,
+            duration_secs: timing.duration_secs,
+        };
+
+        diesel::insert_into(job_phases::table)
+            .values(&new_jobphase)
+            .execute(database_connection)?;
+        Ok(())
+    }
+
+    /// How long the last (at most `limit`) successful jobs of the package in the image took, on
+    /// average, `None` if no timings of such jobs were recorded
+    ///
+    /// Jobs that produced artifacts are successful, the duration of a job is the sum of the
+    /// durations of its phases.
+    pub fn average_job_duration_secs(
+        database_connection: &PgConnection,
+        package_name: &str,
+        package_version: &str,
+        image_name: &str,
+        limit: i64,
+    ) -> Result<Option<f64>> {
+        use crate::schema;
+
+        let job_ids = schema::jobs::table
+            .inner_join(schema::packages::table)
+            .inner_join(schema::images::table)
+            .inner_join(schema::artifacts::table)
+            .filter(schema::packages::name.eq(package_name))
+            .filter(schema::packages::version.eq(package_version))
+            .filter(schema::images::name.eq(image_name))
+            .select(schema::jobs::id)
+            .distinct()
+            .order(schema::jobs::id.desc())
+            .limit(limit)
+            .load::<i32>(database_connection)?;
+
+        let durations = job_phases::table
+            .filter(job_phases::job_id.eq_any(job_ids))
+            .select((job_phases::job_id, job_phases::duration_secs))
+            .load::<(i32, f64)>(database_connection)?
+            .into_iter()
+            .fold(std::collections::HashMap::<i32, f64>::new(), |mut durations, (job_id, secs)| {
+                *durations.entry(job_id).or_default() += secs;
+                durations
+            });
+
+        if durations.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(durations.values().sum::<f64>() / durations.len() as f64))
+        }
+    }
+}
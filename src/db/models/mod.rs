@@ -11,6 +11,9 @@
 mod artifact;
 pub use artifact::*;
 
+mod audit;
+pub use audit::*;
+
 mod endpoint;
 pub use endpoint::*;
 
@@ -26,6 +29,9 @@ pub use job::*;
 mod job_env;
 pub use job_env::*;
 
+mod job_phase;
+pub use job_phase::*;
+
 mod githash;
 pub use githash::*;
 
@@ -35,8 +41,20 @@ pub use package::*;
 mod releases;
 pub use releases::*;
 
+mod release_promotion;
+pub use release_promotion::*;
+
 mod release_store;
 pub use release_store::*;
 
+mod running_job;
+pub use running_job::*;
+
 mod submit;
 pub use submit::*;
+
+mod submit_cancellation;
+pub use submit_cancellation::*;
+
+mod submit_job;
+pub use submit_job::*;
@@ -0,0 +1,64 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::Context;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Release;
+use crate::db::models::ReleaseStore;
+use crate::schema::release_promotions;
+
+/// The promotion of a released artifact from one release store to another, made with "release
+/// promote"
+///
+/// The `release` is the release to the store the artifact was promoted to.
+#[derive(Clone, Debug, Identifiable, Queryable, Associations)]
+#[belongs_to(Release)]
+#[table_name = "release_promotions"]
+pub struct ReleasePromotion {
+    pub id: i32,
+    pub release_id: i32,
+    pub from_release_store_id: i32,
+
+    /// The user who promoted the artifact
+    pub promoted_by: String,
+    pub promoted_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "release_promotions"]
+struct NewReleasePromotion<'a> {
+    pub release_id: i32,
+    pub from_release_store_id: i32,
+    pub promoted_by: &'a str,
+}
+
+impl ReleasePromotion {
+    pub fn create(
+        database_connection: &PgConnection,
+        release: &Release,
+        from: &ReleaseStore,
+        promoted_by: &str,
+    ) -> Result<ReleasePromotion> {
+        let new_promotion = NewReleasePromotion {
+            release_id: release.id,
+            from_release_store_id: from.id,
+            promoted_by,
+        };
+
+        diesel::insert_into(release_promotions::table)
+            .values(&new_promotion)
+            .get_result::<ReleasePromotion>(database_connection)
+            .context("Inserting promotion of release")
+    }
+}
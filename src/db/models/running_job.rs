@@ -0,0 +1,156 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::Error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Submit;
+use crate::schema;
+use crate::schema::running_jobs;
+
+/// A job that is currently being built, so other submits can wait for it instead of building the
+/// same job again
+#[derive(Clone, Debug, Identifiable, Queryable, Associations)]
+#[belongs_to(Submit)]
+#[table_name = "running_jobs"]
+pub struct RunningJob {
+    pub id: i32,
+    pub uuid: ::uuid::Uuid,
+    pub submit_id: i32,
+
+    /// Hash over everything that makes a job produce a certain artifact
+    pub fingerprint: String,
+    pub started_at: NaiveDateTime,
+
+    /// The endpoint the job runs on, `None` while the job waits for an endpoint
+    pub endpoint_name: Option<String>,
+
+    /// When the job got its endpoint
+    pub scheduled_at: Option<NaiveDateTime>,
+
+    /// The phase the job is in, as reported by its script
+    pub phase: Option<String>,
+
+    /// The progress of the job in percent, as reported by its script
+    pub progress: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[table_name = "running_jobs"]
+struct NewRunningJob<'a> {
+    pub uuid: &'a ::uuid::Uuid,
+    pub submit_id: i32,
+    pub fingerprint: &'a str,
+}
+
+impl RunningJob {
+    pub fn create(
+        database_connection: &PgConnection,
+        job_uuid: &::uuid::Uuid,
+        submit: &Submit,
+        fingerprint: &str,
+    ) -> Result<RunningJob> {
+        let new_running_job = NewRunningJob {
+            uuid: job_uuid,
+            submit_id: submit.id,
+            fingerprint,
+        };
+
+        diesel::insert_into(running_jobs::table)
+            .values(&new_running_job)
+            .get_result::<RunningJob>(database_connection)
+            .map_err(Error::from)
+    }
+
+    /// Find an equivalent job of another submit that started before this one and is still running
+    ///
+    /// Jobs that started before `not_before` are ignored, because they are most likely left over
+    /// from a butido process that was killed.
+    pub fn find_earlier_equivalent(
+        &self,
+        database_connection: &PgConnection,
+        not_before: NaiveDateTime,
+    ) -> Result<Option<(RunningJob, Submit)>> {
+        running_jobs::table
+            .inner_join(schema::submits::table)
+            .filter(running_jobs::fingerprint.eq(&self.fingerprint))
+            .filter(running_jobs::submit_id.ne(self.submit_id))
+            .filter(running_jobs::id.lt(self.id))
+            .filter(running_jobs::started_at.ge(not_before))
+            .order_by(running_jobs::id.asc())
+            .first::<(RunningJob, Submit)>(database_connection)
+            .optional()
+            .map_err(Error::from)
+    }
+
+    /// Whether the job with `job_uuid` is still running
+    pub fn is_running(database_connection: &PgConnection, job_uuid: &::uuid::Uuid) -> Result<bool> {
+        running_jobs::table
+            .filter(running_jobs::uuid.eq(job_uuid))
+            .count()
+            .get_result::<i64>(database_connection)
+            .map(|n| n > 0)
+            .map_err(Error::from)
+    }
+
+    /// How many jobs of `submit` are running, ignoring the jobs that started before `not_before`
+    pub fn count_of_submit(database_connection: &PgConnection, submit: &Submit, not_before: NaiveDateTime) -> Result<i64> {
+        running_jobs::table
+            .filter(running_jobs::submit_id.eq(submit.id))
+            .filter(running_jobs::started_at.ge(not_before))
+            .count()
+            .get_result::<i64>(database_connection)
+            .map_err(Error::from)
+    }
+
+    /// Record that the job with `job_uuid` was scheduled on the endpoint `endpoint_name`
+    ///
+    /// The phase and the progress of an earlier endpoint of a rescheduled job are reset.
+    pub fn set_endpoint(database_connection: &PgConnection, job_uuid: &::uuid::Uuid, endpoint_name: &str) -> Result<()> {
+        diesel::update(running_jobs::table.filter(running_jobs::uuid.eq(job_uuid)))
+            .set((
+                running_jobs::endpoint_name.eq(endpoint_name),
+                running_jobs::scheduled_at.eq(chrono::Utc::now().naive_utc()),
+                running_jobs::phase.eq(None as Option<&str>),
+                running_jobs::progress.eq(None as Option<i32>),
+            ))
+            .execute(database_connection)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Record that the job with `job_uuid` is in the phase `phase`
+    pub fn set_phase(database_connection: &PgConnection, job_uuid: &::uuid::Uuid, phase: &str) -> Result<()> {
+        diesel::update(running_jobs::table.filter(running_jobs::uuid.eq(job_uuid)))
+            .set(running_jobs::phase.eq(phase))
+            .execute(database_connection)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Record that the job with `job_uuid` is at `progress` percent
+    pub fn set_progress(database_connection: &PgConnection, job_uuid: &::uuid::Uuid, progress: i32) -> Result<()> {
+        diesel::update(running_jobs::table.filter(running_jobs::uuid.eq(job_uuid)))
+            .set(running_jobs::progress.eq(progress))
+            .execute(database_connection)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    pub fn remove(database_connection: &PgConnection, job_uuid: &::uuid::Uuid) -> Result<()> {
+        diesel::delete(running_jobs::table.filter(running_jobs::uuid.eq(job_uuid)))
+            .execute(database_connection)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
@@ -32,6 +32,17 @@ pub struct Submit {
     pub requested_image_id: i32,
     pub requested_package_id: i32,
     pub repo_hash_id: i32,
+
+    /// Who started the submit, `None` for submits that were recorded before this was known
+    pub submitted_by: Option<String>,
+
+    /// The phases the scripts of the jobs were built with, `None` for submits that were recorded
+    /// before this was known
+    pub phases: Option<Vec<String>>,
+
+    /// The versions that were forced with `build --pin`, as "name=version", `None` for submits
+    /// that were recorded before this was known
+    pub pins: Option<Vec<String>>,
 }
 
 #[derive(Insertable)]
@@ -42,9 +53,13 @@ struct NewSubmit<'a> {
     pub requested_image_id: i32,
     pub requested_package_id: i32,
     pub repo_hash_id: i32,
+    pub submitted_by: &'a str,
+    pub phases: Option<&'a [String]>,
+    pub pins: Option<&'a [String]>,
 }
 
 impl Submit {
+    #[allow(clippy::too_many_arguments)]
     pub fn create(
         database_connection: &PgConnection,
         submit_datetime: &NaiveDateTime,
@@ -52,6 +67,9 @@ impl Submit {
         requested_image: &Image,
         requested_package: &Package,
         repo_hash: &GitHash,
+        submitter: &str,
+        script_phases: Option<&[String]>,
+        version_pins: Option<&[String]>,
     ) -> Result<Submit> {
         let new_submit = NewSubmit {
             uuid: submit_id,
@@ -59,6 +77,9 @@ impl Submit {
             requested_image_id: requested_image.id,
             requested_package_id: requested_package.id,
             repo_hash_id: repo_hash.id,
+            submitted_by: submitter,
+            phases: script_phases,
+            pins: version_pins,
         };
 
         database_connection.transaction::<_, Error, _>(|| {
@@ -0,0 +1,56 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::Context;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+
+use crate::db::models::Submit;
+use crate::schema::submit_cancellations;
+
+/// A request to cancel a running submit, made with "butido cancel"
+///
+/// A submit that is resumed after it was canceled can be canceled again, so there can be several
+/// cancellations of a submit.
+#[derive(Clone, Debug, Identifiable, Queryable, Associations)]
+#[belongs_to(Submit)]
+#[table_name = "submit_cancellations"]
+pub struct SubmitCancellation {
+    pub id: i32,
+    pub submit_id: i32,
+    pub canceled_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "submit_cancellations"]
+struct NewSubmitCancellation {
+    pub submit_id: i32,
+}
+
+impl SubmitCancellation {
+    pub fn create(database_connection: &PgConnection, submit: &Submit) -> Result<SubmitCancellation> {
+        diesel::insert_into(submit_cancellations::table)
+            .values(&NewSubmitCancellation { submit_id: submit.id })
+            .get_result::<SubmitCancellation>(database_connection)
+            .context("Inserting cancellation of submit")
+    }
+
+    /// The latest cancellation of `submit`, if it was canceled at all
+    pub fn latest(database_connection: &PgConnection, submit: &Submit) -> Result<Option<SubmitCancellation>> {
+        submit_cancellations::table
+            .filter(submit_cancellations::submit_id.eq(submit.id))
+            .order_by(submit_cancellations::id.desc())
+            .first::<SubmitCancellation>(database_connection)
+            .optional()
+            .context("Loading cancellation of submit")
+    }
+}
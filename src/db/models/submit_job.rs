@@ -0,0 +1,220 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use anyhow::Context;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::PgConnection;
+use itertools::Itertools;
+
+use crate::db::models::Package;
+use crate::db::models::Submit;
+use crate::schema;
+use crate::schema::job_state_transitions;
+use crate::schema::submit_jobs;
+
+/// A job of the job graph of a submit, recorded before the jobs run so that the submit can be
+/// resumed if butido does not finish it
+#[derive(Clone, Debug, Identifiable, Queryable, Associations)]
+#[belongs_to(Submit)]
+#[belongs_to(Package)]
+#[table_name = "submit_jobs"]
+pub struct SubmitJob {
+    pub id: i32,
+    pub submit_id: i32,
+    pub uuid: ::uuid::Uuid,
+    pub package_id: i32,
+
+    /// The UUIDs of the jobs this job depends on
+    pub dependencies: Vec<::uuid::Uuid>,
+}
+
+#[derive(Insertable)]
+#[table_name = "submit_jobs"]
+struct NewSubmitJob<'a> {
+    pub submit_id: i32,
+    pub uuid: &'a ::uuid::Uuid,
+    pub package_id: i32,
+    pub dependencies: Vec<::uuid::Uuid>,
+}
+
+/// A state that a job of a submit changed to
+#[derive(Clone, Debug, Identifiable, Queryable, Associations)]
+#[belongs_to(SubmitJob)]
+#[table_name = "job_state_transitions"]
+pub struct JobStateTransition {
+    pub id: i32,
+    pub submit_job_id: i32,
+
+    /// The name of the state, as in the result file of a submit (e.g. "running")
+    pub state: String,
+    pub time: NaiveDateTime,
+
+    /// The paths of the artifacts of the job, if it finished
+    pub artifacts: Vec<String>,
+}
+
+/// A dependency of a job with its package and its last recorded state, if any
+pub type DependencyState = (Package, Option<JobStateTransition>);
+
+#[derive(Insertable)]
+#[table_name = "job_state_transitions"]
+struct NewJobStateTransition<'a> {
+    pub submit_job_id: i32,
+    pub state: &'a str,
+    pub artifacts: Vec<String>,
+}
+
+impl SubmitJob {
+    pub fn create(
+        database_connection: &PgConnection,
+        submit: &Submit,
+        job_uuid: &::uuid::Uuid,
+        package: &Package,
+        dependencies: Vec<::uuid::Uuid>,
+    ) -> Result<SubmitJob> {
+        let new_submit_job = NewSubmitJob {
+            submit_id: submit.id,
+            uuid: job_uuid,
+            package_id: package.id,
+            dependencies,
+        };
+
+        diesel::insert_into(submit_jobs::table)
+            .values(&new_submit_job)
+            .get_result::<SubmitJob>(database_connection)
+            .context("Inserting job of submit")
+    }
+
+    /// The packages of the dependencies of the job `job_uuid` of the submit `submit_id`
+    ///
+    /// `None` if the job graph of the submit was not recorded.
+    pub fn dependency_packages(database_connection: &PgConnection, submit_id: i32, job_uuid: &::uuid::Uuid) -> Result<Option<Vec<Package>>> {
+        let submit_job = submit_jobs::table
+            .filter(submit_jobs::submit_id.eq(submit_id))
+            .filter(submit_jobs::uuid.eq(job_uuid))
+            .first::<SubmitJob>(database_connection)
+            .optional()
+            .context("Loading job of submit")?;
+
+        submit_job
+            .map(|submit_job| {
+                submit_jobs::table
+                    .inner_join(schema::packages::table)
+                    .filter(submit_jobs::submit_id.eq(submit_id))
+                    .filter(submit_jobs::uuid.eq_any(submit_job.dependencies))
+                    .select(schema::packages::all_columns)
+                    .load::<Package>(database_connection)
+                    .context("Loading dependencies of job of submit")
+            })
+            .transpose()
+    }
+
+    /// The dependencies of the job `job_uuid` of the submit `submit_id` with their packages and
+    /// the state that was recorded last for them, if any
+    ///
+    /// `None` if the job graph of the submit was not recorded.
+    pub fn dependency_states(
+        database_connection: &PgConnection,
+        submit_id: i32,
+        job_uuid: &::uuid::Uuid,
+    ) -> Result<Option<Vec<DependencyState>>> {
+        let submit_job = submit_jobs::table
+            .filter(submit_jobs::submit_id.eq(submit_id))
+            .filter(submit_jobs::uuid.eq(job_uuid))
+            .first::<SubmitJob>(database_connection)
+            .optional()
+            .context("Loading job of submit")?;
+
+        let submit_job = match submit_job {
+            Some(submit_job) => submit_job,
+            None => return Ok(None),
+        };
+
+        let dependencies = submit_jobs::table
+            .inner_join(schema::packages::table)
+            .filter(submit_jobs::submit_id.eq(submit_id))
+            .filter(submit_jobs::uuid.eq_any(submit_job.dependencies))
+            .load::<(SubmitJob, Package)>(database_connection)
+            .context("Loading dependencies of job of submit")?;
+
+        let submit_jobs = dependencies.iter().map(|(job, _)| job.clone()).collect::<Vec<_>>();
+        let transitions = JobStateTransition::belonging_to(&submit_jobs)
+            .order_by(job_state_transitions::id)
+            .load::<JobStateTransition>(database_connection)
+            .context("Loading job states of dependencies")?
+            .grouped_by(&submit_jobs);
+
+        Ok(Some(dependencies.into_iter()
+            .zip(transitions)
+            .map(|((_, package), transitions)| (package, transitions.into_iter().last()))
+            .collect()))
+    }
+
+    /// The latest state of the jobs of all earlier runs of `submit`, with their packages
+    ///
+    /// A package has one job per run of the submit, only the state that was recorded last is
+    /// returned for each package.
+    pub fn latest_states(database_connection: &PgConnection, submit: &Submit) -> Result<Vec<(Package, JobStateTransition)>> {
+        job_state_transitions::table
+            .inner_join(submit_jobs::table.inner_join(schema::packages::table))
+            .filter(submit_jobs::submit_id.eq(submit.id))
+            .order_by(job_state_transitions::id.desc())
+            .select((schema::packages::all_columns, job_state_transitions::all_columns))
+            .load::<(Package, JobStateTransition)>(database_connection)
+            .context("Loading job states of submit")
+            .map(|states| states.into_iter().unique_by(|(package, _)| package.id).collect())
+    }
+
+    /// All jobs of all runs of `submit` with their packages and their state transitions, in the
+    /// order they were recorded
+    pub fn with_states(database_connection: &PgConnection, submit: &Submit) -> Result<Vec<(SubmitJob, Package, Vec<JobStateTransition>)>> {
+        let jobs = submit_jobs::table
+            .inner_join(schema::packages::table)
+            .filter(submit_jobs::submit_id.eq(submit.id))
+            .order_by(submit_jobs::id)
+            .load::<(SubmitJob, Package)>(database_connection)
+            .context("Loading jobs of submit")?;
+
+        let submit_jobs = jobs.iter().map(|(job, _)| job.clone()).collect::<Vec<_>>();
+        let transitions = JobStateTransition::belonging_to(&submit_jobs)
+            .order_by(job_state_transitions::id)
+            .load::<JobStateTransition>(database_connection)
+            .context("Loading job states of submit")?
+            .grouped_by(&submit_jobs);
+
+        Ok(jobs.into_iter()
+            .zip(transitions)
+            .map(|((job, package), transitions)| (job, package, transitions))
+            .collect())
+    }
+}
+
+impl JobStateTransition {
+    pub fn create(
+        database_connection: &PgConnection,
+        submit_job_id: i32,
+        state: &str,
+        artifacts: Vec<String>,
+    ) -> Result<()> {
+        let new_transition = NewJobStateTransition {
+            submit_job_id,
+            state,
+            artifacts,
+        };
+
+        diesel::insert_into(job_state_transitions::table)
+            .values(&new_transition)
+            .execute(database_connection)
+            .context("Inserting job state transition")?;
+        Ok(())
+    }
+}
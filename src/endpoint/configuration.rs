@@ -32,4 +32,29 @@ pub struct EndpointConfiguration {
     #[getset(get = "pub")]
     #[builder(default)]
     required_docker_api_versions: Option<Vec<String>>,
+
+    /// Negotiate the API version instead of checking `required_docker_api_versions`
+    #[getset(get = "pub")]
+    #[builder(default)]
+    negotiate_docker_api_version: bool,
+
+    #[getset(get = "pub")]
+    #[builder(default)]
+    min_docker_api_version: Option<String>,
+
+    /// Do not fail if required images are missing, because they are pulled before building
+    #[getset(get = "pub")]
+    #[builder(default)]
+    pull_missing_images: bool,
+
+    /// Remove the containers of successful jobs and keep the containers of failed jobs for this
+    /// many hours
+    #[getset(get = "pub")]
+    #[builder(default)]
+    failed_container_retention_hours: Option<u64>,
+
+    /// More labels for the containers of jobs
+    #[getset(get = "pub")]
+    #[builder(default)]
+    container_labels: std::collections::HashMap<String, String>,
 }
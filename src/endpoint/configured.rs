@@ -8,7 +8,10 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
+use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -29,26 +32,59 @@ use tokio::sync::mpsc::UnboundedSender;
 use tokio_stream::StreamExt;
 use typed_builder::TypedBuilder;
 
+use crate::config::EndpointMount;
 use crate::config::EndpointName;
+use crate::endpoint::DockerUri;
 use crate::endpoint::EndpointConfiguration;
+use crate::endpoint::sandbox::SandboxHost;
+use crate::endpoint::sandbox::SandboxJob;
+use crate::endpoint::SshTunnel;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
 use crate::filestore::path::ArtifactPath;
+use crate::filestore::path::FullArtifactPath;
 use crate::job::JobResource;
 use crate::job::RunnableJob;
 use crate::log::LogItem;
 use crate::log::buffer_stream_to_line_stream;
+use crate::log::OutputStream;
 use crate::package::Script;
 use crate::util::docker::ContainerHash;
 use crate::util::docker::ImageName;
+use crate::util::EnvironmentVariableName;
+
+/// The label of the containers of jobs with the UUID of their submit
+pub const CONTAINER_SUBMIT_LABEL: &str = "butido.submit";
+
+/// The label of the containers of jobs with the UUID of the job
+pub const CONTAINER_JOB_LABEL: &str = "butido.job";
+
+/// The label of the containers of jobs with the name of the package of the job
+pub const CONTAINER_PACKAGE_LABEL: &str = "butido.package";
+
+/// The label of the containers of jobs with the version of the package of the job
+pub const CONTAINER_VERSION_LABEL: &str = "butido.version";
+
+/// The label of the containers of jobs with the name of the endpoint they run on
+pub const CONTAINER_ENDPOINT_LABEL: &str = "butido.endpoint";
+
+/// The name of the container of the job `job`: `butido-<package>-<version>-<short job UUID>`
+///
+/// Characters that docker does not allow in container names are replaced with '_'.
+pub fn container_name(package_name: &str, package_version: &str, job: &uuid::Uuid) -> String {
+    let uuid = job.to_string();
+    format!("butido-{}-{}-{}", package_name, package_version, &uuid[..8])
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' { c } else { '_' })
+        .collect()
+}
 
 #[derive(Getters, CopyGetters, TypedBuilder)]
 pub struct Endpoint {
     #[getset(get = "pub")]
     name: EndpointName,
 
-    #[getset(get = "pub")]
-    docker: Docker,
+    executor: Executor,
 
     #[getset(get_copy = "pub")]
     num_max_jobs: usize,
@@ -56,11 +92,94 @@ pub struct Endpoint {
     #[getset(get = "pub")]
     network_mode: Option<String>,
 
+    /// The labels packages can require or prefer for their jobs
+    #[getset(get = "pub")]
+    labels: Vec<String>,
+
     #[getset(get = "pub")]
     uri: String,
 
+    /// The timeout for requests that check the endpoint
+    #[getset(get_copy = "pub")]
+    timeout: std::time::Duration,
+
     #[builder(default)]
     running_jobs: std::sync::atomic::AtomicUsize,
+
+    /// Whether the endpoint answered the last health check
+    #[builder(default = std::sync::atomic::AtomicBool::new(true))]
+    healthy: std::sync::atomic::AtomicBool,
+
+    /// The total memory of the endpoint in bytes, if it could be found out
+    #[builder(default)]
+    mem_total: Option<u64>,
+
+    /// The docker API version that is used for the endpoint, if it was checked or negotiated when
+    /// connecting
+    #[getset(get = "pub")]
+    #[builder(default)]
+    api_version: Option<String>,
+
+    /// The memory in bytes that is reserved by the jobs running on this endpoint
+    #[builder(default)]
+    reserved_memory: std::sync::atomic::AtomicU64,
+
+    /// The number of CPUs of the endpoint in thousandths, if it is configured or could be found
+    /// out
+    #[builder(default)]
+    millicpus_total: Option<u64>,
+
+    /// The CPUs in thousandths that are reserved by the jobs running on this endpoint
+    #[builder(default)]
+    reserved_millicpus: std::sync::atomic::AtomicU64,
+
+    /// The images that are pinned to a digest, but present with other content on this endpoint
+    #[builder(default)]
+    mismatched_images: Vec<ImageName>,
+
+    /// The images that are known to be present on this endpoint, because they were found when
+    /// setting up the endpoint or were pulled since
+    #[builder(default)]
+    present_images: std::sync::RwLock<std::collections::HashSet<ImageName>>,
+
+    /// The tunnel to the docker daemon, for endpoints that are reached over SSH
+    #[builder(default)]
+    tunnel: Option<SshTunnel>,
+
+    /// How long the containers of failed jobs are kept, if the containers of jobs are removed
+    /// at all
+    #[builder(default)]
+    failed_container_retention: Option<chrono::Duration>,
+
+    /// More labels the containers of jobs get, besides the labels butido sets
+    #[builder(default)]
+    container_labels: HashMap<String, String>,
+
+    /// The environment variables of the containers of jobs, unless the job sets them
+    #[getset(get = "pub")]
+    #[builder(default)]
+    env: BTreeMap<EnvironmentVariableName, String>,
+
+    /// The directories of the host that are mounted into the containers of jobs
+    #[getset(get = "pub")]
+    #[builder(default)]
+    mounts: Vec<EndpointMount>,
+}
+
+/// The required images of an endpoint, by whether they are present on it
+#[derive(Debug, Default)]
+struct ImageCheck {
+    present: Vec<ImageName>,
+    mismatched: Vec<ImageName>,
+    missing: Vec<ImageName>,
+}
+
+/// How the builds are run on an endpoint
+pub enum Executor {
+    Docker(Docker),
+
+    /// In a sandbox on a host without docker, over SSH or locally
+    Sandbox(SandboxHost),
 }
 
 impl Debug for Endpoint {
@@ -71,7 +190,7 @@ impl Debug for Endpoint {
 
 impl Endpoint {
     pub(super) async fn setup(epc: EndpointConfiguration) -> Result<Self> {
-        let ep = Endpoint::setup_endpoint(epc.endpoint_name(), epc.endpoint()).with_context(|| {
+        let mut ep = Endpoint::setup_endpoint(epc.endpoint_name(), epc.endpoint()).with_context(|| {
             anyhow!(
                 "Setting up endpoint: {} -> {}",
                 epc.endpoint_name(),
@@ -79,18 +198,95 @@ impl Endpoint {
             )
         })?;
 
+        if let Some(tunnel) = ep.tunnel.as_mut() {
+            tunnel.wait_ready()
+                .await
+                .with_context(|| anyhow!("Connecting to endpoint {} -> {}", epc.endpoint_name(), epc.endpoint().uri()))?;
+        }
+
+        if let Executor::Sandbox(host) = &ep.executor {
+            let (imgs_avail, mem_total) = tokio::join!(
+                tokio::time::timeout(ep.timeout(), host.check_images_available(epc.required_images().as_ref())),
+                tokio::time::timeout(ep.timeout(), host.mem_total())
+            );
+
+            ep.mem_total = match mem_total {
+                Ok(Ok(mem_total)) => Some(mem_total),
+                Ok(Err(e)) => {
+                    log::warn!("Cannot get memory of endpoint {}: {}", epc.endpoint_name(), e);
+                    None
+                },
+                Err(_) => {
+                    log::warn!("Timeout getting memory of endpoint {}", epc.endpoint_name());
+                    None
+                },
+            };
+
+            imgs_avail
+                .map_err(Error::from)
+                .and_then(|r| r)
+                .with_context(|| anyhow!("Checking for available images on {} -> {}", epc.endpoint_name(), epc.endpoint().uri()))?;
+            ep.apply_configured_capacity(epc.endpoint());
+            return Ok(ep)
+        }
+
+        ep.failed_container_retention = epc.failed_container_retention_hours().map(|hours| chrono::Duration::hours(hours as i64));
+        ep.container_labels = epc.container_labels().clone();
+        if let Some(retention) = ep.failed_container_retention {
+            // Failing to clean up does not stop the build, the containers are removed next time
+            if let Err(e) = ep.remove_expired_containers(retention).await {
+                log::warn!("Removing the expired containers of failed jobs on {}: {:#}", epc.endpoint_name(), e);
+            }
+        }
+
         let versions_compat =
             Endpoint::check_version_compat(epc.required_docker_versions().as_ref(), &ep);
-        let api_versions_compat =
-            Endpoint::check_api_version_compat(epc.required_docker_api_versions().as_ref(), &ep);
-        let imgs_avail = Endpoint::check_images_available(epc.required_images().as_ref(), &ep);
+        let api_versions_compat = async {
+            if *epc.negotiate_docker_api_version() {
+                Endpoint::negotiate_api_version(epc.min_docker_api_version().as_ref(), &ep).await.map(Some)
+            } else {
+                Endpoint::check_api_version_compat(epc.required_docker_api_versions().as_ref(), &ep).await
+            }
+        };
+        let imgs_avail = async {
+            let check = Endpoint::check_images_available(epc.required_images().as_ref(), &ep).await?;
+            if *epc.pull_missing_images() {
+                // Missing images are pulled before the first job runs, and so are the pinned
+                // images that are present with other content
+                log::debug!("Images missing on {}: {:?}", epc.endpoint_name(), check.missing);
+                Ok((check.present, vec![]))
+            } else if let Some(img) = check.missing.first() {
+                Err(anyhow!("Image '{}' missing from endpoint '{}'", img.as_ref(), epc.endpoint_name()))
+            } else {
+                Ok((check.present, check.mismatched))
+            }
+        };
+        let info = ep.docker()?.info();
 
-        let (versions_compat, api_versions_compat, imgs_avail) = {
-            let timeout = std::time::Duration::from_secs(epc.endpoint().timeout().unwrap_or(10));
+        let (versions_compat, api_versions_compat, imgs_avail, info) = {
+            let timeout = ep.timeout();
             let versions_compat = tokio::time::timeout(timeout, versions_compat);
             let api_versions_compat = tokio::time::timeout(timeout, api_versions_compat);
             let imgs_avail = tokio::time::timeout(timeout, imgs_avail);
-            tokio::join!(versions_compat, api_versions_compat, imgs_avail)
+            let info = tokio::time::timeout(timeout, info);
+            tokio::join!(versions_compat, api_versions_compat, imgs_avail, info)
+        };
+
+        // The memory of the endpoint is only needed for scheduling jobs with memory limits, so
+        // not being able to find it out is not an error
+        ep.mem_total = match info {
+            Ok(Ok(info)) => {
+                ep.millicpus_total = Some(info.n_cpu * 1000);
+                Some(info.mem_total)
+            },
+            Ok(Err(e)) => {
+                log::warn!("Cannot get memory of endpoint {}: {}", epc.endpoint_name(), e);
+                None
+            },
+            Err(_) => {
+                log::warn!("Timeout getting memory of endpoint {}", epc.endpoint_name());
+                None
+            },
         };
 
         let _ = versions_compat.with_context(|| {
@@ -100,49 +296,100 @@ impl Endpoint {
                 epc.endpoint().uri()
             )
         })?;
-        let _ = api_versions_compat.with_context(|| {
-            anyhow!(
-                "Checking API version compatibility for {} -> {}",
-                epc.endpoint_name(),
-                epc.endpoint().uri()
-            )
-        })?;
-        let _ = imgs_avail.with_context(|| {
-            anyhow!(
-                "Checking for available images on {} -> {}",
-                epc.endpoint_name(),
-                epc.endpoint().uri()
-            )
-        })?;
+        ep.api_version = api_versions_compat
+            .map_err(Error::from)
+            .and_then(|r| r)
+            .with_context(|| {
+                anyhow!(
+                    "Checking API version compatibility for {} -> {}",
+                    epc.endpoint_name(),
+                    epc.endpoint().uri()
+                )
+            })?;
+        let (present_images, mismatched_images) = imgs_avail
+            .map_err(Error::from)
+            .and_then(|r| r)
+            .with_context(|| {
+                anyhow!(
+                    "Checking for available images on {} -> {}",
+                    epc.endpoint_name(),
+                    epc.endpoint().uri()
+                )
+            })?;
+        ep.mismatched_images = mismatched_images;
+        ep.present_images = std::sync::RwLock::new(present_images.into_iter().collect());
+        for image in ep.mismatched_images.iter() {
+            log::warn!("Image {} on endpoint {} does not have the digest it is pinned to, not running its jobs there",
+                image.name(), epc.endpoint_name());
+        }
+
+        ep.apply_configured_capacity(epc.endpoint());
+        Ok(ep)
+    }
 
+    /// Connect to the endpoint without checking it, e.g. to find out whether it is reachable
+    pub(super) async fn connect(epc: &EndpointConfiguration) -> Result<Self> {
+        let mut ep = Endpoint::setup_endpoint(epc.endpoint_name(), epc.endpoint())?;
+
+        // An endpoint that cannot be reached is not an error here, the requests to it fail later
+        if let Some(tunnel) = ep.tunnel.as_mut() {
+            if let Err(e) = tunnel.wait_ready().await {
+                log::warn!("Connecting to endpoint {}: {:#}", epc.endpoint_name(), e);
+            }
+        }
         Ok(ep)
     }
 
     fn setup_endpoint(ep_name: &EndpointName, ep: &crate::config::Endpoint) -> Result<Endpoint> {
         match ep.endpoint_type() {
-            crate::config::EndpointType::Http => shiplift::Uri::from_str(ep.uri())
-                .map(shiplift::Docker::host)
-                .with_context(|| anyhow!("Connecting to {}", ep.uri()))
-                .map_err(Error::from)
-                .map(|docker| {
+            crate::config::EndpointType::Docker | crate::config::EndpointType::Http | crate::config::EndpointType::Socket => {
+                let timeout = std::time::Duration::from_secs(ep.timeout().unwrap_or(10));
+                let (docker, tunnel) = DockerUri::from_str(ep.uri())?
+                    .connect(ep_name.as_ref(), timeout)?;
+
+                Ok({
                     Endpoint::builder()
                         .name(ep_name.clone())
                         .uri(ep.uri().clone())
-                        .docker(docker)
+                        .timeout(timeout)
+                        .executor(Executor::Docker(docker))
                         .num_max_jobs(ep.maxjobs())
                         .network_mode(ep.network_mode().clone())
+                        .labels(ep.labels().clone())
+                        .tunnel(tunnel)
+                        .env(ep.env().clone())
+                        .mounts(ep.mounts().clone())
                         .build()
-                }),
+                })
+            },
+
+            crate::config::EndpointType::Ssh | crate::config::EndpointType::Local => {
+                let settings = ep.sandbox()
+                    .clone()
+                    .ok_or_else(|| anyhow!("Endpoint {} of type '{:?}' has no 'sandbox' settings", ep_name, ep.endpoint_type()))?;
+                if !ep.mounts().is_empty() {
+                    return Err(anyhow!("Endpoint {} of type '{:?}' has 'mounts', which are only supported on docker endpoints", ep_name, ep.endpoint_type()))
+                }
+                let timeout = std::time::Duration::from_secs(ep.timeout().unwrap_or(10));
+                let host = if *ep.endpoint_type() == crate::config::EndpointType::Local {
+                    SandboxHost::local(settings, timeout)
+                } else {
+                    SandboxHost::ssh(ep.uri().clone(), settings, timeout)
+                };
 
-            crate::config::EndpointType::Socket => Ok({
-                Endpoint::builder()
-                    .name(ep_name.clone())
-                    .uri(ep.uri().clone())
-                    .num_max_jobs(ep.maxjobs())
-                    .network_mode(ep.network_mode().clone())
-                    .docker(shiplift::Docker::unix(ep.uri()))
-                    .build()
-            }),
+                Ok({
+                    Endpoint::builder()
+                        .name(ep_name.clone())
+                        .uri(ep.uri().clone())
+                        .timeout(timeout)
+                        .num_max_jobs(ep.maxjobs())
+                        .network_mode(ep.network_mode().clone())
+                        .labels(ep.labels().clone())
+                        .executor(Executor::Sandbox(host))
+                        .env(ep.env().clone())
+                        .build()
+                })
+            },
         }
     }
 
@@ -151,7 +398,7 @@ impl Endpoint {
             None => Ok(()),
             Some(v) => {
                 let avail = ep
-                    .docker()
+                    .docker()?
                     .version()
                     .await
                     .with_context(|| anyhow!("Getting version of endpoint: {}", ep.name))?;
@@ -170,12 +417,14 @@ impl Endpoint {
         }
     }
 
-    async fn check_api_version_compat(req: Option<&Vec<String>>, ep: &Endpoint) -> Result<()> {
+    /// Check the API version of the endpoint against the allowed versions and return it, if there
+    /// are any
+    async fn check_api_version_compat(req: Option<&Vec<String>>, ep: &Endpoint) -> Result<Option<String>> {
         match req {
-            None => Ok(()),
+            None => Ok(None),
             Some(v) => {
                 let avail = ep
-                    .docker()
+                    .docker()?
                     .version()
                     .await
                     .with_context(|| anyhow!("Getting API version of endpoint: {}", ep.name))?;
@@ -184,63 +433,307 @@ impl Endpoint {
                     Err(anyhow!("Incompatible docker API version on endpoint {}: Exepected: {}, Available: [{}]",
                             ep.name(), avail.api_version, v.join(", ")))
                 } else {
-                    Ok(())
+                    Ok(Some(avail.api_version))
                 }
             }
         }
     }
 
-    async fn check_images_available(imgs: &[ImageName], ep: &Endpoint) -> Result<()> {
+    /// Negotiate the API version with the endpoint
+    ///
+    /// The requests do not specify an API version, so the daemon uses its own. That version is
+    /// returned, if it is at least `min`.
+    async fn negotiate_api_version(min: Option<&String>, ep: &Endpoint) -> Result<String> {
+        let avail = ep
+            .docker()?
+            .version()
+            .await
+            .with_context(|| anyhow!("Getting API version of endpoint: {}", ep.name))?;
+
+        if let Some(min) = min {
+            if !crate::util::docker::api_version_at_least(&avail.api_version, min)? {
+                return Err(anyhow!("Docker API version {} of endpoint {} is older than the minimum version {}",
+                    avail.api_version, ep.name(), min))
+            }
+        }
+
+        trace!("Negotiated docker API version {} with endpoint {}", avail.api_version, ep.name());
+        Ok(avail.api_version)
+    }
+
+    /// Create the docker volume `volume` for the cache `cache_name`, if it does not exist yet
+    pub async fn create_cache_volume(&self, volume: &str, cache_name: &str) -> Result<()> {
+        let mut labels = std::collections::HashMap::new();
+        labels.insert(crate::config::CACHE_VOLUME_LABEL, cache_name);
+
+        trace!("Creating cache volume {} on {}", volume, self.name);
+        self.docker()?
+            .volumes()
+            .create(&shiplift::VolumeCreateOptions::builder().name(volume).labels(&labels).build())
+            .await
+            .with_context(|| anyhow!("Creating volume {} on {}", volume, self.name))
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Get the ID of an image on this endpoint, which is the digest of the image configuration
+    pub async fn image_id(&self, image: &ImageName) -> Result<String> {
+        self.docker()?
+            .images()
+            .get(image.as_ref())
+            .inspect()
+            .await
+            .map(|details| details.id)
+            .with_context(|| anyhow!("Inspecting image '{}' on endpoint '{}'", image.as_ref(), self.name))
+            .map_err(Error::from)
+    }
+
+    /// Pull `image` if it is not present on the endpoint, showing the download progress on `bar`
+    ///
+    /// Endpoints without docker are skipped, their images have to be provided beforehand.
+    pub async fn pull_image_if_missing(&self, image: &ImageName, bar: &indicatif::ProgressBar) -> Result<()> {
+        if !self.is_docker() {
+            bar.finish_and_clear();
+            return Ok(())
+        }
+        if self.image_id(image).await.is_ok() {
+            self.mark_image_present(image);
+            bar.finish_and_clear();
+            return Ok(())
+        }
+
+        bar.set_message(format!("Pulling {} on {}", image, self.name));
+        let options = shiplift::PullOptions::builder().image(image.as_ref()).build();
+        let images = self.docker()?.images();
+        let mut stream = images.pull(&options);
+
+        // The progress of the layers, as (downloaded bytes, size)
+        let mut layers = HashMap::<String, (u64, u64)>::new();
+        while let Some(msg) = stream.next().await {
+            let msg = msg.with_context(|| anyhow!("Pulling image {} on {}", image, self.name))?;
+            trace!("Pull progress on {}: {}", self.name, msg);
+
+            if let Some(error) = msg.get("error").and_then(serde_json::Value::as_str) {
+                bar.finish_with_message(format!("Pulling {} on {} failed", image, self.name));
+                return Err(anyhow!("Pulling image {} on {} failed: {}", image, self.name, error))
+            }
+
+            let detail = msg.get("progressDetail");
+            let current = detail.and_then(|d| d.get("current")).and_then(serde_json::Value::as_u64);
+            let total = detail.and_then(|d| d.get("total")).and_then(serde_json::Value::as_u64);
+            if let (Some(id), Some(current), Some(total)) = (msg.get("id").and_then(serde_json::Value::as_str), current, total) {
+                layers.insert(id.to_string(), (current, total));
+                bar.set_length(layers.values().map(|(_, total)| total).sum());
+                bar.set_position(layers.values().map(|(current, _)| current).sum());
+            }
+        }
+
+        self.mark_image_present(image);
+        bar.finish_with_message(format!("Pulled {} on {}", image, self.name));
+        Ok(())
+    }
+
+    /// Write `image` as tarball (like `docker save`) to the file `path`, returns the size of the
+    /// tarball
+    pub async fn export_image(&self, image: &ImageName, path: &Path) -> Result<u64> {
+        use tokio::io::AsyncWriteExt;
+
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .with_context(|| anyhow!("Creating {}", path.display()))?;
+        let images = self.docker()?.images();
+        let mut stream = Box::pin(images.export(vec![image.as_ref()]));
+        let mut size = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.with_context(|| anyhow!("Exporting image {} from {}", image, self.name))?;
+            size += chunk.len() as u64;
+            file.write_all(&chunk)
+                .await
+                .with_context(|| anyhow!("Writing {}", path.display()))?;
+        }
+        file.flush().await?;
+        Ok(size)
+    }
+
+    /// Load the images in the tarball at `path` (like `docker load`) on this endpoint
+    pub async fn load_image(&self, image: &ImageName, path: &Path) -> Result<()> {
+        let tarball = std::fs::File::open(path).with_context(|| anyhow!("Opening {}", path.display()))?;
+        let mut stream = self.docker()?.images().import(tarball);
+        while let Some(msg) = stream.next().await {
+            let msg = msg.with_context(|| anyhow!("Loading image {} on {}", image, self.name))?;
+            trace!("Load progress on {}: {}", self.name, msg);
+            if let Some(error) = msg.get("error").and_then(serde_json::Value::as_str) {
+                return Err(anyhow!("Loading image {} on {} failed: {}", image, self.name, error))
+            }
+        }
+
+        self.mark_image_present(image);
+        Ok(())
+    }
+
+    fn mark_image_present(&self, image: &ImageName) {
+        if let Ok(mut present) = self.present_images.write() {
+            present.insert(image.clone());
+        }
+    }
+
+    /// Whether `image` is known to be present on this endpoint, so that running a job with it
+    /// does not need a pull first
+    ///
+    /// The images of endpoints without docker are always present, they are checked when setting
+    /// up the endpoint.
+    pub fn has_image_locally(&self, image: &ImageName) -> bool {
+        !self.is_docker() || self.present_images.read().map(|present| present.contains(image)).unwrap_or(false)
+    }
+
+    /// Check which of `imgs` are available on the endpoint
+    ///
+    /// Images that are pinned to a digest are available if an image with that digest is present.
+    /// If the image is only present with another digest, it is mismatched, so that no jobs that use
+    /// it are run on this endpoint.
+    async fn check_images_available(imgs: &[ImageName], ep: &Endpoint) -> Result<ImageCheck> {
         use shiplift::ImageListOptions;
 
         trace!("Checking availability of images: {:?}", imgs);
-        let available_names = ep
-            .docker()
+        let available = ep
+            .docker()?
             .images()
             .list(&ImageListOptions::builder().all().build())
             .await
-            .with_context(|| anyhow!("Listing images on endpoint: {}", ep.name))?
-            .into_iter()
-            .flat_map(|image_rep| {
-                image_rep
-                    .repo_tags
-                    .unwrap_or_default()
-                    .into_iter()
-                    .map(ImageName::from)
-            })
-            .collect::<Vec<ImageName>>();
+            .with_context(|| anyhow!("Listing images on endpoint: {}", ep.name))?;
 
-        trace!("Available images = {:?}", available_names);
+        let available_names = available.iter()
+            .flat_map(|image_rep| image_rep.repo_tags.iter().flatten())
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
 
-        imgs.iter()
-            .map(|img| {
-                if !available_names.contains(img) {
-                    Err(anyhow!(
-                        "Image '{}' missing from endpoint '{}'",
-                        img.as_ref(),
-                        ep.name
-                    ))
-                } else {
-                    Ok(())
-                }
-            })
-            .collect::<Result<Vec<_>>>()
-            .map(|_| ())
+        // "debian@sha256:..." -> "sha256:..."
+        let available_digests = available.iter()
+            .flat_map(|image_rep| image_rep.repo_digests.iter().flatten())
+            .filter_map(|repo_digest| repo_digest.split_once('@').map(|(_, digest)| digest))
+            .collect::<Vec<&str>>();
+
+        trace!("Available images = {:?}", available_names);
+        trace!("Available digests = {:?}", available_digests);
+
+        let mut check = ImageCheck::default();
+        for img in imgs {
+            let present = match img.digest() {
+                Some(digest) => available_digests.contains(&digest),
+                None => available_names.contains(&img.name()),
+            };
+
+            if present {
+                check.present.push(img.clone());
+            } else if img.digest().is_some() && available_names.contains(&img.name()) {
+                trace!("Image '{}' is present on endpoint '{}', but with another digest", img.name(), ep.name);
+                check.mismatched.push(img.clone());
+            } else {
+                check.missing.push(img.clone());
+            }
+        }
+        Ok(check)
     }
 
+    /// Create the container for `job` and copy its inputs into it, showing the progress of the
+    /// copying on `bar`
     pub async fn prepare_container(
         &self,
+        submit: &uuid::Uuid,
         job: RunnableJob,
         staging_store: Arc<RwLock<StagingStore>>,
+        additional_staging_stores: Vec<Arc<StagingStore>>,
         release_stores: Vec<Arc<ReleaseStore>>,
+        bar: &indicatif::ProgressBar,
     ) -> Result<PreparedContainer<'_>> {
-        PreparedContainer::new(self, job, staging_store, release_stores).await
+        PreparedContainer::new(self, submit, job, staging_store, additional_staging_stores, release_stores, bar).await
     }
 
     pub fn running_jobs(&self) -> usize {
         self.running_jobs.load(std::sync::atomic::Ordering::Relaxed)
     }
 
+    /// The environment variables of the endpoint that are set in the container of `job`
+    ///
+    /// Variables the job sets itself are not overridden. Hermetic jobs get none of them, as they
+    /// only get their declared inputs.
+    pub fn container_env(&self, job: &RunnableJob) -> Vec<(EnvironmentVariableName, String)> {
+        if *job.hermetic() {
+            return Vec::new()
+        }
+
+        self.env
+            .iter()
+            .filter(|(name, _)| {
+                name.as_ref() != crate::consts::PATCHES_ENV_VAR
+                    && job.environment().all(|(n, _)| n != *name)
+                    && job.secrets().all(|(n, _)| n != *name)
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    /// The mounts of the endpoint for the container of `job`, as docker binds
+    ///
+    /// Hermetic jobs get none of them, as they only get their declared inputs.
+    pub fn container_mounts(&self, job: &RunnableJob) -> Vec<String> {
+        if *job.hermetic() {
+            return Vec::new()
+        }
+        self.mounts.iter().map(EndpointMount::bind).collect()
+    }
+
+    /// Use the CPUs and memory from the configuration instead of the ones that were found out
+    fn apply_configured_capacity(&mut self, config: &crate::config::Endpoint) {
+        if let Some(memory) = config.memory() {
+            self.mem_total = Some(memory.bytes());
+        }
+        if let Some(cpus) = config.cpus() {
+            self.millicpus_total = Some(millicpus(cpus));
+        }
+    }
+
+    /// Whether a job with the `reservation` can run on this endpoint next to the running jobs
+    ///
+    /// Resources of the endpoint that are not known are not checked.
+    pub fn has_free_resources(&self, reservation: &Reservation) -> bool {
+        self.free_capacity_after(reservation).map(|free| free >= 0.0).unwrap_or(true)
+    }
+
+    /// The fraction of the scarcest resource of the endpoint that is left after reserving
+    /// `reservation` next to the running jobs, negative if the job does not fit
+    ///
+    /// Returns `None` if neither the memory nor the CPUs of the endpoint are known.
+    pub fn free_capacity_after(&self, reservation: &Reservation) -> Option<f64> {
+        let memory = self.mem_total.map(|total| {
+            (total, self.reserved_memory.load(std::sync::atomic::Ordering::Relaxed) + reservation.memory)
+        });
+        let cpus = self.millicpus_total.map(|total| {
+            (total, self.reserved_millicpus.load(std::sync::atomic::Ordering::Relaxed) + reservation.millicpus)
+        });
+
+        memory.into_iter()
+            .chain(cpus)
+            .map(|(total, reserved)| (total as f64 - reserved as f64) / total.max(1) as f64)
+            .reduce(f64::min)
+    }
+
+    /// Whether jobs using `image` can run on this endpoint, which they cannot if the image is
+    /// pinned to a digest and the endpoint has other content under its name
+    pub fn has_matching_image(&self, image: &ImageName) -> bool {
+        !self.mismatched_images.contains(image)
+    }
+
+    /// Whether a job reserving `memory` bytes can run on this endpoint at all
+    pub fn can_ever_fit_memory(&self, memory: u64) -> bool {
+        self.mem_total.map(|total| memory <= total).unwrap_or(true)
+    }
+
+    /// Whether a job reserving `millicpus` thousandths of CPUs can run on this endpoint at all
+    pub fn can_ever_fit_cpus(&self, millicpus: u64) -> bool {
+        self.millicpus_total.map(|total| millicpus <= total).unwrap_or(true)
+    }
+
     /// Super non-scientific utilization calculation for the endpoint
     pub fn utilization(&self) -> f64 {
         let max_jobs = self.num_max_jobs() as f64;
@@ -251,19 +744,63 @@ impl Endpoint {
 
     /// Ping the endpoint (once)
     pub async fn ping(&self) -> Result<String> {
-        self.docker.ping().await.map_err(Error::from)
+        match &self.executor {
+            Executor::Docker(docker) => docker.ping().await.map_err(Error::from),
+            Executor::Sandbox(host) => host.ping().await,
+        }
+    }
+
+    /// The docker connection of the endpoint, which fails for endpoints that do not use docker
+    pub fn docker(&self) -> Result<&Docker> {
+        match &self.executor {
+            Executor::Docker(docker) => Ok(docker),
+            Executor::Sandbox(_) => Err(anyhow!("Endpoint {} runs builds in a sandbox, not in docker", self.name)),
+        }
+    }
+
+    /// Whether builds on this endpoint run in docker containers
+    pub fn is_docker(&self) -> bool {
+        matches!(self.executor, Executor::Docker(_))
+    }
+
+    /// Whether the endpoint answered the last health check, only endpoints that are healthy get
+    /// new jobs
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Mark the endpoint as unhealthy, until the next health check that succeeds
+    pub fn set_unhealthy(&self) {
+        self.healthy.store(false, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Ping the endpoint with the timeout of the endpoint and record whether it answered
+    pub async fn check_health(&self) -> Result<()> {
+        let result = match tokio::time::timeout(self.timeout, self.ping()).await {
+            Ok(r) => r.map(|_| ()),
+            Err(_) => Err(anyhow!("No answer within {} seconds", self.timeout.as_secs())),
+        };
+
+        self.healthy.store(result.is_ok(), std::sync::atomic::Ordering::Relaxed);
+        result
     }
 
     pub async fn stats(&self) -> Result<EndpointStats> {
-        self.docker
+        self.docker()?
             .info()
             .await
             .map(EndpointStats::from)
+            .map(|stats| EndpointStats { api_version: self.api_version.clone(), ..stats })
             .map_err(Error::from)
     }
 
     pub async fn container_stats(&self) -> Result<Vec<ContainerStat>> {
-        self.docker
+        // There are no containers on endpoints without docker
+        if !self.is_docker() {
+            return Ok(vec![])
+        }
+
+        self.docker()?
             .containers()
             .list({
                 &shiplift::builder::ContainerListOptions::builder()
@@ -281,7 +818,12 @@ impl Endpoint {
     }
 
     pub async fn number_of_running_containers(&self) -> Result<usize> {
-        self.docker
+        // Only the own jobs run on endpoints without docker
+        if !self.is_docker() {
+            return Ok(self.running_jobs())
+        }
+
+        self.docker()?
             .containers()
             .list({
                 &shiplift::builder::ContainerListOptions::builder()
@@ -298,6 +840,45 @@ impl Endpoint {
             })
     }
 
+    /// Kill and remove the container `id` of a job, because its submit was canceled or butido
+    /// was asked to terminate
+    pub async fn remove_container(&self, id: &str) -> Result<()> {
+        // The processes of jobs on endpoints without docker are killed when the job is dropped
+        if !self.is_docker() {
+            return Ok(())
+        }
+
+        self.docker()?
+            .containers()
+            .get(id)
+            .remove(shiplift::RmContainerOptions::builder().force(true).build())
+            .await
+            .with_context(|| anyhow!("Removing container {} on {}", id, self.name))
+    }
+
+    /// Remove the exited containers of jobs that were created more than `retention` ago
+    ///
+    /// These are the containers of failed jobs, the containers of successful jobs are removed
+    /// right away if failed containers are only kept for a while.
+    async fn remove_expired_containers(&self, retention: chrono::Duration) -> Result<()> {
+        let expired = chrono::Utc::now() - retention;
+        let containers = self.container_stats()
+            .await?
+            .into_iter()
+            .filter(|stat| stat.job.is_some() && stat.state == "exited" && stat.created < expired)
+            .collect::<Vec<_>>();
+
+        for stat in containers.iter() {
+            log::debug!("Removing container {} of failed job {:?} on {}", stat.id, stat.job, self.name);
+            self.remove_container(&stat.id).await?;
+        }
+        if !containers.is_empty() {
+            log::info!("Removed {} containers of failed jobs on {} that were older than {}",
+                containers.len(), self.name, humantime::format_duration(retention.to_std()?));
+        }
+        Ok(())
+    }
+
     pub async fn has_container_with_id(&self, id: &str) -> Result<bool> {
         self.container_stats()
             .await?
@@ -310,7 +891,7 @@ impl Endpoint {
 
     pub async fn get_container_by_id(&self, id: &str) -> Result<Option<Container<'_>>> {
         if self.has_container_with_id(id).await? {
-            Ok(Some(self.docker.containers().get(id)))
+            Ok(Some(self.docker()?.containers().get(id)))
         } else {
             Ok(None)
         }
@@ -325,7 +906,7 @@ impl Endpoint {
             listopts.all();
         }
 
-        self.docker
+        self.docker()?
             .images()
             .list(&listopts.build())
             .await
@@ -351,6 +932,9 @@ pub struct EndpointStats {
     pub n_cpu: u64,
     pub operating_system: String,
     pub system_time: Option<String>,
+
+    /// The docker API version that is used, if it was checked or negotiated when connecting
+    pub api_version: Option<String>,
 }
 
 impl From<shiplift::rep::Info> for EndpointStats {
@@ -366,6 +950,7 @@ impl From<shiplift::rep::Info> for EndpointStats {
             n_cpu: info.n_cpu,
             operating_system: info.operating_system,
             system_time: info.system_time,
+            api_version: None,
         }
     }
 }
@@ -378,10 +963,26 @@ pub struct ContainerStat {
     pub image_id: String,
     pub state: String,
     pub status: String,
+
+    /// The name of the container, without the leading '/'
+    pub name: Option<String>,
+
+    /// The UUID of the submit, for containers of jobs
+    pub submit: Option<String>,
+
+    /// The UUID of the job, for containers of jobs
+    pub job: Option<String>,
+
+    /// The name and version of the package, for containers of jobs
+    pub package: Option<(String, String)>,
+
+    /// The endpoint the job of the container was scheduled on, for containers of jobs
+    pub endpoint: Option<String>,
 }
 
 impl From<shiplift::rep::Container> for ContainerStat {
-    fn from(cont: shiplift::rep::Container) -> Self {
+    fn from(mut cont: shiplift::rep::Container) -> Self {
+        let version = cont.labels.remove(CONTAINER_VERSION_LABEL);
         ContainerStat {
             created: cont.created,
             id: cont.id,
@@ -389,6 +990,13 @@ impl From<shiplift::rep::Container> for ContainerStat {
             image_id: cont.image_id,
             state: cont.state,
             status: cont.status,
+            name: cont.names.first().map(|name| name.trim_start_matches('/').to_string()),
+            submit: cont.labels.remove(CONTAINER_SUBMIT_LABEL),
+            job: cont.labels.remove(CONTAINER_JOB_LABEL),
+            package: cont.labels
+                .remove(CONTAINER_PACKAGE_LABEL)
+                .map(|name| (name, version.unwrap_or_default())),
+            endpoint: cont.labels.remove(CONTAINER_ENDPOINT_LABEL),
         }
     }
 }
@@ -415,13 +1023,49 @@ impl From<shiplift::rep::Image> for Image {
     }
 }
 
-pub struct EndpointHandle(Arc<Endpoint>);
+/// A number of CPUs in thousandths, so that they can be counted atomically
+pub fn millicpus(cpus: f64) -> u64 {
+    (cpus.max(0.0) * 1000.0).round() as u64
+}
+
+/// The resources that are reserved on an endpoint for a job
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Reservation {
+    /// Memory in bytes
+    pub memory: u64,
+
+    /// CPUs in thousandths
+    pub millicpus: u64,
+}
+
+impl Reservation {
+    /// Whether the job reserves any resources
+    pub fn is_empty(&self) -> bool {
+        self.memory == 0 && self.millicpus == 0
+    }
+}
+
+pub struct EndpointHandle(Arc<Endpoint>, Reservation, Arc<tokio::sync::Notify>);
 
 impl EndpointHandle {
-    pub fn new(ep: Arc<Endpoint>) -> Self {
+    /// The endpoint the job runs on
+    pub fn endpoint(&self) -> Arc<Endpoint> {
+        self.0.clone()
+    }
+
+    /// Create a handle for a job on `ep`, which reserves the resources of `reservation` on the
+    /// endpoint
+    ///
+    /// All waiters on `released` are notified when the handle is dropped and the job slot and the
+    /// resources are free again.
+    pub fn new(ep: Arc<Endpoint>, reservation: Reservation, released: Arc<tokio::sync::Notify>) -> Self {
         let res = ep.running_jobs.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
         trace!("Endpoint {} has one job more: {}", ep.name(), res + 1);
-        EndpointHandle(ep)
+        let res = ep.reserved_memory.fetch_add(reservation.memory, std::sync::atomic::Ordering::Relaxed);
+        trace!("Endpoint {} has {} bytes of memory reserved", ep.name(), res + reservation.memory);
+        let res = ep.reserved_millicpus.fetch_add(reservation.millicpus, std::sync::atomic::Ordering::Relaxed);
+        trace!("Endpoint {} has {} millicpus reserved", ep.name(), res + reservation.millicpus);
+        EndpointHandle(ep, reservation, released)
     }
 }
 
@@ -429,6 +1073,9 @@ impl Drop for EndpointHandle {
     fn drop(&mut self) {
         let res = self.0.running_jobs.fetch_sub(1, std::sync::atomic::Ordering::Relaxed);
         trace!("Endpoint {} has one job less: {}", self.0.name(), res - 1);
+        self.0.reserved_memory.fetch_sub(self.1.memory, std::sync::atomic::Ordering::Relaxed);
+        self.0.reserved_millicpus.fetch_sub(self.1.millicpus, std::sync::atomic::Ordering::Relaxed);
+        self.2.notify_waiters();
     }
 }
 
@@ -448,26 +1095,238 @@ pub struct PreparedContainer<'a> {
 
     #[getset(get = "pub")]
     create_info: shiplift::rep::ContainerCreateInfo,
+
+    /// The job, if it runs on an endpoint without docker
+    sandbox_job: Option<SandboxJob>,
+}
+
+/// Where the inputs of a job are copied to
+enum CopyTarget<'a, 'ca> {
+    Container(&'a Container<'ca>),
+
+    /// A local directory with the layout of the container, that is copied to a sandbox host
+    Directory(&'a Path),
+
+    /// Like `Directory`, but the artifacts are bind-mounted from the stores, on local endpoints
+    Local {
+        dir: &'a Path,
+        binds: &'a std::sync::Mutex<Vec<(PathBuf, PathBuf)>>,
+    },
+}
+
+impl<'a, 'ca> CopyTarget<'a, 'ca> {
+    fn id(&self) -> String {
+        match self {
+            CopyTarget::Container(container) => container.id().to_string(),
+            CopyTarget::Directory(dir) | CopyTarget::Local { dir, .. } => dir.display().to_string(),
+        }
+    }
+
+    async fn copy_file_into<P: AsRef<Path>>(&self, path: P, bytes: &[u8]) -> Result<()> {
+        match self {
+            CopyTarget::Container(container) => container.copy_file_into(path, bytes).await.map_err(Error::from),
+            CopyTarget::Directory(dir) | CopyTarget::Local { dir, .. } => {
+                // The path is the absolute path in the container
+                let path = path.as_ref();
+                let dest = dir.join(path.strip_prefix("/").unwrap_or(path));
+                if let Some(parent) = dest.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(&dest, bytes)
+                    .await
+                    .with_context(|| anyhow!("Writing {}", dest.display()))
+            },
+        }
+    }
+
+    /// Copy the file `source` to `path`, reading it chunk by chunk instead of loading it into
+    /// memory
+    async fn copy_path_into<P: AsRef<Path>>(&self, path: P, source: &Path, progress: &CopyProgress) -> Result<()> {
+        let size = tokio::fs::metadata(source)
+            .await
+            .with_context(|| anyhow!("Getting metadata of {}", source.display()))?
+            .len();
+        progress.add_total(size);
+
+        match self {
+            CopyTarget::Container(container) => {
+                let file = tokio::fs::File::open(source)
+                    .await
+                    .with_context(|| anyhow!("Opening {}", source.display()))?;
+                let archive = single_file_tar_stream(path.as_ref(), size, file, progress.clone())?;
+                container.copy_to(Path::new("/"), hyper::Body::wrap_stream(archive))
+                    .await
+                    .map_err(Error::from)
+            },
+            CopyTarget::Directory(dir) | CopyTarget::Local { dir, .. } => {
+                let path = path.as_ref();
+                let dest = dir.join(path.strip_prefix("/").unwrap_or(path));
+                if let Some(parent) = dest.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::copy(source, &dest)
+                    .await
+                    .with_context(|| anyhow!("Copying {} to {}", source.display(), dest.display()))?;
+                progress.inc(size);
+                Ok(())
+            },
+        }
+    }
+
+    async fn copy_artifact_into<P: AsRef<Path>>(&self, path: P, artifact: FullArtifactPath<'_>, progress: &CopyProgress) -> Result<()> {
+        match self {
+            CopyTarget::Local { binds, .. } => {
+                // An empty file as mount point for the artifact
+                self.copy_file_into(path.as_ref(), &[]).await?;
+                binds.lock()
+                    .map_err(|_| anyhow!("BUG: lock of artifact binds poisoned"))?
+                    .push((artifact.joined(), path.as_ref().to_path_buf()));
+                Ok(())
+            },
+            _ => self.copy_path_into(path, &artifact.joined(), progress).await,
+        }
+    }
+}
+
+/// The progress of copying the inputs of a job, shown as message of the progress bar of the job
+#[derive(Clone)]
+struct CopyProgress {
+    bar: indicatif::ProgressBar,
+
+    /// The number of bytes that are copied and the number of bytes that have to be copied
+    bytes: Arc<(std::sync::atomic::AtomicU64, std::sync::atomic::AtomicU64)>,
+}
+
+impl CopyProgress {
+    fn new(bar: indicatif::ProgressBar) -> Self {
+        CopyProgress { bar, bytes: Arc::new(Default::default()) }
+    }
+
+    fn add_total(&self, n: u64) {
+        self.bytes.1.fetch_add(n, std::sync::atomic::Ordering::Relaxed);
+        self.update();
+    }
+
+    fn inc(&self, n: u64) {
+        self.bytes.0.fetch_add(n, std::sync::atomic::Ordering::Relaxed);
+        self.update();
+    }
+
+    fn update(&self) {
+        let copied = self.bytes.0.load(std::sync::atomic::Ordering::Relaxed);
+        let total = self.bytes.1.load(std::sync::atomic::Ordering::Relaxed);
+        self.bar.set_message(format!("Copying inputs: {} / {}", bytesize::ByteSize::b(copied), bytesize::ByteSize::b(total)));
+    }
+}
+
+/// A tar archive that contains `file` with `size` bytes at `path`, without the file being loaded
+/// into memory
+fn single_file_tar_stream(
+    path: &Path,
+    size: u64,
+    file: tokio::fs::File,
+    progress: CopyProgress,
+) -> Result<impl futures::Stream<Item = std::io::Result<Vec<u8>>> + Send + 'static> {
+    const BLOCK_SIZE: u64 = 512;
+
+    // The header is taken from a builder before it is finished, so it contains no data and no
+    // end of the archive, but handles long paths
+    let header = {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o0644);
+
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append_data(&mut header, path.strip_prefix("/").unwrap_or(path), std::io::empty())?;
+        builder.get_ref().clone()
+    };
+    let padding = (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+    let trailer = vec![0u8; (padding + 2 * BLOCK_SIZE) as usize];
+
+    let content = tokio_util::io::ReaderStream::new(file)
+        .map(move |chunk| {
+            chunk.map(|chunk| {
+                progress.inc(chunk.len() as u64);
+                chunk.to_vec()
+            })
+        });
+
+    Ok(futures::stream::once(async { Ok(header) })
+        .chain(content)
+        .chain(futures::stream::once(async { Ok(trailer) })))
 }
 
 impl<'a> PreparedContainer<'a> {
     async fn new(
         endpoint: &'a Endpoint,
+        submit: &uuid::Uuid,
         job: RunnableJob,
         staging_store: Arc<RwLock<StagingStore>>,
+        additional_staging_stores: Vec<Arc<StagingStore>>,
         release_stores: Vec<Arc<ReleaseStore>>,
+        bar: &indicatif::ProgressBar,
     ) -> Result<PreparedContainer<'a>> {
         let script = job.script().clone();
-        let create_info = Self::build_container(endpoint, &job).await?;
-        let container = endpoint.docker.containers().get(&create_info.id);
+        let (create_info, mut sandbox_job) = match endpoint.executor {
+            Executor::Docker(_) => (Self::build_container(endpoint, submit, &job).await?, None),
+            Executor::Sandbox(_) => {
+                let create_info = shiplift::rep::ContainerCreateInfo {
+                    id: format!("sandbox-{}", job.uuid()),
+                    warnings: None,
+                };
+                (create_info, Some(Self::sandbox_job(endpoint, &job)))
+            },
+        };
 
+        // For endpoints without docker, the inputs are collected in a local directory first
+        let local_dir = std::env::temp_dir().join(&create_info.id);
+        let binds = std::sync::Mutex::new(Vec::new());
+        let container = endpoint.docker().ok().map(|docker| docker.containers().get(&create_info.id));
+        let target = match container.as_ref() {
+            Some(container) => CopyTarget::Container(container),
+            None => {
+                for dir in [crate::consts::INPUTS_DIR_PATH, crate::consts::OUTPUTS_DIR_PATH, crate::consts::PATCH_DIR_PATH].iter() {
+                    let dir = local_dir.join(dir.trim_start_matches('/'));
+                    tokio::fs::create_dir_all(&dir)
+                        .await
+                        .with_context(|| anyhow!("Creating directory {}", dir.display()))?;
+                }
+                match &endpoint.executor {
+                    Executor::Sandbox(host) if host.is_local() => CopyTarget::Local { dir: &local_dir, binds: &binds },
+                    _ => CopyTarget::Directory(&local_dir),
+                }
+            },
+        };
+
+        let progress = CopyProgress::new(bar.clone());
         let (cpysrc, cpypch, cpyart, cpyscr) = tokio::join!(
-            Self::copy_source_to_container(&container, &job),
-            Self::copy_patches_to_container(&container, &job),
-            Self::copy_artifacts_to_container(&container, &job, staging_store, &release_stores),
-            Self::copy_script_to_container(&container, &script)
+            Self::copy_source_to_container(&target, &job, &progress),
+            Self::copy_patches_to_container(&target, &job),
+            Self::copy_artifacts_to_container(&target, &job, staging_store, &additional_staging_stores, &release_stores, &progress),
+            Self::copy_script_to_container(&target, &script)
         );
 
+        if let Some(sandbox_job) = sandbox_job.as_mut() {
+            sandbox_job.binds = binds.into_inner().map_err(|_| anyhow!("BUG: lock of artifact binds poisoned"))?;
+        }
+
+        let synced = match (&endpoint.executor, sandbox_job.as_ref()) {
+            (Executor::Sandbox(host), Some(sandbox_job)) => {
+                let copied = cpysrc.is_ok() && cpypch.is_ok() && cpyart.is_ok() && cpyscr.is_ok();
+                let synced = if copied {
+                    host.prepare(sandbox_job, &local_dir).await
+                } else {
+                    Ok(())
+                };
+
+                tokio::fs::remove_dir_all(&local_dir)
+                    .await
+                    .with_context(|| anyhow!("Removing {}", local_dir.display()))?;
+                synced
+            },
+            _ => Ok(()),
+        };
+
         cpysrc.with_context(|| {
             anyhow!(
                 "Copying the sources to container {} on '{}'",
@@ -500,63 +1359,157 @@ impl<'a> PreparedContainer<'a> {
             )
         })?;
 
+        synced?;
+
         Ok({
             PreparedContainer {
                 endpoint,
                 script,
                 create_info,
+                sandbox_job,
             }
         })
     }
 
+    /// The environment of the job on `endpoint`, without the secrets
+    fn environment(endpoint: &Endpoint, job: &RunnableJob) -> Vec<(String, String)> {
+        endpoint.container_env(job)
+            .into_iter()
+            .map(|(k, v)| (k.as_ref().to_string(), v))
+            .chain(job.environment().map(|(k, v)| (k.as_ref().to_string(), v.clone())))
+            .chain({
+                // The paths of the patches inside the container, as they are copied by
+                // `copy_patches_to_container()`
+                let patches = job.package()
+                    .patches()
+                    .iter()
+                    .map(|patch| PathBuf::from(crate::consts::PATCH_DIR_PATH).join(patch).display().to_string())
+                    .collect::<Vec<_>>();
+
+                if patches.is_empty() {
+                    None
+                } else {
+                    Some((crate::consts::PATCHES_ENV_VAR.to_string(), patches.join(" ")))
+                }
+            })
+            .collect()
+    }
+
+    fn sandbox_job(endpoint: &Endpoint, job: &RunnableJob) -> SandboxJob {
+        SandboxJob {
+            uuid: *job.uuid(),
+            image: job.image().clone(),
+            network: *job.network(),
+            command: job.script().command_line(crate::consts::SCRIPT_PATH),
+            env: Self::environment(endpoint, job)
+                .into_iter()
+                .chain(job.secrets().map(|(k, v)| (k.as_ref().to_string(), v.expose().to_string())))
+                .collect(),
+            caches: job.cache_volumes()
+                .iter()
+                .map(|(volume, cv)| (volume.clone(), cv.path().clone()))
+                .collect(),
+            binds: Vec::new(),
+        }
+    }
+
     async fn build_container(
         endpoint: &Endpoint,
+        submit: &uuid::Uuid,
         job: &RunnableJob,
     ) -> Result<shiplift::rep::ContainerCreateInfo> {
-        let envs = job
-            .environment()
-            .map(|(k, v)| format!("{}={}", k.as_ref(), v))
+        let envs = Self::environment(endpoint, job)
+            .into_iter()
+            .map(|(k, v)| format!("{}={}", k, v))
             .collect::<Vec<_>>();
         trace!("Job resources: Environment variables = {:?}", envs);
 
+        // Secrets are added after the environment was traced, so they do not end up in the log
+        let has_secrets = job.secrets().next().is_some();
+        let envs = envs.into_iter()
+            .chain(job.secrets().map(|(k, v)| format!("{}={}", k.as_ref(), v.expose())))
+            .collect::<Vec<_>>();
+
         let builder_opts = {
             let mut builder_opts = shiplift::ContainerOptions::builder(job.image().as_ref());
-            let container_name = format!("butido-{package}-{version}-{id}",
-                package = job.package().name().as_ref(),
-                version = job.package().version().as_ref(),
-                id = job.uuid()
-            );
+            let container_name = container_name(job.package().name(), job.package().version(), job.uuid());
             trace!("container name = {}", container_name);
             builder_opts.name(&container_name);
             builder_opts.env(envs.iter().map(AsRef::as_ref).collect::<Vec<&str>>());
             builder_opts.cmd(vec!["/bin/bash"]); // we start the container with /bin/bash, but exec() the script in it later
             builder_opts.attach_stdin(true); // we have to attach, otherwise bash exits
 
-            if let Some(network_mode) = endpoint.network_mode().as_ref() {
+            let submit = submit.to_string();
+            let job_uuid = job.uuid().to_string();
+            // The labels of butido cannot be overridden by the configured ones
+            let mut labels = endpoint.container_labels
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<HashMap<_, _>>();
+            labels.extend([
+                (CONTAINER_SUBMIT_LABEL, submit.as_str()),
+                (CONTAINER_JOB_LABEL, job_uuid.as_str()),
+                (CONTAINER_PACKAGE_LABEL, job.package().name().as_ref()),
+                (CONTAINER_VERSION_LABEL, job.package().version().as_ref()),
+                (CONTAINER_ENDPOINT_LABEL, endpoint.name().as_ref()),
+            ]);
+            builder_opts.labels(&labels);
+
+            if *job.network() == crate::package::Network::None {
+                trace!("Disabling network for container {}", container_name);
+                builder_opts.network_mode("none");
+            } else if let Some(network_mode) = endpoint.network_mode().as_ref() {
                 builder_opts.network_mode(network_mode);
             }
 
+            let binds = job.cache_volumes()
+                .iter()
+                .map(|(volume, cv)| format!("{}:{}", volume, cv.path()))
+                .chain(endpoint.container_mounts(job))
+                .collect::<Vec<_>>();
+            if !binds.is_empty() {
+                builder_opts.volumes(binds.iter().map(AsRef::as_ref).collect());
+            }
+
+            if let Some(memory) = job.limits().memory() {
+                builder_opts.memory(memory.bytes());
+            }
+            if let Some(cpus) = job.limits().cpus() {
+                builder_opts.cpus(cpus);
+            }
+            if let Some(cpu_shares) = job.limits().cpu_shares() {
+                builder_opts.cpu_shares(cpu_shares);
+            }
+
             builder_opts.build()
         };
-        trace!("Builder options = {:?}", builder_opts);
+        let builder_opts_display = if has_secrets {
+            String::from("<not shown, contains secrets>")
+        } else {
+            format!("{:?}", builder_opts)
+        };
+        trace!("Builder options = {}", builder_opts_display);
+
+        for (volume, cv) in job.cache_volumes() {
+            endpoint.create_cache_volume(volume, cv.name()).await?;
+        }
 
         let create_info = endpoint
-            .docker
+            .docker()?
             .containers()
             .create(&builder_opts)
             .await
-            .with_context(|| anyhow!("Creating container with builder options = {:?}", builder_opts))
+            .with_context(|| anyhow!("Creating container with builder options = {}", builder_opts_display))
             .with_context(|| anyhow!("Creating container on '{}'", endpoint.name))?;
         trace!("Create info = {:?}", create_info);
         Ok(create_info)
     }
 
-    async fn copy_source_to_container<'ca>(
-        container: &Container<'ca>,
+    async fn copy_source_to_container(
+        target: &CopyTarget<'_, '_>,
         job: &RunnableJob,
+        progress: &CopyProgress,
     ) -> Result<()> {
-        use tokio::io::AsyncReadExt;
-
         job.package_sources()
             .into_iter()
             .map(|entry| async {
@@ -569,43 +1522,30 @@ impl<'a> PreparedContainer<'a> {
                             anyhow!(
                                 "Copying package source from {} to container {}",
                                 source_path.display(),
-                                container.id()
+                                target.id()
                             )
                         })?
                 });
                 trace!("Source path    = {:?}", source_path);
                 trace!("Source dest    = {:?}", destination);
-                let mut buf = vec![];
-                tokio::fs::OpenOptions::new()
-                    .create(false)
-                    .create_new(false)
-                    .append(false)
-                    .write(false)
-                    .read(true)
-                    .open(&source_path)
-                    .await
-                    .with_context(|| anyhow!("Getting source file: {}", source_path.display()))?
-                    .read_to_end(&mut buf)
-                    .await
-                    .with_context(|| anyhow!("Reading file {}", source_path.display()))?;
 
                 drop(entry);
-                container.copy_file_into(destination, &buf)
+                target.copy_path_into(destination, &source_path, progress)
                     .await
-                    .inspect(|_| trace!("Successfully copied source {} to container {}", source_path.display(), container.id()))
-                    .with_context(|| anyhow!("Failed to copy source {} to container {}", source_path.display(), container.id()))
+                    .inspect(|_| trace!("Successfully copied source {} to container {}", source_path.display(), target.id()))
+                    .with_context(|| anyhow!("Failed to copy source {} to container {}", source_path.display(), target.id()))
                     .map_err(Error::from)
             })
             .collect::<futures::stream::FuturesUnordered<_>>()
             .collect::<Result<()>>()
             .await
-            .inspect(|_| trace!("Successfully copied sources to container {}", container.id()))
-            .with_context(|| anyhow!("Copying sources to container {}", container.id()))
+            .inspect(|_| trace!("Successfully copied sources to container {}", target.id()))
+            .with_context(|| anyhow!("Copying sources to container {}", target.id()))
             .map_err(Error::from)
     }
 
-    async fn copy_patches_to_container<'ca>(
-        container: &Container<'ca>,
+    async fn copy_patches_to_container(
+        target: &CopyTarget<'_, '_>,
         job: &RunnableJob,
     ) -> Result<()> {
         use tokio::io::AsyncReadExt;
@@ -632,11 +1572,11 @@ impl<'a> PreparedContainer<'a> {
                     .await
                     .with_context(|| anyhow!("Reading file {}", patch.display()))?;
 
-                container.copy_file_into(destination, &buf)
+                target.copy_file_into(destination, &buf)
                     .await
                     .map_err(Error::from)
                     .inspect(|_| trace!("Copying patch {} successfull", patch.display()))
-                    .with_context(|| anyhow!("Copying patch {} to container {}", patch.display(), container.id()))
+                    .with_context(|| anyhow!("Copying patch {} to container {}", patch.display(), target.id()))
                     .map_err(Error::from)
             })
             .collect::<futures::stream::FuturesUnordered<_>>()
@@ -644,15 +1584,17 @@ impl<'a> PreparedContainer<'a> {
             .await
             .map_err(Error::from)
             .inspect(|_| trace!("Copied all patches"))
-            .with_context(|| anyhow!("Copying patches to container {}", container.id()))
+            .with_context(|| anyhow!("Copying patches to container {}", target.id()))
             .map_err(Error::from)
     }
 
-    async fn copy_artifacts_to_container<'ca>(
-        container: &Container<'ca>,
+    async fn copy_artifacts_to_container(
+        target: &CopyTarget<'_, '_>,
         job: &RunnableJob,
         staging_store: Arc<RwLock<StagingStore>>,
+        additional_staging_stores: &[Arc<StagingStore>],
         release_stores: &[Arc<ReleaseStore>],
+        progress: &CopyProgress,
     ) -> Result<()> {
         let stream = job.resources()
             .iter()
@@ -665,32 +1607,35 @@ impl<'a> PreparedContainer<'a> {
                     .with_context(|| {
                         anyhow!(
                             "Collecting artifacts for copying to container {}",
-                            container.id()
+                            target.id()
                         )
                     })?;
                 let destination = PathBuf::from(crate::consts::INPUTS_DIR_PATH).join(artifact_file_name);
                 trace!(
                     "Copying {} to container: {}:{}",
                     art.display(),
-                    container.id(),
+                    target.id(),
                     destination.display()
                 );
                 let staging_read = staging_store.read().await;
-                let buf = match staging_read.root_path().join(&art)?  {
+                let artifact = match staging_read.root_path().join(&art)?  {
                     Some(fp) => fp,
                     None     => {
                         // TODO: Optimize.
                         // I know this is not nice, but it works for now.
                         let mut found = None;
-                        for release_store in release_stores.iter() {
-                            let p = release_store.root_path().join(&art);
+                        let store_roots = additional_staging_stores.iter()
+                            .map(|s| s.root_path())
+                            .chain(release_stores.iter().map(|r| r.root_path()));
+                        for store_root in store_roots {
+                            let p = store_root.join(&art);
                             match p {
                                 Ok(Some(path)) => {
                                     found = Some(path);
                                     break;
                                 },
                                 Err(e) => {
-                                    trace!("Failed to join '{:?}' + '{:?}'", release_store.root_path(), art.display());
+                                    trace!("Failed to join '{:?}' + '{:?}'", store_root, art.display());
                                     return Err(e)
                                 },
                                 Ok(None) =>  continue,
@@ -698,26 +1643,17 @@ impl<'a> PreparedContainer<'a> {
                         }
                         found.ok_or_else(|| anyhow!("Not found in staging or release store: {:?}", art))?
                     },
-                }
-                .read()
-                .await
-                .with_context(|| {
-                    anyhow!(
-                        "Reading artifact {}, so it can be copied to container",
-                        art.display()
-                    )
-                })?;
-                trace!("Successfully read {} into buffer", art.display());
+                };
 
-                let r = container
-                    .copy_file_into(&destination, &buf)
+                let r = target
+                    .copy_artifact_into(&destination, artifact, progress)
                     .await
                     .inspect(|_| trace!("Successfully copied {} to container", art.display()))
                     .with_context(|| {
                         anyhow!(
                             "Copying artifact {} to container {} at {}",
                             art.display(),
-                            container.id(),
+                            target.id(),
                             destination.display()
                         )
                     })
@@ -734,48 +1670,52 @@ impl<'a> PreparedContainer<'a> {
         stream
             .collect::<Result<Vec<_>>>()
             .await
-            .inspect(|_| trace!("Successfully copied all artifacts to the container {}", container.id()))
-            .with_context(|| anyhow!("Copying artifacts to container {}", container.id()))
+            .inspect(|_| trace!("Successfully copied all artifacts to the container {}", target.id()))
+            .with_context(|| anyhow!("Copying artifacts to container {}", target.id()))
             .map_err(Error::from)
             .map(|_| ())
     }
 
-    async fn copy_script_to_container<'ca>(
-        container: &Container<'ca>,
+    async fn copy_script_to_container(
+        target: &CopyTarget<'_, '_>,
         script: &Script,
     ) -> Result<()> {
         let script_path = PathBuf::from(crate::consts::SCRIPT_PATH);
-        container
+        target
             .copy_file_into(script_path, script.as_ref().as_bytes())
             .await
-            .inspect(|_| trace!("Successfully copied script to container {}", container.id()))
-            .with_context(|| anyhow!("Copying the script into container {}", container.id()))
+            .inspect(|_| trace!("Successfully copied script to container {}", target.id()))
+            .with_context(|| anyhow!("Copying the script into container {}", target.id()))
             .map_err(Error::from)
     }
 
     pub async fn start(self) -> Result<StartedContainer<'a>> {
-        self.endpoint
-            .docker
-            .containers()
-            .get(&self.create_info.id)
-            .start()
-            .inspect(|r| trace!("Starting container {} -> {:?}", self.create_info.id, r))
-            .map(|r| {
-                r.with_context(|| {
-                    anyhow!(
-                        "Starting the container {} on '{}'",
-                        self.create_info.id,
-                        self.endpoint.name
-                    )
+        // On endpoints without docker, the sandbox is only started with the script
+        if self.sandbox_job.is_none() {
+            self.endpoint
+                .docker()?
+                .containers()
+                .get(&self.create_info.id)
+                .start()
+                .inspect(|r| trace!("Starting container {} -> {:?}", self.create_info.id, r))
+                .map(|r| {
+                    r.with_context(|| {
+                        anyhow!(
+                            "Starting the container {} on '{}'",
+                            self.create_info.id,
+                            self.endpoint.name
+                        )
+                    })
                 })
-            })
-            .await?;
+                .await?;
+        }
 
         Ok({
             StartedContainer {
                 endpoint: self.endpoint,
                 script: self.script,
                 create_info: self.create_info,
+                sandbox_job: self.sandbox_job,
             }
         })
     }
@@ -785,29 +1725,44 @@ pub struct StartedContainer<'a> {
     endpoint: &'a Endpoint,
     script: Script,
     create_info: shiplift::rep::ContainerCreateInfo,
+    sandbox_job: Option<SandboxJob>,
 }
 
+/// The lines of the output of the script of a job
+type LineStream<'a> = std::pin::Pin<Box<dyn futures::Stream<Item = Result<(OutputStream, String)>> + Send + 'a>>;
+
 impl<'a> StartedContainer<'a> {
     pub async fn execute_script(
         self,
         logsink: UnboundedSender<LogItem>,
     ) -> Result<ExecutedContainer<'a>> {
+        let command = self.script.command_line(crate::consts::SCRIPT_PATH);
         let exec_opts = ExecContainerOptions::builder()
-            .cmd(vec!["/bin/bash", "/script"])
+            .cmd(command.iter().map(String::as_str).collect())
             .attach_stderr(true)
             .attach_stdout(true)
             .build();
         trace!("Exec options = {:?}", exec_opts);
 
         trace!("Moving logs to log sink for container {}", self.create_info.id);
-        let stream = self.endpoint
-            .docker
-            .containers()
-            .get(&self.create_info.id)
-            .exec(&exec_opts);
+        // The sandbox passes stderr on stdout, so only the lines of docker containers are told apart
+        let lines: LineStream<'a> = match (&self.endpoint.executor, self.sandbox_job.as_ref()) {
+            (Executor::Sandbox(host), Some(sandbox_job)) => {
+                Box::pin(host.execute(sandbox_job).await?.map(|line| line.map(|l| (OutputStream::Stdout, l))))
+            },
+            _ => {
+                let stream = self.endpoint
+                    .docker()?
+                    .containers()
+                    .get(&self.create_info.id)
+                    .exec(&exec_opts);
+
+                Box::pin(buffer_stream_to_line_stream(stream).map(|line| line.map_err(Error::from)))
+            },
+        };
 
         let exited_successfully: Option<(bool, Option<String>)> =
-            buffer_stream_to_line_stream(stream)
+            lines
                 .map(|line| {
                     trace!(
                         "['{}':{}] Found log line: {:?}",
@@ -822,9 +1777,13 @@ impl<'a> StartedContainer<'a> {
                             self.create_info.id
                         )
                     })
-                    .and_then(|l| {
+                    .and_then(|(output, l)| {
                         crate::log::parser()
                             .parse(l.as_bytes())
+                            .map(|item| match (output, item) {
+                                (OutputStream::Stderr, LogItem::Line(line)) => LogItem::StderrLine(line),
+                                (_, item) => item,
+                            })
                             .with_context(|| {
                                 anyhow!(
                                     "Parsing log from {}:{}: {:?}",
@@ -881,6 +1840,7 @@ impl<'a> StartedContainer<'a> {
                 create_info: self.create_info,
                 script: self.script,
                 exit_info: exited_successfully,
+                sandbox_job: self.sandbox_job,
             }
         })
     }
@@ -891,6 +1851,7 @@ pub struct ExecutedContainer<'a> {
     create_info: shiplift::rep::ContainerCreateInfo,
     script: Script,
     exit_info: Option<(bool, Option<String>)>,
+    sandbox_job: Option<SandboxJob>,
 }
 
 impl<'a> ExecutedContainer<'a> {
@@ -903,16 +1864,32 @@ impl<'a> ExecutedContainer<'a> {
     }
 
     pub async fn finalize(self, staging_store: Arc<RwLock<StagingStore>>) -> Result<FinalizedContainer> {
+        if let (Executor::Sandbox(host), Some(sandbox_job)) = (&self.endpoint.executor, self.sandbox_job.as_ref()) {
+            return Self::finalize_ssh(host, sandbox_job, &self.create_info, self.exit_info, staging_store).await
+        }
+
         let (exit_info, artifacts) = match self.exit_info {
             Some((false, msg)) => {
                 let err = anyhow!("Error during container run: '{msg}'", msg = msg.as_deref().unwrap_or(""));
 
+                // The container is kept for debugging until it expires, stopped so that it is
+                // found as exited then
+                if self.endpoint.failed_container_retention.is_some() {
+                    let id = &self.create_info.id;
+                    self.endpoint.docker()?
+                        .containers()
+                        .get(id)
+                        .stop(Some(std::time::Duration::new(1, 0)))
+                        .await
+                        .with_context(|| anyhow!("Stopping container {}", id))?;
+                }
+
                 // error because the container errored
                 (Err(err), vec![])
             }
 
             Some((true, _)) | None => {
-                let container = self.endpoint.docker.containers().get(&self.create_info.id);
+                let container = self.endpoint.docker()?.containers().get(&self.create_info.id);
 
                 trace!("Fetching {} from container {}", crate::consts::OUTPUTS_DIR_PATH, self.create_info.id);
                 let tar_stream = container
@@ -932,10 +1909,14 @@ impl<'a> ExecutedContainer<'a> {
                     .write_files_from_tar_stream(tar_stream)
                     .await
                     .with_context(|| anyhow!("Copying the TAR stream to the staging store"))?;
-                container
-                    .stop(Some(std::time::Duration::new(1, 0)))
-                    .await
-                    .with_context(|| anyhow!("Stopping container {}", self.create_info.id))?;
+                if self.endpoint.failed_container_retention.is_some() {
+                    self.endpoint.remove_container(&self.create_info.id).await?;
+                } else {
+                    container
+                        .stop(Some(std::time::Duration::new(1, 0)))
+                        .await
+                        .with_context(|| anyhow!("Stopping container {}", self.create_info.id))?;
+                }
                 (Ok(()), artifacts)
             }
         };
@@ -947,6 +1928,56 @@ impl<'a> ExecutedContainer<'a> {
             }
         })
     }
+
+    /// Copy the outputs of a job on an endpoint without docker back into the staging store and remove the
+    /// directory of the job on the host
+    async fn finalize_ssh(
+        host: &SandboxHost,
+        sandbox_job: &SandboxJob,
+        create_info: &shiplift::rep::ContainerCreateInfo,
+        exit_info: Option<(bool, Option<String>)>,
+        staging_store: Arc<RwLock<StagingStore>>,
+    ) -> Result<FinalizedContainer> {
+        let (exit_info, artifacts) = match exit_info {
+            Some((false, msg)) => {
+                let err = anyhow!("Error during container run: '{msg}'", msg = msg.as_deref().unwrap_or(""));
+                (Err(err), vec![])
+            },
+
+            Some((true, _)) | None => {
+                let local_dir = std::env::temp_dir().join(&create_info.id);
+                let outputs = host.fetch_outputs(&sandbox_job.uuid, &local_dir).await?;
+
+                // The staging store imports TAR streams, as they come from docker
+                let archive = {
+                    let mut builder = tar::Builder::new(Vec::new());
+                    builder.append_dir_all(crate::consts::OUTPUTS_DIR_NAME, &outputs)?;
+                    builder.into_inner()?
+                };
+                tokio::fs::remove_dir_all(&local_dir)
+                    .await
+                    .with_context(|| anyhow!("Removing {}", local_dir.display()))?;
+
+                let mut writelock = staging_store.write().await;
+                let artifacts = writelock
+                    .write_files_from_tar_stream(futures::stream::once(async { Ok(archive) }))
+                    .await
+                    .with_context(|| anyhow!("Copying the outputs of {} to the staging store", create_info.id))?;
+                (Ok(()), artifacts)
+            },
+        };
+
+        host.cleanup(&sandbox_job.uuid)
+            .await
+            .with_context(|| anyhow!("Removing the directory of job {}", sandbox_job.uuid))?;
+
+        Ok({
+            FinalizedContainer {
+                artifacts,
+                exit_info,
+            }
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -960,3 +1991,112 @@ impl FinalizedContainer {
         (self.artifacts, self.exit_info)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_name() {
+        let job = uuid::Uuid::parse_str("6f2e1bba-4d1c-4c4b-8a5e-1d0c3f7f9a21").unwrap();
+        assert_eq!(container_name("gcc", "12.2.0", &job), "butido-gcc-12.2.0-6f2e1bba");
+        assert_eq!(container_name("core/libc++", "1.0~rc1", &job), "butido-core_libc__-1.0_rc1-6f2e1bba");
+    }
+
+    #[test]
+    fn test_single_file_tar_stream() {
+        use futures::TryStreamExt;
+        use std::io::Read;
+        use std::io::Write;
+
+        let content = (0..1000u32).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let mut tmp = tempfile::NamedTempFile::new().unwrap();
+        tmp.write_all(&content).unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (archive, progress) = rt.block_on(async {
+            let file = tokio::fs::File::open(tmp.path()).await.unwrap();
+            let progress = CopyProgress::new(indicatif::ProgressBar::hidden());
+            let archive = single_file_tar_stream(Path::new("/inputs/source.tar.gz"), content.len() as u64, file, progress.clone())
+                .unwrap()
+                .try_concat()
+                .await
+                .unwrap();
+            (archive, progress)
+        });
+
+        assert_eq!(archive.len() % 512, 0);
+        assert_eq!(progress.bytes.0.load(std::sync::atomic::Ordering::Relaxed), content.len() as u64);
+
+        let mut archive = tar::Archive::new(&archive[..]);
+        let mut entries = archive.entries().unwrap();
+        let mut entry = entries.next().unwrap().unwrap();
+        assert_eq!(entry.path().unwrap(), Path::new("inputs/source.tar.gz"));
+        let mut read = Vec::new();
+        entry.read_to_end(&mut read).unwrap();
+        assert_eq!(read, content);
+        drop(entry);
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn test_endpoint_mounts() {
+        let config: crate::config::Endpoint = toml::from_str(indoc::indoc!(r#"
+            uri = "unix:///nonexistent/docker.sock"
+            maxjobs = 1
+            env = { HTTP_PROXY = "http://proxy.dc1:3128" }
+            mounts = [
+                { source = "/srv/mirror", target = "/mirror", read_only = true },
+                { source = "/srv/scratch", target = "/scratch" },
+            ]
+        "#)).unwrap();
+        let ep = Endpoint::setup_endpoint(&EndpointName::from("test".to_string()), &config).unwrap();
+        assert_eq!(ep.env().get(&EnvironmentVariableName::from("HTTP_PROXY")).map(String::as_str), Some("http://proxy.dc1:3128"));
+        assert_eq!(ep.mounts().iter().map(EndpointMount::bind).collect::<Vec<_>>(),
+            vec!["/srv/mirror:/mirror:ro", "/srv/scratch:/scratch"]);
+
+        let config: crate::config::Endpoint = toml::from_str(indoc::indoc!(r#"
+            uri = "localhost"
+            endpoint_type = "local"
+            maxjobs = 1
+            sandbox = { images_dir = "/srv/images", work_dir = "/srv/work" }
+            mounts = [ { source = "/srv/mirror", target = "/mirror" } ]
+        "#)).unwrap();
+        assert!(Endpoint::setup_endpoint(&EndpointName::from("test".to_string()), &config).is_err());
+    }
+
+    #[test]
+    fn test_free_capacity_after() {
+        let mut ep = Endpoint::builder()
+            .name(EndpointName::from("test".to_string()))
+            .uri("/nonexistent/docker.sock".to_string())
+            .timeout(std::time::Duration::from_secs(1))
+            .executor(Executor::Docker(Docker::unix("/nonexistent/docker.sock")))
+            .num_max_jobs(4)
+            .network_mode(None)
+            .labels(vec![])
+            .build();
+        let job = Reservation { memory: 4096, millicpus: millicpus(2.0) };
+        assert_eq!(ep.free_capacity_after(&job), None);
+        assert!(ep.has_free_resources(&job));
+
+        ep.mem_total = Some(8192);
+        ep.millicpus_total = Some(millicpus(8.0));
+        assert_eq!(ep.free_capacity_after(&job), Some(0.5));
+
+        let ep = Arc::new(ep);
+        let released = Arc::new(tokio::sync::Notify::new());
+        let handle = EndpointHandle::new(ep.clone(), job, released.clone());
+        assert_eq!(ep.free_capacity_after(&job), Some(0.0));
+        assert!(ep.has_free_resources(&job));
+
+        let _second = EndpointHandle::new(ep.clone(), job, released);
+        assert!(!ep.has_free_resources(&Reservation { memory: 0, millicpus: millicpus(4.5) }));
+        assert!(ep.has_free_resources(&Reservation { memory: 0, millicpus: millicpus(4.0) }));
+
+        drop(handle);
+        assert_eq!(ep.free_capacity_after(&job), Some(0.0));
+        assert!(ep.can_ever_fit_cpus(millicpus(8.0)));
+        assert!(!ep.can_ever_fit_memory(8193));
+    }
+}
@@ -0,0 +1,236 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Connecting to the docker daemon of an endpoint via its URI
+
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use log::trace;
+use shiplift::Docker;
+use tokio::io::AsyncReadExt;
+
+/// The socket of the docker daemon on hosts that are reached over SSH, if the URI has no path
+const DEFAULT_REMOTE_DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+/// The sockets of rootless docker and podman, relative to `$XDG_RUNTIME_DIR`, in the order they
+/// are looked for with the URI "rootless"
+const ROOTLESS_SOCKETS: &[&str] = &["docker.sock", "podman/podman.sock"];
+
+/// How the docker daemon of an endpoint is reached
+#[derive(Debug, Eq, PartialEq)]
+pub enum DockerUri {
+    /// A unix socket, `unix:///var/run/docker.sock` or just the path of the socket
+    ///
+    /// `$XDG_RUNTIME_DIR` in the path is replaced, e.g. for the socket of a rootless daemon
+    /// (`unix://$XDG_RUNTIME_DIR/docker.sock`). The URI "rootless" is the socket of the rootless
+    /// docker or podman daemon of the user butido runs as.
+    Unix(String),
+
+    /// TCP, `tcp://host:2376`, `http://host:port` or `https://host:port`
+    ///
+    /// For `tcp://` URIs, TLS is used if `DOCKER_CERT_PATH` is set, like the docker CLI does.
+    Tcp(String),
+
+    /// The socket of the daemon on another host, forwarded over SSH,
+    /// `ssh://[user@]host[:port][/path/to/docker.sock]`
+    Ssh {
+        destination: String,
+        port: Option<u16>,
+        socket: String,
+    },
+}
+
+impl FromStr for DockerUri {
+    type Err = anyhow::Error;
+
+    fn from_str(uri: &str) -> Result<Self> {
+        let runtime_dir = std::env::var("XDG_RUNTIME_DIR").ok();
+        if uri == "rootless" {
+            return rootless_socket(runtime_dir.as_deref()).map(DockerUri::Unix)
+        }
+        if uri.starts_with('/') || uri.starts_with("$XDG_RUNTIME_DIR") || uri.starts_with("${XDG_RUNTIME_DIR}") {
+            return expand_runtime_dir(uri, runtime_dir.as_deref()).map(DockerUri::Unix)
+        }
+
+        let (scheme, rest) = uri.split_once("://")
+            .ok_or_else(|| anyhow!("Docker URI '{}' has no scheme, expected unix://, tcp://, http(s):// or ssh://", uri))?;
+        match scheme {
+            "unix" => expand_runtime_dir(rest, runtime_dir.as_deref()).map(DockerUri::Unix),
+            "tcp" if std::env::var_os("DOCKER_CERT_PATH").is_some() => Ok(DockerUri::Tcp(uri.to_string())),
+            "tcp" => Ok(DockerUri::Tcp(format!("http://{}", rest))),
+            "http" | "https" => Ok(DockerUri::Tcp(uri.to_string())),
+            "ssh" => {
+                let url = url::Url::parse(uri).with_context(|| anyhow!("Parsing docker URI '{}'", uri))?;
+                let host = url.host_str().ok_or_else(|| anyhow!("Docker URI '{}' has no host", uri))?;
+                let destination = if url.username().is_empty() {
+                    host.to_string()
+                } else {
+                    format!("{}@{}", url.username(), host)
+                };
+                let socket = match url.path() {
+                    "" | "/" => DEFAULT_REMOTE_DOCKER_SOCKET.to_string(),
+                    path => path.to_string(),
+                };
+                Ok(DockerUri::Ssh { destination, port: url.port(), socket })
+            },
+            other => Err(anyhow!("Unsupported scheme '{}' in docker URI '{}'", other, uri)),
+        }
+    }
+}
+
+/// Replace `$XDG_RUNTIME_DIR` in the socket `path` with `runtime_dir`
+fn expand_runtime_dir(path: &str, runtime_dir: Option<&str>) -> Result<String> {
+    if !path.contains("XDG_RUNTIME_DIR") {
+        return Ok(path.to_string())
+    }
+
+    let runtime_dir = runtime_dir
+        .ok_or_else(|| anyhow!("Docker socket '{}' contains XDG_RUNTIME_DIR, which is not set", path))?;
+    Ok(path.replace("${XDG_RUNTIME_DIR}", runtime_dir).replace("$XDG_RUNTIME_DIR", runtime_dir))
+}
+
+/// The socket of the rootless docker or podman daemon in `runtime_dir`, i.e. `$XDG_RUNTIME_DIR`
+fn rootless_socket(runtime_dir: Option<&str>) -> Result<String> {
+    let runtime_dir = runtime_dir
+        .ok_or_else(|| anyhow!("Cannot find the socket of a rootless daemon, XDG_RUNTIME_DIR is not set"))?;
+    ROOTLESS_SOCKETS
+        .iter()
+        .map(|socket| std::path::Path::new(runtime_dir).join(socket))
+        .find(|path| path.exists())
+        .map(|path| path.display().to_string())
+        .ok_or_else(|| anyhow!("No socket of a rootless docker or podman daemon in {} (looked for {})", runtime_dir, ROOTLESS_SOCKETS.join(", ")))
+}
+
+impl DockerUri {
+    /// Connect to the docker daemon, opening an SSH tunnel to it if necessary
+    ///
+    /// The tunnel has to be kept as long as the connection is used.
+    pub fn connect(&self, endpoint_name: &str, timeout: Duration) -> Result<(Docker, Option<SshTunnel>)> {
+        match self {
+            DockerUri::Unix(path) => Ok((Docker::unix(path), None)),
+            DockerUri::Tcp(uri) => shiplift::Uri::from_str(uri)
+                .map(|uri| (Docker::host(uri), None))
+                .with_context(|| anyhow!("Connecting to {}", uri)),
+            DockerUri::Ssh { destination, port, socket } => {
+                let local_socket = std::env::temp_dir()
+                    .join(format!("butido-{}-{}.sock", std::process::id(), endpoint_name));
+                let tunnel = SshTunnel::open(destination, *port, socket, local_socket, timeout)?;
+                Ok((Docker::unix(tunnel.local_socket.display().to_string()), Some(tunnel)))
+            },
+        }
+    }
+}
+
+/// An SSH process that forwards the socket of a remote docker daemon to a local socket
+///
+/// The process is killed and the local socket is removed when the tunnel is dropped.
+pub struct SshTunnel {
+    child: tokio::process::Child,
+    destination: String,
+    local_socket: PathBuf,
+    timeout: Duration,
+}
+
+impl SshTunnel {
+    fn open(destination: &str, port: Option<u16>, remote_socket: &str, local_socket: PathBuf, timeout: Duration) -> Result<Self> {
+        // A socket of an earlier run that was not removed would make the forwarding fail
+        let _ = std::fs::remove_file(&local_socket);
+
+        let mut cmd = tokio::process::Command::new("ssh");
+        cmd.arg("-nNT")
+            .arg("-o").arg("BatchMode=yes")
+            .arg("-o").arg(format!("ConnectTimeout={}", timeout.as_secs()))
+            .arg("-o").arg("ExitOnForwardFailure=yes");
+        if let Some(port) = port {
+            cmd.arg("-p").arg(port.to_string());
+        }
+        cmd.arg("-L")
+            .arg(format!("{}:{}", local_socket.display(), remote_socket))
+            .arg(destination)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        trace!("Opening SSH tunnel: {:?}", cmd);
+        let child = cmd.spawn().with_context(|| anyhow!("Starting ssh for the docker socket on {}", destination))?;
+        Ok(SshTunnel { child, destination: destination.to_string(), local_socket, timeout })
+    }
+
+    /// Wait until the local socket exists, i.e. ssh connected and forwards the socket
+    pub async fn wait_ready(&mut self) -> Result<()> {
+        let started = std::time::Instant::now();
+        while !self.local_socket.exists() {
+            if let Some(status) = self.child.try_wait()? {
+                let mut stderr = String::new();
+                if let Some(mut err) = self.child.stderr.take() {
+                    let _ = err.read_to_string(&mut stderr).await;
+                }
+                return Err(anyhow!("SSH tunnel to {} exited with {}: {}", self.destination, status, stderr.trim()))
+            }
+
+            if started.elapsed() > self.timeout {
+                return Err(anyhow!("Timeout waiting for the SSH tunnel to {}", self.destination))
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.local_socket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_docker_uris() {
+        assert_eq!(DockerUri::from_str("/var/run/docker.sock").unwrap(), DockerUri::Unix("/var/run/docker.sock".to_string()));
+        assert_eq!(DockerUri::from_str("unix:///var/run/docker.sock").unwrap(), DockerUri::Unix("/var/run/docker.sock".to_string()));
+        assert_eq!(DockerUri::from_str("http://0.0.0.0:8095").unwrap(), DockerUri::Tcp("http://0.0.0.0:8095".to_string()));
+        assert!(DockerUri::from_str("localhost:2375").is_err());
+        assert!(DockerUri::from_str("ftp://localhost").is_err());
+    }
+
+    #[test]
+    fn test_expand_runtime_dir() {
+        assert_eq!(expand_runtime_dir("$XDG_RUNTIME_DIR/docker.sock", Some("/run/user/1000")).unwrap(), "/run/user/1000/docker.sock");
+        assert_eq!(expand_runtime_dir("${XDG_RUNTIME_DIR}/podman/podman.sock", Some("/run/user/1000")).unwrap(), "/run/user/1000/podman/podman.sock");
+        assert_eq!(expand_runtime_dir("/var/run/docker.sock", None).unwrap(), "/var/run/docker.sock");
+        assert!(expand_runtime_dir("$XDG_RUNTIME_DIR/docker.sock", None).is_err());
+        assert!(rootless_socket(None).is_err());
+        assert!(rootless_socket(Some("/nonexistent/butido")).is_err());
+    }
+
+    #[test]
+    fn test_parse_ssh_docker_uris() {
+        assert_eq!(DockerUri::from_str("ssh://builder@remotehost").unwrap(), DockerUri::Ssh {
+            destination: "builder@remotehost".to_string(),
+            port: None,
+            socket: DEFAULT_REMOTE_DOCKER_SOCKET.to_string(),
+        });
+        assert_eq!(DockerUri::from_str("ssh://remotehost:2222/run/user/1000/docker.sock").unwrap(), DockerUri::Ssh {
+            destination: "remotehost".to_string(),
+            port: Some(2222),
+            socket: "/run/user/1000/docker.sock".to_string(),
+        });
+    }
+}
@@ -17,5 +17,10 @@ pub use scheduler::*;
 mod configured;
 pub use configured::*;
 
+mod connection;
+pub use connection::*;
+
+pub mod sandbox;
+
 pub mod util;
 
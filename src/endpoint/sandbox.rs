@@ -0,0 +1,507 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Running builds on hosts without docker, over SSH or on the local host
+//!
+//! The inputs of a job are collected in a local directory with the same layout as in a container
+//! and copied to the host (with rsync over SSH). The script is run in a sandbox (bubblewrap or
+//! chroot) with the root filesystem of the image, and the outputs are copied back.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use futures::Stream;
+use log::trace;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::config::SandboxTool;
+use crate::config::SandboxSettings;
+use crate::package::Network;
+use crate::util::docker::ImageName;
+
+/// The exit code of ssh if the connection failed, as opposed to the exit code of the command
+const SSH_CONNECTION_ERROR: i32 = 255;
+
+/// A host that runs builds in a sandbox
+#[derive(Clone, Debug)]
+pub struct SandboxHost {
+    transport: Transport,
+    settings: SandboxSettings,
+    timeout: Duration,
+}
+
+/// How the commands are run on a sandbox host
+#[derive(Clone, Debug)]
+enum Transport {
+    /// Over SSH, to the destination `[user@]host`
+    Ssh(String),
+
+    /// On the host butido runs on
+    Local,
+}
+
+/// Everything that is needed to run the script of a job on a sandbox host
+#[derive(Clone)]
+pub struct SandboxJob {
+    pub uuid: Uuid,
+    pub image: ImageName,
+    pub network: Network,
+
+    /// The command line that runs the script in the sandbox
+    pub command: Vec<String>,
+
+    /// The environment of the script, including secrets
+    pub env: Vec<(String, String)>,
+
+    /// The cache volumes, as (name of the cache directory, path in the sandbox)
+    pub caches: Vec<(String, String)>,
+
+    /// Files that are bind-mounted read-only into the sandbox, as (path on the host, path in the
+    /// sandbox)
+    ///
+    /// On local endpoints, the artifacts are mounted from the stores instead of being copied.
+    pub binds: Vec<(PathBuf, PathBuf)>,
+}
+
+impl std::fmt::Debug for SandboxJob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The environment is not shown, because it contains secrets
+        write!(f, "SandboxJob({}, {}, {:?})", self.uuid, self.image, self.network)
+    }
+}
+
+impl SandboxHost {
+    pub fn ssh(destination: String, settings: SandboxSettings, timeout: Duration) -> Self {
+        SandboxHost { transport: Transport::Ssh(destination), settings, timeout }
+    }
+
+    pub fn local(settings: SandboxSettings, timeout: Duration) -> Self {
+        SandboxHost { transport: Transport::Local, settings, timeout }
+    }
+
+    /// Whether the builds run on the host butido runs on
+    pub fn is_local(&self) -> bool {
+        matches!(self.transport, Transport::Local)
+    }
+
+    fn name(&self) -> &str {
+        match &self.transport {
+            Transport::Ssh(destination) => destination,
+            Transport::Local => "localhost",
+        }
+    }
+
+    fn ssh_args(&self) -> Vec<String> {
+        vec![
+            String::from("-o"),
+            String::from("BatchMode=yes"),
+            String::from("-o"),
+            format!("ConnectTimeout={}", self.timeout.as_secs()),
+        ]
+    }
+
+    fn command(&self, command: &str) -> tokio::process::Command {
+        let mut cmd = match &self.transport {
+            Transport::Ssh(destination) => {
+                let mut cmd = tokio::process::Command::new("ssh");
+                cmd.args(self.ssh_args()).arg(destination).arg(command);
+                cmd
+            },
+            Transport::Local => {
+                let mut cmd = tokio::process::Command::new("/bin/sh");
+                cmd.arg("-c").arg(command);
+                cmd
+            },
+        };
+        cmd.kill_on_drop(true);
+        cmd
+    }
+
+    /// Run `command` in a shell on the host and return what it printed on stdout
+    pub async fn run(&self, command: &str) -> Result<String> {
+        trace!("Running on {}: {}", self.name(), command);
+        let output = self.command(command)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .with_context(|| anyhow!("Running command on {}", self.name()))?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        } else {
+            Err(anyhow!("Command '{}' failed on {}: {}",
+                command,
+                self.name(),
+                String::from_utf8_lossy(&output.stderr).trim()))
+        }
+    }
+
+    /// Copy the contents of the local directory `from` to the directory `to` on the host
+    async fn copy_to_host(&self, from: &Path, to: &Path) -> Result<()> {
+        match &self.transport {
+            Transport::Ssh(destination) => {
+                self.rsync(&format!("{}/", from.display()), &format!("{}:{}/", destination, to.display())).await
+            },
+            Transport::Local => Self::copy_local(from, to).await,
+        }
+    }
+
+    /// Copy the contents of the directory `from` on the host to the local directory `to`
+    async fn copy_from_host(&self, from: &Path, to: &Path) -> Result<()> {
+        match &self.transport {
+            Transport::Ssh(destination) => {
+                self.rsync(&format!("{}:{}/", destination, from.display()), &format!("{}/", to.display())).await
+            },
+            Transport::Local => Self::copy_local(from, to).await,
+        }
+    }
+
+    async fn copy_local(from: &Path, to: &Path) -> Result<()> {
+        let output = tokio::process::Command::new("cp")
+            .arg("-a")
+            .arg("--")
+            .arg(from.join("."))
+            .arg(to)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .context("Running cp")?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("Copying {} to {} failed: {}", from.display(), to.display(), String::from_utf8_lossy(&output.stderr).trim()))
+        }
+    }
+
+    /// Copy `from` to `to` with rsync, one of them being `<destination>:<path>`
+    async fn rsync(&self, from: &str, to: &str) -> Result<()> {
+        trace!("rsync {} -> {}", from, to);
+        let output = tokio::process::Command::new("rsync")
+            .arg("--archive")
+            .arg("--protect-args")
+            .arg("--rsh")
+            .arg(std::iter::once(String::from("ssh")).chain(self.ssh_args()).collect::<Vec<_>>().join(" "))
+            .arg(from)
+            .arg(to)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .context("Running rsync")?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("rsync from {} to {} failed: {}", from, to, String::from_utf8_lossy(&output.stderr).trim()))
+        }
+    }
+
+    pub async fn ping(&self) -> Result<String> {
+        self.run("echo OK").await.map(|s| s.trim().to_string())
+    }
+
+    /// The total memory of the host in bytes
+    pub async fn mem_total(&self) -> Result<u64> {
+        let kib = self.run("awk '/^MemTotal:/ { print $2 }' /proc/meminfo").await?;
+        kib.trim()
+            .parse::<u64>()
+            .map(|kib| kib * 1024)
+            .with_context(|| anyhow!("Parsing total memory of {}: '{}'", self.name(), kib.trim()))
+    }
+
+    pub async fn check_images_available(&self, imgs: &[ImageName]) -> Result<()> {
+        for img in imgs {
+            let root = shell_quote(&self.image_root(img).display().to_string());
+            self.run(&format!("test -d {}", root))
+                .await
+                .with_context(|| anyhow!("Image '{}' missing from endpoint '{}'", img, self.name()))?;
+        }
+        Ok(())
+    }
+
+    fn image_root(&self, image: &ImageName) -> PathBuf {
+        self.settings.images_dir().join(image.as_ref())
+    }
+
+    fn job_dir(&self, uuid: &Uuid) -> PathBuf {
+        self.settings.work_dir().join("jobs").join(uuid.to_string())
+    }
+
+    fn cache_dir(&self, name: &str) -> PathBuf {
+        self.settings.work_dir().join("caches").join(name)
+    }
+
+    /// The directory on the host that contains `/inputs`, `/outputs`, `/patches` and `/script`
+    fn sandbox_root(&self, uuid: &Uuid) -> PathBuf {
+        match self.settings.tool() {
+            SandboxTool::Bwrap => self.job_dir(uuid),
+            SandboxTool::Chroot => self.job_dir(uuid).join("rootfs"),
+        }
+    }
+
+    /// Copy the inputs of the job from `local_dir` to the host
+    pub async fn prepare(&self, job: &SandboxJob, local_dir: &Path) -> Result<()> {
+        let job_dir = shell_quote(&self.job_dir(&job.uuid).display().to_string());
+        let command = match self.settings.tool() {
+            SandboxTool::Bwrap => format!("mkdir -p {}", job_dir),
+            SandboxTool::Chroot => format!("mkdir -p {dir} && cp -a --reflink=auto {root} {dir}/rootfs",
+                dir = job_dir,
+                root = shell_quote(&self.image_root(&job.image).display().to_string())),
+        };
+        self.run(&command).await?;
+
+        self.copy_to_host(local_dir, &self.sandbox_root(&job.uuid))
+            .await
+            .with_context(|| anyhow!("Copying inputs of job {} to {}", job.uuid, self.name()))
+    }
+
+    /// The shell command that runs the script of `job` in the sandbox
+    fn sandbox_command(&self, job: &SandboxJob) -> String {
+        let q = |p: &Path| shell_quote(&p.display().to_string());
+        let root = self.sandbox_root(&job.uuid);
+        let command = job.command.iter().map(|arg| shell_quote(arg)).collect::<Vec<_>>().join(" ");
+        let inner = format!("/bin/bash -c {}", shell_quote(&format!(". /dev/stdin && exec {}", command)));
+        let mkdir_caches = job.caches
+            .iter()
+            .map(|(name, _)| format!("mkdir -p {} && ", q(&self.cache_dir(name))))
+            .collect::<String>();
+
+        match self.settings.tool() {
+            SandboxTool::Bwrap => {
+                let mut args = vec![
+                    String::from("bwrap --die-with-parent --unshare-pid"),
+                    format!("--overlay-src {} --tmp-overlay /", q(&self.image_root(&job.image))),
+                    String::from("--dev /dev --proc /proc --tmpfs /tmp"),
+                ];
+                if job.network == Network::None {
+                    args.push(String::from("--unshare-net"));
+                }
+                for dir in ["inputs", "outputs"].iter() {
+                    args.push(format!("--bind {} /{}", q(&root.join(dir)), dir));
+                }
+                args.push(format!("--ro-bind {} {}", q(&root.join("patches")), crate::consts::PATCH_DIR_PATH));
+                args.push(format!("--ro-bind {} {}", q(&root.join("script")), crate::consts::SCRIPT_PATH));
+                for (name, path) in job.caches.iter() {
+                    args.push(format!("--bind {} {}", q(&self.cache_dir(name)), shell_quote(path)));
+                }
+                for (source, target) in job.binds.iter() {
+                    args.push(format!("--ro-bind {} {}", q(source), q(target)));
+                }
+                args.push(format!("--clearenv {}", inner));
+
+                format!("{}exec {} 2>&1", mkdir_caches, args.join(" "))
+            },
+
+            SandboxTool::Chroot => {
+                let r = q(&root);
+                let mounts = job.caches
+                    .iter()
+                    .map(|(name, path)| {
+                        let target = q(&root.join(path.trim_start_matches('/')));
+                        format!("mkdir -p {t} && mount --bind {c} {t} && ", t = target, c = q(&self.cache_dir(name)))
+                    })
+                    .chain(job.binds.iter().map(|(source, target)| {
+                        // The targets exist already, they are created when the inputs are collected
+                        format!("mount --bind -o ro {} {} && ", q(source), q(&root.join(target.strip_prefix("/").unwrap_or(target))))
+                    }))
+                    .collect::<String>();
+                let script = format!("mount --rbind /dev {r}/dev && mount -t proc proc {r}/proc && {mounts}exec chroot {r} /usr/bin/env -i {inner}",
+                    r = r,
+                    mounts = mounts,
+                    inner = inner);
+                let unshare_net = if job.network == Network::None { " --net" } else { "" };
+
+                format!("{}exec unshare --mount{} --fork /bin/sh -c {} 2>&1", mkdir_caches, unshare_net, shell_quote(&script))
+            },
+        }
+    }
+
+    /// Run the script of `job`, returning the lines it prints
+    ///
+    /// The environment is passed on stdin, so secrets do not show up in the process list of the
+    /// host. If the connection to the host fails, the stream ends with an error.
+    pub async fn execute(&self, job: &SandboxJob) -> Result<impl Stream<Item = Result<String>>> {
+        let command = self.sandbox_command(job);
+        let env = export_script(&job.env).with_context(|| anyhow!("Passing environment to job {}", job.uuid))?;
+        trace!("Running job {} on {}: {}", job.uuid, self.name(), command);
+
+        let mut child = self.command(&command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| anyhow!("Running job {} on {}", job.uuid, self.name()))?;
+
+        {
+            let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("BUG: stdin of sandbox not captured"))?;
+            stdin.write_all(env.as_bytes()).await.context("Passing environment to job")?;
+            // stdin is closed when dropped, so the sandbox sees the end of the environment
+        }
+
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("BUG: stdout of sandbox not captured"))?;
+        let lines = tokio::io::BufReader::new(stdout).lines();
+        let destination = self.name().to_string();
+        let is_ssh = !self.is_local();
+
+        Ok(futures::stream::unfold(Some((lines, child)), move |state| {
+            let destination = destination.clone();
+            async move {
+                let (mut lines, mut child) = state?;
+                match lines.next_line().await {
+                    Ok(Some(line)) => Some((Ok(line), Some((lines, child)))),
+                    Ok(None) => {
+                        let mut stderr = String::new();
+                        if let Some(mut s) = child.stderr.take() {
+                            let _ = s.read_to_string(&mut stderr).await;
+                        }
+
+                        match child.wait().await {
+                            Ok(status) if is_ssh && status.code() == Some(SSH_CONNECTION_ERROR) => {
+                                Some((Err(anyhow!("Connection to {} failed: {}", destination, stderr.trim())), None))
+                            },
+                            Ok(_) => None,
+                            Err(e) => Some((Err(Error::from(e)), None)),
+                        }
+                    },
+                    Err(e) => Some((Err(Error::from(e)), None)),
+                }
+            }
+        }))
+    }
+
+    /// Copy the outputs of the job to `<local_dir>/outputs`
+    pub async fn fetch_outputs(&self, uuid: &Uuid, local_dir: &Path) -> Result<PathBuf> {
+        let outputs = local_dir.join(crate::consts::OUTPUTS_DIR_NAME);
+        tokio::fs::create_dir_all(&outputs)
+            .await
+            .with_context(|| anyhow!("Creating directory {}", outputs.display()))?;
+        self.copy_from_host(&self.sandbox_root(uuid).join(crate::consts::OUTPUTS_DIR_NAME), &outputs)
+            .await
+            .with_context(|| anyhow!("Copying outputs of job {} from {}", uuid, self.name()))?;
+        Ok(outputs)
+    }
+
+    /// Remove the directory of the job on the host
+    pub async fn cleanup(&self, uuid: &Uuid) -> Result<()> {
+        self.run(&format!("rm -rf {}", shell_quote(&self.job_dir(uuid).display().to_string())))
+            .await
+            .map(|_| ())
+    }
+}
+
+/// The shell script that exports the variables of `env`
+///
+/// The names are not quoted, so names that are not valid shell variable names are rejected.
+fn export_script(env: &[(String, String)]) -> Result<String> {
+    env.iter()
+        .map(|(k, v)| {
+            if is_shell_variable_name(k) {
+                Ok(format!("export {}={}\n", k, shell_quote(v)))
+            } else {
+                Err(anyhow!("Invalid environment variable name: '{}'", k))
+            }
+        })
+        .collect()
+}
+
+/// Whether `name` matches `[A-Za-z_][A-Za-z0-9_]*`
+fn is_shell_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Quote `s` for a POSIX shell
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r#"'\''"#))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("foo"), "'foo'");
+        assert_eq!(shell_quote("it's"), r#"'it'\''s'"#);
+        assert_eq!(shell_quote("$HOME; rm -rf /"), "'$HOME; rm -rf /'");
+    }
+
+    #[test]
+    fn test_export_script() {
+        let env = |k: &str| vec![(String::from(k), String::from("it's"))];
+        assert_eq!(export_script(&env("CFLAGS")).unwrap(), "export CFLAGS='it'\\''s'\n");
+        assert_eq!(export_script(&env("_a1")).unwrap(), "export _a1='it'\\''s'\n");
+        assert!(export_script(&env("")).is_err());
+        assert!(export_script(&env("1A")).is_err());
+        assert!(export_script(&env("A-B")).is_err());
+        assert!(export_script(&env("A=$(reboot)")).is_err());
+        assert!(export_script(&env("A; reboot; B")).is_err());
+    }
+
+    #[test]
+    fn test_sandbox_command() {
+        let settings: SandboxSettings = toml::from_str(r#"
+            images_dir = "/srv/images"
+            work_dir = "/srv/work"
+        "#).unwrap();
+        let host = SandboxHost::ssh(String::from("builder@host"), settings, Duration::from_secs(10));
+        let job = SandboxJob {
+            uuid: Uuid::nil(),
+            image: ImageName::from(String::from("debian:bullseye")),
+            network: Network::None,
+            command: vec![String::from("/usr/bin/env"), String::from("python3"), String::from("/script")],
+            env: vec![(String::from("SECRET"), String::from("hunter2"))],
+            caches: vec![(String::from("butido-cache-ccache"), String::from("/ccache"))],
+            binds: vec![],
+        };
+
+        let command = host.sandbox_command(&job);
+        assert!(command.starts_with("mkdir -p '/srv/work/caches/butido-cache-ccache' && exec bwrap "));
+        assert!(command.contains("--overlay-src '/srv/images/debian:bullseye' --tmp-overlay /"));
+        assert!(command.contains("--unshare-net"));
+        assert!(command.contains("--bind '/srv/work/jobs/00000000-0000-0000-0000-000000000000/outputs' /outputs"));
+        assert!(command.contains("--bind '/srv/work/caches/butido-cache-ccache' '/ccache'"));
+        assert!(command.contains(&format!("--clearenv /bin/bash -c {}", shell_quote(". /dev/stdin && exec '/usr/bin/env' 'python3' '/script'"))));
+        assert!(!command.contains("hunter2"));
+    }
+
+    #[test]
+    fn test_sandbox_command_local_binds() {
+        let settings: SandboxSettings = toml::from_str(r#"
+            images_dir = "/srv/images"
+            work_dir = "/srv/work"
+            tool = "chroot"
+        "#).unwrap();
+        let host = SandboxHost::local(settings, Duration::from_secs(10));
+        let job = SandboxJob {
+            uuid: Uuid::nil(),
+            image: ImageName::from(String::from("debian:bullseye")),
+            network: Network::Default,
+            command: vec![String::from("/bin/bash"), String::from("/script")],
+            env: vec![],
+            caches: vec![],
+            binds: vec![(PathBuf::from("/staging/foo-1.0.tar"), PathBuf::from("/inputs/foo-1.0.tar"))],
+        };
+
+        let command = host.sandbox_command(&job);
+        assert!(command.starts_with("exec unshare --mount --fork /bin/sh -c "));
+        assert!(command.contains("mount --bind -o ro '\\''/staging/foo-1.0.tar'\\'' '\\''/srv/work/jobs/00000000-0000-0000-0000-000000000000/rootfs/inputs/foo-1.0.tar'\\''"));
+    }
+}
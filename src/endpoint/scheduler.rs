@@ -8,6 +8,8 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::HashMap;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -16,109 +18,576 @@ use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
 use colored::Colorize;
+use log::info;
 use diesel::PgConnection;
 use indicatif::ProgressBar;
 use itertools::Itertools;
+use log::debug;
 use log::trace;
+use log::warn;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::RwLock;
 use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+use crate::config::DockerConfig;
+use crate::db::DbPool;
 use crate::db::models as dbmodels;
 use crate::endpoint::Endpoint;
 use crate::endpoint::EndpointHandle;
 use crate::endpoint::EndpointConfiguration;
+use crate::endpoint::Reservation;
+use crate::endpoint::millicpus;
+use crate::filestore::ArtifactMetadata;
 use crate::filestore::ArtifactPath;
+use crate::filestore::ObjectStore;
+use crate::filestore::PhaseTiming;
+use crate::filestore::SourceMaterial;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
 use crate::job::JobResource;
 use crate::job::RunnableJob;
+use crate::log::LiveLog;
+use crate::log::LiveLogEvent;
+use crate::log::LogIndex;
 use crate::log::LogItem;
+use crate::package::EndpointAffinity;
+use crate::util::notifications::NotificationEvent;
+use crate::util::progress::StatusEvent;
+use crate::util::progress::StatusPrinter;
+use crate::util::EnvironmentVariableName;
+use crate::util::docker::ImageName;
+
+/// How long to wait for the rest of the log of a job after its container was removed
+const CANCELED_LOG_TIMEOUT_SECS: u64 = 10;
+
+/// The endpoints jobs are scheduled on and the queue of the jobs that wait for a free endpoint
+///
+/// The pool is shared by the schedulers of submits that run at the same time, so that their jobs
+/// share the job slots of the endpoints.
+pub struct EndpointPool {
+    endpoints: Vec<Arc<Endpoint>>,
+    queue: std::sync::Mutex<WaitQueue>,
+
+    /// Notified when a job slot on an endpoint is released, an endpoint becomes healthy again or
+    /// the first job in the queue changes
+    changed: Arc<tokio::sync::Notify>,
+
+    /// The task that checks the health of the endpoints, aborted when the pool is dropped
+    health_check: tokio::task::JoinHandle<()>,
+}
+
+impl EndpointPool {
+    pub async fn setup(endpoints: Vec<EndpointConfiguration>, docker_config: &DockerConfig) -> Result<Arc<Self>> {
+        let endpoints = crate::endpoint::util::setup_endpoints(endpoints).await?;
+        let changed = Arc::new(tokio::sync::Notify::new());
+        let health_check_interval = std::time::Duration::from_secs(docker_config.health_check_interval());
+        let health_check = tokio::spawn(EndpointScheduler::check_health(endpoints.clone(), changed.clone(), health_check_interval));
+
+        Ok(Arc::new(EndpointPool {
+            endpoints,
+            queue: std::sync::Mutex::new(WaitQueue::default()),
+            changed,
+            health_check,
+        }))
+    }
+}
+
+impl Drop for EndpointPool {
+    fn drop(&mut self) {
+        self.health_check.abort();
+    }
+}
 
 pub struct EndpointScheduler {
     log_dir: Option<PathBuf>,
-    endpoints: Vec<Arc<Endpoint>>,
+
+    /// Writes the index of the log files in `log_dir`
+    log_index: Arc<LogIndex>,
+
+    /// The endpoints, shared with the schedulers of other submits that run at the same time
+    pool: Arc<EndpointPool>,
 
     staging_store: Arc<RwLock<StagingStore>>,
+    additional_staging_stores: Vec<Arc<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
-    db: Arc<PgConnection>,
+    db: DbPool,
     submit: crate::db::models::Submit,
+
+    /// How often a job is rescheduled because its endpoint became unreachable
+    max_reschedules: usize,
+
+    /// Where the log items of the running jobs are streamed to, if at all
+    live_log: Option<LiveLog>,
+
+    /// The commands that are notified when jobs start and finish
+    notification_commands: Vec<Vec<String>>,
+
+    /// Where the status events of the jobs are printed to, if they are printed
+    status: Option<StatusPrinter>,
+
+    /// Cancelled when the submit is canceled, no jobs are scheduled after that and the containers
+    /// of the running jobs are removed
+    canceled: CancellationToken,
+
+    /// How many lines of the logs are stored in the database if the logs are offloaded to the
+    /// log files
+    offloaded_log_lines: Option<usize>,
+
+    /// The maximum size of the logs of the jobs, in bytes
+    max_log_size: Option<u64>,
+
+    /// How long the jobs are expected to run, from the durations of earlier jobs
+    expected_durations: HashMap<Uuid, f64>,
 }
 
 impl EndpointScheduler {
+    #[allow(clippy::too_many_arguments)]
     pub async fn setup(
         endpoints: Vec<EndpointConfiguration>,
         staging_store: Arc<RwLock<StagingStore>>,
+        additional_staging_stores: Vec<Arc<StagingStore>>,
         release_stores: Vec<Arc<ReleaseStore>>,
-        db: Arc<PgConnection>,
+        db: DbPool,
         submit: crate::db::models::Submit,
         log_dir: Option<PathBuf>,
+        docker_config: &DockerConfig,
     ) -> Result<Self> {
-        let endpoints = crate::endpoint::util::setup_endpoints(endpoints).await?;
+        let pool = EndpointPool::setup(endpoints, docker_config).await.map_err(crate::error::Error::Endpoint)?;
+        Ok(Self::with_pool(pool, staging_store, additional_staging_stores, release_stores, db, submit, log_dir, docker_config))
+    }
 
-        Ok(EndpointScheduler {
+    /// A scheduler that schedules the jobs of `submit` on the endpoints of `pool`
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_pool(
+        pool: Arc<EndpointPool>,
+        staging_store: Arc<RwLock<StagingStore>>,
+        additional_staging_stores: Vec<Arc<StagingStore>>,
+        release_stores: Vec<Arc<ReleaseStore>>,
+        db: DbPool,
+        submit: crate::db::models::Submit,
+        log_dir: Option<PathBuf>,
+        docker_config: &DockerConfig,
+    ) -> Self {
+        EndpointScheduler {
             log_dir,
-            endpoints,
+            log_index: Arc::new(LogIndex::default()),
+            pool,
             staging_store,
+            additional_staging_stores,
             release_stores,
             db,
             submit,
-        })
+            max_reschedules: docker_config.max_reschedules(),
+            live_log: None,
+            notification_commands: vec![],
+            status: None,
+            canceled: CancellationToken::new(),
+            offloaded_log_lines: None,
+            max_log_size: None,
+            expected_durations: HashMap::new(),
+        }
+    }
+
+    /// The endpoints of the scheduler, to schedule the jobs of other submits on them as well
+    pub fn pool(&self) -> &Arc<EndpointPool> {
+        &self.pool
+    }
+
+    /// Schedule and run a job
+    ///
+    /// If the job fails because its endpoint became unreachable while the job was running, the
+    /// job is rescheduled onto another endpoint, up to `docker.max_reschedules` times.
+    pub async fn run_job(&self, job: RunnableJob, bar: indicatif::ProgressBar, priority: usize) -> Result<Result<Vec<ArtifactPath>>> {
+        let result = self.run_job_with_reschedules(&job, bar, priority).await;
+
+        let error = match result.as_ref() {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) | Err(e) => Some(format!("{:#}", e)),
+        };
+        let event = NotificationEvent::JobFinished {
+            submit: self.submit.uuid,
+            job: *job.uuid(),
+            package_name: job.package().name().as_ref(),
+            package_version: job.package().version().as_ref(),
+            success: error.is_none(),
+            error,
+            maintainers: job.package().maintainers(),
+        };
+        crate::util::notifications::notify(&self.notification_commands, &event).await;
+
+        if let (Some(status), NotificationEvent::JobFinished { job, package_name, package_version, success, error, .. }) = (self.status, event) {
+            status.print(&StatusEvent::JobFinished { job, package_name, package_version, success, error });
+        }
+        result
+    }
+
+    async fn run_job_with_reschedules(&self, job: &RunnableJob, bar: indicatif::ProgressBar, priority: usize) -> Result<Result<Vec<ArtifactPath>>> {
+        let mut endpoint_failures: Vec<String> = Vec::new();
+
+        loop {
+            let handle = tokio::select! {
+                biased;
+                _ = self.canceled.cancelled() => return Ok(Err(anyhow!("Submit {} was canceled", self.submit.uuid))),
+                handle = self.schedule_job(job.clone(), bar.clone(), priority) => handle?,
+            };
+            let endpoint = handle.endpoint.endpoint();
+
+            // Recorded for "db running", so failing to record it does not fail the job
+            let (job_uuid, endpoint_name) = (*job.uuid(), endpoint.name().to_string());
+            let recorded = crate::db::with_connection(&self.db, move |conn| {
+                dbmodels::RunningJob::set_endpoint(conn, &job_uuid, &endpoint_name)
+            });
+            if let Err(e) = recorded.await {
+                warn!("Recording the endpoint of job {} failed: {:#}", job.uuid(), e);
+            }
+
+            let event = NotificationEvent::JobStarted {
+                submit: self.submit.uuid,
+                job: *job.uuid(),
+                package_name: job.package().name().as_ref(),
+                package_version: job.package().version().as_ref(),
+                endpoint: endpoint.name().as_ref(),
+            };
+            crate::util::notifications::notify(&self.notification_commands, &event).await;
+            if let Some(status) = self.status.as_ref() {
+                status.print(&StatusEvent::JobStarted {
+                    job: *job.uuid(),
+                    package_name: job.package().name().as_ref(),
+                    package_version: job.package().version().as_ref(),
+                    endpoint: endpoint.name().as_ref(),
+                });
+            }
+
+            let result = handle.run().await;
+
+            let error = match result.as_ref() {
+                Ok(Ok(_)) => None,
+                Ok(Err(e)) | Err(e) => Some(e),
+            };
+
+            if let Some(error) = error.filter(|_| !self.canceled.is_cancelled()) {
+                if endpoint.check_health().await.is_err() {
+                    let cause = format!("Endpoint {} became unreachable: {:?}", endpoint.name(), error);
+                    warn!("Job {} failed: {}", job.uuid(), cause);
+                    endpoint_failures.push(cause);
+
+                    if endpoint_failures.len() <= self.max_reschedules {
+                        bar.reset();
+                        bar.set_message(format!("[{} {} {}]: Endpoint {} unreachable, rescheduling ({}/{})...",
+                            job.uuid(),
+                            job.package().name(),
+                            job.package().version(),
+                            endpoint.name(),
+                            endpoint_failures.len(),
+                            self.max_reschedules));
+                        continue
+                    }
+                }
+            }
+
+            if endpoint_failures.is_empty() {
+                return result
+            }
+
+            let context = format!("Job {} was rescheduled {} times: {}",
+                job.uuid(),
+                endpoint_failures.len().min(self.max_reschedules),
+                endpoint_failures.join("; "));
+            return match result {
+                Ok(Ok(artifacts)) => Ok(Ok(artifacts)),
+                Ok(Err(e)) => Ok(Err(e.context(context))),
+                Err(e) => Err(e.context(context)),
+            }
+        }
+    }
+
+    /// Ping all endpoints every `interval`, so jobs are only scheduled on endpoints that answer
+    async fn check_health(endpoints: Vec<Arc<Endpoint>>, changed: Arc<tokio::sync::Notify>, interval: std::time::Duration) {
+        use futures::stream::StreamExt;
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let recovered = endpoints
+                .iter()
+                .map(|ep| async move {
+                    let was_healthy = ep.is_healthy();
+                    match ep.check_health().await {
+                        Ok(()) if !was_healthy => {
+                            info!("Endpoint {} is reachable again, scheduling jobs on it", ep.name());
+                            true
+                        },
+                        Err(e) if was_healthy => {
+                            warn!("Endpoint {} is unhealthy, not scheduling jobs on it: {:?}", ep.name(), e);
+                            false
+                        },
+                        _ => false,
+                    }
+                })
+                .collect::<futures::stream::FuturesUnordered<_>>()
+                .collect::<Vec<bool>>()
+                .await
+                .into_iter()
+                .any(|b| b);
+
+            if recovered {
+                changed.notify_waiters();
+            }
+        }
+    }
+
+    /// Stream the log items of the running jobs to `live_log`
+    pub fn with_live_log(mut self, live_log: Option<LiveLog>) -> Self {
+        self.live_log = live_log;
+        self
+    }
+
+    /// Notify the `commands` when jobs start and finish
+    pub fn with_notification_commands(mut self, commands: Vec<Vec<String>>) -> Self {
+        self.notification_commands = commands;
+        self
+    }
+
+    /// Print the status events of the jobs with `status`
+    pub fn with_status(mut self, status: Option<StatusPrinter>) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Write the complete logs of the jobs to `log_dir` and only store a reference to the log
+    /// file and the last `tail_lines` lines of the log in the database
+    pub fn with_offloaded_logs(mut self, log_dir: PathBuf, tail_lines: usize) -> Self {
+        self.log_dir = Some(log_dir);
+        self.offloaded_log_lines = Some(tail_lines);
+        self
+    }
+
+    /// Drop the lines in the middle of the logs of the jobs that are bigger than `max_log_size`
+    /// bytes
+    pub fn with_max_log_size(mut self, max_log_size: Option<u64>) -> Self {
+        self.max_log_size = max_log_size;
+        self
+    }
+
+    /// Show the remaining time of the running jobs on their progress bars, based on how long the
+    /// jobs are expected to run (in seconds)
+    pub fn with_expected_durations(mut self, expected_durations: HashMap<Uuid, f64>) -> Self {
+        self.expected_durations = expected_durations;
+        self
+    }
+
+    /// The submit the jobs are scheduled for
+    pub fn submit(&self) -> &crate::db::models::Submit {
+        &self.submit
+    }
+
+    /// Cancel the submit: jobs that wait for an endpoint fail and running jobs are killed, their
+    /// containers are removed
+    pub fn cancel(&self) {
+        self.canceled.cancel();
+    }
+
+    /// Whether the submit was canceled
+    pub fn is_canceled(&self) -> bool {
+        self.canceled.is_cancelled()
+    }
+
+    /// Pull the `images` on all endpoints where they are missing, in parallel
+    ///
+    /// `mk_bar` creates the progress bar for pulling one image on one endpoint.
+    pub async fn pull_missing_images<F>(&self, images: &[ImageName], mk_bar: F) -> Result<()>
+        where F: Fn() -> Result<ProgressBar>
+    {
+        use futures::StreamExt;
+
+        self.pool
+            .endpoints
+            .iter()
+            .flat_map(|ep| images.iter().map(move |image| (ep, image)))
+            .map(|(ep, image)| {
+                let bar = mk_bar();
+                async move { ep.pull_image_if_missing(image, &bar?).await }
+            })
+            .collect::<futures::stream::FuturesUnordered<_>>()
+            .collect::<Vec<Result<()>>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Get the ID of `image` from the first healthy docker endpoint that has it
+    ///
+    /// Returns `None` if no endpoint could tell.
+    pub async fn image_digest(&self, image: &ImageName) -> Option<String> {
+        for endpoint in self.pool.endpoints.iter().filter(|ep| ep.is_docker() && ep.is_healthy()) {
+            match endpoint.image_id(image).await {
+                Ok(id) => return Some(id),
+                Err(e) => debug!("{:?}", e),
+            }
+        }
+        None
     }
 
     /// Schedule a Job
     ///
+    /// If several jobs wait for a free endpoint, the one with the highest `priority` gets the next
+    /// free endpoint.
+    ///
     /// # Warning
     ///
     /// This function blocks as long as there is no free endpoint available!
-    pub async fn schedule_job(&self, job: RunnableJob, bar: indicatif::ProgressBar) -> Result<JobHandle> {
-        let endpoint = self.select_free_endpoint().await?;
+    pub async fn schedule_job(&self, job: RunnableJob, bar: indicatif::ProgressBar, priority: usize) -> Result<JobHandle> {
+        let requirements = job.package().resources().clone().unwrap_or_default();
+        let reservation = Reservation {
+            memory: requirements.memory().or_else(|| job.limits().memory()).map(|m| m.bytes()).unwrap_or(0),
+            millicpus: requirements.cpus().or_else(|| job.limits().cpus()).map(millicpus).unwrap_or(0),
+        };
+        let ticket = QueueTicket::new(&self.pool.queue, &self.pool.changed, priority, self.submit.uuid);
+        let endpoint = self.select_free_endpoint(reservation, job.image(), job.package().endpoints().as_ref(), &ticket).await
+            .with_context(|| anyhow!("Scheduling job for {} {}", job.package().name(), job.package().version()))?;
 
+        let expected_secs = self.expected_durations.get(job.uuid()).copied();
         Ok(JobHandle {
             log_dir: self.log_dir.clone(),
+            log_index: self.log_index.clone(),
+            offloaded_log_lines: self.offloaded_log_lines,
+            max_log_size: self.max_log_size,
             bar,
             endpoint,
             job,
             staging_store: self.staging_store.clone(),
+            additional_staging_stores: self.additional_staging_stores.clone(),
             release_stores: self.release_stores.clone(),
             db: self.db.clone(),
             submit: self.submit.clone(),
+            live_log: self.live_log.clone(),
+            status: self.status,
+            canceled: self.canceled.clone(),
+            expected_secs,
         })
     }
 
-    async fn select_free_endpoint(&self) -> Result<EndpointHandle> {
+    /// Select an endpoint that has a free job slot, the resources of the `reservation` left and
+    /// the right content for `image`, and that is allowed by the `affinity` of the package
+    ///
+    /// Jobs that reserve resources go to the endpoint they fit best on, i.e. the one that has the
+    /// least left of its scarcest resource afterwards, so that the endpoints with more resources
+    /// left can take the bigger jobs.
+    ///
+    /// If there is none, this waits until a job slot is released instead of polling the endpoints.
+    async fn select_free_endpoint(
+        &self,
+        reservation: Reservation,
+        image: &ImageName,
+        affinity: Option<&EndpointAffinity>,
+        ticket: &QueueTicket<'_>,
+    ) -> Result<EndpointHandle> {
         use futures::stream::StreamExt;
 
+        if !self.pool.endpoints.iter().any(|ep| ep.can_ever_fit_memory(reservation.memory)) {
+            return Err(anyhow!("No endpoint has enough memory for a job that needs {} bytes", reservation.memory))
+        }
+
+        if !self.pool.endpoints.iter().any(|ep| ep.can_ever_fit_cpus(reservation.millicpus)) {
+            return Err(anyhow!("No endpoint has enough CPUs for a job that needs {} CPUs",
+                reservation.millicpus as f64 / 1000.0))
+        }
+
+        if !self.pool.endpoints.iter().any(|ep| ep.has_matching_image(image)) {
+            return Err(anyhow!("No endpoint has image {} with the digest it is pinned to", image.name()))
+        }
+
+        let allows = |ep: &Endpoint| affinity.map(|a| a.allows(ep.name().as_ref(), ep.labels())).unwrap_or(true);
+        let prefers = |ep: &Endpoint| affinity.map(|a| a.prefers(ep.name().as_ref(), ep.labels())).unwrap_or(false);
+        if let Some(affinity) = affinity.filter(|_| !self.pool.endpoints.iter().any(|ep| allows(ep))) {
+            if affinity.constraints().is_empty() {
+                return Err(anyhow!("No endpoint has one of the names or labels {} that the package requires",
+                    affinity.required().join(", ")))
+            }
+            return Err(anyhow!("No endpoint has one of the names or labels {} and satisfies the constraints {} that the package requires",
+                if affinity.required().is_empty() { String::from("(any)") } else { affinity.required().join(", ") },
+                affinity.constraints().iter().join(", ")))
+        }
+
         loop {
+            // Created before checking, so a slot that is released while checking is not missed
+            let changed = self.pool.changed.notified();
+
+            if !ticket.is_next() {
+                changed.await;
+                continue
+            }
+
             let ep = self
+                .pool
                 .endpoints
                 .iter()
                 .filter(|ep| { // filter out all running containers where the number of max jobs is reached
-                    let r = ep.running_jobs() < ep.num_max_jobs();
+                    let r = ep.is_healthy()
+                        && ep.running_jobs() < ep.num_max_jobs()
+                        && ep.has_free_resources(&reservation)
+                        && ep.has_matching_image(image)
+                        && allows(ep);
                     trace!("Endpoint {} considered for scheduling job: {}", ep.name(), r);
                     r
                 })
                 .map(|ep| {
                     let ep = ep.clone();
                     async {
-                        let num = ep.number_of_running_containers().await?;
-                        trace!("Number of running containers on {} = {}", ep.name(), num);
-                        Ok((ep, num))
+                        match ep.number_of_running_containers().await {
+                            Ok(num) => {
+                                trace!("Number of running containers on {} = {}", ep.name(), num);
+                                let rank = (prefers(&ep), ep.has_image_locally(image));
+                                Some((ep, rank, num))
+                            },
+
+                            // Fail over to the other endpoints until the health check finds
+                            // this one reachable again
+                            Err(e) => {
+                                warn!("Endpoint {} is unhealthy, not scheduling jobs on it: {:?}", ep.name(), e);
+                                ep.set_unhealthy();
+                                None
+                            },
+                        }
                     }
                 })
                 .collect::<futures::stream::FuturesUnordered<_>>()
                 .collect::<Vec<_>>()
-                .await // Vec<Result<_>>
-                .into_iter()
-                .collect::<Result<Vec<_>>>()? // -> Vec<_>
+                .await // Vec<Option<_>>
                 .into_iter()
-                .sorted_by(|(ep1, ep1_running), (ep2, ep2_running)| {
+                .flatten()
+                .sorted_by(|(ep1, (ep1_preferred, ep1_has_image), ep1_running), (ep2, (ep2_preferred, ep2_has_image), ep2_running)| {
+                    if ep1_preferred != ep2_preferred {
+                        trace!("Endpoint preferred by the package: {}: {}, {}: {}", ep1.name(), ep1_preferred, ep2.name(), ep2_preferred);
+                        return ep2_preferred.cmp(ep1_preferred)
+                    }
+
+                    // Endpoints that have the image already are preferred, so that the image is
+                    // not pulled on several endpoints during one submit
+                    if ep1_has_image != ep2_has_image {
+                        trace!("Image {} is present on {}: {}, on {}: {}", image, ep1.name(), ep1_has_image, ep2.name(), ep2_has_image);
+                        return ep2_has_image.cmp(ep1_has_image)
+                    }
+
+                    if !reservation.is_empty() {
+                        let ep1_free = ep1.free_capacity_after(&reservation);
+                        let ep2_free = ep2.free_capacity_after(&reservation);
+                        if let (Some(ep1_free), Some(ep2_free)) = (ep1_free, ep2_free) {
+                            trace!("Capacity left after scheduling on {}: {}, on {}: {}", ep1.name(), ep1_free, ep2.name(), ep2_free);
+                            match ep1_free.partial_cmp(&ep2_free) {
+                                Some(std::cmp::Ordering::Equal) | None => {},
+                                Some(ordering) => return ordering,
+                            }
+                        }
+                    }
+
                     match ep1_running.partial_cmp(ep2_running).unwrap_or(std::cmp::Ordering::Equal) {
                         std::cmp::Ordering::Equal =>  {
                             trace!("Number of running containers on {} and {} equal ({}), using utilization", ep1.name(), ep2.name(), ep2_running);
                             let ep1_util = ep1.utilization();
-                            let ep2_util = ep1.utilization();
+                            let ep2_util = ep2.utilization();
 
                             trace!("{} utilization: {}", ep1.name(), ep1_util);
                             trace!("{} utilization: {}", ep2.name(), ep2_util);
@@ -138,28 +607,103 @@ impl EndpointScheduler {
                     }
                 })
                 .next()
-                .map(|(ep, _)| ep);
+                .map(|(ep, _, _)| ep);
 
             if let Some(endpoint) = ep {
                 trace!("Selected = {}", endpoint.name());
-                return Ok(EndpointHandle::new(endpoint));
+                ticket.served();
+                return Ok(EndpointHandle::new(endpoint, reservation, self.pool.changed.clone()));
             } else {
-                trace!("No free endpoint found, retry...");
-                tokio::task::yield_now().await
+                trace!("No free endpoint found, waiting for a job to finish...");
+                changed.await
             }
         }
     }
 }
 
+/// The jobs that wait for a free endpoint
+///
+/// Jobs with a higher priority are served first, jobs with the same priority in the order they
+/// arrived. If the jobs of several submits wait, the submits are served in turn: the next job is
+/// the one with the highest priority of the submit that got the fewest endpoints so far.
+#[derive(Debug, Default)]
+struct WaitQueue {
+    next_ticket: u64,
+
+    /// The waiting jobs, with the submit they belong to
+    waiting: std::collections::BTreeMap<(std::cmp::Reverse<usize>, u64), Uuid>,
+
+    /// How many jobs of each submit got an endpoint
+    served: HashMap<Uuid, u64>,
+}
+
+impl WaitQueue {
+    fn next(&self) -> Option<&(std::cmp::Reverse<usize>, u64)> {
+        self.waiting
+            .iter()
+            .min_by_key(|(key, submit)| (self.served.get(*submit).copied().unwrap_or(0), **key))
+            .map(|(key, _)| key)
+    }
+}
+
+/// The place of a job in the `WaitQueue`, which is left when the ticket is dropped
+struct QueueTicket<'a> {
+    queue: &'a std::sync::Mutex<WaitQueue>,
+    changed: &'a tokio::sync::Notify,
+    key: (std::cmp::Reverse<usize>, u64),
+    submit: Uuid,
+}
+
+impl<'a> QueueTicket<'a> {
+    fn new(queue: &'a std::sync::Mutex<WaitQueue>, changed: &'a tokio::sync::Notify, priority: usize, submit: Uuid) -> Self {
+        let mut q = queue.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let key = (std::cmp::Reverse(priority), q.next_ticket);
+        q.next_ticket += 1;
+        q.waiting.insert(key, submit);
+        QueueTicket { queue, changed, key, submit }
+    }
+
+    /// Whether this is the waiting job that is served next
+    fn is_next(&self) -> bool {
+        let q = self.queue.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        q.next() == Some(&self.key)
+    }
+
+    /// Record that the job got an endpoint, so that the other submits are served first
+    fn served(&self) {
+        let mut q = self.queue.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        *q.served.entry(self.submit).or_insert(0) += 1;
+    }
+}
+
+impl<'a> Drop for QueueTicket<'a> {
+    fn drop(&mut self) {
+        let mut q = self.queue.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        q.waiting.remove(&self.key);
+        drop(q);
+
+        // The next job in the queue might get a free endpoint now
+        self.changed.notify_waiters();
+    }
+}
+
 pub struct JobHandle {
     log_dir: Option<PathBuf>,
+    log_index: Arc<LogIndex>,
+    offloaded_log_lines: Option<usize>,
+    max_log_size: Option<u64>,
     endpoint: EndpointHandle,
     job: RunnableJob,
     bar: ProgressBar,
-    db: Arc<PgConnection>,
+    db: DbPool,
     staging_store: Arc<RwLock<StagingStore>>,
+    additional_staging_stores: Vec<Arc<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     submit: crate::db::models::Submit,
+    live_log: Option<LiveLog>,
+    status: Option<StatusPrinter>,
+    canceled: CancellationToken,
+    expected_secs: Option<f64>,
 }
 
 impl std::fmt::Debug for JobHandle {
@@ -173,14 +717,70 @@ impl JobHandle {
         let (log_sender, log_receiver) = tokio::sync::mpsc::unbounded_channel::<LogItem>();
         let endpoint_uri = self.endpoint.uri().clone();
         let endpoint_name = self.endpoint.name().clone();
-        let endpoint = dbmodels::Endpoint::create_or_fetch(&self.db, self.endpoint.name())?;
-        let package = dbmodels::Package::create_or_fetch(&self.db, self.job.package())?;
-        let image = dbmodels::Image::create_or_fetch(&self.db, self.job.image())?;
-        let envs = self.create_env_in_db()?;
+        let (endpoint, package, image, envs, patches_hash, options) = {
+            let endpoint_name = self.endpoint.name().clone();
+            let job_package = self.job.package().clone();
+            let job_image = self.job.image().clone();
+            let job_env = self.job_env();
+            let patches_hash = self.job.package().patches_hash()?;
+            let options = self.job.package().options_string();
+
+            crate::db::with_connection(&self.db, move |conn| {
+                let endpoint = dbmodels::Endpoint::create_or_fetch(conn, &endpoint_name)?;
+                let package = dbmodels::Package::create_or_fetch(conn, &job_package)?;
+                let image = dbmodels::Image::create_or_fetch(conn, &job_image)?;
+                let envs = Self::create_env_in_db(conn, job_env)?;
+                Ok((endpoint, package, image, envs, patches_hash, options))
+            })
+            .await?
+        };
+        let db = self.db.clone();
         let job_id = *self.job.uuid();
+
+        // Only recorded in the database and the metadata files of the artifacts, so not being able
+        // to find out the image ID does not fail the job
+        let image_digest = if self.endpoint.is_docker() {
+            self.endpoint
+                .image_id(self.job.image())
+                .await
+                .map_err(|e| warn!("{:?}", e))
+                .ok()
+        } else {
+            None
+        };
+        let meta_env = self.job_env()
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect::<std::collections::BTreeMap<_, _>>();
+        let image_name = self.job.image().to_string();
+        let sources = self.job
+            .package()
+            .sources()
+            .values()
+            .map(|source| SourceMaterial {
+                url: source.url().to_string(),
+                hash_type: source.hash().hashtype().to_string(),
+                hash: source.hash().value().to_string(),
+            })
+            .collect::<Vec<_>>();
+        let started = chrono::Utc::now();
+        let secrets = self.job
+            .secrets()
+            .map(|(_, secret)| secret.expose().to_string())
+            .collect::<Vec<_>>();
+        let network = *self.job.network();
+        let hermetic = *self.job.hermetic();
+        let job_package = self.job.package().clone();
+        let endpoint_env = self.endpoint
+            .container_env(&self.job)
+            .into_iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>();
+        let endpoint_mounts = self.endpoint.container_mounts(&self.job);
+
         trace!("Running on Job {} on Endpoint {}", job_id, self.endpoint.name());
         let prepared_container = self.endpoint
-            .prepare_container(self.job, self.staging_store.clone(), self.release_stores.clone())
+            .prepare_container(&self.submit.uuid, self.job, self.staging_store.clone(), self.additional_staging_stores.clone(), self.release_stores.clone(), &self.bar)
             .await?;
         let container_id = prepared_container.create_info().id.clone();
         let running_container = prepared_container
@@ -203,15 +803,43 @@ impl JobHandle {
             package_name: &package.name,
             package_version: &package.version,
             log_dir: self.log_dir.as_ref(),
+            log_index: &self.log_index,
+            db: &self.db,
+            submit: self.submit.uuid,
+            raw_logfile: self.offloaded_log_lines.is_some(),
+            max_log_size: self.max_log_size,
             job_id,
             log_receiver,
             bar: self.bar.clone(),
+            secrets,
+            live_log: self.live_log.clone(),
+            status: self.status,
+            expected_secs: self.expected_secs,
+            started: std::time::Instant::now(),
+            message: String::new(),
         }
         .join();
         drop(self.bar);
 
-        let (run_container, logres) = tokio::join!(running_container, logres);
-        let log = logres.with_context(|| anyhow!("Collecting logs for job on '{}'", endpoint_name))?;
+        let (run_container, logres) = {
+            let finished = async { tokio::join!(running_container, logres) };
+            tokio::pin!(finished);
+            tokio::select! {
+                results = &mut finished => results,
+                _ = self.canceled.cancelled() => {
+                    warn!("Submit {} was canceled, removing container {} of job {}", self.submit.uuid, container_id, job_id);
+                    self.endpoint.remove_container(&container_id).await?;
+
+                    // The log ends with the container, waiting for it writes the rest of the log
+                    // file. Jobs on endpoints without docker are only killed when they are dropped.
+                    if self.endpoint.is_docker() && tokio::time::timeout(std::time::Duration::from_secs(CANCELED_LOG_TIMEOUT_SECS), finished).await.is_err() {
+                        warn!("The log of job {} did not end after its container was removed", job_id);
+                    }
+                    return Ok(Err(anyhow!("Submit {} was canceled, removed container {} of job {}", self.submit.uuid, container_id, job_id)))
+                },
+            }
+        };
+        let (log, phase_timings) = logres.with_context(|| anyhow!("Collecting logs for job on '{}'", endpoint_name))?;
         let run_container = run_container
             .with_context(|| anyhow!("Running container {} failed", container_id))
             .with_context(|| {
@@ -224,24 +852,68 @@ impl JobHandle {
                 )
             })?;
 
-        let job = dbmodels::Job::create(
-            &self.db,
-            &job_id,
-            &self.submit,
-            &endpoint,
-            &package,
-            &image,
-            &run_container.container_hash(),
-            run_container.script(),
-            &log,
-        )
-        .context("Recording job that is ready in database")?;
-
-        trace!("DB: Job entry for job {} created: {}", job.uuid, job.id);
-        for env in envs {
-            dbmodels::JobEnv::create(&self.db, &job, &env)
-                .with_context(|| format!("Creating Environment Variable mapping for Job: {}", job.uuid))?;
-        }
+        // If the build has no network access, a failure might be caused by the build trying to
+        // reach the network, which is pointed out in the error
+        let network_access = if network == crate::package::Network::None {
+            crate::package::find_network_access(&log)
+        } else {
+            None
+        };
+
+        let script_hash = {
+            use sha2::Digest;
+            format!("{:x}", sha2::Sha256::digest(run_container.script().as_ref().as_bytes()))
+        };
+
+        let log_text = match (self.log_dir.as_ref(), self.offloaded_log_lines) {
+            (Some(log_dir), Some(tail_lines)) => {
+                let path = crate::log::job_log_path(log_dir, &self.submit.uuid, &package.name, &package.version, &job_id);
+                crate::log::offloaded_log_text(&path, &log, tail_lines)
+            },
+            _ => log,
+        };
+
+        let (job, package, git_hash) = {
+            let submit = self.submit.clone();
+            let container_hash = run_container.container_hash();
+            let script = run_container.script().clone();
+            let phases = phase_timings.clone();
+            let image_digest = image_digest.clone();
+
+            crate::db::with_connection(&db, move |conn| {
+                let job = dbmodels::Job::create(
+                    conn,
+                    &job_id,
+                    &submit,
+                    &endpoint,
+                    &package,
+                    &image,
+                    &container_hash,
+                    &script,
+                    &log_text,
+                    patches_hash.as_deref(),
+                    options.as_deref(),
+                    image_digest.as_deref(),
+                    endpoint_env,
+                    endpoint_mounts,
+                )
+                .context("Recording job that is ready in database")?;
+
+                trace!("DB: Job entry for job {} created: {}", job.uuid, job.id);
+                for env in envs {
+                    dbmodels::JobEnv::create(conn, &job, &env)
+                        .with_context(|| format!("Creating Environment Variable mapping for Job: {}", job.uuid))?;
+                }
+                for (position, timing) in phases.iter().enumerate() {
+                    dbmodels::JobPhase::create(conn, &job, position as i32, timing)
+                        .with_context(|| format!("Recording phase timing for Job: {}", job.uuid))?;
+                }
+
+                let git_hash = dbmodels::GitHash::with_id(conn, submit.repo_hash_id)?.hash;
+                Ok((job, package, git_hash))
+            })
+            .await?
+        };
 
         let res: crate::endpoint::FinalizedContainer = run_container
             .finalize(self.staging_store.clone())
@@ -270,7 +942,18 @@ impl JobHandle {
                     &container_id,
                 )
             })
-            .map_err(Error::from);
+            .map_err(|e| Error::from(crate::error::Error::Container(e)));
+
+        let res = match (res, network_access) {
+            (Err(e), Some(access)) => Err(e.context(anyhow!(
+                "Phase '{}' tried to access the network, but the network is disabled for {} {}: {}",
+                access.phase.as_deref().unwrap_or("unknown"),
+                package.name,
+                package.version,
+                access.line
+            ))),
+            (res, _) => res,
+        };
 
         if res.is_err() {
             trace!("Error was returned from script");
@@ -279,12 +962,39 @@ impl JobHandle {
              })
         }
 
+        // Record the hashes and sizes of the artifacts, so that the stores can be verified later
+        let mut hashes = Vec::with_capacity(paths.len());
+        {
+            let staging_read = self.staging_store.read().await;
+            for p in paths.iter() {
+                let hash = match staging_read.root_path().join(p)? {
+                    Some(full) => {
+                        let full = full.joined();
+                        let size = tokio::fs::metadata(&full).await?.len() as i64;
+                        Some((ObjectStore::hash_file(&full).await?, size))
+                    },
+                    None => None,
+                };
+                hashes.push(hash);
+            }
+        }
+
+        let job_uuid = job.uuid;
+        let paths = crate::db::with_connection(&db, move |conn| {
+            for (p, hash) in paths.iter().zip(hashes.iter()) {
+                trace!("DB: Creating artifact entry for path: {}", p.display());
+                let output = job_package.artifact_output(p.as_ref());
+                let (hash, size) = hash.as_ref().map(|(hash, size)| (Some(hash.as_str()), Some(*size))).unwrap_or_default();
+                let _ = dbmodels::Artifact::create(conn, p, &job, Some(output.as_ref()), hash, size, hermetic)?;
+            }
+            Ok(paths)
+        })
+        .await?;
+
         // Have to do it the ugly way here because of borrowing semantics
         let mut r = vec![];
         let staging_read = self.staging_store.read().await;
         for p in paths.iter() {
-            trace!("DB: Creating artifact entry for path: {}", p.display());
-            let _ = dbmodels::Artifact::create(&self.db, p, &job)?;
             r.push({
                 staging_read
                     .get(p)
@@ -292,6 +1002,33 @@ impl JobHandle {
                     .clone()
             });
         }
+
+        let mut meta = ArtifactMetadata {
+            package_name: package.name.clone(),
+            package_version: package.version.clone(),
+            submit_uuid: self.submit.uuid,
+            job_uuid,
+            image_name,
+            endpoint: endpoint_name.to_string(),
+            image_digest,
+            git_hash,
+            script_hash,
+            env: meta_env,
+            sources,
+            phases: phase_timings,
+            started: started.to_rfc3339(),
+            finished: chrono::Utc::now().to_rfc3339(),
+            compression: None,
+        };
+        for p in r.iter() {
+            if let Some(full) = staging_read.root_path().join(p)? {
+                meta.compression = staging_read.compression_of(p).cloned();
+                trace!("Writing metadata for {}", full.display());
+                meta.write_for(&full.joined()).await?;
+                meta.write_provenance_for(&full.joined(), &p.display().to_string()).await?;
+            }
+        }
+
         Ok(Ok(r))
     }
 
@@ -321,36 +1058,32 @@ impl JobHandle {
         ))
     }
 
-    fn create_env_in_db(&self) -> Result<Vec<dbmodels::EnvVar>> {
-        trace!("Creating environment in database");
+    /// Collect the environment of the job, hardcoded variables of the package first
+    fn job_env(&self) -> Vec<(EnvironmentVariableName, String)> {
         trace!("Hardcoded = {:?}", self.job.package().environment());
         trace!("Dynamic   = {:?}", self.job.resources());
         self.job
             .package()
             .environment()
-            .as_ref()
-            .map(|hm| {
-                hm.iter()
-                    .inspect(|(k, v)| {
-                        trace!("Creating environment variable in database: {} = {}", k, v)
-                    })
-                    .map(|(k, v)| dbmodels::EnvVar::create_or_fetch(&self.db, k, v))
-                    .collect::<Result<Vec<_>>>()
-            })
-            .transpose()?
-            .unwrap_or_default()
-            .into_iter()
-            .map(Ok)
+            .iter()
+            .flat_map(|hm| hm.iter())
             .chain({
                 self.job
                     .resources()
                     .iter()
                     .filter_map(JobResource::env)
-                    .inspect(|(k, v)| {
-                        trace!("Creating environment variable in database: {} = {}", k, v)
-                    })
-                    .map(|(k, v)| dbmodels::EnvVar::create_or_fetch(&self.db, k, v))
             })
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    fn create_env_in_db(conn: &PgConnection, env: Vec<(EnvironmentVariableName, String)>) -> Result<Vec<dbmodels::EnvVar>> {
+        trace!("Creating environment in database");
+        env.iter()
+            .inspect(|(k, v)| {
+                trace!("Creating environment variable in database: {} = {}", k, v)
+            })
+            .map(|(k, v)| dbmodels::EnvVar::create_or_fetch(conn, k, v))
             .collect()
     }
 }
@@ -361,18 +1094,44 @@ struct LogReceiver<'a> {
     package_name: &'a str,
     package_version: &'a str,
     log_dir: Option<&'a PathBuf>,
+    log_index: &'a LogIndex,
+
+    /// Where the phase and the progress of the job are recorded
+    db: &'a DbPool,
+    submit: Uuid,
+
+    /// Write the log file without colors, so that it can be parsed again, because it is the log
+    /// that is referenced in the database
+    raw_logfile: bool,
+
+    /// The maximum size of the log that is returned, the log file is not truncated
+    max_log_size: Option<u64>,
     job_id: Uuid,
     log_receiver: UnboundedReceiver<LogItem>,
     bar: ProgressBar,
+
+    /// Values that are redacted from the log
+    secrets: Vec<String>,
+
+    live_log: Option<LiveLog>,
+    status: Option<StatusPrinter>,
+
+    /// How long the job is expected to run, to show the remaining time on the bar
+    expected_secs: Option<f64>,
+    started: std::time::Instant,
+
+    /// The message on the bar, without the remaining time
+    message: String,
 }
 
 impl<'a> LogReceiver<'a> {
-    async fn join(mut self) -> Result<String> {
+    /// Receive the log until the job finished
+    ///
+    /// Returns the log and the timings of the phases of the job
+    async fn join(mut self) -> Result<(String, Vec<PhaseTiming>)> {
         let mut success = None;
-        let mut accu = vec![];
-
-        // Reserve a reasonable amount of elements.
-        accu.reserve(4096);
+        let mut accu = crate::log::LogBuffer::new(self.max_log_size);
+        let mut phases: Vec<(String, chrono::DateTime<chrono::Utc>, std::time::Instant)> = vec![];
 
         let mut logfile = self.get_logfile()
             .await
@@ -394,38 +1153,65 @@ impl<'a> LogReceiver<'a> {
             // happening, even if there was no log output for several seconds.
             let logitem = match tokio::time::timeout(timeout_duration, self.log_receiver.recv()).await {
                 Err(_ /* elapsed */) => {
-                    self.bar.tick(); // just ping the progressbar here
+                    if self.expected_secs.is_some() && !self.message.is_empty() {
+                        self.show_message(); // updates the remaining time as well
+                    } else {
+                        self.bar.tick(); // just ping the progressbar here
+                    }
                     continue
                 },
 
                 Ok(None) => break, // if the log is empty, we're done
-                Ok(Some(logitem)) => logitem,
+                Ok(Some(logitem)) => self.redact(logitem),
             };
 
             if let Some(lf) = logfile.as_mut() {
-                lf.write_all(logitem.display()?.to_string().as_bytes())
-                    .await?;
+                let line = if self.raw_logfile {
+                    logitem.raw()?
+                } else {
+                    logitem.display()?.to_string()
+                };
+                lf.write_all(line.as_bytes()).await?;
                 lf.write_all(b"\n").await?;
             }
 
+            if let Some(live_log) = self.live_log.as_ref() {
+                live_log.send(LiveLogEvent::new(self.job_id, self.package_name, self.package_version, &logitem));
+            }
+
             match logitem {
-                LogItem::Line(_) => {
+                LogItem::Line(_) | LogItem::StderrLine(_) => {
                     // ignore
                 }
                 LogItem::Progress(u) => {
                     trace!("Setting bar to {}", u as u64);
+                    if u as u64 != self.bar.position() {
+                        let (job_id, progress) = (self.job_id, u.min(100) as i32);
+                        self.record(move |conn| dbmodels::RunningJob::set_progress(conn, &job_id, progress)).await;
+                    }
                     self.bar.set_position(u as u64);
                 }
                 LogItem::CurrentPhase(ref phasename) => {
                     trace!("Setting bar phase to {}", phasename);
-                    self.bar.set_message(format!(
+                    let (job_id, phase) = (self.job_id, phasename.clone());
+                    self.record(move |conn| dbmodels::RunningJob::set_phase(conn, &job_id, &phase)).await;
+                    phases.push((phasename.clone(), chrono::Utc::now(), std::time::Instant::now()));
+                    if let Some(status) = self.status.as_ref() {
+                        status.print(&StatusEvent::PhaseChanged {
+                            job: self.job_id,
+                            package_name: self.package_name,
+                            package_version: self.package_version,
+                            phase: phasename,
+                        });
+                    }
+                    self.set_message(format!(
                         "[{}/{} {} {} {}]: Phase: {}",
                         self.endpoint_name, self.container_id_chrs, self.job_id, self.package_name, self.package_version, phasename
                     ));
                 }
                 LogItem::State(Ok(())) => {
                     trace!("Setting bar state to Ok");
-                    self.bar.set_message(format!(
+                    self.set_message(format!(
                         "[{}/{} {} {} {}]: State Ok",
                         self.endpoint_name, self.container_id_chrs, self.job_id, self.package_name, self.package_version
                     ));
@@ -433,7 +1219,7 @@ impl<'a> LogReceiver<'a> {
                 }
                 LogItem::State(Err(ref e)) => {
                     trace!("Setting bar state to Err: {}", e);
-                    self.bar.set_message(format!(
+                    self.set_message(format!(
                         "[{}/{} {} {} {}]: State Err: {}",
                         self.endpoint_name, self.container_id_chrs, self.job_id, self.package_name, self.package_version, e
                     ));
@@ -464,30 +1250,146 @@ impl<'a> LogReceiver<'a> {
             lf.flush().await?;
         }
 
-        Ok({
-            accu.iter()
-                .map(crate::log::LogItem::raw)
-                .collect::<Result<Vec<String>>>()?
-                .join("\n")
-        })
+        // Each phase lasts until the next phase starts, the last one until the log ended
+        let end = std::time::Instant::now();
+        let ends = phases.iter()
+            .skip(1)
+            .map(|(_, _, started)| *started)
+            .chain(std::iter::once(end));
+        let timings = phases.iter()
+            .zip(ends)
+            .map(|((name, started, instant), end)| PhaseTiming {
+                name: name.clone(),
+                started: started.to_rfc3339(),
+                duration_secs: end.duration_since(*instant).as_secs_f64(),
+            })
+            .collect();
+
+        if accu.is_truncated() {
+            warn!("The log of job {} exceeded the maximum log size, dropped lines in the middle of the log", self.job_id);
+        }
+        let log = accu.into_items()
+            .iter()
+            .map(crate::log::LogItem::raw)
+            .collect::<Result<Vec<String>>>()?
+            .join("\n");
+        Ok((log, timings))
+    }
+
+    fn redact(&self, item: LogItem) -> LogItem {
+        match item {
+            LogItem::Line(line) if !self.secrets.is_empty() => {
+                let line = String::from_utf8_lossy(&line);
+                LogItem::Line(crate::util::secret::redact(&line, &self.secrets).into_bytes())
+            },
+            LogItem::StderrLine(line) if !self.secrets.is_empty() => {
+                let line = String::from_utf8_lossy(&line);
+                LogItem::StderrLine(crate::util::secret::redact(&line, &self.secrets).into_bytes())
+            },
+            other => other,
+        }
+    }
+
+    /// Show `message` on the bar, followed by the remaining time of the job if it is known
+    fn set_message(&mut self, message: String) {
+        self.message = message;
+        self.show_message();
+    }
+
+    fn show_message(&self) {
+        match self.expected_secs {
+            Some(expected_secs) => {
+                let eta = format_eta(expected_secs, self.started.elapsed().as_secs_f64());
+                self.bar.set_message(format!("{} ({})", self.message, eta));
+            },
+            None => self.bar.set_message(self.message.clone()),
+        }
+    }
+
+    /// Record the phase or the progress of the job for "db running"
+    ///
+    /// Failing to record it only results in a warning, the job goes on.
+    async fn record<F>(&self, f: F)
+    where
+        F: FnOnce(&diesel::PgConnection) -> Result<()> + Send + 'static,
+    {
+        if let Err(e) = crate::db::with_connection(self.db, f).await {
+            warn!("Recording the state of job {} failed: {:#}", self.job_id, e);
+        }
     }
 
     async fn get_logfile(&self) -> Option<Result<tokio::io::BufWriter<tokio::fs::File>>> {
-        if let Some(log_dir) = self.log_dir.as_ref() {
-            Some({
-                let path = log_dir.join(format!("{}.log", self.job_id));
-                tokio::fs::OpenOptions::new()
-                    .create(true)
-                    .create_new(true)
-                    .write(true)
-                    .open(&path)
-                    .await
-                    .map(tokio::io::BufWriter::new)
-                    .with_context(|| anyhow!("Opening {}", path.display()))
-                    .map_err(Error::from)
-            })
-        } else {
-            None
+        let log_dir = self.log_dir.as_ref()?;
+        Some(self.create_logfile(log_dir).await)
+    }
+
+    /// Create the log file of the job in the directory of the submit in `log_dir` and add it to
+    /// the index of the directory
+    async fn create_logfile(&self, log_dir: &Path) -> Result<tokio::io::BufWriter<tokio::fs::File>> {
+        let dir = crate::log::submit_log_dir(log_dir, &self.submit);
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .with_context(|| anyhow!("Creating {}", dir.display()))?;
+
+        let path = crate::log::job_log_path(log_dir, &self.submit, self.package_name, self.package_version, &self.job_id);
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .create_new(true)
+            .write(true)
+            .open(&path)
+            .await
+            .map(tokio::io::BufWriter::new)
+            .with_context(|| anyhow!("Opening {}", path.display()))?;
+
+        if let Err(e) = self.log_index.add(&path, self.job_id, self.package_name, self.package_version).await {
+            warn!("Adding {} to the log index failed: {:#}", path.display(), e);
         }
+        Ok(file)
+    }
+}
+
+/// The remaining time of a job that is expected to run for `expected_secs` and runs for
+/// `running_secs`, for the progress bars
+pub fn format_eta(expected_secs: f64, running_secs: f64) -> String {
+    let format = |secs: f64| humantime::format_duration(std::time::Duration::from_secs(secs.round() as u64));
+    if running_secs <= expected_secs {
+        format!("ETA {}", format(expected_secs - running_secs))
+    } else {
+        format!("{} longer than usual", format(running_secs - expected_secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_eta() {
+        assert_eq!(format_eta(90.0, 20.0), "ETA 1m 10s");
+        assert_eq!(format_eta(90.0, 90.0), "ETA 0s");
+        assert_eq!(format_eta(60.0, 3660.0), "1h longer than usual");
+    }
+
+    #[test]
+    fn test_wait_queue_serves_submits_in_turn() {
+        let queue = std::sync::Mutex::new(WaitQueue::default());
+        let changed = tokio::sync::Notify::new();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let a1 = QueueTicket::new(&queue, &changed, 10, a);
+        let a2 = QueueTicket::new(&queue, &changed, 5, a);
+        let b1 = QueueTicket::new(&queue, &changed, 1, b);
+
+        // The highest priority first, while no submit got an endpoint yet
+        assert!(a1.is_next());
+        a1.served();
+        drop(a1);
+
+        // Then the other submit, even though its job has a lower priority
+        assert!(b1.is_next());
+        assert!(!a2.is_next());
+        b1.served();
+        drop(b1);
+        assert!(a2.is_next());
     }
 }
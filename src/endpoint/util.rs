@@ -28,3 +28,13 @@ pub async fn setup_endpoints(endpoints: Vec<EndpointConfiguration>) -> Result<Ve
     unordered.collect().await
 }
 
+/// Connect to the endpoints without checking versions and images, so this also works for
+/// endpoints that are not reachable
+pub async fn connect_endpoints_unchecked(endpoints: Vec<EndpointConfiguration>) -> Result<Vec<Arc<Endpoint>>> {
+    futures::future::join_all(endpoints.iter().map(Endpoint::connect))
+        .await
+        .into_iter()
+        .map(|r_ep| r_ep.map(Arc::new))
+        .collect()
+}
+
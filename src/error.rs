@@ -0,0 +1,158 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The categories of errors butido fails with
+//!
+//! Errors are still `anyhow::Error`s with their context, but the error of a major category is
+//! wrapped in an `Error`, e.g. with `.map_err(Error::Config)`. The wrapper does not change the
+//! messages of the error, but the category can be found in the chain of the error to map it to an
+//! exit code and a machine-readable code.
+
+/// An error of one of the major categories
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Loading or validating the configuration failed
+    #[error(transparent)]
+    Config(anyhow::Error),
+
+    /// Loading or parsing the package repository failed
+    #[error(transparent)]
+    Repository(anyhow::Error),
+
+    /// Resolving the dependencies of a package failed
+    #[error(transparent)]
+    Resolution(anyhow::Error),
+
+    /// Connecting to or talking to an endpoint failed
+    #[error(transparent)]
+    Endpoint(anyhow::Error),
+
+    /// A job failed in its container
+    #[error(transparent)]
+    Container(anyhow::Error),
+
+    /// Connecting to or querying the database failed
+    #[error(transparent)]
+    Database(anyhow::Error),
+
+    /// Loading or writing a staging or release store failed
+    #[error(transparent)]
+    Store(anyhow::Error),
+}
+
+impl Error {
+    /// The wrapped error
+    pub fn inner(&self) -> &anyhow::Error {
+        match self {
+            Error::Config(e)
+            | Error::Repository(e)
+            | Error::Resolution(e)
+            | Error::Endpoint(e)
+            | Error::Container(e)
+            | Error::Database(e)
+            | Error::Store(e) => e,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Config(_) => ErrorKind::Config,
+            Error::Repository(_) => ErrorKind::Repository,
+            Error::Resolution(_) => ErrorKind::Resolution,
+            Error::Endpoint(_) => ErrorKind::Endpoint,
+            Error::Container(_) => ErrorKind::Container,
+            Error::Database(_) => ErrorKind::Database,
+            Error::Store(_) => ErrorKind::Store,
+        }
+    }
+}
+
+/// The category of an error
+#[derive(Clone, Copy, Debug, Eq, PartialEq, parse_display::Display)]
+#[display(style = "lowercase")]
+pub enum ErrorKind {
+    Config,
+    Repository,
+    Resolution,
+    Endpoint,
+    Container,
+    Database,
+    Store,
+}
+
+impl ErrorKind {
+    /// The category of `error`
+    ///
+    /// If several categories are in the chain of the error, the innermost one is the cause of the
+    /// error. Errors of the database and docker libraries are in their categories, even if they
+    /// were not wrapped.
+    pub fn of(error: &anyhow::Error) -> Option<ErrorKind> {
+        error.chain()
+            .filter_map(|e| {
+                if let Some(e) = e.downcast_ref::<Error>() {
+                    // The wrapper hides the outermost error of the wrapped one from the chain
+                    Some(ErrorKind::of(e.inner()).unwrap_or_else(|| e.kind()))
+                } else if e.is::<diesel::result::Error>() || e.is::<diesel::ConnectionError>() || e.is::<diesel::r2d2::PoolError>() {
+                    Some(ErrorKind::Database)
+                } else if e.is::<shiplift::Error>() {
+                    Some(ErrorKind::Endpoint)
+                } else {
+                    None
+                }
+            })
+            .last()
+    }
+
+    /// The machine-readable code of the category, as written to the `--result-file`
+    pub fn code(self) -> String {
+        self.to_string()
+    }
+
+    /// The status code butido exits with if it fails with an error of the category
+    ///
+    /// Errors without a category exit with 1.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Config => 3,
+            ErrorKind::Repository => 4,
+            ErrorKind::Resolution => 5,
+            ErrorKind::Endpoint => 6,
+            ErrorKind::Container => 7,
+            ErrorKind::Database => 8,
+            ErrorKind::Store => 9,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use anyhow::Context;
+
+    #[test]
+    fn test_kind_of() {
+        let error = Err::<(), _>(Error::Resolution(anyhow!("Dependency of a 1 not found: b =2")))
+            .context("build command failed")
+            .unwrap_err();
+        assert_eq!(ErrorKind::of(&error), Some(ErrorKind::Resolution));
+        assert_eq!(format!("{:#}", error), "build command failed: Dependency of a 1 not found: b =2");
+
+        // The innermost category is the cause
+        let error = anyhow::Error::from(Error::Endpoint(anyhow::Error::from(Error::Database(anyhow!("Connection refused")))));
+        assert_eq!(ErrorKind::of(&error), Some(ErrorKind::Database));
+
+        let error = anyhow::Error::from(diesel::result::Error::NotFound).context("Loading submit");
+        assert_eq!(ErrorKind::of(&error), Some(ErrorKind::Database));
+
+        assert_eq!(ErrorKind::of(&anyhow!("Something failed")), None);
+        assert_eq!(ErrorKind::Resolution.code(), "resolution");
+    }
+}
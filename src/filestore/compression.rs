@@ -0,0 +1,203 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Compression of the tarballs that are collected from the jobs
+//!
+//! The tarballs are (re)compressed with the `zstd`, `xz` or `gzip` command before they are added
+//! to the staging store, so these have to be installed if `artifact_compression` is configured.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use log::trace;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::config::ArtifactCompression;
+use crate::config::CompressionFormat;
+
+/// The extensions of compressed tarballs that can be recompressed, with the command that
+/// decompresses them
+const COMPRESSED_TARBALLS: &[(&str, &str)] = &[
+    (".tar.gz", "gzip"),
+    (".tgz", "gzip"),
+    (".tar.xz", "xz"),
+    (".txz", "xz"),
+    (".tar.zst", "zstd"),
+    (".tar.bz2", "bzip2"),
+    (".tbz2", "bzip2"),
+];
+
+/// How an artifact was compressed by butido, as recorded in its metadata
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct CompressionInfo {
+    pub format: CompressionFormat,
+    pub level: Option<u32>,
+
+    /// The file name of the artifact as it was collected from the job
+    pub original_name: String,
+}
+
+/// The file name the artifact `name` gets with `compression` and the command that decompresses
+/// it first, or None if it is not compressed
+fn compressed_name(name: &str, compression: &ArtifactCompression) -> Option<(String, Option<&'static str>)> {
+    let target = compression.format();
+    if let Some(stem) = name.strip_suffix(".tar") {
+        return Some((format!("{}{}", stem, target.extension()), None))
+    }
+
+    if !compression.recompress() {
+        return None
+    }
+
+    COMPRESSED_TARBALLS.iter()
+        .filter(|(_, command)| *command != target.command())
+        .find_map(|(ext, command)| {
+            name.strip_suffix(ext).map(|stem| (format!("{}{}", stem, target.extension()), Some(*command)))
+        })
+}
+
+/// The arguments of the command of `format` to compress stdin to stdout
+fn compress_args(format: CompressionFormat, level: Option<u32>) -> Vec<String> {
+    let mut args = match format {
+        CompressionFormat::Zstd => vec![String::from("-q"), String::from("-c")],
+        CompressionFormat::Xz => vec![String::from("-c")],
+
+        // Without the name and timestamp of the input, so the same tarball is compressed the same
+        CompressionFormat::Gzip => vec![String::from("-c"), String::from("-n")],
+    };
+    args.extend(level.map(|l| format!("-{}", l)));
+    args
+}
+
+/// Compress the artifact at `path` with `compression`, replacing it
+///
+/// Returns the path of the compressed artifact, or None if the artifact is not a tarball that is
+/// compressed.
+pub fn compress_artifact(path: &Path, compression: &ArtifactCompression) -> Result<Option<(PathBuf, CompressionInfo)>> {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let (compressed_name, decompress) = match compressed_name(name, compression) {
+        Some(c) => c,
+        None => return Ok(None),
+    };
+
+    let dest = path.with_file_name(&compressed_name);
+    if dest.exists() {
+        return Err(anyhow!("Cannot compress {}, file exists: {}", path.display(), dest.display()))
+    }
+
+    trace!("Compressing {} to {}", path.display(), dest.display());
+    let partial = path.with_file_name(format!(".{}.part", compressed_name));
+    let result = run_compression(path, &partial, decompress, compression)
+        .and_then(|_| {
+            std::fs::rename(&partial, &dest)
+                .with_context(|| anyhow!("Moving {} to {}", partial.display(), dest.display()))
+        });
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e)
+    }
+
+    std::fs::remove_file(path).with_context(|| anyhow!("Removing {}", path.display()))?;
+    let info = CompressionInfo {
+        format: compression.format(),
+        level: compression.level(),
+        original_name: name.to_string(),
+    };
+    Ok(Some((dest, info)))
+}
+
+fn run_compression(src: &Path, dest: &Path, decompress: Option<&str>, compression: &ArtifactCompression) -> Result<()> {
+    let output = std::fs::File::create(dest).with_context(|| anyhow!("Creating file: {}", dest.display()))?;
+    let mut decompressor = decompress
+        .map(|command| {
+            Command::new(command)
+                .arg("-dc")
+                .arg(src)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .with_context(|| anyhow!("Starting {}", command))
+        })
+        .transpose()?;
+
+    let input = match decompressor.as_mut() {
+        Some(child) => Stdio::from(child.stdout.take().unwrap()), // piped above
+        None => Stdio::from(std::fs::File::open(src).with_context(|| anyhow!("Opening file: {}", src.display()))?),
+    };
+
+    let command = compression.format().command();
+    let compressed = Command::new(command)
+        .args(compress_args(compression.format(), compression.level()))
+        .stdin(input)
+        .stdout(output)
+        .output()
+        .with_context(|| anyhow!("Starting {}", command))?;
+
+    if let Some(child) = decompressor {
+        let decompressed = child.wait_with_output()?;
+        if !decompressed.status.success() {
+            return Err(anyhow!("Decompressing {} failed: {}", src.display(), String::from_utf8_lossy(&decompressed.stderr).trim()))
+        }
+    }
+    if !compressed.status.success() {
+        return Err(anyhow!("Compressing {} failed: {}", src.display(), String::from_utf8_lossy(&compressed.stderr).trim()))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compression(toml: &str) -> ArtifactCompression {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_compressed_name() {
+        let zstd = compression(r#"format = "zstd""#);
+        assert_eq!(compressed_name("a-1.0.tar", &zstd), Some((String::from("a-1.0.tar.zst"), None)));
+        assert_eq!(compressed_name("a-1.0.tar.gz", &zstd), None);
+        assert_eq!(compressed_name("a-1.0.deb", &zstd), None);
+
+        let zstd = compression(r#"format = "zstd"
+            recompress = true"#);
+        assert_eq!(compressed_name("a-1.0.tar.gz", &zstd), Some((String::from("a-1.0.tar.zst"), Some("gzip"))));
+        assert_eq!(compressed_name("a-1.0.tbz2", &zstd), Some((String::from("a-1.0.tar.zst"), Some("bzip2"))));
+        assert_eq!(compressed_name("a-1.0.tar.zst", &zstd), None);
+        assert_eq!(compressed_name("a-1.0.rpm", &zstd), None);
+
+        let gzip = compression(r#"format = "gzip"
+            recompress = true"#);
+        assert_eq!(compressed_name("a-1.0.tgz", &gzip), None);
+    }
+
+    #[test]
+    fn test_compress_args() {
+        assert_eq!(compress_args(CompressionFormat::Gzip, Some(9)), vec!["-c", "-n", "-9"]);
+        assert_eq!(compress_args(CompressionFormat::Xz, None), vec!["-c"]);
+    }
+
+    #[test]
+    fn test_check_level() {
+        assert!(compression("format = \"zstd\"\nlevel = 19").check_level().is_ok());
+        assert!(compression("format = \"gzip\"\nlevel = 0").check_level().is_err());
+        assert!(compression("format = \"xz\"").check_level().is_ok());
+    }
+}
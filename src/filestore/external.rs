@@ -0,0 +1,156 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use indicatif::ProgressBar;
+use log::debug;
+use log::trace;
+
+use crate::filestore::path::ArtifactPath;
+use crate::package::ExternalDependency;
+use crate::util::progress::TransferBars;
+
+/// The local cache of the artifacts of external dependencies
+///
+/// The artifacts are stored as "<name>-<version>/<file name>", so that the cache can be loaded as
+/// a store to copy the artifacts into the containers from.
+#[derive(Debug)]
+pub struct ExternalArtifactCache {
+    root: PathBuf,
+}
+
+impl ExternalArtifactCache {
+    pub fn new(root: PathBuf) -> Self {
+        ExternalArtifactCache { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The path of the artifact of `dependency`, relative to the root of the cache
+    pub fn artifact_path(dependency: &ExternalDependency) -> Result<ArtifactPath> {
+        let file_name = dependency
+            .artifact()
+            .file_name()
+            .ok_or_else(|| anyhow!("Artifact of external dependency {} {} is not a file: {}",
+                dependency.name(), dependency.version(), dependency.artifact().display()))?;
+
+        ArtifactPath::new(PathBuf::from(format!("{}-{}", dependency.name(), dependency.version())).join(file_name))
+    }
+
+    /// Make sure the artifact of `dependency` is in the cache, fetching it from the first of the
+    /// `sources` (base URLs of remote release stores) that has it
+    ///
+    /// Cached artifacts are verified again and fetched again if their hash does not match.
+    pub async fn fetch(&self, dependency: &ExternalDependency, sources: &[String], transfers: &TransferBars) -> Result<ArtifactPath> {
+        // Hashing cached artifacts is not a transfer, so it is not shown
+        let progress = &ProgressBar::hidden();
+        let artifact_path = Self::artifact_path(dependency)?;
+        let path = self.root.join(artifact_path.as_ref());
+
+        if path.is_file() {
+            match self.verify(dependency, &path, progress).await {
+                Ok(()) => {
+                    trace!("{} is cached already", path.display());
+                    return Ok(artifact_path)
+                },
+                Err(e) => debug!("Fetching {} again: {:#}", path.display(), e),
+            }
+        }
+
+        if sources.is_empty() {
+            return Err(anyhow!("No external artifact sources configured to fetch {} {} from",
+                dependency.name(), dependency.version()))
+        }
+
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .with_context(|| anyhow!("Creating directory: {}", dir.display()))?;
+        }
+
+        let mut errors = vec![];
+        for source in sources {
+            let url = format!("{}/{}", source.trim_end_matches('/'), dependency.artifact().display());
+            match self.fetch_from(&url, &path, transfers).await {
+                Ok(()) => {
+                    self.verify(dependency, &path, progress)
+                        .await
+                        .with_context(|| anyhow!("Verifying {}", url))?;
+                    return Ok(artifact_path)
+                },
+                Err(e) => {
+                    debug!("Fetching {} failed: {:#}", url, e);
+                    errors.push(format!("{}: {:#}", url, e));
+                },
+            }
+        }
+
+        Err(anyhow!("Fetching {} {} failed:\n{}", dependency.name(), dependency.version(), errors.join("\n")))
+    }
+
+    async fn fetch_from(&self, url: &str, path: &Path, transfers: &TransferBars) -> Result<()> {
+        use futures::StreamExt;
+        use tokio::io::AsyncWriteExt;
+
+        trace!("Fetching {} to {}", url, path.display());
+        let response = reqwest::get(url)
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| anyhow!("Downloading '{}'", url))?;
+
+        // Download next to the artifact first, so that there is never an incomplete artifact
+        let partial = path.with_file_name({
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            format!(".{}.part", name)
+        });
+        let mut file = tokio::fs::File::create(&partial)
+            .await
+            .with_context(|| anyhow!("Creating file: {}", partial.display()))?;
+        let bar = transfers.transfer(url.to_string())?;
+        bar.inc_length(response.content_length().unwrap_or(0));
+        let mut stream = response.bytes_stream();
+        while let Some(bytes) = stream.next().await {
+            let bytes = match bytes {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    // The next source counts the bytes again
+                    bar.restart();
+                    bar.finish();
+                    return Err(e.into())
+                },
+            };
+            file.write_all(bytes.as_ref()).await?;
+            bar.inc(bytes.len() as u64);
+        }
+        bar.finish();
+        file.flush().await?;
+
+        tokio::fs::rename(&partial, path)
+            .await
+            .with_context(|| anyhow!("Moving {} to {}", partial.display(), path.display()))
+    }
+
+    async fn verify(&self, dependency: &ExternalDependency, path: &Path, progress: &ProgressBar) -> Result<()> {
+        let file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| anyhow!("Opening file: {}", path.display()))?;
+        dependency.hash()
+            .matches_hash_of(tokio::io::BufReader::new(file), progress)
+            .await
+            .with_context(|| anyhow!("Verifying {} {}", dependency.name(), dependency.version()))
+    }
+}
@@ -0,0 +1,110 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The manifest of the artifacts of a submit, written into its staging directory
+//!
+//! The manifest lists every artifact in the staging directory with the package it belongs to,
+//! its hash and size and the job that built it, so the staging directory can be released or
+//! processed by other tools without asking the database which artifacts belong to the submit.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// The name of the manifest file in the staging directory of a submit
+pub const MANIFEST_FILE_NAME: &str = "butido-manifest.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubmitManifest {
+    pub submit: Uuid,
+
+    /// When the manifest was written (RFC 3339)
+    pub created: String,
+    pub artifacts: Vec<ManifestArtifact>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestArtifact {
+    /// The path of the artifact, relative to the staging directory
+    pub path: PathBuf,
+    pub package_name: String,
+    pub package_version: String,
+    pub sha256: String,
+    pub size: u64,
+
+    /// The job that built the artifact
+    pub job: Uuid,
+}
+
+impl SubmitManifest {
+    /// The path of the manifest in the staging directory `staging_dir`
+    pub fn path_in(staging_dir: &Path) -> PathBuf {
+        staging_dir.join(MANIFEST_FILE_NAME)
+    }
+
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = tokio::fs::read(path)
+            .await
+            .with_context(|| anyhow!("Reading manifest {}", path.display()))?;
+        serde_json::from_slice(&content).with_context(|| anyhow!("Parsing manifest {}", path.display()))
+    }
+
+    /// Write the manifest into the staging directory `staging_dir`
+    pub async fn write_to(&self, staging_dir: &Path) -> Result<PathBuf> {
+        let path = Self::path_in(staging_dir);
+        let content = serde_json::to_string_pretty(self)?;
+        tokio::fs::write(&path, content)
+            .await
+            .with_context(|| anyhow!("Writing manifest {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// The artifact at `path` (relative to the staging directory), if it is listed
+    pub fn artifact(&self, path: &Path) -> Option<&ManifestArtifact> {
+        self.artifacts.iter().find(|artifact| artifact.path == path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_and_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+
+        let manifest = SubmitManifest {
+            submit: Uuid::new_v4(),
+            created: String::from("2022-01-01T00:00:00Z"),
+            artifacts: vec![ManifestArtifact {
+                path: PathBuf::from("x86_64/a-1.0.tar.gz"),
+                package_name: String::from("a"),
+                package_version: String::from("1.0"),
+                sha256: String::from("abc"),
+                size: 3,
+                job: Uuid::new_v4(),
+            }],
+        };
+        let path = manifest.write_to(dir).await.unwrap();
+        assert_eq!(path, dir.join(MANIFEST_FILE_NAME));
+
+        let loaded = SubmitManifest::load(&path).await.unwrap();
+        assert_eq!(loaded.submit, manifest.submit);
+        assert_eq!(loaded.artifact(Path::new("x86_64/a-1.0.tar.gz")).unwrap().job, manifest.artifacts[0].job);
+        assert!(loaded.artifact(Path::new("x86_64/b-1.0.tar.gz")).is_none());
+    }
+}
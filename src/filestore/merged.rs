@@ -0,0 +1,89 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The stores artifacts of a submit are searched in, as one store
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use diesel::PgConnection;
+use log::trace;
+
+use crate::filestore::path::ArtifactPath;
+use crate::filestore::path::FullArtifactPath;
+use crate::filestore::ReleaseStore;
+use crate::filestore::StagingStore;
+
+/// The staging store, the additional staging stores and the release stores, searched in this order
+#[derive(Clone, Copy)]
+pub struct MergedStores<'a> {
+    staging_store: Option<&'a StagingStore>,
+    additional_staging_stores: &'a [Arc<StagingStore>],
+    release_stores: &'a [Arc<ReleaseStore>],
+}
+
+impl<'a> MergedStores<'a> {
+    pub fn new(
+        staging_store: Option<&'a StagingStore>,
+        additional_staging_stores: &'a [Arc<StagingStore>],
+        release_stores: &'a [Arc<ReleaseStore>],
+    ) -> Self {
+        MergedStores { staging_store, additional_staging_stores, release_stores }
+    }
+
+    /// The artifact at `artifact_path` in the first store that has it
+    pub fn get(&self, artifact_path: &ArtifactPath) -> Result<Option<FullArtifactPath<'a>>> {
+        let staging = self.staging_store.into_iter().chain(self.additional_staging_stores.iter().map(AsRef::as_ref));
+        for store in staging {
+            if let Some(art) = store.get(artifact_path) {
+                trace!("Found in staging store {:?}: {:?}", store.root_path(), art);
+                return store.root_path().join(art)
+            }
+        }
+
+        for store in self.release_stores {
+            if let Some(art) = store.get(artifact_path) {
+                trace!("Found in release store {:?}: {:?}", store.root_path(), art);
+                return store.root_path().join(art)
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// An artifact with the sha256 hash `hash` in the first store that has one
+    ///
+    /// The artifact is searched at the paths the database recorded for artifacts with this hash
+    /// first. If it is not found there (e.g. because the store was reorganized), the artifact is
+    /// searched by the object it is linked to in the object store.
+    pub fn find_by_hash(&self, database_connection: &PgConnection, hash: &str) -> Result<Option<FullArtifactPath<'a>>> {
+        for path in crate::db::models::Artifact::paths_with_sha256(database_connection, hash)? {
+            if let Some(full) = self.get(&ArtifactPath::new(path.into())?)? {
+                return Ok(Some(full))
+            }
+        }
+
+        trace!("Searching artifact with hash {} in the object stores", hash);
+        let staging = self.staging_store.into_iter().chain(self.additional_staging_stores.iter().map(AsRef::as_ref));
+        for store in staging {
+            if let Some(art) = store.find_by_hash(hash)? {
+                return store.root_path().join(art)
+            }
+        }
+
+        for store in self.release_stores {
+            if let Some(art) = store.find_by_hash(hash)? {
+                return store.root_path().join(art)
+            }
+        }
+
+        Ok(None)
+    }
+}
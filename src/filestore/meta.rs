@@ -0,0 +1,286 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Metadata files that are written next to artifacts
+//!
+//! For each artifact `<artifact>` a `<artifact>.meta.json` is written, describing how the artifact
+//! was built, so the artifact stays self-describing even when it is copied out of the stores.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::filestore::CompressionInfo;
+
+/// The suffix that is appended to the artifact file name for the metadata file
+pub const META_FILE_SUFFIX: &str = ".meta.json";
+
+/// The suffix that is appended to the artifact file name for the provenance attestation
+pub const PROVENANCE_FILE_SUFFIX: &str = ".intoto.json";
+
+/// The suffix that is appended to the provenance file name for its signature
+pub const SIGNATURE_FILE_SUFFIX: &str = ".sig";
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(suffix);
+    PathBuf::from(p)
+}
+
+/// Get the path of the metadata file for the artifact at `artifact`
+pub fn meta_path_for(artifact: &Path) -> PathBuf {
+    with_suffix(artifact, META_FILE_SUFFIX)
+}
+
+/// Get the path of the provenance attestation for the artifact at `artifact`
+pub fn provenance_path_for(artifact: &Path) -> PathBuf {
+    with_suffix(artifact, PROVENANCE_FILE_SUFFIX)
+}
+
+/// Get the path of the signature of the provenance attestation for the artifact at `artifact`
+pub fn signature_path_for(artifact: &Path) -> PathBuf {
+    with_suffix(&provenance_path_for(artifact), SIGNATURE_FILE_SUFFIX)
+}
+
+/// All files that are written next to the artifact at `artifact`
+pub fn sidecar_paths_for(artifact: &Path) -> Vec<PathBuf> {
+    vec![meta_path_for(artifact), provenance_path_for(artifact), signature_path_for(artifact)]
+}
+
+/// Whether `path` is a file that is written next to an artifact (or the manifest of a staging
+/// directory), and not an artifact itself
+pub fn is_sidecar_path(path: &Path) -> bool {
+    let suffixes = [META_FILE_SUFFIX, PROVENANCE_FILE_SUFFIX, SIGNATURE_FILE_SUFFIX];
+    let is_manifest = path.file_name().map(|name| name == crate::filestore::MANIFEST_FILE_NAME).unwrap_or(false);
+    is_manifest || path.to_str()
+        .map(|s| suffixes.iter().any(|suffix| s.ends_with(suffix)))
+        .unwrap_or(false)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PhaseTiming {
+    /// The name of the phase
+    pub name: String,
+
+    /// When the phase started (RFC 3339)
+    pub started: String,
+
+    /// How long the phase took, in seconds
+    pub duration_secs: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SourceMaterial {
+    pub url: String,
+    pub hash_type: String,
+    pub hash: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArtifactMetadata {
+    pub package_name: String,
+    pub package_version: String,
+    pub submit_uuid: Uuid,
+    pub job_uuid: Uuid,
+    pub image_name: String,
+
+    /// The name of the endpoint the job ran on
+    pub endpoint: String,
+
+    /// The ID of the image on the endpoint the job ran on, if it could be found out
+    pub image_digest: Option<String>,
+
+    /// The commit of the repository the package was built from
+    pub git_hash: String,
+
+    /// sha256 of the script that was run
+    pub script_hash: String,
+    pub env: BTreeMap<String, String>,
+    pub sources: Vec<SourceMaterial>,
+    pub phases: Vec<PhaseTiming>,
+
+    /// When the job started and finished (RFC 3339)
+    pub started: String,
+    pub finished: String,
+
+    /// How the artifact was compressed when it was collected from the job, if it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<CompressionInfo>,
+}
+
+impl ArtifactMetadata {
+    /// Write the metadata to the metadata file for the artifact at `artifact`
+    pub async fn write_for(&self, artifact: &Path) -> Result<()> {
+        let path = meta_path_for(artifact);
+        let content = serde_json::to_string_pretty(self)?;
+        tokio::fs::write(&path, content)
+            .await
+            .with_context(|| anyhow!("Writing metadata file {}", path.display()))
+            .map_err(Error::from)
+    }
+
+    /// Build the in-toto statement with the SLSA provenance of the artifact `name`
+    ///
+    /// The materials are the sources, the image and the commit of the repository, the invocation
+    /// is described by the hash of the script and the environment of the job.
+    pub fn provenance(&self, name: &str, sha256: &str) -> serde_json::Value {
+        let materials = self.sources
+            .iter()
+            .map(|source| serde_json::json!({
+                "uri": source.url,
+                "digest": { source.hash_type.clone(): source.hash },
+            }))
+            .chain(std::iter::once({
+                let digest = self.image_digest
+                    .as_deref()
+                    .map(|d| d.trim_start_matches("sha256:"))
+                    .map(|d| serde_json::json!({ "sha256": d }))
+                    .unwrap_or_else(|| serde_json::json!({}));
+                serde_json::json!({ "uri": format!("docker-image://{}", self.image_name), "digest": digest })
+            }))
+            .chain(std::iter::once(serde_json::json!({
+                "uri": "git+repository",
+                "digest": { "sha1": self.git_hash },
+            })))
+            .collect::<Vec<_>>();
+
+        serde_json::json!({
+            "_type": "https://in-toto.io/Statement/v0.1",
+            "subject": [{ "name": name, "digest": { "sha256": sha256 } }],
+            "predicateType": "https://slsa.dev/provenance/v0.2",
+            "predicate": {
+                "builder": { "id": format!("butido/{}@{}", env!("CARGO_PKG_VERSION"), self.endpoint) },
+                "buildType": "https://github.com/science-computing/butido/job@v1",
+                "invocation": {
+                    "configSource": {
+                        "uri": "git+repository",
+                        "digest": { "sha1": self.git_hash },
+                        "entryPoint": format!("{} {}", self.package_name, self.package_version),
+                    },
+                    "parameters": { "script_sha256": self.script_hash },
+                    "environment": self.env,
+                },
+                "metadata": {
+                    "buildInvocationId": self.job_uuid.to_string(),
+                    "buildStartedOn": self.started,
+                    "buildFinishedOn": self.finished,
+                    "completeness": { "parameters": true, "environment": true, "materials": false },
+                    "reproducible": false,
+                },
+                "materials": materials,
+            },
+        })
+    }
+
+    /// Write the provenance attestation for the artifact at `artifact`, which is listed under
+    /// `name` in the attestation
+    pub async fn write_provenance_for(&self, artifact: &Path, name: &str) -> Result<()> {
+        let sha256 = crate::filestore::ObjectStore::hash_file(artifact).await?;
+        let path = provenance_path_for(artifact);
+        let content = serde_json::to_string_pretty(&self.provenance(name, &sha256))?;
+        tokio::fs::write(&path, content)
+            .await
+            .with_context(|| anyhow!("Writing provenance file {}", path.display()))
+            .map_err(Error::from)
+    }
+}
+
+/// Sign the provenance attestation of the artifact at `artifact` with `command`
+///
+/// The attestation is passed to the command on stdin, the signature is read from its stdout.
+pub async fn sign_provenance(artifact: &Path, command: &[String]) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let (program, args) = command.split_first()
+        .ok_or_else(|| anyhow!("Signing command is empty"))?;
+    let (provenance, signature) = (provenance_path_for(artifact), signature_path_for(artifact));
+    let content = tokio::fs::read(&provenance)
+        .await
+        .with_context(|| anyhow!("Reading {}", provenance.display()))?;
+
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .with_context(|| anyhow!("Spawning signing command {}", program))?;
+    {
+        let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("No stdin for signing command"))?;
+        stdin.write_all(&content).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow!("Signing command {} failed for {}: {}", program, provenance.display(), output.status))
+    }
+
+    tokio::fs::write(&signature, output.stdout)
+        .await
+        .with_context(|| anyhow!("Writing signature {}", signature.display()))
+        .map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meta_path() {
+        let p = meta_path_for(Path::new("x86_64/a-1.0.tar.gz"));
+        assert_eq!(p, PathBuf::from("x86_64/a-1.0.tar.gz.meta.json"));
+        assert!(is_sidecar_path(&p));
+        assert!(is_sidecar_path(&signature_path_for(Path::new("x86_64/a-1.0.tar.gz"))));
+        assert!(!is_sidecar_path(Path::new("x86_64/a-1.0.tar.gz")));
+        assert!(is_sidecar_path(Path::new("/staging/submit/butido-manifest.json")));
+    }
+
+    #[test]
+    fn test_provenance() {
+        let meta = ArtifactMetadata {
+            package_name: String::from("a"),
+            package_version: String::from("1.0"),
+            submit_uuid: Uuid::new_v4(),
+            job_uuid: Uuid::new_v4(),
+            image_name: String::from("debian:bullseye"),
+            endpoint: String::from("ep"),
+            image_digest: Some(String::from("sha256:abc")),
+            git_hash: String::from("0123"),
+            script_hash: String::from("4567"),
+            env: BTreeMap::new(),
+            sources: vec![SourceMaterial {
+                url: String::from("https://example.com/a.tar.gz"),
+                hash_type: String::from("sha1"),
+                hash: String::from("89ab"),
+            }],
+            phases: vec![],
+            started: String::new(),
+            finished: String::new(),
+            compression: None,
+        };
+
+        let p = meta.provenance("a-1.0.tar.gz", "cdef");
+        assert_eq!(p["subject"][0]["name"], "a-1.0.tar.gz");
+        assert_eq!(p["subject"][0]["digest"]["sha256"], "cdef");
+
+        let materials = p["predicate"]["materials"].as_array().unwrap();
+        assert_eq!(materials.len(), 3);
+        assert_eq!(materials[0]["digest"]["sha1"], "89ab");
+        assert_eq!(materials[1]["digest"]["sha256"], "abc");
+        assert_eq!(materials[2]["digest"]["sha1"], "0123");
+        assert_eq!(p["predicate"]["invocation"]["parameters"]["script_sha256"], "4567");
+    }
+}
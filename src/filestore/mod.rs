@@ -8,9 +8,30 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+mod compression;
+pub use compression::*;
+
+mod external;
+pub use external::*;
+
+mod manifest;
+pub use manifest::*;
+
+mod merged;
+pub use merged::*;
+
+mod meta;
+pub use meta::*;
+
+mod objects;
+pub use objects::*;
+
 mod release;
 pub use release::*;
 
+mod remote;
+pub use remote::*;
+
 mod staging;
 pub use staging::*;
 
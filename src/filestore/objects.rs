@@ -0,0 +1,259 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Content-addressed storage for artifacts
+//!
+//! Artifacts are stored in an object directory, named by the sha256 hash of their content. The
+//! artifact paths in the stores are hardlinks to these objects, so identical artifacts that are
+//! produced by multiple submits are only stored once and releasing an artifact only creates
+//! another link.
+//!
+//! The object directory is shared by all stores in a directory (all staging stores or all release
+//! stores), because hardlinks only work within one filesystem.
+
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use log::debug;
+use log::trace;
+use log::warn;
+
+/// The name of the object directory
+pub const OBJECTS_DIR_NAME: &str = ".objects";
+
+#[derive(Clone, Debug)]
+pub struct ObjectStore {
+    root: PathBuf,
+}
+
+impl ObjectStore {
+    /// The object store shared by all stores located in `dir`
+    pub fn in_directory(dir: &Path) -> Self {
+        ObjectStore { root: dir.join(OBJECTS_DIR_NAME) }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub(in crate::filestore) fn object_path(&self, hash: &str) -> PathBuf {
+        self.root.join("sha256").join(hash)
+    }
+
+    /// Compute the sha256 hash of the file at `path`, as it is used to name objects
+    pub async fn hash_file(path: &Path) -> Result<String> {
+        use sha2::Digest;
+        use tokio::io::AsyncReadExt;
+
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| anyhow!("Opening file: {}", path.display()))?;
+
+        let mut buffer = vec![0; 64 * 1024];
+        let mut hasher = sha2::Sha256::new();
+        loop {
+            let count = file.read(&mut buffer).await?;
+            if count == 0 {
+                break;
+            }
+            hasher.update(&buffer[..count]);
+        }
+
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Like `hash_file()`, but blocking
+    pub fn hash_file_blocking(path: &Path) -> Result<String> {
+        use sha2::Digest;
+
+        let mut file = std::fs::File::open(path).with_context(|| anyhow!("Opening file: {}", path.display()))?;
+        let mut hasher = sha2::Sha256::new();
+        std::io::copy(&mut file, &mut hasher).with_context(|| anyhow!("Reading file: {}", path.display()))?;
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Store the file at `path` in the object store
+    ///
+    /// If an object with the same content exists already, `path` is replaced by a link to it.
+    /// Deduplication is best effort: If the file cannot be linked (e.g. because it is on another
+    /// filesystem than the object store), it is left as it is.
+    pub async fn insert(&self, path: &Path) -> Result<()> {
+        self.insert_if(path, |_| true).await
+    }
+
+    /// Like `insert()`, but `path` is only replaced by a link to an existing object if `accept`
+    /// returns true for the metadata of that object
+    pub async fn insert_if<F>(&self, path: &Path, accept: F) -> Result<()>
+    where
+        F: Fn(&std::fs::Metadata) -> bool,
+    {
+        let object = self.object_path(&Self::hash_file(path).await?);
+        if let Some(dir) = object.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .with_context(|| anyhow!("Creating directory: {}", dir.display()))?;
+        }
+
+        trace!("Linking {} to {}", path.display(), object.display());
+        match tokio::fs::hard_link(path, &object).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                let (obj_meta, path_meta) = (tokio::fs::metadata(&object).await?, tokio::fs::metadata(path).await?);
+                if obj_meta.dev() == path_meta.dev() && obj_meta.ino() == path_meta.ino() {
+                    trace!("{} is already linked to {}", path.display(), object.display());
+                    return Ok(())
+                }
+
+                if !accept(&obj_meta) {
+                    debug!("Not linking {} to {}, the object is not accepted", path.display(), object.display());
+                    return Ok(())
+                }
+
+                // Link to a temporary path first and move it over `path`, so that `path` exists
+                // at all times
+                let tmp = path.with_file_name({
+                    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                    format!(".{}.link", name)
+                });
+                debug!("Replacing {} with link to {}", path.display(), object.display());
+                tokio::fs::hard_link(&object, &tmp)
+                    .await
+                    .with_context(|| anyhow!("Linking {} to {}", object.display(), tmp.display()))?;
+                tokio::fs::rename(&tmp, path)
+                    .await
+                    .with_context(|| anyhow!("Moving {} to {}", tmp.display(), path.display()))
+                    .map_err(anyhow::Error::from)
+            },
+            Err(e) => {
+                warn!("Cannot link {} into object store {}: {}", path.display(), self.root.display(), e);
+                Ok(())
+            },
+        }
+    }
+
+    /// Link the file `src` to `dest` and store `dest` in the object store
+    ///
+    /// If `src` cannot be linked, e.g. because it is on another filesystem, it is copied.
+    pub async fn link_from(&self, src: &Path, dest: &Path) -> Result<()> {
+        if let Err(e) = tokio::fs::hard_link(src, dest).await {
+            debug!("Cannot link {} to {}, copying: {}", src.display(), dest.display(), e);
+            tokio::fs::copy(src, dest)
+                .await
+                .with_context(|| anyhow!("Copying {} to {}", src.display(), dest.display()))?;
+        }
+
+        self.insert(dest).await
+    }
+
+    /// Remove the file at `path` and its object, if no other file links to the object anymore
+    pub async fn remove(&self, path: &Path) -> Result<()> {
+        let object = self.object_path(&Self::hash_file(path).await?);
+        tokio::fs::remove_file(path)
+            .await
+            .with_context(|| anyhow!("Removing file: {}", path.display()))?;
+
+        match tokio::fs::metadata(&object).await {
+            Ok(meta) if meta.nlink() == 1 => {
+                trace!("Removing unreferenced object: {}", object.display());
+                tokio::fs::remove_file(&object)
+                    .await
+                    .with_context(|| anyhow!("Removing object: {}", object.display()))
+                    .map_err(anyhow::Error::from)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove all objects that no file links to anymore
+    ///
+    /// # Returns
+    ///
+    /// The number of removed objects
+    pub async fn prune(&self) -> Result<usize> {
+        let dir = self.root.join("sha256");
+        if !dir.is_dir() {
+            return Ok(0)
+        }
+
+        let mut removed = 0;
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.metadata().await?.nlink() == 1 {
+                trace!("Removing unreferenced object: {}", entry.path().display());
+                tokio::fs::remove_file(entry.path()).await?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_files_are_deduplicated() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        std::fs::create_dir_all(dir.join("b")).unwrap();
+        let (a, b) = (dir.join("a/pkg.tar"), dir.join("b/pkg.tar"));
+        std::fs::write(&a, b"artifact").unwrap();
+        std::fs::write(&b, b"artifact").unwrap();
+
+        let store = ObjectStore::in_directory(dir);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            store.insert(&a).await.unwrap();
+            store.insert(&b).await.unwrap();
+            // inserting again is a no-op
+            store.insert(&b).await.unwrap();
+        });
+
+        let (meta_a, meta_b) = (std::fs::metadata(&a).unwrap(), std::fs::metadata(&b).unwrap());
+        assert_eq!(meta_a.ino(), meta_b.ino());
+        assert_eq!(meta_a.nlink(), 3);
+        assert_eq!(std::fs::read(&b).unwrap(), b"artifact");
+
+        rt.block_on(async {
+            store.remove(&a).await.unwrap();
+            assert_eq!(store.prune().await.unwrap(), 0);
+            store.remove(&b).await.unwrap();
+            assert_eq!(store.prune().await.unwrap(), 0);
+        });
+        assert_eq!(std::fs::read_dir(dir.join(OBJECTS_DIR_NAME).join("sha256")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_rejected_objects_are_not_linked() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let (a, b) = (dir.join("a.tar"), dir.join("b.tar"));
+        std::fs::write(&a, b"artifact").unwrap();
+        std::fs::write(&b, b"artifact").unwrap();
+
+        let store = ObjectStore::in_directory(dir);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            store.insert(&a).await.unwrap();
+            store.insert_if(&b, |_| false).await.unwrap();
+        });
+
+        assert_ne!(std::fs::metadata(&a).unwrap().ino(), std::fs::metadata(&b).unwrap().ino());
+        assert_eq!(std::fs::metadata(&a).unwrap().nlink(), 2);
+        assert_eq!(std::fs::metadata(&b).unwrap().nlink(), 1);
+    }
+}
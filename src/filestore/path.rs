@@ -44,11 +44,11 @@ impl StoreRoot {
         }
     }
 
-    pub fn join<'a>(&'a self, ap: &'a ArtifactPath) -> Result<Option<FullArtifactPath<'a>>> {
+    pub fn join<'a>(&'a self, ap: &ArtifactPath) -> Result<Option<FullArtifactPath<'a>>> {
         let join = self.0.join(&ap.0);
 
         if join.is_file() {
-            Ok(Some(FullArtifactPath(self, ap)))
+            Ok(Some(FullArtifactPath(self, ap.clone())))
         } else if join.is_dir() {
             Err(anyhow!("Cannot load non-file path: {}", join.display()))
         } else {
@@ -56,6 +56,18 @@ impl StoreRoot {
         }
     }
 
+    /// The directory this store is located in
+    ///
+    /// Falls back to the store root itself, if it has no parent.
+    pub(in crate::filestore) fn parent(&self) -> &Path {
+        self.0.parent().unwrap_or(&self.0)
+    }
+
+    /// The path of `ap` in this store, whether it exists or not
+    pub(in crate::filestore) fn path_of(&self, ap: &ArtifactPath) -> PathBuf {
+        self.0.join(&ap.0)
+    }
+
     pub(in crate::filestore) fn is_dir(&self, subpath: &Path) -> bool {
         self.0.join(subpath).is_dir()
     }
@@ -77,6 +89,7 @@ impl StoreRoot {
                 log::trace!("{:?} is file = {}", e, is_file);
                 is_file
             })
+            .filter_ok(|e| !crate::filestore::is_sidecar_path(e.path()))
             .inspect(|p| log::trace!("Loading Artifact from path: {:?}", p))
             .map_err(Error::from)
             .and_then_ok(move |de| {
@@ -94,6 +107,11 @@ impl StoreRoot {
     /// `self` and returns the written pathes.
     ///
     /// The function filteres out the "/output" directory (that's what is meant by "butido-style").
+    ///
+    /// The files belong to the user butido runs as, not to the user that owned them in the
+    /// container, so they are made readable and writable for their owner. Otherwise an
+    /// unprivileged butido (e.g. with a rootless daemon) could not release or remove artifacts the
+    /// build created with restrictive permissions.
     pub(in crate::filestore) fn unpack_archive_here<R>(&self, mut ar: tar::Archive<R>) -> Result<Vec<PathBuf>>
     where
         R: std::io::Read,
@@ -121,14 +139,29 @@ impl StoreRoot {
                 let unpack_dest = self.0.join(&path);
                 log::trace!("Unpack to = '{:?}'", unpack_dest);
 
-                entry.unpack(unpack_dest)
-                    .map(|_| path)
-                    .map_err(Error::from)
+                entry.unpack(&unpack_dest)?;
+                ensure_owner_access(&unpack_dest)?;
+                Ok(path)
             })
             .collect::<Result<Vec<_>>>()
     }
 }
 
+/// Make the file at `path` readable and writable for its owner
+fn ensure_owner_access(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = std::fs::metadata(path)
+        .with_context(|| anyhow!("Getting permissions of {}", path.display()))?
+        .permissions();
+    if permissions.mode() & 0o600 != 0o600 {
+        permissions.set_mode(permissions.mode() | 0o600);
+        std::fs::set_permissions(path, permissions)
+            .with_context(|| anyhow!("Setting permissions of {}", path.display()))?;
+    }
+    Ok(())
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ArtifactPath(PathBuf);
 
@@ -171,32 +204,24 @@ impl AsRef<Path> for ArtifactPath {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct FullArtifactPath<'a>(&'a StoreRoot, &'a ArtifactPath);
+pub struct FullArtifactPath<'a>(&'a StoreRoot, ArtifactPath);
 
 impl<'a> FullArtifactPath<'a> {
 
     pub fn is_in_staging_store(&self, store: &StagingStore) -> bool {
-        store.0.root_path() == self.0
+        store.store.root_path() == self.0
     }
 
     pub fn artifact_path(&self) -> &ArtifactPath {
-        self.1
+        &self.1
     }
 
     pub fn joined(&self) -> PathBuf {
         self.0 .0.join(&self.1 .0)
     }
 
-    pub fn display(&self) -> FullArtifactPathDisplay<'a> {
-        FullArtifactPathDisplay(self.0, self.1)
-    }
-
-    pub async fn read(self) -> Result<Vec<u8>> {
-        tokio::fs::read(self.joined())
-            .await
-            .map(Vec::from)
-            .with_context(|| anyhow!("Reading artifact from path {}", self.0.display()))
-            .map_err(Error::from)
+    pub fn display(&self) -> FullArtifactPathDisplay<'_> {
+        FullArtifactPathDisplay(self.0, &self.1)
     }
 }
 
@@ -31,11 +31,26 @@ impl ReleaseStore {
         FileStoreImpl::load(root, progress).map(ReleaseStore)
     }
 
+    /// Open the store without loading it, see `FileStoreImpl::open()`
+    pub fn open(root: StoreRoot) -> Self {
+        ReleaseStore(FileStoreImpl::open(root))
+    }
+
     pub fn root_path(&self) -> &StoreRoot {
         self.0.root_path()
     }
 
-    pub fn get(&self, p: &ArtifactPath) -> Option<&ArtifactPath> {
+    pub fn get<'a>(&'a self, p: &'a ArtifactPath) -> Option<&'a ArtifactPath> {
         self.0.get(p)
     }
+
+    /// The artifact linked to the object with the sha256 hash `hash`, see `FileStoreImpl::find_by_hash()`
+    pub fn find_by_hash(&self, hash: &str) -> Result<Option<&ArtifactPath>> {
+        self.0.find_by_hash(hash)
+    }
+
+    /// Iterate over all artifacts in the store
+    pub fn artifacts(&self) -> Result<impl Iterator<Item = &ArtifactPath>> {
+        self.0.artifacts()
+    }
 }
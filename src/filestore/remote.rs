@@ -0,0 +1,364 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Mirroring the artifacts of a release store to a remote
+//!
+//! The files are pushed one after another with the `rsync` or `sftp` command or with HTTP PUT
+//! requests. Afterwards, a verification pass checks that the remote has the same files: rsync
+//! compares checksums, sftp and HTTP remotes can only be asked for the sizes of the files.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use indicatif::ProgressBar;
+use log::debug;
+use log::trace;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::ReleaseRemote;
+use crate::config::RemoteKind;
+use crate::filestore::sidecar_paths_for;
+use crate::util::progress::TransferBar;
+use crate::util::progress::TransferBars;
+
+/// Push files of the release store at `root` to a remote
+pub struct RemotePush<'a> {
+    name: &'a str,
+    remote: &'a ReleaseRemote,
+    root: &'a Path,
+}
+
+impl<'a> RemotePush<'a> {
+    pub fn new(name: &'a str, remote: &'a ReleaseRemote, root: &'a Path) -> Self {
+        RemotePush { name, remote, root }
+    }
+
+    /// The `artifacts` (relative to the root of the store) and the files next to them that exist,
+    /// which are pushed as well
+    pub fn files_with_sidecars(&self, artifacts: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+        artifacts
+            .into_iter()
+            .flat_map(|artifact| {
+                let sidecars = sidecar_paths_for(&artifact)
+                    .into_iter()
+                    .filter(|sidecar| self.root.join(sidecar).is_file())
+                    .collect::<Vec<_>>();
+                std::iter::once(artifact).chain(sidecars)
+            })
+            .collect()
+    }
+
+    /// Push the `files` (relative to the root of the store), with a bar for each file on
+    /// `transfers`
+    ///
+    /// Only HTTP uploads are counted while they run, files pushed with rsync and sftp are counted
+    /// once they are pushed completely.
+    pub async fn push(&self, files: &[PathBuf], transfers: &TransferBars) -> Result<()> {
+        transfers.total().set_message(format!("Pushing {} files to {}", files.len(), self.name));
+        for file in files {
+            let bar = transfers.transfer(format!("Pushing {} to {}", file.display(), self.name))?;
+            self.push_file(file, &bar)
+                .await
+                .with_context(|| anyhow!("Pushing {} to remote '{}'", file.display(), self.name))?;
+            bar.finish();
+        }
+        transfers.total().finish_with_message(format!("Pushed {} files to {}", files.len(), self.name));
+        Ok(())
+    }
+
+    /// Check that the remote has the same `files` as the store, returning the ones that differ or
+    /// are missing
+    pub async fn verify(&self, files: &[PathBuf], bar: &ProgressBar) -> Result<Vec<PathBuf>> {
+        bar.set_length(files.len() as u64);
+        bar.set_position(0);
+        bar.set_message(format!("Verifying files on {}", self.name));
+
+        let differing = match self.remote.kind() {
+            // rsync compares all files at once
+            RemoteKind::Rsync => {
+                let differing = self.rsync_differing(files).await?;
+                bar.inc(files.len() as u64);
+                differing
+            },
+            RemoteKind::Sftp | RemoteKind::Http => {
+                let mut differing = vec![];
+                for file in files {
+                    let local_size = tokio::fs::metadata(self.root.join(file))
+                        .await
+                        .with_context(|| anyhow!("Getting size of {}", file.display()))?
+                        .len();
+                    let remote_size = self.remote_size(file).await?;
+                    trace!("Size of {}: {} local, {:?} on {}", file.display(), local_size, remote_size, self.name);
+                    if remote_size != Some(local_size) {
+                        differing.push(file.clone());
+                    }
+                    bar.inc(1);
+                }
+                differing
+            },
+        };
+
+        if differing.is_empty() {
+            bar.finish_with_message(format!("Verified {} files on {}", files.len(), self.name));
+        } else {
+            bar.finish_with_message(format!("{} of {} files differ on {}", differing.len(), files.len(), self.name));
+        }
+        Ok(differing)
+    }
+
+    async fn push_file(&self, file: &Path, bar: &TransferBar) -> Result<()> {
+        let path = self.root.join(file);
+        let size = tokio::fs::metadata(&path)
+            .await
+            .with_context(|| anyhow!("Getting size of {}", path.display()))?
+            .len();
+        bar.inc_length(size);
+
+        match self.remote.kind() {
+            RemoteKind::Rsync => {
+                // "./" marks where the path that is recreated on the remote starts
+                let relative = Path::new(".").join(file);
+                run(tokio::process::Command::new("rsync")
+                    .current_dir(self.root)
+                    .arg("--relative")
+                    .arg("--times")
+                    .arg("--checksum")
+                    .arg("--")
+                    .arg(relative)
+                    .arg(rsync_target(self.remote.target())), None)
+                    .await
+                    .map(|_| bar.inc(size))
+            },
+
+            RemoteKind::Sftp => {
+                let (host, base) = sftp_target(self.remote.target());
+                let script = sftp_put_script(&path, base, file);
+                trace!("sftp batch for {}: {}", host, script);
+                run(tokio::process::Command::new("sftp").arg("-b").arg("-").arg(host), Some(script))
+                    .await
+                    .map(|_| bar.inc(size))
+            },
+
+            RemoteKind::Http => {
+                use futures::StreamExt;
+
+                let handle = tokio::fs::File::open(&path)
+                    .await
+                    .with_context(|| anyhow!("Opening file: {}", path.display()))?;
+                let counting = bar.clone();
+                let stream = tokio_util::io::ReaderStream::new(handle)
+                    .inspect(move |chunk| if let Ok(chunk) = chunk {
+                        counting.inc(chunk.len() as u64);
+                    });
+                let body = reqwest::Body::wrap_stream(stream);
+
+                let url = remote_path(self.remote.target(), file);
+                trace!("PUT {}", url);
+                self.http_request(reqwest::Method::PUT, &url)?
+                    .header(reqwest::header::CONTENT_LENGTH, size)
+                    .body(body)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .with_context(|| anyhow!("Uploading to '{}'", url))
+                    .map(|_| ())
+            },
+        }
+    }
+
+    /// The files that rsync would transfer, because they are missing or differ on the remote
+    async fn rsync_differing(&self, files: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let list = files.iter()
+            .map(|f| f.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let output = run(tokio::process::Command::new("rsync")
+            .current_dir(self.root)
+            .arg("--files-from=-")
+            .arg("--checksum")
+            .arg("--dry-run")
+            .arg("--itemize-changes")
+            .arg(".")
+            .arg(rsync_target(self.remote.target())), Some(list))
+            .await
+            .with_context(|| anyhow!("Comparing files with remote '{}'", self.name))?;
+
+        Ok(parse_rsync_itemized(&output))
+    }
+
+    /// The size of `file` on the remote, `None` if it is missing
+    async fn remote_size(&self, file: &Path) -> Result<Option<u64>> {
+        match self.remote.kind() {
+            RemoteKind::Rsync => unreachable!("rsync remotes are verified with checksums"),
+            RemoteKind::Sftp => {
+                let (host, base) = sftp_target(self.remote.target());
+                let script = format!("ls -ln {}\n", sftp_quote(&remote_path(base, file)));
+                match run(tokio::process::Command::new("sftp").arg("-b").arg("-").arg(host), Some(script)).await {
+                    Ok(output) => Ok(parse_sftp_ls_size(&output)),
+                    Err(e) => {
+                        debug!("{} is missing on {}: {:#}", file.display(), self.name, e);
+                        Ok(None)
+                    },
+                }
+            },
+            RemoteKind::Http => {
+                let url = remote_path(self.remote.target(), file);
+                let response = self.http_request(reqwest::Method::HEAD, &url)?
+                    .send()
+                    .await
+                    .with_context(|| anyhow!("Requesting '{}'", url))?;
+
+                if response.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Ok(None)
+                }
+                response.error_for_status_ref().with_context(|| anyhow!("Requesting '{}'", url))?;
+
+                // The body of a HEAD response is empty, so the length comes from the header
+                Ok(response.headers()
+                    .get(reqwest::header::CONTENT_LENGTH)
+                    .and_then(|len| len.to_str().ok())
+                    .and_then(|len| len.parse::<u64>().ok()))
+            },
+        }
+    }
+
+    fn http_request(&self, method: reqwest::Method, url: &str) -> Result<reqwest::RequestBuilder> {
+        let request = reqwest::Client::new().request(method, url);
+        match self.remote.authorization_env() {
+            Some(var) => {
+                let value = std::env::var(var).with_context(|| anyhow!("Reading authorization for remote '{}' from ${}", self.name, var))?;
+                Ok(request.header(reqwest::header::AUTHORIZATION, value))
+            },
+            None => Ok(request),
+        }
+    }
+}
+
+/// Run `command` with `input` on stdin, returning its stdout
+async fn run(command: &mut tokio::process::Command, input: Option<String>) -> Result<String> {
+    trace!("Running {:?}", command);
+    let mut child = command
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| anyhow!("Starting {:?}", command.as_std().get_program()))?;
+
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes()).await?;
+        // stdin is closed when dropped, so the command knows the input is complete
+    }
+
+    let output = child.wait_with_output().await?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(anyhow!("{:?} failed with {}: {}",
+            command.as_std().get_program(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+/// The rsync destination, with a trailing slash so that the files end up below it
+fn rsync_target(target: &str) -> String {
+    format!("{}/", target.trim_end_matches('/'))
+}
+
+/// Split an sftp target "user@host:/path" into the host and the base path on it
+fn sftp_target(target: &str) -> (&str, &str) {
+    target.split_once(':').unwrap_or((target, "."))
+}
+
+/// The path of `file` below `base` on the remote, which is a base URL for HTTP remotes
+fn remote_path(base: &str, file: &Path) -> String {
+    format!("{}/{}", base.trim_end_matches('/'), file.display())
+}
+
+fn sftp_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The sftp batch that creates the directories of `file` below `base` and uploads `local` there
+///
+/// The directories might exist already, so errors of "mkdir" are ignored with "-".
+fn sftp_put_script(local: &Path, base: &str, file: &Path) -> String {
+    let mut script = String::new();
+    let mut dir = PathBuf::new();
+    for component in file.parent().into_iter().flat_map(Path::components) {
+        dir.push(component);
+        script.push_str(&format!("-mkdir {}\n", sftp_quote(&remote_path(base, &dir))));
+    }
+    script.push_str(&format!("put {} {}\n", sftp_quote(&local.display().to_string()), sftp_quote(&remote_path(base, file))));
+    script
+}
+
+/// The files in the output of "rsync --itemize-changes", e.g. ">f.st...... a/b.pkg"
+///
+/// Directories and other items that are not regular files are skipped.
+fn parse_rsync_itemized(output: &str) -> Vec<PathBuf> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter(|(flags, _)| flags.chars().nth(1) == Some('f'))
+        .map(|(_, path)| PathBuf::from(path))
+        .collect()
+}
+
+/// The size of the file in the output of an sftp batch that ran "ls -ln"
+///
+/// sftp echoes the commands of the batch with a "sftp>" prompt, so these lines are skipped.
+fn parse_sftp_ls_size(output: &str) -> Option<u64> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with("sftp>"))
+        .find_map(|line| line.split_whitespace().nth(4))
+        .and_then(|size| size.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_targets() {
+        assert_eq!(rsync_target("host:/srv/packages/"), "host:/srv/packages/");
+        assert_eq!(rsync_target("rsync://host/module"), "rsync://host/module/");
+        assert_eq!(sftp_target("deploy@host:/srv/packages"), ("deploy@host", "/srv/packages"));
+        assert_eq!(sftp_target("deploy@host"), ("deploy@host", "."));
+        assert_eq!(remote_path("https://example.com/packages/", Path::new("a/b.pkg")), "https://example.com/packages/a/b.pkg");
+    }
+
+    #[test]
+    fn test_sftp_put_script() {
+        let script = sftp_put_script(Path::new("/releases/default/a/b.pkg"), "/srv", Path::new("a/b.pkg"));
+        assert_eq!(script, "-mkdir \"/srv/a\"\nput \"/releases/default/a/b.pkg\" \"/srv/a/b.pkg\"\n");
+        assert_eq!(sftp_quote("a \"b\""), "\"a \\\"b\\\"\"");
+    }
+
+    #[test]
+    fn test_parse_rsync_itemized() {
+        let output = "cd+++++++++ a/\n>f+++++++++ a/b.pkg\n>fc.t...... c.pkg\n";
+        assert_eq!(parse_rsync_itemized(output), vec![PathBuf::from("a/b.pkg"), PathBuf::from("c.pkg")]);
+        assert!(parse_rsync_itemized("").is_empty());
+    }
+
+    #[test]
+    fn test_parse_sftp_ls_size() {
+        let output = "sftp> ls -ln \"/srv/a/b.pkg\"\n-rw-r--r--    1 1000     1000         1234 Jan  1 12:00 /srv/a/b.pkg\n";
+        assert_eq!(parse_sftp_ls_size(output), Some(1234));
+        assert_eq!(parse_sftp_ls_size("sftp> ls -ln \"/srv/a/b.pkg\"\n"), None);
+    }
+}
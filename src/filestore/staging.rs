@@ -8,10 +8,10 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::HashMap;
 use std::fmt::Debug;
 
 use anyhow::Context;
-use anyhow::Error;
 use anyhow::Result;
 use anyhow::anyhow;
 use futures::stream::Stream;
@@ -19,25 +19,97 @@ use indicatif::ProgressBar;
 use log::trace;
 use result_inspect::ResultInspect;
 
+use crate::config::ArtifactCompression;
+use crate::config::ArtifactOwnership;
+use crate::filestore::compress_artifact;
 use crate::filestore::path::ArtifactPath;
 use crate::filestore::path::StoreRoot;
 use crate::filestore::util::FileStoreImpl;
+use crate::filestore::CompressionInfo;
 
-pub struct StagingStore(pub(in crate::filestore) FileStoreImpl);
+pub struct StagingStore {
+    pub(in crate::filestore) store: FileStoreImpl,
+
+    /// How the tarballs written from the jobs are compressed
+    compression: Option<ArtifactCompression>,
+
+    /// The artifacts that were compressed when they were written to the store
+    compressed: HashMap<ArtifactPath, CompressionInfo>,
+
+    /// The owner and permissions the artifacts written from the jobs get
+    ownership: Option<ArtifactOwnership>,
+}
+
+/// Whether the file with the metadata `meta` has the configured owner and permissions
+fn has_ownership(meta: &std::fs::Metadata, ownership: &ArtifactOwnership, mode: Option<u32>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    ownership.uid().map(|uid| meta.uid() == uid).unwrap_or(true)
+        && ownership.gid().map(|gid| meta.gid() == gid).unwrap_or(true)
+        && mode.map(|mode| meta.mode() & 0o7777 == mode).unwrap_or(true)
+}
+
+/// Change the owner and permissions of the file at `path` to the configured ones
+fn apply_ownership(path: &std::path::Path, ownership: &ArtifactOwnership) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if ownership.uid().is_some() || ownership.gid().is_some() {
+        let (uid, gid) = (ownership.uid().map(nix::unistd::Uid::from_raw), ownership.gid().map(nix::unistd::Gid::from_raw));
+        nix::unistd::chown(path, uid, gid)
+            .with_context(|| anyhow!("Changing owner of {} to {:?}:{:?}", path.display(), ownership.uid(), ownership.gid()))?;
+    }
+    if let Some(mode) = ownership.mode()? {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .with_context(|| anyhow!("Setting permissions of {} to {:o}", path.display(), mode))?;
+    }
+    Ok(())
+}
 
 impl Debug for StagingStore {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        write!(f, "StagingStore(root: {})", self.0.root_path().display())
+        write!(f, "StagingStore(root: {})", self.store.root_path().display())
     }
 }
 
 impl StagingStore {
     pub fn load(root: StoreRoot, progress: &ProgressBar) -> Result<Self> {
-        FileStoreImpl::load(root, progress).map(StagingStore)
+        FileStoreImpl::load(root, progress).map(|store| StagingStore {
+            store,
+            compression: None,
+            compressed: HashMap::new(),
+            ownership: None,
+        })
+    }
+
+    /// Open the store without loading it, see `FileStoreImpl::open()`
+    pub fn open(root: StoreRoot) -> Self {
+        StagingStore {
+            store: FileStoreImpl::open(root),
+            compression: None,
+            compressed: HashMap::new(),
+            ownership: None,
+        }
+    }
+
+    /// Compress the tarballs that are written to the store from now on with `compression`
+    pub fn set_compression(&mut self, compression: Option<ArtifactCompression>) {
+        self.compression = compression;
+    }
+
+    /// Set the owner and permissions of the artifacts that are written to the store from now on
+    pub fn set_ownership(&mut self, ownership: Option<ArtifactOwnership>) {
+        self.ownership = ownership;
+    }
+
+    /// How the artifact `p` was compressed when it was written to the store, if it was
+    pub fn compression_of(&self, p: &ArtifactPath) -> Option<&CompressionInfo> {
+        self.compressed.get(p)
     }
 
     /// Write the passed tar stream to the file store
     ///
+    /// The archive is unpacked while it is received, so it is never completely in memory.
+    ///
     /// # Returns
     ///
     /// Returns a list of Artifacts that were written from the stream
@@ -45,23 +117,47 @@ impl StagingStore {
     where
         S: Stream<Item = Result<Vec<u8>>>,
     {
-        use futures::stream::TryStreamExt;
+        use futures::stream::StreamExt;
+
+        let dest = self.store.root_path();
+        let compression = self.compression.as_ref();
+        let reader = tokio_util::io::StreamReader::new({
+            stream.map(|chunk| {
+                chunk
+                    .map(std::io::Cursor::new)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+            })
+        });
+        let reader = tokio_util::io::SyncIoBridge::new(Box::pin(reader));
 
-        let dest = self.0.root_path();
-        stream
-            .try_concat()
-            .await
-            .and_then(|bytes| {
+        // The tar crate reads synchronously, from the stream that is received on this runtime
+        let mut compressed = vec![];
+        let artifacts = tokio::task::block_in_place(|| {
                 trace!("Unpacking archive to {}", dest.display());
-                dest.unpack_archive_here(tar::Archive::new(&bytes[..]))
-                    .context("Unpacking TAR")
-                    .map_err(Error::from)
+                let paths = dest.unpack_archive_here(tar::Archive::new(reader))
+                    .context("Unpacking TAR")?;
+
+                match compression {
+                    None => Ok(paths),
+                    Some(compression) => paths.into_iter()
+                        .map(|path| {
+                            match compress_artifact(&dest.path_of(&ArtifactPath::new(path.clone())?), compression)? {
+                                Some((full, info)) => {
+                                    let path = path.with_file_name(full.file_name().unwrap()); // compressed next to the tarball
+                                    compressed.push((path.clone(), info));
+                                    Ok(path)
+                                },
+                                None => Ok(path),
+                            }
+                        })
+                        .collect::<Result<Vec<_>>>(),
+                }
             })
-            .context("Concatenating the output bytestream")?
+            .context("Unpacking the output bytestream")?
             .into_iter()
             .inspect(|p| trace!("Trying to load into staging store: {}", p.display()))
             .filter_map(|path| {
-                if self.0.root_path().is_dir(&path) {
+                if self.store.root_path().is_dir(&path) {
                     None
                 } else {
                     // Clippy doesn't detect this properly
@@ -69,19 +165,83 @@ impl StagingStore {
                     ArtifactPath::new(path.to_path_buf())
                         .inspect(|r| trace!("Loaded from path {} = {:?}", path.display(), r))
                         .with_context(|| anyhow!("Loading from path: {}", path.display()))
-                        .map(|ap| self.0.load_from_path(&ap).clone())
+                        .map(|ap| self.store.load_from_path(&ap).clone())
                         .map(Some)
                         .transpose()
                 }
             })
-            .collect()
+            .collect::<Result<Vec<ArtifactPath>>>()?;
+
+        for (path, info) in compressed {
+            self.compressed.insert(ArtifactPath::new(path)?, info);
+        }
+
+        for artifact in artifacts.iter() {
+            if let Some(full) = self.store.root_path().join(artifact)? {
+                match self.ownership.as_ref() {
+                    None => self.store.objects().insert(&full.joined()).await?,
+
+                    // The artifact is only linked to an object that has the configured owner and
+                    // permissions already, so changing them afterwards does not change the
+                    // artifacts of other stores that link to the same object
+                    Some(ownership) => {
+                        let mode = ownership.mode()?;
+                        self.store.objects()
+                            .insert_if(&full.joined(), |meta| has_ownership(meta, ownership, mode))
+                            .await?;
+                        apply_ownership(&full.joined(), ownership)?;
+                    },
+                }
+            }
+        }
+
+        Ok(artifacts)
+    }
+
+    /// Add the artifact `artifact` from the store at `other` to this store
+    ///
+    /// The artifact is linked into this store if possible, and copied otherwise, together with
+    /// the files that are written next to it.
+    pub async fn import_from(&mut self, other: &StoreRoot, artifact: &ArtifactPath) -> Result<ArtifactPath> {
+        let (src, dest) = (other.path_of(artifact), self.store.root_path().path_of(artifact));
+        if !dest.exists() {
+            if let Some(dir) = dest.parent() {
+                tokio::fs::create_dir_all(dir)
+                    .await
+                    .with_context(|| anyhow!("Creating directory: {}", dir.display()))?;
+            }
+
+            trace!("Importing {} to {}", src.display(), dest.display());
+            self.store.objects().link_from(&src, &dest).await?;
+
+            let sidecars = crate::filestore::sidecar_paths_for(&src)
+                .into_iter()
+                .zip(crate::filestore::sidecar_paths_for(&dest).into_iter());
+            for (src, dest) in sidecars.filter(|(src, _)| src.exists()) {
+                tokio::fs::copy(&src, &dest)
+                    .await
+                    .with_context(|| anyhow!("Copying {} to {}", src.display(), dest.display()))?;
+            }
+        }
+
+        Ok(self.store.load_from_path(artifact).clone())
     }
 
     pub fn root_path(&self) -> &StoreRoot {
-        self.0.root_path()
+        self.store.root_path()
+    }
+
+    pub fn get<'a>(&'a self, p: &'a ArtifactPath) -> Option<&'a ArtifactPath> {
+        self.store.get(p)
+    }
+
+    /// The artifact linked to the object with the sha256 hash `hash`, see `FileStoreImpl::find_by_hash()`
+    pub fn find_by_hash(&self, hash: &str) -> Result<Option<&ArtifactPath>> {
+        self.store.find_by_hash(hash)
     }
 
-    pub fn get(&self, p: &ArtifactPath) -> Option<&ArtifactPath> {
-        self.0.get(p)
+    /// Iterate over all artifacts in the store
+    pub fn artifacts(&self) -> Result<impl Iterator<Item = &ArtifactPath>> {
+        self.store.artifacts()
     }
 }
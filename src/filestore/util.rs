@@ -12,10 +12,13 @@
 //!
 
 use std::collections::HashSet;
+use std::os::unix::fs::MetadataExt;
 
 use anyhow::Result;
 use indicatif::ProgressBar;
+use once_cell::sync::OnceCell;
 
+use crate::filestore::objects::ObjectStore;
 use crate::filestore::path::ArtifactPath;
 use crate::filestore::path::StoreRoot;
 
@@ -29,34 +32,147 @@ use crate::filestore::path::StoreRoot;
 pub struct FileStoreImpl {
     #[getset(get = "pub")]
     root_path: StoreRoot,
-    store: HashSet<ArtifactPath>,
+
+    /// The artifacts in the store
+    ///
+    /// If the store was opened lazily, the store is only indexed when all of its artifacts are
+    /// requested. Until then, artifacts are looked up in the filesystem.
+    store: OnceCell<HashSet<ArtifactPath>>,
+
+    /// The object store the artifacts are linked to, shared with the other stores next to this one
+    #[getset(get = "pub")]
+    objects: ObjectStore,
 }
 
 impl FileStoreImpl {
     /// Loads the passed path recursively
     pub fn load(root_path: StoreRoot, progress: &ProgressBar) -> Result<Self> {
-        let store = root_path
+        let store = Self::index(&root_path, progress)?;
+        let objects = ObjectStore::in_directory(root_path.parent());
+        Ok(FileStoreImpl { root_path, store: OnceCell::from(store), objects })
+    }
+
+    /// Opens the passed path without loading it
+    ///
+    /// Artifacts are looked up in the filesystem when they are requested, which is a lot faster
+    /// than loading big stores if only a few artifacts are needed.
+    pub fn open(root_path: StoreRoot) -> Self {
+        let objects = ObjectStore::in_directory(root_path.parent());
+        FileStoreImpl { root_path, store: OnceCell::new(), objects }
+    }
+
+    fn index(root_path: &StoreRoot, progress: &ProgressBar) -> Result<HashSet<ArtifactPath>> {
+        root_path
             .find_artifacts_recursive()
             .inspect(|path| {
                 log::trace!("Found artifact path: {:?}", path);
                 progress.tick();
             })
-            .collect::<Result<HashSet<ArtifactPath>>>()?;
+            .collect()
+    }
+
+    pub fn get<'a>(&'a self, artifact_path: &'a ArtifactPath) -> Option<&'a ArtifactPath> {
+        match self.store.get() {
+            Some(store) => store.get(artifact_path),
+            None => {
+                let is_artifact = !crate::filestore::is_sidecar_path(artifact_path.as_ref())
+                    && self.root_path.path_of(artifact_path).is_file();
+                log::trace!("Looked up {:?} in {:?}: {}", artifact_path, self.root_path, is_artifact);
+                Some(artifact_path).filter(|_| is_artifact)
+            },
+        }
+    }
 
-        Ok(FileStoreImpl { root_path, store })
+    /// All artifacts in the store, indexing the store if it was opened lazily
+    pub fn artifacts(&self) -> Result<impl Iterator<Item = &ArtifactPath>> {
+        self.store
+            .get_or_try_init(|| Self::index(&self.root_path, &ProgressBar::hidden()))
+            .map(|store| store.iter())
     }
 
-    pub fn get(&self, artifact_path: &ArtifactPath) -> Option<&ArtifactPath> {
-        self.store.get(artifact_path)
+    /// The artifact in the store that is linked to the object with the sha256 hash `hash`
+    ///
+    /// This finds artifacts independent of their path in the store, but indexes the store if it
+    /// was opened lazily. Artifacts that are not linked to the object store are not found.
+    pub fn find_by_hash(&self, hash: &str) -> Result<Option<&ArtifactPath>> {
+        let object = match std::fs::metadata(self.objects.object_path(hash)) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        for artifact in self.artifacts()? {
+            let metadata = std::fs::metadata(self.root_path.path_of(artifact))?;
+            if metadata.dev() == object.dev() && metadata.ino() == object.ino() {
+                return Ok(Some(artifact))
+            }
+        }
+        Ok(None)
     }
 
     pub(in crate::filestore) fn load_from_path<'a>(
         &mut self,
         artifact_path: &'a ArtifactPath,
     ) -> &'a ArtifactPath {
-        if !self.store.contains(artifact_path) {
-            self.store.insert(artifact_path.clone());
+        // A lazily opened store finds the artifact in the filesystem
+        if let Some(store) = self.store.get_mut() {
+            if !store.contains(artifact_path) {
+                store.insert(artifact_path.clone());
+            }
         }
         artifact_path
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_finds_artifacts_like_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("store");
+        std::fs::create_dir_all(root.join("x86_64")).unwrap();
+        std::fs::write(root.join("x86_64/a-1.tar"), b"a").unwrap();
+        std::fs::write(root.join("x86_64/a-1.tar.meta.json"), b"{}").unwrap();
+
+        let artifact = ArtifactPath::new(std::path::PathBuf::from("x86_64/a-1.tar")).unwrap();
+        let sidecar = ArtifactPath::new(std::path::PathBuf::from("x86_64/a-1.tar.meta.json")).unwrap();
+        let missing = ArtifactPath::new(std::path::PathBuf::from("x86_64/b-1.tar")).unwrap();
+
+        let loaded = FileStoreImpl::load(StoreRoot::new(root.clone()).unwrap(), &ProgressBar::hidden()).unwrap();
+        let opened = FileStoreImpl::open(StoreRoot::new(root).unwrap());
+        for store in [&loaded, &opened].iter() {
+            assert_eq!(store.get(&artifact), Some(&artifact));
+            assert_eq!(store.get(&sidecar), None);
+            assert_eq!(store.get(&missing), None);
+        }
+
+        // Opened stores are indexed when all artifacts are requested
+        assert_eq!(opened.artifacts().unwrap().collect::<Vec<_>>(), vec![&artifact]);
+        assert_eq!(opened.get(&artifact), Some(&artifact));
+    }
+
+    #[test]
+    fn test_find_by_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("store");
+        std::fs::create_dir_all(root.join("x86_64")).unwrap();
+        std::fs::write(root.join("x86_64/a-1.tar"), b"a").unwrap();
+        std::fs::write(root.join("x86_64/b-1.tar"), b"b").unwrap();
+
+        let store = FileStoreImpl::open(StoreRoot::new(root.clone()).unwrap());
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let hash = rt.block_on(async {
+            store.objects().insert(&root.join("x86_64/a-1.tar")).await.unwrap();
+            ObjectStore::hash_file(&root.join("x86_64/a-1.tar")).await.unwrap()
+        });
+
+        // The artifact is found after it was moved
+        std::fs::create_dir_all(root.join("noarch")).unwrap();
+        std::fs::rename(root.join("x86_64/a-1.tar"), root.join("noarch/a-1.tar")).unwrap();
+        let moved = ArtifactPath::new(std::path::PathBuf::from("noarch/a-1.tar")).unwrap();
+        assert_eq!(store.find_by_hash(&hash).unwrap(), Some(&moved));
+        assert_eq!(store.find_by_hash(&"0".repeat(64)).unwrap(), None);
+    }
+}
@@ -8,7 +8,12 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+
 use daggy::Dag as DaggyDag;
+use daggy::NodeIndex;
 use daggy::Walker;
 use getset::Getters;
 use uuid::Uuid;
@@ -16,9 +21,11 @@ use uuid::Uuid;
 use crate::job::Job;
 use crate::job::JobResource;
 use crate::package::Package;
+use crate::package::PackageName;
 use crate::package::PhaseName;
 use crate::package::Shebang;
 use crate::util::docker::ImageName;
+use crate::util::EnvironmentVariableName;
 
 #[derive(Debug, Getters)]
 pub struct Dag {
@@ -33,14 +40,22 @@ impl Dag {
         image: ImageName,
         phases: Vec<PhaseName>,
         resources: Vec<JobResource>,
+        default_env: &BTreeMap<EnvironmentVariableName, String>,
     ) -> Self {
         let build_job = |_, p: &Package| {
+            // The default environment is overridden by the package and the resources
+            let defaults = default_env
+                .iter()
+                .filter(|(name, _)| !p.environment().as_ref().map(|env| env.contains_key(*name)).unwrap_or(false))
+                .filter(|(name, _)| !resources.iter().filter_map(JobResource::env).any(|(k, _)| k == *name))
+                .map(|(k, v)| JobResource::from((k.clone(), v.clone())));
+
             Job::new(
                 p.clone(),
                 script_shebang.clone(),
                 image.clone(),
                 phases.clone(),
-                resources.clone(),
+                defaults.chain(resources.iter().cloned()).collect(),
             )
         };
 
@@ -56,26 +71,259 @@ impl Dag {
             .map(move |idx| {
                 let job = self.dag.graph().node_weight(idx).unwrap(); // TODO
                 let children = self.dag.children(idx);
-                let children_uuids = children.iter(&self.dag)
+                let children_jobs = children.iter(&self.dag)
                     .filter_map(|(_, node_idx)| {
                         self.dag.graph().node_weight(node_idx)
                     })
-                    .map(Job::uuid)
-                    .cloned()
+                    .collect::<Vec<_>>();
+
+                let dependency_outputs = children_jobs
+                    .iter()
+                    .map(|child| {
+                        let outputs = DependencyOutputs {
+                            package: child.package(),
+                            outputs: job.package().outputs_required_from(child.package()),
+                        };
+                        (*child.uuid(), outputs)
+                    })
                     .collect();
 
                 JobDefinition {
                     job,
-                    dependencies: children_uuids
+                    dependencies: children_jobs.into_iter().map(Job::uuid).cloned().collect(),
+                    dependency_outputs,
                 }
             })
     }
 
+    /// For each job, the number of jobs on the longest chain from the job up to the root of the
+    /// DAG, including the job itself
+    ///
+    /// Jobs with a longer chain are on the critical path of the submit and should be run first.
+    pub fn critical_path_lengths(&self) -> HashMap<Uuid, usize> {
+        fn length(dag: &DaggyDag<Job, i8>, idx: NodeIndex, memo: &mut HashMap<NodeIndex, usize>) -> usize {
+            if let Some(l) = memo.get(&idx) {
+                return *l
+            }
+
+            let parents = dag.parents(idx).iter(dag).map(|(_, p)| p);
+            let l = 1 + parents.map(|p| length(dag, p, memo)).max().unwrap_or(0);
+            memo.insert(idx, l);
+            l
+        }
+
+        let mut memo = HashMap::new();
+        self.dag
+            .graph()
+            .node_indices()
+            .map(|idx| (*self.dag.graph()[idx].uuid(), length(&self.dag, idx, &mut memo)))
+            .collect()
+    }
+
+    /// The longest chain of dependencies in the DAG, in the order the jobs have to be run
+    pub fn critical_path(&self) -> Vec<&Job> {
+        fn depth(dag: &DaggyDag<Job, i8>, idx: NodeIndex, memo: &mut HashMap<NodeIndex, usize>) -> usize {
+            if let Some(d) = memo.get(&idx) {
+                return *d
+            }
+
+            let children = dag.children(idx).iter(dag).map(|(_, c)| c);
+            let d = 1 + children.map(|c| depth(dag, c, memo)).max().unwrap_or(0);
+            memo.insert(idx, d);
+            d
+        }
+
+        let mut memo = HashMap::new();
+        let deepest_child = |idx: Option<NodeIndex>, memo: &mut HashMap<NodeIndex, usize>| {
+            let candidates = match idx {
+                Some(idx) => self.dag.children(idx).iter(&self.dag).map(|(_, c)| c).collect::<Vec<_>>(),
+                None => self.dag
+                    .graph()
+                    .node_indices()
+                    .filter(|i| self.dag.parents(*i).iter(&self.dag).next().is_none())
+                    .collect(),
+            };
+            candidates.into_iter().max_by_key(|c| depth(&self.dag, *c, memo))
+        };
+
+        let mut path = vec![];
+        let mut current = deepest_child(None, &mut memo);
+        while let Some(idx) = current {
+            path.push(&self.dag.graph()[idx]);
+            current = deepest_child(Some(idx), &mut memo);
+        }
+        path.reverse();
+        path
+    }
 }
 
 #[derive(Debug)]
 pub struct JobDefinition<'a> {
     pub job: &'a Job,
     pub dependencies: Vec<Uuid>,
+
+    /// The outputs the job needs from each of its dependencies
+    pub dependency_outputs: HashMap<Uuid, DependencyOutputs<'a>>,
+}
+
+/// The outputs of a dependency that a job depends on
+#[derive(Debug)]
+pub struct DependencyOutputs<'a> {
+    pub package: &'a Package,
+    pub outputs: Vec<PackageName>,
+}
+
+impl<'a> DependencyOutputs<'a> {
+    /// Whether the artifact at `path` belongs to one of the outputs
+    pub fn contains(&self, path: &Path) -> bool {
+        self.outputs.contains(self.package.artifact_output(path))
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::package::condition::ConditionData;
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+    use crate::package::Dependencies;
+    use crate::package::Dependency;
+    use crate::package::PackageOptions;
+    use crate::package::VersionResolution;
+    use crate::repository::Repository;
+
+    #[test]
+    fn test_critical_path() {
+        // a depends on b and c, b depends on d
+        let mut btree = BTreeMap::new();
+        let mut packages = ["a", "b", "c", "d"]
+            .iter()
+            .map(|name| package(name, "1", "https://rust-lang.org", "123"))
+            .collect::<Vec<_>>();
+        let deps = |names: &[&str]| {
+            Dependencies::with_runtime_dependencies(names.iter()
+                .map(|name| Dependency::from(format!("{} =1", name)))
+                .collect())
+        };
+        packages[0].set_dependencies(deps(&["b", "c"]));
+        packages[1].set_dependencies(deps(&["d"]));
+        for p in packages.iter() {
+            btree.insert((p.name().clone(), p.version().clone()), p.clone());
+        }
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &PackageOptions::new(),
+        };
+        let dag = crate::package::Dag::for_root_package(packages[0].clone(), &repo, None, &condition_data, &VersionResolution::default()).unwrap();
+        let dag = Dag::from_package_dag(
+            dag,
+            Shebang::from(String::from("#!/bin/bash")),
+            ImageName::from(String::from("debian:bullseye")),
+            vec![],
+            vec![],
+            &BTreeMap::new(),
+        );
+
+        let critical_path_lengths = dag.critical_path_lengths();
+        let lengths = dag.iter()
+            .map(|jobdef| (jobdef.job.package().name().clone(), critical_path_lengths[jobdef.job.uuid()]))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(lengths[&pname("a")], 1);
+        assert_eq!(lengths[&pname("b")], 2);
+        assert_eq!(lengths[&pname("c")], 2);
+        assert_eq!(lengths[&pname("d")], 3);
+
+        let path = dag.critical_path()
+            .into_iter()
+            .map(|job| (job.package().name().clone(), job.package().version().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(path, vec![
+            (pname("d"), pversion("1")),
+            (pname("b"), pversion("1")),
+            (pname("a"), pversion("1")),
+        ]);
+    }
+
+    #[test]
+    fn test_dependency_outputs() {
+        // a depends on the "b-dev" output of b
+        let mut btree = BTreeMap::new();
+        let mut a = package("a", "1", "https://rust-lang.org", "123");
+        a.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from("b-dev =1"))));
+        let mut b = package("b", "1", "https://rust-lang.org", "124");
+        b.set_outputs(vec![pname("b-dev"), pname("b-doc")]);
+        for p in [&a, &b] {
+            btree.insert((p.name().clone(), p.version().clone()), p.clone());
+        }
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &PackageOptions::new(),
+        };
+        let dag = crate::package::Dag::for_root_package(a, &repo, None, &condition_data, &VersionResolution::default()).unwrap();
+        let dag = Dag::from_package_dag(
+            dag,
+            Shebang::from(String::from("#!/bin/bash")),
+            ImageName::from(String::from("debian:bullseye")),
+            vec![],
+            vec![],
+            &BTreeMap::new(),
+        );
+
+        let jobdef = dag.iter().find(|jobdef| *jobdef.job.package().name() == pname("a")).unwrap();
+        assert_eq!(jobdef.dependencies.len(), 1);
+        let outputs = &jobdef.dependency_outputs[&jobdef.dependencies[0]];
+        assert_eq!(*outputs.package.name(), pname("b"));
+        assert_eq!(outputs.outputs, vec![pname("b-dev")]);
+
+        assert!(outputs.contains(Path::new("b-dev/b-dev-1.rpm")));
+        assert!(!outputs.contains(Path::new("b-doc/b-doc-1.rpm")));
+        assert!(!outputs.contains(Path::new("b-1.rpm")));
+        assert_eq!(*outputs.package.artifact_output(Path::new("b-1.rpm")), pname("b"));
+    }
+
+    #[test]
+    fn test_default_env() {
+        let name = |s: &str| EnvironmentVariableName::from(s);
+        let mut a = package("a", "1", "https://rust-lang.org", "123");
+        a.set_environment(std::iter::once((name("B"), String::from("package"))).collect());
+        let mut btree = BTreeMap::new();
+        btree.insert((a.name().clone(), a.version().clone()), a.clone());
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &PackageOptions::new(),
+        };
+        let dag = crate::package::Dag::for_root_package(a, &repo, None, &condition_data, &VersionResolution::default()).unwrap();
+        let default_env = ["A", "B", "C"].iter()
+            .map(|n| (name(n), String::from("default")))
+            .collect::<BTreeMap<_, _>>();
+        let dag = Dag::from_package_dag(
+            dag,
+            Shebang::from(String::from("#!/bin/bash")),
+            ImageName::from(String::from("debian:bullseye")),
+            vec![],
+            vec![JobResource::from((name("C"), String::from("cli")))],
+            &default_env,
+        );
+
+        let jobdef = dag.iter().next().unwrap();
+        let env = jobdef.job.resources().iter().filter_map(JobResource::env).map(|(k, v)| (k.clone(), v.clone())).collect::<Vec<_>>();
+        assert_eq!(env, vec![
+            (name("A"), String::from("default")),
+            (name("C"), String::from("cli")),
+        ]);
+    }
+}
@@ -10,11 +10,15 @@
 
 use crate::filestore::ArtifactPath;
 use crate::util::EnvironmentVariableName;
+use crate::util::secret::Secret;
 
 #[derive(Clone, Debug)]
 pub enum JobResource {
     Environment(EnvironmentVariableName, String),
     Artifact(ArtifactPath),
+
+    /// A secret that is passed to the container as environment variable, but not recorded anywhere
+    Secret(EnvironmentVariableName, Secret),
 }
 
 impl From<(EnvironmentVariableName, String)> for JobResource {
@@ -23,6 +27,12 @@ impl From<(EnvironmentVariableName, String)> for JobResource {
     }
 }
 
+impl From<(EnvironmentVariableName, Secret)> for JobResource {
+    fn from(tpl: (EnvironmentVariableName, Secret)) -> Self {
+        JobResource::Secret(tpl.0, tpl.1)
+    }
+}
+
 impl From<ArtifactPath> for JobResource {
     fn from(a: ArtifactPath) -> Self {
         JobResource::Artifact(a)
@@ -36,6 +46,12 @@ impl JobResource {
             _ => None,
         }
     }
+    pub fn secret(&self) -> Option<(&EnvironmentVariableName, &Secret)> {
+        match self {
+            JobResource::Secret(k, v) => Some((k, v)),
+            _ => None,
+        }
+    }
     pub fn artifact(&self) -> Option<&ArtifactPath> {
         match self {
             JobResource::Artifact(a) => Some(a),
@@ -16,10 +16,13 @@ use log::debug;
 use log::trace;
 use uuid::Uuid;
 
+use crate::config::CacheVolume;
 use crate::config::Configuration;
 use crate::filestore::ArtifactPath;
 use crate::job::Job;
 use crate::job::JobResource;
+use crate::package::ContainerLimits;
+use crate::package::Network;
 use crate::package::Package;
 use crate::package::Script;
 use crate::package::ScriptBuilder;
@@ -27,9 +30,10 @@ use crate::source::SourceCache;
 use crate::source::SourceEntry;
 use crate::util::EnvironmentVariableName;
 use crate::util::docker::ImageName;
+use crate::util::secret::Secret;
 
 /// A job configuration that can be run. All inputs are clear here.
-#[derive(Debug, Getters)]
+#[derive(Clone, Debug, Getters)]
 pub struct RunnableJob {
     #[getset(get = "pub")]
     uuid: Uuid,
@@ -48,6 +52,23 @@ pub struct RunnableJob {
 
     #[getset(get = "pub")]
     resources: Vec<JobResource>,
+
+    /// The resource limits of the package, with the defaults from the configuration
+    #[getset(get = "pub")]
+    limits: ContainerLimits,
+
+    /// The network access of the container, from the package or the configuration
+    #[getset(get = "pub")]
+    network: Network,
+
+    /// The cache volumes that are mounted into the container, with the names of their docker
+    /// volumes
+    #[getset(get = "pub")]
+    cache_volumes: Vec<(String, CacheVolume)>,
+
+    /// Whether the job is built hermetically, with only its declared inputs
+    #[getset(get = "pub")]
+    hermetic: bool,
 }
 
 impl RunnableJob {
@@ -58,8 +79,11 @@ impl RunnableJob {
         git_author_env: Option<&(EnvironmentVariableName, String)>,
         git_commit_env: Option<&(EnvironmentVariableName, String)>,
         dependencies: Vec<ArtifactPath>,
+        hermetic: bool,
     ) -> Result<Self> {
-        if config.containers().check_env_names() {
+        // Hermetic jobs always check the variables and get no variables from the host
+        // environment, so the variables have to be allowed explicitly
+        if config.containers().check_env_names() || hermetic {
             debug!("Checking environment if all variables are allowed!");
             job.resources()
                 .iter()
@@ -72,12 +96,16 @@ impl RunnableJob {
                         .into_iter()
                         .flatten()
                 })
+                .chain(job.package().phases().values().flat_map(|phase| phase.env().iter()))
                 .chain(git_author_env.as_ref().into_iter().map(|(k, v)| (k, v)))
                 .chain(git_commit_env.as_ref().into_iter().map(|(k, v)| (k, v)))
                 .inspect(|(name, _)| debug!("Checking: {}", name))
                 .try_for_each(|(name, _)| {
                     trace!("{:?} contains? {:?}", config.containers().allowed_env(), name);
-                    if !config.containers().allowed_env().contains(name) {
+                    if !config.containers().allowed_env().contains(name)
+                        && (hermetic || !config.containers().pass_env().contains(name))
+                        && !config.build().env().contains_key(name)
+                    {
                         Err(anyhow!("Environment variable name not allowed: {}", name))
                     } else {
                         Ok(())
@@ -101,7 +129,7 @@ impl RunnableJob {
             .chain({
                 job.resources()
                     .iter()
-                    .filter(|jr| jr.env().is_some())
+                    .filter(|jr| jr.env().is_some() || jr.secret().is_some())
                     .cloned()
             })
             .chain(git_author_env.into_iter().cloned().map(JobResource::from))
@@ -121,11 +149,68 @@ impl RunnableJob {
             image: job.image().clone(),
             resources,
             source_cache: source_cache.clone(),
+            limits: job.package()
+                .limits()
+                .clone()
+                .unwrap_or_default()
+                .or(config.containers().limits()),
+            // Hermetic jobs have no network access and no cache volumes, which are not part of
+            // the inputs of the job
+            network: if hermetic {
+                Network::None
+            } else {
+                job.package().network().unwrap_or_else(|| config.containers().network())
+            },
+            cache_volumes: if hermetic {
+                vec![]
+            } else {
+                Self::cache_volumes_for(job.package(), job.image(), config)
+            },
+            hermetic,
 
             script,
         })
     }
 
+    /// Create a job that runs exactly the passed script, e.g. to reproduce a job that was
+    /// recorded in the database
+    pub fn with_script(
+        package: Package,
+        image: ImageName,
+        source_cache: &SourceCache,
+        script: Script,
+        resources: Vec<JobResource>,
+        config: &Configuration,
+    ) -> Self {
+        let limits = package.limits()
+            .clone()
+            .unwrap_or_default()
+            .or(config.containers().limits());
+        let network = package.network().unwrap_or_else(|| config.containers().network());
+        let cache_volumes = Self::cache_volumes_for(&package, &image, config);
+
+        RunnableJob {
+            uuid: Uuid::new_v4(),
+            package,
+            image,
+            resources,
+            source_cache: source_cache.clone(),
+            script,
+            limits,
+            network,
+            cache_volumes,
+            hermetic: false,
+        }
+    }
+
+    fn cache_volumes_for(package: &Package, image: &ImageName, config: &Configuration) -> Vec<(String, CacheVolume)> {
+        config.containers()
+            .cache_volumes()
+            .iter()
+            .map(|cv| (cv.volume_name(package.name(), image), cv.clone()))
+            .collect()
+    }
+
     pub fn package_sources(&self) -> Vec<SourceEntry> {
         self.source_cache.sources_for(self.package())
     }
@@ -144,4 +229,46 @@ impl RunnableJob {
             })
     }
 
+    /// Compute a hash over everything that determines the artifacts this job produces: The
+    /// package, the image, the script, the environment, the patches and the build options
+    ///
+    /// Two jobs with the same fingerprint are expected to produce the same artifacts.
+    pub fn fingerprint(&self) -> Result<String> {
+        use sha2::Digest;
+
+        let mut env = self.environment()
+            .map(|(k, v)| format!("{}={}", k.as_ref(), v))
+            .collect::<Vec<_>>();
+        env.sort();
+
+        let mut m = sha2::Sha256::new();
+        m.update(self.package.name().as_bytes());
+        m.update(b"\0");
+        m.update(self.package.version().as_bytes());
+        m.update(b"\0");
+        m.update(self.image.as_ref().as_bytes());
+        m.update(b"\0");
+        m.update(self.script.as_ref().as_bytes());
+        m.update(b"\0");
+        for e in env {
+            m.update(e.as_bytes());
+            m.update(b"\0");
+        }
+        if let Some(patches_hash) = self.package.patches_hash()? {
+            m.update(patches_hash.as_bytes());
+        }
+        if let Some(options) = self.package.options_string() {
+            m.update(b"\0");
+            m.update(options.as_bytes());
+        }
+        if self.hermetic {
+            m.update(b"\0hermetic");
+        }
+        Ok(format!("{:x}", m.finalize()))
+    }
+
+    /// The secrets that are passed to the container in addition to the environment
+    pub fn secrets(&self) -> impl Iterator<Item = (&EnvironmentVariableName, &Secret)> {
+        self.resources.iter().filter_map(|r| r.secret())
+    }
 }
@@ -0,0 +1,126 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The layout of the log files in the log directory
+//!
+//! The logs of the jobs of a submit are written to `<log_dir>/<submit>/`, one file
+//! `<package>-<version>-<job>.log` per job. The `index.json` in the directory of a submit maps the
+//! jobs to their log files and packages.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// The name of the index file in the log directory of a submit
+pub const LOG_INDEX_FILE_NAME: &str = "index.json";
+
+/// The directory the logs of the jobs of `submit` are written to
+pub fn submit_log_dir(log_dir: &Path, submit: &Uuid) -> PathBuf {
+    log_dir.join(submit.to_string())
+}
+
+/// The log file of the job `job` of `submit`, which builds `package_name` `package_version`
+///
+/// Slashes in the name of the package (of packages in namespaces) are replaced, so that all log
+/// files are directly in the directory of the submit.
+pub fn job_log_path(log_dir: &Path, submit: &Uuid, package_name: &str, package_version: &str, job: &Uuid) -> PathBuf {
+    let file_name = format!("{}-{}-{}.log", package_name, package_version, job).replace('/', "_");
+    submit_log_dir(log_dir, submit).join(file_name)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LogIndexEntry {
+    /// The log file, relative to the directory of the submit
+    pub file: PathBuf,
+    pub package_name: String,
+    pub package_version: String,
+}
+
+/// Writes the index files of the log directories of submits
+///
+/// The jobs of a submit run concurrently, so the index file is only written by one job at a time.
+/// The entries of the file are kept, so that jobs of a resumed submit are added to it.
+#[derive(Debug, Default)]
+pub struct LogIndex(tokio::sync::Mutex<()>);
+
+impl LogIndex {
+    /// Add the log file `log_file` of the job `job` to the index of the submit the file is in
+    pub async fn add(&self, log_file: &Path, job: Uuid, package_name: &str, package_version: &str) -> Result<()> {
+        let dir = log_file.parent().ok_or_else(|| anyhow!("Log file without directory: {}", log_file.display()))?;
+        let file = log_file.file_name().map(PathBuf::from).ok_or_else(|| anyhow!("Log file without name: {}", log_file.display()))?;
+        let path = dir.join(LOG_INDEX_FILE_NAME);
+
+        let _lock = self.0.lock().await;
+        let mut index = Self::load(&path).await?;
+        index.insert(job, LogIndexEntry {
+            file,
+            package_name: package_name.to_string(),
+            package_version: package_version.to_string(),
+        });
+
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_vec_pretty(&index)?)
+            .await
+            .with_context(|| anyhow!("Writing {}", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .with_context(|| anyhow!("Moving {} to {}", tmp_path.display(), path.display()))
+    }
+
+    /// The entries of the index file at `path`, empty if there is no index yet
+    pub async fn load(path: &Path) -> Result<BTreeMap<Uuid, LogIndexEntry>> {
+        match tokio::fs::read(path).await {
+            Ok(content) => serde_json::from_slice(&content).with_context(|| anyhow!("Parsing {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e).with_context(|| anyhow!("Reading {}", path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_log_path() {
+        let submit = Uuid::nil();
+        let job = Uuid::parse_str("00000000-0000-0000-0000-000000000001").unwrap();
+        assert_eq!(job_log_path(Path::new("/logs"), &submit, "core/gcc", "12.2.0", &job),
+            PathBuf::from("/logs/00000000-0000-0000-0000-000000000000/core_gcc-12.2.0-00000000-0000-0000-0000-000000000001.log"));
+    }
+
+    #[tokio::test]
+    async fn test_index_keeps_entries() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let submit = Uuid::new_v4();
+        std::fs::create_dir_all(submit_log_dir(dir, &submit)).unwrap();
+
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let index = LogIndex::default();
+        index.add(&job_log_path(dir, &submit, "a", "1", &a), a, "a", "1").await.unwrap();
+
+        // Another process, e.g. for a resumed submit
+        let index = LogIndex::default();
+        index.add(&job_log_path(dir, &submit, "b", "2", &b), b, "b", "2").await.unwrap();
+
+        let entries = LogIndex::load(&submit_log_dir(dir, &submit).join(LOG_INDEX_FILE_NAME)).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[&a].file, PathBuf::from(format!("a-1-{}.log", a)));
+        assert_eq!(entries[&b].package_name, "b");
+    }
+}
@@ -14,9 +14,14 @@ use colored::Colorize;
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum LogItem {
-    /// A line from the log, unmodified
+    /// A line from the log (written to stdout by the script), unmodified
     Line(Vec<u8>),
 
+    /// A line the script wrote to stderr, unmodified
+    ///
+    /// Logs from before stdout and stderr were told apart only have `Line`s.
+    StderrLine(Vec<u8>),
+
     /// A progress report
     Progress(usize),
 
@@ -32,6 +37,7 @@ impl LogItem {
     pub fn display(&self) -> Result<Display> {
         match self {
             LogItem::Line(s) => Ok(Display(String::from_utf8(s.to_vec())?.normal())),
+            LogItem::StderrLine(s) => Ok(Display(String::from_utf8(s.to_vec())?.normal())),
             LogItem::Progress(u) => Ok(Display(format!("#BUTIDO:PROGRESS:{}", u).cyan())),
             LogItem::CurrentPhase(p) => Ok(Display(format!("#BUTIDO:PHASE:{}", p).cyan())),
             LogItem::State(Ok(())) => Ok(Display("#BUTIDO:STATE:OK".to_string().green())),
@@ -42,6 +48,7 @@ impl LogItem {
     pub fn raw(&self) -> Result<String> {
         match self {
             LogItem::Line(s) => String::from_utf8(s.to_vec()).map_err(Error::from),
+            LogItem::StderrLine(s) => Ok(format!("#BUTIDO:STDERR:{}", String::from_utf8(s.to_vec())?)),
             LogItem::Progress(u) => Ok(format!("#BUTIDO:PROGRESS:{}", u)),
             LogItem::CurrentPhase(p) => Ok(format!("#BUTIDO:PHASE:{}", p)),
             LogItem::State(Ok(())) => Ok("#BUTIDO:STATE:OK".to_string()),
@@ -0,0 +1,258 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Streaming the logs of running jobs to clients, as server-sent events
+//!
+//! `GET /` streams the logs of all jobs, `GET /jobs/<uuid>` the log of one job. Each event has the
+//! kind of the log item as event name and a JSON object as data.
+//!
+//! The same events are parsed again by the clients in butido, e.g. "butido tui".
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use futures::StreamExt;
+use log::debug;
+use log::trace;
+use serde::Deserialize;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::log::LogItem;
+
+/// The number of events that are buffered for a slow client before it misses events
+const BUFFERED_EVENTS: usize = 1024;
+
+/// A log item of a running job, as it is sent to the clients
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LiveLogEvent {
+    pub job: Uuid,
+    pub package_name: String,
+    pub package_version: String,
+
+    /// "line", "stderr", "progress", "phase" or "state"
+    #[serde(skip)]
+    pub kind: &'static str,
+
+    /// The log item, as it appears in the log
+    pub data: String,
+}
+
+impl LiveLogEvent {
+    pub fn new(job: Uuid, package_name: &str, package_version: &str, item: &LogItem) -> Self {
+        let (kind, data) = match item {
+            // Lines are not necessarily valid UTF-8
+            LogItem::Line(line) => ("line", String::from_utf8_lossy(line).to_string()),
+            LogItem::StderrLine(line) => ("stderr", String::from_utf8_lossy(line).to_string()),
+            LogItem::Progress(_) => ("progress", item.raw().unwrap_or_default()),
+            LogItem::CurrentPhase(_) => ("phase", item.raw().unwrap_or_default()),
+            LogItem::State(_) => ("state", item.raw().unwrap_or_default()),
+        };
+
+        LiveLogEvent {
+            job,
+            package_name: package_name.to_string(),
+            package_version: package_version.to_string(),
+            kind,
+            data,
+        }
+    }
+
+    /// The event in the format of server-sent events
+    fn to_sse(&self) -> Result<String> {
+        Ok(format!("event: {}\ndata: {}\n\n", self.kind, serde_json::to_string(self)?))
+    }
+
+    /// Parse an event in the format of server-sent events, without the empty line at its end
+    pub fn from_sse(event: &str) -> Result<Self> {
+        let mut kind = None;
+        let mut data = None;
+        for line in event.lines() {
+            if let Some(k) = line.strip_prefix("event: ") {
+                kind = Some(k);
+            } else if let Some(d) = line.strip_prefix("data: ") {
+                data = Some(d);
+            }
+        }
+
+        let data = data.ok_or_else(|| anyhow!("Event without data: {}", event))?;
+        let mut parsed = serde_json::from_str::<LiveLogEvent>(data)
+            .with_context(|| anyhow!("Parsing event data: {}", data))?;
+        parsed.kind = match kind {
+            Some("line") | None => "line",
+            Some("stderr") => "stderr",
+            Some("progress") => "progress",
+            Some("phase") => "phase",
+            Some("state") => "state",
+            Some(other) => return Err(anyhow!("Unknown event kind: {}", other)),
+        };
+        Ok(parsed)
+    }
+
+    /// The log item of the event
+    pub fn item(&self) -> Result<LogItem> {
+        match self.kind {
+            "line" => return Ok(LogItem::Line(self.data.as_bytes().to_vec())),
+            "stderr" => return Ok(LogItem::StderrLine(self.data.as_bytes().to_vec())),
+            _ => {},
+        }
+
+        crate::log::parser()
+            .parse(self.data.as_bytes())
+            .map_err(|e| anyhow!("Parsing '{}': {}", self.data, e))
+    }
+
+    /// Remove the next complete event from `buffer`, which holds the stream received so far
+    pub fn next_from_stream(buffer: &mut Vec<u8>) -> Option<Result<Self>> {
+        let end = buffer.windows(2).position(|w| w == b"\n\n")?;
+        let event = buffer.drain(..end + 2).collect::<Vec<u8>>();
+        Some(Self::from_sse(&String::from_utf8_lossy(&event)))
+    }
+}
+
+/// Distributes the log items of the running jobs to the connected clients
+#[derive(Clone)]
+pub struct LiveLog(tokio::sync::broadcast::Sender<Arc<LiveLogEvent>>);
+
+impl LiveLog {
+    pub fn new() -> Self {
+        LiveLog(tokio::sync::broadcast::channel(BUFFERED_EVENTS).0)
+    }
+
+    pub fn send(&self, event: LiveLogEvent) {
+        // Fails only if no client is connected
+        let _ = self.0.send(Arc::new(event));
+    }
+
+    /// Serve the log on `addr` until the returned task is aborted
+    pub fn serve(&self, addr: SocketAddr) -> Result<tokio::task::JoinHandle<()>> {
+        use hyper::service::make_service_fn;
+        use hyper::service::service_fn;
+
+        let sender = self.0.clone();
+        let make_service = make_service_fn(move |_| {
+            let sender = sender.clone();
+            async move {
+                Ok::<_, std::convert::Infallible>(service_fn(move |request| {
+                    let response = Self::respond(&sender, request);
+                    async move { Ok::<_, std::convert::Infallible>(response) }
+                }))
+            }
+        });
+
+        let server = hyper::Server::try_bind(&addr)
+            .with_context(|| anyhow!("Binding the log socket to {}", addr))?
+            .serve(make_service);
+        debug!("Streaming logs on http://{}", addr);
+
+        Ok(tokio::spawn(async move {
+            if let Err(e) = server.await {
+                log::error!("Serving the live log failed: {}", e);
+            }
+        }))
+    }
+
+    fn respond(
+        sender: &tokio::sync::broadcast::Sender<Arc<LiveLogEvent>>,
+        request: hyper::Request<hyper::Body>,
+    ) -> hyper::Response<hyper::Body> {
+        let status = |code| {
+            let mut response = hyper::Response::new(hyper::Body::empty());
+            *response.status_mut() = code;
+            response
+        };
+
+        if request.method() != hyper::Method::GET {
+            return status(hyper::StatusCode::METHOD_NOT_ALLOWED)
+        }
+
+        let job = match parse_path(request.uri().path()) {
+            Ok(job) => job,
+            Err(_) => return status(hyper::StatusCode::NOT_FOUND),
+        };
+        trace!("Client connected to the live log, job = {:?}", job);
+
+        let events = tokio_stream::wrappers::BroadcastStream::new(sender.subscribe())
+            .filter_map(move |event| async move {
+                match event {
+                    Ok(event) if job.map(|job| job == event.job).unwrap_or(true) => {
+                        Some(event.to_sse().map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string())))
+                    },
+                    Ok(_) => None,
+
+                    // The client was too slow and missed events, it gets the next ones
+                    Err(_) => None,
+                }
+            });
+
+        let mut response = hyper::Response::new(hyper::Body::wrap_stream(events));
+        response.headers_mut().insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static("text/event-stream"));
+        response.headers_mut().insert(hyper::header::CACHE_CONTROL, hyper::header::HeaderValue::from_static("no-cache"));
+        response
+    }
+}
+
+/// Parse the path of a request into the job the client wants the log of, `None` for all jobs
+fn parse_path(path: &str) -> Result<Option<Uuid>> {
+    match path.trim_end_matches('/') {
+        "" => Ok(None),
+        p => p.strip_prefix("/jobs/")
+            .ok_or_else(|| anyhow!("Unknown path: {}", path))
+            .and_then(|id| Uuid::parse_str(id).map_err(|e| anyhow!("Invalid job UUID '{}': {}", id, e)))
+            .map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(parse_path("/").unwrap(), None);
+        assert_eq!(parse_path("/jobs/00000000-0000-0000-0000-000000000000").unwrap(), Some(Uuid::nil()));
+        assert!(parse_path("/jobs/foo").is_err());
+        assert!(parse_path("/submits").is_err());
+    }
+
+    #[test]
+    fn test_event_to_sse() {
+        let event = LiveLogEvent::new(Uuid::nil(), "foo", "1.0", &LogItem::CurrentPhase(String::from("build")));
+        assert_eq!(
+            event.to_sse().unwrap(),
+            "event: phase\ndata: {\"job\":\"00000000-0000-0000-0000-000000000000\",\"package_name\":\"foo\",\"package_version\":\"1.0\",\"data\":\"#BUTIDO:PHASE:build\"}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_events_from_stream() {
+        let phase = LiveLogEvent::new(Uuid::nil(), "foo", "1.0", &LogItem::CurrentPhase(String::from("build")));
+        let line = LiveLogEvent::new(Uuid::nil(), "foo", "1.0", &LogItem::Line(b"make all".to_vec()));
+        let stream = format!("{}{}", phase.to_sse().unwrap(), line.to_sse().unwrap());
+
+        // The second event is incomplete until the rest of the stream arrives
+        let (first, rest) = stream.as_bytes().split_at(stream.len() - 5);
+        let mut buffer = first.to_vec();
+
+        let event = LiveLogEvent::next_from_stream(&mut buffer).unwrap().unwrap();
+        assert_eq!(event.kind, "phase");
+        assert_eq!(event.package_name, "foo");
+        assert!(matches!(event.item().unwrap(), LogItem::CurrentPhase(p) if p == "build"));
+        assert!(LiveLogEvent::next_from_stream(&mut buffer).is_none());
+
+        buffer.extend_from_slice(rest);
+        let event = LiveLogEvent::next_from_stream(&mut buffer).unwrap().unwrap();
+        assert!(matches!(event.item().unwrap(), LogItem::Line(l) if l == b"make all"));
+        assert!(buffer.is_empty());
+    }
+}
@@ -17,4 +17,17 @@ pub use item::*;
 mod sink;
 pub use sink::*;
 
+mod index;
+pub use index::*;
+
+mod live;
+pub use live::*;
+
+mod offload;
+pub use offload::*;
+
+mod truncate;
+pub use truncate::*;
+
 mod util;
+pub use util::OutputStream;
@@ -0,0 +1,83 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Logs of jobs that are stored in a file, with only a reference to the file and the end of the
+//! log in the database
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use log::warn;
+
+/// The first line of the log text of a job in the database if the log was offloaded to a file
+const LOG_FILE_REFERENCE: &str = "#BUTIDO:LOGFILE:";
+
+/// The text that is stored in the database for a log that was written to `path`
+///
+/// This is the reference to the file, followed by the last `tail_lines` lines of the log, so that
+/// the state of the job can still be found in the database.
+pub fn offloaded_log_text(path: &Path, log: &str, tail_lines: usize) -> String {
+    let lines = log.lines().collect::<Vec<_>>();
+    std::iter::once(format!("{}{}", LOG_FILE_REFERENCE, path.display()))
+        .chain(lines[lines.len().saturating_sub(tail_lines)..].iter().map(|l| l.to_string()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The file the log was offloaded to, if `log_text` from the database is a reference
+pub fn log_file_reference(log_text: &str) -> Option<&Path> {
+    log_text
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix(LOG_FILE_REFERENCE))
+        .map(Path::new)
+}
+
+/// The complete log of a job with the `log_text` from the database
+///
+/// If the log was offloaded, it is read from its file. If the file does not exist anymore, the
+/// end of the log that is stored in the database is returned.
+pub fn resolve_log(log_text: &str) -> Result<Cow<'_, str>> {
+    let path = match log_file_reference(log_text) {
+        Some(path) => path,
+        None => return Ok(Cow::Borrowed(log_text)),
+    };
+
+    match std::fs::read_to_string(path) {
+        Ok(log) => Ok(Cow::Owned(log)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            warn!("Log file {} does not exist anymore, only the end of the log is available", path.display());
+            Ok(Cow::Borrowed(log_text.split_once('\n').map(|(_, tail)| tail).unwrap_or("")))
+        },
+        Err(e) => Err(anyhow!("Reading log file {}: {}", path.display(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offloaded_log_text() {
+        let text = offloaded_log_text(Path::new("/logs/job.log"), "a\nb\nc\n#BUTIDO:STATE:OK", 2);
+        assert_eq!(text, "#BUTIDO:LOGFILE:/logs/job.log\nc\n#BUTIDO:STATE:OK");
+        assert_eq!(log_file_reference(&text), Some(Path::new("/logs/job.log")));
+        assert_eq!(log_file_reference("a\nb"), None);
+    }
+
+    #[test]
+    fn test_resolve_missing_log_file() {
+        let text = offloaded_log_text(Path::new("/nonexistent/butido/job.log"), "a\nb", 1);
+        assert_eq!(resolve_log(&text).unwrap(), "b");
+        assert_eq!(resolve_log("a\nb").unwrap(), "a\nb");
+    }
+}
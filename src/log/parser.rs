@@ -13,10 +13,8 @@ use std::str::FromStr;
 
 use anyhow::Error;
 use anyhow::Result;
-use futures::AsyncBufReadExt;
 use futures::Stream;
 use futures::StreamExt;
-use futures::TryStreamExt;
 use pom::parser::Parser as PomParser;
 use shiplift::tty::TtyChunk;
 
@@ -25,15 +23,35 @@ use crate::log::LogItem;
 
 type IoResult<T> = RResult<T, futures::io::Error>;
 
-pub fn buffer_stream_to_line_stream<S>(stream: S) -> impl Stream<Item = IoResult<String>>
+/// The lines of the output of a container, with the stream they were written to
+pub fn buffer_stream_to_line_stream<S>(stream: S) -> impl Stream<Item = IoResult<(OutputStream, String)>>
 where
     S: Stream<Item = shiplift::Result<TtyChunk>> + std::marker::Unpin,
 {
-    stream
-        .map(|r| r.map(TtyChunkBuf::from))
-        .map_err(|e| futures::io::Error::new(futures::io::ErrorKind::Other, e))
-        .into_async_read()
-        .lines()
+    let state = (Some(stream), LineBuffers::default(), std::collections::VecDeque::new());
+    futures::stream::unfold(state, |(mut stream, mut buffers, mut pending)| async move {
+        loop {
+            if let Some((output, line)) = pending.pop_front() {
+                let line = String::from_utf8(line)
+                    .map_err(|e| futures::io::Error::new(futures::io::ErrorKind::InvalidData, e));
+                return Some((line.map(|l| (output, l)), (stream, buffers, pending)))
+            }
+
+            let mut lines = vec![];
+            match stream.as_mut()?.next().await {
+                Some(Ok(chunk)) => buffers.push(TtyChunkBuf::from(chunk), &mut lines),
+                Some(Err(e)) => {
+                    let e = futures::io::Error::new(futures::io::ErrorKind::Other, e);
+                    return Some((Err(e), (None, buffers, pending)))
+                },
+                None => {
+                    std::mem::take(&mut buffers).finish(&mut lines);
+                    stream = None;
+                },
+            }
+            pending.extend(lines);
+        }
+    })
 }
 
 pub struct ParsedLog(Vec<LogItem>);
@@ -47,6 +65,10 @@ impl std::fmt::Debug for ParsedLog {
                     let s = std::str::from_utf8(l).unwrap_or("ERROR UTF8 ENCODING");
                     writeln!(f, "[{}] Line('{}')", i, s)?
                 },
+                LogItem::StderrLine(l)   => {
+                    let s = std::str::from_utf8(l).unwrap_or("ERROR UTF8 ENCODING");
+                    writeln!(f, "[{}] StderrLine('{}')", i, s)?
+                },
                 LogItem::Progress(u)     => writeln!(f, "[{}] Progress({})", i, u)?,
                 LogItem::CurrentPhase(s) => writeln!(f, "[{}] Phase({})", i, s)?,
                 LogItem::State(Ok(_))    => writeln!(f, "[{}] State::OK", i)?,
@@ -104,6 +126,43 @@ impl ParsedLog {
     pub fn into_iter(self) -> impl Iterator<Item = LogItem> {
         self.0.into_iter()
     }
+
+    /// The last `lines` items of the log of a failed job, and the phase it failed in
+    pub fn failure_tail(self, lines: usize) -> FailureTail {
+        let mut failed_phase = None;
+        let mut current_phase = None;
+        for item in self.0.iter() {
+            match item {
+                LogItem::CurrentPhase(phase) => current_phase = Some(phase),
+                LogItem::State(Err(_)) => {
+                    failed_phase = current_phase.cloned();
+                    break
+                },
+                _ => {},
+            }
+        }
+        let script_failed = self.0.iter().any(|item| matches!(item, LogItem::State(Err(_))));
+
+        let skip = self.0.len().saturating_sub(lines);
+        FailureTail {
+            items: self.0.into_iter().enumerate().skip(skip).collect(),
+            failed_phase,
+            script_failed,
+        }
+    }
+}
+
+/// The end of the log of a failed job
+#[derive(Debug)]
+pub struct FailureTail {
+    /// The last items of the log, with their numbers (starting at 0)
+    pub items: Vec<(usize, LogItem)>,
+
+    /// The phase the script was in when it reported the error
+    pub failed_phase: Option<String>,
+
+    /// Whether the script reported an error, i.e. the job did not fail because of something else
+    pub script_failed: bool,
 }
 
 pub fn parser<'a>() -> PomParser<'a, u8, LogItem> {
@@ -137,6 +196,7 @@ pub fn parser<'a>() -> PomParser<'a, u8, LogItem> {
     (seq(b"#BUTIDO:")
         * ((seq(b"PROGRESS:") * number.map(LogItem::Progress))
             | (seq(b"PHASE:") * string().map(LogItem::CurrentPhase))
+            | (seq(b"STDERR:") * ignored().map(LogItem::StderrLine))
             | ((seq(b"STATE:ERR:") * string().map(|s| LogItem::State(Err(s))))
                 | seq(b"STATE:OK").map(|_| LogItem::State(Ok(()))))))
         | ignored().map(LogItem::Line)
@@ -159,6 +219,60 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_failure_tail() {
+        let log = ParsedLog::from_str(indoc::indoc!(r#"
+            #BUTIDO:PHASE:unpack
+            unpacking
+            #BUTIDO:PHASE:build
+            make: *** [all] Error 2
+            #BUTIDO:STATE:ERR:make failed
+        "#)).unwrap();
+
+        let tail = log.failure_tail(2);
+        assert_eq!(tail.failed_phase.as_deref(), Some("build"));
+        assert!(tail.script_failed);
+        assert_eq!(tail.items.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![3, 4]);
+
+        // All items if the log is shorter than the number of lines
+        let log = ParsedLog::from_str("#BUTIDO:PHASE:build\nfailed").unwrap();
+        let tail = log.failure_tail(10);
+        assert_eq!(tail.items.len(), 2);
+        assert_eq!(tail.failed_phase, None);
+        assert!(!tail.script_failed);
+    }
+
+    #[tokio::test]
+    async fn test_line_stream_separates_stdout_and_stderr() {
+        let chunks = vec![
+            Ok(TtyChunk::StdOut(b"configure\nma".to_vec())),
+            Ok(TtyChunk::StdErr(b"warning: x\r\n".to_vec())),
+            Ok(TtyChunk::StdOut(b"ke\n".to_vec())),
+            Ok(TtyChunk::StdErr(b"error".to_vec())),
+        ];
+        let lines = buffer_stream_to_line_stream(futures::stream::iter(chunks))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<IoResult<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(lines, vec![
+            (OutputStream::Stdout, String::from("configure")),
+            (OutputStream::Stderr, String::from("warning: x")),
+            (OutputStream::Stdout, String::from("make")),
+            (OutputStream::Stderr, String::from("error")),
+        ]);
+    }
+
+    #[test]
+    fn test_stderr_line() {
+        let item = LogItem::StderrLine(b"error: foo".to_vec());
+        let raw = item.raw().unwrap();
+        assert_eq!(raw, "#BUTIDO:STDERR:error: foo");
+        assert_eq!(parser().parse(raw.as_bytes()).unwrap(), item);
+    }
+
     #[test]
     fn test_non_log() {
         let s = "foo bar";
@@ -0,0 +1,167 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Limiting the size of the logs of jobs
+
+use std::collections::VecDeque;
+
+use crate::log::LogItem;
+
+/// The items of a log, with the lines in the middle of the log dropped once the log exceeds its
+/// maximum size
+///
+/// The first half of the maximum size is kept for the head of the log and the rest for the tail.
+/// Phases and states from the dropped middle of the log are kept, so that the phases and the final
+/// state of the job can still be found in the log.
+#[derive(Debug)]
+pub struct LogBuffer {
+    max_bytes: Option<u64>,
+    head: Vec<LogItem>,
+    head_bytes: u64,
+    tail: VecDeque<LogItem>,
+    tail_bytes: u64,
+    dropped_lines: usize,
+    dropped_bytes: u64,
+    dropped_markers: Vec<LogItem>,
+}
+
+impl LogBuffer {
+    /// A buffer for a log of at most `max_bytes`, or of any size if `None`
+    pub fn new(max_bytes: Option<u64>) -> Self {
+        LogBuffer {
+            max_bytes,
+            head: Vec::with_capacity(4096),
+            head_bytes: 0,
+            tail: VecDeque::new(),
+            tail_bytes: 0,
+            dropped_lines: 0,
+            dropped_bytes: 0,
+            dropped_markers: vec![],
+        }
+    }
+
+    fn size_of(item: &LogItem) -> u64 {
+        match item {
+            LogItem::Line(line) | LogItem::StderrLine(line) => line.len() as u64 + 1,
+            _ => 0,
+        }
+    }
+
+    pub fn push(&mut self, item: LogItem) {
+        let size = Self::size_of(&item);
+        let max_bytes = match self.max_bytes {
+            Some(max_bytes) => max_bytes,
+            None => {
+                self.head.push(item);
+                return
+            },
+        };
+
+        if self.tail.is_empty() && self.head_bytes + size <= max_bytes / 2 {
+            self.head_bytes += size;
+            self.head.push(item);
+            return
+        }
+
+        self.tail_bytes += size;
+        self.tail.push_back(item);
+        while self.tail_bytes > max_bytes - self.head_bytes && self.tail.len() > 1 {
+            let dropped = match self.tail.pop_front() {
+                Some(dropped) => dropped,
+                None => break,
+            };
+            let size = Self::size_of(&dropped);
+            self.tail_bytes -= size;
+            match dropped {
+                LogItem::Line(_) | LogItem::StderrLine(_) => {
+                    self.dropped_lines += 1;
+                    self.dropped_bytes += size;
+                },
+                LogItem::Progress(_) => {},
+                marker => self.dropped_markers.push(marker),
+            }
+        }
+    }
+
+    /// Whether lines were dropped from the log
+    pub fn is_truncated(&self) -> bool {
+        self.dropped_lines > 0
+    }
+
+    /// The items of the log, with a line that tells how much was dropped in place of the dropped
+    /// lines
+    pub fn into_items(self) -> Vec<LogItem> {
+        let note = match self.max_bytes {
+            Some(max_bytes) if self.dropped_lines > 0 => Some(LogItem::Line({
+                format!("[butido: {} lines ({}) of the log were dropped, the log exceeded the maximum size of {}]",
+                    self.dropped_lines,
+                    bytesize::ByteSize::b(self.dropped_bytes),
+                    bytesize::ByteSize::b(max_bytes))
+                .into_bytes()
+            })),
+            _ => None,
+        };
+
+        self.head
+            .into_iter()
+            .chain(note)
+            .chain(self.dropped_markers)
+            .chain(self.tail)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(s: &str) -> LogItem {
+        LogItem::Line(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_log_below_limit_is_kept() {
+        let mut buffer = LogBuffer::new(Some(100));
+        (0..10).for_each(|i| buffer.push(line(&format!("line {}", i))));
+        assert!(!buffer.is_truncated());
+        assert_eq!(buffer.into_items().len(), 10);
+    }
+
+    #[test]
+    fn test_middle_of_log_is_dropped() {
+        let mut buffer = LogBuffer::new(Some(40));
+        buffer.push(LogItem::CurrentPhase("configure".to_string()));
+        (0..10).for_each(|i| buffer.push(line(&format!("line {}", i))));
+        buffer.push(LogItem::CurrentPhase("build".to_string()));
+        buffer.push(LogItem::Progress(50));
+        (10..20).for_each(|i| buffer.push(line(&format!("line {}", i))));
+        buffer.push(LogItem::State(Ok(())));
+        assert!(buffer.is_truncated());
+
+        let items = buffer.into_items();
+        assert_eq!(items[0], LogItem::CurrentPhase("configure".to_string()));
+        assert_eq!(items[1], line("line 0"));
+        assert_eq!(items[2], line("line 1"));
+        assert!(String::from_utf8_lossy(match &items[3] {
+            LogItem::Line(l) => l,
+            other => panic!("Expected the truncation note, got {:?}", other),
+        }).starts_with("[butido: 15 lines"));
+        assert_eq!(items[4], LogItem::CurrentPhase("build".to_string()));
+        assert_eq!(&items[5..], &[line("line 17"), line("line 18"), line("line 19"), LogItem::State(Ok(()))]);
+    }
+
+    #[test]
+    fn test_unlimited_log() {
+        let mut buffer = LogBuffer::new(None);
+        (0..1000).for_each(|i| buffer.push(line(&format!("line {}", i))));
+        assert!(!buffer.is_truncated());
+        assert_eq!(buffer.into_items().len(), 1000);
+    }
+}
@@ -36,3 +36,47 @@ impl AsRef<[u8]> for TtyChunkBuf {
         }
     }
 }
+
+/// The output stream of the script a line was written to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Splits the chunks of stdout and stderr into lines, separately for each stream, as the chunks of
+/// both streams are interleaved and can end in the middle of a line
+#[derive(Default)]
+pub struct LineBuffers {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl LineBuffers {
+    /// Add the chunk to the buffer of its stream, the completed lines are appended to `lines`
+    pub fn push(&mut self, chunk: TtyChunkBuf, lines: &mut Vec<(OutputStream, Vec<u8>)>) {
+        let (stream, buffer, data) = match chunk {
+            TtyChunkBuf::StdOut(data) | TtyChunkBuf::StdIn(data) => (OutputStream::Stdout, &mut self.stdout, data),
+            TtyChunkBuf::StdErr(data) => (OutputStream::Stderr, &mut self.stderr, data),
+        };
+
+        buffer.extend(data);
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            let mut line = buffer.drain(..=end).collect::<Vec<u8>>();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            lines.push((stream, line));
+        }
+    }
+
+    /// The unterminated last lines of the streams
+    pub fn finish(self, lines: &mut Vec<(OutputStream, Vec<u8>)>) {
+        for (stream, line) in [(OutputStream::Stdout, self.stdout), (OutputStream::Stderr, self.stderr)] {
+            if !line.is_empty() {
+                lines.push((stream, line));
+            }
+        }
+    }
+}
@@ -50,6 +50,7 @@ extern crate diesel;
 #[macro_use]
 extern crate diesel_migrations;
 
+use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
@@ -71,6 +72,7 @@ mod config;
 mod consts;
 mod db;
 mod endpoint;
+mod error;
 mod filestore;
 mod job;
 mod log;
@@ -83,11 +85,13 @@ mod ui;
 mod util;
 
 use crate::config::*;
+use crate::package::PackageName;
 use crate::repository::Repository;
 use crate::util::progress::ProgressBars;
+use crate::util::progress::ProgressMode;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     human_panic::setup_panic!(Metadata {
         name: env!("CARGO_PKG_NAME").into(),
         version: env!("CARGO_PKG_VERSION").into(),
@@ -95,6 +99,19 @@ async fn main() -> Result<()> {
         homepage: "atos.net/de/deutschland/sc".into(),
     });
 
+    if let Err(e) = run().await {
+        eprintln!("Error: {:?}", e);
+
+        // A build that was stopped by a signal exits with the status code of the signal
+        let exit_code = match crate::util::signal::terminated_by(&e) {
+            Some(signal) => signal.exit_code(),
+            None => crate::error::ErrorKind::of(&e).map(crate::error::ErrorKind::exit_code).unwrap_or(1),
+        };
+        std::process::exit(exit_code)
+    }
+}
+
+async fn run() -> Result<()> {
     env_logger::try_init()?;
     debug!("Debugging enabled");
 
@@ -114,57 +131,66 @@ async fn main() -> Result<()> {
         .workdir()
         .ok_or_else(|| anyhow!("Not a repository with working directory. Cannot do my job!"))?;
 
-    let mut config = ::config::Config::default();
-    config.merge(::config::File::from(repo_path.join("config.toml")).required(true))
-        .context("Failed to load config.toml from repository")?;
+    let config = load_config(repo_path, &cli).map_err(crate::error::Error::Config)?;
 
-    {
-        let xdg = xdg::BaseDirectories::with_prefix("butido")?;
-        let xdg_config_file = xdg.find_config_file("config.toml");
-        if let Some(xdg_config) = xdg_config_file {
-            debug!("Configuration file found with XDG: {}", xdg_config.display());
-            config.merge(::config::File::from(xdg_config).required(false))
-                .context("Failed to load config.toml from XDG configuration directory")?;
-        } else {
-            debug!("No configuration file found with XDG: {}", xdg.get_config_home().display());
-        }
-    }
+    let progress_mode = match cli.value_of("progress") {
+        Some("plain") => ProgressMode::Plain,
+        Some("json") => ProgressMode::Json,
+        _ if cli.is_present("hide_bars") => ProgressMode::Hidden,
+        Some(_) => ProgressMode::Bars,
 
-    config.merge(::config::Environment::with_prefix("BUTIDO"))?;
-
-    let config = config.try_into::<NotValidatedConfiguration>()
-        .context("Failed to load Configuration object")?
-        .validate()
-        .context("Failed to validate configuration")?;
-
-    let hide_bars = cli.is_present("hide_bars") || crate::util::stdout_is_pipe();
+        // Bars are of no use in logs
+        None if crate::util::stdout_is_pipe() => ProgressMode::Plain,
+        None => ProgressMode::Bars,
+    };
     let progressbars = ProgressBars::setup(
         config.progress_format().clone(),
-        hide_bars,
+        config.progress_bytes_format().clone(),
+        progress_mode,
     );
 
-    let load_repo = || -> Result<Repository> {
+    let repo_head = repo.head()
+        .and_then(|head| head.peel_to_commit())
+        .map(|commit| commit.id().to_string());
+    // Load the repository, or only the part that is required for the package `subtree_of`
+    let load_repo_subtree = |subtree_of: Option<PackageName>| -> Result<Repository> {
         let bar = progressbars.bar()?;
-        let repo = Repository::load(repo_path, &bar)
-            .context("Loading the repository")?;
+        let repo = match (repo_head.as_ref(), subtree_of) {
+            (Ok(head), subtree_of) if !cli.is_present("no_repo_cache") => {
+                Repository::load_cached(repo_path, head.clone(), subtree_of.as_ref(), &bar)
+            },
+            (_, Some(root)) => Repository::load_subtree(repo_path, &root, &bar),
+            (_, None) => Repository::load(repo_path, &bar),
+        }
+        .context("Loading the repository")
+        .map_err(crate::error::Error::Repository)?;
         bar.finish_with_message("Repository loading finished");
         Ok(repo)
     };
+    let load_repo = || load_repo_subtree(None);
 
     let db_connection_config = crate::db::DbConnectionConfig::parse(&config, &cli)?;
     match cli.subcommand() {
         Some(("generate-completions", matches)) => generate_completions(matches),
-        Some(("db", matches)) => crate::commands::db(db_connection_config, &config, matches)?,
+        Some(("completions", matches)) => crate::commands::completions(matches)?,
+        Some(("__complete", matches)) => crate::commands::complete(matches, &config, load_repo)
+            .context("__complete command failed")?,
+        Some(("db", matches)) => crate::commands::db(db_connection_config, &config, matches, progressbars.clone(), load_repo).await?,
+        Some(("config", matches)) => {
+            crate::commands::config(db_connection_config, &config, matches)
+                .await
+                .context("config command failed")?
+        }
         Some(("build", matches)) => {
-            let conn = db_connection_config.establish_connection()?;
+            let pool = db_connection_config.establish_pool()?;
 
-            let repo = load_repo()?;
+            let repo = load_repo_subtree(matches.value_of("package_name").map(String::from).map(PackageName::from))?;
 
             crate::commands::build(
                 repo_path,
                 matches,
                 progressbars,
-                conn,
+                pool,
                 &config,
                 repo,
                 repo_path,
@@ -188,7 +214,8 @@ async fn main() -> Result<()> {
 
         Some(("versions-of", matches)) => {
             let repo = load_repo()?;
-            crate::commands::versions_of(matches, repo)
+            let conn = db_connection_config.establish_connection()?;
+            crate::commands::versions_of(matches, repo, conn)
                 .await
                 .context("versions-of command failed")?
         }
@@ -208,6 +235,36 @@ async fn main() -> Result<()> {
                 .context("find-artifact command failed")?
         }
 
+        Some(("explain-rebuild", matches)) => {
+            let repo = load_repo_subtree(matches.value_of("package_name").map(String::from).map(PackageName::from))?;
+            let conn = db_connection_config.establish_connection()?;
+            crate::commands::explain_rebuild(repo_path, matches, progressbars, &config, repo, conn)
+                .await
+                .context("explain-rebuild command failed")?
+        }
+
+        Some(("verify-reproducibility", matches)) => {
+            let repo = load_repo_subtree(matches.value_of("package_name").map(String::from).map(PackageName::from))?;
+            let conn = db_connection_config.establish_connection()?;
+            crate::commands::verify_reproducibility(matches, &config, progressbars, repo, conn)
+                .await
+                .context("verify-reproducibility command failed")?
+        }
+
+        Some(("verify-artifacts", matches)) => {
+            let conn = db_connection_config.establish_connection()?;
+            crate::commands::verify_artifacts(matches, &config, conn)
+                .await
+                .context("verify-artifacts command failed")?
+        }
+
+        Some(("verify-stores", matches)) => {
+            let conn = db_connection_config.establish_connection()?;
+            crate::commands::verify_stores(matches, &config, conn)
+                .await
+                .context("verify-stores command failed")?
+        }
+
         Some(("find-pkg", matches)) => {
             let repo = load_repo()?;
             crate::commands::find_pkg(matches, &config, repo)
@@ -217,17 +274,32 @@ async fn main() -> Result<()> {
 
         Some(("source", matches)) => {
             let repo = load_repo()?;
-            crate::commands::source(matches, &config, repo, progressbars)
+            crate::commands::source(repo_path, matches, &config, db_connection_config, repo, progressbars)
                 .await
                 .context("source command failed")?
         }
 
         Some(("release", matches)) => {
-            crate::commands::release(db_connection_config, &config, matches)
+            crate::commands::release(db_connection_config, &config, progressbars, matches)
                 .await
                 .context("release command failed")?
         }
 
+        Some(("export", matches)) => {
+            let repo = load_repo()?;
+            let conn = db_connection_config.establish_connection()?;
+            crate::commands::export(matches, &config, repo, conn)
+                .await
+                .context("export command failed")?
+        }
+
+        Some(("license-report", matches)) => {
+            let repo = load_repo()?;
+            crate::commands::license_report(matches, &config, repo, db_connection_config)
+                .await
+                .context("license-report command failed")?
+        }
+
         Some(("lint", matches)) => {
             let repo = load_repo()?;
             crate::commands::lint(repo_path, matches, progressbars, &config, repo)
@@ -235,13 +307,38 @@ async fn main() -> Result<()> {
                 .context("lint command failed")?
         }
 
-        Some(("tree-of", matches)) => {
+        Some(("new-version", matches)) => {
             let repo = load_repo()?;
-            crate::commands::tree_of(matches, repo)
+            crate::commands::new_version(repo_path, matches, &config, progressbars, repo)
+                .await
+                .context("new-version command failed")?
+        }
+
+        Some(("repo", matches)) => {
+            if let Some(("migrate", matches)) = matches.subcommand() {
+                crate::commands::repo_migrate(repo_path, matches).context("repo migrate command failed")?
+            } else {
+                let repo = load_repo()?;
+                crate::commands::repo(matches, &config, repo)
+                    .await
+                    .context("repo command failed")?
+            }
+        }
+
+        Some(("tree-of", matches)) => {
+            let repo = load_repo_subtree(matches.value_of("package_name").map(String::from).map(PackageName::from))?;
+            crate::commands::tree_of(matches, &config, repo)
                 .await
                 .context("tree-of command failed")?
         }
 
+        Some(("script-diff", matches)) => {
+            let repo = load_repo()?;
+            crate::commands::script_diff(matches, &config, repo)
+                .await
+                .context("script-diff command failed")?
+        }
+
         Some(("metrics", _)) => {
             let repo = load_repo()?;
             let conn = db_connection_config.establish_connection()?;
@@ -250,8 +347,28 @@ async fn main() -> Result<()> {
                 .context("metrics command failed")?
         }
 
+        Some(("cancel", matches)) => {
+            let conn = db_connection_config.establish_connection()?;
+            crate::commands::cancel(matches, &config, conn)
+                .await
+                .context("cancel command failed")?
+        }
+
+        Some(("tui", matches)) => {
+            let conn = db_connection_config.establish_connection()?;
+            crate::commands::tui(matches, &config, conn)
+                .await
+                .context("tui command failed")?
+        }
+
+        Some(("schedule", matches)) => {
+            crate::commands::schedule(matches, &config)
+                .await
+                .context("schedule command failed")?
+        }
+
         Some(("endpoint", matches)) => {
-            crate::commands::endpoint(matches, &config, progressbars)
+            crate::commands::endpoint(matches, &config, db_connection_config, progressbars)
                 .await
                 .context("endpoint command failed")?
         },
@@ -270,6 +387,51 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Load the configuration of the repository at `repo_path`, with the settings of the user, the
+/// profile and the environment
+fn load_config(repo_path: &Path, cli: &ArgMatches) -> Result<Configuration> {
+    let mut config = ::config::Config::default();
+    config.merge(::config::File::from(repo_path.join("config.toml")).required(true))
+        .context("Failed to load config.toml from repository")?;
+
+    {
+        let xdg = xdg::BaseDirectories::with_prefix("butido")?;
+        let xdg_config_file = xdg.find_config_file("config.toml");
+        if let Some(xdg_config) = xdg_config_file {
+            debug!("Configuration file found with XDG: {}", xdg_config.display());
+            config.merge(::config::File::from(xdg_config).required(false))
+                .context("Failed to load config.toml from XDG configuration directory")?;
+        } else {
+            debug!("No configuration file found with XDG: {}", xdg.get_config_home().display());
+        }
+    }
+
+    {
+        // Repository specific settings have precedence over the settings of the user
+        let repo_config_file = repo_path.join(crate::consts::REPO_CONFIG_OVERLAY_FILE);
+        if repo_config_file.is_file() {
+            debug!("Configuration overlay found in repository: {}", repo_config_file.display());
+            config.merge(::config::File::from(repo_config_file).required(true))
+                .with_context(|| anyhow!("Failed to load {} from repository", crate::consts::REPO_CONFIG_OVERLAY_FILE))?;
+        }
+    }
+
+    // The profile overrides the settings of the files, but not the environment
+    if let Some(profile) = cli.value_of("profile") {
+        debug!("Using configuration profile: {}", profile);
+        let profile = crate::config::ProfileSource::load(&config, profile)
+            .context("Failed to load configuration profile")?;
+        config.merge(profile)?;
+    }
+
+    config.merge(::config::Environment::with_prefix("BUTIDO"))?;
+
+    config.try_into::<NotValidatedConfiguration>()
+        .context("Failed to load Configuration object")?
+        .validate()
+        .context("Failed to validate configuration")
+}
+
 fn generate_completions(matches: &ArgMatches) {
     use clap_generate::generate;
     use clap_generate::generators::{Bash, Elvish, Fish, Zsh};
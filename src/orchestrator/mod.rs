@@ -12,5 +12,18 @@
 mod orchestrator;
 pub use orchestrator::*;
 
+mod report;
+pub use report::*;
+
+mod resume;
+pub use resume::*;
+
+mod status;
+pub use status::*;
+
+mod tree;
+pub use tree::JobState;
+pub use tree::tree_layout;
+
 mod util;
 
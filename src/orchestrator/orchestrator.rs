@@ -12,6 +12,7 @@
 
 use std::borrow::Borrow;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -19,12 +20,18 @@ use anyhow::Error;
 use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
-use diesel::PgConnection;
+use diesel::BelongingToDsl;
+use diesel::ExpressionMethods;
+use diesel::OptionalExtension;
+use diesel::QueryDsl;
+use diesel::RunQueryDsl;
 use git2::Repository;
 use indicatif::ProgressBar;
 use itertools::Itertools;
 use log::debug;
+use log::info;
 use log::trace;
+use log::warn;
 use resiter::FilterMap;
 use tokio::sync::RwLock;
 use tokio::sync::mpsc::Receiver;
@@ -34,19 +41,46 @@ use typed_builder::TypedBuilder;
 use uuid::Uuid;
 
 use crate::config::Configuration;
+use crate::db::DbPool;
 use crate::db::models as dbmodels;
 use crate::endpoint::EndpointConfiguration;
+use crate::endpoint::EndpointPool;
 use crate::endpoint::EndpointScheduler;
 use crate::filestore::ArtifactPath;
+use crate::filestore::path::StoreRoot;
 use crate::filestore::ReleaseStore;
 use crate::filestore::StagingStore;
 use crate::job::Dag;
+use crate::job::Job;
 use crate::job::JobDefinition;
 use crate::job::RunnableJob;
+use crate::log::LiveLog;
+use crate::orchestrator::JobReports;
+use crate::orchestrator::JobStateRecorder;
+use crate::orchestrator::ResumedJob;
+use crate::orchestrator::StatusJob;
+use crate::orchestrator::SubmitProgress;
+use crate::orchestrator::tree::JobState;
 use crate::orchestrator::util::*;
+use crate::package::PackageName;
+use crate::schema;
 use crate::source::SourceCache;
 use crate::util::EnvironmentVariableName;
 use crate::util::progress::ProgressBars;
+use crate::util::progress::ProgressGroup;
+
+/// Jobs that are registered as running for longer than this are ignored when looking for
+/// equivalent jobs of other submits, because they are most likely left over from a killed process
+pub const RUNNING_JOB_STALE_AFTER_HOURS: i64 = 24;
+
+/// How often to check whether an equivalent job of another submit finished
+const RUNNING_JOB_POLL_INTERVAL_SECS: u64 = 5;
+
+/// How often to check whether the submit was canceled with "butido cancel"
+const CANCEL_POLL_INTERVAL_SECS: u64 = 5;
+
+/// How many earlier jobs of a package the expected duration of its job is computed from
+pub const EXPECTED_DURATION_JOBS: i64 = 10;
 
 #[cfg_attr(doc, aquamarine::aquamarine)]
 /// The Orchestrator
@@ -58,9 +92,9 @@ use crate::util::progress::ProgressBars;
 /// Because of the implementation of [JobTask], the work happens in
 /// form of a tree, propagating results to the root (which is held by the Orchestrator itself).
 /// The Orchestrator also holds the connection to the database, the access to the filesystem via
-/// the [ReleaseStore](crate::filestore::ReleaseStore) and the
-/// [StagingStore](crate::filestore::StagingStore), which are merged into a
-/// [MergedStores](crate::filestore::MergedStores) object.
+/// the [ReleaseStore](crate::filestore::ReleaseStore)s, the
+/// [StagingStore](crate::filestore::StagingStore) of the submit and the additional (read-only)
+/// staging stores, which are searched in this order: staging, additional staging, release.
 ///
 ///
 /// # Control Flow
@@ -159,12 +193,26 @@ pub struct Orchestrator<'a> {
     scheduler: EndpointScheduler,
     progress_generator: ProgressBars,
     staging_store: Arc<RwLock<StagingStore>>,
+    additional_staging_stores: Vec<Arc<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     source_cache: SourceCache,
     jobdag: Dag,
     config: &'a Configuration,
     repository: Repository,
-    database: Arc<PgConnection>,
+    database: DbPool,
+    progress_tree: bool,
+    job_reports: JobReports,
+    resume: bool,
+    no_deps: bool,
+    substituted: HashSet<PackageName>,
+    progress_group: Option<ProgressGroup>,
+    concurrent: bool,
+    status_file: Option<PathBuf>,
+    hermetic: bool,
+
+    /// How long the jobs are expected to run, in seconds, if earlier jobs of their packages are
+    /// known
+    expected_durations: HashMap<Uuid, f64>,
 }
 
 #[derive(TypedBuilder)]
@@ -172,31 +220,96 @@ pub struct OrchestratorSetup<'a> {
     progress_generator: ProgressBars,
     endpoint_config: Vec<EndpointConfiguration>,
     staging_store: Arc<RwLock<StagingStore>>,
+    additional_staging_stores: Vec<Arc<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
     source_cache: SourceCache,
     jobdag: Dag,
-    database: Arc<PgConnection>,
+    database: DbPool,
     submit: dbmodels::Submit,
     log_dir: Option<PathBuf>,
     config: &'a Configuration,
     repository: Repository,
+
+    /// Show the progress bars as a tree, mirroring the dependencies of the jobs
+    #[builder(default)]
+    progress_tree: bool,
+
+    /// Stream the logs of the running jobs to the clients of this live log
+    #[builder(default)]
+    live_log: Option<LiveLog>,
+
+    /// Where the states and artifacts of the jobs are recorded
+    #[builder(default)]
+    job_reports: JobReports,
+
+    /// Do not run the jobs again that finished in earlier runs of the submit
+    #[builder(default)]
+    resume: bool,
+
+    /// Do not build the dependencies of the package, but use their artifacts from the stores
+    #[builder(default)]
+    no_deps: bool,
+
+    /// Do not build these packages, but use their released artifacts (`build --exclude
+    /// --substitute-released`)
+    #[builder(default)]
+    substituted: HashSet<PackageName>,
+
+    /// Schedule the jobs on the endpoints of this pool, which other submits that run at the same
+    /// time use as well, instead of setting up the endpoints of `endpoint_config`
+    #[builder(default)]
+    endpoint_pool: Option<Arc<EndpointPool>>,
+
+    /// Show the progress bars of the jobs in this group, below the ones of other submits
+    #[builder(default)]
+    progress_group: Option<ProgressGroup>,
+
+    /// Other submits run at the same time, so the termination signals are left to the caller once
+    /// the jobs of this submit finished
+    #[builder(default)]
+    concurrent: bool,
+
+    /// Write the progress of the submit to this file while the jobs run
+    #[builder(default)]
+    status_file: Option<PathBuf>,
+
+    /// Build the jobs hermetically and only reuse artifacts of hermetic builds
+    #[builder(default)]
+    hermetic: bool,
 }
 
 impl<'a> OrchestratorSetup<'a> {
     pub async fn setup(self) -> Result<Orchestrator<'a>> {
-        let scheduler = EndpointScheduler::setup(
-            self.endpoint_config,
+        let pool = match self.endpoint_pool {
+            Some(pool) => pool,
+            None => EndpointPool::setup(self.endpoint_config, self.config.docker()).await.map_err(crate::error::Error::Endpoint)?,
+        };
+        let expected_durations = expected_durations(&self.database, &self.jobdag)?;
+        let scheduler = EndpointScheduler::with_pool(
+            pool,
             self.staging_store.clone(),
+            self.additional_staging_stores.clone(),
             self.release_stores.clone(),
             self.database.clone(),
             self.submit.clone(),
             self.log_dir,
+            self.config.docker(),
         )
-        .await?;
+        .with_live_log(self.live_log)
+        .with_notification_commands(self.config.notification_commands().clone())
+        .with_status(self.progress_generator.status())
+        .with_max_log_size(self.config.max_log_size().map(|size| size.bytes()))
+        .with_expected_durations(expected_durations.clone());
+        let scheduler = if *self.config.offload_logs() {
+            scheduler.with_offloaded_logs(self.config.log_dir().clone(), *self.config.offloaded_log_lines())
+        } else {
+            scheduler
+        };
 
         Ok(Orchestrator {
             scheduler,
             staging_store: self.staging_store.clone(),
+            additional_staging_stores: self.additional_staging_stores.clone(),
             release_stores: self.release_stores.clone(),
             progress_generator: self.progress_generator,
             source_cache: self.source_cache,
@@ -204,6 +317,16 @@ impl<'a> OrchestratorSetup<'a> {
             config: self.config,
             database: self.database,
             repository: self.repository,
+            progress_tree: self.progress_tree,
+            job_reports: self.job_reports,
+            resume: self.resume,
+            no_deps: self.no_deps,
+            substituted: self.substituted,
+            progress_group: self.progress_group,
+            concurrent: self.concurrent,
+            status_file: self.status_file,
+            hermetic: self.hermetic,
+            expected_durations,
         })
     }
 }
@@ -212,13 +335,13 @@ impl<'a> OrchestratorSetup<'a> {
 ///
 /// Represents a result that came from the run of a job inside a container
 ///
-/// It is either a list of artifacts with the UUID of the job they were produced by,
+/// It is either the list of the UUIDs of the jobs that finished (the job and all jobs below it),
 /// or a UUID and an Error object, where the UUID is the job UUID and the error is the
 /// anyhow::Error that was issued.
 ///
-/// The artifacts are encapsulated into a `ProducedArtifact`, see the documentation of the type for
-/// why.
-type JobResult = std::result::Result<HashMap<Uuid, Vec<ProducedArtifact>>, HashMap<Uuid, Error>>;
+/// Only the UUIDs are passed up the tree, the artifacts of the jobs are in the
+/// `ProducedArtifacts` of the tree.
+type JobResult = std::result::Result<Vec<Uuid>, HashMap<Uuid, Error>>;
 
 /// A type that represents whether an artifact was built or reused from an old job
 ///
@@ -248,6 +371,65 @@ impl ProducedArtifact {
     }
 }
 
+/// Merge the `artifacts` of the job `job` into the artifacts of the jobs in `into`
+///
+/// The artifacts are passed up the tree with the artifacts of all jobs below, so the same
+/// artifact arrives on every path through the tree and could be reused by several jobs. Every
+/// artifact path is kept once; if it was built by any of the jobs, it counts as built.
+fn merge_produced_artifacts(into: &mut HashMap<Uuid, Vec<ProducedArtifact>>, job: Uuid, artifacts: Vec<ProducedArtifact>) {
+    let mut known = into.values_mut()
+        .flat_map(|v| v.iter_mut())
+        .map(|a| (Borrow::<ArtifactPath>::borrow(a).clone(), a))
+        .collect::<HashMap<_, _>>();
+
+    let mut new = vec![];
+    for artifact in artifacts {
+        let path: &ArtifactPath = artifact.borrow();
+        match known.get_mut(path) {
+            Some(existing) => {
+                if artifact.was_build() && !existing.was_build() {
+                    **existing = artifact;
+                }
+            },
+            None => {
+                if !new.iter().any(|a: &ProducedArtifact| Borrow::<ArtifactPath>::borrow(a) == path) {
+                    new.push(artifact);
+                }
+            },
+        }
+    }
+    into.entry(job).or_default().extend(new);
+}
+
+/// The artifacts of the jobs of a tree that finished
+///
+/// Every job adds its artifacts once, the jobs above it only get the UUIDs of the jobs below them
+/// and look up the artifacts when they need them.
+#[derive(Clone, Default)]
+struct ProducedArtifacts(Arc<std::sync::Mutex<HashMap<Uuid, Vec<ProducedArtifact>>>>);
+
+impl ProducedArtifacts {
+    fn insert(&self, job: Uuid, artifacts: Vec<ProducedArtifact>) -> Result<()> {
+        self.0.lock()
+            .map_err(|_| anyhow!("Lock poisoned"))?
+            .insert(job, artifacts);
+        Ok(())
+    }
+
+    /// The artifacts of the `jobs`, every artifact path once
+    fn of_jobs<'a>(&self, jobs: impl IntoIterator<Item = &'a Uuid>) -> Result<HashMap<Uuid, Vec<ProducedArtifact>>> {
+        let produced = self.0.lock().map_err(|_| anyhow!("Lock poisoned"))?;
+        let mut artifacts = HashMap::new();
+        for job in jobs {
+            let of_job = produced.get(job)
+                .cloned()
+                .ok_or_else(|| anyhow!("No artifacts recorded for finished job {}", job))?;
+            merge_produced_artifacts(&mut artifacts, *job, of_job);
+        }
+        Ok(artifacts)
+    }
+}
+
 impl Borrow<ArtifactPath> for ProducedArtifact {
     fn borrow(&self) -> &ArtifactPath  {
         match self {
@@ -257,13 +439,323 @@ impl Borrow<ArtifactPath> for ProducedArtifact {
     }
 }
 
+/// The environment that artifacts replacing `job` must have been built with
+///
+/// Use the environment of the job definition, as it appears in the job DAG.
+///
+/// This is because we do not have access to the commandline-passed (additional)
+/// environment variables at this point. But using the JobResource::env() variables
+/// works as well.
+fn reuse_env(
+    job: &Job,
+    git_author_env: Option<&(EnvironmentVariableName, String)>,
+    git_commit_env: Option<&(EnvironmentVariableName, String)>,
+) -> Vec<(EnvironmentVariableName, String)> {
+    job.resources()
+        .iter()
+        .filter_map(crate::job::JobResource::env)
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .chain(git_author_env.cloned().into_iter())
+        .chain(git_commit_env.cloned().into_iter())
+        .collect()
+}
+
+/// Find artifacts of earlier builds that can be used instead of running `job`, preferring the
+/// ones in the staging stores
+///
+/// This blocks on the database lookup.
+#[allow(clippy::too_many_arguments)]
+fn find_replacement_artifacts(
+    database_connection: &diesel::PgConnection,
+    config: &Configuration,
+    job: &Job,
+    image_digest: Option<&str>,
+    env: &[(EnvironmentVariableName, String)],
+    staging_store: &StagingStore,
+    additional_staging_stores: &[Arc<StagingStore>],
+    release_stores: &[Arc<ReleaseStore>],
+    hermetic: bool,
+) -> Result<Vec<ArtifactPath>> {
+    let replacement_artifacts = crate::db::FindArtifacts::builder()
+        .database_connection(database_connection)
+        .config(config)
+        .package(job.package())
+        .release_stores(release_stores)
+        .image_name(Some(job.image()))
+        .image_digest(image_digest)
+
+        // We can simply pass the staging store here, because it doesn't hurt. There are
+        // two scenarios:
+        //
+        // 1. We are in a fresh build for a package. In this case, the artifacts for this
+        //    very build are not in there yet, and there won't be any artifacts from the
+        //    staging store (possibly from the release store, which would be fine).
+        // 2. We are in a re-build, where the user passed the staging store to the build
+        //    subcommand. In this case, there might be an artifact for this job in the
+        //    staging store. In this case, we want to use it as a replacement, of course.
+        //
+        // The fact that released artifacts are returned prefferably from this function
+        // call does not change anything, because if there is an artifact that's a released
+        // one that matches this job, we should use it anyways.
+        .staging_store(Some(staging_store))
+        .additional_staging_stores(additional_staging_stores)
+        .env_filter(env)
+        .script_filter(true)
+        .hermetic_only(hermetic)
+        .build()
+        .run()?;
+
+    debug!("[{}]: Found {} replacement artifacts", job.uuid(), replacement_artifacts.len());
+    trace!("[{}]: Found replacement artifacts: {:?}", job.uuid(), replacement_artifacts);
+    let artifacts = replacement_artifacts
+        .into_iter()
+
+        // First of all, we sort by whether the artifact path is in the staging store,
+        // because we prefer staging store artifacts at this point.
+        .sorted_by(|(p1, _), (p2, _)| {
+            let r1 = p1.is_in_staging_store(staging_store);
+            let r2 = p2.is_in_staging_store(staging_store);
+            r1.cmp(&r2)
+        })
+
+        // We don't need duplicates here, so remove them by making the iterator unique
+        // If we have two artifacts that are the same, the one in the staging store will be
+        // preffered in the next step
+        .unique_by(|tpl| tpl.0.artifact_path().clone())
+
+        // Fetch the artifact from the staging store, if there is one.
+        // If there is none, try the release store.
+        // If there is none, there won't be a replacement artifact
+        .filter_map(|(full_artifact_path, _)| {
+            trace!("Searching for {:?} in stores", full_artifact_path.display());
+            if let Some(ap) = staging_store.get(full_artifact_path.artifact_path()) {
+                Some(ap.clone())
+            } else if let Some(ap) = additional_staging_stores
+                .iter()
+                .find_map(|s| s.get(full_artifact_path.artifact_path()))
+            {
+                Some(ap.clone())
+            } else {
+                release_stores
+                    .iter()
+                    .find_map(|rs| rs.get(full_artifact_path.artifact_path()))
+                    .cloned()
+            }
+        })
+        .collect();
+    Ok(artifacts)
+}
+
+/// Find the released artifacts of the package of `job`, that were built in the same image and with
+/// the same environment
+///
+/// Unlike `find_replacement_artifacts()`, the script of the job is not compared, because the
+/// artifacts are used instead of a package that cannot be built.
+fn find_released_artifacts(
+    database_connection: &diesel::PgConnection,
+    config: &Configuration,
+    job: &Job,
+    image_digest: Option<&str>,
+    env: &[(EnvironmentVariableName, String)],
+    release_stores: &[Arc<ReleaseStore>],
+    hermetic: bool,
+) -> Result<Vec<ArtifactPath>> {
+    let artifacts = crate::db::FindArtifacts::builder()
+        .database_connection(database_connection)
+        .config(config)
+        .package(job.package())
+        .release_stores(release_stores)
+        .image_name(Some(job.image()))
+        .image_digest(image_digest)
+        .env_filter(env)
+        .script_filter(false)
+        .hermetic_only(hermetic)
+        .build()
+        .run()?
+        .into_iter()
+        .filter(|(_, released)| released.is_some())
+        .unique_by(|(path, _)| path.artifact_path().clone())
+        .filter_map(|(path, _)| {
+            release_stores
+                .iter()
+                .find_map(|rs| rs.get(path.artifact_path()))
+                .cloned()
+        })
+        .collect();
+    Ok(artifacts)
+}
+
 impl<'a> Orchestrator<'a> {
+    /// The endpoints the jobs are scheduled on, to schedule the jobs of other submits that run at
+    /// the same time on them as well
+    pub fn endpoint_pool(&self) -> Arc<EndpointPool> {
+        self.scheduler.pool().clone()
+    }
+
     pub async fn run(self, output: &mut Vec<ArtifactPath>) -> Result<HashMap<Uuid, Error>> {
         let (results, errors) = self.run_tree().await?;
         output.extend(results.into_iter());
         Ok(errors)
     }
 
+    /// Wait until the submit is canceled, by a cancellation that is newer than `earlier`
+    ///
+    /// Failing to check the cancellations only results in a warning.
+    async fn wait_for_cancellation(&self, earlier: Option<i32>) {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(CANCEL_POLL_INTERVAL_SECS)).await;
+            let submit = self.scheduler.submit().clone();
+            let latest = crate::db::with_connection(&self.database, move |conn| {
+                dbmodels::SubmitCancellation::latest(conn, &submit)
+            })
+            .await;
+
+            match latest {
+                Ok(Some(cancellation)) if Some(cancellation.id) != earlier => return,
+                Ok(_) => {},
+                Err(e) => warn!("Checking whether the submit was canceled failed: {:#}", e),
+            }
+        }
+    }
+
+    /// Find the artifacts of all jobs except the root job in the stores, so that only the root job
+    /// is built
+    ///
+    /// The jobs in `skipped` are skipped. Fails with the list of all jobs no artifacts were found
+    /// for.
+    async fn find_prebuilt_dependencies(
+        &self,
+        skipped: &HashSet<Uuid>,
+        git_author_env: Option<&(EnvironmentVariableName, String)>,
+        git_commit_env: Option<&(EnvironmentVariableName, String)>,
+    ) -> Result<HashMap<Uuid, Vec<ArtifactPath>>> {
+        let dependencies = self.jobdag
+            .iter()
+            .flat_map(|jobdef| jobdef.dependencies)
+            .filter(|uuid| !skipped.contains(uuid))
+            .collect::<HashSet<_>>();
+
+        let (prebuilt, mut missing) = self.find_prebuilt_artifacts(&dependencies, false, git_author_env, git_commit_env).await?;
+        if missing.is_empty() {
+            Ok(prebuilt)
+        } else {
+            missing.sort();
+            Err(anyhow!("No artifacts found for {} dependencies, which are not built with --no-deps: {}",
+                missing.len(), missing.join(", ")))
+        }
+    }
+
+    /// Find the released artifacts of the jobs of the packages that are substituted
+    ///
+    /// The jobs in `skipped` are skipped. Fails with the list of all jobs no released artifacts
+    /// were found for.
+    async fn find_substituted_artifacts(
+        &self,
+        skipped: &HashSet<Uuid>,
+        git_author_env: Option<&(EnvironmentVariableName, String)>,
+        git_commit_env: Option<&(EnvironmentVariableName, String)>,
+    ) -> Result<HashMap<Uuid, Vec<ArtifactPath>>> {
+        let jobs = self.jobdag
+            .iter()
+            .filter(|jobdef| self.substituted.contains(jobdef.job.package().name()))
+            .map(|jobdef| *jobdef.job.uuid())
+            .filter(|uuid| !skipped.contains(uuid))
+            .collect::<HashSet<_>>();
+
+        let (substituted, mut missing) = self.find_prebuilt_artifacts(&jobs, true, git_author_env, git_commit_env).await?;
+        if missing.is_empty() {
+            for jobdef in self.jobdag.iter().filter(|jobdef| substituted.contains_key(jobdef.job.uuid())) {
+                info!("Excluded {} {}, using its released artifacts",
+                    jobdef.job.package().name(), jobdef.job.package().version());
+            }
+            Ok(substituted)
+        } else {
+            missing.sort();
+            Err(anyhow!("No released artifacts found for {} excluded packages: {}",
+                missing.len(), missing.join(", ")))
+        }
+    }
+
+    /// Find the artifacts of the jobs `jobs` in the stores, only the released ones if
+    /// `released_only`
+    ///
+    /// Returns the artifacts and the descriptions of the jobs no artifacts were found for.
+    async fn find_prebuilt_artifacts(
+        &self,
+        jobs: &HashSet<Uuid>,
+        released_only: bool,
+        git_author_env: Option<&(EnvironmentVariableName, String)>,
+        git_commit_env: Option<&(EnvironmentVariableName, String)>,
+    ) -> Result<(HashMap<Uuid, Vec<ArtifactPath>>, Vec<String>)> {
+        let staging_store = self.staging_store.read().await;
+        let mut prebuilt = HashMap::new();
+        let mut missing = vec![];
+        for jobdef in self.jobdag.iter().filter(|jobdef| jobs.contains(jobdef.job.uuid())) {
+            let package = jobdef.job.package();
+            let image_digest = if self.config.docker().require_image_digest_match() {
+                match self.scheduler.image_digest(jobdef.job.image()).await {
+                    Some(digest) => Some(digest),
+                    None => {
+                        missing.push(format!("{} {} (the ID of image {} is unknown)", package.name(), package.version(), jobdef.job.image()));
+                        continue
+                    },
+                }
+            } else {
+                None
+            };
+
+            let env = reuse_env(jobdef.job, git_author_env, git_commit_env);
+            let artifacts = tokio::task::block_in_place(|| {
+                let database_connection = self.database.get()?;
+                if released_only {
+                    find_released_artifacts(
+                        &database_connection,
+                        self.config,
+                        jobdef.job,
+                        image_digest.as_deref(),
+                        &env,
+                        &self.release_stores,
+                        self.hermetic,
+                    )
+                } else {
+                    find_replacement_artifacts(
+                        &database_connection,
+                        self.config,
+                        jobdef.job,
+                        image_digest.as_deref(),
+                        &env,
+                        &staging_store,
+                        &self.additional_staging_stores,
+                        &self.release_stores,
+                        self.hermetic,
+                    )
+                }
+            })?;
+
+            if artifacts.is_empty() {
+                missing.push(format!("{} {}", package.name(), package.version()));
+            } else {
+                prebuilt.insert(*jobdef.job.uuid(), artifacts);
+            }
+        }
+
+        Ok((prebuilt, missing))
+    }
+
+    /// The progress of the submit, to show it and write it to the status file
+    fn progress(&self) -> SubmitProgress {
+        let jobs = self.jobdag
+            .iter()
+            .map(|jobdef| StatusJob {
+                uuid: *jobdef.job.uuid(),
+                package_name: jobdef.job.package().name().to_string(),
+                package_version: jobdef.job.package().version().to_string(),
+                expected_secs: self.expected_durations.get(jobdef.job.uuid()).copied(),
+            })
+            .collect();
+        SubmitProgress::new(self.scheduler.submit().uuid, jobs)
+    }
+
     async fn run_tree(self) -> Result<(Vec<ArtifactPath>, HashMap<Uuid, Error>)> {
         let multibar = Arc::new({
             let mp = indicatif::MultiProgress::new();
@@ -272,6 +764,10 @@ impl<'a> Orchestrator<'a> {
             }
             mp
         });
+        let add_bar = |bar: ProgressBar| match self.progress_group.as_ref() {
+            Some(group) => group.add(bar),
+            None => multibar.add(bar),
+        };
 
         let git_author_env = {
             self.config
@@ -300,6 +796,90 @@ impl<'a> Orchestrator<'a> {
                 .transpose()?
         };
 
+        // Pull missing images before the first job runs, so that no job has to wait for the
+        // download of its image
+        if self.config.docker().pull_missing_images() {
+            let images = self.jobdag
+                .iter()
+                .map(|jobdef| jobdef.job.image().clone())
+                .unique()
+                .collect::<Vec<_>>();
+
+            self.scheduler
+                .pull_missing_images(&images, || Ok(add_bar(self.progress_generator.bar()?)))
+                .await
+                .context("Pulling missing images")?;
+        }
+
+        // The progress of the whole submit is shown above the bars of its jobs
+        let progress_line = add_bar(self.progress_generator.message_bar()?);
+
+        // In the tree view, the bars have to be added in the order of the tree, so they are created
+        // upfront
+        let mut tree_bars = if self.progress_tree {
+            let dependencies = self.jobdag
+                .iter()
+                .map(|jobdef| (*jobdef.job.uuid(), jobdef.dependencies))
+                .collect::<HashMap<_, _>>();
+            let root = dependencies.keys()
+                .find(|id| !dependencies.values().any(|deps| deps.contains(id)))
+                .cloned()
+                .ok_or_else(|| anyhow!("Failed to find root job"))?;
+
+            crate::orchestrator::tree::tree_layout(root, &dependencies)
+                .into_iter()
+                .map(|(id, prefix)| {
+                    let bar = add_bar(self.progress_generator.bar_with_prefix()?);
+                    Ok((id, (bar, prefix)))
+                })
+                .collect::<Result<HashMap<_, _>>>()?
+        } else {
+            HashMap::new()
+        };
+
+        // The job graph is recorded, so that the submit can be resumed if it does not finish.
+        // When resuming, the jobs that finished in the earlier runs are found before that.
+        let (state_recorder, mut resumed_jobs, earlier_cancellation) = {
+            let submit = self.scheduler.submit();
+            let staging_store = self.staging_store.read().await;
+            tokio::task::block_in_place(|| -> Result<_> {
+                let conn = self.database.get()?;
+                let resumed_jobs = if self.resume {
+                    crate::orchestrator::resumed_jobs(&conn, submit, &self.jobdag, |artifact| {
+                        staging_store.get(artifact)
+                            .or_else(|| self.additional_staging_stores.iter().find_map(|s| s.get(artifact)))
+                            .or_else(|| self.release_stores.iter().find_map(|s| s.get(artifact)))
+                            .cloned()
+                    })?
+                } else {
+                    HashMap::new()
+                };
+
+                let recorder = JobStateRecorder::record_graph(self.database.clone(), &conn, submit, &self.jobdag)?;
+
+                // A resumed submit might have been canceled in an earlier run
+                let earlier_cancellation = dbmodels::SubmitCancellation::latest(&conn, submit)?.map(|c| c.id);
+                Ok((recorder, resumed_jobs, earlier_cancellation))
+            })?
+        };
+        if self.resume {
+            info!("Resuming submit {}, {} jobs finished in earlier runs", self.scheduler.submit().uuid, resumed_jobs.len());
+        }
+
+        let resumed = resumed_jobs.keys().copied().collect::<HashSet<_>>();
+        let mut prebuilt_dependencies = self.find_substituted_artifacts(&resumed, git_author_env.as_ref(), git_commit_env.as_ref()).await?;
+        if self.no_deps {
+            let skipped = resumed.iter().chain(prebuilt_dependencies.keys()).copied().collect::<HashSet<_>>();
+            let prebuilt = self.find_prebuilt_dependencies(&skipped, git_author_env.as_ref(), git_commit_env.as_ref()).await?;
+            prebuilt_dependencies.extend(prebuilt);
+        }
+
+        // Jobs on the longest remaining dependency chain are scheduled first, because they
+        // determine how long the whole submit takes
+        let critical_path_lengths = self.jobdag.critical_path_lengths();
+        let live_tasks = LiveTasks::default();
+        let produced_artifacts = ProducedArtifacts::default();
+
         // For each job in the jobdag, built a tuple with
         //
         // 1. The receiver that is used by the task to receive results from dependency tasks from
@@ -312,27 +892,41 @@ impl<'a> Orchestrator<'a> {
         let jobs: Vec<(Receiver<JobResult>, TaskPreparation, Sender<JobResult>, _)> = self.jobdag
             .iter()
             .map(|jobdef| {
-                // We initialize the channel with 100 elements here, as there is unlikely a task
-                // that depends on 100 other tasks.
-                // Either way, this might be increased in future.
-                let (sender, receiver) = tokio::sync::mpsc::channel(100);
+                // Every dependency sends exactly one result, so the dependencies never wait for
+                // the task to receive
+                let (sender, receiver) = tokio::sync::mpsc::channel(jobdef.dependencies.len().max(1));
 
                 trace!("Creating TaskPreparation object for job {}", jobdef.job.uuid());
-                let bar = self.progress_generator.bar()?;
-                let bar = multibar.add(bar);
+                let (bar, tree_prefix) = match tree_bars.remove(jobdef.job.uuid()) {
+                    Some((bar, prefix)) => (bar, Some(prefix)),
+                    None => (add_bar(self.progress_generator.bar()?), None),
+                };
                 bar.set_length(100);
+                let priority = critical_path_lengths.get(jobdef.job.uuid()).copied().unwrap_or(0);
+                let resumed = resumed_jobs.remove(jobdef.job.uuid());
+                let prebuilt = prebuilt_dependencies.remove(jobdef.job.uuid());
                 let tp = TaskPreparation {
                     jobdef,
 
                     bar,
+                    tree_prefix,
+                    priority,
                     config: self.config,
                     git_author_env: git_author_env.as_ref(),
                     git_commit_env: git_commit_env.as_ref(),
                     source_cache: &self.source_cache,
                     scheduler: &self.scheduler,
                     staging_store: self.staging_store.clone(),
+                    additional_staging_stores: self.additional_staging_stores.clone(),
                     release_stores: self.release_stores.clone(),
                     database: self.database.clone(),
+                    job_reports: self.job_reports.clone(),
+                    state_recorder: state_recorder.clone(),
+                    live_tasks: live_tasks.clone(),
+                    produced_artifacts: produced_artifacts.clone(),
+                    resumed,
+                    prebuilt,
+                    hermetic: self.hermetic,
                 };
 
                 Ok((receiver, tp, sender, std::cell::RefCell::new(None as Option<Vec<Sender<JobResult>>>)))
@@ -398,8 +992,8 @@ impl<'a> Orchestrator<'a> {
             .ok_or_else(|| anyhow!("Failed to find root task"))?;
         trace!("Root job id = {}", root_job_id);
 
-        // Create a sender and a receiver for the root of the tree
-        let (root_sender, mut root_receiver) = tokio::sync::mpsc::channel(100);
+        // Create a sender and a receiver for the root of the tree, which sends one result
+        let (root_sender, mut root_receiver) = tokio::sync::mpsc::channel(1);
 
         // Make all prepared jobs into real jobs and run them
         //
@@ -421,12 +1015,51 @@ impl<'a> Orchestrator<'a> {
             .collect::<futures::stream::FuturesUnordered<_>>();
         debug!("Built {} jobs", running_jobs.len());
 
-        running_jobs.collect::<Result<()>>().await?;
+        // The progress of the submit is updated until all jobs finished
+        let progress = self.progress();
+        let jobs_finished = async {
+            tokio::select! {
+                result = running_jobs.collect::<Result<()>>() => result,
+                () = progress.update_periodically(&self.job_reports, &progress_line, self.status_file.as_deref()) => {
+                    unreachable!("The progress is updated until the jobs finished")
+                },
+            }
+        };
+        tokio::pin!(jobs_finished);
+        let terminated = tokio::select! {
+            result = &mut jobs_finished => result.map(|_| None),
+            () = self.wait_for_cancellation(earlier_cancellation) => {
+                warn!("Submit {} was canceled, stopping its jobs", self.scheduler.submit().uuid);
+                self.scheduler.cancel();
+                jobs_finished.await.map(|_| None)
+            },
+            signal = crate::util::signal::termination() => {
+                let signal = signal?;
+                warn!("Received {}, stopping the jobs of submit {} (again to exit immediately)", signal, self.scheduler.submit().uuid);
+                self.scheduler.cancel();
+                crate::util::signal::exit_on_termination();
+                jobs_finished.await.map(|_| Some(signal))
+            },
+        };
+        progress_line.finish_with_message(progress.line(&self.job_reports));
+        if let Some(status_file) = self.status_file.as_ref() {
+            if let Err(e) = progress.write_status_file(status_file, &self.job_reports).await {
+                warn!("Writing the status file failed: {:#}", e);
+            }
+        }
+        if !matches!(terminated, Ok(Some(_))) && !self.concurrent {
+            // Signals are not handled by stopping the jobs anymore, so they have to exit again
+            crate::util::signal::exit_on_termination();
+        }
+        if let Some(signal) = terminated? {
+            return Err(Error::from(crate::util::signal::Terminated(signal)))
+        }
         trace!("All jobs finished");
         match root_receiver.recv().await {
             None                     => Err(anyhow!("No result received...")),
-            Some(Ok(results)) => {
-                let results = results.into_iter()
+            Some(Ok(jobs)) => {
+                let results = produced_artifacts.of_jobs(jobs.iter())?
+                    .into_iter()
                     .flat_map(|tpl| tpl.1.into_iter())
                     .map(ProducedArtifact::unpack)
                     .collect();
@@ -437,6 +1070,29 @@ impl<'a> Orchestrator<'a> {
     }
 }
 
+/// How long the jobs of `jobdag` are expected to run, from the durations of the last successful
+/// jobs of their packages in their images
+fn expected_durations(database: &DbPool, jobdag: &Dag) -> Result<HashMap<Uuid, f64>> {
+    tokio::task::block_in_place(|| {
+        let conn = database.get()?;
+        let mut durations = HashMap::new();
+        for jobdef in jobdag.iter() {
+            let package = jobdef.job.package();
+            let expected_secs = dbmodels::JobPhase::average_job_duration_secs(
+                &conn,
+                package.name(),
+                package.version(),
+                jobdef.job.image().as_ref(),
+                EXPECTED_DURATION_JOBS,
+            )?;
+            if let Some(secs) = expected_secs {
+                durations.insert(*jobdef.job.uuid(), secs);
+            }
+        }
+        Ok(durations)
+    })
+}
+
 /// Helper type: A task with all things attached, but not sender and receivers
 ///
 /// This is the preparation of the JobTask, but without the associated sender and receiver, because
@@ -447,6 +1103,8 @@ struct TaskPreparation<'a> {
     jobdef: JobDefinition<'a>,
 
     bar: ProgressBar,
+    tree_prefix: Option<String>,
+    priority: usize,
 
     config: &'a Configuration,
     git_author_env: Option<&'a (EnvironmentVariableName, String)>,
@@ -454,10 +1112,23 @@ struct TaskPreparation<'a> {
     source_cache: &'a SourceCache,
     scheduler: &'a EndpointScheduler,
     staging_store: Arc<RwLock<StagingStore>>,
+    additional_staging_stores: Vec<Arc<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
-    database: Arc<PgConnection>,
+    database: DbPool,
+    job_reports: JobReports,
+    state_recorder: JobStateRecorder,
+    live_tasks: LiveTasks,
+    produced_artifacts: ProducedArtifacts,
+    resumed: Option<ResumedJob>,
+    prebuilt: Option<Vec<ArtifactPath>>,
+    hermetic: bool,
 }
 
+/// The jobs whose tasks did not finish yet
+///
+/// A task that waits for its dependencies uses this to find dependencies that will never report.
+type LiveTasks = Arc<std::sync::Mutex<HashSet<Uuid>>>;
+
 /// Helper type for executing one job task
 ///
 /// This type represents a task for a job that can immediately be executed (see `JobTask::run()`).
@@ -466,14 +1137,35 @@ struct JobTask<'a> {
 
     bar: ProgressBar,
 
+    /// The prefix that draws the tree in front of the bar, if the tree view is used
+    tree_prefix: Option<String>,
+
+    /// The length of the longest dependency chain that waits for this job, used as scheduling
+    /// priority
+    priority: usize,
+
     config: &'a Configuration,
     git_author_env: Option<&'a (EnvironmentVariableName, String)>,
     git_commit_env: Option<&'a (EnvironmentVariableName, String)>,
     source_cache: &'a SourceCache,
     scheduler: &'a EndpointScheduler,
     staging_store: Arc<RwLock<StagingStore>>,
+    additional_staging_stores: Vec<Arc<StagingStore>>,
     release_stores: Vec<Arc<ReleaseStore>>,
-    database: Arc<PgConnection>,
+    database: DbPool,
+    job_reports: JobReports,
+    state_recorder: JobStateRecorder,
+    live_tasks: LiveTasks,
+    produced_artifacts: ProducedArtifacts,
+
+    /// The result of the job from an earlier run of the submit, if it does not have to run again
+    resumed: Option<ResumedJob>,
+
+    /// The artifacts of the job from the stores, if it is not built because of "--no-deps"
+    prebuilt: Option<Vec<ArtifactPath>>,
+
+    /// Build the job hermetically, see `OrchestratorSetup::hermetic`
+    hermetic: bool,
 
     /// Channel where the dependencies arrive
     receiver: Receiver<JobResult>,
@@ -502,6 +1194,10 @@ struct JobTask<'a> {
 /// In the latter case, we cleanup by telling the progressbar to finish.
 impl<'a> Drop for JobTask<'a> {
     fn drop(&mut self) {
+        if let Ok(mut live_tasks) = self.live_tasks.lock() {
+            live_tasks.remove(self.jobdef.job.uuid());
+        }
+
         if !self.bar.is_finished() {
             // If there are dependencies, the error is probably from another task
             // If there are no dependencies, the error was caused by something else
@@ -511,6 +1207,7 @@ impl<'a> Drop for JobTask<'a> {
                 "error on other task"
             };
 
+            self.set_state(JobState::Failed);
             self.bar.finish_with_message(format!("[{} {} {}] Stopped, {msg}",
                 self.jobdef.job.uuid(),
                 self.jobdef.job.package().name(),
@@ -528,10 +1225,12 @@ impl<'a> JobTask<'a> {
             prep.jobdef.job.package().name(),
             prep.jobdef.job.package().version()
         ));
-        JobTask {
+        let task = JobTask {
             jobdef: prep.jobdef,
 
             bar,
+            tree_prefix: prep.tree_prefix,
+            priority: prep.priority,
 
             config: prep.config,
             git_author_env: prep.git_author_env,
@@ -539,11 +1238,39 @@ impl<'a> JobTask<'a> {
             source_cache: prep.source_cache,
             scheduler: prep.scheduler,
             staging_store: prep.staging_store,
+            additional_staging_stores: prep.additional_staging_stores,
             release_stores: prep.release_stores,
             database: prep.database.clone(),
+            job_reports: prep.job_reports,
+            state_recorder: prep.state_recorder,
+            live_tasks: prep.live_tasks,
+            produced_artifacts: prep.produced_artifacts,
+            resumed: prep.resumed,
+            prebuilt: prep.prebuilt,
+            hermetic: prep.hermetic,
 
             receiver,
             sender,
+        };
+        task.set_state(JobState::Waiting);
+        if let Ok(mut live_tasks) = task.live_tasks.lock() {
+            live_tasks.insert(*task.jobdef.job.uuid());
+        }
+        task
+    }
+
+    /// Show the state of the job in the tree view and record it
+    ///
+    /// The artifacts of jobs that are done or reused have to be set before.
+    fn set_state(&self, state: JobState) {
+        self.job_reports.set_state(self.jobdef.job, state);
+        let artifacts = match state {
+            JobState::Done | JobState::Reused => self.job_reports.artifacts(self.jobdef.job),
+            JobState::Waiting | JobState::Running | JobState::Failed => vec![],
+        };
+        self.state_recorder.record(self.jobdef.job.uuid(), state, &artifacts);
+        if let Some(prefix) = self.tree_prefix.as_ref() {
+            self.bar.set_prefix(format!("{}[{}] ", prefix, state));
         }
     }
 
@@ -558,36 +1285,28 @@ impl<'a> JobTask<'a> {
         });
 
         let dep_len = self.jobdef.dependencies.len();
-        // A list of job run results from dependencies that were received from the tasks for the
-        // dependencies
-        let mut received_dependencies: HashMap<Uuid, Vec<ProducedArtifact>> = HashMap::with_capacity(dep_len);
+        // The jobs that finished, received from the tasks for the dependencies
+        let mut received_jobs: HashSet<Uuid> = HashSet::with_capacity(dep_len);
 
         // A list of errors that were received from the tasks for the dependencies
         let mut received_errors: HashMap<Uuid, Error> = HashMap::with_capacity(dep_len);
 
-        // Helper function to check whether all UUIDs are in a list of UUIDs
-        let all_dependencies_are_in = |dependency_uuids: &[Uuid], list: &HashMap<Uuid, Vec<_>>| {
-            dependency_uuids.iter().all(|dependency_uuid| {
-                list.keys().any(|id| id == dependency_uuid)
-            })
-        };
-
-        // as long as the job definition lists dependencies that are not in the received_dependencies list...
-        while !all_dependencies_are_in(&self.jobdef.dependencies, &received_dependencies) {
+        // as long as the job definition lists dependencies that are not in the received_jobs list...
+        while !self.jobdef.dependencies.iter().all(|dependency_uuid| received_jobs.contains(dependency_uuid)) {
             // Update the status bar message
             self.bar.set_message({
                 format!("[{} {} {}]: Waiting ({}/{})...",
                     self.jobdef.job.uuid(),
                     self.jobdef.job.package().name(),
                     self.jobdef.job.package().version(),
-                    received_dependencies.iter().filter(|(rd_uuid, _)| self.jobdef.dependencies.contains(rd_uuid)).count(),
+                    received_jobs.iter().filter(|rd_uuid| self.jobdef.dependencies.contains(rd_uuid)).count(),
                     dep_len)
             });
             trace!("[{}]: Updated bar", self.jobdef.job.uuid());
 
             trace!("[{}]: receiving...", self.jobdef.job.uuid());
             // receive from the receiver
-            let continue_receiving = self.perform_receive(&mut received_dependencies, &mut received_errors).await?;
+            let continue_receiving = self.perform_receive(&mut received_jobs, &mut received_errors).await?;
 
             trace!("[{}]: Received errors = {}", self.jobdef.job.uuid(), received_errors.display_error_map());
             // if there are any errors from child tasks
@@ -599,6 +1318,7 @@ impl<'a> JobTask<'a> {
                 log::error!("[{}]: Received errors = {}", self.jobdef.job.uuid(), received_errors.display_error_map());
                 self.sender[0].send(Err(received_errors)).await;
 
+                self.set_state(JobState::Failed);
                 // ... and stop operation, because the whole tree will fail anyways.
                 self.bar.finish_with_message(format!("[{} {} {}] Stopping, errors from child received",
                     self.jobdef.job.uuid(),
@@ -611,6 +1331,18 @@ impl<'a> JobTask<'a> {
                 break;
             }
         }
+        let received_dependencies = self.produced_artifacts.of_jobs(received_jobs.iter())?;
+
+        // A job that finished in an earlier run of the submit has the same result as back then,
+        // because all its dependencies finished back then as well
+        if let Some(resumed) = self.resumed.take() {
+            return self.send_resumed(received_dependencies, resumed).await
+        }
+
+        if let Some(artifacts) = self.prebuilt.take() {
+            let artifacts = artifacts.into_iter().map(ProducedArtifact::Reused).collect();
+            return self.send_reused(received_dependencies, artifacts).await
+        }
 
         // Check if any of the received dependencies was built (and not reused).
         // If any dependency was built, we need to build as well.
@@ -621,102 +1353,44 @@ impl<'a> JobTask<'a> {
         // If no dependency was built, we can check for replacements for this job as well, so
         // check if a job that looks very similar to this job has already produced artifacts.
         // If it has, simply return those (plus the received ones)
-        if !any_dependency_was_built {
-            let staging_store = self.staging_store.read().await;
-
-            // Use the environment of the job definition, as it appears in the job DAG.
-            //
-            // This is because we do not have access to the commandline-passed (additional)
-            // environment variables at this point. But using the JobResource::env() variables
-            // works as well.
-            let additional_env = self.jobdef.job.resources()
-                .iter()
-                .filter_map(crate::job::JobResource::env)
-                .map(|(k, v)| (k.clone(), v.clone()))
-                .chain(self.git_author_env.cloned().into_iter())
-                .chain(self.git_commit_env.cloned().into_iter())
-                .collect::<Vec<_>>();
-
-            let replacement_artifacts = crate::db::FindArtifacts::builder()
-                .database_connection(self.database.clone())
-                .config(self.config)
-                .package(self.jobdef.job.package())
-                .release_stores(&self.release_stores)
-                .image_name(Some(self.jobdef.job.image()))
-
-                // We can simply pass the staging store here, because it doesn't hurt. There are
-                // two scenarios:
-                //
-                // 1. We are in a fresh build for a package. In this case, the artifacts for this
-                //    very build are not in there yet, and there won't be any artifacts from the
-                //    staging store (possibly from the release store, which would be fine).
-                // 2. We are in a re-build, where the user passed the staging store to the build
-                //    subcommand. In this case, there might be an artifact for this job in the
-                //    staging store. In this case, we want to use it as a replacement, of course.
-                //
-                // The fact that released artifacts are returned prefferably from this function
-                // call does not change anything, because if there is an artifact that's a released
-                // one that matches this job, we should use it anyways.
-                .staging_store(Some(&staging_store))
-                .env_filter(&additional_env)
-                .script_filter(true)
-                .build()
-                .run()?;
-
-            debug!("[{}]: Found {} replacement artifacts", self.jobdef.job.uuid(), replacement_artifacts.len());
-            trace!("[{}]: Found replacement artifacts: {:?}", self.jobdef.job.uuid(), replacement_artifacts);
-            let mut artifacts = replacement_artifacts
-                .into_iter()
+        // If artifacts are only reused when they were built in the very same image, we have to
+        // find out the ID of the image first. If no endpoint can tell us, nothing is reused.
+        let image_digest = if !any_dependency_was_built && self.config.docker().require_image_digest_match() {
+            let digest = self.scheduler.image_digest(self.jobdef.job.image()).await;
+            if digest.is_none() {
+                warn!("[{}]: Could not find out the ID of image {}, not reusing artifacts",
+                    self.jobdef.job.uuid(), self.jobdef.job.image());
+            }
+            Some(digest)
+        } else {
+            None
+        };
 
-                // First of all, we sort by whether the artifact path is in the staging store,
-                // because we prefer staging store artifacts at this point.
-                .sorted_by(|(p1, _), (p2, _)| {
-                    let r1 = p1.is_in_staging_store(&staging_store);
-                    let r2 = p2.is_in_staging_store(&staging_store);
-                    r1.cmp(&r2)
-                })
+        if !any_dependency_was_built && !matches!(image_digest, Some(None)) {
+            let staging_store = self.staging_store.read().await;
 
-                // We don't need duplicates here, so remove them by making the iterator unique
-                // If we have two artifacts that are the same, the one in the staging store will be
-                // preffered in the next step
-                .unique_by(|tpl| tpl.0.artifact_path().clone())
-
-                // Fetch the artifact from the staging store, if there is one.
-                // If there is none, try the release store.
-                // If there is none, there won't be a replacement artifact
-                .filter_map(|(full_artifact_path, _)| {
-                    trace!("Searching for {:?} in stores", full_artifact_path.display());
-                    if let Some(ap) = staging_store.get(full_artifact_path.artifact_path()) {
-                        Some(ap.clone())
-                    } else {
-                        self.release_stores
-                            .iter()
-                            .find_map(|rs| rs.get(full_artifact_path.artifact_path()))
-                            .cloned()
-                    }
-                })
-                .map(ProducedArtifact::Reused)
-                .collect::<Vec<ProducedArtifact>>();
+            let additional_env = reuse_env(self.jobdef.job, self.git_author_env, self.git_commit_env);
+
+            // The database lookup is blocking, so don't stall the other tasks on this thread
+            let artifacts = tokio::task::block_in_place(|| {
+                let database_connection = self.database.get()?;
+                find_replacement_artifacts(
+                    &database_connection,
+                    self.config,
+                    self.jobdef.job,
+                    image_digest.as_ref().and_then(Option::as_deref),
+                    &additional_env,
+                    &staging_store,
+                    &self.additional_staging_stores,
+                    &self.release_stores,
+                    self.hermetic,
+                )
+            })?;
 
             if !artifacts.is_empty() {
-                received_dependencies.insert(*self.jobdef.job.uuid(), artifacts);
-                trace!("[{}]: Sending to parent: {:?}", self.jobdef.job.uuid(), received_dependencies);
-                for s in self.sender.iter() {
-                    s.send(Ok(received_dependencies.clone()))
-                        .await
-                        .context("Cannot send received dependencies to parent")
-                        .with_context(|| {
-                            format!("Sending-Channel is closed in Task for {}: {} {}",
-                                self.jobdef.job.uuid(),
-                                self.jobdef.job.package().name(),
-                                self.jobdef.job.package().version())
-                        })?;
-                }
-                self.bar.finish_with_message(format!("[{} {} {}] Reusing artifact",
-                    self.jobdef.job.uuid(),
-                    self.jobdef.job.package().name(),
-                    self.jobdef.job.package().version()));
-                return Ok(())
+                drop(staging_store);
+                let artifacts = artifacts.into_iter().map(ProducedArtifact::Reused).collect();
+                return self.send_reused(received_dependencies, artifacts).await
             }
         }
 
@@ -724,12 +1398,27 @@ impl<'a> JobTask<'a> {
         //      Vec<(Uuid, Vec<ArtifactPath>)>
         // to
         //      Vec<ArtifactPath>
+        //
+        // Of the direct dependencies, only the artifacts of the outputs this job depends on are
+        // used.
         let dependency_artifacts = received_dependencies
-            .values()
-            .flat_map(|v| v.iter())
-            .map(ProducedArtifact::borrow)
+            .iter()
+            .flat_map(|(uuid, v)| {
+                let outputs = self.jobdef.dependency_outputs.get(uuid);
+                v.iter()
+                    .map(ProducedArtifact::borrow)
+                    .filter(move |a: &&ArtifactPath| outputs.map(|o| o.contains(a.as_ref())).unwrap_or(true))
+            })
             .cloned()
-            .collect::<Vec<ArtifactPath>>();
+            .map(Ok)
+            .chain({
+                self.jobdef.job.package()
+                    .dependencies()
+                    .external()
+                    .iter()
+                    .map(crate::filestore::ExternalArtifactCache::artifact_path)
+            })
+            .collect::<Result<Vec<ArtifactPath>>>()?;
         trace!("[{}]: Dependency artifacts = {:?}", self.jobdef.job.uuid(), dependency_artifacts);
         self.bar.set_message(format!("[{} {} {}]: Preparing...",
             self.jobdef.job.uuid(),
@@ -744,19 +1433,50 @@ impl<'a> JobTask<'a> {
             self.config,
             self.git_author_env,
             self.git_commit_env,
-            dependency_artifacts)?;
+            dependency_artifacts,
+            self.hermetic)?;
+
+        // Register the job as running, so that concurrent submits that need the very same job
+        // wait for it instead of building it as well
+        let job_uuid = *self.jobdef.job.uuid();
+        let running_job = {
+            let submit = self.scheduler.submit().clone();
+            let fingerprint = runnable.fingerprint()?;
+            crate::db::with_connection(&self.database, move |conn| {
+                dbmodels::RunningJob::create(conn, &job_uuid, &submit, &fingerprint)
+            })
+            .await?
+        };
+        let running_job_guard = RunningJobGuard::new(self.database.clone(), job_uuid);
+
+        if !any_dependency_was_built {
+            match self.wait_for_equivalent_job(&running_job).await {
+                Ok(Some(artifacts)) => {
+                    running_job_guard.remove().await?;
+                    return self.send_reused(received_dependencies, artifacts).await
+                },
+                Ok(None) => {},
+                Err(e) => {
+                    running_job_guard.remove().await?;
+                    return Err(e)
+                },
+            }
+        }
 
         self.bar.set_message(format!("[{} {} {}]: Scheduling...",
             self.jobdef.job.uuid(),
             self.jobdef.job.package().name(),
             self.jobdef.job.package().version()
         ));
-        let job_uuid = *self.jobdef.job.uuid();
-
         // Schedule the job on the scheduler
-        match self.scheduler.schedule_job(runnable, self.bar.clone()).await?.run().await? {
+        self.set_state(JobState::Running);
+        let result = self.scheduler.run_job(runnable, self.bar.clone(), self.priority).await;
+        running_job_guard.remove().await?;
+
+        match result? {
             Err(e) => {
                 trace!("[{}]: Scheduler returned error = {:?}", self.jobdef.job.uuid(), e);
+                self.set_state(JobState::Failed);
                 // ... and we send that to our parent
                 //
                 // We only send to one parent, because it doesn't matter anymore
@@ -777,14 +1497,13 @@ impl<'a> JobTask<'a> {
             // it returns the database artifact objects it created!
             Ok(artifacts) => {
                 trace!("[{}]: Scheduler returned artifacts = {:?}", self.jobdef.job.uuid(), artifacts);
+                self.job_reports.set_artifacts(self.jobdef.job, artifacts.iter().map(|a| (a.clone(), true)).collect());
+                self.set_state(JobState::Done);
 
                 // mark the produced artifacts as "built" (rather than reused)
                 let artifacts = artifacts.into_iter().map(ProducedArtifact::Built).collect();
 
-                received_dependencies.insert(*self.jobdef.job.uuid(), artifacts);
-                for s in self.sender.iter() {
-                    s.send(Ok(received_dependencies.clone())).await?;
-                }
+                self.send_artifacts(&received_dependencies, artifacts).await?;
             },
         }
 
@@ -792,21 +1511,202 @@ impl<'a> JobTask<'a> {
         Ok(())
     }
 
+    /// Send the artifacts of the same job from an earlier run of the submit with the
+    /// `received_dependencies` to the parents
+    ///
+    /// The artifacts count as built if they were built back then, so that the jobs that depend on
+    /// this job and did not finish back then are built again as well.
+    async fn send_resumed(&self, received_dependencies: HashMap<Uuid, Vec<ProducedArtifact>>, resumed: ResumedJob) -> Result<()> {
+        let built = resumed.state == JobState::Done;
+        let artifacts = resumed.artifacts
+            .into_iter()
+            .map(|artifact| if built {
+                ProducedArtifact::Built(artifact)
+            } else {
+                ProducedArtifact::Reused(artifact)
+            })
+            .collect();
+
+        self.send_finished(received_dependencies, artifacts, resumed.state, "Finished in an earlier run").await
+    }
+
+    /// Send the reused `artifacts` for this job with the `received_dependencies` to the parents
+    async fn send_reused(&self, received_dependencies: HashMap<Uuid, Vec<ProducedArtifact>>, artifacts: Vec<ProducedArtifact>) -> Result<()> {
+        self.send_finished(received_dependencies, artifacts, JobState::Reused, "Reusing artifact").await
+    }
+
+    /// Send the `artifacts` for this job, which did not run, with the `received_dependencies` to
+    /// the parents
+    async fn send_finished(
+        &self,
+        received_dependencies: HashMap<Uuid, Vec<ProducedArtifact>>,
+        artifacts: Vec<ProducedArtifact>,
+        state: JobState,
+        message: &str,
+    ) -> Result<()> {
+        self.job_reports.set_artifacts(self.jobdef.job, {
+            artifacts.iter().map(|a| (a.clone().unpack(), a.was_build())).collect()
+        });
+        self.send_artifacts(&received_dependencies, artifacts).await?;
+        self.set_state(state);
+        self.bar.finish_with_message(format!("[{} {} {}] {}",
+            self.jobdef.job.uuid(),
+            self.jobdef.job.package().name(),
+            self.jobdef.job.package().version(),
+            message));
+        Ok(())
+    }
+
+    /// Wait for an equivalent job of a concurrently running submit, that was started before
+    /// `running_job`
+    ///
+    /// Returns the artifacts of that job, imported into our staging store, or None if there is no
+    /// such job or it did not produce artifacts.
+    async fn wait_for_equivalent_job(&self, running_job: &dbmodels::RunningJob) -> Result<Option<Vec<ProducedArtifact>>> {
+        let equivalent = {
+            let running_job = running_job.clone();
+            let not_before = (chrono::Utc::now() - chrono::Duration::hours(RUNNING_JOB_STALE_AFTER_HOURS)).naive_utc();
+            crate::db::with_connection(&self.database, move |conn| {
+                running_job.find_earlier_equivalent(conn, not_before)
+            })
+            .await?
+        };
+
+        let (other, other_submit) = match equivalent {
+            Some(e) => e,
+            None => return Ok(None),
+        };
+
+        debug!("[{}]: Waiting for equivalent job {} of submit {}", self.jobdef.job.uuid(), other.uuid, other_submit.uuid);
+        self.bar.set_message(format!("[{} {} {}]: Waiting for job {} of submit {}...",
+            self.jobdef.job.uuid(),
+            self.jobdef.job.package().name(),
+            self.jobdef.job.package().version(),
+            other.uuid,
+            other_submit.uuid
+        ));
+
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(RUNNING_JOB_POLL_INTERVAL_SECS)).await;
+            if self.scheduler.is_canceled() {
+                return Err(anyhow!("Submit {} was canceled", self.scheduler.submit().uuid))
+            }
+
+            let other_uuid = other.uuid;
+            let is_running = crate::db::with_connection(&self.database, move |conn| {
+                dbmodels::RunningJob::is_running(conn, &other_uuid)
+            })
+            .await?;
+
+            if !is_running {
+                break
+            }
+        }
+
+        let other_uuid = other.uuid;
+        let paths = crate::db::with_connection(&self.database, move |conn| {
+            let job = schema::jobs::table
+                .filter(schema::jobs::uuid.eq(other_uuid))
+                .first::<dbmodels::Job>(conn)
+                .optional()?;
+
+            match job {
+                None => Ok(vec![]),
+                Some(job) => dbmodels::Artifact::belonging_to(&job)
+                    .load::<dbmodels::Artifact>(conn)
+                    .map(|artifacts| artifacts.iter().map(dbmodels::Artifact::path_buf).collect::<Vec<_>>())
+                    .map_err(Error::from),
+            }
+        })
+        .await?;
+
+        if paths.is_empty() {
+            debug!("[{}]: Equivalent job {} did not produce artifacts, building", self.jobdef.job.uuid(), other.uuid);
+            return Ok(None)
+        }
+
+        let other_root = StoreRoot::new(self.config.staging_directory().join(other_submit.uuid.to_string()))?;
+        let mut staging_store = self.staging_store.write().await;
+        let mut artifacts = Vec::with_capacity(paths.len());
+        for path in paths {
+            let ap = ArtifactPath::new(path)?;
+            artifacts.push(ProducedArtifact::Reused(staging_store.import_from(&other_root, &ap).await?));
+        }
+        Ok(Some(artifacts))
+    }
+
+    /// Receive the next result from the dependencies
+    ///
+    /// If nothing arrives for the configured watchdog timeout, the dependencies that did not
+    /// report yet are checked: if the task of one of them finished, it never reports, so this
+    /// fails with the dependencies that never reported instead of waiting forever.
+    async fn receive_watched(&mut self, received_jobs: &HashSet<Uuid>, received_errors: &HashMap<Uuid, Error>) -> Result<Option<JobResult>> {
+        let timeout = self.config.build().dependency_watchdog_timeout();
+        if timeout == 0 {
+            return Ok(self.receiver.recv().await)
+        }
+
+        loop {
+            match tokio::time::timeout(std::time::Duration::from_secs(timeout), self.receiver.recv()).await {
+                Ok(received) => return Ok(received),
+                Err(_elapsed) => {
+                    let (stopped, running) = {
+                        let live_tasks = self.live_tasks.lock().map_err(|_| anyhow!("Lock poisoned"))?;
+                        unreported_dependencies(&self.jobdef.dependencies, received_jobs, received_errors, &live_tasks)
+                    };
+                    if !stopped.is_empty() {
+                        return Err(anyhow!("No progress for {}s while waiting for dependencies, never reported: {} (the tasks of {} finished)",
+                            timeout,
+                            stopped.iter().chain(running.iter()).join(", "),
+                            stopped.iter().join(", ")))
+                            .with_context(|| anyhow!("Job {} for {} {} cannot run",
+                                self.jobdef.job.uuid(),
+                                self.jobdef.job.package().name(),
+                                self.jobdef.job.package().version()))
+                    }
+                    debug!("[{}]: No progress for {}s, still waiting for {}", self.jobdef.job.uuid(), timeout, running.iter().join(", "));
+                },
+            }
+        }
+    }
+
+    /// Record the `artifacts` of this job and send it with the jobs of the `received_dependencies`
+    /// to the parents
+    async fn send_artifacts(&self, received_dependencies: &HashMap<Uuid, Vec<ProducedArtifact>>, artifacts: Vec<ProducedArtifact>) -> Result<()> {
+        self.produced_artifacts.insert(*self.jobdef.job.uuid(), artifacts)?;
+        let jobs = received_dependencies.keys()
+            .copied()
+            .chain(std::iter::once(*self.jobdef.job.uuid()))
+            .collect::<Vec<_>>();
+        trace!("[{}]: Sending to parent: {:?}", self.jobdef.job.uuid(), jobs);
+        for s in self.sender.iter() {
+            s.send(Ok(jobs.clone()))
+                .await
+                .context("Cannot send received dependencies to parent")
+                .with_context(|| {
+                    format!("Sending-Channel is closed in Task for {}: {} {}",
+                        self.jobdef.job.uuid(),
+                        self.jobdef.job.package().name(),
+                        self.jobdef.job.package().version())
+                })?;
+        }
+        Ok(())
+    }
+
     /// Performe a recv() call on the receiving side of the channel
     ///
-    /// Put the dependencies you received into the `received_dependencies`, the errors in the
-    /// `received_errors`
+    /// Put the jobs you received into the `received_jobs`, the errors in the `received_errors`
     ///
     /// Return Ok(true) if we should continue operation
     /// Return Ok(false) if the channel is empty and we're done receiving or if the channel is
     /// empty and there were errors collected
-    async fn perform_receive(&mut self, received_dependencies: &mut HashMap<Uuid, Vec<ProducedArtifact>>, received_errors: &mut HashMap<Uuid, Error>) -> Result<bool> {
-        match self.receiver.recv().await {
-            Some(Ok(mut v)) => {
-                // The task we depend on succeeded and returned an
-                // (uuid of the job, [ArtifactPath])
-                trace!("[{}]: Received: {:?}", self.jobdef.job.uuid(), v);
-                received_dependencies.extend(v);
+    async fn perform_receive(&mut self, received_jobs: &mut HashSet<Uuid>, received_errors: &mut HashMap<Uuid, Error>) -> Result<bool> {
+        match self.receive_watched(received_jobs, received_errors).await? {
+            Some(Ok(jobs)) => {
+                // The task we depend on succeeded and returned the uuids of the jobs that
+                // finished, whose artifacts are in the produced artifacts
+                trace!("[{}]: Received: {:?}", self.jobdef.job.uuid(), jobs);
+                received_jobs.extend(jobs);
                 Ok(true)
             },
             Some(Err(mut e)) => {
@@ -829,11 +1729,10 @@ impl<'a> JobTask<'a> {
                 }
 
                 // Find all dependencies that we need but which are not received
-                let received = received_dependencies.keys().collect::<Vec<_>>();
                 let missing_deps: Vec<_> = self.jobdef
                     .dependencies
                     .iter()
-                    .filter(|d| !received.contains(d))
+                    .filter(|d| !received_jobs.contains(d))
                     .collect();
                 trace!("[{}]: Missing dependencies = {:?}", self.jobdef.job.uuid(), missing_deps);
 
@@ -851,3 +1750,122 @@ impl<'a> JobTask<'a> {
 
 }
 
+/// The `dependencies` that did not report yet, split into those whose tasks finished (so
+/// they will never report) and those whose tasks still run
+fn unreported_dependencies(
+    dependencies: &[Uuid],
+    received_jobs: &HashSet<Uuid>,
+    received_errors: &HashMap<Uuid, Error>,
+    live_tasks: &HashSet<Uuid>,
+) -> (Vec<Uuid>, Vec<Uuid>) {
+    dependencies.iter()
+        .filter(|d| !received_jobs.contains(d) && !received_errors.contains_key(d))
+        .partition(|d| !live_tasks.contains(d))
+}
+
+/// The entry of a job in the list of running jobs in the database
+///
+/// The entry is removed with `remove()`, or when the guard is dropped without that, e.g. because
+/// the `JobTask` future was dropped, so that no entry is left behind for other submits to wait for.
+struct RunningJobGuard {
+    database: DbPool,
+    job_uuid: Uuid,
+    removed: bool,
+}
+
+impl RunningJobGuard {
+    fn new(database: DbPool, job_uuid: Uuid) -> Self {
+        RunningJobGuard { database, job_uuid, removed: false }
+    }
+
+    /// Remove the job from the list of running jobs in the database
+    async fn remove(mut self) -> Result<()> {
+        self.removed = true;
+        let job_uuid = self.job_uuid;
+        crate::db::with_connection(&self.database, move |conn| dbmodels::RunningJob::remove(conn, &job_uuid)).await
+    }
+}
+
+impl Drop for RunningJobGuard {
+    fn drop(&mut self) {
+        if self.removed {
+            return
+        }
+
+        let database = self.database.clone();
+        let job_uuid = self.job_uuid;
+        let remove = move || {
+            let result = database.get()
+                .map_err(Error::from)
+                .and_then(|conn| dbmodels::RunningJob::remove(&conn, &job_uuid));
+            if let Err(e) = result {
+                warn!("Failed to remove job {} from the running jobs: {:?}", job_uuid, e);
+            }
+        };
+
+        // Removing the entry blocks, so it is not done on the runtime if there is one
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => drop(handle.spawn_blocking(remove)),
+            Err(_) => remove(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unreported_dependencies() {
+        let ids = (0..4).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+        let received = std::iter::once(ids[0]).collect::<HashSet<_>>();
+        let errors = std::iter::once((ids[1], anyhow!("failed"))).collect::<HashMap<_, _>>();
+        let live = std::iter::once(ids[2]).collect::<HashSet<_>>();
+
+        let (stopped, running) = unreported_dependencies(&ids, &received, &errors, &live);
+        assert_eq!(stopped, vec![ids[3]]);
+        assert_eq!(running, vec![ids[2]]);
+
+        let (stopped, running) = unreported_dependencies(&ids[..3], &received, &errors, &live);
+        assert!(stopped.is_empty());
+        assert_eq!(running, vec![ids[2]]);
+    }
+
+    #[test]
+    fn test_merge_produced_artifacts() {
+        let path = |p: &str| ArtifactPath::new(PathBuf::from(p)).unwrap();
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        let mut merged = HashMap::new();
+        merge_produced_artifacts(&mut merged, a, vec![ProducedArtifact::Reused(path("a.pkg")), ProducedArtifact::Reused(path("a.pkg"))]);
+        merge_produced_artifacts(&mut merged, b, vec![ProducedArtifact::Built(path("a.pkg")), ProducedArtifact::Built(path("b.pkg"))]);
+
+        // The same results arriving on another path through the tree
+        let again = merged.clone();
+        for (job, artifacts) in again {
+            merge_produced_artifacts(&mut merged, job, artifacts);
+        }
+        merge_produced_artifacts(&mut merged, c, vec![]);
+
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged.values().map(Vec::len).sum::<usize>(), 2);
+        assert!(merged[&a][0].was_build(), "a.pkg was built by b");
+        assert_eq!(merged[&b].iter().map(|a| a.clone().unpack()).collect::<Vec<_>>(), vec![path("b.pkg")]);
+        assert!(merged[&c].is_empty());
+    }
+
+    #[test]
+    fn test_produced_artifacts_of_jobs() {
+        let path = |p: &str| ArtifactPath::new(PathBuf::from(p)).unwrap();
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        let produced = ProducedArtifacts::default();
+        produced.insert(a, vec![ProducedArtifact::Reused(path("a.pkg"))]).unwrap();
+        produced.insert(b, vec![ProducedArtifact::Reused(path("a.pkg")), ProducedArtifact::Built(path("b.pkg"))]).unwrap();
+
+        let artifacts = produced.of_jobs([a, b].iter()).unwrap();
+        assert_eq!(artifacts.values().map(Vec::len).sum::<usize>(), 2);
+        assert_eq!(produced.of_jobs([b].iter()).unwrap()[&b].len(), 2);
+        assert!(produced.of_jobs([a, c].iter()).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! What happened to the jobs of a submit, for reporting the result of the submit
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use uuid::Uuid;
+
+use crate::filestore::path::ArtifactPath;
+use crate::job::Job;
+use crate::orchestrator::JobState;
+
+/// What happened to one job
+#[derive(Clone, Debug)]
+pub struct JobReport {
+    pub package_name: String,
+    pub package_version: String,
+    pub state: JobState,
+
+    /// How long the job ran, `None` if it did not run
+    pub duration_secs: Option<f64>,
+
+    /// The artifacts of the job, with whether they were built (and not reused)
+    pub artifacts: Vec<(ArtifactPath, bool)>,
+
+    started: Option<Instant>,
+}
+
+impl JobReport {
+    /// How long the job is running, `None` if it is not running
+    pub fn running_secs(&self) -> Option<f64> {
+        self.started
+            .filter(|_| self.state == JobState::Running)
+            .map(|started| started.elapsed().as_secs_f64())
+    }
+}
+
+/// The reports of all jobs of a submit, collected while the jobs run
+///
+/// Cloning this results in a handle to the same reports.
+#[derive(Clone, Debug, Default)]
+pub struct JobReports(Arc<Mutex<HashMap<Uuid, JobReport>>>);
+
+impl JobReports {
+    pub fn set_state(&self, job: &Job, state: JobState) {
+        let mut reports = self.0.lock().unwrap(); // only poisoned if another thread panicked
+        let report = reports.entry(*job.uuid()).or_insert_with(|| JobReport {
+            package_name: job.package().name().to_string(),
+            package_version: job.package().version().to_string(),
+            state,
+            duration_secs: None,
+            artifacts: vec![],
+            started: None,
+        });
+
+        report.state = state;
+        match state {
+            JobState::Running => report.started = Some(Instant::now()),
+            JobState::Done | JobState::Failed => {
+                report.duration_secs = report.started.map(|started| started.elapsed().as_secs_f64());
+            },
+            JobState::Waiting | JobState::Reused => {},
+        }
+    }
+
+    pub fn set_artifacts(&self, job: &Job, artifacts: Vec<(ArtifactPath, bool)>) {
+        if let Some(report) = self.0.lock().unwrap().get_mut(job.uuid()) { // only poisoned if another thread panicked
+            report.artifacts = artifacts;
+        }
+    }
+
+    /// The artifacts of `job`, as set with `set_artifacts()`
+    pub fn artifacts(&self, job: &Job) -> Vec<ArtifactPath> {
+        self.0.lock().unwrap() // only poisoned if another thread panicked
+            .get(job.uuid())
+            .map(|report| report.artifacts.iter().map(|(artifact, _)| artifact.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// The reports of all jobs that were started so far
+    pub fn reports(&self) -> HashMap<Uuid, JobReport> {
+        self.0.lock().unwrap().clone() // only poisoned if another thread panicked
+    }
+}
@@ -0,0 +1,167 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Recording the job graph of a submit and the states of its jobs in the database, so that a
+//! submit can be resumed with "build --resume" after butido crashed or the host rebooted
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use diesel::PgConnection;
+use log::debug;
+use log::warn;
+use uuid::Uuid;
+
+use crate::db::models as dbmodels;
+use crate::db::DbPool;
+use crate::filestore::ArtifactPath;
+use crate::job::Dag;
+use crate::orchestrator::JobState;
+
+/// Records the state transitions of the jobs of a submit
+///
+/// Cloning this results in a handle to the same recorder.
+#[derive(Clone)]
+pub struct JobStateRecorder {
+    database: DbPool,
+
+    /// The ids of the recorded jobs in the database, by job UUID
+    submit_jobs: Arc<HashMap<Uuid, i32>>,
+}
+
+impl JobStateRecorder {
+    /// Record the jobs of `jobdag` with their dependencies as jobs of `submit`
+    pub fn record_graph(database: DbPool, conn: &PgConnection, submit: &dbmodels::Submit, jobdag: &Dag) -> Result<Self> {
+        let submit_jobs = jobdag.iter()
+            .map(|jobdef| {
+                let package = dbmodels::Package::create_or_fetch(conn, jobdef.job.package())?;
+                let submit_job = dbmodels::SubmitJob::create(conn, submit, jobdef.job.uuid(), &package, jobdef.dependencies)?;
+                Ok((*jobdef.job.uuid(), submit_job.id))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        Ok(JobStateRecorder { database, submit_jobs: Arc::new(submit_jobs) })
+    }
+
+    /// Record that `job` changed to `state`, with the `artifacts` of the job if it finished
+    ///
+    /// Failing to record the state only results in a warning, because the state is only needed
+    /// for resuming the submit.
+    pub fn record(&self, job: &Uuid, state: JobState, artifacts: &[ArtifactPath]) {
+        let submit_job_id = match self.submit_jobs.get(job) {
+            Some(id) => *id,
+            None => return,
+        };
+
+        let artifacts = artifacts.iter().map(|a| a.display().to_string()).collect();
+        let result = tokio::task::block_in_place(|| {
+            let conn = self.database.get()?;
+            dbmodels::JobStateTransition::create(&conn, submit_job_id, &state.to_string(), artifacts)
+        });
+
+        if let Err(e) = result {
+            warn!("Recording state '{}' of job {} failed: {:#}", state, job, e);
+        }
+    }
+}
+
+/// A job that finished in an earlier run of the submit
+#[derive(Clone, Debug)]
+pub struct ResumedJob {
+    /// Either `Done` or `Reused`
+    pub state: JobState,
+    pub artifacts: Vec<ArtifactPath>,
+}
+
+/// The jobs of `jobdag` that finished in earlier runs of `submit` and do not have to run again,
+/// by the UUIDs of the jobs in `jobdag`
+///
+/// `available` checks whether an artifact of an earlier run is still in one of the stores and
+/// returns it from there. Jobs whose artifacts are not all available run again, and so does
+/// every job that depends on a job that runs again.
+pub fn resumed_jobs<F>(conn: &PgConnection, submit: &dbmodels::Submit, jobdag: &Dag, available: F) -> Result<HashMap<Uuid, ResumedJob>>
+where
+    F: Fn(&ArtifactPath) -> Option<ArtifactPath>,
+{
+    let finished = dbmodels::SubmitJob::latest_states(conn, submit)?
+        .into_iter()
+        .filter_map(|(package, transition)| {
+            let state = JobState::from_str(&transition.state).ok()?;
+            if state != JobState::Done && state != JobState::Reused {
+                return None
+            }
+
+            let artifacts = transition.artifacts
+                .iter()
+                .map(|path| ArtifactPath::new(PathBuf::from(path)).ok().and_then(|path| available(&path)))
+                .collect::<Option<Vec<_>>>();
+
+            match artifacts {
+                Some(artifacts) => Some(((package.name, package.version), ResumedJob { state, artifacts })),
+                None => {
+                    debug!("Artifacts of {} {} from an earlier run are missing, it runs again", package.name, package.version);
+                    None
+                },
+            }
+        })
+        .collect::<HashMap<_, _>>();
+
+    let candidates = jobdag.iter()
+        .filter_map(|jobdef| {
+            let key = (jobdef.job.package().name().to_string(), jobdef.job.package().version().to_string());
+            finished.get(&key).map(|resumed| (*jobdef.job.uuid(), (jobdef.dependencies, resumed.clone())))
+        })
+        .collect::<HashMap<_, _>>();
+
+    Ok(without_dependents_of_other_jobs(candidates))
+}
+
+/// Remove the jobs that depend on a job that is not in `jobs`, directly or indirectly
+///
+/// `jobs` maps the UUIDs of the jobs to their dependencies and a value that is returned for the
+/// remaining jobs.
+fn without_dependents_of_other_jobs<T>(mut jobs: HashMap<Uuid, (Vec<Uuid>, T)>) -> HashMap<Uuid, T> {
+    loop {
+        let removed = jobs.iter()
+            .filter(|(_, (dependencies, _))| dependencies.iter().any(|d| !jobs.contains_key(d)))
+            .map(|(uuid, _)| *uuid)
+            .collect::<HashSet<_>>();
+
+        if removed.is_empty() {
+            break
+        }
+        jobs.retain(|uuid, _| !removed.contains(uuid));
+    }
+
+    jobs.into_iter().map(|(uuid, (_, value))| (uuid, value)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_without_dependents_of_other_jobs() {
+        // 0 depends on 1 and 2, 1 depends on 3, 3 is not in the map
+        let ids = (0..4).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+        let mut jobs = HashMap::new();
+        jobs.insert(ids[0], (vec![ids[1], ids[2]], 0));
+        jobs.insert(ids[1], (vec![ids[3]], 1));
+        jobs.insert(ids[2], (vec![], 2));
+
+        let remaining = without_dependents_of_other_jobs(jobs);
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining.get(&ids[2]), Some(&2));
+    }
+}
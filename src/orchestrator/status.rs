@@ -0,0 +1,243 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The progress of a running submit, shown on a line above the bars of its jobs and written to
+//! a status file for tools that poll the progress of long submits
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use indicatif::ProgressBar;
+use log::warn;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::orchestrator::JobReport;
+use crate::orchestrator::JobReports;
+use crate::orchestrator::JobState;
+
+/// How often the status file is written while the jobs run
+pub const STATUS_FILE_INTERVAL_SECS: u64 = 10;
+
+/// How often the progress line of the submit is updated
+const PROGRESS_LINE_INTERVAL_SECS: u64 = 1;
+
+/// A job of the submit, with how long it is expected to take
+#[derive(Clone, Debug)]
+pub struct StatusJob {
+    pub uuid: Uuid,
+    pub package_name: String,
+    pub package_version: String,
+
+    /// The average duration of earlier jobs of the package in the image, if any were recorded
+    pub expected_secs: Option<f64>,
+}
+
+/// The progress of the jobs of a submit, the content of the status file
+#[derive(Debug, Serialize)]
+struct SubmitStatus<'a> {
+    submit: Uuid,
+    updated_at: String,
+    elapsed_secs: f64,
+    jobs_total: usize,
+    jobs_waiting: usize,
+    jobs_running: usize,
+    jobs_done: usize,
+    jobs_reused: usize,
+    jobs_failed: usize,
+    running: Vec<RunningJob<'a>>,
+
+    /// The estimated time until all jobs finished, `None` if no durations of earlier jobs are
+    /// known
+    eta_secs: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+struct RunningJob<'a> {
+    job: Uuid,
+    package_name: &'a str,
+    package_version: &'a str,
+    running_secs: f64,
+    expected_secs: Option<f64>,
+}
+
+/// The progress of the jobs of a submit, computed from the reports of the jobs
+#[derive(Debug)]
+pub struct SubmitProgress {
+    submit: Uuid,
+    started: std::time::Instant,
+    jobs: Vec<StatusJob>,
+}
+
+impl SubmitProgress {
+    pub fn new(submit: Uuid, jobs: Vec<StatusJob>) -> Self {
+        SubmitProgress { submit, started: std::time::Instant::now(), jobs }
+    }
+
+    fn status<'a>(&'a self, reports: &HashMap<Uuid, JobReport>) -> SubmitStatus<'a> {
+        let state = |job: &StatusJob| reports.get(&job.uuid).map(|r| r.state).unwrap_or(JobState::Waiting);
+        let count = |s: JobState| self.jobs.iter().filter(|job| state(job) == s).count();
+
+        let running = self.jobs
+            .iter()
+            .filter_map(|job| {
+                let running_secs = reports.get(&job.uuid)?.running_secs()?;
+                Some(RunningJob {
+                    job: job.uuid,
+                    package_name: &job.package_name,
+                    package_version: &job.package_version,
+                    running_secs,
+                    expected_secs: job.expected_secs,
+                })
+            })
+            .collect::<Vec<_>>();
+        let waiting = self.jobs
+            .iter()
+            .filter(|job| state(job) == JobState::Waiting)
+            .map(|job| job.expected_secs)
+            .collect::<Vec<_>>();
+        let eta_secs = estimate_remaining_secs(
+            &running.iter().map(|job| (job.running_secs, job.expected_secs)).collect::<Vec<_>>(),
+            &waiting,
+        );
+
+        SubmitStatus {
+            submit: self.submit,
+            updated_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            elapsed_secs: self.started.elapsed().as_secs_f64(),
+            jobs_total: self.jobs.len(),
+            jobs_waiting: count(JobState::Waiting),
+            jobs_running: count(JobState::Running),
+            jobs_done: count(JobState::Done),
+            jobs_reused: count(JobState::Reused),
+            jobs_failed: count(JobState::Failed),
+            running,
+            eta_secs,
+        }
+    }
+
+    /// The progress line of the submit, e.g. "Submit ...: 3/10 jobs finished, 2 running, ETA 5m"
+    pub fn line(&self, reports: &JobReports) -> String {
+        let status = self.status(&reports.reports());
+        let finished = status.jobs_done + status.jobs_reused + status.jobs_failed;
+        let mut line = format!("Submit {}: {}/{} jobs finished, {} running", self.submit, finished, status.jobs_total, status.jobs_running);
+        if status.jobs_failed > 0 {
+            line.push_str(&format!(", {} failed", status.jobs_failed));
+        }
+        if let Some(eta_secs) = status.eta_secs.filter(|_| finished < status.jobs_total) {
+            line.push_str(&format!(", ETA {}", humantime::format_duration(std::time::Duration::from_secs(eta_secs.round() as u64))));
+        }
+        line
+    }
+
+    /// Write the current status of the jobs in `reports` to the file at `path`
+    ///
+    /// The file is written next to its path and moved there, so readers never see a partial file.
+    pub async fn write_status_file(&self, path: &Path, reports: &JobReports) -> Result<()> {
+        let content = serde_json::to_vec_pretty(&self.status(&reports.reports()))?;
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, content)
+            .await
+            .with_context(|| anyhow!("Writing {}", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, path)
+            .await
+            .with_context(|| anyhow!("Moving {} to {}", tmp_path.display(), path.display()))
+    }
+
+    /// Update the progress line on `bar` every second and write the status file at
+    /// `status_file` every `STATUS_FILE_INTERVAL_SECS` seconds, forever
+    ///
+    /// Failing to write the file only results in a warning.
+    pub async fn update_periodically(&self, reports: &JobReports, bar: &ProgressBar, status_file: Option<&Path>) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(PROGRESS_LINE_INTERVAL_SECS));
+        let mut status_file_written: Option<std::time::Instant> = None;
+        loop {
+            interval.tick().await;
+            bar.set_message(self.line(reports));
+
+            let due = status_file_written
+                .map(|written| written.elapsed().as_secs() >= STATUS_FILE_INTERVAL_SECS)
+                .unwrap_or(true);
+            if let Some(path) = status_file.filter(|_| due) {
+                if let Err(e) = self.write_status_file(path, reports).await {
+                    warn!("Writing the status file failed: {:#}", e);
+                }
+                status_file_written = Some(std::time::Instant::now());
+            }
+        }
+    }
+}
+
+/// Estimate how long the `running` jobs (how long they run, how long they are expected to take)
+/// and the `waiting` jobs (how long they are expected to take) need to finish
+///
+/// Jobs without an expected duration are expected to take as long as the average of the others.
+/// The remaining work is spread over as many jobs as are running now, which is a rough estimate
+/// for the parallelism of the rest of the submit. `None` if no expected duration is known.
+pub fn estimate_remaining_secs(running: &[(f64, Option<f64>)], waiting: &[Option<f64>]) -> Option<f64> {
+    let known = running.iter()
+        .map(|(_, expected)| expected)
+        .chain(waiting.iter())
+        .filter_map(|expected| *expected)
+        .collect::<Vec<_>>();
+    if known.is_empty() {
+        return None
+    }
+    let average = known.iter().sum::<f64>() / known.len() as f64;
+
+    let remaining = running.iter()
+        .map(|(running, expected)| (expected.unwrap_or(average) - running).max(0.0))
+        .chain(waiting.iter().map(|expected| expected.unwrap_or(average)))
+        .sum::<f64>();
+    Some(remaining / running.len().max(1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_remaining_secs() {
+        assert_eq!(estimate_remaining_secs(&[], &[]), None);
+        assert_eq!(estimate_remaining_secs(&[(10.0, None)], &[None]), None);
+
+        // One job runs, it needs 50 more seconds, then the waiting job needs 100
+        assert_eq!(estimate_remaining_secs(&[(50.0, Some(100.0))], &[Some(100.0)]), Some(150.0));
+
+        // Jobs that run longer than expected are expected to finish now
+        assert_eq!(estimate_remaining_secs(&[(150.0, Some(100.0))], &[]), Some(0.0));
+
+        // The unknown job takes the average, the work is spread over the two running jobs
+        assert_eq!(estimate_remaining_secs(&[(0.0, Some(20.0)), (0.0, Some(40.0))], &[None]), Some(45.0));
+    }
+
+    #[test]
+    fn test_status_counts_unreported_jobs_as_waiting() {
+        let job = |package_name: &str, expected_secs| StatusJob {
+            uuid: Uuid::new_v4(),
+            package_name: String::from(package_name),
+            package_version: String::from("1"),
+            expected_secs,
+        };
+        let progress = SubmitProgress::new(Uuid::nil(), vec![job("a", Some(60.0)), job("b", None)]);
+
+        let status = progress.status(&HashMap::new());
+        assert_eq!(status.jobs_total, 2);
+        assert_eq!(status.jobs_waiting, 2);
+        assert!(status.running.is_empty());
+        assert_eq!(status.eta_secs, Some(120.0));
+
+        assert_eq!(progress.line(&JobReports::default()),
+            "Submit 00000000-0000-0000-0000-000000000000: 0/2 jobs finished, 0 running, ETA 2m");
+    }
+}
@@ -0,0 +1,86 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Helpers for showing the progress bars of the jobs as a tree, mirroring the dependencies
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use uuid::Uuid;
+
+/// The state of a job, as shown in the tree view
+#[derive(Clone, Copy, Debug, Eq, PartialEq, parse_display::Display, parse_display::FromStr, serde::Serialize)]
+#[display(style = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Waiting,
+    Running,
+    Done,
+    Failed,
+    Reused,
+}
+
+/// Compute the order in which the jobs are shown and the prefix that draws the tree for each job
+///
+/// `dependencies` maps each job to the jobs it depends on. A job that is a dependency of several
+/// jobs is only shown below the first of them.
+pub fn tree_layout(root: Uuid, dependencies: &HashMap<Uuid, Vec<Uuid>>) -> Vec<(Uuid, String)> {
+    fn visit(
+        id: Uuid,
+        prefix: String,
+        indent: String,
+        dependencies: &HashMap<Uuid, Vec<Uuid>>,
+        seen: &mut HashSet<Uuid>,
+        out: &mut Vec<(Uuid, String)>,
+    ) {
+        out.push((id, prefix));
+
+        let children = dependencies.get(&id)
+            .map(|deps| deps.iter().filter(|d| seen.insert(**d)).cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let n = children.len();
+        for (i, child) in children.into_iter().enumerate() {
+            let last = i + 1 == n;
+            let prefix = format!("{}{}", indent, if last { "└─ " } else { "├─ " });
+            let child_indent = format!("{}{}", indent, if last { "   " } else { "│  " });
+            visit(child, prefix, child_indent, dependencies, seen, out);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    seen.insert(root);
+    let mut out = Vec::with_capacity(dependencies.len());
+    visit(root, String::new(), String::new(), dependencies, &mut seen, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_layout() {
+        let ids = (0..4).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+        let mut deps = HashMap::new();
+        deps.insert(ids[0], vec![ids[1], ids[2]]);
+        deps.insert(ids[1], vec![ids[3]]);
+        deps.insert(ids[2], vec![ids[3]]);
+
+        let layout = tree_layout(ids[0], &deps);
+        let expected = vec![
+            (ids[0], String::from("")),
+            (ids[1], String::from("├─ ")),
+            (ids[3], String::from("│  └─ ")),
+            (ids[2], String::from("└─ ")),
+        ];
+        assert_eq!(layout, expected);
+    }
+}
@@ -0,0 +1,146 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::convert::TryFrom;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// A constraint on the labels of an endpoint, either "key=value" or a plain "key"
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct EndpointConstraint(String);
+
+impl EndpointConstraint {
+    /// Whether an endpoint with `endpoint_labels` satisfies the constraint
+    ///
+    /// "key=value" is satisfied by the label "key=value", a plain "key" by the label "key" and by
+    /// every label "key=...".
+    pub fn is_satisfied_by(&self, endpoint_labels: &[String]) -> bool {
+        endpoint_labels.iter().any(|label| {
+            *label == self.0 || (!self.0.contains('=') && label.split_once('=').map(|(key, _)| key == self.0).unwrap_or(false))
+        })
+    }
+}
+
+impl TryFrom<String> for EndpointConstraint {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let key = s.split_once('=').map(|(key, _)| key).unwrap_or(&s);
+        if key.is_empty() || s.contains(char::is_whitespace) {
+            return Err(anyhow!("Invalid endpoint constraint '{}', expected KEY=VALUE or KEY", s))
+        }
+        Ok(EndpointConstraint(s))
+    }
+}
+
+impl std::str::FromStr for EndpointConstraint {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EndpointConstraint::try_from(s.to_string())
+    }
+}
+
+impl From<EndpointConstraint> for String {
+    fn from(c: EndpointConstraint) -> Self {
+        c.0
+    }
+}
+
+impl std::fmt::Display for EndpointConstraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The endpoints the jobs of a package run on, by the names or labels of the endpoints
+#[derive(Clone, Debug, Default, Eq, PartialEq, Getters, Serialize, Deserialize)]
+pub struct EndpointAffinity {
+    /// The jobs only run on endpoints with one of these names or labels, on all endpoints if empty
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    required: Vec<String>,
+
+    /// The jobs run on endpoints with one of these names or labels if one of them has a free
+    /// job slot, on the others otherwise
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    preferred: Vec<String>,
+
+    /// The jobs only run on endpoints that satisfy all of these constraints on their labels
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    constraints: Vec<EndpointConstraint>,
+}
+
+impl EndpointAffinity {
+    fn matches(names_or_labels: &[String], endpoint_name: &str, endpoint_labels: &[String]) -> bool {
+        names_or_labels
+            .iter()
+            .any(|n| n == endpoint_name || endpoint_labels.contains(n))
+    }
+
+    /// Whether the jobs can run on the endpoint with `endpoint_name` and `endpoint_labels`
+    pub fn allows(&self, endpoint_name: &str, endpoint_labels: &[String]) -> bool {
+        (self.required.is_empty() || Self::matches(&self.required, endpoint_name, endpoint_labels))
+            && self.constraints.iter().all(|c| c.is_satisfied_by(endpoint_labels))
+    }
+
+    /// Whether the endpoint with `endpoint_name` and `endpoint_labels` is preferred for the jobs
+    pub fn prefers(&self, endpoint_name: &str, endpoint_labels: &[String]) -> bool {
+        Self::matches(&self.preferred, endpoint_name, endpoint_labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_affinity() {
+        let affinity: EndpointAffinity = toml::from_str(indoc::indoc!(r#"
+            required = ["bigmem", "builder3"]
+            preferred = ["ssd"]
+        "#)).unwrap();
+        let labels = |ls: &[&str]| ls.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+
+        assert!(affinity.allows("builder1", &labels(&["bigmem", "ssd"])));
+        assert!(affinity.allows("builder3", &[]));
+        assert!(!affinity.allows("builder2", &labels(&["ssd"])));
+        assert!(affinity.prefers("builder1", &labels(&["bigmem", "ssd"])));
+        assert!(!affinity.prefers("builder3", &[]));
+
+        let any = EndpointAffinity::default();
+        assert!(any.allows("builder2", &[]));
+        assert!(!any.prefers("builder2", &[]));
+    }
+
+    #[test]
+    fn test_endpoint_constraints() {
+        let affinity: EndpointAffinity = toml::from_str(indoc::indoc!(r#"
+            constraints = ["arch=x86_64", "fast-io", "zone"]
+        "#)).unwrap();
+        let labels = |ls: &[&str]| ls.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+
+        assert!(affinity.allows("builder1", &labels(&["arch=x86_64", "fast-io", "zone=dc1"])));
+        assert!(!affinity.allows("builder1", &labels(&["arch=aarch64", "fast-io", "zone=dc1"])));
+        assert!(!affinity.allows("builder1", &labels(&["arch=x86_64", "zone=dc1"])));
+        assert!(affinity.allows("builder1", &labels(&["arch=x86_64", "fast-io=yes", "zone=dc1"])));
+        assert!(!affinity.allows("zone", &labels(&["arch=x86_64", "fast-io"])));
+
+        assert!(toml::from_str::<EndpointAffinity>(r#"constraints = ["=x86_64"]"#).is_err());
+        assert!("arch = x86_64".parse::<EndpointConstraint>().is_err());
+    }
+}
@@ -10,6 +10,7 @@
 
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::io::Result as IoResult;
 use std::io::Write;
 
@@ -27,7 +28,9 @@ use resiter::AndThen;
 
 use crate::package::Package;
 use crate::package::PackageName;
+use crate::package::PackageVersion;
 use crate::package::PackageVersionConstraint;
+use crate::package::VersionResolution;
 use crate::package::condition::ConditionCheckable;
 use crate::package::condition::ConditionData;
 use crate::package::dependency::ParseDependency;
@@ -49,6 +52,7 @@ impl Dag {
         repo: &Repository,
         progress: Option<&ProgressBar>,
         conditional_data: &ConditionData<'_>, // required for selecting packages with conditional dependencies
+        resolution: &VersionResolution, // required for selecting one of several matching versions
     ) -> Result<Self> {
 
         /// helper fn with bad name to check the dependency condition of a dependency and parse the dependency into a tuple of
@@ -71,19 +75,28 @@ impl Dag {
         ///
         /// It also filters out dependencies that do not match the `conditional_data` passed and
         /// makes the dependencies unique over (name, version).
-        fn get_package_dependencies<'a>(package: &'a Package, conditional_data: &'a ConditionData<'_>)
-            -> impl Iterator<Item = Result<(PackageName, PackageVersionConstraint)>> + 'a
+        ///
+        /// The conditions are checked against the options of the package, with the option values
+        /// from the `conditional_data`.
+        fn get_package_dependencies(package: &Package, conditional_data: &ConditionData<'_>)
+            -> impl Iterator<Item = Result<(PackageName, PackageVersionConstraint)>>
         {
+            let options = package.options_with(conditional_data.options);
+            let conditional_data = ConditionData {
+                image_name: conditional_data.image_name,
+                env: conditional_data.env,
+                options: &options,
+            };
 
             package.dependencies()
                 .build()
                 .iter()
-                .map(move |d| process(d, conditional_data))
+                .map(|d| process(d, &conditional_data))
                 .chain({
                     package.dependencies()
                         .runtime()
                         .iter()
-                        .map(move |d| process(d, conditional_data))
+                        .map(|d| process(d, &conditional_data))
                 })
 
                 // Now filter out all dependencies where their condition did not match our
@@ -100,27 +113,65 @@ impl Dag {
                 // Make all dependencies unique, because we don't want to build one dependency
                 // multiple times
                 .unique_by(|res| res.as_ref().ok().cloned())
+                .collect::<Vec<_>>()
+                .into_iter()
         }
 
+        /// Helper fn to select the packages that satisfy a dependency on `name`
+        ///
+        /// Packages that are named `name` (or have an output with that name) are preferred over
+        /// packages that provide `name` as a virtual package.
+        fn prefer_named<'a, T, F>(candidates: Vec<T>, name: &PackageName, package: F) -> Vec<T>
+            where F: Fn(&T) -> &'a Package
+        {
+            if candidates.iter().any(|c| package(c).is_named(name)) {
+                candidates.into_iter().filter(|c| package(c).is_named(name)).collect()
+            } else {
+                candidates
+            }
+        }
+
+        /// A dependency on a virtual package that is provided by several packages
+        struct VirtualDependency<'a> {
+            package: &'a Package,
+            name: PackageName,
+            constraint: PackageVersionConstraint,
+            providers: Vec<&'a Package>,
+        }
+
+        #[allow(clippy::too_many_arguments)]
         fn add_sub_packages<'a>(
             repo: &'a Repository,
             mappings: &mut HashMap<&'a Package, daggy::NodeIndex>,
             dag: &mut daggy::Dag<&'a Package, i8>,
+            virtuals: &mut Vec<VirtualDependency<'a>>,
             p: &'a Package,
             progress: Option<&ProgressBar>,
             conditional_data: &ConditionData<'_>,
+            resolution: &VersionResolution,
         ) -> Result<()> {
             get_package_dependencies(p, conditional_data)
                 .and_then_ok(|(name, constr)| {
                     trace!("Dependency for {} {} found: {:?}", p.name(), p.version(), name);
-                    let packs = repo.find_with_version(&name, &constr);
+                    let constr = resolution.constraint_for(&name, constr);
+                    let packs = prefer_named(repo.find_with_version(&name, &constr), &name, |pk| pk);
                     if packs.is_empty() {
                         return Err(anyhow!("Dependency of {} {} not found: {} {}", p.name(), p.version(), name, constr))
                     }
+                    let packs = resolution.select(&name, &constr, packs)
+                        .map_err(|e| e.context(anyhow!("Resolving dependencies of {} {}", p.name(), p.version())))?;
                     trace!("Found in repo: {:?}", packs);
 
+                    // If several packages provide the virtual package, the one that is in the tree
+                    // anyways is used, which can only be decided once the tree is complete
+                    if packs.iter().map(|pk| pk.qualified_name()).unique().count() > 1 {
+                        trace!("Virtual package {} is provided by multiple packages: {:?}", name, packs);
+                        virtuals.push(VirtualDependency { package: p, name, constraint: constr, providers: packs });
+                        return Ok(())
+                    }
+
                     // If we didn't check that dependency already
-                    if !mappings.keys().any(|p| packs.iter().any(|pk| pk.name() == p.name() && pk.version() == p.version())) {
+                    if !mappings.keys().any(|p| packs.iter().any(|pk| pk == p)) {
                         // recurse
                         packs.into_iter()
                             .try_for_each(|p| {
@@ -130,7 +181,7 @@ impl Dag {
                                 mappings.insert(p, idx);
 
                                 trace!("Recursing for: {:?}", p);
-                                add_sub_packages(repo, mappings, dag, p, progress, conditional_data)
+                                add_sub_packages(repo, mappings, dag, virtuals, p, progress, conditional_data, resolution)
                             })
                     } else {
                         Ok(())
@@ -139,21 +190,108 @@ impl Dag {
                 .collect::<Result<()>>()
         }
 
+        /// Check that exactly one of the providers of each virtual dependency is in the tree
+        ///
+        /// A provider is in the tree if another package depends on it explicitly.
+        fn check_virtual_dependencies(
+            mappings: &HashMap<&Package, daggy::NodeIndex>,
+            virtuals: &[VirtualDependency<'_>],
+        ) -> Result<()> {
+            for v in virtuals {
+                let in_tree = v.providers
+                    .iter()
+                    .map(|pk| pk.qualified_name())
+                    .filter(|name| mappings.keys().any(|m| m.qualified_name() == *name))
+                    .unique()
+                    .count();
+
+                if in_tree != 1 {
+                    let candidates = v.providers
+                        .iter()
+                        .map(|pk| format!("{} {}", pk.qualified_name(), pk.version()))
+                        .join(", ");
+
+                    return Err(anyhow!(
+                        "Dependency of {} {} on {} {} is ambiguous, it is provided by: {}. Depend on one of them explicitly.",
+                        v.package.name(), v.package.version(), v.name, v.constraint, candidates
+                    ))
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Build the error for the dependency of `from` on `to`, which would close a cycle
+        ///
+        /// The error shows the whole cycle and the pkg.toml files of the packages in it.
+        fn cycle_error(dag: &daggy::Dag<&Package, i8>, from: daggy::NodeIndex, to: daggy::NodeIndex) -> Error {
+            /// Find a path from `current` to `target`, depth first
+            fn find_path(
+                dag: &daggy::Dag<&Package, i8>,
+                current: daggy::NodeIndex,
+                target: daggy::NodeIndex,
+                visited: &mut HashSet<daggy::NodeIndex>,
+                path: &mut Vec<daggy::NodeIndex>,
+            ) -> bool {
+                path.push(current);
+                if current == target {
+                    return true
+                }
+
+                if visited.insert(current) {
+                    let mut children = dag.children(current).iter(dag).map(|(_, c)| c);
+                    if children.any(|child| find_path(dag, child, target, visited, path)) {
+                        return true
+                    }
+                }
+
+                path.pop();
+                false
+            }
+
+            // The edge would close a cycle, so there is a path from `to` back to `from` already
+            let mut path = vec![from];
+            find_path(dag, to, from, &mut HashSet::new(), &mut path);
+
+            let packages = path.iter().map(|idx| dag[*idx]).collect::<Vec<_>>();
+            let cycle = packages
+                .iter()
+                .map(|p| format!("{} {}", p.name(), p.version()))
+                .join(" -> ");
+            let locations = packages
+                .iter()
+                .unique_by(|p| (p.name(), p.version()))
+                .map(|p| format!("\n    {}", p.display_with_location()))
+                .join("");
+
+            anyhow!("Dependency cycle detected: {}\nThe packages in the cycle are:{}", cycle, locations)
+        }
+
         fn add_edges(mappings: &HashMap<&Package, daggy::NodeIndex>,
             dag: &mut daggy::Dag<&Package, i8>,
             conditional_data: &ConditionData<'_>,
+            resolution: &VersionResolution,
         ) -> Result<()>
         {
             for (package, idx) in mappings {
                 get_package_dependencies(package, conditional_data)
                     .and_then_ok(|(name, constr)| {
-                        mappings
-                            .iter()
-                            .filter(|(package, _)| *package.name() == name && constr.matches(package.version()))
-                            .try_for_each(|(_, dep_idx)| {
-                                dag.add_edge(*idx, *dep_idx, 0)
+                        let constr = resolution.constraint_for(&name, constr);
+                        let dependencies = mappings
+                            .keys()
+                            .copied()
+                            .filter(|package| package.satisfies(&name) && constr.matches(package.version()))
+                            .collect::<Vec<_>>();
+
+                        // Several versions of a dependency can be in the tree if other packages
+                        // depend on them with other constraints
+                        resolution.select(&name, &constr, prefer_named(dependencies, &name, |package| package))?
+                            .into_iter()
+                            .map(|dep| mappings[dep])
+                            .try_for_each(|dep_idx| {
+                                dag.add_edge(*idx, dep_idx, 0)
                                     .map(|_| ())
-                                    .map_err(Error::from)
+                                    .map_err(|_| cycle_error(dag, *idx, dep_idx))
                             })
                     })
                     .collect::<Result<()>>()?
@@ -168,12 +306,19 @@ impl Dag {
         trace!("Making package Tree for {:?}", p);
         let root_idx = dag.add_node(&p);
         mappings.insert(&p, root_idx);
-        add_sub_packages(repo, &mut mappings, &mut dag, &p, progress, conditional_data)?;
-        add_edges(&mappings, &mut dag, conditional_data)?;
+        let mut virtuals = vec![];
+        add_sub_packages(repo, &mut mappings, &mut dag, &mut virtuals, &p, progress, conditional_data, resolution)
+            .and_then(|_| check_virtual_dependencies(&mappings, &virtuals))
+            .and_then(|_| add_edges(&mappings, &mut dag, conditional_data, resolution))
+            .map_err(crate::error::Error::Resolution)?;
         trace!("Finished makeing package Tree");
 
         Ok(Dag {
-            dag: dag.map(|_, p: &&Package| -> Package { (*p).clone() }, |_, e| *e),
+            dag: dag.map(|_, p: &&Package| -> Package {
+                let mut p = (*p).clone();
+                p.set_option_values(conditional_data.options);
+                p
+            }, |_, e| *e),
             root_idx
         })
     }
@@ -191,9 +336,82 @@ impl Dag {
             .collect()
     }
 
+    /// The packages on a path from the root of the tree to the package `name`, including both, or
+    /// `None` if the package is not in the tree
+    pub fn dependency_path(&self, name: &PackageName) -> Option<Vec<&Package>> {
+        // Breadth first, so that the shortest path is found
+        let mut parents = HashMap::new();
+        let mut unvisited = std::collections::VecDeque::from(vec![self.root_idx]);
+        let mut visited = HashSet::new();
+        while let Some(idx) = unvisited.pop_front() {
+            if self.dag[idx].name() == name {
+                let mut path = vec![&self.dag[idx]];
+                let mut current = idx;
+                while let Some(parent) = parents.get(&current) {
+                    path.push(&self.dag[*parent]);
+                    current = *parent;
+                }
+                path.reverse();
+                return Some(path)
+            }
+
+            for (_, child) in self.dag.children(idx).iter(&self.dag) {
+                if visited.insert(child) {
+                    parents.insert(child, idx);
+                    unvisited.push_back(child);
+                }
+            }
+        }
+        None
+    }
+
     pub fn display(&self) -> DagDisplay {
         DagDisplay(self, self.root_idx)
     }
+
+    /// The part of the tree that is rooted at the package `name`, in `version` if given
+    ///
+    /// Fails if the package is not in the tree, or in several versions and `version` is not given.
+    pub fn subtree(&self, name: &PackageName, version: Option<&PackageVersion>) -> Result<Dag> {
+        let matching = self.dag
+            .graph()
+            .node_indices()
+            .filter(|idx| {
+                let p = &self.dag[*idx];
+                p.name() == name && version.map(|v| p.version() == v).unwrap_or(true)
+            })
+            .collect::<Vec<_>>();
+
+        let old_root = match matching.as_slice() {
+            [idx] => *idx,
+            [] => {
+                let root = &self.dag[self.root_idx];
+                return Err(anyhow!("{} {} is not in the tree of {} {}",
+                    name, version.map(|v| v.to_string()).unwrap_or_default(), root.name(), root.version()))
+            },
+            _ => return Err(anyhow!("{} is in the tree in several versions: {}",
+                name, matching.iter().map(|idx| self.dag[*idx].version()).join(", "))),
+        };
+
+        let mut dag = daggy::Dag::new();
+        let mut mappings = HashMap::new();
+        let root_idx = dag.add_node(self.dag[old_root].clone());
+        mappings.insert(old_root, root_idx);
+
+        let mut unvisited = vec![old_root];
+        while let Some(old_idx) = unvisited.pop() {
+            let parent = mappings[&old_idx];
+            for (edge, old_child) in self.dag.children(old_idx).iter(&self.dag) {
+                let child = *mappings.entry(old_child).or_insert_with(|| {
+                    unvisited.push(old_child);
+                    dag.add_node(self.dag[old_child].clone())
+                });
+                dag.add_edge(parent, child, self.dag[edge])?;
+            }
+        }
+
+        Ok(Dag { dag, root_idx })
+    }
 }
 
 #[derive(Clone)]
@@ -206,7 +424,11 @@ impl<'a> TreeItem for DagDisplay<'a> {
         let p = self.0.dag.graph().node_weight(self.1)
             .ok_or_else(|| anyhow!("Error finding node: {:?}", self.1))
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-        write!(f, "{} {}", p.name(), p.version())
+        write!(f, "{} {}", p.name(), p.version())?;
+        if let Some(options) = p.options_string() {
+            write!(f, " [{}]", options)?;
+        }
+        Ok(())
     }
 
     fn children(&self) -> Cow<[Self::Child]> {
@@ -226,6 +448,7 @@ mod tests {
 
     use crate::package::Dependencies;
     use crate::package::Dependency;
+    use crate::package::PackageOptions;
     use crate::package::condition::Condition;
     use crate::package::condition::OneOrMore;
     use crate::package::tests::package;
@@ -253,9 +476,10 @@ mod tests {
         let condition_data = ConditionData {
             image_name: None,
             env: &[],
+            options: &PackageOptions::new(),
         };
 
-        let r = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        let r = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data, &VersionResolution::default());
 
         assert!(r.is_ok());
     }
@@ -291,9 +515,10 @@ mod tests {
         let condition_data = ConditionData {
             image_name: None,
             env: &[],
+            options: &PackageOptions::new(),
         };
 
-        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data, &VersionResolution::default());
         assert!(dag.is_ok());
         let dag = dag.unwrap();
         let ps = dag.all_packages();
@@ -385,9 +610,10 @@ mod tests {
         let condition_data = ConditionData {
             image_name: None,
             env: &[],
+            options: &PackageOptions::new(),
         };
 
-        let r = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        let r = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data, &VersionResolution::default());
         assert!(r.is_ok());
         let r = r.unwrap();
         let ps = r.all_packages();
@@ -533,9 +759,10 @@ mod tests {
         let condition_data = ConditionData {
             image_name: None,
             env: &[],
+            options: &PackageOptions::new(),
         };
 
-        let r = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        let r = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data, &VersionResolution::default());
         assert!(r.is_ok());
         let r = r.unwrap();
         let ps = r.all_packages();
@@ -643,9 +870,10 @@ mod tests {
         let condition_data = ConditionData {
             image_name: None,
             env: &[],
+            options: &PackageOptions::new(),
         };
 
-        let r = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        let r = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data, &VersionResolution::default());
         assert!(r.is_ok());
         let r = r.unwrap();
         let ps = r.all_packages();
@@ -699,11 +927,12 @@ mod tests {
         let condition_data = ConditionData {
             image_name: None,
             env: &[],
+            options: &PackageOptions::new(),
         };
 
         let progress = ProgressBar::hidden();
 
-        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data, &VersionResolution::default());
         assert!(dag.is_ok());
         let dag = dag.unwrap();
         let ps = dag.all_packages();
@@ -733,11 +962,12 @@ mod tests {
         let condition_data = ConditionData {
             image_name: Some(&img_name),
             env: &[],
+            options: &PackageOptions::new(),
         };
 
         let progress = ProgressBar::hidden();
 
-        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data, &VersionResolution::default());
         assert!(dag.is_ok());
         let dag = dag.unwrap();
         let ps = dag.all_packages();
@@ -763,11 +993,12 @@ mod tests {
         let condition_data = ConditionData {
             image_name: Some(&img_name),
             env: &[],
+            options: &PackageOptions::new(),
         };
 
         let progress = ProgressBar::hidden();
 
-        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data);
+        let dag = Dag::for_root_package(p1, &repo, Some(&progress), &condition_data, &VersionResolution::default());
         assert!(dag.is_ok());
         let dag = dag.unwrap();
         let ps = dag.all_packages();
@@ -780,5 +1011,226 @@ mod tests {
         assert!(ps.iter().any(|p| *p.version() == pversion("2")));
     }
 
+    // Test whether the dependency DAG is correctly build if the dependency is conditional on an
+    // option of the package, which is off by default
+    #[test]
+    fn test_add_two_dependent_packages_with_option_conditional() {
+        let condition: Condition = toml::from_str("options = { ssl = true }").unwrap();
+        let (mut p1, repo) = repo_with_ab_packages_with_condition(condition);
+        p1.set_options({
+            let mut options = PackageOptions::new();
+            options.insert(String::from("ssl"), false);
+            options
+        });
+
+        let build = |options: &PackageOptions| {
+            let condition_data = ConditionData {
+                image_name: None,
+                env: &[],
+                options,
+            };
+            Dag::for_root_package(p1.clone(), &repo, None, &condition_data, &VersionResolution::default()).unwrap()
+        };
+
+        let dag = build(&PackageOptions::new());
+        assert!(!dag.all_packages().iter().any(|p| *p.name() == pname("b")));
+
+        let mut options = PackageOptions::new();
+        options.insert(String::from("ssl"), true);
+        let dag = build(&options);
+        let ps = dag.all_packages();
+        assert!(ps.iter().any(|p| *p.name() == pname("b")));
+
+        let root = ps.iter().find(|p| *p.name() == pname("a")).unwrap();
+        assert_eq!(root.options_string(), Some(String::from("ssl=on")));
+    }
+
+    /// Helper to build a repository with "a 1" depending on `dependencies` and packages "b 1" and
+    /// "c 1" which provide the virtual package "libssl" (if `provides` is set for them)
+    fn repo_with_libssl_providers(dependencies: &[&str], provides: &[bool]) -> (Package, Repository) {
+        let mut btree = BTreeMap::new();
+
+        let mut a = package("a", "1", "https://rust-lang.org", "123");
+        a.set_dependencies(Dependencies::with_runtime_dependencies({
+            dependencies.iter().map(|d| Dependency::from(String::from(*d))).collect()
+        }));
+        btree.insert((pname("a"), pversion("1")), a.clone());
+
+        for (name, provides) in ["b", "c"].iter().zip(provides) {
+            let mut pack = package(name, "1", "https://rust-lang.org", "124");
+            if *provides {
+                pack.set_provides(vec![pname("libssl")]);
+            }
+            btree.insert((pname(name), pversion("1")), pack);
+        }
+
+        (a, Repository::from(btree))
+    }
+
+    fn tree_names(root: Package, repo: &Repository) -> Result<Vec<PackageName>> {
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &PackageOptions::new(),
+        };
+
+        Dag::for_root_package(root, repo, None, &condition_data, &VersionResolution::default()).map(|dag| {
+            dag.all_packages().into_iter().map(|p| p.name().clone()).sorted().collect()
+        })
+    }
+
+    #[test]
+    fn test_virtual_package_with_one_provider() {
+        let (a, repo) = repo_with_libssl_providers(&["libssl =1"], &[true, false]);
+        assert_eq!(tree_names(a, &repo).unwrap(), vec![pname("a"), pname("b")]);
+    }
+
+    #[test]
+    fn test_virtual_package_with_ambiguous_providers() {
+        let (a, repo) = repo_with_libssl_providers(&["libssl =1"], &[true, true]);
+        let err = tree_names(a, &repo).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Dependency of a 1 on libssl =1 is ambiguous, it is provided by: b 1, c 1. Depend on one of them explicitly."
+        );
+    }
+
+    #[test]
+    fn test_virtual_package_with_explicitly_requested_provider() {
+        let (a, repo) = repo_with_libssl_providers(&["libssl =1", "c =1"], &[true, true]);
+        assert_eq!(tree_names(a, &repo).unwrap(), vec![pname("a"), pname("c")]);
+    }
+
+    #[test]
+    fn test_subtree() {
+        // a -> b -> c, a -> d
+        let mut btree = BTreeMap::new();
+        let mut a = package("a", "1", "https://rust-lang.org", "123");
+        a.set_dependencies(Dependencies::with_runtime_dependencies(vec![
+            Dependency::from(String::from("b =1")),
+            Dependency::from(String::from("d =1")),
+        ]));
+        let mut b = package("b", "1", "https://rust-lang.org", "124");
+        b.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from("c =1"))));
+        btree.insert((pname("a"), pversion("1")), a.clone());
+        btree.insert((pname("b"), pversion("1")), b);
+        btree.insert((pname("c"), pversion("1")), package("c", "1", "https://rust-lang.org", "125"));
+        btree.insert((pname("d"), pversion("1")), package("d", "1", "https://rust-lang.org", "126"));
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &PackageOptions::new(),
+        };
+        let dag = Dag::for_root_package(a, &repo, None, &condition_data, &VersionResolution::default()).unwrap();
+
+        let subtree = dag.subtree(&pname("b"), None).unwrap();
+        assert_eq!(*subtree.dag()[*subtree.root_idx()].name(), pname("b"));
+        let names = subtree.all_packages().into_iter().map(|p| p.name().clone()).sorted().collect::<Vec<_>>();
+        assert_eq!(names, vec![pname("b"), pname("c")]);
+        assert_eq!(subtree.dag().edge_count(), 1);
+
+        assert!(dag.subtree(&pname("b"), Some(&pversion("2"))).is_err());
+        assert!(dag.subtree(&pname("e"), None).is_err());
+
+        let path = dag.dependency_path(&pname("c")).unwrap().into_iter().map(|p| p.name().clone()).collect::<Vec<_>>();
+        assert_eq!(path, vec![pname("a"), pname("b"), pname("c")]);
+        assert!(dag.dependency_path(&pname("e")).is_none());
+    }
+
+    #[test]
+    fn test_dependency_cycle() {
+        // a -> b -> c -> a
+        let mut btree = BTreeMap::new();
+        let mut packages = ["a", "b", "c"]
+            .iter()
+            .map(|name| package(name, "1", "https://rust-lang.org", "123"))
+            .collect::<Vec<_>>();
+        for (p, dep) in packages.iter_mut().zip(["b", "c", "a"].iter()) {
+            p.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(format!("{} =1", dep))));
+            p.set_definition_file(std::path::PathBuf::from(format!("{}/pkg.toml", p.name())));
+            btree.insert((p.name().clone(), p.version().clone()), p.clone());
+        }
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &PackageOptions::new(),
+        };
+
+        let err = Dag::for_root_package(packages[0].clone(), &repo, None, &condition_data, &VersionResolution::default()).unwrap_err().to_string();
+        let cycle = err.lines().next().unwrap();
+
+        // Depending on the order the edges are added in, the cycle may start at any package
+        assert!([
+            "Dependency cycle detected: a 1 -> b 1 -> c 1 -> a 1",
+            "Dependency cycle detected: b 1 -> c 1 -> a 1 -> b 1",
+            "Dependency cycle detected: c 1 -> a 1 -> b 1 -> c 1",
+        ].contains(&cycle), "Unexpected error: {}", err);
+        assert!(err.contains("    a 1 (a/pkg.toml)"), "Unexpected error: {}", err);
+        assert!(err.contains("    c 1 (c/pkg.toml)"), "Unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_dependency_on_itself() {
+        let mut btree = BTreeMap::new();
+        let mut a = package("a", "1", "https://rust-lang.org", "123");
+        a.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from("a =1"))));
+        btree.insert((pname("a"), pversion("1")), a.clone());
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &PackageOptions::new(),
+        };
+
+        let err = Dag::for_root_package(a, &repo, None, &condition_data, &VersionResolution::default()).unwrap_err().to_string();
+        assert_eq!(err, "Dependency cycle detected: a 1 -> a 1\nThe packages in the cycle are:\n    a 1");
+    }
+
+    #[test]
+    fn test_dependency_with_multiple_matching_versions() {
+        let mut btree = BTreeMap::new();
+        let mut a = package("a", "1", "https://rust-lang.org", "123");
+        a.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from("b ~1"))));
+        btree.insert((pname("a"), pversion("1")), a.clone());
+        for version in ["1.9", "1.10", "2.0"].iter() {
+            btree.insert((pname("b"), pversion(version)), package("b", version, "https://rust-lang.org", "124"));
+        }
+
+        let repo = Repository::from(btree);
+        let condition_data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &PackageOptions::new(),
+        };
+        let b_versions = |resolution: &str| -> Result<Vec<String>> {
+            let resolution: VersionResolution = toml::from_str(resolution).unwrap();
+            Dag::for_root_package(a.clone(), &repo, None, &condition_data, &resolution).map(|dag| {
+                dag.all_packages()
+                    .into_iter()
+                    .filter(|p| *p.name() == pname("b"))
+                    .map(|p| p.version().to_string())
+                    .collect()
+            })
+        };
+
+        assert_eq!(b_versions(r#"strategy = "highest""#).unwrap(), vec!["1.10"]);
+        assert_eq!(b_versions(r#"strategy = "lowest""#).unwrap(), vec!["1.9"]);
+        assert_eq!(b_versions("[pins]\nb = \"1.9\"").unwrap(), vec!["1.9"]);
+
+        // Overrides ignore the constraint of the dependency
+        let resolution = VersionResolution::default().with_overrides(vec![(pname("b"), pversion("2.0"))].into_iter().collect());
+        let dag = Dag::for_root_package(a.clone(), &repo, None, &condition_data, &resolution).unwrap();
+        assert!(dag.all_packages().iter().any(|p| *p.name() == pname("b") && *p.version() == pversion("2.0")));
+
+        let err = b_versions("").unwrap_err();
+        assert!(err.to_string().starts_with("Resolving dependencies of a 1"), "Unexpected error: {:?}", err);
+        assert!(format!("{:#}", err).contains("matches multiple versions of b: 1.9, 1.10"), "Unexpected error: {:?}", err);
+    }
+
 }
 
@@ -13,8 +13,10 @@ use std::collections::BTreeMap;
 use serde::Deserialize;
 use serde::Serialize;
 use getset::Getters;
+use anyhow::anyhow;
 use anyhow::Result;
 
+use crate::package::PackageOptions;
 use crate::util::EnvironmentVariableName;
 use crate::util::docker::ImageName;
 
@@ -23,8 +25,8 @@ use crate::util::docker::ImageName;
 /// This type represents a condition whether a dependency should be included in the package tree or
 /// not.
 ///
-/// Right now, we are supporting condition by environment (set or equal) or whether a specific
-/// build image is used.
+/// Right now, we are supporting condition by environment (set or equal), whether a specific
+/// build image is used or whether build options of the package are switched on or off.
 /// All these settings are optional, of course.
 ///
 #[derive(Serialize, Deserialize, Getters, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -40,6 +42,10 @@ pub struct Condition {
     #[serde(rename = "in_image", skip_serializing_if = "Option::is_none")]
     #[getset(get = "pub")]
     pub(super) in_image: Option<OneOrMore<String>>,
+
+    #[serde(rename = "options", skip_serializing_if = "Option::is_none")]
+    #[getset(get = "pub")]
+    pub(super) options: Option<BTreeMap<String, bool>>,
 }
 
 impl Condition {
@@ -49,14 +55,14 @@ impl Condition {
                in_image: Option<OneOrMore<String>>)
         -> Self
     {
-        Condition { has_env, env_eq, in_image }
+        Condition { has_env, env_eq, in_image, options: None }
     }
 
     /// Check whether the condition matches a certain set of data
     ///
     /// # Return value
     ///
-    /// Always returns Ok(_) in the current implementation
+    /// Returns an error if the condition uses an option the package does not have
     pub fn matches(&self, data: &ConditionData<'_>) -> Result<bool> {
         if !self.matches_env_cond(data)? {
             return Ok(false)
@@ -70,6 +76,26 @@ impl Condition {
             return Ok(false)
         }
 
+        if !self.matches_options_cond(data)? {
+            return Ok(false)
+        }
+
+        Ok(true)
+    }
+
+    fn matches_options_cond(&self, data: &ConditionData<'_>) -> Result<bool> {
+        if let Some(options_cond) = self.options.as_ref() {
+            for (name, required) in options_cond {
+                let enabled = data.options
+                    .get(name)
+                    .ok_or_else(|| anyhow!("Condition uses unknown option '{}'", name))?;
+
+                if enabled != required {
+                    return Ok(false)
+                }
+            }
+        }
+
         Ok(true)
     }
 
@@ -185,6 +211,12 @@ impl From<String> for OneOrMore<String> {
 pub struct ConditionData<'a> {
     pub(crate) image_name: Option<&'a ImageName>,
     pub(crate) env: &'a [(EnvironmentVariableName, String)],
+
+    /// The values of the build options
+    ///
+    /// These are the values set on the commandline. While checking the conditions of the
+    /// dependencies of a package, these are the options of that package.
+    pub(crate) options: &'a PackageOptions,
 }
 
 /// Trait for all things that have a condition that can be checked against ConditionData.
@@ -290,6 +322,7 @@ mod tests {
         let data = ConditionData {
             image_name: None,
             env: &[],
+            options: &PackageOptions::new(),
         };
 
         let condition = Condition::new(None, None, None);
@@ -302,6 +335,7 @@ mod tests {
         let data = ConditionData {
             image_name: None,
             env: &[],
+            options: &PackageOptions::new(),
         };
 
         let condition = Condition::new(None, None, {
@@ -317,6 +351,7 @@ mod tests {
         let data = ConditionData {
             image_name: Some(&img),
             env: &[],
+            options: &PackageOptions::new(),
         };
 
         let condition = Condition::new(None, None, {
@@ -332,6 +367,7 @@ mod tests {
         let data = ConditionData {
             image_name: Some(&img),
             env: &[],
+            options: &PackageOptions::new(),
         };
 
         let condition = Condition::new(None, None, {
@@ -346,6 +382,7 @@ mod tests {
         let data = ConditionData {
             image_name: None,
             env: &[],
+            options: &PackageOptions::new(),
         };
 
         let condition = Condition::new({
@@ -360,6 +397,7 @@ mod tests {
         let data = ConditionData {
             image_name: None,
             env: &[(EnvironmentVariableName::from("A"), String::from("1"))],
+            options: &PackageOptions::new(),
         };
 
         let condition = Condition::new({
@@ -374,6 +412,7 @@ mod tests {
         let data = ConditionData {
             image_name: None,
             env: &[],
+            options: &PackageOptions::new(),
         };
 
         let condition = Condition::new(None, {
@@ -390,6 +429,7 @@ mod tests {
         let data = ConditionData {
             image_name: None,
             env: &[(EnvironmentVariableName::from("A"), String::from("1"))],
+            options: &PackageOptions::new(),
         };
 
         let condition = Condition::new(None, {
@@ -406,6 +446,7 @@ mod tests {
         let data = ConditionData {
             image_name: None,
             env: &[(EnvironmentVariableName::from("A"), String::from("1"))],
+            options: &PackageOptions::new(),
         };
 
         let condition = Condition::new(None, {
@@ -417,4 +458,34 @@ mod tests {
         assert!(condition.matches(&data).unwrap());
     }
 
+    #[test]
+    fn test_condition_options() {
+        let c: Condition = toml::from_str("options = { ssl = true, docs = false }").expect("Deserializing options");
+
+        let mut options = PackageOptions::new();
+        options.insert(String::from("ssl"), true);
+        options.insert(String::from("docs"), false);
+        let data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &options,
+        };
+        assert!(c.matches(&data).unwrap());
+
+        options.insert(String::from("docs"), true);
+        let data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &options,
+        };
+        assert!(!c.matches(&data).unwrap());
+
+        let data = ConditionData {
+            image_name: None,
+            env: &[],
+            options: &PackageOptions::new(),
+        };
+        assert!(c.matches(&data).is_err());
+    }
+
 }
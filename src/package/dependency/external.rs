@@ -0,0 +1,68 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::path::PathBuf;
+
+use getset::Getters;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::SourceHash;
+
+/// A dependency on a prebuilt artifact that is not built from this repository, but fetched from
+/// one of the configured remote release stores
+///
+/// The artifact is verified with its hash before it is passed to the build of the package that
+/// declares the dependency.
+#[derive(Serialize, Deserialize, Clone, Debug, Getters)]
+pub struct ExternalDependency {
+    #[getset(get = "pub")]
+    name: PackageName,
+
+    #[getset(get = "pub")]
+    version: PackageVersion,
+
+    /// The path of the artifact in the remote release store
+    #[getset(get = "pub")]
+    artifact: PathBuf,
+
+    #[getset(get = "pub")]
+    hash: SourceHash,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    pub struct TestSettings {
+        external: Vec<ExternalDependency>,
+    }
+
+    #[test]
+    fn test_parse_external_dependency() {
+        let pretty = r#"
+            [[external]]
+            name = "glibc"
+            version = "2.31"
+            artifact = "glibc-2.31.tar.gz"
+            hash = { type = "sha256", hash = "abc" }
+        "#;
+
+        let s: TestSettings = toml::from_str(pretty).expect("Parsing TestSettings failed");
+        let dep = &s.external[0];
+        assert_eq!(dep.name().as_str(), "glibc");
+        assert_eq!(dep.version().as_str(), "2.31");
+        assert_eq!(dep.artifact(), &PathBuf::from("glibc-2.31.tar.gz"));
+        assert_eq!(dep.hash().value().to_string(), "abc");
+    }
+}
@@ -8,19 +8,19 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
-use std::convert::TryFrom;
-
-use anyhow::anyhow;
 use anyhow::Result;
-use lazy_static::lazy_static;
-use regex::Regex;
+use pom::parser::Parser as PomParser;
 
 use crate::package::PackageName;
 use crate::package::PackageVersionConstraint;
+use crate::util::parser::*;
 
 mod build;
 pub use build::*;
 
+mod external;
+pub use external::*;
+
 mod runtime;
 pub use runtime::*;
 
@@ -34,38 +34,30 @@ pub trait ParseDependency {
     fn parse_as_name_and_version(&self) -> Result<(PackageName, PackageVersionConstraint)>;
 }
 
-lazy_static! {
-    pub(in crate::package::dependency)  static ref DEPENDENCY_PARSING_RE: Regex =
-        Regex::new("^(?P<name>[[:alpha:]]([[[:alnum:]]\\.\\-_])*) (?P<version>([\\*=><])?[[:alnum:]]([[[:alnum:]][[:punct:]]])*)$").unwrap();
+/// Parser for a dependency string
+///
+/// ```text
+/// dependency = spaces name spaces constraint spaces
+/// constraint = comparison (spaces "," spaces comparison)*
+/// comparison = ("=" | "~" | ">" | ">=" | "<" | "<=") spaces version
+/// ```
+///
+/// e.g. "vim =8.2", "gtk+3.0 ~3.24" or "openssl >=1.1, <3".
+fn dependency_parser<'a>() -> PomParser<'a, u8, (PackageName, PackageVersionConstraint)> {
+    spaces() * PackageName::parser().expect("a package name")
+        - spaces()
+        + PackageVersionConstraint::parser()
+        - spaces()
+        - pom::parser::end().expect("',' or the end of the dependency")
 }
 
 /// Helper function for the actual implementation of the ParseDependency trait.
-///
-/// TODO: Reimplement using pom crate
 pub(in crate::package::dependency) fn parse_package_dependency_string_into_name_and_version(
     s: &str,
 ) -> Result<(PackageName, PackageVersionConstraint)> {
-    let caps = crate::package::dependency::DEPENDENCY_PARSING_RE
-        .captures(s)
-        .ok_or_else(|| {
-            anyhow!(
-                "Could not parse into package name and package version constraint: '{}'",
-                s
-            )
-        })?;
-
-    let name = caps
-        .name("name")
-        .map(|m| String::from(m.as_str()))
-        .ok_or_else(|| anyhow!("Could not parse name: '{}'", s))?;
-
-    let vers = caps
-        .name("version")
-        .map(|m| String::from(m.as_str()))
-        .ok_or_else(|| anyhow!("Could not parse version: '{}'", s))?;
-
-    let v = PackageVersionConstraint::try_from(vers)?;
-    Ok((PackageName::from(name), v))
+    dependency_parser()
+        .parse(s.as_bytes())
+        .map_err(|e| describe_error("dependency", s, &e))
 }
 
 #[cfg(test)]
@@ -132,4 +124,106 @@ mod tests {
             PackageVersionConstraint::from_version(String::from("="), exact("0.123"))
         );
     }
+
+    fn parse(s: &str) -> Result<(PackageName, PackageVersionConstraint)> {
+        parse_package_dependency_string_into_name_and_version(s)
+    }
+
+    fn parse_err(s: &str) -> String {
+        parse(s).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_dependency_names() {
+        for (s, n) in [
+            ("gtk+3.0 =3.24", "gtk+3.0"),
+            ("389-ds-base =1.4", "389-ds-base"),
+            ("python3.9-setuptools =1", "python3.9-setuptools"),
+            ("lib_foo.bar =1", "lib_foo.bar"),
+            ("core/gcc =12", "core/gcc"),
+            ("apps/editors/vim =8.2", "apps/editors/vim"),
+        ].iter() {
+            assert_eq!(parse(s).unwrap().0, name(n), "Parsing '{}'", s);
+        }
+    }
+
+    #[test]
+    fn test_dependency_with_spaces() {
+        for s in ["vim =8.2", "  vim =8.2", "vim =8.2  ", "vim  = 8.2", "vim=8.2", "vim\t=8.2"].iter() {
+            let (n, c) = parse(s).unwrap();
+            assert_eq!(n, name("vim"), "Parsing '{}'", s);
+            assert_eq!(c, PackageVersionConstraint::from_version(String::from("="), exact("8.2")), "Parsing '{}'", s);
+        }
+    }
+
+    #[test]
+    fn test_dependency_comparators() {
+        for (s, constraint) in [
+            ("vim =8.2", "=8.2"),
+            ("vim ~8.2", "~8.2"),
+            ("vim >8.2", ">8.2"),
+            ("vim >=8.2", ">=8.2"),
+            ("vim <8.2", "<8.2"),
+            ("vim <=8.2", "<=8.2"),
+            ("vim =8.2+git1", "=8.2+git1"),
+        ].iter() {
+            assert_eq!(parse(s).unwrap().1.to_string(), *constraint, "Parsing '{}'", s);
+        }
+    }
+
+    #[test]
+    fn test_dependency_with_range() {
+        let (n, c) = parse("openssl >=1.1, <3").unwrap();
+        assert_eq!(n, name("openssl"));
+        assert_eq!(c.to_string(), ">=1.1,<3");
+        assert!(c.matches(&exact("1.1")));
+        assert!(c.matches(&exact("1.1.1k")));
+        assert!(c.matches(&exact("2.10")));
+        assert!(!c.matches(&exact("1.0.2")));
+        assert!(!c.matches(&exact("3")));
+        assert!(!c.matches(&exact("3.0.1")));
+
+        assert_eq!(parse("openssl >=1.1,<3").unwrap().1, c);
+        assert_eq!(parse("openssl >=1.1 , <3").unwrap().1, c);
+        assert_eq!(parse("openssl >1, <=2, ~1.5").unwrap().1.to_string(), ">1,<=2,~1.5");
+    }
+
+    #[test]
+    fn test_dependency_parse_errors() {
+        assert_eq!(
+            parse_err("vim"),
+            "Could not parse dependency 'vim': expected a comparator (=, ~, >, >=, <, <=), found the end\n    vim\n       ^"
+        );
+        assert_eq!(
+            parse_err("vim 8.2"),
+            "Could not parse dependency 'vim 8.2': expected a comparator (=, ~, >, >=, <, <=), found '8'\n    vim 8.2\n        ^"
+        );
+        assert_eq!(
+            parse_err("vim *8.2"),
+            "Could not parse dependency 'vim *8.2': expected a comparator (=, ~, >, >=, <, <=), found '*'\n    vim *8.2\n        ^"
+        );
+        assert_eq!(
+            parse_err("vim =a"),
+            "Could not parse dependency 'vim =a': expected a version, found 'a'\n    vim =a\n         ^"
+        );
+        assert_eq!(
+            parse_err("vim =8.2 foo"),
+            "Could not parse dependency 'vim =8.2 foo': expected ',' or the end of the dependency, found 'f'\n    vim =8.2 foo\n             ^"
+        );
+        assert_eq!(
+            parse_err("vim =8.2,"),
+            "Could not parse dependency 'vim =8.2,': expected a comparator (=, ~, >, >=, <, <=), found the end\n    vim =8.2,\n             ^"
+        );
+        assert_eq!(
+            parse_err("=8.2"),
+            "Could not parse dependency '=8.2': expected a package name, found '='\n    =8.2\n    ^"
+        );
+        assert_eq!(
+            parse_err(""),
+            "Could not parse dependency '': expected a package name, found the end\n    \n    ^"
+        );
+        assert!(parse("vim ==8.2").is_err());
+        assert!(parse("vim =8.2 =8.3").is_err());
+        assert!(parse("vim -=8.2").is_err());
+    }
 }
@@ -0,0 +1,149 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! License expressions of packages, like "MIT OR Apache-2.0"
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+/// A license expression in the syntax of SPDX license expressions
+///
+/// The identifiers are not checked against the SPDX license list, so custom identifiers like
+/// "LicenseRef-Proprietary" can be used as well. An exception ("GPL-2.0-only WITH
+/// Classpath-exception-2.0") is part of the identifier of its license.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LicenseExpression {
+    License(String),
+
+    /// All of the licenses apply
+    And(Vec<LicenseExpression>),
+
+    /// One of the licenses can be chosen
+    Or(Vec<LicenseExpression>),
+}
+
+impl LicenseExpression {
+    pub fn parse(s: &str) -> Result<Self> {
+        let spaced = s.replace('(', " ( ").replace(')', " ) ");
+        let tokens = spaced.split_whitespace().collect::<Vec<_>>();
+        let mut position = 0;
+        let expression = Self::parse_or(&tokens, &mut position)?;
+        match tokens.get(position) {
+            None => Ok(expression),
+            Some(token) => Err(anyhow!("Unexpected '{}' in license expression '{}'", token, s)),
+        }
+    }
+
+    fn parse_or(tokens: &[&str], position: &mut usize) -> Result<Self> {
+        let mut alternatives = vec![Self::parse_and(tokens, position)?];
+        while tokens.get(*position).map(|t| t.eq_ignore_ascii_case("OR")).unwrap_or(false) {
+            *position += 1;
+            alternatives.push(Self::parse_and(tokens, position)?);
+        }
+        Ok(if alternatives.len() == 1 { alternatives.remove(0) } else { LicenseExpression::Or(alternatives) })
+    }
+
+    fn parse_and(tokens: &[&str], position: &mut usize) -> Result<Self> {
+        let mut licenses = vec![Self::parse_license(tokens, position)?];
+        while tokens.get(*position).map(|t| t.eq_ignore_ascii_case("AND")).unwrap_or(false) {
+            *position += 1;
+            licenses.push(Self::parse_license(tokens, position)?);
+        }
+        Ok(if licenses.len() == 1 { licenses.remove(0) } else { LicenseExpression::And(licenses) })
+    }
+
+    fn parse_license(tokens: &[&str], position: &mut usize) -> Result<Self> {
+        let is_operator = |t: &str| ["AND", "OR", "WITH"].iter().any(|op| t.eq_ignore_ascii_case(op));
+
+        match tokens.get(*position).copied() {
+            Some("(") => {
+                *position += 1;
+                let expression = Self::parse_or(tokens, position)?;
+                if tokens.get(*position) != Some(&")") {
+                    return Err(anyhow!("Missing ')' in license expression"))
+                }
+                *position += 1;
+                Ok(expression)
+            },
+            Some(license) if license != ")" && !is_operator(license) => {
+                *position += 1;
+                if tokens.get(*position).map(|t| t.eq_ignore_ascii_case("WITH")).unwrap_or(false) {
+                    let exception = tokens.get(*position + 1)
+                        .filter(|t| **t != "(" && **t != ")" && !is_operator(t))
+                        .ok_or_else(|| anyhow!("Missing exception after 'WITH {}' in license expression", license))?;
+                    *position += 2;
+                    return Ok(LicenseExpression::License(format!("{} WITH {}", license, exception)))
+                }
+                Ok(LicenseExpression::License(license.to_string()))
+            },
+            Some(other) => Err(anyhow!("Expected a license instead of '{}' in license expression", other)),
+            None => Err(anyhow!("Unexpected end of license expression")),
+        }
+    }
+
+    /// Whether the licenses that have to apply are all `acceptable`, choosing an acceptable
+    /// alternative where there is a choice
+    pub fn is_acceptable(&self, acceptable: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            LicenseExpression::License(license) => acceptable(license),
+            LicenseExpression::And(licenses) => licenses.iter().all(|l| l.is_acceptable(acceptable)),
+            LicenseExpression::Or(alternatives) => alternatives.iter().any(|l| l.is_acceptable(acceptable)),
+        }
+    }
+
+    /// All licenses in the expression
+    pub fn licenses(&self) -> Vec<&str> {
+        match self {
+            LicenseExpression::License(license) => vec![license.as_str()],
+            LicenseExpression::And(licenses) | LicenseExpression::Or(licenses) => {
+                licenses.iter().flat_map(LicenseExpression::licenses).collect()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn license(s: &str) -> LicenseExpression {
+        LicenseExpression::License(String::from(s))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(LicenseExpression::parse("MIT").unwrap(), license("MIT"));
+        assert_eq!(LicenseExpression::parse("MIT OR Apache-2.0").unwrap(),
+            LicenseExpression::Or(vec![license("MIT"), license("Apache-2.0")]));
+
+        // AND binds stronger than OR
+        assert_eq!(LicenseExpression::parse("MIT or BSD-3-Clause AND (Zlib OR GPL-2.0-only WITH Classpath-exception-2.0)").unwrap(),
+            LicenseExpression::Or(vec![
+                license("MIT"),
+                LicenseExpression::And(vec![
+                    license("BSD-3-Clause"),
+                    LicenseExpression::Or(vec![license("Zlib"), license("GPL-2.0-only WITH Classpath-exception-2.0")]),
+                ]),
+            ]));
+
+        for invalid in ["", "MIT OR", "(MIT", "MIT)", "MIT Apache-2.0", "GPL-2.0 WITH", "AND MIT"].iter() {
+            assert!(LicenseExpression::parse(invalid).is_err(), "'{}' was parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn test_is_acceptable() {
+        let not_gpl = |l: &str| !l.starts_with("GPL");
+        assert!(LicenseExpression::parse("MIT OR GPL-3.0-only").unwrap().is_acceptable(&not_gpl));
+        assert!(!LicenseExpression::parse("MIT AND GPL-3.0-only").unwrap().is_acceptable(&not_gpl));
+        assert!(!LicenseExpression::parse("GPL-2.0-only OR GPL-3.0-only").unwrap().is_acceptable(&not_gpl));
+        assert_eq!(LicenseExpression::parse("MIT AND (Zlib OR BSD-2-Clause)").unwrap().licenses(), vec!["MIT", "Zlib", "BSD-2-Clause"]);
+    }
+}
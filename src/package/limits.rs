@@ -0,0 +1,168 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use std::convert::TryFrom;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use anyhow::Result;
+use getset::CopyGetters;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// An amount of memory in bytes
+///
+/// Can be written as number of bytes or as string with a binary unit suffix, e.g. "512M" or "4GiB".
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(try_from = "RawMemorySize", into = "u64")]
+pub struct MemorySize(u64);
+
+impl MemorySize {
+    pub fn bytes(&self) -> u64 {
+        self.0
+    }
+}
+
+impl From<MemorySize> for u64 {
+    fn from(m: MemorySize) -> u64 {
+        m.0
+    }
+}
+
+impl std::fmt::Display for MemorySize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}MiB", self.0 / (1024 * 1024))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMemorySize {
+    Bytes(u64),
+    Text(String),
+}
+
+impl TryFrom<RawMemorySize> for MemorySize {
+    type Error = Error;
+
+    fn try_from(raw: RawMemorySize) -> Result<Self> {
+        let s = match raw {
+            RawMemorySize::Bytes(b) => return Ok(MemorySize(b)),
+            RawMemorySize::Text(s) => s,
+        };
+
+        let lower = s.trim().to_lowercase();
+        let number_len = lower.find(|c: char| !c.is_ascii_digit()).unwrap_or(lower.len());
+        let (number, unit) = lower.split_at(number_len);
+        let number = number.parse::<u64>().map_err(|e| anyhow!("Invalid memory size '{}': {}", s, e))?;
+        let factor: u64 = match unit.trim().trim_end_matches("ib").trim_end_matches('b') {
+            "" => 1,
+            "k" => 1024,
+            "m" => 1024 * 1024,
+            "g" => 1024 * 1024 * 1024,
+            "t" => 1024 * 1024 * 1024 * 1024,
+            _ => return Err(anyhow!("Invalid unit in memory size '{}'", s)),
+        };
+
+        number.checked_mul(factor)
+            .map(MemorySize)
+            .ok_or_else(|| anyhow!("Memory size too big: '{}'", s))
+    }
+}
+
+/// Resource limits for the container a package is built in
+#[derive(Clone, Debug, Default, CopyGetters, Serialize, Deserialize)]
+pub struct ContainerLimits {
+    /// Memory limit of the container
+    ///
+    /// This is also the amount of memory that is reserved on the endpoint when scheduling the job.
+    #[getset(get_copy = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory: Option<MemorySize>,
+
+    /// Number of CPUs the container may use, e.g. 1.5
+    #[getset(get_copy = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpus: Option<f64>,
+
+    /// Relative CPU weight of the container (docker default is 1024)
+    #[getset(get_copy = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_shares: Option<u32>,
+}
+
+impl ContainerLimits {
+    /// Fill the limits that are not set in `self` from `defaults`
+    pub fn or(&self, defaults: &ContainerLimits) -> ContainerLimits {
+        ContainerLimits {
+            memory: self.memory.or(defaults.memory),
+            cpus: self.cpus.or(defaults.cpus),
+            cpu_shares: self.cpu_shares.or(defaults.cpu_shares),
+        }
+    }
+}
+
+/// The resources the build of a package is estimated to need
+///
+/// Unlike the limits, these are not enforced in the container. The scheduler reserves them on the
+/// endpoint the job runs on, so that an endpoint does not get more jobs than it can handle. If
+/// they are not set, the limits are reserved.
+#[derive(Clone, Debug, Default, CopyGetters, Serialize, Deserialize)]
+pub struct ResourceRequirements {
+    /// Memory the build needs
+    #[getset(get_copy = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory: Option<MemorySize>,
+
+    /// Number of CPUs the build keeps busy, e.g. 4 for `make -j4`
+    #[getset(get_copy = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpus: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct T {
+        limits: ContainerLimits,
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let t: T = toml::from_str(r#"
+            [limits]
+            memory = "4GiB"
+            cpus = 1.5
+        "#).unwrap();
+        assert_eq!(t.limits.memory(), Some(MemorySize(4 * 1024 * 1024 * 1024)));
+        assert_eq!(t.limits.cpus(), Some(1.5));
+        assert_eq!(t.limits.cpu_shares(), None);
+
+        let t: T = toml::from_str("limits = { memory = 1024 }").unwrap();
+        assert_eq!(t.limits.memory(), Some(MemorySize(1024)));
+
+        let t: T = toml::from_str(r#"limits = { memory = "512m" }"#).unwrap();
+        assert_eq!(t.limits.memory(), Some(MemorySize(512 * 1024 * 1024)));
+
+        assert!(toml::from_str::<T>(r#"limits = { memory = "4X" }"#).is_err());
+    }
+
+    #[test]
+    fn test_limits_defaults() {
+        let pkg: T = toml::from_str("limits = { memory = 1024 }").unwrap();
+        let global: T = toml::from_str("limits = { memory = 2048, cpus = 2.0 }").unwrap();
+
+        let limits = pkg.limits.or(&global.limits);
+        assert_eq!(limits.memory(), Some(MemorySize(1024)));
+        assert_eq!(limits.cpus(), Some(2.0));
+    }
+}
@@ -0,0 +1,140 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Diagnostics of the script linter
+
+use getset::CopyGetters;
+use getset::Getters;
+use regex::Regex;
+
+use crate::package::Script;
+
+/// The severity of a diagnostic of the linter
+#[derive(parse_display::Display, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LintSeverity {
+    #[display("info")]
+    Info,
+
+    #[display("warning")]
+    Warning,
+
+    #[display("error")]
+    Error,
+}
+
+impl LintSeverity {
+    fn parse(s: &str) -> Self {
+        match s {
+            "error" | "fatal" => LintSeverity::Error,
+            "note" | "info" | "style" => LintSeverity::Info,
+            _ => LintSeverity::Warning,
+        }
+    }
+}
+
+/// A diagnostic of the linter for a line of the script of a package
+#[derive(Clone, Debug, CopyGetters, Getters)]
+pub struct LintDiagnostic {
+    /// The line in the script, starting at 1
+    #[getset(get_copy = "pub")]
+    line: usize,
+
+    #[getset(get_copy = "pub")]
+    column: Option<usize>,
+
+    #[getset(get_copy = "pub")]
+    severity: LintSeverity,
+
+    #[getset(get = "pub")]
+    message: String,
+
+    /// The phase the line belongs to and the line in the phase, starting at 1
+    #[getset(get = "pub")]
+    phase: Option<(String, usize)>,
+}
+
+impl LintDiagnostic {
+    /// The diagnostics in the `output` of the linter for `script`
+    ///
+    /// Lines of the output that are not diagnostics in the format of gcc (like `shellcheck -f gcc`
+    /// prints them) are ignored.
+    pub fn parse(output: &str, script: &Script) -> Vec<Self> {
+        // "file:line:column: severity: message", the column and the severity are optional
+        let re = Regex::new(r"^[^:]*:(?P<line>\d+):(?:(?P<column>\d+):)?\s*(?:(?P<severity>fatal|error|warning|note|info|style)\s*:)?\s*(?P<message>.*)$")
+            .unwrap(); // the expression is valid
+
+        output.lines()
+            .filter_map(|line| re.captures(line.trim_end()))
+            .filter_map(|captures| {
+                let line = captures.name("line")?.as_str().parse().ok()?;
+                Some(LintDiagnostic {
+                    line,
+                    column: captures.name("column").and_then(|c| c.as_str().parse().ok()),
+                    severity: captures.name("severity")
+                        .map(|s| LintSeverity::parse(s.as_str()))
+                        .unwrap_or(LintSeverity::Warning),
+                    message: captures.name("message")?.as_str().to_string(),
+                    phase: script.phase_of_line(line).map(|(phase, line)| (phase.to_string(), line)),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script() -> Script {
+        Script::from(String::from(indoc::indoc!(r#"
+            #!/bin/bash
+            ### phase unpack
+            tar xf src.tar.gz
+            ### / unpack phase
+
+            ### phase build
+            cd src
+            make $FLAGS
+            ### / build phase
+
+        "#)))
+    }
+
+    #[test]
+    fn test_parse_diagnostics() {
+        let output = indoc::indoc!(r#"
+            -:8:6: warning: Double quote to prevent globbing and word splitting. [SC2086]
+            -:7:1: error: Use 'cd ... || exit' in case cd fails. [SC2164]
+            -:1:1: note: Something
+            In - line 8:
+            -:3: no severity
+        "#);
+
+        let diagnostics = LintDiagnostic::parse(output, &script());
+        assert_eq!(diagnostics.len(), 4);
+
+        assert_eq!(diagnostics[0].line(), 8);
+        assert_eq!(diagnostics[0].column(), Some(6));
+        assert_eq!(diagnostics[0].severity(), LintSeverity::Warning);
+        assert_eq!(diagnostics[0].message(), "Double quote to prevent globbing and word splitting. [SC2086]");
+        assert_eq!(diagnostics[0].phase(), &Some((String::from("build"), 2)));
+
+        assert_eq!(diagnostics[1].severity(), LintSeverity::Error);
+        assert_eq!(diagnostics[1].phase(), &Some((String::from("build"), 1)));
+
+        assert_eq!(diagnostics[2].severity(), LintSeverity::Info);
+        assert_eq!(diagnostics[2].phase(), &None);
+
+        assert_eq!(diagnostics[3].line(), 3);
+        assert_eq!(diagnostics[3].column(), None);
+        assert_eq!(diagnostics[3].severity(), LintSeverity::Warning);
+        assert_eq!(diagnostics[3].phase(), &Some((String::from("unpack"), 1)));
+    }
+}
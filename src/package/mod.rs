@@ -10,12 +10,30 @@
 
 //! Module that contains all types and functionality that has to do with a package.
 
+mod affinity;
+pub use affinity::*;
+
 mod dependency;
 pub use dependency::*;
 
+mod license;
+pub use license::*;
+
+mod limits;
+pub use limits::*;
+
+mod lint;
+pub use lint::*;
+
 mod name;
 pub use name::*;
 
+mod network;
+pub use network::*;
+
+mod options;
+pub use options::*;
+
 #[allow(clippy::module_inception)]
 mod package;
 pub use package::*;
@@ -23,12 +41,18 @@ pub use package::*;
 mod phase;
 pub use phase::*;
 
+mod resolution;
+pub use resolution::*;
+
 mod script;
 pub use script::*;
 
 mod source;
 pub use source::*;
 
+mod template;
+pub use template::*;
+
 mod dag;
 pub use dag::*;
 
@@ -50,10 +50,27 @@ impl From<String> for PackageName {
 }
 
 impl PackageName {
+    /// Parser for a package name, which starts with a letter or a number and can contain dots,
+    /// dashes, underscores and pluses, e.g. "gtk+3.0" or "389-ds-base"
+    ///
+    /// The name can be qualified with the namespace of the package, e.g. "core/gcc".
     pub fn parser<'a>() -> PomParser<'a, u8, Self> {
         use crate::util::parser::*;
-        (letters() + ((letters() | numbers()).repeat(0..)))
+        use pom::parser::sym;
+        let segment = || (letters() | numbers()) + ((letters() | numbers() | dot() | dash() | under() | plus()).repeat(0..));
+        (segment() + (sym(b'/') + segment()).repeat(0..))
             .collect()
             .convert(|b| String::from_utf8(b.to_vec()).map(Self::from))
     }
+
+    /// Split a fully-qualified name like "core/gcc" into the namespace ("core") and the name of
+    /// the package ("gcc")
+    ///
+    /// The namespace is `None` if the name is not qualified.
+    pub fn split_namespace(&self) -> (Option<&str>, &str) {
+        match self.0.rsplit_once('/') {
+            Some((namespace, name)) => (Some(namespace), name),
+            None => (None, &self.0),
+        }
+    }
 }
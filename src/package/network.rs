@@ -0,0 +1,89 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Whether the build container has network access
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    /// No network access, the build can only use the sources that were downloaded before
+    None,
+
+    /// The network of the endpoint, as configured with `network_mode` for the endpoint
+    Default,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network::Default
+    }
+}
+
+/// Messages tools print when they cannot reach the network, lowercase
+const NETWORK_ERROR_MESSAGES: &[&str] = &[
+    "could not resolve host",
+    "temporary failure in name resolution",
+    "name or service not known",
+    "network is unreachable",
+    "no address associated with hostname",
+    "failed to establish a new connection",
+];
+
+/// An attempt to access the network, as found in the log of a job
+#[derive(Debug, Eq, PartialEq)]
+pub struct NetworkAccess {
+    /// The phase that was running, if the log contained phase information
+    pub phase: Option<String>,
+
+    /// The log line that shows the attempt
+    pub line: String,
+}
+
+/// Find the first line in the `log` of a job that looks like the build tried to access the network
+pub fn find_network_access(log: &str) -> Option<NetworkAccess> {
+    let mut phase = None;
+    for line in log.lines() {
+        if let Some(p) = line.strip_prefix("#BUTIDO:PHASE:") {
+            phase = Some(p.to_string());
+            continue
+        }
+
+        let lower = line.to_lowercase();
+        if NETWORK_ERROR_MESSAGES.iter().any(|msg| lower.contains(msg)) {
+            return Some(NetworkAccess { phase, line: line.to_string() })
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_network_access() {
+        let log = indoc::indoc!(r#"
+            #BUTIDO:PHASE:unpack
+            tar xf source.tar.gz
+            #BUTIDO:PHASE:build
+            curl: (6) Could not resolve host: example.com
+            #BUTIDO:STATE:ERR:failed
+        "#);
+
+        let access = find_network_access(log).unwrap();
+        assert_eq!(access.phase.as_deref(), Some("build"));
+        assert_eq!(access.line, "curl: (6) Could not resolve host: example.com");
+
+        assert!(find_network_access("#BUTIDO:PHASE:build\nmake: *** Error 1").is_none());
+    }
+}
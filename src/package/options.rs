@@ -0,0 +1,77 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Build options of packages, which can be switched on or off on the commandline
+
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+/// Build options by name, with whether they are enabled
+pub type PackageOptions = BTreeMap<String, bool>;
+
+/// Parse the value of an option, e.g. "on" or "off"
+pub fn parse_option_value(s: &str) -> Result<bool> {
+    match s {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" => Ok(false),
+        other => Err(anyhow!("Invalid option value '{}', expected 'on' or 'off'", other)),
+    }
+}
+
+/// Parse a "name=value" option setting from the commandline
+pub fn parse_option_setting(s: &str) -> Result<(String, bool)> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), parse_option_value(value)?)),
+        _ => Err(anyhow!("Expected NAME=on or NAME=off, got '{}'", s)),
+    }
+}
+
+/// Parse options formatted by `display_options()`
+pub fn parse_options(s: &str) -> Result<PackageOptions> {
+    s.split(',')
+        .filter(|setting| !setting.is_empty())
+        .map(parse_option_setting)
+        .collect()
+}
+
+/// Format options as "name=on,other=off"
+pub fn display_options(options: &PackageOptions) -> String {
+    options
+        .iter()
+        .map(|(name, enabled)| format!("{}={}", name, if *enabled { "on" } else { "off" }))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_option_setting() {
+        assert_eq!(parse_option_setting("ssl=on").unwrap(), (String::from("ssl"), true));
+        assert_eq!(parse_option_setting("docs=off").unwrap(), (String::from("docs"), false));
+        assert!(parse_option_setting("ssl").is_err());
+        assert!(parse_option_setting("=on").is_err());
+        assert!(parse_option_setting("ssl=maybe").is_err());
+    }
+
+    #[test]
+    fn test_display_options() {
+        let mut options = PackageOptions::new();
+        options.insert(String::from("ssl"), true);
+        options.insert(String::from("docs"), false);
+        assert_eq!(display_options(&options), "docs=off,ssl=on");
+        assert_eq!(parse_options(&display_options(&options)).unwrap(), options);
+        assert!(parse_options("").unwrap().is_empty());
+    }
+}
@@ -9,17 +9,27 @@
 //
 
 use std::collections::HashMap;
+use std::path::Component;
+use std::path::Path;
 use std::path::PathBuf;
 
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
 use getset::Getters;
+use itertools::Itertools;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::package::affinity::*;
 use crate::package::dependency::*;
+use crate::package::limits::*;
+use crate::package::network::*;
+use crate::package::options::*;
 use crate::package::name::*;
 use crate::package::source::*;
 use crate::package::version::*;
-use crate::package::{Phase, PhaseName};
+use crate::package::{Phase, PhaseName, Shebang};
 use crate::util::docker::ImageName;
 use crate::util::EnvironmentVariableName;
 
@@ -43,6 +53,48 @@ pub struct Package {
     #[getset(get = "pub")]
     patches: Vec<PathBuf>,
 
+    /// The people responsible for the package, e.g. "Jane Doe <jane@example.com>"
+    ///
+    /// They are told in the failure notifications, so they can be pinged if the package breaks.
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    maintainers: Vec<String>,
+
+    /// The license of the package as SPDX license expression, e.g. "MIT OR Apache-2.0"
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
+
+    /// Names of the outputs of the package, that other packages can depend on individually
+    ///
+    /// The artifacts of an output are the ones in the `/outputs/<output>/` directory of the
+    /// container. All other artifacts belong to the package itself.
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    outputs: Vec<PackageName>,
+
+    /// Names of virtual packages this package provides
+    ///
+    /// A dependency on a virtual package can be satisfied by every package that provides it.
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    provides: Vec<PackageName>,
+
+    /// Build options of the package with their default values
+    ///
+    /// Options can be switched on or off on the commandline, and are used in the conditions of
+    /// dependencies and in the script (as `{{this.options.<name>}}`).
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "PackageOptions::is_empty")]
+    options: PackageOptions,
+
+    /// URL templates for mirrors of the sources of this package
+    ///
+    /// These are tried before the mirrors from the configuration.
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_mirrors: Option<Vec<String>>,
+
     #[getset(get = "pub")]
     #[serde(skip_serializing_if = "Option::is_none")]
     environment: Option<HashMap<EnvironmentVariableName, String>>,
@@ -58,6 +110,48 @@ pub struct Package {
     #[getset(get = "pub")]
     phases: HashMap<PhaseName, Phase>,
 
+    /// The shebang of the script of the package, overriding the shebang from the configuration
+    ///
+    /// The phases are put into the script as they are, so they have to be written for the
+    /// interpreter of the shebang.
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shebang: Option<Shebang>,
+
+    /// Resource limits for the build container, overriding the limits from the configuration
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limits: Option<ContainerLimits>,
+
+    /// The estimated resources the build needs, for scheduling its job, the limits if not set
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resources: Option<ResourceRequirements>,
+
+    /// Network access of the build container, overriding the setting from the configuration
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    network: Option<Network>,
+
+    /// The endpoints the jobs of the package are required to or preferably run on
+    #[getset(get = "pub")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    endpoints: Option<EndpointAffinity>,
+
+    /// The namespace of the package, set when loading the repository
+    ///
+    /// The namespace is the path of the directories above the directory whose pkg.toml file sets
+    /// the name of the package, e.g. "core" for a package named "gcc" in "core/gcc/pkg.toml".
+    /// The package can be referred to by its fully-qualified name "core/gcc" as well.
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    namespace: Option<String>,
+
+    /// The pkg.toml file the package is defined in, set when loading the repository
+    #[getset(get = "pub")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    definition_file: Option<PathBuf>,
+
     /// Meta field
     ///
     /// Contains only key-value string-string data, that the packager can set for a package and
@@ -70,6 +164,7 @@ pub struct Package {
 
 impl std::hash::Hash for Package {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.namespace.hash(state);
         self.name.hash(state);
         self.version.hash(state);
     }
@@ -91,19 +186,237 @@ impl Package {
             sources,
             dependencies,
             patches: vec![],
+            maintainers: vec![],
+            license: None,
+            outputs: vec![],
+            provides: vec![],
+            options: PackageOptions::new(),
+            source_mirrors: None,
             environment: None,
             allowed_images: None,
             denied_images: None,
             phases: HashMap::new(),
+            shebang: None,
+            limits: None,
+            resources: None,
+            network: None,
+            endpoints: None,
+            namespace: None,
+            definition_file: None,
             meta: None,
         }
     }
 
+    /// Compute a hash over the names and contents of the patches of this package
+    ///
+    /// Returns `None` if the package has no patches. The hash is used to find out whether an
+    /// artifact was built with the same patches.
+    pub fn patches_hash(&self) -> Result<Option<String>> {
+        use sha2::Digest;
+
+        if self.patches.is_empty() {
+            return Ok(None)
+        }
+
+        let mut m = sha2::Sha256::new();
+        for patch in self.patches.iter() {
+            let content = std::fs::read(patch)
+                .with_context(|| anyhow!("Reading patch {}", patch.display()))?;
+
+            if let Some(name) = patch.file_name() {
+                m.update(name.to_string_lossy().as_bytes());
+            }
+            m.update(&content);
+        }
+        Ok(Some(format!("{:x}", m.finalize())))
+    }
+
+    /// Get the options of this package, with the values from `values` for the options that are
+    /// set there
+    ///
+    /// Options in `values` that this package does not have are ignored.
+    pub fn options_with(&self, values: &PackageOptions) -> PackageOptions {
+        self.options
+            .iter()
+            .map(|(name, default)| (name.clone(), *values.get(name).unwrap_or(default)))
+            .collect()
+    }
+
+    /// Set the options of this package to the values from `values`, see `options_with()`
+    pub fn set_option_values(&mut self, values: &PackageOptions) {
+        self.options = self.options_with(values);
+    }
+
+    /// Get the options of this package formatted as "name=on,other=off", `None` if the package
+    /// has no options
+    pub fn options_string(&self) -> Option<String> {
+        if self.options.is_empty() {
+            None
+        } else {
+            Some(display_options(&self.options))
+        }
+    }
+
+    /// Get the fully-qualified name of this package, e.g. "core/gcc", which is the name if the
+    /// package is not in a namespace
+    pub fn qualified_name(&self) -> PackageName {
+        match self.namespace.as_ref() {
+            Some(namespace) => PackageName::from(format!("{}/{}", namespace, self.name)),
+            None => self.name.clone(),
+        }
+    }
+
+    /// Strip the namespace from `name`, if `name` is not qualified or qualified with the namespace
+    /// of this package
+    fn in_namespace_of<'a>(&self, name: &'a PackageName) -> Option<&'a str> {
+        match name.split_namespace() {
+            (Some(namespace), name) if self.namespace.as_deref() == Some(namespace) => Some(name),
+            (Some(_), _) => None,
+            (None, name) => Some(name),
+        }
+    }
+
+    /// Whether `name` is the name or the fully-qualified name of this package
+    pub fn has_name(&self, name: &PackageName) -> bool {
+        self.in_namespace_of(name)
+            .map(|name| self.name.as_str() == name)
+            .unwrap_or(false)
+    }
+
+    /// Whether `name` is the name of this package or of one of its outputs, optionally qualified
+    /// with the namespace of the package
+    pub fn is_named(&self, name: &PackageName) -> bool {
+        self.in_namespace_of(name)
+            .map(|name| self.name.as_str() == name || self.outputs.iter().any(|o| o.as_str() == name))
+            .unwrap_or(false)
+    }
+
+    /// Whether a dependency on `name` is satisfied by this package, either by the package itself,
+    /// by one of its outputs or by a virtual package it provides
+    pub fn satisfies(&self, name: &PackageName) -> bool {
+        self.is_named(name) || self.provides.contains(name)
+    }
+
+    /// Whether this package depends on `other` directly, at build time or at runtime
+    ///
+    /// The conditions of the dependencies are not checked.
+    pub fn depends_on(&self, other: &Package) -> Result<bool> {
+        let build = self.dependencies.build().iter().map(ParseDependency::parse_as_name_and_version);
+        let runtime = self.dependencies.runtime().iter().map(ParseDependency::parse_as_name_and_version);
+        for dependency in build.chain(runtime) {
+            let (name, constraint) = dependency?;
+            if other.satisfies(&name) && constraint.matches(other.version()) {
+                return Ok(true)
+            }
+        }
+        Ok(false)
+    }
+
+    /// Get the output the artifact at `path` (relative to the store) belongs to
+    ///
+    /// This is the package name for artifacts that are not in the directory of an output.
+    pub fn artifact_output(&self, path: &Path) -> &PackageName {
+        match path.components().next() {
+            Some(Component::Normal(first)) => self.outputs
+                .iter()
+                .find(|output| std::ffi::OsStr::new(output.as_str()) == first)
+                .unwrap_or(&self.name),
+            _ => &self.name,
+        }
+    }
+
+    /// Get the names by which this package depends on the outputs of `dependency`
+    ///
+    /// A dependency on a virtual package that `dependency` provides is a dependency on the package
+    /// itself.
+    pub fn outputs_required_from(&self, dependency: &Package) -> Vec<PackageName> {
+        let build = self.dependencies.build.iter().map(ParseDependency::parse_as_name_and_version);
+        let runtime = self.dependencies.runtime.iter().map(ParseDependency::parse_as_name_and_version);
+
+        build
+            .chain(runtime)
+            .filter_map(Result::ok)
+            .filter(|(name, constr)| dependency.satisfies(name) && constr.matches(dependency.version()))
+            .map(|(name, _)| if dependency.is_named(&name) { name } else { dependency.name().clone() })
+            .unique()
+            .collect()
+    }
+
+    pub fn set_namespace(&mut self, namespace: Option<String>) {
+        self.namespace = namespace;
+    }
+
+    pub fn set_definition_file(&mut self, path: PathBuf) {
+        self.definition_file = Some(path);
+    }
+
+    /// Make the keyring paths of the source signatures relative to the repository root `root`
+    pub fn resolve_source_keyrings(&mut self, root: &Path) {
+        self.sources.values_mut().for_each(|source| source.resolve_keyring(root));
+    }
+
+    /// Whether the package may be built on `image`, according to its allowed and denied images
+    pub fn allows_image(&self, image: &ImageName) -> bool {
+        let allowed = self.allowed_images
+            .as_ref()
+            .map(|allowed| allowed.iter().any(|i| i.matches(image)))
+            .unwrap_or(true);
+        let denied = self.denied_images
+            .as_ref()
+            .map(|denied| denied.iter().any(|i| i.matches(image)))
+            .unwrap_or(false);
+        allowed && !denied
+    }
+
+    /// Get "name version", followed by the pkg.toml file the package is defined in if it is known
+    pub fn display_with_location(&self) -> String {
+        match self.definition_file.as_ref() {
+            Some(path) => format!("{} {} ({})", self.name, self.version, path.display()),
+            None => format!("{} {}", self.name, self.version),
+        }
+    }
+
     #[cfg(test)]
     pub fn set_dependencies(&mut self, dependencies: Dependencies) {
         self.dependencies = dependencies;
     }
 
+    #[cfg(test)]
+    pub fn set_options(&mut self, options: PackageOptions) {
+        self.options = options;
+    }
+
+    #[cfg(test)]
+    pub fn set_provides(&mut self, provides: Vec<PackageName>) {
+        self.provides = provides;
+    }
+
+    #[cfg(test)]
+    pub fn set_environment(&mut self, environment: HashMap<EnvironmentVariableName, String>) {
+        self.environment = Some(environment);
+    }
+
+    #[cfg(test)]
+    pub fn set_outputs(&mut self, outputs: Vec<PackageName>) {
+        self.outputs = outputs;
+    }
+
+    #[cfg(test)]
+    pub fn set_image_lists(&mut self, allowed: Option<Vec<ImageName>>, denied: Option<Vec<ImageName>>) {
+        self.allowed_images = allowed;
+        self.denied_images = denied;
+    }
+
+    #[cfg(test)]
+    pub fn set_phases(&mut self, phases: HashMap<PhaseName, Phase>) {
+        self.phases = phases;
+    }
+
+    #[cfg(test)]
+    pub fn set_shebang(&mut self, shebang: Option<Shebang>) {
+        self.shebang = shebang;
+    }
+
     /// Get a wrapper object around self which implements a debug interface with all details about
     /// the Package object
     #[cfg(debug_assertions)]
@@ -155,6 +468,14 @@ impl<'a> std::fmt::Debug for DebugPackage<'a> {
         writeln!(f, "\tRuntime Dependencies = ")?;
         self.0.dependencies.runtime.iter().try_for_each(|r| writeln!(f, "\t\t{:?}", r))?;
 
+        writeln!(f, "\tOptions = {}", display_options(&self.0.options))?;
+
+        writeln!(f, "\tProvides = ")?;
+        self.0.provides.iter().try_for_each(|p| writeln!(f, "\t\t{}", p))?;
+
+        writeln!(f, "\tOutputs = ")?;
+        self.0.outputs.iter().try_for_each(|o| writeln!(f, "\t\t{}", o))?;
+
         writeln!(f, "\tPatches = ")?;
         self.0.patches.iter().try_for_each(|p| writeln!(f, "\t\t{}", p.display()))?;
 
@@ -177,30 +498,37 @@ impl<'a> std::fmt::Debug for DebugPackage<'a> {
             .map(|v| v.iter().try_for_each(|i| writeln!(f, "\t\t{:?}", i)))
             .transpose()?;
 
+        if let Some(shebang) = self.0.shebang.as_ref() {
+            writeln!(f, "\tShebang = {}", shebang.as_ref())?;
+        }
+
         writeln!(f, "\tPhases = ")?;
         self.0.phases
             .iter()
             .try_for_each(|(k, _)| writeln!(f, "\t\t{:?} = ...", k))?;
 
+        writeln!(f, "\tLimits = {:?}", self.0.limits)?;
+        writeln!(f, "\tNetwork = {:?}", self.0.network)?;
+
         Ok(())
     }
 }
 
 impl PartialEq for Package {
     fn eq(&self, other: &Package) -> bool {
-        (self.name(), self.version()).eq(&(other.name(), other.version()))
+        (self.namespace(), self.name(), self.version()).eq(&(other.namespace(), other.name(), other.version()))
     }
 }
 
 impl PartialOrd for Package {
     fn partial_cmp(&self, other: &Package) -> Option<std::cmp::Ordering> {
-        (self.name(), self.version()).partial_cmp(&(other.name(), other.version()))
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Package {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        (self.name(), self.version()).cmp(&(other.name(), other.version()))
+        (self.namespace(), self.name(), self.version()).cmp(&(other.namespace(), other.name(), other.version()))
     }
 }
 
@@ -213,6 +541,11 @@ pub struct Dependencies {
 
     #[getset(get = "pub")]
     runtime: Vec<Dependency>,
+
+    /// Prebuilt artifacts that are fetched from a remote release store instead of being built
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[getset(get = "pub")]
+    external: Vec<ExternalDependency>,
 }
 
 #[cfg(test)]
@@ -221,6 +554,7 @@ impl Dependencies {
         Dependencies {
             build: vec![],
             runtime: vec![],
+            external: vec![],
         }
     }
 
@@ -232,6 +566,7 @@ impl Dependencies {
         Dependencies {
             build: vec![],
             runtime: runtime_dependencies,
+            external: vec![],
         }
     }
 }
@@ -274,4 +609,19 @@ pub mod tests {
         let dependencies = Dependencies::empty();
         Package::new(name, version, version_is_semver, sources, dependencies)
     }
+
+    #[test]
+    fn test_allows_image() {
+        let image = |name: &str| ImageName::from(String::from(name));
+        let mut p = package("a", "1", "https://example.com", "0");
+        assert!(p.allows_image(&image("debian:bullseye")));
+
+        p.set_image_lists(Some(vec![image("debian:bullseye")]), None);
+        assert!(p.allows_image(&image("debian:bullseye")));
+        assert!(!p.allows_image(&image("centos:7")));
+
+        p.set_image_lists(None, Some(vec![image("centos:7")]));
+        assert!(p.allows_image(&image("debian:bullseye")));
+        assert!(!p.allows_image(&image("centos:7")));
+    }
 }
@@ -8,11 +8,15 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+use getset::Getters;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::util::EnvironmentVariableName;
+
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
 #[serde(transparent)]
 pub struct PhaseName(String);
@@ -30,11 +34,39 @@ impl From<String> for PhaseName {
     }
 }
 
+/// A phase of the packaging script
+///
+/// In the pkg.toml, a phase is either `<phase>.script = "..."` or `<phase>.path = "..."`, with an
+/// optional `<phase>.env` table and `<phase>.workdir` for the environment the phase runs in.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Getters)]
+pub struct Phase {
+    #[serde(flatten)]
+    #[getset(get = "pub")]
+    script: PhaseScript,
+
+    /// Environment variables that are only set while this phase runs
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[getset(get = "pub")]
+    env: HashMap<EnvironmentVariableName, String>,
+
+    /// The directory this phase runs in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[getset(get = "pub")]
+    workdir: Option<PathBuf>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
-pub enum Phase {
+pub enum PhaseScript {
     #[serde(rename = "path")]
     Path(PathBuf),
 
     #[serde(rename = "script")]
     Text(String),
 }
+
+impl Phase {
+    /// Whether the phase has to run in an environment of its own
+    pub fn has_own_environment(&self) -> bool {
+        !self.env.is_empty() || self.workdir.is_some()
+    }
+}
@@ -0,0 +1,226 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Selecting one version of a package if a dependency matches several versions
+
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use getset::CopyGetters;
+use getset::Getters;
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::package::Package;
+use crate::package::PackageName;
+use crate::package::PackageVersion;
+use crate::package::PackageVersionConstraint;
+
+/// Which version is used if a dependency matches several versions of a package
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionStrategy {
+    /// Fail, the dependency has to be made unambiguous
+    #[default]
+    Error,
+
+    /// Use the newest matching version
+    Highest,
+
+    /// Use the oldest matching version
+    Lowest,
+}
+
+/// How dependencies that match several versions of a package are resolved
+#[derive(Clone, Debug, Default, CopyGetters, Getters, Deserialize)]
+pub struct VersionResolution {
+    /// The strategy for packages that are not pinned
+    #[serde(default)]
+    #[getset(get_copy = "pub")]
+    strategy: VersionStrategy,
+
+    /// Packages whose version is fixed, if a dependency matches several versions of them
+    ///
+    /// Packages can be pinned by their name or by their fully-qualified name.
+    #[serde(default)]
+    #[getset(get = "pub")]
+    pins: HashMap<PackageName, PackageVersion>,
+
+    /// Packages whose version is forced for all dependencies on them, regardless of the version
+    /// constraints of the dependencies (`build --pin`)
+    #[serde(skip)]
+    #[getset(get = "pub")]
+    overrides: HashMap<PackageName, PackageVersion>,
+}
+
+impl VersionResolution {
+    pub fn with_overrides(mut self, overrides: HashMap<PackageName, PackageVersion>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// The version constraint that is used for the dependency on `name` with the version
+    /// constraint `constraint`, which is replaced if the version of the package is overridden
+    pub fn constraint_for(&self, name: &PackageName, constraint: PackageVersionConstraint) -> PackageVersionConstraint {
+        match self.overrides.get(name) {
+            Some(version) => PackageVersionConstraint::exact(version.clone()),
+            None => constraint,
+        }
+    }
+
+    /// Select the packages from `candidates` that are used for the dependency on `name` with the
+    /// version constraint `constraint`
+    ///
+    /// If several versions of the same package are candidates, only one of them is selected,
+    /// either the pinned version or the one chosen by the strategy.
+    pub fn select<'a>(
+        &self,
+        name: &PackageName,
+        constraint: &PackageVersionConstraint,
+        candidates: Vec<&'a Package>,
+    ) -> Result<Vec<&'a Package>> {
+        candidates
+            .into_iter()
+            .into_group_map_by(|p| p.qualified_name())
+            .into_iter()
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(package_name, versions)| self.select_version(name, constraint, &package_name, versions))
+            .collect()
+    }
+
+    fn select_version<'a>(
+        &self,
+        name: &PackageName,
+        constraint: &PackageVersionConstraint,
+        package_name: &PackageName,
+        versions: Vec<&'a Package>,
+    ) -> Result<&'a Package> {
+        // Pins can use the name or the fully-qualified name of the package, overrides take
+        // precedence over the configured pins
+        let lookup = |pins: &'_ HashMap<PackageName, PackageVersion>| -> Option<PackageVersion> {
+            pins.get(package_name)
+                .or_else(|| versions.first().and_then(|p| pins.get(p.name())))
+                .cloned()
+        };
+        let pin = lookup(&self.overrides).or_else(|| lookup(&self.pins));
+        if let Some(pin) = pin {
+            return versions
+                .iter()
+                .find(|p| *p.version() == pin)
+                .copied()
+                .ok_or_else(|| {
+                    anyhow!("{} is pinned to version {}, which does not match the dependency on {} {}",
+                        package_name, pin, name, constraint)
+                })
+        }
+
+        if versions.len() == 1 {
+            return Ok(versions[0])
+        }
+
+        let newer = |a: &&Package, b: &&Package| a.version().compare(b.version());
+        match self.strategy {
+            VersionStrategy::Highest => Ok(versions.into_iter().max_by(newer).unwrap()), // never empty
+            VersionStrategy::Lowest => Ok(versions.into_iter().min_by(newer).unwrap()), // never empty
+            VersionStrategy::Error => {
+                let matching = versions
+                    .iter()
+                    .map(|p| p.version())
+                    .sorted_by(|a, b| a.compare(b))
+                    .join(", ");
+
+                Err(anyhow!(
+                    "Dependency on {} {} matches multiple versions of {}: {}. Pin one of them or use another resolver strategy.",
+                    name, constraint, package_name, matching
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    use crate::package::tests::package;
+    use crate::package::tests::pname;
+    use crate::package::tests::pversion;
+
+    fn resolution(strategy: VersionStrategy, pins: &[(&str, &str)]) -> VersionResolution {
+        VersionResolution {
+            strategy,
+            pins: pins.iter().map(|(n, v)| (pname(n), pversion(v))).collect(),
+            overrides: HashMap::new(),
+        }
+    }
+
+    fn selected_versions(resolution: &VersionResolution, candidates: &[Package]) -> Result<Vec<String>> {
+        let constraint = PackageVersionConstraint::try_from("~1").unwrap();
+        resolution
+            .select(&pname("a"), &constraint, candidates.iter().collect())
+            .map(|packages| packages.iter().map(|p| p.version().to_string()).collect())
+    }
+
+    #[test]
+    fn test_select_version() {
+        let candidates = vec![
+            package("a", "1.9", "https://rust-lang.org", "123"),
+            package("a", "1.10", "https://rust-lang.org", "123"),
+            package("a", "1.2", "https://rust-lang.org", "123"),
+        ];
+
+        let highest = resolution(VersionStrategy::Highest, &[]);
+        assert_eq!(selected_versions(&highest, &candidates).unwrap(), vec!["1.10"]);
+
+        let lowest = resolution(VersionStrategy::Lowest, &[]);
+        assert_eq!(selected_versions(&lowest, &candidates).unwrap(), vec!["1.2"]);
+
+        let err = selected_versions(&VersionResolution::default(), &candidates).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Dependency on a ~1 matches multiple versions of a: 1.2, 1.9, 1.10. Pin one of them or use another resolver strategy."
+        );
+
+        let single = &candidates[..1];
+        assert_eq!(selected_versions(&VersionResolution::default(), single).unwrap(), vec!["1.9"]);
+    }
+
+    #[test]
+    fn test_select_pinned_version() {
+        let candidates = vec![
+            package("a", "1.9", "https://rust-lang.org", "123"),
+            package("a", "1.10", "https://rust-lang.org", "123"),
+        ];
+
+        let pinned = resolution(VersionStrategy::Highest, &[("a", "1.9")]);
+        assert_eq!(selected_versions(&pinned, &candidates).unwrap(), vec!["1.9"]);
+
+        let pinned = resolution(VersionStrategy::Error, &[("a", "2.0")]);
+        assert!(selected_versions(&pinned, &candidates).is_err());
+    }
+
+    #[test]
+    fn test_overrides() {
+        let candidates = vec![
+            package("a", "1.9", "https://rust-lang.org", "123"),
+            package("a", "1.10", "https://rust-lang.org", "123"),
+        ];
+
+        let overridden = resolution(VersionStrategy::Error, &[("a", "1.9")])
+            .with_overrides(vec![(pname("a"), pversion("1.10"))].into_iter().collect());
+        assert_eq!(selected_versions(&overridden, &candidates).unwrap(), vec!["1.10"]);
+
+        let constraint = PackageVersionConstraint::try_from("~1").unwrap();
+        assert_eq!(overridden.constraint_for(&pname("a"), constraint.clone()).to_string(), "=1.10");
+        assert_eq!(overridden.constraint_for(&pname("b"), constraint).to_string(), "~1");
+    }
+}
@@ -22,6 +22,7 @@ use handlebars::{
     RenderContext, RenderError,
 };
 use log::trace;
+use log::warn;
 use serde::Deserialize;
 use serde::Serialize;
 use syntect::easy::HighlightLines;
@@ -32,6 +33,7 @@ use tokio::process::Command;
 
 use crate::package::Package;
 use crate::package::Phase;
+use crate::package::PhaseScript;
 use crate::package::PhaseName;
 
 #[derive(parse_display::Display, Serialize, Deserialize, Clone, Debug)]
@@ -45,7 +47,8 @@ impl From<String> for Script {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(transparent)]
 pub struct Shebang(String);
 
 impl Script {
@@ -57,6 +60,47 @@ impl Script {
         self.0.lines().enumerate().map(|(n, l)| (n + 1, l))
     }
 
+    /// The command line that runs the script when it is stored at `path`
+    ///
+    /// The interpreter and its optional argument are read from the shebang like the kernel does,
+    /// so the script does not have to be executable. Scripts without a shebang are run with bash.
+    pub fn command_line(&self, path: &str) -> Vec<String> {
+        let shebang = self.0
+            .lines()
+            .next()
+            .and_then(|l| l.strip_prefix("#!"))
+            .map(str::trim)
+            .filter(|s| !s.is_empty());
+
+        let mut command = match shebang {
+            Some(shebang) => match shebang.split_once(|c: char| c == ' ' || c == '\t') {
+                Some((interpreter, argument)) => vec![interpreter.to_string(), argument.trim().to_string()],
+                None => vec![shebang.to_string()],
+            },
+            None => vec![String::from("/bin/bash")],
+        };
+        command.push(path.to_string());
+        command
+    }
+
+    /// The phase the `line` of the script (starting at 1) belongs to, and the line in the phase
+    ///
+    /// The phases are found by the comments `ScriptBuilder` puts around them.
+    pub fn phase_of_line(&self, line: usize) -> Option<(&str, usize)> {
+        let mut phase = None;
+        for (n, l) in self.lines_numbered().take(line) {
+            if let Some(name) = l.strip_prefix("### phase ") {
+                phase = Some((name.trim(), n));
+            } else if l.starts_with("### / ") && l.ends_with(" phase") {
+                phase = None;
+            }
+        }
+
+        phase
+            .filter(|(_, start)| *start < line)
+            .map(|(name, start)| (name, line - start))
+    }
+
     pub async fn lint(&self, mut cmd: Command) -> Result<(ExitStatus, String, String)> {
         use tokio::io::AsyncWriteExt;
         use tokio::io::BufWriter;
@@ -108,14 +152,31 @@ pub struct HighlightedScript<'a> {
     ts: ThemeSet,
 }
 
+/// Whether the configured highlighting theme is the path of a `.tmTheme` file rather than the
+/// name of one of the bundled themes
+pub fn is_theme_file(script_theme: &str) -> bool {
+    script_theme.ends_with(".tmTheme")
+}
+
 impl<'a> HighlightedScript<'a> {
     fn new(script: &'a Script, script_theme: &'a str) -> Self {
+        let mut ts = ThemeSet::load_defaults();
+        if is_theme_file(script_theme) {
+            // The file was already parsed once when validating the configuration
+            match ThemeSet::get_theme(script_theme) {
+                Ok(theme) => {
+                    ts.themes.insert(script_theme.to_string(), theme);
+                },
+                Err(e) => warn!("Failed to load theme {}: {}", script_theme, e),
+            }
+        }
+
         HighlightedScript {
             script,
             script_theme,
 
             ps: SyntaxSet::load_defaults_newlines(),
-            ts: ThemeSet::load_defaults(),
+            ts,
         }
     }
 
@@ -152,6 +213,12 @@ impl From<String> for Shebang {
     }
 }
 
+impl AsRef<str> for Shebang {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
 impl AsRef<str> for Script {
     fn as_ref(&self) -> &str {
         self.0.as_ref()
@@ -167,19 +234,27 @@ impl<'a> ScriptBuilder<'a> {
         ScriptBuilder { shebang }
     }
 
+    /// Build the script of `package`
+    ///
+    /// The shebang of the package is used if it sets one, the shebang of the builder otherwise.
     pub fn build(
         self,
         package: &Package,
         phaseorder: &[PhaseName],
         strict_mode: bool,
     ) -> Result<Script> {
-        let mut script = format!("{shebang}\n", shebang = self.shebang.0);
+        let shebang = package.shebang().as_ref().unwrap_or(self.shebang);
+        let mut script = format!("{shebang}\n", shebang = shebang.0);
 
         for name in phaseorder {
-            match package.phases().get(name) {
-                Some(Phase::Text(text)) => {
+            match package.phases().get(name).map(|phase| (phase, phase.script())) {
+                Some((phase, PhaseScript::Text(text))) => {
                     use unindent::Unindent;
 
+                    // whack hack: insert empty line on top because unindent ignores the
+                    // indentation of the first line, see commit message for more info
+                    let text = format!("\n{}", text).unindent();
+
                     script.push_str(&indoc::formatdoc!(
                         r#"
                         ### phase {}
@@ -187,9 +262,7 @@ impl<'a> ScriptBuilder<'a> {
                         ### / {} phase
                     "#,
                         name.as_str(),
-                        // whack hack: insert empty line on top because unindent ignores the
-                        // indentation of the first line, see commit message for more info
-                        format!("\n{}", text).unindent(),
+                        if phase.has_own_environment() { Self::in_phase_environment(name, phase, &text)? } else { text },
                         name.as_str(),
                     ));
 
@@ -199,7 +272,7 @@ impl<'a> ScriptBuilder<'a> {
                 // TODO: Support path embedding
                 // (requires possibility to have stuff in Script type that gets copied to
                 // container)
-                Some(Phase::Path(pb)) => {
+                Some((_, PhaseScript::Path(pb))) => {
                     script.push_str(&format!(
                         r#"
                         # Phase (from file {path}): {name}
@@ -225,6 +298,38 @@ impl<'a> ScriptBuilder<'a> {
         Self::interpolate_package(script, package, strict_mode).map(Script)
     }
 
+    /// Wrap the `text` of `phase` in a subshell with the environment variables and working
+    /// directory of the phase, so they do not leak into the following phases
+    ///
+    /// The values are interpolated together with the rest of the script. If the subshell fails,
+    /// the script exits with its exit code.
+    fn in_phase_environment(name: &PhaseName, phase: &Phase, text: &str) -> Result<String> {
+        use crate::endpoint::sandbox::shell_quote;
+
+        let mut env = phase.env().iter().collect::<Vec<_>>();
+        env.sort();
+
+        let is_valid_name = |var: &str| {
+            var.chars().next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+                && var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        };
+        if let Some((var, _)) = env.iter().find(|(var, _)| !is_valid_name(var.as_ref())) {
+            return Err(anyhow!("Invalid environment variable name in phase {}: '{}'", name.as_str(), var))
+        }
+
+        let mut s = String::from("(\n");
+        if let Some(workdir) = phase.workdir() {
+            let workdir = shell_quote(&workdir.display().to_string());
+            s.push_str(&format!("mkdir -p {dir} && cd {dir} || exit 1\n", dir = workdir));
+        }
+        for (name, value) in env {
+            s.push_str(&format!("export {}={}\n", name.as_ref(), shell_quote(value)));
+        }
+        s.push_str(text.trim_end_matches('\n'));
+        s.push_str("\n) || exit $?");
+        Ok(s)
+    }
+
     fn interpolate_package(script: String, package: &Package, strict_mode: bool) -> Result<String> {
         let mut hb = Handlebars::new();
         hb.register_escape_fn(handlebars::no_escape);
@@ -234,6 +339,7 @@ impl<'a> ScriptBuilder<'a> {
         hb.register_helper("progress", Box::new(ProgressHelper));
         hb.register_helper("join", Box::new(JoinHelper));
         hb.register_helper("joinwith", Box::new(JoinWithHelper));
+        crate::package::register_template_helpers(&mut hb);
         hb.set_strict_mode(strict_mode);
 
         #[cfg(debug_assertions)]
@@ -397,3 +503,99 @@ where
     out.write(&s)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::package::tests::package;
+
+    #[test]
+    fn test_phase_environment() {
+        let phase = |toml: &str| toml::from_str::<Phase>(toml).unwrap();
+        let mut phases = HashMap::new();
+        phases.insert(PhaseName::from(String::from("unpack")), phase(r#"script = "tar xf src.tar.gz""#));
+        phases.insert(PhaseName::from(String::from("build")), phase(indoc::indoc!(r#"
+            script = "make"
+            workdir = "/build/{{this.name}}"
+            env = { CFLAGS = "-O2 -g", LANG = "C" }
+        "#)));
+
+        let mut pkg = package("foo", "1.0", "https://example.com", "0");
+        pkg.set_phases(phases);
+
+        let shebang = Shebang::from(String::from("#!/bin/bash"));
+        let phaseorder = [PhaseName::from(String::from("unpack")), PhaseName::from(String::from("build"))];
+        let script = ScriptBuilder::new(&shebang).build(&pkg, &phaseorder, true).unwrap();
+
+        assert_eq!(script.as_ref(), indoc::indoc!(r#"
+            #!/bin/bash
+            ### phase unpack
+            tar xf src.tar.gz
+            ### / unpack phase
+
+            ### phase build
+            (
+            mkdir -p '/build/foo' && cd '/build/foo' || exit 1
+            export CFLAGS='-O2 -g'
+            export LANG='C'
+            make
+            ) || exit $?
+            ### / build phase
+
+        "#));
+    }
+
+    #[test]
+    fn test_phase_environment_invalid_name() {
+        let mut phases = HashMap::new();
+        phases.insert(
+            PhaseName::from(String::from("build")),
+            toml::from_str::<Phase>(indoc::indoc!(r#"
+                script = "make"
+                env = { "FOO BAR" = "1" }
+            "#)).unwrap(),
+        );
+
+        let mut pkg = package("foo", "1.0", "https://example.com", "0");
+        pkg.set_phases(phases);
+
+        let shebang = Shebang::from(String::from("#!/bin/bash"));
+        let phaseorder = [PhaseName::from(String::from("build"))];
+        assert!(ScriptBuilder::new(&shebang).build(&pkg, &phaseorder, true).is_err());
+    }
+
+    #[test]
+    fn test_package_shebang() {
+        let mut phases = HashMap::new();
+        phases.insert(
+            PhaseName::from(String::from("build")),
+            toml::from_str::<Phase>(r#"script = "print('hello')""#).unwrap(),
+        );
+
+        let mut pkg = package("foo", "1.0", "https://example.com", "0");
+        pkg.set_phases(phases);
+
+        let shebang = Shebang::from(String::from("#!/bin/bash"));
+        let phaseorder = [PhaseName::from(String::from("build"))];
+        let bash = ScriptBuilder::new(&shebang).build(&pkg, &phaseorder, true).unwrap();
+        assert!(bash.as_ref().starts_with("#!/bin/bash\n"));
+
+        pkg.set_shebang(Some(Shebang::from(String::from("#!/usr/bin/env python3"))));
+        let python = ScriptBuilder::new(&shebang).build(&pkg, &phaseorder, true).unwrap();
+        assert!(python.as_ref().starts_with("#!/usr/bin/env python3\n"));
+        assert_eq!(bash.as_ref().lines().skip(1).collect::<Vec<_>>(), python.as_ref().lines().skip(1).collect::<Vec<_>>());
+
+        assert_eq!(bash.command_line("/script"), vec!["/bin/bash", "/script"]);
+        assert_eq!(python.command_line("/script"), vec!["/usr/bin/env", "python3", "/script"]);
+    }
+
+    #[test]
+    fn test_command_line() {
+        let command_line = |s: &str| Script::from(String::from(s)).command_line("/script");
+        assert_eq!(command_line("#!/bin/sh -eu\necho"), vec!["/bin/sh", "-eu", "/script"]);
+        assert_eq!(command_line("#! /usr/bin/python3\nprint()"), vec!["/usr/bin/python3", "/script"]);
+        assert_eq!(command_line("#!/usr/bin/env  perl -w \nprint"), vec!["/usr/bin/env", "perl -w", "/script"]);
+        assert_eq!(command_line("echo"), vec!["/bin/bash", "/script"]);
+    }
+}
@@ -8,6 +8,9 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::path::Path;
+use std::path::PathBuf;
+
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
@@ -25,6 +28,17 @@ pub struct Source {
     hash: SourceHash,
     #[getset(get = "pub")]
     download_manually: bool,
+
+    /// The detached signature that upstream publishes for the source
+    #[serde(default)]
+    #[getset(get = "pub")]
+    signature: Option<SourceSignature>,
+
+    /// Whether the downloaded archive is normalized before it is stored in the source cache, so
+    /// that the hash does not depend on timestamps, owners or the order of the members
+    #[serde(default)]
+    #[getset(get = "pub")]
+    normalize: bool,
 }
 
 impl Source {
@@ -34,8 +48,31 @@ impl Source {
             url,
             hash,
             download_manually: false,
+            signature: None,
+            normalize: false,
         }
     }
+
+    /// Make the keyring path of the signature relative to `root`, if it is not absolute
+    pub fn resolve_keyring(&mut self, root: &Path) {
+        if let Some(signature) = self.signature.as_mut() {
+            signature.keyring = root.join(&signature.keyring);
+        }
+    }
+}
+
+/// A detached (GPG) signature of a source, which is verified with `gpgv`
+#[derive(Clone, Debug, Serialize, Deserialize, Getters)]
+pub struct SourceSignature {
+    /// Where the signature is downloaded from
+    #[getset(get = "pub")]
+    url: Url,
+
+    /// The keyring with the keys of upstream, relative to the root of the repository
+    ///
+    /// `gpgv` requires this to be a keyring in the binary format, as written by `gpg --export`.
+    #[getset(get = "pub")]
+    keyring: PathBuf,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Getters)]
@@ -50,10 +87,10 @@ pub struct SourceHash {
 }
 
 impl SourceHash {
-    pub async fn matches_hash_of<R: tokio::io::AsyncRead + Unpin>(&self, reader: R) -> Result<()> {
+    pub async fn matches_hash_of<R: tokio::io::AsyncRead + Unpin>(&self, reader: R, progress: &indicatif::ProgressBar) -> Result<()> {
         trace!("Hashing buffer with: {:?}", self.hashtype);
         let h = self.hashtype
-            .hash_from_reader(reader)
+            .hash_from_reader(reader, progress)
             .await
             .context("Hashing failed")?;
         trace!("Hashing buffer with: {} finished", self.hashtype);
@@ -93,75 +130,60 @@ pub enum HashType {
 }
 
 impl HashType {
-    async fn hash_from_reader<R: tokio::io::AsyncRead + Unpin>(&self, mut reader: R) -> Result<HashValue> {
-        use tokio::io::AsyncReadExt;
-
-        let mut buffer = [0; 1024];
-
+    pub async fn hash_from_reader<R: tokio::io::AsyncRead + Unpin>(&self, reader: R, progress: &indicatif::ProgressBar) -> Result<HashValue> {
         match self {
             HashType::Sha1 => {
-                use sha1::Digest;
-
                 trace!("SHA1 hashing buffer");
-                let mut m = sha1::Sha1::new();
-                loop {
-                    let count = reader.read(&mut buffer)
-                        .await
-                        .context("Reading buffer failed")?;
-
-                    if count == 0 {
-                        trace!("ready");
-                        break;
-                    }
-
-                    m.update(&buffer[..count]);
-                }
-                Ok(HashValue(format!("{:x}", m.finalize())))
+                hash_with::<sha1::Sha1, _>(reader, progress).await
             }
             HashType::Sha256 => {
-                use sha2::Digest;
-
                 trace!("SHA256 hashing buffer");
-                let mut m = sha2::Sha256::new();
-                loop {
-                    let count = reader.read(&mut buffer)
-                        .await
-                        .context("Reading buffer failed")?;
-
-                    if count == 0 {
-                        trace!("ready");
-                        break;
-                    }
-
-                    m.update(&buffer[..count]);
-                }
-                let h = format!("{:x}", m.finalize());
-                trace!("Hash = {:?}", h);
-                Ok(HashValue(h))
+                hash_with::<sha2::Sha256, _>(reader, progress).await
             }
             HashType::Sha512 => {
-                use sha2::Digest;
-
                 trace!("SHA512 hashing buffer");
-                let mut m = sha2::Sha512::new();
-                loop {
-                    let count = reader.read(&mut buffer)
-                        .await
-                        .context("Reading buffer failed")?;
-
-                    if count == 0 {
-                        trace!("ready");
-                        break;
-                    }
-
-                    m.update(&buffer[..count]);
-                }
-                Ok(HashValue(String::from_utf8(m.finalize()[..].to_vec())?))
+                hash_with::<sha2::Sha512, _>(reader, progress).await
             }
         }
+        .map(|h| {
+            trace!("Hash = {:?}", h);
+            HashValue(h)
+        })
     }
 }
 
+/// Stream the contents of `reader` through the hasher `D` in chunks, so that big files do not
+/// have to be held in memory
+///
+/// The progress bar is incremented by the number of bytes read.
+async fn hash_with<D, R>(mut reader: R, progress: &indicatif::ProgressBar) -> Result<String>
+    where D: sha2::Digest,
+          R: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt;
+
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    let mut m = D::new();
+    loop {
+        let count = reader.read(&mut buffer)
+            .await
+            .context("Reading buffer failed")?;
+
+        if count == 0 {
+            trace!("ready");
+            break;
+        }
+
+        m.update(&buffer[..count]);
+        progress.inc(count as u64);
+    }
+
+    Ok(m.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// The size of the chunks that are read from a file when hashing it
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
 #[derive(parse_display::Display, Serialize, Deserialize, Clone, Debug, Hash, Eq, PartialEq)]
 #[serde(transparent)]
 #[display("{0}")]
@@ -173,3 +195,47 @@ impl From<String> for HashValue {
         HashValue(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(ht: HashType, data: &[u8]) -> HashValue {
+        let bar = indicatif::ProgressBar::hidden();
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let h = rt.block_on(ht.hash_from_reader(data, &bar)).unwrap();
+        assert_eq!(bar.position(), data.len() as u64);
+        h
+    }
+
+    #[test]
+    fn test_hash_values() {
+        assert_eq!(hash(HashType::Sha1, b"abc"), HashValue::from(String::from("a9993e364706816aba3e25717850c26c9cd0d89d")));
+        assert_eq!(hash(HashType::Sha256, b"abc"), HashValue::from(String::from("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")));
+        assert_eq!(hash(HashType::Sha512, b"abc"), HashValue::from(String::from("ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f")));
+    }
+
+    #[test]
+    fn test_signature_keyring() {
+        let mut source: Source = toml::from_str(indoc::indoc!(r#"
+            url = "https://example.com/a-1.0.tar.gz"
+            hash = { type = "sha256", hash = "123" }
+            download_manually = false
+            signature = { url = "https://example.com/a-1.0.tar.gz.asc", keyring = "keys/a.gpg" }
+        "#)).unwrap();
+
+        source.resolve_keyring(Path::new("/repo"));
+        let signature = source.signature().as_ref().unwrap();
+        assert_eq!(signature.url().as_str(), "https://example.com/a-1.0.tar.gz.asc");
+        assert_eq!(signature.keyring(), Path::new("/repo/keys/a.gpg"));
+    }
+
+    #[test]
+    fn test_hash_bigger_than_buffer() {
+        use sha2::Digest;
+
+        let data = (0..(3 * HASH_BUFFER_SIZE + 17)).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let expected = format!("{:x}", sha2::Sha256::digest(&data));
+        assert_eq!(hash(HashType::Sha256, &data), HashValue::from(expected));
+    }
+}
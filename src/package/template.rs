@@ -0,0 +1,219 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Helpers for the handlebars templates in packages, i.e. the scripts and the URLs of the
+//! sources
+
+use handlebars::{Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext, RenderError};
+
+/// Register the helpers for deriving values in the templates of packages with `hb`
+pub fn register_template_helpers(hb: &mut Handlebars) {
+    hb.register_helper("version_major", Box::new(VersionComponentHelper { index: 0, name: "major" }));
+    hb.register_helper("version_minor", Box::new(VersionComponentHelper { index: 1, name: "minor" }));
+    hb.register_helper("replace", Box::new(ReplaceHelper));
+    hb.register_helper("basename", Box::new(BasenameHelper));
+    hb.register_helper("env", Box::new(EnvHelper));
+}
+
+/// The handlebars registry the URLs of the sources of packages are rendered with
+pub fn source_url_registry() -> Handlebars<'static> {
+    let mut hb = Handlebars::new();
+    hb.register_escape_fn(handlebars::no_escape);
+    hb.set_strict_mode(true);
+    register_template_helpers(&mut hb);
+    hb
+}
+
+/// A source URL template for any version, derived from the literal `url` of the source of
+/// `version`
+///
+/// The version, the version with underscores instead of dots and the "major.minor" part of the
+/// version are replaced by the templates that render them. URLs that already are templates are
+/// returned as they are.
+pub fn url_template_for_version(url: &str, version: &str) -> String {
+    if url.contains("{{") || version.is_empty() {
+        return url.to_string()
+    }
+
+    let mut replacements = vec![
+        (version.to_string(), String::from("{{version}}")),
+        (version.replace('.', "_"), String::from(r#"{{replace version "." "_"}}"#)),
+    ];
+    if let (Some(major), Some(minor)) = (version_component(version, 0), version_component(version, 1)) {
+        replacements.push((format!("{}.{}", major, minor), String::from("{{version_major version}}.{{version_minor version}}")));
+    }
+
+    // Longer strings first, so that "1.2" does not replace a part of "1.2.3"
+    replacements.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+    replacements.dedup_by(|a, b| a.0 == b.0);
+
+    let mut template = String::with_capacity(url.len());
+    let mut rest = url;
+    'outer: while !rest.is_empty() {
+        for (from, to) in replacements.iter() {
+            if let Some(tail) = rest.strip_prefix(from.as_str()) {
+                template.push_str(to);
+                rest = tail;
+                continue 'outer
+            }
+        }
+        let c = rest.chars().next().unwrap(); // not empty
+        template.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    template
+}
+
+fn string_param<'h>(h: &'h Helper, index: usize, what: &str) -> Result<&'h str, RenderError> {
+    h.param(index)
+        .ok_or_else(|| RenderError::new(format!("Required parameter missing: {}", what)))?
+        .value()
+        .as_str()
+        .ok_or_else(|| RenderError::new(format!("Required parameter must be a string: {}", what)))
+}
+
+/// The `index`th component of `version`, where the components are separated by any character
+/// that is not alphanumeric (e.g. "1.2-rc1" has the components "1", "2" and "rc1")
+fn version_component(version: &str, index: usize) -> Option<&str> {
+    version
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|component| !component.is_empty())
+        .nth(index)
+}
+
+/// `{{version_major this.version}}` renders "1" for version "1.2.3",
+/// `{{version_minor this.version}}` renders "2"
+#[derive(Clone, Copy)]
+struct VersionComponentHelper {
+    index: usize,
+    name: &'static str,
+}
+
+impl HelperDef for VersionComponentHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        _: &Context,
+        _rc: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let version = string_param(h, 0, "version")?;
+        let component = version_component(version, self.index)
+            .ok_or_else(|| RenderError::new(format!("Version '{}' has no {} component", version, self.name)))?;
+        out.write(component)?;
+        Ok(())
+    }
+}
+
+/// `{{replace this.version "." "_"}}` renders "1_2_3" for version "1.2.3"
+#[derive(Clone, Copy)]
+struct ReplaceHelper;
+
+impl HelperDef for ReplaceHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        _: &Context,
+        _rc: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let s = string_param(h, 0, "string")?;
+        let from = string_param(h, 1, "pattern")?;
+        let to = string_param(h, 2, "replacement")?;
+        out.write(&s.replace(from, to))?;
+        Ok(())
+    }
+}
+
+/// `{{basename "https://example.com/dist/foo-1.0.tar.gz"}}` renders "foo-1.0.tar.gz"
+#[derive(Clone, Copy)]
+struct BasenameHelper;
+
+impl HelperDef for BasenameHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        _: &Context,
+        _rc: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let path = string_param(h, 0, "path")?;
+        out.write(path.trim_end_matches('/').rsplit('/').next().unwrap_or(""))?;
+        Ok(())
+    }
+}
+
+/// `{{env "NAME"}}` renders the environment variable NAME of butido, `{{env "NAME" "default"}}`
+/// renders "default" if it is not set
+#[derive(Clone, Copy)]
+struct EnvHelper;
+
+impl HelperDef for EnvHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        _: &Context,
+        _rc: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let name = string_param(h, 0, "environment variable name")?;
+        match std::env::var(name) {
+            Ok(value) => out.write(&value)?,
+            Err(_) if h.param(1).is_some() => out.write(string_param(h, 1, "default")?)?,
+            Err(e) => return Err(RenderError::new(format!("Environment variable {}: {}", name, e))),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str) -> Result<String, RenderError> {
+        source_url_registry().render_template(template, &serde_json::json!({ "version": "1.22.3-rc1" }))
+    }
+
+    #[test]
+    fn test_version_components() {
+        assert_eq!(render("{{version_major this.version}}.{{version_minor this.version}}").unwrap(), "1.22");
+        assert_eq!(render(r#"{{version_major "7"}}"#).unwrap(), "7");
+        assert!(render(r#"{{version_minor "7"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_replace_and_basename() {
+        assert_eq!(render(r#"{{replace version "." "_"}}"#).unwrap(), "1_22_3-rc1");
+        assert_eq!(render(r#"{{basename "https://example.com/dist/foo.tar.gz"}}"#).unwrap(), "foo.tar.gz");
+        assert_eq!(render(r#"{{basename "/usr/lib/"}}"#).unwrap(), "lib");
+    }
+
+    #[test]
+    fn test_env() {
+        assert_eq!(render(r#"{{env "BUTIDO_TEST_TEMPLATE_UNSET" "fallback"}}"#).unwrap(), "fallback");
+        assert!(render(r#"{{env "BUTIDO_TEST_TEMPLATE_UNSET"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_url_template_for_version() {
+        let url = "https://example.com/v1.2/foo-1.2.3.tar.gz?tag=foo_1_2_3";
+        let template = url_template_for_version(url, "1.2.3");
+        assert_eq!(template, r#"https://example.com/v{{version_major version}}.{{version_minor version}}/foo-{{version}}.tar.gz?tag=foo_{{replace version "." "_"}}"#);
+        let rendered = source_url_registry().render_template(&template, &serde_json::json!({ "version": "1.3.0" })).unwrap();
+        assert_eq!(rendered, "https://example.com/v1.3/foo-1.3.0.tar.gz?tag=foo_1_3_0");
+
+        assert_eq!(url_template_for_version("https://example.com/foo-{{version}}.tar.gz", "1.0"), "https://example.com/foo-{{version}}.tar.gz");
+        assert_eq!(url_template_for_version("https://example.com/foo.tar.gz", "1.0"), "https://example.com/foo.tar.gz");
+    }
+}
@@ -8,10 +8,9 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::cmp::Ordering;
 use std::ops::Deref;
 
-use anyhow::Context;
-use anyhow::Error;
 use anyhow::Result;
 use pom::parser::Parser as PomParser;
 use serde::Deserialize;
@@ -19,26 +18,74 @@ use serde::Serialize;
 
 use crate::util::parser::*;
 
+/// A constraint on the version of a package
+///
+/// `=1.2` only matches version "1.2", `~1.2` matches "1.2" and all versions that start with
+/// "1.2" followed by a separator, e.g. "1.2.3" or "1.2-rc1". `>`, `>=`, `<` and `<=` compare the
+/// versions with `PackageVersion::compare()`. Several comparisons can be combined with commas to
+/// form a range, e.g. `>=1.2, <2`.
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct PackageVersionConstraint {
     constraint: String,
     version: PackageVersion,
+
+    /// More comparisons a version has to satisfy, e.g. the upper bound of a range
+    and: Vec<(String, PackageVersion)>,
 }
 
 impl PackageVersionConstraint {
-    fn parser<'a>() -> PomParser<'a, u8, Self> {
-        (pom::parser::sym(b'=') + PackageVersion::parser())
-            .convert(|(constraint, version)| {
-                String::from_utf8(vec![constraint]).map(|c| (c, version))
-            })
-            .map(|(constraint, version)| PackageVersionConstraint {
-                constraint,
-                version,
+    fn comparator<'a>() -> PomParser<'a, u8, String> {
+        use pom::parser::seq;
+
+        (seq(b">=") | seq(b"<=") | seq(b"=") | seq(b"~") | seq(b">") | seq(b"<"))
+            .convert(|c| String::from_utf8(c.to_vec()))
+    }
+
+    fn comparison<'a>() -> PomParser<'a, u8, (String, PackageVersion)> {
+        Self::comparator().expect("a comparator (=, ~, >, >=, <, <=)")
+            - spaces()
+            + PackageVersion::parser().expect("a version")
+    }
+
+    /// Parser for a constraint, up to the first character that is not part of it
+    pub fn parser<'a>() -> PomParser<'a, u8, Self> {
+        separated(Self::comparison(), spaces() * pom::parser::sym(b',') * spaces())
+            .map(|mut comparisons| {
+                let (constraint, version) = comparisons.remove(0);
+                PackageVersionConstraint {
+                    constraint,
+                    version,
+                    and: comparisons,
+                }
             })
     }
 
     pub fn matches(&self, v: &PackageVersion) -> bool {
-        self.version == *v
+        fn matches_comparison(comparator: &str, version: &PackageVersion, v: &PackageVersion) -> bool {
+            match comparator {
+                "~" => match v.strip_prefix(version.as_str()) {
+                    Some(rest) => rest.is_empty() || rest.starts_with(['.', '-', '_']),
+                    None => false,
+                },
+                ">" => v.compare(version) == Ordering::Greater,
+                ">=" => v.compare(version) != Ordering::Less,
+                "<" => v.compare(version) == Ordering::Less,
+                "<=" => v.compare(version) != Ordering::Greater,
+                _ => version == v,
+            }
+        }
+
+        matches_comparison(&self.constraint, &self.version, v)
+            && self.and.iter().all(|(constraint, version)| matches_comparison(constraint, version, v))
+    }
+
+    /// The constraint that only matches `version`
+    pub fn exact(version: PackageVersion) -> Self {
+        PackageVersionConstraint {
+            constraint: String::from("="),
+            version,
+            and: vec![],
+        }
     }
 
     #[cfg(test)]
@@ -46,6 +93,7 @@ impl PackageVersionConstraint {
         PackageVersionConstraint {
             constraint,
             version,
+            and: vec![],
         }
     }
 }
@@ -62,18 +110,20 @@ impl std::convert::TryFrom<&str> for PackageVersionConstraint {
     type Error = anyhow::Error;
 
     fn try_from(s: &str) -> Result<Self> {
-        PackageVersionConstraint::parser()
+        (spaces() * PackageVersionConstraint::parser() - spaces() - pom::parser::end().expect("',' or the end of the constraint"))
             .parse(s.as_bytes())
-            .context("Failed to parse package version constraint")
-            .context("A package version constraint must have a comparator and a version string, like so: =0.1.0")
-            .map_err(Error::from)
-
+            .map_err(|e| describe_error("version constraint", s, &e))
+            .map_err(|e| e.context("A package version constraint must have a comparator and a version string, like so: =0.1.0"))
     }
 }
 
 impl std::fmt::Display for PackageVersionConstraint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}{}", self.constraint, self.version)
+        write!(f, "{}{}", self.constraint, self.version)?;
+        for (constraint, version) in self.and.iter() {
+            write!(f, ",{}{}", constraint, version)?;
+        }
+        Ok(())
     }
 }
 
@@ -114,15 +164,50 @@ impl From<String> for PackageVersion {
 
 impl PackageVersion {
     fn parser<'a>() -> PomParser<'a, u8, Self> {
-        (numbers() + ((dash() | under() | dot() | letters() | numbers()).repeat(0..)))
+        (numbers() + ((dash() | under() | dot() | plus() | letters() | numbers()).repeat(0..)))
             .collect()
             .convert(|b| String::from_utf8(b.to_vec()).map(Self::from))
     }
+
+    /// Compare two versions by their components, numbers are compared by their value
+    ///
+    /// E.G. "1.10" is newer than "1.9". This is different from the `Ord` implementation, which
+    /// compares the versions as strings.
+    pub fn compare(&self, other: &PackageVersion) -> Ordering {
+        // Runs of digits or letters, without the separators in between
+        fn components(s: &str) -> Vec<&str> {
+            let mut components = vec![];
+            let mut rest = s;
+            while let Some(start) = rest.find(char::is_alphanumeric) {
+                rest = &rest[start..];
+                let is_digit = rest.starts_with(|c: char| c.is_ascii_digit());
+                let end = rest
+                    .find(|c: char| !c.is_alphanumeric() || c.is_ascii_digit() != is_digit)
+                    .unwrap_or(rest.len());
+                components.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+            components
+        }
+
+        let (a, b) = (components(&self.0), components(&other.0));
+        for (x, y) in a.iter().zip(b.iter()) {
+            let ord = match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                _ => x.cmp(y),
+            };
+            if ord != Ordering::Equal {
+                return ord
+            }
+        }
+        a.len().cmp(&b.len())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::convert::TryFrom;
 
     #[test]
     fn test_parse_version_1() {
@@ -148,10 +233,10 @@ mod tests {
             .is_err());
         assert!(PackageVersionConstraint::parser()
             .parse(b">1")
-            .is_err());
+            .is_ok());
         assert!(PackageVersionConstraint::parser()
             .parse(b"<1")
-            .is_err());
+            .is_ok());
         assert!(PackageVersionConstraint::parser()
             .parse(b"=a")
             .is_err());
@@ -172,6 +257,40 @@ mod tests {
             .is_err());
     }
 
+    #[test]
+    fn test_prefix_constraint() {
+        let c = PackageVersionConstraint::try_from("~1.2").unwrap();
+        assert!(c.matches(&PackageVersion::from(String::from("1.2"))));
+        assert!(c.matches(&PackageVersion::from(String::from("1.2.3"))));
+        assert!(c.matches(&PackageVersion::from(String::from("1.2-rc1"))));
+        assert!(!c.matches(&PackageVersion::from(String::from("1.20"))));
+        assert!(!c.matches(&PackageVersion::from(String::from("1.3"))));
+        assert_eq!(c.to_string(), "~1.2");
+    }
+
+    #[test]
+    fn test_range_constraint() {
+        let c = PackageVersionConstraint::try_from(">1.9, <=1.10").unwrap();
+        assert!(!c.matches(&PackageVersion::from(String::from("1.9"))));
+        assert!(c.matches(&PackageVersion::from(String::from("1.9.1"))));
+        assert!(c.matches(&PackageVersion::from(String::from("1.10"))));
+        assert!(!c.matches(&PackageVersion::from(String::from("1.10.1"))));
+        assert_eq!(c.to_string(), ">1.9,<=1.10");
+
+        assert!(PackageVersionConstraint::try_from(">1.9,").is_err());
+        assert!(PackageVersionConstraint::try_from(">1.9 <2").is_err());
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        let v = |s: &str| PackageVersion::from(String::from(s));
+        assert_eq!(v("1.10").compare(&v("1.9")), Ordering::Greater);
+        assert_eq!(v("1.2").compare(&v("1.2.1")), Ordering::Less);
+        assert_eq!(v("1.2.0").compare(&v("1.2.0")), Ordering::Equal);
+        assert_eq!(v("2.0-rc1").compare(&v("2.0-rc2")), Ordering::Less);
+        assert_eq!(v("10").compare(&v("9a")), Ordering::Greater);
+    }
+
     #[test]
     fn test_parse_version_2() {
         let s = "=1";
@@ -0,0 +1,167 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! On-disk cache for the parsed repository
+//!
+//! Parsing all pkg.toml files of a big repository takes a while. Because most invocations of
+//! butido happen on an unchanged repository, the parsed packages are serialized to a cache file.
+//! The cache is keyed by the HEAD commit of the repository and the modification time and size of
+//! each pkg.toml file, so any change to the repository invalidates it.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use log::debug;
+use log::trace;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::package::Package;
+
+/// The key the cache is valid for
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CacheKey {
+    /// The commit HEAD points to
+    head: String,
+
+    /// pkg.toml files, relative to the repository root, with their mtime (seconds, nanoseconds)
+    /// and size
+    files: BTreeMap<PathBuf, (u64, u32, u64)>,
+}
+
+impl CacheKey {
+    /// Compute the cache key for the repository at `root`
+    pub fn compute(root: &Path, head: String) -> Result<Self> {
+        crate::repository::fs::pkgtoml_files(root)?
+            .into_iter()
+            .map(|entry| {
+                let meta = entry.metadata()
+                    .with_context(|| anyhow::anyhow!("Getting metadata for {}", entry.path().display()))?;
+                let mtime = meta.modified()?.duration_since(std::time::UNIX_EPOCH)?;
+                let path = entry.path().strip_prefix(root)?.to_path_buf();
+                Ok((path, (mtime.as_secs(), mtime.subsec_nanos(), meta.len())))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()
+            .map(|files| CacheKey { head, files })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    /// The version of butido that wrote the cache, because the serialized format of `Package`
+    /// might change between versions
+    butido_version: String,
+    key: CacheKey,
+    packages: Vec<Package>,
+}
+
+/// Handle to the cache file for one repository
+pub struct RepositoryCache {
+    path: PathBuf,
+}
+
+impl RepositoryCache {
+    /// Get the cache for the repository at `repo_root`, located in the XDG cache directory
+    pub fn for_repository(repo_root: &Path) -> Result<Self> {
+        use std::hash::Hash;
+        use std::hash::Hasher;
+
+        // One cache file per repository, so switching between repositories does not thrash the
+        // cache
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        repo_root.canonicalize()?.hash(&mut hasher);
+        let filename = format!("repository-{:016x}.json", hasher.finish());
+
+        let path = xdg::BaseDirectories::with_prefix("butido")?
+            .place_cache_file(filename)
+            .context("Creating cache directory")?;
+
+        Ok(RepositoryCache { path })
+    }
+
+    #[cfg(test)]
+    fn with_path(path: PathBuf) -> Self {
+        RepositoryCache { path }
+    }
+
+    /// Load the packages from the cache, if the cache exists and is valid for `key`
+    pub fn load(&self, key: &CacheKey) -> Option<Vec<Package>> {
+        let content = std::fs::read_to_string(&self.path).ok()?;
+        match serde_json::from_str::<CacheFile>(&content) {
+            Ok(cache) if cache.butido_version == env!("CARGO_PKG_VERSION") && cache.key == *key => {
+                debug!("Using repository cache at {}", self.path.display());
+                Some(cache.packages)
+            },
+            Ok(_) => {
+                debug!("Repository cache at {} is outdated", self.path.display());
+                None
+            },
+            Err(e) => {
+                debug!("Failed to read repository cache at {}: {}", self.path.display(), e);
+                None
+            },
+        }
+    }
+
+    /// Write the packages to the cache
+    pub fn store<'a, I>(&self, key: CacheKey, packages: I) -> Result<()>
+        where I: IntoIterator<Item = &'a Package>
+    {
+        trace!("Writing repository cache to {}", self.path.display());
+        let cache = CacheFile {
+            butido_version: String::from(env!("CARGO_PKG_VERSION")),
+            key,
+            packages: packages.into_iter().cloned().collect(),
+        };
+
+        let content = serde_json::to_string(&cache)?;
+
+        // Write to a temporary file first, so a concurrently running butido does never read a
+        // partially written cache
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, content)
+            .with_context(|| anyhow::anyhow!("Writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| anyhow::anyhow!("Moving {} to {}", tmp.display(), self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::package;
+
+    fn key(head: &str, mtime: u64) -> CacheKey {
+        let mut files = BTreeMap::new();
+        files.insert(PathBuf::from("a/pkg.toml"), (mtime, 0, 42));
+        CacheKey { head: String::from(head), files }
+    }
+
+    #[test]
+    fn test_cache_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = RepositoryCache::with_path(dir.path().join("cache.json"));
+
+        let pkgs = vec![package("a", "1", "https://rust-lang.org", "123")];
+        cache.store(key("abc", 1), pkgs.iter()).unwrap();
+
+        let loaded = cache.load(&key("abc", 1)).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].name(), pkgs[0].name());
+        assert_eq!(loaded[0].version(), pkgs[0].version());
+
+        // other HEAD or changed file invalidates the cache
+        assert!(cache.load(&key("def", 1)).is_none());
+        assert!(cache.load(&key("abc", 2)).is_none());
+    }
+}
@@ -10,6 +10,7 @@
 
 use std::collections::HashMap;
 
+use crate::repository::fs::path::PackageFileFormat;
 use crate::repository::fs::path::PathComponent;
 
 /// One element in the tree inside FileSystemRepresentation
@@ -17,7 +18,7 @@ use crate::repository::fs::path::PathComponent;
 /// This is either a File, or a Directory that contains more (Files or Directories).
 #[derive(Debug)]
 pub enum Element {
-    File(String),
+    File(PackageFileFormat, String),
     Dir(HashMap<PathComponent, Element>)
 }
 
@@ -25,7 +26,7 @@ impl Element {
     /// Helper fn to get the directory contents of the element, if the element is an Element::Dir
     pub fn get_map_mut(&mut self) -> Option<&mut HashMap<PathComponent, Element>> {
         match self {
-            Element::File(_) => None,
+            Element::File(..) => None,
             Element::Dir(ref mut hm) => Some(hm),
         }
     }
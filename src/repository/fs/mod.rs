@@ -10,7 +10,9 @@
 
 mod representation;
 pub use representation::FileSystemRepresentation;
+pub use representation::pkgtoml_files;
 
 mod element;
 mod path;
+pub use path::PackageFileFormat;
 
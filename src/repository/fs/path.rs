@@ -10,14 +10,16 @@
 
 use std::convert::TryFrom;
 use std::path::Component;
+use std::path::Path;
 
 use anyhow::anyhow;
 use anyhow::Result;
 
 /// Helper type for filtering for pathes we need or dont need
 ///
-/// We either have a directory, which has a name, or we have a pkg.toml file, which is of interest.
-/// All other files can be ignored and thus are not represented by this type.
+/// We either have a directory, which has a name, or we have a package definition file (pkg.toml,
+/// pkg.yaml or pkg.json), which is of interest. All other files can be ignored and thus are not
+/// represented by this type.
 ///
 /// The PathComponent::DirName(_) represents a _part_ of a Path. Something like
 ///
@@ -28,6 +30,7 @@ use anyhow::Result;
 ///
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum PathComponent {
+    /// The package definition file of a directory, in any of the supported formats
     PkgToml,
     DirName(String),
 }
@@ -43,7 +46,7 @@ impl TryFrom<&std::path::Component<'_>> for PathComponent {
             Component::ParentDir => anyhow::bail!("Unexpected path component: ParentDir"),
             Component::Normal(filename) => {
                 let filename = filename.to_str().ok_or_else(|| anyhow!("UTF8-error"))?;
-                if filename == "pkg.toml" {
+                if PackageFileFormat::from_file_name(filename).is_some() {
                     Ok(PathComponent::PkgToml)
                 } else {
                     Ok(PathComponent::DirName(filename.to_string()))
@@ -69,3 +72,66 @@ impl PathComponent {
     }
 }
 
+
+/// The formats package definition files can be written in
+///
+/// All formats are loaded into the same `Package` type, so a repository can contain files in
+/// different formats, e.g. while being converted from another build system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PackageFileFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl PackageFileFormat {
+    /// All supported formats, in the order they are documented
+    pub const ALL: [PackageFileFormat; 3] = [PackageFileFormat::Toml, PackageFileFormat::Yaml, PackageFileFormat::Json];
+
+    /// Get the format of a package definition file by its name, or None if the file is not a
+    /// package definition file
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        PackageFileFormat::ALL.iter().copied().find(|format| format.file_name() == name)
+    }
+
+    /// Get the format of the package definition file `path` points to
+    pub fn of(path: &Path) -> Result<Self> {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(PackageFileFormat::from_file_name)
+            .ok_or_else(|| anyhow!("Not a package definition file: {}", path.display()))
+    }
+
+    /// The name of a package definition file in this format
+    pub fn file_name(self) -> &'static str {
+        match self {
+            PackageFileFormat::Toml => "pkg.toml",
+            PackageFileFormat::Yaml => "pkg.yaml",
+            PackageFileFormat::Json => "pkg.json",
+        }
+    }
+
+    /// The format to load the file with
+    pub fn config_format(self) -> config::FileFormat {
+        match self {
+            PackageFileFormat::Toml => config::FileFormat::Toml,
+            PackageFileFormat::Yaml => config::FileFormat::Yaml,
+            PackageFileFormat::Json => config::FileFormat::Json,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_file_format() {
+        assert_eq!(PackageFileFormat::from_file_name("pkg.toml"), Some(PackageFileFormat::Toml));
+        assert_eq!(PackageFileFormat::from_file_name("pkg.yaml"), Some(PackageFileFormat::Yaml));
+        assert_eq!(PackageFileFormat::from_file_name("pkg.json"), Some(PackageFileFormat::Json));
+        assert_eq!(PackageFileFormat::from_file_name("pkg.ini"), None);
+        assert_eq!(PackageFileFormat::of(Path::new("foo/bar/pkg.json")).unwrap(), PackageFileFormat::Json);
+        assert!(PackageFileFormat::of(Path::new("foo/bar")).is_err());
+    }
+}
@@ -25,11 +25,13 @@ use walkdir::DirEntry;
 use walkdir::WalkDir;
 
 use crate::repository::fs::element::Element;
+use crate::repository::fs::path::PackageFileFormat;
 use crate::repository::fs::path::PathComponent;
 
 /// A type representing the filesystem
 ///
-/// This type can be used to load pkg.toml files from the filesystem. As soon as this object is
+/// This type can be used to load package definition files (pkg.toml, pkg.yaml or pkg.json) from
+/// the filesystem. As soon as this object is
 /// loaded, all filesystem access is done and postprocessing of the loaded data can happen
 #[derive(Debug, getset::Getters)]
 pub struct FileSystemRepresentation {
@@ -82,8 +84,15 @@ impl FileSystemRepresentation {
                 for cmp in de_path.components() {
                     match PathComponent::try_from(&cmp)? {
                         PathComponent::PkgToml => {
-                            curr_hm.entry(PathComponent::PkgToml)
-                                .or_insert(Element::File(load_file(de_path)?));
+                            let format = PackageFileFormat::of(de_path)?;
+                            if let Some(Element::File(other, _)) = curr_hm.get(&PathComponent::PkgToml) {
+                                anyhow::bail!("Multiple package definition files in {}: {} and {}",
+                                    de_path.parent().map(|p| p.display().to_string()).unwrap_or_default(),
+                                    other.file_name(),
+                                    format.file_name());
+                            }
+
+                            curr_hm.insert(PathComponent::PkgToml, Element::File(format, load_file(de_path)?));
                         },
                         dir @ PathComponent::DirName(_) => {
                             curr_hm.entry(dir.clone())
@@ -122,13 +131,13 @@ impl FileSystemRepresentation {
 
         // Helper to check whether a tree contains pkg.toml files, recursively
         fn toml_files_in_tree(hm: &HashMap<PathComponent, Element>) -> bool {
-            if let Some(Element::File(_)) = hm.get(&PathComponent::PkgToml) {
+            if let Some(Element::File(..)) = hm.get(&PathComponent::PkgToml) {
                 return true
             }
 
             for value in hm.values() {
                 match value {
-                    Element::File(_) => return true,
+                    Element::File(..) => return true,
                     Element::Dir(hm) => if toml_files_in_tree(hm) {
                         return true
                     },
@@ -141,7 +150,7 @@ impl FileSystemRepresentation {
             let elem = PathComponent::try_from(&elem)?;
 
             match curr_hm.get(&elem) {
-                Some(Element::File(_)) => {
+                Some(Element::File(..)) => {
                     // if I have a file now, and the current hashmap only holds either
                     // * No directory
                     // * or a directory where all subdirs do not contain a pkg.toml
@@ -175,13 +184,13 @@ impl FileSystemRepresentation {
             let elem = PathComponent::try_from(&elem)?;
 
             if !elem.is_pkg_toml() {
-                if let Some(Element::File(intermediate)) = curr_hm.get(&PathComponent::PkgToml) {
-                    res.push((curr_path.join("pkg.toml"), intermediate));
+                if let Some(Element::File(format, intermediate)) = curr_hm.get(&PathComponent::PkgToml) {
+                    res.push((curr_path.join(format.file_name()), intermediate));
                 }
             }
 
             match curr_hm.get(&elem) {
-                Some(Element::File(cont)) => res.push((curr_path.join("pkg.toml"), cont)),
+                Some(Element::File(format, cont)) => res.push((curr_path.join(format.file_name()), cont)),
                 Some(Element::Dir(hm)) => {
                     curr_path = curr_path.join(elem.dir_name().unwrap()); // unwrap safe by above match
                     curr_hm = hm;
@@ -194,6 +203,20 @@ impl FileSystemRepresentation {
     }
 }
 
+/// List all package definition files below `root`, without loading them
+///
+/// The same files are considered as in `FileSystemRepresentation::load()`.
+pub fn pkgtoml_files(root: &Path) -> Result<Vec<DirEntry>> {
+    WalkDir::new(root)
+        .follow_links(false)
+        .same_file_system(true)
+        .into_iter()
+        .filter_entry(|e| !is_hidden(e) && (is_pkgtoml(e) || is_dir(e)))
+        .filter_ok(is_pkgtoml)
+        .map_err(Error::from)
+        .collect()
+}
+
 /// Helper to check whether a DirEntry points to a hidden file
 fn is_hidden(entry: &DirEntry) -> bool {
     log::trace!("Check {:?} is hidden", entry);
@@ -206,10 +229,10 @@ fn is_dir(entry: &DirEntry) -> bool {
     entry.file_type().is_dir()
 }
 
-/// Helper to check whether a DirEntry points to a pkg.toml file
+/// Helper to check whether a DirEntry points to a package definition file
 fn is_pkgtoml(entry: &DirEntry) -> bool {
-    log::trace!("Check {:?} == 'pkg.toml'", entry);
-    entry.file_name().to_str().map(|s| s == "pkg.toml").unwrap_or(false)
+    log::trace!("Check {:?} is a package definition file", entry);
+    entry.file_name().to_str().and_then(PackageFileFormat::from_file_name).is_some()
 }
 
 /// Helper fn to load a Path into memory as String
@@ -229,7 +252,7 @@ mod tests {
     }
 
     fn pkgtoml(content: &str) -> (PathComponent, Element) {
-        (PathComponent::PkgToml, Element::File(content.to_string()))
+        (PathComponent::PkgToml, Element::File(PackageFileFormat::Toml, content.to_string()))
     }
 
     fn pb(s: &str) -> PathBuf {
@@ -428,4 +451,45 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn test_hierarchy_with_mixed_formats() {
+        let fsr = FileSystemRepresentation {
+            root: PathBuf::from("/"),
+
+            // Representing
+            //  /
+            //  /pkg.toml
+            //  /foo
+            //  /foo/pkg.yaml
+            //  /foo/bar
+            //  /foo/bar/pkg.json
+            elements: vec![
+                pkgtoml("content1"),
+                dir("foo", vec![
+                    (PathComponent::PkgToml, Element::File(PackageFileFormat::Yaml, s("content2"))),
+                    dir("bar", vec![
+                        (PathComponent::PkgToml, Element::File(PackageFileFormat::Json, s("content3"))),
+                    ])
+                ])
+            ].into_iter().collect(),
+
+            files: vec![
+                PathBuf::from("pkg.toml"),
+                PathBuf::from("foo/pkg.yaml"),
+                PathBuf::from("foo/bar/pkg.json")
+            ],
+        };
+
+        let path = "foo/pkg.yaml".as_ref();
+        assert!(!fsr.is_leaf_file(path).unwrap());
+
+        let path = "foo/bar/pkg.json".as_ref();
+        assert!(fsr.is_leaf_file(path).unwrap());
+        assert_eq!(fsr.get_files_for(path).unwrap(), vec![
+            (pb("pkg.toml"),         &s("content1")),
+            (pb("foo/pkg.yaml"),     &s("content2")),
+            (pb("foo/bar/pkg.json"), &s("content3")),
+        ]);
+    }
+
 }
@@ -13,4 +13,10 @@ mod repository;
 pub use repository::*;
 
 mod fs;
+pub use fs::PackageFileFormat;
+pub use fs::pkgtoml_files;
+
+mod cache;
+
+pub mod schema;
 
@@ -17,6 +17,7 @@ use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
 use log::trace;
+use log::warn;
 use resiter::AndThen;
 use resiter::FilterMap;
 use resiter::Map;
@@ -25,6 +26,8 @@ use crate::package::Package;
 use crate::package::PackageName;
 use crate::package::PackageVersion;
 use crate::package::PackageVersionConstraint;
+use crate::repository::fs::FileSystemRepresentation;
+use crate::repository::fs::PackageFileFormat;
 
 /// A repository represents a collection of packages
 pub struct Repository {
@@ -44,14 +47,181 @@ impl Repository {
     }
 
     pub fn load(path: &Path, progress: &indicatif::ProgressBar) -> Result<Self> {
-        use crate::repository::fs::FileSystemRepresentation;
-        use config::Config;
         use rayon::iter::IntoParallelRefIterator;
         use rayon::iter::ParallelIterator;
 
         trace!("Loading files from filesystem");
         let fsr = FileSystemRepresentation::load(path.to_path_buf())?;
 
+        Self::leaf_files(&fsr)?
+            .par_iter()
+            .inspect(|path| trace!("Loading files for {:?}", path))
+            .map(|path| {
+                progress.tick();
+                Self::load_package(&fsr, path)
+                    .map(|pkg| ((pkg.qualified_name(), pkg.version().clone()), pkg))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()
+            .map(Repository::new)
+    }
+
+    /// Load only the packages named `root` and all packages they depend on, transitively
+    ///
+    /// Only the pkg.toml files that are needed for these packages are parsed completely, which is
+    /// a lot faster than loading the whole repository if only a small part of it is needed.
+    /// Dependencies are followed independently of their conditions, so the result is the same as
+    /// the relevant part of the full repository.
+    pub fn load_subtree(path: &Path, root: &PackageName, progress: &indicatif::ProgressBar) -> Result<Self> {
+        use rayon::iter::IntoParallelRefIterator;
+        use rayon::iter::ParallelIterator;
+        use crate::package::ParseDependency;
+
+        trace!("Loading files from filesystem");
+        let fsr = FileSystemRepresentation::load(path.to_path_buf())?;
+
+        // Index all leaf files by the names the package they define can be depended on by,
+        // without loading them completely
+        let mut files_by_name: BTreeMap<PackageName, Vec<PathBuf>> = BTreeMap::new();
+        for leaf in Self::leaf_files(&fsr)? {
+            let names = Self::package_names_of(&fsr, &leaf)?;
+            trace!("{} defines package {:?}", leaf.display(), names);
+            for name in names {
+                files_by_name.entry(name).or_default().push(leaf.clone());
+            }
+        }
+
+        let mut inner = BTreeMap::new();
+        let mut seen = std::collections::HashSet::new();
+        let mut queue = vec![root.clone()];
+        while let Some(name) = queue.pop() {
+            if !seen.insert(name.clone()) {
+                continue
+            }
+
+            let files = match files_by_name.get(&name) {
+                Some(files) => files,
+                None => {
+                    // Missing packages are reported when building the DAG
+                    trace!("No pkg.toml found for package {}", name);
+                    continue
+                },
+            };
+
+            let packages = files.par_iter()
+                .inspect(|path| trace!("Loading files for {:?}", path))
+                .map(|path| {
+                    progress.tick();
+                    Self::load_package(&fsr, path)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            for pkg in packages {
+                let deps = pkg.dependencies();
+                for dep in deps.build().iter().map(ParseDependency::parse_as_name_and_version)
+                    .chain(deps.runtime().iter().map(ParseDependency::parse_as_name_and_version))
+                {
+                    queue.push(dep?.0);
+                }
+                inner.insert((pkg.qualified_name(), pkg.version().clone()), pkg);
+            }
+        }
+
+        Ok(Repository::new(inner))
+    }
+
+    /// Get all leaf files from the `FileSystemRepresentation`, which are the files that define a
+    /// package
+    fn leaf_files(fsr: &FileSystemRepresentation) -> Result<Vec<PathBuf>> {
+        fsr.files()
+            .iter()
+            .inspect(|path| trace!("Checking for leaf file: {}", path.display()))
+            .filter_map(|path| {
+                match fsr.is_leaf_file(path) {
+                    Ok(true) => Some(Ok(path.clone())),
+                    Ok(false) => None,
+                    Err(e) => Some(Err(e)),
+                }
+            })
+            .collect()
+    }
+
+    /// Get the names the package defined by the leaf file `path` can be depended on by: Its name,
+    /// its outputs and the virtual packages it provides
+    ///
+    /// Only the "name", "outputs" and "provides" keys in the pkg.toml files are looked at,
+    /// starting at the leaf. Returns nothing if the file does not define a package name.
+    fn package_names_of(fsr: &FileSystemRepresentation, path: &Path) -> Result<Vec<PackageName>> {
+        use config::Config;
+
+        fn get<T: serde::de::DeserializeOwned>(config: &Config, key: &str) -> Result<Option<T>> {
+            match config.get::<T>(key) {
+                Ok(value) => Ok(Some(value)),
+                Err(config::ConfigError::NotFound(_)) => Ok(None),
+                Err(e) => Err(e).map_err(Error::from),
+            }
+        }
+
+        let mut name: Option<String> = None;
+        let mut name_layer: Option<&Path> = None;
+        let mut outputs: Option<Vec<String>> = None;
+        let mut provides: Option<Vec<String>> = None;
+        let layers = fsr.get_files_for(path)?;
+        for (layer, content) in layers.iter().rev() {
+            let mut config = Config::default();
+            config.merge(config::File::from_str(content, PackageFileFormat::of(layer)?.config_format()))
+                .with_context(|| anyhow!("Loading contents of {}", layer.display()))?;
+
+            // The deepest layer that sets a key wins, as when merging the layers
+            let layer_name: Option<String> = get(&config, "name")?;
+            if layer_name.is_some() {
+                name_layer = Some(layer);
+            }
+            if name.is_none() {
+                name = layer_name;
+            }
+            if outputs.is_none() {
+                outputs = get(&config, "outputs")?;
+            }
+            if provides.is_none() {
+                provides = get(&config, "provides")?;
+            }
+        }
+
+        let namespace = name_layer.and_then(Self::namespace_of);
+        Ok(name
+            .map(|name| {
+                let named = std::iter::once(name).chain(outputs.unwrap_or_default()).collect::<Vec<_>>();
+                let qualified = namespace
+                    .iter()
+                    .flat_map(|namespace| named.iter().map(move |name| format!("{}/{}", namespace, name)))
+                    .collect::<Vec<_>>();
+
+                named.into_iter()
+                    .chain(qualified)
+                    .chain(provides.unwrap_or_default())
+                    .map(PackageName::from)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Get the namespace of a package from the pkg.toml file `layer` that sets its name
+    ///
+    /// The namespace is the path of the directories above the directory of that file, e.g. "core"
+    /// for "core/gcc/pkg.toml". Packages whose name is set in a top-level directory have no
+    /// namespace.
+    fn namespace_of(layer: &Path) -> Option<String> {
+        layer.parent()
+            .and_then(Path::parent)
+            .map(|dir| dir.display().to_string())
+            .filter(|namespace| !namespace.is_empty())
+    }
+
+    /// Load the package defined by the leaf file `path`, merging all pkg.toml files from the root
+    /// of the repository down to `path`
+    fn load_package(fsr: &FileSystemRepresentation, path: &Path) -> Result<Package> {
+        use config::Config;
+
         fn get_patches(config: &Config) -> Result<Vec<PathBuf>> {
             match config.get_array("patches") {
                 Ok(v)  => v.into_iter()
@@ -66,103 +236,213 @@ impl Repository {
             }
         }
 
-        fsr.files()
-            .par_iter()
-            .inspect(|path| trace!("Checking for leaf file: {}", path.display()))
-            .filter_map(|path| {
-                match fsr.is_leaf_file(path) {
-                    Ok(true) => Some(Ok(path)),
-                    Ok(false) => None,
-                    Err(e) => Some(Err(e)),
+        // The outermost layer that sets the name of the package
+        let mut name_layer: Option<PathBuf> = None;
+
+        fsr.get_files_for(path)?
+            .iter()
+            .inspect(|(path, _)| trace!("Loading layer at {}", path.display()))
+            .fold(Ok(Config::default()) as Result<_>, |config, (path, content)| {
+                let mut config = config?;
+                let patches_before_merge = get_patches(&config)?;
+
+                let format = PackageFileFormat::of(path)?;
+                crate::repository::schema::parse(content, format)
+                    .and_then(|layer| crate::repository::schema::validate(&layer))
+                    .with_context(|| anyhow!("Checking {} against its schema version", path.display()))?;
+                config.merge(config::File::from_str(content, format.config_format()))
+                    .with_context(|| anyhow!("Loading contents of {}", path.display()))?;
+
+                if name_layer.is_none() && config.get_str("name").is_ok() {
+                    name_layer = Some(path.clone());
                 }
-            })
-            .inspect(|r| trace!("Loading files for {:?}", r))
-            .map(|path| {
-                progress.tick();
-                let path = path?;
-                fsr.get_files_for(path)?
-                    .iter()
-                    .inspect(|(path, _)| trace!("Loading layer at {}", path.display()))
-                    .fold(Ok(Config::default()) as Result<_>, |config, (path, content)| {
-                        let mut config = config?;
-                        let patches_before_merge = get_patches(&config)?;
-
-                        config.merge(config::File::from_str(content, config::FileFormat::Toml))
-                            .with_context(|| anyhow!("Loading contents of {}", path.display()))?;
-
-                        // get the patches that are in the `config` object after the merge
-                        let patches = get_patches(&config)?
-                            .into_iter()
-                            .map(|p| if let Some(current_dir) = path.parent() {
-                                Ok(current_dir.join(p))
-                            } else {
-                                Err(anyhow!("Path should point to path with parent, but doesn't: {}", path.display()))
-                            })
-                            .inspect(|patch| trace!("Patch: {:?}", patch))
-
-                            // if the patch file exists, use it (as config::Value).
-                            //
-                            // Otherwise we have an error here, because we're refering to a non-existing file.
-                            .and_then_ok(|patch| if patch.exists() {
-                                trace!("Path to patch exists: {}", patch.display());
-                                Ok(Some(patch))
-                            } else if patches_before_merge.iter().any(|pb| pb.file_name() == patch.file_name()) {
-                                // We have a patch already in the array that is named equal to the patch
-                                // we have in the fold iteration.
-                                // It seems like this patch was already in the list and we re-found it
-                                // because we loaded a "deeper" pkg.toml file.
-                                Ok(None)
-                            } else {
-                                trace!("Path to patch does not exist: {}", patch.display());
-                                Err(anyhow!("Patch does not exist: {}", patch.display()))
-                            })
-                            .filter_map_ok(|o| o)
-                            .collect::<Result<Vec<_>>>()?;
-
-                        // If we found any patches, use them. Otherwise use the array from before the merge
-                        // (which already has the correct pathes from the previous recursion).
-                        let patches = if !patches.is_empty() {
-                            patches
-                        } else {
-                            patches_before_merge
-                        };
-
-                        trace!("Patches after postprocessing merge: {:?}", patches);
-                        let patches = patches
-                            .into_iter()
-                            .map(|p| p.display().to_string())
-                            .map(config::Value::from)
-                            .collect::<Vec<_>>();
-                        config.set_once("patches", config::Value::from(patches))?;
-                        Ok(config)
+
+                // get the patches that are in the `config` object after the merge
+                let patches = get_patches(&config)?
+                    .into_iter()
+                    .map(|p| if let Some(current_dir) = path.parent() {
+                        Ok(current_dir.join(p))
+                    } else {
+                        Err(anyhow!("Path should point to path with parent, but doesn't: {}", path.display()))
                     })
-                    .and_then(|c| c.try_into::<Package>().map_err(Error::from))
-                    .map(|pkg| ((pkg.name().clone(), pkg.version().clone()), pkg))
+                    .inspect(|patch| trace!("Patch: {:?}", patch))
+
+                    // if the patch file exists, use it (as config::Value).
+                    //
+                    // Otherwise we have an error here, because we're refering to a non-existing file.
+                    .and_then_ok(|patch| if patch.exists() {
+                        trace!("Path to patch exists: {}", patch.display());
+                        Ok(Some(patch))
+                    } else if patches_before_merge.iter().any(|pb| pb.file_name() == patch.file_name()) {
+                        // We have a patch already in the array that is named equal to the patch
+                        // we have in the fold iteration.
+                        // It seems like this patch was already in the list and we re-found it
+                        // because we loaded a "deeper" pkg.toml file.
+                        Ok(None)
+                    } else {
+                        trace!("Path to patch does not exist: {}", patch.display());
+                        Err(anyhow!("Patch does not exist: {}", patch.display()))
+                    })
+                    .filter_map_ok(|o| o)
+                    .collect::<Result<Vec<_>>>()?;
+
+                // If we found any patches, use them. Otherwise use the array from before the merge
+                // (which already has the correct pathes from the previous recursion).
+                let patches = if !patches.is_empty() {
+                    patches
+                } else {
+                    patches_before_merge
+                };
+
+                trace!("Patches after postprocessing merge: {:?}", patches);
+                let patches = patches
+                    .into_iter()
+                    .map(|p| p.display().to_string())
+                    .map(config::Value::from)
+                    .collect::<Vec<_>>();
+                config.set_once("patches", config::Value::from(patches))?;
+                Ok(config)
+            })
+            .and_then(Self::interpolate_source_urls)
+            .and_then(|c| c.try_into::<Package>().map_err(Error::from))
+            .map(|mut pkg| {
+                pkg.set_namespace(name_layer.as_deref().and_then(Self::namespace_of));
+                pkg.set_definition_file(path.to_path_buf());
+                pkg.resolve_source_keyrings(fsr.root());
+                pkg
             })
-            .collect::<Result<BTreeMap<_, _>>>()
-            .map(Repository::new)
     }
 
+    /// Render the URLs of the sources of the package in `config` and of their signatures as
+    /// handlebars templates, so they can be derived from the version of the package
+    fn interpolate_source_urls(mut config: config::Config) -> Result<config::Config> {
+        use std::collections::HashMap;
+        use config::Value;
+
+        let sources = match config.get_table("sources") {
+            Ok(sources) => sources,
+            Err(config::ConfigError::NotFound(_)) => return Ok(config),
+            Err(e) => return Err(Error::from(e)),
+        };
+
+        let data = serde_json::json!({
+            "name": config.get_str("name").ok(),
+            "version": config.get_str("version").ok(),
+        });
+        let hb = crate::package::source_url_registry();
+
+        let render_url = |table: &mut HashMap<String, Value>| -> Result<()> {
+            if let Some(url) = table.get("url").and_then(|url| url.clone().into_str().ok()) {
+                if url.contains("{{") {
+                    let rendered = hb.render_template(&url, &data)
+                        .with_context(|| anyhow!("Rendering source URL template: {}", url))?;
+                    table.insert(String::from("url"), Value::from(rendered));
+                }
+            }
+            Ok(())
+        };
+
+        let sources = sources.into_iter()
+            .map(|(name, source)| {
+                let mut source = match source.clone().into_table() {
+                    Ok(table) => table,
+                    Err(_) => return Ok((name, source)), // reported when deserializing the package
+                };
+                render_url(&mut source)?;
+                if let Some(mut signature) = source.get("signature").and_then(|s| s.clone().into_table().ok()) {
+                    render_url(&mut signature)?;
+                    source.insert(String::from("signature"), Value::from(signature));
+                }
+                Ok((name, Value::from(source)))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        config.set_once("sources", Value::from(sources))?;
+        Ok(config)
+    }
+
+    /// Load the repository, using the on-disk cache if it is valid for the current state of the
+    /// repository
+    ///
+    /// `head` is the commit the repository HEAD points to. If the cache is outdated, the
+    /// repository is loaded from the filesystem and the cache is refreshed.
+    /// If only the subtree of a package is required (`subtree_of`), only that subtree is loaded
+    /// in this case and the cache is not refreshed.
+    pub fn load_cached(path: &Path, head: String, subtree_of: Option<&PackageName>, progress: &indicatif::ProgressBar) -> Result<Self> {
+        use crate::repository::cache::CacheKey;
+        use crate::repository::cache::RepositoryCache;
+
+        let cache = RepositoryCache::for_repository(path)?;
+        let key = CacheKey::compute(path, head)?;
+
+        if let Some(packages) = cache.load(&key) {
+            let inner = packages
+                .into_iter()
+                .map(|pkg| ((pkg.qualified_name(), pkg.version().clone()), pkg))
+                .collect();
+            return Ok(Repository::new(inner))
+        }
+
+        if let Some(root) = subtree_of {
+            return Self::load_subtree(path, root, progress)
+        }
+
+        let repo = Self::load(path, progress)?;
+        if let Err(e) = cache.store(key, repo.packages()) {
+            // Not being able to write the cache is not a reason to fail
+            warn!("Failed to write repository cache: {:?}", e);
+        }
+        Ok(repo)
+    }
+
+    /// Find the packages named `name`, which can be the name or the fully-qualified name
     pub fn find_by_name<'a>(&'a self, name: &PackageName) -> Vec<&'a Package> {
         trace!("Searching for '{}' in repository", name);
         self.inner
-            .iter()
-            .filter(|((n, _), _)| {
-                trace!("{} == {} -> {}", name, n, name == n);
-                name == n
+            .values()
+            .filter(|p| {
+                let found = p.has_name(name);
+                trace!("{} == {} -> {}", name, p.qualified_name(), found);
+                found
             })
-            .map(|(_, pack)| pack)
             .collect()
     }
 
     pub fn find<'a>(&'a self, name: &PackageName, version: &PackageVersion) -> Vec<&'a Package> {
         self.inner
             .iter()
-            .filter(|((n, v), _)| n == name && v == version)
+            .filter(|((_, v), p)| p.has_name(name) && v == version)
             .map(|(_, p)| p)
             .collect()
     }
 
+    /// Get the fully-qualified name of the package `name` refers to
+    ///
+    /// `name` can be the name or the fully-qualified name of a package. Fails if packages in
+    /// several namespaces have that name, listing all of them. If there is no such package, `name`
+    /// is returned as is, so that the caller can report that the package was not found.
+    pub fn resolve_name(&self, name: &PackageName) -> Result<PackageName> {
+        let mut candidates = self.inner
+            .values()
+            .filter(|p| p.has_name(name))
+            .map(Package::qualified_name)
+            .collect::<Vec<_>>();
+        candidates.sort();
+        candidates.dedup();
+
+        match candidates.len() {
+            0 => Ok(name.clone()),
+            1 => Ok(candidates.remove(0)),
+            _ => Err(anyhow!(
+                "Package name {} is ambiguous, it could be any of: {}",
+                name,
+                candidates.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+
+    /// Find the packages that provide `name` in a version matching `vc`, either as the package
+    /// itself, as one of its outputs or as a virtual package
     pub fn find_with_version<'a>(
         &'a self,
         name: &PackageName,
@@ -170,7 +450,7 @@ impl Repository {
     ) -> Vec<&'a Package> {
         self.inner
             .iter()
-            .filter(|((n, v), _)| n == name && vc.matches(v))
+            .filter(|((_, v), p)| p.satisfies(name) && vc.matches(v))
             .map(|(_, p)| p)
             .collect()
     }
@@ -178,11 +458,50 @@ impl Repository {
     pub fn packages(&self) -> impl Iterator<Item = &Package> {
         self.inner.values()
     }
+
+    /// The packages that are affected by changes of the files `changed`, relative to the
+    /// repository root `root`
+    ///
+    /// A package is changed if one of the package definition files it is merged from or one of its
+    /// patches changed. The packages that depend on changed packages (at build time or at runtime,
+    /// independent of the conditions of the dependencies) are affected as well, transitively.
+    pub fn affected_by(&self, root: &Path, changed: &[PathBuf]) -> Result<Vec<&Package>> {
+        let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        let changed_definition_dirs = changed.iter()
+            .filter(|path| PackageFileFormat::of(path).is_ok())
+            .map(|path| path.parent().map(Path::to_path_buf).unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        let mut affected = self.packages()
+            .filter(|package| {
+                let definition_changed = package.definition_file()
+                    .as_ref()
+                    .map(|file| relative(file))
+                    .map(|file| changed_definition_dirs.iter().any(|dir| file.starts_with(dir)))
+                    .unwrap_or(false);
+                definition_changed || package.patches().iter().any(|patch| changed.contains(&relative(patch)))
+            })
+            .inspect(|package| trace!("{} {} changed", package.name(), package.version()))
+            .collect::<Vec<_>>();
+
+        let mut next = 0;
+        while let Some(changed_package) = affected.get(next).copied() {
+            next += 1;
+            for package in self.packages().filter(|p| !affected.contains(p)).collect::<Vec<_>>() {
+                if package.depends_on(changed_package)? {
+                    trace!("{} {} depends on {} {}", package.name(), package.version(), changed_package.name(), changed_package.version());
+                    affected.push(package);
+                }
+            }
+        }
+        Ok(affected)
+    }
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use std::convert::TryFrom;
     use crate::package::tests::package;
     use crate::package::tests::pname;
     use crate::package::tests::pversion;
@@ -272,4 +591,102 @@ pub mod tests {
         assert_eq!(*p.version(), pversion("2"));
         assert!(!p.version_is_semver());
     }
+
+    #[test]
+    fn test_namespace_of() {
+        assert_eq!(Repository::namespace_of(Path::new("core/gcc/pkg.toml")), Some(String::from("core")));
+        assert_eq!(Repository::namespace_of(Path::new("apps/editors/vim/pkg.toml")), Some(String::from("apps/editors")));
+        assert_eq!(Repository::namespace_of(Path::new("gcc/pkg.toml")), None);
+    }
+
+    #[test]
+    fn test_find_namespaced_packages() {
+        let mut btree = BTreeMap::new();
+
+        for (namespace, name, vers) in [("core", "gcc", "12"), ("devel", "gcc", "13"), ("core", "binutils", "2")].iter() {
+            let mut pack = package(name, vers, "https://rust-lang.org", "123");
+            pack.set_namespace(Some(String::from(*namespace)));
+            btree.insert((pack.qualified_name(), pversion(vers)), pack);
+        }
+
+        let repo = Repository::from(btree);
+
+        assert_eq!(repo.find_by_name(&pname("gcc")).len(), 2);
+        assert_eq!(repo.find_by_name(&pname("core/gcc")).len(), 1);
+        assert_eq!(repo.find(&pname("devel/gcc"), &pversion("13")).len(), 1);
+        assert!(repo.find(&pname("devel/gcc"), &pversion("12")).is_empty());
+
+        assert_eq!(repo.resolve_name(&pname("binutils")).unwrap(), pname("core/binutils"));
+        assert_eq!(repo.resolve_name(&pname("core/gcc")).unwrap(), pname("core/gcc"));
+        assert_eq!(repo.resolve_name(&pname("vim")).unwrap(), pname("vim"));
+        assert_eq!(
+            repo.resolve_name(&pname("gcc")).unwrap_err().to_string(),
+            "Package name gcc is ambiguous, it could be any of: core/gcc, devel/gcc"
+        );
+
+        let constraint = PackageVersionConstraint::try_from(">=12").unwrap();
+        assert_eq!(repo.find_with_version(&pname("core/gcc"), &constraint).len(), 1);
+        assert_eq!(repo.find_with_version(&pname("gcc"), &constraint).len(), 2);
+    }
+
+    #[test]
+    fn test_affected_by() {
+        use crate::package::Dependencies;
+        use crate::package::Dependency;
+
+        // b depends on a, c on b, d on nothing
+        let mut btree = BTreeMap::new();
+        for (dir, name, dependency) in [("libs/a", "a", None), ("libs/b", "b", Some("a =1")), ("app/c", "c", Some("b =1")), ("tools/d", "d", None)].iter() {
+            let mut pack = package(name, "1", "https://rust-lang.org", "123");
+            pack.set_definition_file(PathBuf::from(format!("/repo/{}/pkg.toml", dir)));
+            if let Some(dependency) = dependency {
+                pack.set_dependencies(Dependencies::with_runtime_dependency(Dependency::from(String::from(*dependency))));
+            }
+            btree.insert((pname(name), pversion("1")), pack);
+        }
+        let repo = Repository::from(btree);
+
+        let affected = |changed: &[&str]| {
+            let changed = changed.iter().map(PathBuf::from).collect::<Vec<_>>();
+            let mut names = repo.affected_by(Path::new("/repo"), &changed)
+                .unwrap()
+                .into_iter()
+                .map(|p| p.name().to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(affected(&["libs/a/pkg.toml"]), vec!["a", "b", "c"]);
+        assert_eq!(affected(&["libs/b/pkg.toml", "README.md"]), vec!["b", "c"]);
+        assert_eq!(affected(&["libs/pkg.toml"]), vec!["a", "b", "c"]);
+        assert_eq!(affected(&["pkg.toml"]), vec!["a", "b", "c", "d"]);
+        assert!(affected(&["README.md", "libs/a/notes.txt"]).is_empty());
+
+        assert!(repo.find(&pname("c"), &pversion("1"))[0].depends_on(repo.find(&pname("b"), &pversion("1"))[0]).unwrap());
+        assert!(!repo.find(&pname("c"), &pversion("1"))[0].depends_on(repo.find(&pname("a"), &pversion("1"))[0]).unwrap());
+    }
+
+    #[test]
+    fn test_interpolate_source_urls() {
+        let mut config = config::Config::default();
+        config.merge(config::File::from_str(r#"
+            name = "python"
+            version = "3.11.4"
+
+            [sources.src]
+            url = "https://www.python.org/ftp/python/{{version}}/Python-{{version}}.tgz"
+            hash = { type = "sha1", hash = "123" }
+            signature = { url = "https://www.python.org/ftp/{{version_major version}}.{{version_minor version}}.asc", keyring = "k.gpg" }
+
+            [sources.patch]
+            url = "https://example.com/fix.patch"
+            hash = { type = "sha1", hash = "456" }
+        "#, config::FileFormat::Toml)).unwrap();
+
+        let config = Repository::interpolate_source_urls(config).unwrap();
+        assert_eq!(config.get_str("sources.src.url").unwrap(), "https://www.python.org/ftp/python/3.11.4/Python-3.11.4.tgz");
+        assert_eq!(config.get_str("sources.src.signature.url").unwrap(), "https://www.python.org/ftp/3.11.asc");
+        assert_eq!(config.get_str("sources.src.signature.keyring").unwrap(), "k.gpg");
+        assert_eq!(config.get_str("sources.patch.url").unwrap(), "https://example.com/fix.patch");
+    }
 }
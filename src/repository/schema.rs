@@ -0,0 +1,257 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! The versions of the format of package definition files
+//!
+//! A package definition file declares the version of the format it is written in with the
+//! top-level key `schema_version`. Files without it are of version 1, the format from before the
+//! key existed, in which unknown keys are ignored. From version 2 on, unknown keys are errors, so
+//! a misspelled key does not go unnoticed.
+//!
+//! `migrate()` rewrites files to the current version, one version at a time.
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use config::Source;
+
+use crate::repository::fs::PackageFileFormat;
+
+/// The key of the schema version in package definition files
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// The schema version this version of butido writes
+pub const CURRENT_SCHEMA_VERSION: u32 = 2;
+
+/// The schema version of files that do not declare one
+const UNVERSIONED_SCHEMA_VERSION: u32 = 1;
+
+/// The top-level keys of package definition files, from schema version 2 on
+const KNOWN_KEYS: &[&str] = &[
+    SCHEMA_VERSION_KEY,
+    "name",
+    "version",
+    "version_is_semver",
+    "sources",
+    "dependencies",
+    "patches",
+    "maintainers",
+    "license",
+    "outputs",
+    "provides",
+    "options",
+    "source_mirrors",
+    "environment",
+    "allowed_images",
+    "denied_images",
+    "phases",
+    "shebang",
+    "limits",
+    "resources",
+    "network",
+    "endpoints",
+    "meta",
+];
+
+/// A migration of the contents of a definition file to the next schema version
+type Migration = fn(&str, PackageFileFormat, &config::Config) -> Result<String>;
+
+/// The migrations, by the schema version they migrate from
+const MIGRATIONS: &[(u32, Migration)] = &[
+    (1, migrate_1_to_2),
+];
+
+/// Parse the contents of a definition file in `format`, without merging it with other files
+pub fn parse(content: &str, format: PackageFileFormat) -> Result<config::Config> {
+    let mut layer = config::Config::default();
+    layer.merge(config::File::from_str(content, format.config_format()))?;
+    Ok(layer)
+}
+
+/// The schema version the parsed definition file `layer` declares
+pub fn schema_version(layer: &config::Config) -> Result<u32> {
+    match layer.get::<u32>(SCHEMA_VERSION_KEY) {
+        Ok(version) => Ok(version),
+        Err(config::ConfigError::NotFound(_)) => Ok(UNVERSIONED_SCHEMA_VERSION),
+        Err(e) => Err(e).with_context(|| anyhow!("Invalid {}, expected a number", SCHEMA_VERSION_KEY)),
+    }
+}
+
+/// Check the parsed definition file `layer` against the schema version it declares
+pub fn validate(layer: &config::Config) -> Result<()> {
+    let version = schema_version(layer)?;
+    if version == 0 || version > CURRENT_SCHEMA_VERSION {
+        return Err(anyhow!("Schema version {} is not supported, this version of butido supports the versions up to {}",
+            version, CURRENT_SCHEMA_VERSION))
+    }
+
+    if version >= 2 {
+        let unknown = unknown_keys(layer)?;
+        if !unknown.is_empty() {
+            return Err(anyhow!("Unknown keys for schema version {}: {}", version, unknown.join(", ")))
+        }
+    }
+    Ok(())
+}
+
+/// The top-level keys of `layer` that are not part of the format, sorted
+fn unknown_keys(layer: &config::Config) -> Result<Vec<String>> {
+    let mut unknown = layer.collect()?
+        .into_keys()
+        .filter(|key| !KNOWN_KEYS.contains(&key.as_str()))
+        .collect::<Vec<_>>();
+    unknown.sort();
+    Ok(unknown)
+}
+
+/// The contents of the definition file `content` in `format`, migrated to the current schema
+/// version, with the version it was migrated from
+///
+/// Returns `None` if the file is of the current version already. Only what the migrations change
+/// is rewritten, the rest of the file is kept as it is.
+pub fn migrate(content: &str, format: PackageFileFormat) -> Result<Option<(u32, String)>> {
+    let mut layer = parse(content, format)?;
+    validate(&layer)?;
+    let from = schema_version(&layer)?;
+    if from == CURRENT_SCHEMA_VERSION {
+        return Ok(None)
+    }
+
+    let mut content = content.to_string();
+    for version in from..CURRENT_SCHEMA_VERSION {
+        let (_, migration) = MIGRATIONS.iter()
+            .find(|(v, _)| *v == version)
+            .ok_or_else(|| anyhow!("BUG: No migration from schema version {}", version))?;
+        content = migration(&content, format, &layer)
+            .with_context(|| anyhow!("Migrating from schema version {} to {}", version, version + 1))?;
+        layer = parse(&content, format)?;
+    }
+    Ok(Some((from, content)))
+}
+
+/// Version 2 declares its version and does not allow unknown keys
+///
+/// Unknown keys are not removed, as they might be misspelled keys that have to be fixed by hand.
+fn migrate_1_to_2(content: &str, format: PackageFileFormat, layer: &config::Config) -> Result<String> {
+    let unknown = unknown_keys(layer)?;
+    if !unknown.is_empty() {
+        return Err(anyhow!("Unknown keys, which have to be fixed or removed first: {}", unknown.join(", ")))
+    }
+    set_schema_version(content, format, 2)
+}
+
+/// `content` in `format` with the schema version set to `version`
+///
+/// The key is added at the top of the file (after the comments the file starts with) if it is not
+/// set yet.
+fn set_schema_version(content: &str, format: PackageFileFormat, version: u32) -> Result<String> {
+    let existing = match format {
+        PackageFileFormat::Toml => r#"(?m)^(\s*schema_version\s*=\s*)\d+"#,
+        PackageFileFormat::Yaml => r#"(?m)^(schema_version\s*:\s*)\d+"#,
+        PackageFileFormat::Json => r#"("schema_version"\s*:\s*)\d+"#,
+    };
+    let existing = regex::Regex::new(existing)?;
+
+    // Only the top-level key, before the first table of a TOML file
+    let top_level = match format {
+        PackageFileFormat::Toml => regex::Regex::new(r#"(?m)^\s*\["#)?
+            .find(content)
+            .map(|table| table.start())
+            .unwrap_or(content.len()),
+        PackageFileFormat::Yaml | PackageFileFormat::Json => content.len(),
+    };
+    if let Some(captures) = existing.captures(&content[..top_level]) {
+        let key = captures.get(1).unwrap(); // always matches
+        let value_end = captures.get(0).unwrap().end(); // always matches
+        return Ok(format!("{}{}{}", &content[..key.end()], version, &content[value_end..]))
+    }
+
+    match format {
+        PackageFileFormat::Toml | PackageFileFormat::Yaml => {
+            let line = match format {
+                PackageFileFormat::Toml => format!("schema_version = {}\n", version),
+                _ => format!("schema_version: {}\n", version),
+            };
+            let mut position = 0;
+            for l in content.split_inclusive('\n') {
+                let trimmed = l.trim();
+                if !(trimmed.is_empty() || trimmed.starts_with('#') || trimmed == "---") {
+                    break
+                }
+                position += l.len();
+            }
+            // Separated from the comments, if they are not separated by an empty line already
+            let separator = if position > 0 && !content[..position].ends_with("\n\n") { "\n" } else { "" };
+            Ok(format!("{}{}{}{}", &content[..position], separator, line, &content[position..]))
+        },
+
+        PackageFileFormat::Json => {
+            let open = content.find('{')
+                .ok_or_else(|| anyhow!("Expected a JSON object"))?;
+            let rest = &content[open + 1..];
+            let whitespace = &rest[..rest.len() - rest.trim_start().len()];
+            if rest.trim_start().starts_with('}') {
+                Ok(format!("{}{}\"schema_version\": {}{}", &content[..=open], whitespace, version, rest))
+            } else {
+                Ok(format!("{}{}\"schema_version\": {},{}", &content[..=open], whitespace, version, rest))
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let toml = |content: &str| parse(content, PackageFileFormat::Toml).unwrap();
+
+        assert!(validate(&toml("name = \"a\"\nnmae = \"typo\"\n")).is_ok());
+        assert!(validate(&toml("schema_version = 2\nname = \"a\"\n[sources.src]\nurl = \"https://example.com\"\n")).is_ok());
+        assert!(validate(&toml("schema_version = 2\nnmae = \"typo\"\n")).is_err());
+        assert!(validate(&toml("schema_version = 3\n")).is_err());
+        assert!(validate(&toml("schema_version = 0\n")).is_err());
+        assert!(validate(&toml("schema_version = \"two\"\n")).is_err());
+    }
+
+    #[test]
+    fn test_migrate() {
+        let content = indoc::indoc!(r#"
+            # The definition of a
+            # from upstream
+
+            name = "a"
+
+            [sources.src]
+            url = "https://example.com/a.tar.gz"
+        "#);
+        let (from, migrated) = migrate(content, PackageFileFormat::Toml).unwrap().unwrap();
+        assert_eq!(from, 1);
+        assert_eq!(migrated, content.replace("\nname = ", "\nschema_version = 2\nname = "));
+        assert!(migrate(&migrated, PackageFileFormat::Toml).unwrap().is_none());
+
+        let (_, migrated) = migrate("schema_version = 1\n[meta]\nschema_version = \"1\"\n", PackageFileFormat::Toml).unwrap().unwrap();
+        assert_eq!(migrated, "schema_version = 2\n[meta]\nschema_version = \"1\"\n");
+
+        let (_, migrated) = migrate("# a\nversion = \"1\"\n", PackageFileFormat::Toml).unwrap().unwrap();
+        assert_eq!(migrated, "# a\n\nschema_version = 2\nversion = \"1\"\n");
+
+        let (_, migrated) = migrate("name: a\nversion: \"1\"\n", PackageFileFormat::Yaml).unwrap().unwrap();
+        assert_eq!(migrated, "schema_version: 2\nname: a\nversion: \"1\"\n");
+
+        let (_, migrated) = migrate("{\n    \"name\": \"a\"\n}\n", PackageFileFormat::Json).unwrap().unwrap();
+        assert_eq!(migrated, "{\n    \"schema_version\": 2,\n    \"name\": \"a\"\n}\n");
+        let (_, migrated) = migrate("{}", PackageFileFormat::Json).unwrap().unwrap();
+        assert_eq!(migrated, "{\"schema_version\": 2}");
+
+        assert!(migrate("nmae = \"typo\"\n", PackageFileFormat::Toml).is_err());
+    }
+}
@@ -3,6 +3,20 @@ table! {
         id -> Int4,
         path -> Varchar,
         job_id -> Int4,
+        output -> Nullable<Varchar>,
+        sha256 -> Nullable<Varchar>,
+        size -> Nullable<Int8>,
+        hermetic -> Bool,
+    }
+}
+
+table! {
+    audit (id) {
+        id -> Int4,
+        recorded_at -> Timestamptz,
+        username -> Varchar,
+        operation -> Varchar,
+        parameters -> Jsonb,
     }
 }
 
@@ -43,6 +57,27 @@ table! {
     }
 }
 
+table! {
+    job_phases (id) {
+        id -> Int4,
+        job_id -> Int4,
+        position -> Int4,
+        name -> Varchar,
+        started -> Timestamptz,
+        duration_secs -> Float8,
+    }
+}
+
+table! {
+    job_state_transitions (id) {
+        id -> Int4,
+        submit_job_id -> Int4,
+        state -> Varchar,
+        time -> Timestamptz,
+        artifacts -> Array<Varchar>,
+    }
+}
+
 table! {
     jobs (id) {
         id -> Int4,
@@ -54,6 +89,11 @@ table! {
         script_text -> Text,
         log_text -> Text,
         uuid -> Uuid,
+        patches_hash -> Nullable<Varchar>,
+        options -> Nullable<Varchar>,
+        image_digest -> Nullable<Varchar>,
+        endpoint_env -> Array<Varchar>,
+        endpoint_mounts -> Array<Varchar>,
     }
 }
 
@@ -65,6 +105,16 @@ table! {
     }
 }
 
+table! {
+    release_promotions (id) {
+        id -> Int4,
+        release_id -> Int4,
+        from_release_store_id -> Int4,
+        promoted_by -> Varchar,
+        promoted_at -> Timestamptz,
+    }
+}
+
 table! {
     release_stores (id) {
         id -> Int4,
@@ -81,6 +131,28 @@ table! {
     }
 }
 
+table! {
+    running_jobs (id) {
+        id -> Int4,
+        uuid -> Uuid,
+        submit_id -> Int4,
+        fingerprint -> Varchar,
+        started_at -> Timestamptz,
+        endpoint_name -> Nullable<Varchar>,
+        scheduled_at -> Nullable<Timestamptz>,
+        phase -> Nullable<Varchar>,
+        progress -> Nullable<Int4>,
+    }
+}
+
+table! {
+    submit_cancellations (id) {
+        id -> Int4,
+        submit_id -> Int4,
+        canceled_at -> Timestamptz,
+    }
+}
+
 table! {
     submit_envs (id) {
         id -> Int4,
@@ -89,6 +161,16 @@ table! {
     }
 }
 
+table! {
+    submit_jobs (id) {
+        id -> Int4,
+        submit_id -> Int4,
+        uuid -> Uuid,
+        package_id -> Int4,
+        dependencies -> Array<Uuid>,
+    }
+}
+
 table! {
     submits (id) {
         id -> Int4,
@@ -97,35 +179,53 @@ table! {
         requested_image_id -> Int4,
         requested_package_id -> Int4,
         repo_hash_id -> Int4,
+        submitted_by -> Nullable<Varchar>,
+        phases -> Nullable<Array<Varchar>>,
+        pins -> Nullable<Array<Varchar>>,
     }
 }
 
 joinable!(artifacts -> jobs (job_id));
 joinable!(job_envs -> envvars (env_id));
 joinable!(job_envs -> jobs (job_id));
+joinable!(job_phases -> jobs (job_id));
+joinable!(job_state_transitions -> submit_jobs (submit_job_id));
 joinable!(jobs -> endpoints (endpoint_id));
 joinable!(jobs -> images (image_id));
 joinable!(jobs -> packages (package_id));
 joinable!(jobs -> submits (submit_id));
+joinable!(release_promotions -> release_stores (from_release_store_id));
+joinable!(release_promotions -> releases (release_id));
 joinable!(releases -> artifacts (artifact_id));
 joinable!(releases -> release_stores (release_store_id));
+joinable!(running_jobs -> submits (submit_id));
+joinable!(submit_cancellations -> submits (submit_id));
 joinable!(submit_envs -> envvars (env_id));
 joinable!(submit_envs -> submits (submit_id));
+joinable!(submit_jobs -> packages (package_id));
+joinable!(submit_jobs -> submits (submit_id));
 joinable!(submits -> githashes (repo_hash_id));
 joinable!(submits -> images (requested_image_id));
 joinable!(submits -> packages (requested_package_id));
 
 allow_tables_to_appear_in_same_query!(
     artifacts,
+    audit,
     endpoints,
     envvars,
     githashes,
     images,
     job_envs,
+    job_phases,
+    job_state_transitions,
     jobs,
     packages,
+    release_promotions,
     release_stores,
     releases,
+    running_jobs,
+    submit_cancellations,
     submit_envs,
+    submit_jobs,
     submits,
 );
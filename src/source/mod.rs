@@ -8,19 +8,28 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
+use log::debug;
 use log::trace;
+use log::warn;
 use url::Url;
 
 use crate::package::Package;
 use crate::package::PackageName;
 use crate::package::PackageVersion;
 use crate::package::Source;
+use crate::package::SourceSignature;
+
+mod normalize;
+
+mod verification_cache;
+pub use verification_cache::VerificationCache;
 
 #[derive(Clone, Debug)]
 pub struct SourceCache {
@@ -35,6 +44,19 @@ impl SourceCache {
     pub fn sources_for(&self, p: &Package) -> Vec<SourceEntry> {
         SourceEntry::for_package(self.root.clone(), p)
     }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The path of the file the results of source verifications are cached in
+    pub fn verification_cache_path(&self) -> PathBuf {
+        self.root.join("verification-cache.json")
+    }
+
+    pub fn verification_cache(&self) -> VerificationCache {
+        VerificationCache::load(self.verification_cache_path())
+    }
 }
 
 #[derive(Debug)]
@@ -44,6 +66,7 @@ pub struct SourceEntry {
     package_version: PackageVersion,
     package_source_name: String,
     package_source: Source,
+    package_source_mirrors: Vec<String>,
 }
 
 impl SourceEntry {
@@ -63,6 +86,7 @@ impl SourceEntry {
                 package_version: package.version().clone(),
                 package_source_name: source_name,
                 package_source: source,
+                package_source_mirrors: package.source_mirrors().clone().unwrap_or_default(),
             })
             .collect()
     }
@@ -73,10 +97,99 @@ impl SourceEntry {
         })
     }
 
+    /// The path where the content of the source is stored, addressed by its hash
+    ///
+    /// `path()` links to this file, so sources with identical content that are used by multiple
+    /// packages are only stored once.
+    pub fn object_path(&self) -> PathBuf {
+        let hash = self.package_source.hash();
+        self.cache_root
+            .join("objects")
+            .join(hash.hashtype().to_string())
+            .join(hash.value().to_string())
+    }
+
+    /// Link `path()` to the content-addressed object, if the object exists
+    ///
+    /// Returns whether the link was created.
+    pub async fn link_object(&self) -> Result<bool> {
+        let (object, link) = (self.object_path(), self.path());
+        if !object.is_file() {
+            return Ok(false)
+        }
+
+        let dir = self.source_file_directory();
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .with_context(|| anyhow!("Creating directory: {}", dir.display()))?;
+
+        // A dangling link does not "exist", but still has to be replaced
+        if tokio::fs::symlink_metadata(&link).await.is_ok() {
+            tokio::fs::remove_file(&link).await?;
+        }
+
+        // The link is relative, so the cache root can be moved
+        let target = Path::new("..").join(object.strip_prefix(&self.cache_root)?);
+        trace!("Linking {} to {}", link.display(), target.display());
+        tokio::fs::symlink(&target, &link)
+            .await
+            .with_context(|| anyhow!("Linking {} to {}", link.display(), target.display()))?;
+        Ok(true)
+    }
+
+    pub fn package_name(&self) -> &PackageName {
+        &self.package_name
+    }
+
+    pub fn package_version(&self) -> &PackageVersion {
+        &self.package_version
+    }
+
+    /// The name of the source in the package
+    pub fn source_name(&self) -> &str {
+        &self.package_source_name
+    }
+
     pub fn url(&self) -> &Url {
         self.package_source.url()
     }
 
+    /// Get the URLs this source can be downloaded from, in the order they should be tried
+    ///
+    /// These are the mirrors of the package, the `global_mirrors` and finally the upstream URL.
+    /// Each mirror URL is returned together with the template it was rendered from, the upstream
+    /// URL has no template.
+    pub fn download_urls(&self, global_mirrors: &[String]) -> Result<Vec<(Option<String>, Url)>> {
+        let url = self.url();
+        let data = serde_json::json!({
+            "name": self.package_name,
+            "version": self.package_version,
+            "source_name": self.package_source_name,
+            "filename": url.path_segments().and_then(|segments| segments.last()).unwrap_or(""),
+            "host": url.host_str().unwrap_or(""),
+            "path": url.path().trim_start_matches('/'),
+            "url": url.as_str(),
+        });
+
+        let mut hb = handlebars::Handlebars::new();
+        hb.register_escape_fn(handlebars::no_escape);
+        hb.set_strict_mode(true);
+        crate::package::register_template_helpers(&mut hb);
+
+        self.package_source_mirrors
+            .iter()
+            .chain(global_mirrors.iter())
+            .map(|template| {
+                let rendered = hb.render_template(template, &data)
+                    .with_context(|| anyhow!("Rendering mirror URL template: {}", template))?;
+                let mirror_url = Url::parse(&rendered)
+                    .with_context(|| anyhow!("Parsing mirror URL: {}", rendered))?;
+                Ok((Some(template.clone()), mirror_url))
+            })
+            .chain(std::iter::once(Ok((None, url.clone()))))
+            .collect()
+    }
+
     pub fn download_manually(&self) -> bool {
         *self.package_source.download_manually()
     }
@@ -87,7 +200,8 @@ impl SourceEntry {
         Ok(())
     }
 
-    pub async fn verify_hash(&self) -> Result<()> {
+    /// Verify the hash of the source file, incrementing `progress` by the number of bytes hashed
+    pub async fn verify_hash(&self, progress: &indicatif::ProgressBar) -> Result<()> {
         let p = self.path();
         trace!("Verifying : {}", p.display());
 
@@ -103,13 +217,69 @@ impl SourceEntry {
         trace!("Reader constructed for path: {}", p.display());
         self.package_source
             .hash()
-            .matches_hash_of(reader)
+            .matches_hash_of(reader, progress)
             .await
     }
 
-    pub async fn create(&self) -> Result<tokio::fs::File> {
-        let p = self.path();
-        trace!("Creating source file: {}", p.display());
+    /// The upstream signature of the source, if the package declares one
+    pub fn signature(&self) -> Option<&SourceSignature> {
+        self.package_source.signature().as_ref()
+    }
+
+    /// The path where the upstream signature of the source is stored
+    pub fn signature_path(&self) -> PathBuf {
+        self.path().with_extension("source.sig")
+    }
+
+    /// Verify the source file against its upstream signature with `gpgv`
+    ///
+    /// Sources without a signature are not checked.
+    pub async fn verify_signature(&self) -> Result<()> {
+        let signature = match self.signature() {
+            Some(signature) => signature,
+            None => return Ok(()),
+        };
+
+        let sig_path = self.signature_path();
+        if !sig_path.exists() {
+            return Err(anyhow!("Signature missing: {}", sig_path.display()))
+        }
+
+        trace!("Verifying {} with signature {}", self.path().display(), sig_path.display());
+        let output = tokio::process::Command::new("gpgv")
+            .arg("--keyring")
+            .arg(signature.keyring())
+            .arg(&sig_path)
+            .arg(self.path())
+            .output()
+            .await
+            .context("Starting gpgv")?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("Bad signature {}: {}", sig_path.display(), String::from_utf8_lossy(&output.stderr).trim()))
+        }
+    }
+
+    /// The path where a download of the source is stored while it is not finished
+    pub fn partial_path(&self) -> PathBuf {
+        self.path().with_extension("source.part")
+    }
+
+    /// The path where the URL the partial download is downloaded from is stored
+    pub fn partial_origin_path(&self) -> PathBuf {
+        self.path().with_extension("source.part.url")
+    }
+
+    /// Open the file for a (partial) download of the source from `url`, for appending
+    ///
+    /// Returns the file and the number of bytes that were already downloaded, so the download can
+    /// be resumed. A partial download from another URL is discarded, because the bytes of two
+    /// servers must not be mixed.
+    pub async fn open_partial(&self, url: &Url) -> Result<(tokio::fs::File, u64)> {
+        let p = self.partial_path();
+        trace!("Opening partial source file: {}", p.display());
 
         if !self.cache_root.is_dir() {
             trace!("Cache root does not exist: {}", self.cache_root.display());
@@ -136,14 +306,203 @@ impl SourceEntry {
             }
         }
 
-        trace!("Creating file now: {}", p.display());
-        tokio::fs::OpenOptions::new()
+        let origin = self.partial_origin_path();
+        let same_origin = tokio::fs::read_to_string(&origin)
+            .await
+            .map(|o| o == url.as_str())
+            .unwrap_or(false);
+        if !same_origin {
+            if p.exists() {
+                debug!("Discarding partial download {}, it is not from {}", p.display(), url);
+                tokio::fs::remove_file(&p)
+                    .await
+                    .with_context(|| anyhow!("Removing file: {}", p.display()))?;
+            }
+            tokio::fs::write(&origin, url.as_str())
+                .await
+                .with_context(|| anyhow!("Writing file: {}", origin.display()))?;
+        }
+
+        trace!("Opening file now: {}", p.display());
+        let file = tokio::fs::OpenOptions::new()
             .create(true)
-            .create_new(true)
-            .write(true)
+            .append(true)
             .open(&p)
             .await
-            .with_context(|| anyhow!("Creating file: {}", p.display()))
-            .map_err(Error::from)
+            .with_context(|| anyhow!("Opening file: {}", p.display()))?;
+
+        let len = file.metadata()
+            .await
+            .with_context(|| anyhow!("Getting metadata of file: {}", p.display()))?
+            .len();
+
+        Ok((file, len))
+    }
+
+    /// Move the finished download from the partial path to the content-addressed object and link
+    /// it to the final path
+    ///
+    /// If the download does not match the expected hash, it is moved to the final path directly,
+    /// so it is not shared with other packages and `verify` reports the mismatch.
+    pub async fn finish_partial(&self) -> Result<()> {
+        let from = self.partial_path();
+        self.remove_partial_origin().await?;
+        if *self.package_source.normalize() {
+            // The upstream signature is of the archive as it was downloaded
+            if self.signature().is_some() {
+                return Err(anyhow!("Source {} of {} {} has a signature, it cannot be normalized",
+                    self.package_source_name, self.package_name, self.package_version))
+            }
+
+            trace!("Normalizing {}", from.display());
+            let path = from.clone();
+            tokio::task::spawn_blocking(move || normalize::normalize(&path)).await??;
+        }
+
+        let reader = tokio::fs::File::open(&from)
+            .await
+            .map(tokio::io::BufReader::new)
+            .with_context(|| anyhow!("Opening file: {}", from.display()))?;
+
+        let hidden = indicatif::ProgressBar::hidden();
+        if let Err(e) = self.package_source.hash().matches_hash_of(reader, &hidden).await {
+            warn!("Not storing {} by its hash: {}", self.path().display(), e);
+            let to = self.path();
+            trace!("Moving {} to {}", from.display(), to.display());
+            return tokio::fs::rename(&from, &to)
+                .await
+                .with_context(|| anyhow!("Moving {} to {}", from.display(), to.display()))
+                .map_err(Error::from)
+        }
+
+        let to = self.object_path();
+        if let Some(dir) = to.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .with_context(|| anyhow!("Creating directory: {}", dir.display()))?;
+        }
+        trace!("Moving {} to {}", from.display(), to.display());
+        tokio::fs::rename(&from, &to)
+            .await
+            .with_context(|| anyhow!("Moving {} to {}", from.display(), to.display()))?;
+
+        self.link_object().await.map(|_| ())
+    }
+
+    pub async fn remove_partial(&self) -> Result<()> {
+        let p = self.partial_path();
+        if p.exists() {
+            tokio::fs::remove_file(&p).await?;
+        }
+        self.remove_partial_origin().await
+    }
+
+    async fn remove_partial_origin(&self) -> Result<()> {
+        let p = self.partial_origin_path();
+        if p.exists() {
+            tokio::fs::remove_file(&p).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::tests::package;
+
+    #[test]
+    fn test_download_urls_with_mirrors() {
+        let pkg = package("a", "1.0", "https://example.com/releases/a-1.0.tar.gz", "123");
+        let entry = SourceEntry::for_package(PathBuf::from("/tmp"), &pkg).pop().unwrap();
+
+        let mirrors = vec![
+            String::from("https://mirror.example.org/{{name}}/{{version}}/{{filename}}"),
+            String::from("https://other.example.org/{{host}}/{{path}}"),
+        ];
+
+        let urls = entry.download_urls(&mirrors).unwrap();
+        assert_eq!(urls.len(), 3);
+        assert_eq!(urls[0].0.as_ref(), Some(&mirrors[0]));
+        assert_eq!(urls[0].1.as_str(), "https://mirror.example.org/a/1.0/a-1.0.tar.gz");
+        assert_eq!(urls[1].1.as_str(), "https://other.example.org/example.com/releases/a-1.0.tar.gz");
+        assert_eq!(urls[2].0, None);
+        assert_eq!(urls[2].1.as_str(), "https://example.com/releases/a-1.0.tar.gz");
+    }
+
+    #[test]
+    fn test_download_urls_without_mirrors() {
+        let pkg = package("a", "1.0", "https://example.com/a.tar.gz", "123");
+        let entry = SourceEntry::for_package(PathBuf::from("/tmp"), &pkg).pop().unwrap();
+
+        let urls = entry.download_urls(&[]).unwrap();
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].1.as_str(), "https://example.com/a.tar.gz");
+    }
+
+    #[test]
+    fn test_download_urls_unknown_variable() {
+        let pkg = package("a", "1.0", "https://example.com/a.tar.gz", "123");
+        let entry = SourceEntry::for_package(PathBuf::from("/tmp"), &pkg).pop().unwrap();
+
+        assert!(entry.download_urls(&[String::from("https://mirror/{{unknown}}")]).is_err());
+    }
+
+    #[test]
+    fn test_identical_sources_are_stored_once() {
+        let root = tempfile::tempdir().unwrap();
+
+        // sha1 of "abc"
+        let hash = "a9993e364706816aba3e25717850c26c9cd0d89d";
+        let a = package("a", "1.0", "https://example.com/a-1.0.tar.gz", hash);
+        let b = package("a", "1.1", "https://example.com/a-1.0.tar.gz", hash);
+        let sc = SourceCache::new(root.path().to_path_buf());
+        let entry_a = sc.sources_for(&a).pop().unwrap();
+        let entry_b = sc.sources_for(&b).pop().unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            assert!(!entry_b.link_object().await.unwrap());
+
+            {
+                use tokio::io::AsyncWriteExt;
+                let url = Url::parse("https://example.com/a-1.0.tar.gz").unwrap();
+                let (mut file, len) = entry_a.open_partial(&url).await.unwrap();
+                assert_eq!(len, 0);
+                file.write_all(b"abc").await.unwrap();
+                file.flush().await.unwrap();
+            }
+            entry_a.finish_partial().await.unwrap();
+
+            assert!(entry_b.link_object().await.unwrap());
+        });
+
+        assert_eq!(entry_a.object_path(), entry_b.object_path());
+        assert!(entry_a.object_path().is_file());
+        assert!(!entry_a.partial_path().exists());
+        assert!(!entry_a.partial_origin_path().exists());
+        assert_eq!(std::fs::read(entry_a.path()).unwrap(), b"abc");
+        assert_eq!(std::fs::read(entry_b.path()).unwrap(), b"abc");
+        assert!(std::fs::symlink_metadata(entry_b.path()).unwrap().file_type().is_symlink());
+    }
+
+    #[tokio::test]
+    async fn test_partial_download_from_other_url_is_discarded() {
+        use tokio::io::AsyncWriteExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let pkg = package("a", "1.0", "https://example.com/a-1.0.tar.gz", "123");
+        let entry = SourceCache::new(root.path().to_path_buf()).sources_for(&pkg).pop().unwrap();
+        let mirror = Url::parse("https://mirror.example.org/a-1.0.tar.gz").unwrap();
+        let upstream = Url::parse("https://example.com/a-1.0.tar.gz").unwrap();
+
+        let (mut file, len) = entry.open_partial(&mirror).await.unwrap();
+        assert_eq!(len, 0);
+        file.write_all(b"abc").await.unwrap();
+        file.flush().await.unwrap();
+
+        assert_eq!(entry.open_partial(&mirror).await.unwrap().1, 3);
+        assert_eq!(entry.open_partial(&upstream).await.unwrap().1, 0);
+        assert_eq!(std::fs::read_to_string(entry.partial_origin_path()).unwrap(), upstream.as_str());
     }
 }
@@ -0,0 +1,244 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Normalization of downloaded source archives
+//!
+//! Archives with the same content can differ in their bytes, e.g. if they are generated on
+//! demand (like the archives of a git forge) or repacked by a mirror. A normalized archive only
+//! depends on the paths, the contents, the link targets and whether files are executable, so
+//! such archives have the same hash after the normalization.
+
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use log::debug;
+use log::trace;
+use tar::EntryType;
+
+/// A member of the archive that is normalized, and where its content is in the (uncompressed) tar
+struct Member {
+    path: PathBuf,
+    entry_type: EntryType,
+    link_name: Option<PathBuf>,
+    executable: bool,
+    position: u64,
+    size: u64,
+}
+
+/// Normalize the tar archive (optionally gzip-compressed) at `path` in place
+///
+/// The members are sorted by path (hard links last); their modification times, owners and groups are set to zero;
+/// files get the mode 0644 (0755 if they were executable), directories 0755. Extended headers
+/// like the pax global header of git archives are dropped. A compressed archive is compressed
+/// again with fixed settings.
+pub fn normalize(path: &Path) -> Result<()> {
+    let compressed = {
+        let mut magic = [0u8; 2];
+        let mut file = std::fs::File::open(path).with_context(|| anyhow!("Opening {}", path.display()))?;
+        file.read_exact(&mut magic).with_context(|| anyhow!("Reading {}", path.display()))?;
+        magic == [0x1f, 0x8b]
+    };
+
+    // The members are copied from the uncompressed archive in another order, so the
+    // uncompressed archive has to be seekable
+    let tar_path = path.with_extension("normalize.tar");
+    let result = if compressed {
+        trace!("Decompressing {} to {}", path.display(), tar_path.display());
+        decompress(path, &tar_path).and_then(|_| normalize_tar(&tar_path, path, true))
+    } else {
+        normalize_tar(path, path, false)
+    };
+    if tar_path.exists() {
+        let _ = std::fs::remove_file(&tar_path);
+    }
+    result.with_context(|| anyhow!("Normalizing {}", path.display()))
+}
+
+fn decompress(from: &Path, to: &Path) -> Result<()> {
+    let mut decoder = flate2::read::MultiGzDecoder::new(BufReader::new(std::fs::File::open(from)?));
+    let mut out = BufWriter::new(std::fs::File::create(to)?);
+    std::io::copy(&mut decoder, &mut out).context("Decompressing failed, not a gzip archive")?;
+    out.flush().map_err(anyhow::Error::from)
+}
+
+/// Write the normalized archive of the tar archive `tar_path` to `target`, gzip-compressed if
+/// `compress` is set
+fn normalize_tar(tar_path: &Path, target: &Path, compress: bool) -> Result<()> {
+    let mut members = read_members(tar_path)?;
+    // Hard links come last, as their targets have to be extracted before them
+    members.sort_by(|a, b| {
+        (a.entry_type == EntryType::Link, &a.path).cmp(&(b.entry_type == EntryType::Link, &b.path))
+    });
+
+    let normalized_path = target.with_extension("normalized");
+    {
+        let out = BufWriter::new(std::fs::File::create(&normalized_path)?);
+        if compress {
+            let encoder = flate2::GzBuilder::new()
+                .mtime(0)
+                .operating_system(255)
+                .write(out, flate2::Compression::default());
+            write_members(tar_path, &members, encoder)?
+                .finish()?
+                .flush()?;
+        } else {
+            write_members(tar_path, &members, out)?.flush()?;
+        }
+    }
+
+    std::fs::rename(&normalized_path, target)
+        .with_context(|| anyhow!("Moving {} to {}", normalized_path.display(), target.display()))
+}
+
+fn read_members(tar_path: &Path) -> Result<Vec<Member>> {
+    let mut archive = tar::Archive::new(BufReader::new(std::fs::File::open(tar_path)?));
+    let mut members = vec![];
+    for entry in archive.entries().context("Not a tar archive")? {
+        let entry = entry.context("Reading tar archive failed")?;
+        let header = entry.header();
+        let entry_type = match header.entry_type() {
+            EntryType::Regular | EntryType::Continuous => EntryType::Regular,
+            t @ EntryType::Directory | t @ EntryType::Symlink | t @ EntryType::Link => t,
+            EntryType::XGlobalHeader | EntryType::XHeader => {
+                debug!("Dropping extended header {}", String::from_utf8_lossy(&entry.path_bytes()));
+                continue
+            },
+            other => {
+                return Err(anyhow!("Cannot normalize member {} of type {:?}", String::from_utf8_lossy(&entry.path_bytes()), other))
+            },
+        };
+
+        members.push(Member {
+            path: entry.path()?.into_owned(),
+            entry_type,
+            link_name: entry.link_name()?.map(|link| link.into_owned()),
+            executable: header.mode()? & 0o111 != 0,
+            position: entry.raw_file_position(),
+            size: if entry_type == EntryType::Regular { entry.size() } else { 0 },
+        });
+    }
+
+    if members.is_empty() {
+        return Err(anyhow!("Not a tar archive, no members found"))
+    }
+    Ok(members)
+}
+
+fn write_members<W: Write>(tar_path: &Path, members: &[Member], out: W) -> Result<W> {
+    let mut input = BufReader::new(std::fs::File::open(tar_path)?);
+    let mut builder = tar::Builder::new(out);
+    for member in members {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(member.entry_type);
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_size(member.size);
+        header.set_mode(match member.entry_type {
+            EntryType::Directory => 0o755,
+            EntryType::Symlink => 0o777,
+            _ if member.executable => 0o755,
+            _ => 0o644,
+        });
+
+        match member.link_name.as_ref() {
+            Some(link_name) => builder.append_link(&mut header, &member.path, link_name)?,
+            None => {
+                input.seek(SeekFrom::Start(member.position))?;
+                builder.append_data(&mut header, &member.path, (&mut input).take(member.size))?
+            },
+        }
+    }
+    builder.into_inner().map_err(anyhow::Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tar archive with the `files`, with the modification time, owner and mode of the members
+    fn archive(files: &[(&str, &[u8])], mtime: u64, uid: u64, mode: u32) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (path, content) in files {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(content.len() as u64);
+            header.set_mtime(mtime);
+            header.set_uid(uid);
+            header.set_mode(mode);
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn gzip(data: &[u8], mtime: u32) -> Vec<u8> {
+        let mut encoder = flate2::GzBuilder::new()
+            .mtime(mtime)
+            .filename("a.tar")
+            .write(vec![], flate2::Compression::best());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn normalized(name: &str, data: &[u8]) -> Vec<u8> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(format!("{}.source.part", name));
+        std::fs::write(&path, data).unwrap();
+        normalize(&path).unwrap();
+        std::fs::read(&path).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_tar() {
+        let a = archive(&[("a/x", b"x"), ("a/y", b"y")], 1600000000, 1000, 0o664);
+        let b = archive(&[("a/y", b"y"), ("a/x", b"x")], 1700000000, 0, 0o644);
+        assert_ne!(a, b);
+        assert_eq!(normalized("a", &a), normalized("b", &b));
+
+        let mut members = tar::Archive::new(&normalized("a", &a)[..])
+            .entries()
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                (e.path().unwrap().display().to_string(), e.header().mtime().unwrap(), e.header().mode().unwrap())
+            })
+            .collect::<Vec<_>>();
+        members.sort();
+        assert_eq!(members, vec![("a/x".to_string(), 0, 0o644), ("a/y".to_string(), 0, 0o644)]);
+    }
+
+    #[test]
+    fn test_normalize_gzip() {
+        let a = gzip(&archive(&[("a/x", b"x"), ("a/y", b"y")], 1600000000, 1000, 0o755), 1600000000);
+        let b = gzip(&archive(&[("a/y", b"y"), ("a/x", b"x")], 1700000000, 0, 0o700), 1700000000);
+        assert_ne!(a, b);
+        assert_eq!(normalized("c", &a), normalized("d", &b));
+
+        // The content differs, as the files are executable
+        let c = gzip(&archive(&[("a/x", b"x"), ("a/y", b"y")], 1600000000, 1000, 0o644), 1600000000);
+        assert_ne!(normalized("c", &a), normalized("e", &c));
+    }
+
+    #[test]
+    fn test_normalize_not_an_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("f.source.part");
+        std::fs::write(&path, b"not an archive at all, just some text").unwrap();
+        assert!(normalize(&path).is_err());
+    }
+}
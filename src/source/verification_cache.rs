@@ -0,0 +1,173 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Cache of the results of the hash verification of sources
+//!
+//! Hashing large sources takes a long time, so a source that was verified before is only hashed
+//! again if it changed. A source is considered unchanged if the size and modification time of
+//! its file and the expected hash are the same as when it was verified.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use log::trace;
+use log::warn;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::source::SourceEntry;
+
+/// The state of a source file when it was verified
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+struct Entry {
+    size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+
+    /// The expected hash as "type:value"
+    hash: String,
+}
+
+impl Entry {
+    fn new(meta: &std::fs::Metadata, hash: String) -> Option<Self> {
+        let modified = meta.modified()
+            .ok()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?;
+
+        Some(Entry {
+            size: meta.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+            hash,
+        })
+    }
+
+    fn of_source(source: &SourceEntry, meta: &std::fs::Metadata) -> Option<Self> {
+        let hash = source.package_source.hash();
+        Entry::new(meta, format!("{}:{}", hash.hashtype(), hash.value()))
+    }
+}
+
+/// The sources that were verified successfully, stored as JSON in a file in the source cache
+#[derive(Debug)]
+pub struct VerificationCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+impl VerificationCache {
+    /// Load the cache from `path`
+    ///
+    /// A missing or unreadable file is an empty cache, the sources are verified again then.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                warn!("Ignoring the verification cache {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) => {
+                trace!("No verification cache at {}: {}", path.display(), e);
+                HashMap::new()
+            },
+        };
+
+        VerificationCache { path, entries: Mutex::new(entries) }
+    }
+
+    /// Whether `source`, whose file has the metadata `meta`, was verified and did not change since
+    pub fn is_verified(&self, source: &SourceEntry, meta: &std::fs::Metadata) -> bool {
+        Entry::of_source(source, meta)
+            .map(|entry| self.contains(&source.path(), &entry))
+            .unwrap_or(false)
+    }
+
+    /// Record that `source`, whose file has the metadata `meta`, was verified successfully
+    pub fn insert_verified(&self, source: &SourceEntry, meta: &std::fs::Metadata) {
+        if let Some(entry) = Entry::of_source(source, meta) {
+            self.insert(source.path(), entry);
+        }
+    }
+
+    /// Forget `source`, e.g. because it did not match its hash
+    pub fn remove(&self, source: &SourceEntry) {
+        self.entries.lock().unwrap().remove(&source.path());
+    }
+
+    fn contains(&self, path: &Path, entry: &Entry) -> bool {
+        self.entries.lock().unwrap().get(path) == Some(entry)
+    }
+
+    fn insert(&self, path: PathBuf, entry: Entry) {
+        self.entries.lock().unwrap().insert(path, entry);
+    }
+
+    /// Write the cache to its file
+    pub async fn store(&self) -> Result<()> {
+        let content = {
+            let entries = self.entries.lock().unwrap();
+            serde_json::to_vec(&*entries)?
+        };
+
+        // Written next to the file and moved, so that the cache is never written partially
+        let tmp_path = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, content)
+            .await
+            .with_context(|| anyhow!("Writing {}", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, &self.path)
+            .await
+            .with_context(|| anyhow!("Moving {} to {}", tmp_path.display(), self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_changed_files_are_not_verified() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let file = dir.join("a.source");
+        std::fs::write(&file, b"content").unwrap();
+
+        let cache = VerificationCache::load(dir.join("verification-cache.json"));
+        let entry = Entry::new(&file.metadata().unwrap(), String::from("sha256:abc")).unwrap();
+        assert!(!cache.contains(&file, &entry));
+        cache.insert(file.clone(), entry.clone());
+        cache.store().await.unwrap();
+
+        let cache = VerificationCache::load(dir.join("verification-cache.json"));
+        assert!(cache.contains(&file, &entry));
+
+        // Another expected hash
+        let other_hash = Entry::new(&file.metadata().unwrap(), String::from("sha256:def")).unwrap();
+        assert!(!cache.contains(&file, &other_hash));
+
+        // Another content
+        std::fs::write(&file, b"other content").unwrap();
+        let changed = Entry::new(&file.metadata().unwrap(), String::from("sha256:abc")).unwrap();
+        assert!(!cache.contains(&file, &changed));
+    }
+
+    #[test]
+    fn test_broken_cache_is_empty() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"{ not json").unwrap();
+        assert!(VerificationCache::load(file.path().to_path_buf()).entries.lock().unwrap().is_empty());
+    }
+}
@@ -67,6 +67,52 @@ pub fn script_to_printable(
     Ok(script)
 }
 
+/// Render a line-based unified diff between `old` and `new`
+///
+/// Removed lines are printed red, added lines green and the hunk headers cyan, if `colored` is
+/// set.
+pub fn diff_to_printable(old: &str, new: &str, old_header: &str, new_header: &str, colored: bool) -> String {
+    use colored::Colorize;
+    use similar::ChangeTag;
+    use similar::TextDiff;
+
+    let diff = TextDiff::from_lines(old, new);
+    let mut out = String::new();
+
+    out.push_str(&format!("--- {}\n+++ {}\n", old_header, new_header));
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        let header = hunk.header().to_string();
+        if colored {
+            out.push_str(&format!("{}\n", header.cyan()));
+        } else {
+            out.push_str(&format!("{}\n", header));
+        }
+
+        for change in hunk.iter_changes() {
+            let (sign, line) = (change.tag(), change.to_string_lossy());
+            let line = line.trim_end_matches('\n');
+            let line = match sign {
+                ChangeTag::Delete => format!("-{}", line),
+                ChangeTag::Insert => format!("+{}", line),
+                ChangeTag::Equal => format!(" {}", line),
+            };
+
+            if colored {
+                match sign {
+                    ChangeTag::Delete => out.push_str(&format!("{}", line.red())),
+                    ChangeTag::Insert => out.push_str(&format!("{}", line.green())),
+                    ChangeTag::Equal => out.push_str(&line),
+                }
+            } else {
+                out.push_str(&line);
+            }
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
 pub fn find_linter_command(repo_path: &Path, config: &Configuration) -> Result<Option<PathBuf>> {
     match config.script_linter().as_ref() {
         None => Ok(None),
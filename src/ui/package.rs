@@ -28,6 +28,7 @@ pub struct PackagePrintFlags {
     pub print_sources: bool,
     pub print_dependencies: bool,
     pub print_patches: bool,
+    pub print_maintainers: bool,
     pub print_env: bool,
     pub print_flags: bool,
     pub print_allowed_images: bool,
@@ -49,6 +50,7 @@ impl PackagePrintFlags {
             self.print_sources
                 || self.print_dependencies
                 || self.print_patches
+                || self.print_maintainers
                 || self.print_env
                 || self.print_flags
                 || self.print_allowed_images
@@ -140,6 +142,10 @@ impl<'a, P: Borrow<Package>> PreparePrintPackage<'a, P> {
             "print_patches",
             serde_json::Value::Bool(self.flags.print_all || self.flags.print_patches),
         );
+        data.insert(
+            "print_maintainers",
+            serde_json::Value::Bool(self.flags.print_all || self.flags.print_maintainers),
+        );
         data.insert(
             "print_env",
             serde_json::Value::Bool(self.flags.print_all || self.flags.print_env),
@@ -0,0 +1,232 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Completing commandline arguments for the scripts of the "completions" subcommand
+//!
+//! The completion scripts pass the words of the commandline to `butido __complete`, which uses
+//! the definition of the CLI to find out what is completed.
+
+use clap::App;
+use clap::AppSettings;
+use clap::Arg;
+use clap::ArgSettings;
+
+/// Values that are only known from the configuration or the repository
+#[derive(Debug, Eq, PartialEq)]
+pub enum DynamicValues {
+    Packages,
+
+    /// The versions of a package, if the package was already given on the commandline
+    Versions(Option<String>),
+
+    Endpoints,
+    Images,
+}
+
+/// What can be completed for the last word of a commandline
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Completion {
+    /// The (partial) word that is completed
+    pub prefix: String,
+
+    /// Subcommands, flags or possible values of the completed argument
+    pub candidates: Vec<String>,
+
+    /// Values that have to be loaded before they can be offered
+    pub dynamic: Option<DynamicValues>,
+}
+
+fn is_package_arg(arg: &Arg) -> bool {
+    matches!(arg.get_name(), "package_name" | "package")
+}
+
+fn dynamic_values(arg: &Arg, package: Option<&String>) -> Option<DynamicValues> {
+    match arg.get_name() {
+        "package_name" | "package" => Some(DynamicValues::Packages),
+        "package_version" | "version_a" | "version_b" => Some(DynamicValues::Versions(package.cloned())),
+        "endpoint" | "endpoint_name" => Some(DynamicValues::Endpoints),
+        "image" | "filter_image" => Some(DynamicValues::Images),
+        _ => None,
+    }
+}
+
+fn find_option<'a, 'help>(app: &'a App<'help>, word: &str) -> Option<&'a Arg<'help>> {
+    if let Some(long) = word.strip_prefix("--") {
+        let long = long.split('=').next().unwrap_or(long);
+        app.get_arguments().find(|arg| arg.get_long() == Some(long))
+    } else {
+        let mut chars = word.chars().skip(1);
+        match (chars.next(), chars.next()) {
+            (Some(short), None) => app.get_arguments().find(|arg| arg.get_short() == Some(short)),
+            _ => None,
+        }
+    }
+}
+
+fn nth_positional<'a, 'help>(app: &'a App<'help>, n: usize) -> Option<&'a Arg<'help>> {
+    let mut positionals = app.get_positionals().collect::<Vec<_>>();
+    positionals.sort_by_key(|arg| arg.get_index());
+
+    positionals.get(n).copied().or_else(|| {
+        // An argument with multiple values also takes all further positional values
+        positionals.last().copied().filter(|arg| arg.is_set(ArgSettings::MultipleValues))
+    })
+}
+
+fn values_of(arg: &Arg, package: Option<&String>) -> (Vec<String>, Option<DynamicValues>) {
+    let candidates = arg.get_possible_values()
+        .map(|values| values.iter().map(|v| v.to_string()).collect())
+        .unwrap_or_default();
+    (candidates, dynamic_values(arg, package))
+}
+
+/// Find what can be completed for the last of `words`, which are the arguments after the program
+/// name
+pub fn complete(app: &App, words: &[String]) -> Completion {
+    let (prefix, done) = match words.split_last() {
+        Some((prefix, done)) => (prefix.clone(), done),
+        None => (String::new(), words),
+    };
+
+    let mut app = app;
+    let mut positionals = 0;
+    let mut package = None;
+    let mut pending_option = None;
+    let mut only_positionals = false;
+
+    for word in done {
+        if let Some(option) = pending_option.take() {
+            if is_package_arg(option) {
+                package = Some(word.clone());
+            }
+            continue
+        }
+
+        if !only_positionals && word == "--" {
+            only_positionals = true;
+        } else if !only_positionals && word.starts_with('-') && word.len() > 1 {
+            if let Some(option) = find_option(app, word).filter(|o| o.is_set(ArgSettings::TakesValue)) {
+                match word.split_once('=') {
+                    Some((_, value)) if is_package_arg(option) => package = Some(value.to_string()),
+                    Some(_) => {},
+                    None => pending_option = Some(option),
+                }
+            }
+        } else if let Some(subcommand) = app.find_subcommand(word.as_str()).filter(|_| !only_positionals) {
+            app = subcommand;
+            positionals = 0;
+        } else {
+            if nth_positional(app, positionals).map(is_package_arg).unwrap_or(false) {
+                package = Some(word.clone());
+            }
+            positionals += 1;
+        }
+    }
+
+    if let Some(option) = pending_option {
+        let (candidates, dynamic) = values_of(option, package.as_ref());
+        return Completion { prefix, candidates, dynamic }
+    }
+
+    if prefix.starts_with('-') && !only_positionals {
+        let candidates = app.get_arguments()
+            .filter(|arg| !arg.is_set(ArgSettings::Hidden))
+            .flat_map(|arg| {
+                let long = arg.get_long().map(|l| format!("--{}", l));
+                let short = arg.get_short().map(|s| format!("-{}", s));
+                long.into_iter().chain(short)
+            })
+            .collect();
+        return Completion { prefix, candidates, dynamic: None }
+    }
+
+    let (mut candidates, dynamic) = nth_positional(app, positionals)
+        .map(|arg| values_of(arg, package.as_ref()))
+        .unwrap_or_default();
+
+    if !only_positionals {
+        candidates.extend({
+            app.get_subcommands()
+                .filter(|sub| !sub.is_set(AppSettings::Hidden))
+                .map(|sub| sub.get_name().to_string())
+        });
+    }
+
+    Completion { prefix, candidates, dynamic }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> App<'static> {
+        App::new("butido")
+            .subcommand(App::new("build")
+                .arg(Arg::new("package_name").index(1))
+                .arg(Arg::new("package_version").index(2))
+                .arg(Arg::new("image").long("image").short('I').takes_value(true))
+                .arg(Arg::new("write-log").long("write-log"))
+            )
+            .subcommand(App::new("endpoint")
+                .arg(Arg::new("endpoint_name").index(1))
+                .subcommand(App::new("ping"))
+            )
+            .subcommand(App::new("__complete").setting(AppSettings::Hidden))
+    }
+
+    fn complete_words(words: &[&str]) -> Completion {
+        complete(&app(), &words.iter().map(|w| w.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_complete_subcommands() {
+        let c = complete_words(&["b"]);
+        assert_eq!(c.prefix, "b");
+        assert_eq!(c.candidates, vec!["build", "endpoint"]);
+        assert_eq!(c.dynamic, None);
+    }
+
+    #[test]
+    fn test_complete_positionals() {
+        assert_eq!(complete_words(&["build", ""]).dynamic, Some(DynamicValues::Packages));
+        assert_eq!(
+            complete_words(&["build", "-I", "debian", "foo", "1."]).dynamic,
+            Some(DynamicValues::Versions(Some(String::from("foo"))))
+        );
+
+        let c = complete_words(&["endpoint", ""]);
+        assert_eq!(c.candidates, vec!["ping"]);
+        assert_eq!(c.dynamic, Some(DynamicValues::Endpoints));
+    }
+
+    #[test]
+    fn test_complete_options() {
+        assert_eq!(complete_words(&["build", "--image", "deb"]).dynamic, Some(DynamicValues::Images));
+        assert_eq!(complete_words(&["build", "--"]).candidates, vec!["--image", "-I", "--write-log"]);
+        assert_eq!(complete_words(&["build", "--write-log", ""]).dynamic, Some(DynamicValues::Packages));
+    }
+
+    #[test]
+    fn test_complete_butido_cli() {
+        let words = |w: &[&str]| w.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        let cli = crate::cli::cli();
+
+        assert_eq!(complete(&cli, &words(&["build", "-I", ""])).dynamic, Some(DynamicValues::Images));
+        assert_eq!(
+            complete(&cli, &words(&["script-diff", "foo", ""])).dynamic,
+            Some(DynamicValues::Versions(Some(String::from("foo"))))
+        );
+        assert!(!complete(&cli, &words(&[""])).candidates.contains(&String::from("__complete")));
+
+        let matches = cli.get_matches_from(vec!["butido", "__complete", "--", "build", "--image", ""]);
+        let values = matches.subcommand_matches("__complete").unwrap().values_of("words").unwrap().collect::<Vec<_>>();
+        assert_eq!(values, vec!["build", "--image", ""]);
+    }
+}
@@ -0,0 +1,181 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Cron-like schedules, like "30 2 * * 1-5"
+
+use std::collections::BTreeSet;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::Datelike;
+use chrono::Duration;
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
+use chrono::Timelike;
+
+/// A schedule in the syntax of crontab entries: "MINUTE HOUR DAY-OF-MONTH MONTH DAY-OF-WEEK"
+///
+/// Every field is "*", a number, a range ("1-5"), a list ("1,15") or any of these with a step
+/// ("*/15", "0-30/10"). Sunday is 0 (or 7) in the day of the week. As in cron, a time matches if
+/// the day of the month or the day of the week matches, if both are restricted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CronSchedule {
+    minutes: BTreeSet<u32>,
+    hours: BTreeSet<u32>,
+    days_of_month: BTreeSet<u32>,
+    months: BTreeSet<u32>,
+    days_of_week: BTreeSet<u32>,
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(s: &str) -> Result<Self> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(anyhow!("Expected 5 fields in schedule '{}', found {}", s, fields.len()))
+        }
+
+        let field = |i: usize, name: &str, min: u32, max: u32| {
+            parse_field(fields[i], min, max).with_context(|| anyhow!("Parsing {} in schedule '{}'", name, s))
+        };
+        let days_of_week = field(4, "day of week", 0, 7)?
+            .into_iter()
+            .map(|d| d % 7)
+            .collect();
+
+        Ok(CronSchedule {
+            minutes: field(0, "minute", 0, 59)?,
+            hours: field(1, "hour", 0, 23)?,
+            days_of_month: field(2, "day of month", 1, 31)?,
+            months: field(3, "month", 1, 12)?,
+            days_of_week,
+            days_of_month_restricted: fields[2] != "*",
+            days_of_week_restricted: fields[4] != "*",
+        })
+    }
+
+    pub fn matches(&self, time: &NaiveDateTime) -> bool {
+        self.day_matches(&time.date())
+            && self.minutes.contains(&time.minute())
+            && self.hours.contains(&time.hour())
+    }
+
+    /// The first time after `time` that matches, if there is one in the next four years
+    pub fn next_after(&self, time: &NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = time.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let end = start + Duration::days(4 * 366);
+
+        let mut candidate = start;
+        while candidate < end {
+            if !self.day_matches(&candidate.date()) {
+                candidate = (candidate.date() + Duration::days(1)).and_hms_opt(0, 0, 0)?;
+            } else if !self.hours.contains(&candidate.hour()) {
+                candidate = candidate.with_minute(0)? + Duration::hours(1);
+            } else if !self.minutes.contains(&candidate.minute()) {
+                candidate += Duration::minutes(1);
+            } else {
+                return Some(candidate)
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, date: &NaiveDate) -> bool {
+        let day_of_month = self.days_of_month.contains(&date.day());
+        let day_of_week = self.days_of_week.contains(&date.weekday().num_days_from_sunday());
+        let day = match (self.days_of_month_restricted, self.days_of_week_restricted) {
+            (true, true) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        };
+        day && self.months.contains(&date.month())
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<BTreeSet<u32>> {
+    let mut values = BTreeSet::new();
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().with_context(|| anyhow!("Invalid step '{}'", step))?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(anyhow!("Invalid step 0"))
+        }
+
+        let (from, to) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((from, to)) => (parse_value(from, min, max)?, parse_value(to, min, max)?),
+                None if step != 1 => (parse_value(range, min, max)?, max),
+                None => {
+                    let value = parse_value(range, min, max)?;
+                    (value, value)
+                },
+            },
+        };
+        if from > to {
+            return Err(anyhow!("Invalid range '{}'", range))
+        }
+        values.extend((from..=to).step_by(step as usize));
+    }
+    Ok(values)
+}
+
+fn parse_value(value: &str, min: u32, max: u32) -> Result<u32> {
+    match value.parse::<u32>() {
+        Ok(v) if (min..=max).contains(&v) => Ok(v),
+        _ => Err(anyhow!("Expected a number from {} to {} instead of '{}'", min, max, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let s = CronSchedule::parse("*/15 2,14 1-7 * 1-5").unwrap();
+        assert_eq!(s.minutes.iter().copied().collect::<Vec<_>>(), vec![0, 15, 30, 45]);
+        assert_eq!(s.hours.iter().copied().collect::<Vec<_>>(), vec![2, 14]);
+        assert_eq!(s.days_of_month.len(), 7);
+        assert_eq!(s.months.len(), 12);
+        assert_eq!(CronSchedule::parse("0 0 * * 7").unwrap().days_of_week.iter().copied().collect::<Vec<_>>(), vec![0]);
+
+        for invalid in ["", "* * * *", "60 * * * *", "* 24 * * *", "* * 0 * *", "*/0 * * * *", "5-1 * * * *", "a * * * *"].iter() {
+            assert!(CronSchedule::parse(invalid).is_err(), "'{}' was parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn test_next_after() {
+        // Nightly at 02:30
+        let nightly = CronSchedule::parse("30 2 * * *").unwrap();
+        assert_eq!(nightly.next_after(&time(2022, 3, 1, 1, 0)), Some(time(2022, 3, 1, 2, 30)));
+        assert_eq!(nightly.next_after(&time(2022, 3, 1, 2, 30)), Some(time(2022, 3, 2, 2, 30)));
+        assert_eq!(nightly.next_after(&time(2022, 12, 31, 23, 59)), Some(time(2023, 1, 1, 2, 30)));
+
+        // On weekdays only, 2022-03-05 is a saturday
+        let weekdays = CronSchedule::parse("0 3 * * 1-5").unwrap();
+        assert_eq!(weekdays.next_after(&time(2022, 3, 5, 0, 0)), Some(time(2022, 3, 7, 3, 0)));
+
+        // On the 1st of the month or on sundays
+        let either = CronSchedule::parse("0 0 1 * 0").unwrap();
+        assert_eq!(either.next_after(&time(2022, 3, 2, 0, 0)), Some(time(2022, 3, 6, 0, 0)));
+        assert!(either.matches(&time(2022, 4, 1, 0, 0)));
+
+        assert_eq!(CronSchedule::parse("0 0 31 2 *").unwrap().next_after(&time(2022, 1, 1, 0, 0)), None);
+    }
+}
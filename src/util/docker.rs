@@ -8,6 +8,9 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -45,6 +48,45 @@ impl AsRef<str> for ImageName {
     }
 }
 
+impl ImageName {
+    /// The name of the image, without the digest it is pinned to
+    pub fn name(&self) -> &str {
+        self.0.split_once('@').map(|(name, _)| name).unwrap_or(&self.0)
+    }
+
+    /// The digest the image is pinned to with "name@sha256:...", if any
+    ///
+    /// This is the digest of the image manifest in the registry, as docker uses it.
+    pub fn digest(&self) -> Option<&str> {
+        self.0.split_once('@').map(|(_, digest)| digest)
+    }
+
+    /// Whether `other` is this image
+    ///
+    /// If this image is not pinned to a digest, `other` matches regardless of its digest.
+    pub fn matches(&self, other: &ImageName) -> bool {
+        self.name() == other.name() && self.digest().map(|d| other.digest() == Some(d)).unwrap_or(true)
+    }
+
+    /// Check that the digest the image is pinned to, if any, is a valid sha256 digest
+    pub fn check_digest(&self) -> Result<()> {
+        match self.digest() {
+            None => Ok(()),
+            Some(digest) => {
+                let valid = digest.strip_prefix("sha256:")
+                    .map(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+                    .unwrap_or(false);
+
+                if valid {
+                    Ok(())
+                } else {
+                    Err(anyhow!("Invalid digest in image '{}', expected 'sha256:' followed by 64 hex digits", self.0))
+                }
+            },
+        }
+    }
+}
+
 #[derive(
     parse_display::Display,
     Serialize,
@@ -72,3 +114,51 @@ impl AsRef<str> for ContainerHash {
         self.0.as_ref()
     }
 }
+
+/// Whether the docker API version `version` (e.g. "1.41") is at least `min`
+pub fn api_version_at_least(version: &str, min: &str) -> Result<bool> {
+    fn parse(v: &str) -> Result<Vec<u64>> {
+        v.split('.')
+            .map(|part| part.parse::<u64>().with_context(|| anyhow!("Invalid docker API version: '{}'", v)))
+            .collect()
+    }
+
+    Ok(parse(version)? >= parse(min)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_version_at_least() {
+        assert!(api_version_at_least("1.41", "1.41").unwrap());
+        assert!(api_version_at_least("1.41", "1.38").unwrap());
+        assert!(api_version_at_least("1.100", "1.41").unwrap());
+        assert!(!api_version_at_least("1.38", "1.41").unwrap());
+        assert!(api_version_at_least("1.x", "1.41").is_err());
+    }
+
+    #[test]
+    fn test_image_name_digest() {
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let pinned = ImageName::from(format!("debian:bullseye@{}", digest));
+        let unpinned = ImageName::from("debian:bullseye");
+
+        assert_eq!(pinned.name(), "debian:bullseye");
+        assert_eq!(pinned.digest(), Some(digest.as_str()));
+        assert_eq!(unpinned.name(), "debian:bullseye");
+        assert_eq!(unpinned.digest(), None);
+
+        assert!(unpinned.matches(&pinned));
+        assert!(!pinned.matches(&unpinned));
+        assert!(pinned.matches(&pinned));
+        assert!(!pinned.matches(&ImageName::from(format!("debian:bullseye@sha256:{}", "b".repeat(64)))));
+        assert!(!unpinned.matches(&ImageName::from("debian:buster")));
+
+        assert!(pinned.check_digest().is_ok());
+        assert!(unpinned.check_digest().is_ok());
+        assert!(ImageName::from("debian@sha256:abc").check_digest().is_err());
+        assert!(ImageName::from(format!("debian@md5:{}", "a".repeat(64))).check_digest().is_err());
+    }
+}
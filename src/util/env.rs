@@ -26,3 +26,109 @@ pub fn parse_to_env(s: &str) -> Result<(EnvironmentVariableName, String)> {
         ),
     ))
 }
+
+/// Parse the environment variables in `content`, in the format of dotenv files
+///
+/// Each line is a `KEY=VALUE` assignment, optionally prefixed with `export`. Empty lines and lines
+/// starting with `#` are ignored. Values can be quoted: in single quotes they are taken
+/// literally, in double quotes the escapes `\n`, `\t`, `\"`, `\\` and `\$` are replaced. Unquoted
+/// values end at a ` #` comment and are trimmed.
+pub fn parse_env_file(content: &str) -> Result<Vec<(EnvironmentVariableName, String)>> {
+    content.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            let line = line.strip_prefix("export ").map(str::trim_start).unwrap_or(line);
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| anyhow!("Line {}: expected KEY=VALUE: {}", number, line))?;
+
+            let key = key.trim();
+            let valid_key = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid_key {
+                return Err(anyhow!("Line {}: invalid variable name '{}'", number, key))
+            }
+
+            let value = parse_env_file_value(value.trim())
+                .map_err(|e| anyhow!("Line {}: {}", number, e))?;
+            Ok((EnvironmentVariableName::from(key), value))
+        })
+        .collect()
+}
+
+fn parse_env_file_value(value: &str) -> Result<String> {
+    let quote = match value.chars().next() {
+        Some(quote) if quote == '"' || quote == '\'' => quote,
+        _ => {
+            let value = value.find(" #").map(|comment| &value[..comment]).unwrap_or(value);
+            return Ok(value.trim().to_string())
+        },
+    };
+
+    let mut result = String::new();
+    let mut chars = value[1..].chars();
+    loop {
+        match chars.next() {
+            None => return Err(anyhow!("Missing closing {} in value {}", quote, value)),
+            Some(c) if c == quote => break,
+            Some('\\') if quote == '"' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some(c) if c == '"' || c == '\\' || c == '$' => result.push(c),
+                Some(c) => {
+                    result.push('\\');
+                    result.push(c);
+                },
+                None => return Err(anyhow!("Missing closing {} in value {}", quote, value)),
+            },
+            Some(c) => result.push(c),
+        }
+    }
+
+    let rest = chars.as_str().trim();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(anyhow!("Unexpected '{}' after the quoted value", rest))
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_file() {
+        let content = indoc::indoc!(r#"
+            # A comment
+            FOO=bar
+            export BAZ = "quoted # not a comment" # a comment
+
+            SINGLE='literal \n $HOME'
+            DOUBLE="line\nnext \"quoted\" \$HOME"
+            UNQUOTED=some value # comment
+            EMPTY=
+            URL=https://example.com/?a=b
+        "#);
+
+        let env = parse_env_file(content).unwrap();
+        let env = env.iter().map(|(k, v)| (k.as_ref() as &str, v.as_str())).collect::<Vec<_>>();
+        assert_eq!(env, vec![
+            ("FOO", "bar"),
+            ("BAZ", "quoted # not a comment"),
+            ("SINGLE", "literal \\n $HOME"),
+            ("DOUBLE", "line\nnext \"quoted\" $HOME"),
+            ("UNQUOTED", "some value"),
+            ("EMPTY", ""),
+            ("URL", "https://example.com/?a=b"),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_env_file_errors() {
+        assert!(parse_env_file("NO_VALUE").is_err());
+        assert!(parse_env_file("1FOO=bar").is_err());
+        assert!(parse_env_file("FOO=\"unterminated").is_err());
+        assert!(parse_env_file("FOO='a' b").is_err());
+    }
+}
@@ -71,7 +71,7 @@ pub fn build_package_filter_by_dependency_name(
 pub fn build_package_filter_by_name(name: PackageName) -> impl filters::filter::Filter<Package> {
     move |p: &Package| {
         trace!("Checking {:?} -> name == {}", p, name);
-        *p.name() == name
+        p.has_name(&name)
     }
 }
 
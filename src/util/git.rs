@@ -8,6 +8,9 @@
 // SPDX-License-Identifier: EPL-2.0
 //
 
+use std::path::Path;
+use std::path::PathBuf;
+
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Error;
@@ -38,3 +41,121 @@ pub fn get_repo_head_commit_hash(r: &Repository) -> Result<String> {
     trace!("Found git commit hash = {}", s);
     Ok(s)
 }
+
+/// Get the name of the branch HEAD points to, `None` if HEAD is detached
+pub fn get_repo_head_branch(r: &Repository) -> Result<Option<String>> {
+    let head = r
+        .head()
+        .with_context(|| anyhow!("Getting HEAD from repository at {}", r.path().display()))?;
+
+    let branch = if head.is_branch() {
+        head.shorthand().map(String::from)
+    } else {
+        None
+    };
+
+    trace!("Found git branch = {:?}", branch);
+    Ok(branch)
+}
+
+/// The files that differ between the revision `since` (a commit, branch, tag,...) and HEAD,
+/// relative to the root of the repository
+///
+/// Renamed files are listed with their old and their new path.
+pub fn changed_files_since(r: &Repository, since: &str) -> Result<Vec<PathBuf>> {
+    let old = r.revparse_single(since)
+        .and_then(|object| object.peel_to_tree())
+        .with_context(|| anyhow!("Finding revision {} in repository at {}", since, r.path().display()))?;
+    let head = r.head()
+        .and_then(|head| head.peel_to_tree())
+        .with_context(|| anyhow!("Getting HEAD from repository at {}", r.path().display()))?;
+
+    let diff = r.diff_tree_to_tree(Some(&old), Some(&head), None)?;
+    let mut files = diff.deltas()
+        .flat_map(|delta| vec![delta.old_file().path(), delta.new_file().path()])
+        .flatten()
+        .map(Path::to_path_buf)
+        .collect::<Vec<_>>();
+    files.sort();
+    files.dedup();
+
+    trace!("Files changed since {}: {:?}", since, files);
+    Ok(files)
+}
+
+/// Whether the file at `path`, relative to the root of the repository, is committed in HEAD
+pub fn is_committed(r: &Repository, path: &Path) -> Result<bool> {
+    let head = r.head()
+        .and_then(|head| head.peel_to_tree())
+        .with_context(|| anyhow!("Getting HEAD from repository at {}", r.path().display()))?;
+
+    match head.get_path(path) {
+        Ok(entry) => Ok(entry.kind() == Some(git2::ObjectType::Blob)),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(false),
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+/// Get the user from the git configuration of the repository (or the global one if there is no
+/// repository), as "name <email>", `None` if neither the name nor the email is set
+pub fn get_user_identity(r: Option<&Repository>) -> Option<String> {
+    let config = match r {
+        Some(r) => r.config(),
+        None => git2::Config::open_default(),
+    }.ok()?;
+    let name = config.get_string("user.name").ok().filter(|s| !s.is_empty());
+    let email = config.get_string("user.email").ok().filter(|s| !s.is_empty());
+
+    let identity = match (name, email) {
+        (Some(name), Some(email)) => Some(format!("{} <{}>", name, email)),
+        (Some(name), None) => Some(name),
+        (None, Some(email)) => Some(format!("<{}>", email)),
+        (None, None) => None,
+    };
+    trace!("Found git user = {:?}", identity);
+    identity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_identity_from_repository_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        {
+            let mut config = repo.config().unwrap().open_level(git2::ConfigLevel::Local).unwrap();
+            config.set_str("user.name", "Jane Doe").unwrap();
+            config.set_str("user.email", "jane@example.com").unwrap();
+        }
+        assert_eq!(get_user_identity(Some(&repo)), Some(String::from("Jane Doe <jane@example.com>")));
+
+        {
+            let mut config = repo.config().unwrap().open_level(git2::ConfigLevel::Local).unwrap();
+            config.set_str("user.email", "").unwrap();
+        }
+        assert_eq!(get_user_identity(Some(&repo)), Some(String::from("Jane Doe")));
+    }
+
+    #[test]
+    fn test_is_committed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let repo = Repository::init(dir).unwrap();
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        std::fs::write(dir.join("a/fix.patch"), "patch").unwrap();
+        std::fs::write(dir.join("a/new.patch"), "patch").unwrap();
+        {
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("a/fix.patch")).unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let signature = git2::Signature::now("Jane Doe", "jane@example.com").unwrap();
+            repo.commit(Some("HEAD"), &signature, &signature, "Add patch", &tree, &[]).unwrap();
+        }
+
+        assert!(is_committed(&repo, Path::new("a/fix.patch")).unwrap());
+        assert!(!is_committed(&repo, Path::new("a/new.patch")).unwrap());
+        assert!(!is_committed(&repo, Path::new("a")).unwrap());
+    }
+}
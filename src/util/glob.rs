@@ -0,0 +1,97 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Selecting artifacts by shell-like glob patterns
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use regex::Regex;
+
+/// A glob pattern, where `*` matches any number of characters (including `/`) and `?` matches one
+/// character
+#[derive(Clone, Debug)]
+pub struct GlobPattern(Regex);
+
+impl GlobPattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let regex = pattern
+            .chars()
+            .map(|c| match c {
+                '*' => ".*".to_string(),
+                '?' => ".".to_string(),
+                c => regex::escape(&c.to_string()),
+            })
+            .collect::<String>();
+
+        Regex::new(&format!("^{}$", regex))
+            .map(GlobPattern)
+            .map_err(|e| anyhow!("Invalid pattern '{}': {}", pattern, e))
+    }
+
+    /// Whether the pattern matches the whole `path` or its file name
+    pub fn matches(&self, path: &Path) -> bool {
+        self.0.is_match(&path.to_string_lossy())
+            || path.file_name().map(|name| self.0.is_match(&name.to_string_lossy())).unwrap_or(false)
+    }
+}
+
+/// Artifacts to select by patterns they have to match and patterns they must not match
+#[derive(Debug, Default)]
+pub struct PathSelection {
+    include: Vec<GlobPattern>,
+    exclude: Vec<GlobPattern>,
+}
+
+impl PathSelection {
+    pub fn new<'a, I, E>(include: I, exclude: E) -> Result<Self>
+        where I: IntoIterator<Item = &'a str>,
+              E: IntoIterator<Item = &'a str>,
+    {
+        Ok(PathSelection {
+            include: include.into_iter().map(GlobPattern::new).collect::<Result<_>>()?,
+            exclude: exclude.into_iter().map(GlobPattern::new).collect::<Result<_>>()?,
+        })
+    }
+
+    /// Whether `path` matches one of the included patterns (if there are any) and none of the
+    /// excluded ones
+    pub fn selects(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches(path)))
+            && !self.exclude.iter().any(|p| p.matches(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_pattern() {
+        let p = GlobPattern::new("*-debug-?.tar.gz").unwrap();
+        assert!(p.matches(Path::new("foo-debug-1.tar.gz")));
+        assert!(p.matches(Path::new("x86_64/foo-debug-1.tar.gz")));
+        assert!(!p.matches(Path::new("foo-debug-10.tar.gz")));
+        assert!(!p.matches(Path::new("foo-debug-1.tar.gz.sig")));
+        assert!(GlobPattern::new("foo.tar.gz").unwrap().matches(Path::new("dir/foo.tar.gz")));
+        assert!(!GlobPattern::new("foo.tar.gz").unwrap().matches(Path::new("fooxtar.gz")));
+    }
+
+    #[test]
+    fn test_path_selection() {
+        let sel = PathSelection::new(vec!["*.rpm"], vec!["*-debuginfo-*", "*-tests-*"]).unwrap();
+        assert!(sel.selects(Path::new("foo-1.0.rpm")));
+        assert!(!sel.selects(Path::new("foo-debuginfo-1.0.rpm")));
+        assert!(!sel.selects(Path::new("foo-tests-1.0.rpm")));
+        assert!(!sel.selects(Path::new("foo-1.0.tar.gz")));
+        assert!(PathSelection::default().selects(Path::new("foo-1.0.tar.gz")));
+    }
+}
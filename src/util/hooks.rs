@@ -0,0 +1,138 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Hooks are external commands that run on the butido host before a submit starts and after it
+//! finished, with a JSON description of the submit on stdin
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use log::debug;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// The description of a submit that is passed to the hooks
+#[derive(Debug, Serialize)]
+pub struct SubmitDescription<'a> {
+    pub submit: Uuid,
+    pub started_at: NaiveDateTime,
+    pub image: &'a str,
+    pub package_name: &'a str,
+    pub package_version: &'a str,
+    pub repo_hash: &'a str,
+    pub staging_dir: &'a Path,
+}
+
+/// The description of a finished submit that is passed to the post-submit hooks
+#[derive(Debug, Serialize)]
+pub struct SubmitResult<'a> {
+    #[serde(flatten)]
+    pub submit: &'a SubmitDescription<'a>,
+    pub success: bool,
+
+    /// The paths of the artifacts that were created
+    pub artifacts: Vec<PathBuf>,
+
+    /// The jobs that failed
+    pub failed_jobs: Vec<Uuid>,
+}
+
+#[derive(Serialize)]
+struct HookInput<'a, T: Serialize> {
+    hook: &'a str,
+
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+/// Run the `hooks` one after another, passing `data` as JSON on stdin
+///
+/// `hook` is the name of the hook ("pre-submit" or "post-submit"), which is also passed in the
+/// JSON object. Fails with the first hook that exits unsuccessfully.
+pub async fn run_hooks<T: Serialize>(hook: &str, hooks: &[Vec<String>], data: &T) -> Result<()> {
+    if hooks.is_empty() {
+        return Ok(())
+    }
+
+    let input = serde_json::to_vec(&HookInput { hook, data })?;
+    let what = format!("{} hook", hook);
+    for command in hooks {
+        run_with_input(&what, command, &input, std::process::Stdio::inherit()).await?;
+    }
+
+    Ok(())
+}
+
+/// Run `command` (program and arguments) with `input` on stdin and fail if it exits unsuccessfully
+///
+/// `what` describes the command in messages, e.g. "pre-submit hook".
+pub async fn run_with_input(what: &str, command: &[String], input: &[u8], stdout: std::process::Stdio) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let (program, args) = command.split_first()
+        .ok_or_else(|| anyhow!("Empty {} command", what))?;
+    debug!("Running {}: {:?}", what, command);
+
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(stdout)
+        .spawn()
+        .with_context(|| anyhow!("Spawning {} {}", what, program))?;
+    {
+        let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("No stdin for {}", what))?;
+        // The command does not have to read its input
+        if let Err(e) = stdin.write_all(input).await {
+            debug!("Writing the input of {} {} failed: {}", what, program, e);
+        }
+    }
+
+    let status = child.wait()
+        .await
+        .with_context(|| anyhow!("Waiting for {} {}", what, program))?;
+    if !status.success() {
+        return Err(anyhow!("{} {} failed: {}", what, program, status))
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_hooks() {
+        let submit = SubmitDescription {
+            submit: Uuid::nil(),
+            started_at: chrono::NaiveDate::from_ymd_opt(2022, 1, 1).unwrap().and_hms_opt(12, 0, 0).unwrap(),
+            image: "debian:bullseye",
+            package_name: "foo",
+            package_version: "1.0",
+            repo_hash: "abc",
+            staging_dir: Path::new("/tmp/staging"),
+        };
+
+        let check = |expected: &str| vec![
+            String::from("sh"),
+            String::from("-c"),
+            format!(r#"test "$(cat)" = '{}'"#, expected),
+        ];
+
+        let expected = r#"{"hook":"pre-submit","submit":"00000000-0000-0000-0000-000000000000","started_at":"2022-01-01T12:00:00","image":"debian:bullseye","package_name":"foo","package_version":"1.0","repo_hash":"abc","staging_dir":"/tmp/staging"}"#;
+        assert!(run_hooks("pre-submit", &[check(expected)], &submit).await.is_ok());
+        assert!(run_hooks("pre-submit", &[check("{}")], &submit).await.is_err());
+        assert!(run_hooks("pre-submit", &[vec![String::from("true")], vec![String::from("false")]], &submit).await.is_err());
+    }
+}
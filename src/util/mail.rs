@@ -0,0 +1,293 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Notification mails about failed submits, sent over SMTP
+
+use std::fmt::Write;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use log::debug;
+use log::trace;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use uuid::Uuid;
+
+use crate::config::EmailNotificationConfig;
+use crate::config::SmtpSecurity;
+use crate::util::hooks::SubmitDescription;
+
+/// SMTP lines must not be longer than 1000 characters, including the line ending
+const MAX_LINE_LENGTH: usize = 990;
+
+/// A job of a submit that failed
+#[derive(Debug)]
+pub struct FailedJob {
+    pub uuid: Uuid,
+    pub package_name: String,
+    pub package_version: String,
+    pub error: String,
+
+    /// The last lines of the log of the job
+    pub log_tail: Vec<String>,
+}
+
+/// A submit that failed, either because jobs failed or because it could not be run at all
+#[derive(Debug)]
+pub struct SubmitFailure<'a> {
+    pub submit: &'a SubmitDescription<'a>,
+
+    /// The branch of the repository, `None` if HEAD is detached
+    pub branch: Option<&'a str>,
+
+    /// The error if the submit could not be run
+    pub error: Option<String>,
+    pub failed_jobs: Vec<FailedJob>,
+}
+
+/// Mail the `failure` to the configured recipients, unless it is from a branch that is not
+/// notified about
+pub async fn notify_submit_failure(config: &EmailNotificationConfig, failure: &SubmitFailure<'_>) -> Result<()> {
+    if !config.notifies_for_branch(failure.branch) {
+        debug!("Not sending a mail for branch {:?}", failure.branch);
+        return Ok(())
+    }
+
+    let subject = format!("butido: submit {} for {} {} failed",
+        failure.submit.submit, failure.submit.package_name, failure.submit.package_version);
+    send_mail(config, &subject, &failure_body(failure)).await
+        .with_context(|| anyhow!("Sending mail via {}:{}", config.smtp_host(), config.smtp_port()))
+}
+
+fn failure_body(failure: &SubmitFailure<'_>) -> String {
+    let mut body = String::new();
+    let submit = failure.submit;
+
+    // Writing to a String does not fail
+    let _ = writeln!(body, "Submit {} for {} {} on {} failed.", submit.submit, submit.package_name, submit.package_version, submit.image);
+    let _ = writeln!(body);
+    let _ = writeln!(body, "Started at: {}", submit.started_at);
+    let _ = writeln!(body, "Commit:     {}", submit.repo_hash);
+    if let Some(branch) = failure.branch {
+        let _ = writeln!(body, "Branch:     {}", branch);
+    }
+
+    if let Some(error) = failure.error.as_ref() {
+        let _ = writeln!(body);
+        let _ = writeln!(body, "Error: {}", error);
+    }
+
+    for job in failure.failed_jobs.iter() {
+        let _ = writeln!(body);
+        let _ = writeln!(body, "Job {} for {} {} failed: {}", job.uuid, job.package_name, job.package_version, job.error);
+        let _ = writeln!(body);
+        let _ = writeln!(body, "Last {} lines of the log:", job.log_tail.len());
+        let _ = writeln!(body);
+        for line in job.log_tail.iter() {
+            let _ = writeln!(body, "    {}", line);
+        }
+        let _ = writeln!(body);
+        let _ = writeln!(body, "To investigate, run: butido db log-of {}", job.uuid);
+    }
+
+    body
+}
+
+/// Format a mail with a plain text `body`, ready to be sent with the SMTP DATA command
+///
+/// Lines end with CRLF and are dot-stuffed, the final "." line is not part of the message.
+fn format_message(from: &str, to: &[String], subject: &str, body: &str, date: chrono::DateTime<chrono::Local>) -> String {
+    let subject = if subject.is_ascii() {
+        subject.to_string()
+    } else {
+        format!("=?UTF-8?B?{}?=", base64::encode(subject))
+    };
+
+    let mut message = String::new();
+    let headers = [
+        format!("Date: {}", date.to_rfc2822()),
+        format!("From: {}", from),
+        format!("To: {}", to.join(", ")),
+        format!("Subject: {}", subject),
+        String::from("MIME-Version: 1.0"),
+        String::from("Content-Type: text/plain; charset=utf-8"),
+        String::from("Content-Transfer-Encoding: 8bit"),
+    ];
+    for header in headers.iter() {
+        message.push_str(header);
+        message.push_str("\r\n");
+    }
+    message.push_str("\r\n");
+
+    for line in body.lines() {
+        if line.starts_with('.') {
+            message.push('.');
+        }
+        message.extend(line.chars().take(MAX_LINE_LENGTH));
+        message.push_str("\r\n");
+    }
+    message
+}
+
+trait SmtpStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> SmtpStream for T {}
+
+struct SmtpConnection {
+    stream: BufReader<Box<dyn SmtpStream>>,
+}
+
+impl SmtpConnection {
+    fn new(stream: Box<dyn SmtpStream>) -> Self {
+        SmtpConnection { stream: BufReader::new(stream) }
+    }
+
+    /// Read a (possibly multiline) response, returning its code and text
+    async fn response(&mut self) -> Result<(u16, String)> {
+        let mut text = vec![];
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(anyhow!("Connection closed by SMTP server"))
+            }
+
+            let line = line.trim_end();
+            trace!("SMTP < {}", line);
+            let code = line.get(..3)
+                .and_then(|c| c.parse::<u16>().ok())
+                .ok_or_else(|| anyhow!("Invalid SMTP response: {}", line))?;
+            text.push(line.get(4..).unwrap_or_default().to_string());
+
+            // "250-..." is continued in the next line, "250 ..." is the last line
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, text.join("\n")))
+            }
+        }
+    }
+
+    /// Expect a response of the class of `expected`, e.g. any 2xx response for 250
+    async fn expect(&mut self, what: &str, expected: u16) -> Result<String> {
+        let (code, text) = self.response().await?;
+        if code / 100 == expected / 100 {
+            Ok(text)
+        } else {
+            Err(anyhow!("SMTP server rejected {}: {} {}", what, code, text))
+        }
+    }
+
+    /// Send `command` and expect a response of the class of `expected`
+    ///
+    /// Only the first word of the command is used in errors, so credentials do not end up there.
+    async fn command(&mut self, command: &str, expected: u16) -> Result<String> {
+        let verb = command.split(' ').next().unwrap_or_default();
+        trace!("SMTP > {}", verb);
+        self.stream.get_mut().write_all(format!("{}\r\n", command).as_bytes()).await?;
+        self.stream.get_mut().flush().await?;
+        self.expect(verb, expected).await
+    }
+
+    async fn into_tls(self, host: &str) -> Result<Self> {
+        let stream = tls(host, self.stream.into_inner()).await?;
+        Ok(SmtpConnection::new(Box::new(stream)))
+    }
+}
+
+async fn tls<S: AsyncRead + AsyncWrite + Unpin>(host: &str, stream: S) -> Result<tokio_native_tls::TlsStream<S>> {
+    let connector = tokio_native_tls::native_tls::TlsConnector::new()?;
+    tokio_native_tls::TlsConnector::from(connector)
+        .connect(host, stream)
+        .await
+        .with_context(|| anyhow!("Establishing TLS connection to {}", host))
+}
+
+async fn send_mail(config: &EmailNotificationConfig, subject: &str, body: &str) -> Result<()> {
+    let host = config.smtp_host();
+    let tcp = tokio::net::TcpStream::connect((host.as_str(), config.smtp_port()))
+        .await
+        .with_context(|| anyhow!("Connecting to {}:{}", host, config.smtp_port()))?;
+
+    // We have no name, so we greet with our address
+    let ehlo = match tcp.local_addr()?.ip() {
+        std::net::IpAddr::V4(ip) => format!("EHLO [{}]", ip),
+        std::net::IpAddr::V6(ip) => format!("EHLO [IPv6:{}]", ip),
+    };
+
+    let mut conn = if config.security() == SmtpSecurity::Tls {
+        SmtpConnection::new(Box::new(tls(host, tcp).await?))
+    } else {
+        SmtpConnection::new(Box::new(tcp))
+    };
+    conn.expect("connection", 220).await?;
+    conn.command(&ehlo, 250).await?;
+
+    if config.security() == SmtpSecurity::StartTls {
+        conn.command("STARTTLS", 220).await?;
+        conn = conn.into_tls(host).await?;
+        conn.command(&ehlo, 250).await?;
+    }
+
+    if let Some(username) = config.username() {
+        let password = match config.password_env() {
+            Some(var) => std::env::var(var).with_context(|| anyhow!("Reading SMTP password from ${}", var))?,
+            None => String::new(),
+        };
+        let credentials = base64::encode(format!("\0{}\0{}", username, password));
+        conn.command(&format!("AUTH PLAIN {}", credentials), 235).await?;
+    }
+
+    conn.command(&format!("MAIL FROM:<{}>", config.from()), 250).await?;
+    for recipient in config.to() {
+        conn.command(&format!("RCPT TO:<{}>", recipient), 250).await?;
+    }
+    conn.command("DATA", 354).await?;
+
+    let message = format_message(config.from(), config.to(), subject, body, chrono::Local::now());
+    conn.command(&format!("{}.", message), 250).await?;
+    conn.command("QUIT", 221).await?;
+    debug!("Sent mail '{}' to {}", subject, config.to().join(", "));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_format_message() {
+        let date = chrono::Local.with_ymd_and_hms(2022, 1, 1, 12, 0, 0).unwrap();
+        let to = vec![String::from("a@example.com"), String::from("b@example.com")];
+        let message = format_message("butido@example.com", &to, "Failed", "first\n.dot\nlast", date);
+
+        let (headers, body) = message.split_once("\r\n\r\n").unwrap();
+        assert!(headers.contains("From: butido@example.com\r\n"));
+        assert!(headers.contains("To: a@example.com, b@example.com\r\n"));
+        assert!(headers.contains("Subject: Failed\r\n"));
+        assert_eq!(body, "first\r\n..dot\r\nlast\r\n");
+
+        let message = format_message("butido@example.com", &to, "Fehlgeschlagen: Bäckerei", "", date);
+        assert!(message.contains("Subject: =?UTF-8?B?RmVobGdlc2NobGFnZW46IELDpGNrZXJlaQ==?=\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_response() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        server.write_all(b"250-smtp.example.com\r\n250-STARTTLS\r\n250 AUTH PLAIN\r\n554 No\r\n").await.unwrap();
+        drop(server);
+        let mut conn = SmtpConnection::new(Box::new(client));
+
+        assert_eq!(conn.response().await.unwrap(), (250, String::from("smtp.example.com\nSTARTTLS\nAUTH PLAIN")));
+        assert!(conn.expect("DATA", 354).await.is_err());
+        assert!(conn.response().await.is_err());
+    }
+}
@@ -40,13 +40,45 @@ impl AsRef<str> for EnvironmentVariableName {
 }
 
 
+pub mod completion;
+pub mod cron;
 pub mod docker;
 pub mod env;
 pub mod filters;
 pub mod git;
+pub mod glob;
+pub mod hooks;
+pub mod mail;
+pub mod notifications;
+pub mod oci;
 pub mod parser;
 pub mod progress;
+pub mod secret;
+pub mod signal;
+pub mod terminal;
 
 pub fn stdout_is_pipe() -> bool {
     !atty::is(atty::Stream::Stdout)
 }
+
+/// The name of the user that runs butido, as recorded in the database
+///
+/// This is the configured `operator`, or the login name of the user.
+pub fn operator(config: &crate::config::Configuration) -> String {
+    config.operator()
+        .clone()
+        .or_else(|| std::env::var("USER").ok())
+        .or_else(|| std::env::var("LOGNAME").ok())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+/// The identity of the user that starts a submit, as recorded with the submit
+///
+/// This is the configured `identity`, the user from the git configuration of `git_repo` (or the
+/// global git configuration), or the operator.
+pub fn submitter(config: &crate::config::Configuration, git_repo: Option<&git2::Repository>) -> String {
+    config.identity()
+        .clone()
+        .or_else(|| crate::util::git::get_user_identity(git_repo))
+        .unwrap_or_else(|| operator(config))
+}
@@ -0,0 +1,122 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Notifications are external commands that are told about the progress of a submit, with a
+//! JSON object describing the event on stdin
+//!
+//! Unlike hooks, notifications cannot stop a submit: if a notification command fails, a warning
+//! is printed and the submit continues.
+
+use log::warn;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::util::hooks::SubmitResult;
+
+/// An event of a submit that the notification commands are told about
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum NotificationEvent<'a> {
+    /// A job was scheduled on an endpoint and starts running
+    JobStarted {
+        submit: Uuid,
+        job: Uuid,
+        package_name: &'a str,
+        package_version: &'a str,
+        endpoint: &'a str,
+    },
+
+    /// A job finished, after all attempts to run it
+    JobFinished {
+        submit: Uuid,
+        job: Uuid,
+        package_name: &'a str,
+        package_version: &'a str,
+        success: bool,
+
+        /// The error if the job failed
+        error: Option<String>,
+
+        /// The maintainers of the package, to ping them if their package broke
+        #[serde(skip_serializing_if = "<[String]>::is_empty")]
+        maintainers: &'a [String],
+    },
+
+    /// The submit finished, with the same description that is passed to the post-submit hooks
+    SubmitFinished(&'a SubmitResult<'a>),
+}
+
+impl NotificationEvent<'_> {
+    fn name(&self) -> &'static str {
+        match self {
+            NotificationEvent::JobStarted { .. } => "job-started",
+            NotificationEvent::JobFinished { .. } => "job-finished",
+            NotificationEvent::SubmitFinished(_) => "submit-finished",
+        }
+    }
+}
+
+/// Pass `event` to all notification `commands`, one after another
+///
+/// The output of the commands is discarded, so they do not interfere with the progress bars.
+pub async fn notify(commands: &[Vec<String>], event: &NotificationEvent<'_>) {
+    if commands.is_empty() {
+        return
+    }
+
+    let input = match serde_json::to_vec(event) {
+        Ok(input) => input,
+        Err(e) => {
+            warn!("Serializing {} notification failed: {}", event.name(), e);
+            return
+        },
+    };
+
+    let what = format!("{} notification", event.name());
+    for command in commands {
+        if let Err(e) = crate::util::hooks::run_with_input(&what, command, &input, std::process::Stdio::null()).await {
+            warn!("{:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_event() {
+        let event = NotificationEvent::JobFinished {
+            submit: Uuid::nil(),
+            job: Uuid::nil(),
+            package_name: "foo",
+            package_version: "1.0",
+            success: false,
+            error: Some(String::from("Phase 'build' failed")),
+            maintainers: &[],
+        };
+
+        let expected = r#"{"event":"job-finished","submit":"00000000-0000-0000-0000-000000000000","job":"00000000-0000-0000-0000-000000000000","package_name":"foo","package_version":"1.0","success":false,"error":"Phase 'build' failed"}"#;
+        assert_eq!(serde_json::to_string(&event).unwrap(), expected);
+        assert_eq!(event.name(), "job-finished");
+
+        let maintainers = vec![String::from("Jane Doe <jane@example.com>")];
+        let event = NotificationEvent::JobFinished {
+            submit: Uuid::nil(),
+            job: Uuid::nil(),
+            package_name: "foo",
+            package_version: "1.0",
+            success: false,
+            error: None,
+            maintainers: &maintainers,
+        };
+        assert!(serde_json::to_string(&event).unwrap().ends_with(r#""error":null,"maintainers":["Jane Doe <jane@example.com>"]}"#));
+    }
+}
@@ -0,0 +1,240 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Writing OCI image layouts
+//!
+//! The images have a single, uncompressed layer that contains files (the artifacts), so that
+//! containers can use them with "COPY --from" or by mounting the image.
+//! See <https://github.com/opencontainers/image-spec/blob/main/image-layout.md>.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use log::trace;
+use sha2::Digest;
+
+const MEDIA_TYPE_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+const MEDIA_TYPE_CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+const MEDIA_TYPE_LAYER: &str = "application/vnd.oci.image.layer.v1.tar";
+
+/// An image with the `files` in its only layer
+#[derive(Debug)]
+pub struct OciImage {
+    /// The files of the layer, as (file on the host, absolute path in the image)
+    pub files: Vec<(PathBuf, PathBuf)>,
+
+    /// The architecture of the image, in the notation of the OCI spec (e.g. "amd64")
+    pub architecture: String,
+
+    /// The labels of the image, which are also set as annotations of the manifest
+    pub labels: BTreeMap<String, String>,
+
+    /// The name of the image in the layout ("org.opencontainers.image.ref.name")
+    pub tag: String,
+}
+
+/// A blob that was written to the layout
+struct Blob {
+    digest: String,
+    size: u64,
+}
+
+impl Blob {
+    fn descriptor(&self, media_type: &str) -> serde_json::Value {
+        serde_json::json!({
+            "mediaType": media_type,
+            "digest": self.digest,
+            "size": self.size,
+        })
+    }
+}
+
+impl OciImage {
+    /// Write the image as OCI image layout to `dir`, which must not exist or be empty
+    ///
+    /// Returns the digest of the manifest of the image.
+    pub fn write_layout(&self, dir: &Path) -> Result<String> {
+        if dir.exists() && std::fs::read_dir(dir)?.next().is_some() {
+            return Err(anyhow!("Not an empty directory: {}", dir.display()))
+        }
+
+        let blobs = dir.join("blobs").join("sha256");
+        std::fs::create_dir_all(&blobs).with_context(|| anyhow!("Creating directory: {}", blobs.display()))?;
+
+        // The layer is not compressed, so its digest is its "diff ID" as well
+        let layer = self.write_layer(&blobs)?;
+        let config = write_blob(&blobs, &serde_json::to_vec(&serde_json::json!({
+            "architecture": self.architecture,
+            "os": "linux",
+            "config": {
+                "Labels": self.labels,
+            },
+            "rootfs": {
+                "type": "layers",
+                "diff_ids": [layer.digest],
+            },
+        }))?)?;
+
+        let manifest = write_blob(&blobs, &serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": MEDIA_TYPE_MANIFEST,
+            "config": config.descriptor(MEDIA_TYPE_CONFIG),
+            "layers": [layer.descriptor(MEDIA_TYPE_LAYER)],
+            "annotations": self.labels,
+        }))?)?;
+
+        let mut manifest_descriptor = manifest.descriptor(MEDIA_TYPE_MANIFEST);
+        manifest_descriptor["annotations"] = serde_json::json!({ "org.opencontainers.image.ref.name": self.tag });
+        let index = serde_json::json!({
+            "schemaVersion": 2,
+            "manifests": [manifest_descriptor],
+        });
+
+        std::fs::write(dir.join("index.json"), serde_json::to_vec(&index)?)?;
+        std::fs::write(dir.join("oci-layout"), br#"{"imageLayoutVersion":"1.0.0"}"#)?;
+        Ok(manifest.digest)
+    }
+
+    /// Write the layer tar archive
+    ///
+    /// The entries are sorted and have no timestamps or owners, so that the same files always
+    /// result in the same layer.
+    fn write_layer(&self, blobs: &Path) -> Result<Blob> {
+        let partial = blobs.join(".layer.tar.part");
+        let file = std::fs::File::create(&partial).with_context(|| anyhow!("Creating file: {}", partial.display()))?;
+        let mut builder = tar::Builder::new(HashingWriter::new(std::io::BufWriter::new(file)));
+
+        let files = self.files.iter()
+            .map(|(host, image)| (image.strip_prefix("/").unwrap_or(image), host))
+            .collect::<BTreeMap<_, _>>();
+
+        // Entries for all parent directories, so that they do not end up with arbitrary modes
+        let dirs = files.keys()
+            .flat_map(|path| path.ancestors().skip(1))
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect::<BTreeSet<_>>();
+        for dir in dirs {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(0o0755);
+            header.set_size(0);
+            header.set_mtime(0);
+            builder.append_data(&mut header, dir, std::io::empty())?;
+        }
+
+        for (path, host) in files {
+            trace!("Adding {} to layer as {}", host.display(), path.display());
+            let content = std::fs::File::open(host).with_context(|| anyhow!("Opening file: {}", host.display()))?;
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.metadata()?.len());
+            header.set_mode(0o0644);
+            header.set_mtime(0);
+            builder.append_data(&mut header, path, content)
+                .with_context(|| anyhow!("Adding {} to layer", host.display()))?;
+        }
+
+        let mut writer = builder.into_inner()?;
+        writer.flush()?;
+        let (digest, size) = (writer.digest(), writer.size);
+        let path = blobs.join(digest.trim_start_matches("sha256:"));
+        std::fs::rename(&partial, &path).with_context(|| anyhow!("Moving {} to {}", partial.display(), path.display()))?;
+        Ok(Blob { digest, size })
+    }
+}
+
+fn write_blob(blobs: &Path, content: &[u8]) -> Result<Blob> {
+    let hex = format!("{:x}", sha2::Sha256::digest(content));
+    let path = blobs.join(&hex);
+    std::fs::write(&path, content).with_context(|| anyhow!("Writing {}", path.display()))?;
+    Ok(Blob { digest: format!("sha256:{}", hex), size: content.len() as u64 })
+}
+
+/// A writer that hashes and counts everything written to it
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: sha2::Sha256,
+    size: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        HashingWriter { inner, hasher: sha2::Sha256::new(), size: 0 }
+    }
+
+    fn digest(&self) -> String {
+        format!("sha256:{:x}", self.hasher.clone().finalize())
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let tmp = dir.path();
+        let artifact = tmp.join("a-1.0.tar.gz");
+        std::fs::write(&artifact, b"artifact").unwrap();
+
+        let image = OciImage {
+            files: vec![(artifact, PathBuf::from("/packages/x86_64/a-1.0.tar.gz"))],
+            architecture: String::from("amd64"),
+            labels: BTreeMap::new(),
+            tag: String::from("1.0"),
+        };
+
+        let layout = tmp.join("layout");
+        let digest = image.write_layout(&layout).unwrap();
+        assert!(layout.join("oci-layout").is_file());
+
+        let index: serde_json::Value = serde_json::from_slice(&std::fs::read(layout.join("index.json")).unwrap()).unwrap();
+        assert_eq!(index["manifests"][0]["digest"], digest.as_str());
+        assert_eq!(index["manifests"][0]["annotations"]["org.opencontainers.image.ref.name"], "1.0");
+
+        // Every blob is stored under its digest
+        let manifest_path = layout.join("blobs/sha256").join(digest.trim_start_matches("sha256:"));
+        let manifest: serde_json::Value = serde_json::from_slice(&std::fs::read(manifest_path).unwrap()).unwrap();
+        let layer_digest = manifest["layers"][0]["digest"].as_str().unwrap();
+        let layer = std::fs::read(layout.join("blobs/sha256").join(layer_digest.trim_start_matches("sha256:"))).unwrap();
+        assert_eq!(format!("sha256:{:x}", sha2::Sha256::digest(&layer)), layer_digest);
+
+        let paths = tar::Archive::new(layer.as_slice())
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().display().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["packages", "packages/x86_64", "packages/x86_64/a-1.0.tar.gz"]);
+
+        // The same files result in the same image, and the directory has to be empty
+        let other = tmp.join("other");
+        assert_eq!(image.write_layout(&other).unwrap(), digest);
+        assert!(image.write_layout(&other).is_err());
+    }
+}
@@ -31,6 +31,10 @@ pub fn dot<'a>() -> PomParser<'a, u8, Vec<u8>> {
     sym(b'.').map(|b| vec![b])
 }
 
+pub fn plus<'a>() -> PomParser<'a, u8, Vec<u8>> {
+    sym(b'+').map(|b| vec![b])
+}
+
 pub fn equal<'a>() -> PomParser<'a, u8, Vec<u8>> {
     sym(b'=').map(|b| vec![b])
 }
@@ -53,3 +57,70 @@ pub fn nonempty_string_with_optional_quotes<'a>() -> Parser<'a, u8, String> {
     let string = (sym(b'"') * inner_string() - sym(b'"')) | inner_string();
     string.convert(String::from_utf8)
 }
+
+/// Optional spaces or tabs
+pub fn spaces<'a>() -> PomParser<'a, u8, ()> {
+    one_of(b" \t").repeat(0..).discard()
+}
+
+/// One or more `item`s, separated by `separator`
+///
+/// Unlike `pom::parser::list()`, this fails with the error of `item` if a separator is not
+/// followed by an item, instead of ignoring the separator.
+pub fn separated<'a, O: 'a, U: 'a>(
+    item: PomParser<'a, u8, O>,
+    separator: PomParser<'a, u8, U>,
+) -> PomParser<'a, u8, Vec<O>> {
+    PomParser::new(move |input: &'a [u8], start: usize| {
+        let (first, mut pos) = item.parse_at(input, start)?;
+        let mut items = vec![first];
+        while let Ok((_, separator_pos)) = separator.parse_at(input, pos) {
+            let (next, next_pos) = item.parse_at(input, separator_pos)?;
+            items.push(next);
+            pos = next_pos;
+        }
+        Ok((items, pos))
+    })
+}
+
+/// Describe the error of parsing `input` as `what`, pointing at the offending character
+///
+/// The description uses the innermost `expect()`ed parser that failed, e.g.
+///
+/// ```text
+/// Could not parse dependency 'foo *1': expected a version constraint like =1.0, found '*'
+///     foo *1
+///         ^
+/// ```
+pub fn describe_error(what: &str, input: &str, error: &pom::Error) -> anyhow::Error {
+    fn innermost_expectation(error: &pom::Error) -> Option<(&str, usize)> {
+        match error {
+            pom::Error::Expect { message, position, inner } => innermost_expectation(inner)
+                .or_else(|| Some((message.strip_prefix("Expect ").unwrap_or(message), *position))),
+            pom::Error::Custom { inner: Some(inner), .. } => innermost_expectation(inner),
+            _ => None,
+        }
+    }
+
+    let (expected, position) = match innermost_expectation(error) {
+        Some((expected, position)) => (expected.to_string(), position),
+        None => match error {
+            pom::Error::Mismatch { position, .. }
+            | pom::Error::Conversion { position, .. }
+            | pom::Error::Custom { position, .. } => (String::from("valid input"), *position),
+            _ => (String::from("more input"), input.len()),
+        },
+    };
+
+    let found = input
+        .get(position..)
+        .and_then(|rest| rest.chars().next())
+        .map(|c| format!("'{}'", c))
+        .unwrap_or_else(|| String::from("the end"));
+    let column = input.get(..position).map(|s| s.chars().count()).unwrap_or(position);
+
+    anyhow::anyhow!(
+        "Could not parse {} '{}': expected {}, found {}\n    {}\n    {}^",
+        what, input, expected, found, input, " ".repeat(column)
+    )
+}
@@ -10,20 +10,56 @@
 
 use indicatif::*;
 use getset::CopyGetters;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// How the progress is shown
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProgressMode {
+    /// With progress bars
+    Bars,
+
+    /// Not at all
+    Hidden,
+
+    /// As timestamped lines with status events, for logs of CI jobs
+    Plain,
+
+    /// As status events, one JSON object per line
+    Json,
+}
 
 #[derive(Clone, Debug, CopyGetters)]
 pub struct ProgressBars {
     bar_template: String,
 
+    /// The template for bars that count bytes
+    bytes_bar_template: String,
+
     #[getset(get_copy = "pub")]
     hide: bool,
+
+    #[getset(get_copy = "pub")]
+    mode: ProgressMode,
 }
 
 impl ProgressBars {
-    pub fn setup(bar_template: String, hide: bool) -> Self {
+    pub fn setup(bar_template: String, bytes_bar_template: String, mode: ProgressMode) -> Self {
         ProgressBars {
             bar_template,
-            hide,
+            bytes_bar_template,
+            hide: mode != ProgressMode::Bars,
+            mode,
+        }
+    }
+
+    /// Where the status events are printed to, if the progress is shown as status events
+    /// instead of bars
+    pub fn status(&self) -> Option<StatusPrinter> {
+        match self.mode {
+            ProgressMode::Bars | ProgressMode::Hidden => None,
+            ProgressMode::Plain => Some(StatusPrinter { json: false }),
+            ProgressMode::Json => Some(StatusPrinter { json: true }),
         }
     }
 
@@ -36,4 +72,272 @@ impl ProgressBars {
             Ok(b)
         }
     }
+
+    /// A progress bar that shows its prefix in front of the configured template
+    pub fn bar_with_prefix(&self) -> anyhow::Result<ProgressBar> {
+        if self.hide {
+            Ok(ProgressBar::hidden())
+        } else {
+            let b = ProgressBar::new(1);
+            b.set_style(ProgressStyle::default_bar().template(&format!("{{prefix}}{}", self.bar_template))?);
+            Ok(b)
+        }
+    }
+
+    /// A progress bar for transferring data, which counts bytes and shows the speed and ETA
+    ///
+    /// The length is zero and has to be set (or increased) once the size of the data is known.
+    pub fn bytes_bar(&self) -> anyhow::Result<ProgressBar> {
+        if self.hide {
+            // ProgressBar::hidden() has the maximum length, which cannot be increased
+            Ok(ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::hidden()))
+        } else {
+            let b = ProgressBar::new(0);
+            b.set_style(ProgressStyle::default_bar().template(&self.bytes_bar_template)?);
+            Ok(b)
+        }
+    }
+
+    /// Bars for transfers that run at the same time, below which a bar shows the progress of all
+    /// of them together
+    pub fn transfers(&self) -> anyhow::Result<TransferBars> {
+        let multi = if self.hide {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        } else {
+            MultiProgress::new()
+        };
+        let total = multi.add(self.bytes_bar()?);
+        Ok(TransferBars { multi, total, bars: self.clone() })
+    }
+
+    /// The bars that several groups of bars share, e.g. the jobs of submits that run at the same
+    /// time, see `group()`
+    pub fn multi(&self) -> MultiProgress {
+        if self.hide {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        } else {
+            MultiProgress::new()
+        }
+    }
+
+    /// A line that only shows its message
+    pub fn message_bar(&self) -> anyhow::Result<ProgressBar> {
+        if self.hide {
+            Ok(ProgressBar::hidden())
+        } else {
+            let b = ProgressBar::new(1);
+            b.set_style(ProgressStyle::default_bar().template("{msg}")?);
+            Ok(b)
+        }
+    }
+
+    /// A group of bars at the end of `multi`, below a line that shows `header`
+    pub fn group(&self, multi: &MultiProgress, header: String) -> anyhow::Result<ProgressGroup> {
+        let header_bar = multi.add(self.message_bar()?);
+        header_bar.set_message(header);
+        Ok(ProgressGroup {
+            multi: multi.clone(),
+            last: std::sync::Arc::new(std::sync::Mutex::new(header_bar.clone())),
+            header: header_bar,
+        })
+    }
+}
+
+/// Bars that are shown together below a header line, in `MultiProgress` they share with other
+/// groups
+#[derive(Clone)]
+pub struct ProgressGroup {
+    multi: MultiProgress,
+    header: ProgressBar,
+
+    /// The bar the next one is added after
+    last: std::sync::Arc<std::sync::Mutex<ProgressBar>>,
+}
+
+impl ProgressGroup {
+    /// The header line of the group
+    pub fn header(&self) -> &ProgressBar {
+        &self.header
+    }
+
+    /// Add `bar` to the end of the group
+    pub fn add(&self, bar: ProgressBar) -> ProgressBar {
+        let mut last = self.last.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let bar = self.multi.insert_after(&last, bar);
+        *last = bar.clone();
+        bar
+    }
+}
+
+/// Byte-based progress bars for transfers, with a bar for the total throughput
+#[derive(Clone)]
+pub struct TransferBars {
+    multi: MultiProgress,
+    total: ProgressBar,
+    bars: ProgressBars,
+}
+
+impl TransferBars {
+    /// The bar of all transfers together
+    pub fn total(&self) -> &ProgressBar {
+        &self.total
+    }
+
+    /// Add a bar for a transfer, which shows `message`
+    pub fn transfer(&self, message: String) -> anyhow::Result<TransferBar> {
+        let bar = self.multi.insert_before(&self.total, self.bars.bytes_bar()?);
+        bar.set_message(message);
+        Ok(TransferBar { bar, total: self.total.clone() })
+    }
+}
+
+/// The bar of a single transfer, which counts everything for the total bar as well
+#[derive(Clone)]
+pub struct TransferBar {
+    bar: ProgressBar,
+    total: ProgressBar,
+}
+
+impl TransferBar {
+    /// Add `len` bytes to the length of the transfer, once its size is known
+    pub fn inc_length(&self, len: u64) {
+        self.bar.inc_length(len);
+        self.total.inc_length(len);
+    }
+
+    /// Count `bytes` as transferred
+    pub fn inc(&self, bytes: u64) {
+        self.bar.inc(bytes);
+        self.total.inc(bytes);
+    }
+
+    /// Start the transfer over, without counting what was transferred so far
+    pub fn restart(&self) {
+        self.total.set_position(self.total.position().saturating_sub(self.bar.position()));
+        self.total.set_length(self.total.length().unwrap_or(0).saturating_sub(self.bar.length().unwrap_or(0)));
+        self.bar.set_position(0);
+        self.bar.set_length(0);
+    }
+
+    /// The bar of the transfer itself, which does not count for the total
+    pub fn bar(&self) -> &ProgressBar {
+        &self.bar
+    }
+
+    /// Remove the bar of the finished transfer, it stays counted in the total
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// An event of a job that is printed in the plain and JSON progress modes
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum StatusEvent<'a> {
+    JobStarted {
+        job: Uuid,
+        package_name: &'a str,
+        package_version: &'a str,
+        endpoint: &'a str,
+    },
+
+    PhaseChanged {
+        job: Uuid,
+        package_name: &'a str,
+        package_version: &'a str,
+        phase: &'a str,
+    },
+
+    JobFinished {
+        job: Uuid,
+        package_name: &'a str,
+        package_version: &'a str,
+        success: bool,
+
+        /// The error if the job failed
+        error: Option<String>,
+    },
+}
+
+impl std::fmt::Display for StatusEvent<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StatusEvent::JobStarted { job, package_name, package_version, endpoint } => {
+                write!(f, "[{} {} {}]: Started on {}", job, package_name, package_version, endpoint)
+            },
+            StatusEvent::PhaseChanged { job, package_name, package_version, phase } => {
+                write!(f, "[{} {} {}]: Phase: {}", job, package_name, package_version, phase)
+            },
+            StatusEvent::JobFinished { job, package_name, package_version, success: true, .. } => {
+                write!(f, "[{} {} {}]: Finished successfully", job, package_name, package_version)
+            },
+            StatusEvent::JobFinished { job, package_name, package_version, error, .. } => {
+                write!(f, "[{} {} {}]: Failed: {}", job, package_name, package_version, error.as_deref().unwrap_or("unknown error"))
+            },
+        }
+    }
+}
+
+/// Prints status events to stderr, line by line, so that they do not mix with the output of the
+/// commands
+#[derive(Clone, Copy, Debug)]
+pub struct StatusPrinter {
+    json: bool,
+}
+
+impl StatusPrinter {
+    pub fn print(&self, event: &StatusEvent<'_>) {
+        eprintln!("{}", self.format(event, &chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)));
+    }
+
+    fn format(&self, event: &StatusEvent<'_>, time: &str) -> String {
+        if self.json {
+            let mut value = serde_json::to_value(event).unwrap_or_default(); // only strings, uuids and bools
+            value["time"] = serde_json::Value::from(time);
+            value.to_string()
+        } else {
+            format!("{} {}", time, event)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_format() {
+        let event = StatusEvent::PhaseChanged {
+            job: Uuid::nil(),
+            package_name: "foo",
+            package_version: "1.0",
+            phase: "build",
+        };
+        let time = "2026-10-14T12:00:00Z";
+
+        assert_eq!(StatusPrinter { json: false }.format(&event, time),
+            "2026-10-14T12:00:00Z [00000000-0000-0000-0000-000000000000 foo 1.0]: Phase: build");
+        assert_eq!(StatusPrinter { json: true }.format(&event, time),
+            r#"{"event":"phase-changed","job":"00000000-0000-0000-0000-000000000000","package_name":"foo","package_version":"1.0","phase":"build","time":"2026-10-14T12:00:00Z"}"#);
+    }
+
+    #[test]
+    fn test_transfer_bars() {
+        let bars = ProgressBars::setup(String::from("{msg}"), String::from("{bytes} {msg}"), ProgressMode::Hidden);
+        let transfers = bars.transfers().unwrap();
+        let a = transfers.transfer(String::from("a")).unwrap();
+        let b = transfers.transfer(String::from("b")).unwrap();
+
+        a.inc_length(10);
+        b.inc_length(20);
+        a.inc(10);
+        b.inc(5);
+        a.finish();
+        assert_eq!(transfers.total().position(), 15);
+        assert_eq!(transfers.total().length(), Some(30));
+
+        b.restart();
+        assert_eq!(transfers.total().position(), 10);
+        assert_eq!(transfers.total().length(), Some(10));
+    }
 }
@@ -0,0 +1,120 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Secrets that are passed to the build containers
+//!
+//! Other than normal environment variables, secrets are never written to the database, the job
+//! logs or the metadata files of the artifacts.
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::util::EnvironmentVariableName;
+
+/// The string secrets are replaced with in the logs
+pub const REDACTED: &str = "********";
+
+/// A secret value
+///
+/// The `Debug` implementation does not print the value, so a secret does not leak by accident.
+#[derive(Clone)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Get the actual value of the secret
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret({})", REDACTED)
+    }
+}
+
+/// Parse a "NAME=@FILE" or "NAME=!COMMAND" argument
+///
+/// With "@FILE" the value is read from the file, with "!COMMAND" the command is run with `sh -c`
+/// and its output is the value. One trailing newline is removed from the value.
+pub fn parse_secret(s: &str) -> Result<(EnvironmentVariableName, Secret)> {
+    let (name, source) = s.split_once('=')
+        .ok_or_else(|| anyhow!("Secret is not of the form NAME=@FILE or NAME=!COMMAND: {}", name_of(s)))?;
+
+    let mut value = if let Some(path) = source.strip_prefix('@') {
+        std::fs::read_to_string(path)
+            .with_context(|| anyhow!("Reading secret {} from {}", name, path))?
+    } else if let Some(command) = source.strip_prefix('!') {
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stderr(std::process::Stdio::inherit())
+            .output()
+            .with_context(|| anyhow!("Running command for secret {}", name))?;
+
+        if !output.status.success() {
+            return Err(anyhow!("Command for secret {} failed: {}", name, output.status))
+        }
+        String::from_utf8(output.stdout)
+            .with_context(|| anyhow!("Output of command for secret {} is not UTF-8", name))?
+    } else {
+        return Err(anyhow!("Secret {} must be passed as NAME=@FILE or NAME=!COMMAND", name))
+    };
+
+    if value.ends_with('\n') {
+        value.pop();
+    }
+
+    Ok((EnvironmentVariableName::from(name), Secret(value)))
+}
+
+// Only the name part of an argument may be printed in error messages
+fn name_of(s: &str) -> &str {
+    s.split('=').next().unwrap_or_default()
+}
+
+/// Replace all occurrences of the `secrets` in `line`
+pub fn redact<S: AsRef<str>>(line: &str, secrets: &[S]) -> String {
+    secrets.iter()
+        .map(AsRef::as_ref)
+        .filter(|secret| !secret.is_empty())
+        .fold(line.to_string(), |line, secret| line.replace(secret, REDACTED))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_secret() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "s3cr3t").unwrap();
+
+        let (name, secret) = parse_secret(&format!("TOKEN=@{}", file.path().display())).unwrap();
+        assert_eq!(name, EnvironmentVariableName::from("TOKEN"));
+        assert_eq!(secret.expose(), "s3cr3t");
+        assert!(!format!("{:?}", secret).contains("s3cr3t"));
+
+        let (_, secret) = parse_secret("TOKEN=!echo from-command").unwrap();
+        assert_eq!(secret.expose(), "from-command");
+
+        assert!(parse_secret("TOKEN=plain").is_err());
+    }
+
+    #[test]
+    fn test_redact() {
+        let secrets = ["s3cr3t", ""];
+        assert_eq!(redact("token is s3cr3t!", &secrets), format!("token is {}!", REDACTED));
+        assert_eq!(redact("nothing here", &secrets), "nothing here");
+    }
+}
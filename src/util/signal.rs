@@ -0,0 +1,91 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Stopping gracefully when butido is asked to terminate with SIGINT or SIGTERM
+
+use anyhow::Result;
+use tokio::signal::unix::SignalKind;
+
+/// A signal that asks butido to terminate
+#[derive(Clone, Copy, Debug, Eq, PartialEq, parse_display::Display)]
+pub enum TerminationSignal {
+    #[display("SIGINT")]
+    Interrupt,
+
+    #[display("SIGTERM")]
+    Terminate,
+}
+
+impl TerminationSignal {
+    /// The status code butido exits with after it stopped because of the signal
+    ///
+    /// Like in shells, this is 128 plus the number of the signal.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            TerminationSignal::Interrupt => 130,
+            TerminationSignal::Terminate => 143,
+        }
+    }
+}
+
+/// Wait until butido receives SIGINT or SIGTERM
+///
+/// Once this was called, the signals do not terminate the process anymore, see
+/// `exit_on_termination()`.
+pub async fn termination() -> Result<TerminationSignal> {
+    let mut interrupt = tokio::signal::unix::signal(SignalKind::interrupt())?;
+    let mut terminate = tokio::signal::unix::signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = interrupt.recv() => Ok(TerminationSignal::Interrupt),
+        _ = terminate.recv() => Ok(TerminationSignal::Terminate),
+    }
+}
+
+/// Exit immediately with the status code of the signal when butido receives SIGINT or SIGTERM,
+/// instead of stopping gracefully
+pub fn exit_on_termination() {
+    tokio::spawn(async {
+        if let Ok(signal) = termination().await {
+            std::process::exit(signal.exit_code())
+        }
+    });
+}
+
+/// The error of a submit that was stopped because butido received a signal
+#[derive(Debug)]
+pub struct Terminated(pub TerminationSignal);
+
+impl std::fmt::Display for Terminated {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Received {}, stopped the jobs of the submit", self.0)
+    }
+}
+
+impl std::error::Error for Terminated {}
+
+/// The signal that stopped the submit, if `error` is caused by one
+pub fn terminated_by(error: &anyhow::Error) -> Option<TerminationSignal> {
+    error.chain().find_map(|e| e.downcast_ref::<Terminated>()).map(|t| t.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_terminated_by() {
+        let error = Err::<(), _>(anyhow::Error::from(Terminated(TerminationSignal::Terminate)))
+            .context("Running submit")
+            .unwrap_err();
+        assert_eq!(terminated_by(&error), Some(TerminationSignal::Terminate));
+        assert_eq!(terminated_by(&anyhow::anyhow!("Job failed")), None);
+    }
+}
@@ -0,0 +1,236 @@
+//
+// Copyright (c) 2020-2022 science+computing ag and other contributors
+//
+// This program and the accompanying materials are made
+// available under the terms of the Eclipse Public License 2.0
+// which is available at https://www.eclipse.org/legal/epl-2.0/
+//
+// SPDX-License-Identifier: EPL-2.0
+//
+
+//! Drawing full-screen views on the terminal, with ANSI escape sequences
+//!
+//! The terminal is switched to raw mode with the `stty` command, so that keys can be read as they
+//! are pressed.
+
+use std::io::Read;
+use std::io::Write;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+/// The terminal in raw mode, showing the alternate screen
+///
+/// The previous mode and screen are restored when this is dropped.
+pub struct RawTerminal {
+    saved_mode: String,
+}
+
+impl RawTerminal {
+    pub fn enter() -> Result<Self> {
+        let saved_mode = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+
+        // Alternate screen, hidden cursor
+        let mut out = std::io::stdout();
+        write!(out, "\x1b[?1049h\x1b[?25l")?;
+        out.flush()?;
+        Ok(RawTerminal { saved_mode: saved_mode.trim().to_string() })
+    }
+
+    /// The size of the terminal as (rows, columns)
+    pub fn size(&self) -> (usize, usize) {
+        stty(&["size"])
+            .ok()
+            .and_then(|size| {
+                let mut it = size.split_whitespace().map(str::parse::<usize>);
+                Some((it.next()?.ok()?, it.next()?.ok()?))
+            })
+            .filter(|(rows, cols)| *rows > 0 && *cols > 0)
+            .unwrap_or((24, 80))
+    }
+
+    /// Replace the content of the screen with `lines`
+    ///
+    /// The lines must not be longer than the terminal is wide, see `fit()`.
+    pub fn draw(&self, lines: &[String]) -> Result<()> {
+        let mut out = std::io::stdout();
+        write!(out, "\x1b[H")?;
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                write!(out, "\r\n")?;
+            }
+            write!(out, "{}\x1b[K", line)?;
+        }
+        write!(out, "\x1b[J")?;
+        out.flush().map_err(anyhow::Error::from)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved_mode]);
+        let mut out = std::io::stdout();
+        let _ = write!(out, "\x1b[?25h\x1b[?1049l");
+        let _ = out.flush();
+    }
+}
+
+fn stty(args: &[&str]) -> Result<String> {
+    let tty = std::fs::File::open("/dev/tty").context("Opening /dev/tty")?;
+    let output = std::process::Command::new("stty")
+        .args(args)
+        .stdin(tty)
+        .output()
+        .context("Starting stty")?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(anyhow!("stty {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+/// A key that was pressed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Tab,
+    Enter,
+    Esc,
+
+    /// Ctrl-C, which does not send a signal in raw mode
+    Interrupt,
+}
+
+/// Parse the bytes read from the terminal in raw mode into keys
+///
+/// Unknown escape sequences and control characters are ignored.
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            0x1b if bytes.get(i + 1) == Some(&b'[') => {
+                // CSI sequences end with a byte in the range 0x40..=0x7e
+                let end = bytes[i + 2..].iter()
+                    .position(|b| (0x40..=0x7e).contains(b))
+                    .map(|p| i + 2 + p)
+                    .unwrap_or(bytes.len() - 1);
+
+                match &bytes[i + 2..=end] {
+                    b"A" => keys.push(Key::Up),
+                    b"B" => keys.push(Key::Down),
+                    b"5~" => keys.push(Key::PageUp),
+                    b"6~" => keys.push(Key::PageDown),
+                    _ => {},
+                }
+                i = end;
+            },
+            0x1b => keys.push(Key::Esc),
+            0x03 => keys.push(Key::Interrupt),
+            b'\t' => keys.push(Key::Tab),
+            b'\r' | b'\n' => keys.push(Key::Enter),
+            b if b.is_ascii_graphic() || b == b' ' => keys.push(Key::Char(b as char)),
+            _ => {},
+        }
+        i += 1;
+    }
+    keys
+}
+
+/// Read the keys pressed on the terminal, in a thread of its own
+///
+/// The thread ends when the receiver is dropped and the next key is pressed.
+pub fn keys() -> Result<tokio::sync::mpsc::UnboundedReceiver<Key>> {
+    let mut tty = std::fs::File::open("/dev/tty").context("Opening /dev/tty")?;
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        let mut buffer = [0; 64];
+        while let Ok(n) = tty.read(&mut buffer) {
+            if n == 0 || parse_keys(&buffer[..n]).into_iter().any(|key| sender.send(key).is_err()) {
+                break
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+/// Make `s` fit into a line of `width` columns
+///
+/// Escape sequences and control characters are removed, so that text from build logs cannot mess
+/// up the screen, and tabs are replaced by spaces.
+pub fn fit(s: &str, width: usize) -> String {
+    let mut result = String::with_capacity(width);
+    let mut chars = s.chars();
+    let mut n = 0;
+    while let Some(c) = chars.next() {
+        if n == width {
+            break
+        }
+
+        match c {
+            '\x1b' => {
+                // Skip the sequence up to its final byte
+                if chars.next() == Some('[') {
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break
+                        }
+                    }
+                }
+            },
+            '\t' => {
+                result.push(' ');
+                n += 1;
+            },
+            c if c.is_control() => {},
+            c => {
+                result.push(c);
+                n += 1;
+            },
+        }
+    }
+    result
+}
+
+/// A bar of `width` columns that is filled to `used` of `max`
+pub fn usage_bar(used: usize, max: usize, width: usize) -> String {
+    let filled = (used * width + max / 2)
+        .checked_div(max)
+        .map(|filled| std::cmp::min(width, filled))
+        .unwrap_or(width);
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(width - filled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys(b"q"), vec![Key::Char('q')]);
+        assert_eq!(parse_keys(b"\x1b[A\x1b[B\t\r"), vec![Key::Up, Key::Down, Key::Tab, Key::Enter]);
+        assert_eq!(parse_keys(b"\x1b[5~j\x1b[1;5Ck"), vec![Key::PageUp, Key::Char('j'), Key::Char('k')]);
+        assert_eq!(parse_keys(b"\x1b\x03\x01"), vec![Key::Esc, Key::Interrupt]);
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit("hello world", 5), "hello");
+        assert_eq!(fit("\x1b[31mred\x1b[0m\tok\r", 80), "red ok");
+        assert_eq!(fit("äöü", 2), "äö");
+    }
+
+    #[test]
+    fn test_usage_bar() {
+        assert_eq!(usage_bar(0, 4, 4), "[    ]");
+        assert_eq!(usage_bar(2, 4, 4), "[##  ]");
+        assert_eq!(usage_bar(6, 4, 4), "[####]");
+    }
+}